@@ -0,0 +1,376 @@
+//! クライアント毎のアクセス制御.
+use cannyls::{ErrorKind, Result};
+use fibers_rpc::Call;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::device::DeviceId;
+use crate::rpc;
+
+/// クライアントの識別子(署名の鍵のID)毎に、呼び出し可能なRPCおよびアクセス可能なデバイスを制限するためのアクセス制御リスト.
+///
+/// `Server::access_control`で使用され、lumpやデバイスに対するリクエスト(アクセスログの対象と同じ)、
+/// デバイスの情報を参照するリクエスト、および管理用のRPCに適用される.
+///
+/// 管理用のRPCのうち、特定のデバイスを対象としないもの(e.g., `rpc::SetLogLevelRpc`)は、リクエストのオプションを伴わないので、
+/// 呼び出し元は常に匿名のクライアントとして扱われる.
+/// また、それらの呼び出しには、全てのデバイスへのアクセスが許可されている必要がある.
+///
+/// デバイス一覧の取得(`rpc::ListDevicesRpc`)のように、リクエストのオプションを伴わない(i.e., 署名を検証できない)RPCの場合には、
+/// 呼び出し元は常に匿名のクライアントとして扱われ、その権限で参照可能なデバイスのみが結果に含まれる.
+/// インポートセッションに対するリクエスト(e.g., `rpc::CommitImportSessionRpc`)は、セッションの対象デバイスに対して検査される.
+///
+/// クライアントの識別には、検証済みのリクエストの署名(`Server::verify_signatures`)の鍵のIDが使用される.
+/// 署名の検証が有効になっていない場合には、全てのリクエストが匿名のものとして扱われる.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate cannyls_rpc;
+/// use cannyls_rpc::rpc;
+/// use cannyls_rpc::{AccessControl, Permissions};
+///
+/// let mut writer = Permissions::read_only();
+/// writer.allow::<rpc::PutLumpRpc>();
+///
+/// // テナント毎に、アクセス可能なデバイスを制限する
+/// let mut tenant = Permissions::all();
+/// tenant.allow_device_prefix("tenant1/");
+///
+/// let mut acl = AccessControl::new();
+/// acl.grant("reader", Permissions::read_only())
+///     .grant("writer", writer)
+///     .grant("tenant1", tenant)
+///     .grant("admin", Permissions::admin());
+/// # let _ = acl;
+/// ```
+#[derive(Debug, Clone)]
+pub struct AccessControl {
+    credentials: HashMap<String, Permissions>,
+    anonymous: Permissions,
+}
+impl AccessControl {
+    /// 新しい`AccessControl`インスタンスを生成する.
+    ///
+    /// 初期状態では、いずれのクライアント(匿名のものを含む)にも、何の権限も与えられていない.
+    pub fn new() -> Self {
+        AccessControl {
+            credentials: HashMap::new(),
+            anonymous: Permissions::none(),
+        }
+    }
+
+    /// 指定の識別子を持つクライアントに、権限を与える.
+    ///
+    /// 既に権限が与えられていた場合には、上書きされる.
+    pub fn grant<T: Into<String>>(&mut self, key_id: T, permissions: Permissions) -> &mut Self {
+        self.credentials.insert(key_id.into(), permissions);
+        self
+    }
+
+    /// 匿名(ないし未登録の識別子を持つ)クライアントに与えられる権限を設定する.
+    ///
+    /// デフォルト値は`Permissions::none()`.
+    pub fn grant_anonymous(&mut self, permissions: Permissions) -> &mut Self {
+        self.anonymous = permissions;
+        self
+    }
+
+    /// 指定のクライアントに与えられている権限を返す.
+    ///
+    /// `key_id`が`None`ないし未登録の場合には、匿名クライアントの権限が返される.
+    pub fn permissions(&self, key_id: Option<&str>) -> &Permissions {
+        key_id
+            .and_then(|id| self.credentials.get(id))
+            .unwrap_or(&self.anonymous)
+    }
+
+    /// 指定のクライアントが、指定のデバイスに対して指定のRPCを呼び出せるかどうかを検査する.
+    ///
+    /// 許可されていない場合には`ErrorKind::InvalidInput`が返される.
+    pub(crate) fn check(
+        &self,
+        key_id: Option<&str>,
+        procedure: &str,
+        device_id: &DeviceId,
+    ) -> Result<()> {
+        let permissions = self.permissions(key_id);
+        let client = || key_id.map_or("anonymous clients".to_owned(), |id| format!("{:?}", id));
+        track_assert!(
+            permissions.is_allowed(procedure),
+            ErrorKind::InvalidInput,
+            "Permission denied: {} is not allowed for {}",
+            procedure,
+            client()
+        );
+        track_assert!(
+            permissions.is_device_allowed(device_id),
+            ErrorKind::InvalidInput,
+            "Permission denied: device {:?} is not accessible for {}",
+            device_id.as_str(),
+            client()
+        );
+        Ok(())
+    }
+
+    /// `check`と同様だが、特定のデバイスを対象としないRPC用.
+    ///
+    /// 全てのデバイスへのアクセスを許可されていない場合には、呼び出しは拒否される.
+    pub(crate) fn check_server_wide(&self, key_id: Option<&str>, procedure: &str) -> Result<()> {
+        let permissions = self.permissions(key_id);
+        let client = || key_id.map_or("anonymous clients".to_owned(), |id| format!("{:?}", id));
+        track_assert!(
+            permissions.is_allowed(procedure),
+            ErrorKind::InvalidInput,
+            "Permission denied: {} is not allowed for {}",
+            procedure,
+            client()
+        );
+        track_assert!(
+            permissions.devices.is_none(),
+            ErrorKind::InvalidInput,
+            "Permission denied: {} requires access to all devices, but {} is restricted",
+            procedure,
+            client()
+        );
+        Ok(())
+    }
+}
+impl Default for AccessControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 呼び出し可能なRPCおよびアクセス可能なデバイスの集合.
+///
+/// RPCはサーバ側で処理される際の名前で判定されるので、
+/// 例えば`rpc::GetLumpRpc`を許可した場合には、`rpc::GetLumpWithMetaRpc`も許可される.
+///
+/// デバイスは、`allow_device`ないし`allow_device_prefix`が一度も呼ばれていない場合には、全てが対象となる.
+/// なお範囲のコピー(`rpc::CopyRangeRpc`)の場合には、コピー元とコピー先の両方のデバイスが対象である必要がある.
+///
+/// 管理用のRPC(`Server::enable_admin_rpc`で登録されるもの)は、`all`には含まれず、
+/// `allow_admin`ないし`allow`で明示的に許可する必要がある.
+#[derive(Debug, Clone)]
+pub struct Permissions {
+    procedures: Option<BTreeSet<&'static str>>, // `None`は管理用以外の全てのRPCを許可する
+    devices: Option<DevicePatterns>,            // `None`は全てのデバイスを許可する
+    admin: BTreeSet<&'static str>,              // 許可された管理用のRPC
+}
+impl Permissions {
+    /// 管理用のRPCを除く、全てのRPCを許可する`Permissions`を返す.
+    pub fn all() -> Self {
+        Permissions {
+            procedures: None,
+            devices: None,
+            admin: BTreeSet::new(),
+        }
+    }
+
+    /// 管理用のRPCを含む、全てのRPCを許可する`Permissions`を返す.
+    pub fn admin() -> Self {
+        let mut permissions = Self::all();
+        permissions.allow_admin();
+        permissions
+    }
+
+    /// いずれのRPCも許可しない`Permissions`を返す.
+    pub fn none() -> Self {
+        Permissions {
+            procedures: Some(BTreeSet::new()),
+            devices: None,
+            admin: BTreeSet::new(),
+        }
+    }
+
+    /// 参照系のRPCのみを許可する`Permissions`を返す.
+    ///
+    /// 許可されるのは、lumpの取得・ヘッダの取得・存在確認・一覧の取得・使用量の取得、
+    /// スクラブ(`rpc::ScrubRangeRpc`)とエクスポート(`rpc::ExportLumpsRpc`)、
+    /// およびデバイスの情報(状態やメトリクス等)を参照するRPC.
+    /// 更新を伴い得るスクリプト(`rpc::ScriptRpc`)は含まれない.
+    pub fn read_only() -> Self {
+        let mut permissions = Self::none();
+        permissions
+            .allow::<rpc::GetLumpRpc>()
+            .allow::<rpc::GetLumpRangeRpc>()
+            .allow::<rpc::GetLumpWithChecksumRpc>()
+            .allow::<rpc::GetLumpsRpc>()
+            .allow::<rpc::HeadLumpRpc>()
+            .allow::<rpc::ExistsLumpRpc>()
+            .allow::<rpc::ListLumpRpc>()
+            .allow::<rpc::ListLumpRangeRpc>()
+            .allow::<rpc::ListLumpsChunkRpc>()
+            .allow::<rpc::ExportLumpsRpc>()
+            .allow::<rpc::UsageRangeRpc>()
+            .allow::<rpc::ScrubRangeRpc>()
+            .allow::<rpc::JournalUsageRpc>()
+            .allow::<rpc::StorageHeaderRpc>()
+            .allow::<rpc::MetricsSnapshotRpc>()
+            .allow::<rpc::RequestStatsRpc>()
+            .allow::<rpc::ListDevicesRpc>()
+            .allow::<rpc::DeviceStatusRpc>()
+            .allow::<rpc::DeviceLabelsRpc>()
+            .allow::<rpc::ReadinessRpc>();
+        permissions
+    }
+
+    /// 指定されたRPCを許可する.
+    pub fn allow<T: Call>(&mut self) -> &mut Self {
+        self.allow_procedure(T::NAME)
+    }
+
+    /// 指定された名前のRPCを許可する.
+    pub fn allow_procedure(&mut self, procedure: &'static str) -> &mut Self {
+        if ADMIN_PROCEDURES.contains(&procedure) {
+            self.admin.insert(procedure);
+        } else if let Some(ref mut procedures) = self.procedures {
+            procedures.insert(procedure);
+        }
+        self
+    }
+
+    /// 全ての管理用のRPCを許可する.
+    ///
+    /// デバイスの範囲の制限(`allow_device`等)は、管理用のRPCにも適用される.
+    pub fn allow_admin(&mut self) -> &mut Self {
+        self.admin.extend(ADMIN_PROCEDURES.iter().copied());
+        self
+    }
+
+    /// 指定された名前のRPCが許可されているかどうかを判定する.
+    pub fn is_allowed(&self, procedure: &str) -> bool {
+        if ADMIN_PROCEDURES.contains(&procedure) {
+            return self.admin.contains(procedure);
+        }
+        self.procedures
+            .as_ref()
+            .is_none_or(|procedures| procedures.contains(procedure))
+    }
+
+    /// 指定されたデバイスへのアクセスを許可する.
+    ///
+    /// 最初の呼び出し以降は、許可されたデバイスにのみアクセス可能となる.
+    pub fn allow_device(&mut self, device_id: DeviceId) -> &mut Self {
+        self.devices
+            .get_or_insert_with(DevicePatterns::default)
+            .exact
+            .insert(device_id.into_string());
+        self
+    }
+
+    /// IDが指定の接頭辞で始まるデバイスへのアクセスを許可する.
+    ///
+    /// 最初の呼び出し以降は、許可されたデバイスにのみアクセス可能となる.
+    pub fn allow_device_prefix<T: Into<String>>(&mut self, prefix: T) -> &mut Self {
+        self.devices
+            .get_or_insert_with(DevicePatterns::default)
+            .prefixes
+            .push(prefix.into());
+        self
+    }
+
+    /// 指定されたデバイスへのアクセスが許可されているかどうかを判定する.
+    pub fn is_device_allowed(&self, device_id: &DeviceId) -> bool {
+        self.devices.as_ref().is_none_or(|devices| {
+            let id = device_id.as_str();
+            devices.exact.contains(id) || devices.prefixes.iter().any(|p| id.starts_with(p))
+        })
+    }
+}
+
+// 管理用のRPCの一覧.
+const ADMIN_PROCEDURES: [&str; 10] = [
+    rpc::SetJournalSyncRpc::NAME,
+    rpc::SetQueueLimitsRpc::NAME,
+    rpc::SetWriteWatermarkRpc::NAME,
+    rpc::SetLogLevelRpc::NAME,
+    rpc::ListInFlightRpc::NAME,
+    rpc::CancelInFlightRpc::NAME,
+    rpc::ResetRequestStatsRpc::NAME,
+    rpc::ProvisionDeviceRpc::NAME,
+    rpc::DeleteDeviceRpc::NAME,
+    rpc::StopDeviceRpc::NAME,
+];
+
+#[derive(Debug, Clone, Default)]
+struct DevicePatterns {
+    exact: BTreeSet<String>,
+    prefixes: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_control_works() {
+        let mut acl = AccessControl::new();
+        acl.grant("reader", Permissions::read_only())
+            .grant("writer", Permissions::all())
+            .grant("admin", Permissions::admin());
+
+        let get = rpc::GetLumpRpc::NAME;
+        let put = rpc::PutLumpRpc::NAME;
+        let delete_device = rpc::DeleteDeviceRpc::NAME;
+        let device = DeviceId::new("foo");
+        assert!(acl.check(Some("reader"), get, &device).is_ok());
+        assert!(acl.check(Some("reader"), put, &device).is_err());
+        assert!(acl.check(Some("writer"), put, &device).is_ok());
+        assert!(acl.check(Some("admin"), put, &device).is_ok());
+
+        // 管理用のRPCは`all`には含まれない
+        assert!(acl.check(Some("writer"), delete_device, &device).is_err());
+        assert!(acl.check(Some("admin"), delete_device, &device).is_ok());
+
+        // 個別に許可することも可能
+        let mut permissions = Permissions::all();
+        permissions.allow::<rpc::DeleteDeviceRpc>();
+        acl.grant("operator", permissions);
+        assert!(acl.check(Some("operator"), delete_device, &device).is_ok());
+        assert!(acl
+            .check(Some("operator"), rpc::StopDeviceRpc::NAME, &device)
+            .is_err());
+        assert!(acl
+            .check_server_wide(Some("admin"), rpc::SetLogLevelRpc::NAME)
+            .is_ok());
+        assert!(acl.check(Some("unknown"), get, &device).is_err());
+        assert!(acl.check(None, get, &device).is_err());
+
+        acl.grant_anonymous(Permissions::read_only());
+        assert!(acl.check(Some("unknown"), get, &device).is_ok());
+        assert!(acl.check(None, get, &device).is_ok());
+        assert!(acl.check(None, put, &device).is_err());
+    }
+
+    #[test]
+    fn device_scoping_works() {
+        let mut permissions = Permissions::all();
+        assert!(permissions.is_device_allowed(&DeviceId::new("foo")));
+
+        permissions
+            .allow_device(DeviceId::new("foo"))
+            .allow_device_prefix("bar/");
+        assert!(permissions.is_device_allowed(&DeviceId::new("foo")));
+        assert!(!permissions.is_device_allowed(&DeviceId::new("foo2")));
+        assert!(permissions.is_device_allowed(&DeviceId::new("bar/0")));
+        assert!(!permissions.is_device_allowed(&DeviceId::new("bar")));
+        assert!(!permissions.is_device_allowed(&DeviceId::new("baz")));
+
+        // デバイスの範囲が制限されている場合には、特定のデバイスを対象としない管理用のRPCは呼び出せない
+        permissions.allow_admin();
+        let mut acl = AccessControl::new();
+        acl.grant("tenant", permissions);
+        let set_log_level = rpc::SetLogLevelRpc::NAME;
+        assert!(acl
+            .check_server_wide(Some("tenant"), set_log_level)
+            .is_err());
+        let stop_device = rpc::StopDeviceRpc::NAME;
+        assert!(acl
+            .check(Some("tenant"), stop_device, &DeviceId::new("foo"))
+            .is_ok());
+        assert!(acl
+            .check(Some("tenant"), stop_device, &DeviceId::new("baz"))
+            .is_err());
+    }
+}
@@ -0,0 +1,235 @@
+//! 設定ファイル(`NodeConfig`)に従ってデバイス群を構築し、RPCサーバとして動作し続けるデーモン.
+//!
+//! 使い方は`cannyls-rpc-server --help`を参照のこと.
+extern crate cannyls_rpc;
+extern crate fibers;
+extern crate fibers_rpc;
+extern crate futures;
+#[macro_use]
+extern crate slog;
+#[macro_use]
+extern crate trackable;
+
+use cannyls_rpc::{
+    DeviceId, DeviceRegistry, DeviceRegistryHandle, Error, ErrorKind, NodeConfig, Result, Server,
+};
+use fibers::{Executor, Spawn, ThreadPoolExecutor};
+use fibers_rpc::server::ServerBuilder;
+use futures::Future;
+use slog::{Drain, Key, Logger, Never, OwnedKVList, Record, Serializer, KV};
+use std::env;
+use std::fmt::{self, Write};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use trackable::error::ErrorKindExt;
+
+const USAGE: &str = "\
+Usage: cannyls-rpc-server <CONFIG_FILE>
+
+Provisions the devices described in the configuration file and serves them over RPC.
+Logs are written to stderr.
+
+On SIGHUP, the devices in the configuration file are reloaded:
+new devices are provisioned, and removed ones are stopped gracefully and deregistered.
+Devices provisioned via the administrative RPCs are left as is.
+Other settings are not reloaded.
+
+Configuration (a subset of TOML):
+    listen = \"<ADDR>\"         Address to listen on (required)
+    admin_rpc = <BOOL>        Enables the administrative RPCs [default: false]
+    log_level = \"<LEVEL>\"     One of trace, debug, info, warning, error and critical [default: info]
+
+    [[device]]                One section per device
+    id = \"<ID>\"               Device ID (required)
+    path = \"<PATH>\"           Path of the lusf file (either `path` or `memory` is required)
+    memory = true             Uses an in-memory device instead of a file
+    capacity = <BYTES>        Capacity of the device; used only when it is created (required)
+    block_size = <BYTES>      Block size of the storage [default: 512]
+    labels = { k = \"v\" }      Labels attached to the device
+
+    Arrays, floats, dates, dotted keys and multi-line values are not supported.
+
+Example of CONFIG_FILE:
+
+    listen = \"0.0.0.0:14278\"
+    admin_rpc = false
+    log_level = \"info\"
+
+    [[device]]
+    id = \"dev0\"
+    path = \"/var/lib/cannyls/dev0.lusf\"
+    capacity = 10_737_418_240
+    labels = { zone = \"a\" }
+";
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if args.len() != 1 || args[0].starts_with('-') {
+        let help = args.len() == 1 && (args[0] == "-h" || args[0] == "--help");
+        if help {
+            print!("{}", USAGE);
+            return;
+        }
+        eprint!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let logger = Logger::root(StderrDrain, o!());
+    if let Err(e) = run(&args[0], &logger) {
+        crit!(logger, "Server terminated abnormally: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(config_path: &str, logger: &Logger) -> Result<()> {
+    let config = track!(NodeConfig::load(config_path))?;
+    let executor = track!(ThreadPoolExecutor::new().map_err(Error::from))?;
+
+    // Device Registry
+    let registry = DeviceRegistry::new(logger.clone());
+    let handle = registry.handle();
+    handle.set_log_level(config.log_level);
+    let registry_logger = logger.clone();
+    executor.spawn(registry.map_err(move |e| {
+        crit!(
+            registry_logger,
+            "Device registry terminated abnormally: {}",
+            e
+        );
+        process::exit(1);
+    }));
+
+    // 設定ファイル由来のデバイスとして登録し、SIGHUP時の差分の反映対象とする
+    let mut executor = executor;
+    let reload = handle.reload_devices(config.devices);
+    let reload = track!(executor.run_future(reload).map_err(Error::from))?;
+    let failures = track!(reload)?
+        .added
+        .into_iter()
+        .filter(|(device_id, result)| !log_provision_result(logger, device_id, result))
+        .count();
+    track_assert_eq!(
+        failures,
+        0,
+        ErrorKind::Other,
+        "Some devices could not be provisioned"
+    );
+    #[cfg(unix)]
+    reload_on_sighup(
+        config_path,
+        handle.clone(),
+        executor.handle(),
+        logger.clone(),
+    );
+
+    // RPC Server
+    let mut builder = ServerBuilder::new(config.listen);
+    builder.logger(logger.clone());
+    let mut server = Server::new(handle);
+    if config.admin_rpc {
+        server.enable_admin_rpc();
+    }
+    server.register(&mut builder);
+    let server = executor.spawn_monitor(builder.finish(executor.handle()));
+
+    let result = track!(executor.run_future(server).map_err(Error::from))?;
+    track!(result.map_err(|e| Error::from(ErrorKind::Other.cause(e))))
+}
+
+// デバイスの構築結果をログに出力し、成功したかどうかを返す.
+fn log_provision_result(logger: &Logger, device_id: &DeviceId, result: &Result<bool>) -> bool {
+    match result {
+        Ok(created) => {
+            info!(
+                logger,
+                "Device is provisioned";
+                "device_id" => device_id.as_str(),
+                "created" => created
+            );
+            true
+        }
+        Err(e) => {
+            error!(logger, "Cannot provision device: {}", e; "device_id" => device_id.as_str());
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+fn reload_on_sighup<S>(config_path: &str, handle: DeviceRegistryHandle, spawner: S, logger: Logger)
+where
+    S: Spawn + Send + 'static,
+{
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    static HANGUP: AtomicBool = AtomicBool::new(false);
+    extern "C" fn on_hangup(_: libc::c_int) {
+        HANGUP.store(true, Ordering::SeqCst);
+    }
+    unsafe {
+        libc::signal(
+            libc::SIGHUP,
+            on_hangup as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+
+    // シグナルハンドラ内では最小限の処理しか行えないので、別スレッドでフラグを監視する
+    let config_path = config_path.to_owned();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(100));
+        if !HANGUP.swap(false, Ordering::SeqCst) {
+            continue;
+        }
+        info!(logger, "Reloading the configuration file (SIGHUP)");
+        let logger = logger.clone();
+        spawner.spawn(handle.reload_config(&config_path).then(move |result| {
+            match result {
+                Err(e) => error!(logger, "Cannot reload the configuration file: {}", e),
+                Ok(reload) => {
+                    for (device_id, result) in &reload.added {
+                        log_provision_result(&logger, device_id, result);
+                    }
+                    for device_id in reload.removed {
+                        info!(logger, "Device is being retired"; "device_id" => device_id.as_str());
+                    }
+                }
+            }
+            Ok(())
+        }));
+    });
+}
+
+/// ログレコードを一行ずつ標準エラー出力に書き出す`Drain`の実装.
+struct StderrDrain;
+impl Drain for StderrDrain {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> std::result::Result<(), Never> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut line = format!(
+            "{}.{:03} {} {}",
+            now.as_secs(),
+            now.subsec_millis(),
+            record.level().as_short_str(),
+            record.msg()
+        );
+        let mut serializer = LineSerializer(&mut line);
+        let _ = record.kv().serialize(record, &mut serializer);
+        let _ = values.serialize(record, &mut serializer);
+        eprintln!("{}", line);
+        Ok(())
+    }
+}
+
+struct LineSerializer<'a>(&'a mut String);
+impl<'a> Serializer for LineSerializer<'a> {
+    fn emit_arguments(&mut self, key: Key, val: &fmt::Arguments<'_>) -> slog::Result {
+        let _ = write!(self.0, ", {}: {}", key, val);
+        Ok(())
+    }
+}
@@ -0,0 +1,335 @@
+//! リモートのデバイスを、`Client`を用いて操作するためのコマンドラインツール.
+//!
+//! 使い方は`cannyls-rpc --help`を参照のこと.
+extern crate cannyls_rpc;
+extern crate fibers;
+extern crate fibers_rpc;
+extern crate futures;
+#[macro_use]
+extern crate trackable;
+
+use cannyls_rpc::{
+    Client, Deadline, DeviceId, Error, LumpData, LumpId, RequestBuilder, StorageUsage,
+};
+use fibers::{Executor, InPlaceExecutor, Spawn};
+use fibers_rpc::client::ClientService;
+use futures::Future;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::process;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: cannyls-rpc [OPTIONS] <COMMAND> [ARGS]
+
+Commands:
+    get <DEVICE> <LUMP_ID> [-o FILE]      Gets the data of a lump (written to stdout by default)
+    put <DEVICE> <LUMP_ID> [-i FILE]      Puts a lump (the data is read from stdin by default)
+    delete <DEVICE> <LUMP_ID>             Deletes a lump
+    head <DEVICE> <LUMP_ID>               Prints the approximate data size of a lump
+    list <DEVICE> [<START> <END>]         Lists the lumps (in the range [START, END) if specified)
+    usage_range <DEVICE> <START> <END>    Prints the approximate storage usage of the range
+    delete_range <DEVICE> <START> <END>   Deletes the lumps in the range and lists them
+
+Lump IDs are hexadecimal numbers without the `0x` prefix (e.g., `ab12`).
+
+Options:
+    -s, --server <ENDPOINT>    Address of the RPC server (`host:port`) [env: CANNYLS_RPC_SERVER]
+        --deadline <MILLIS>    Deadline of the request
+        --timeout <MILLIS>     Timeout of the RPC
+        --prioritized          Processes the request even if the device is overloaded
+    -h, --help                 Prints this message
+
+Exit status:
+    0    Success
+    1    The request failed
+    2    Invalid command line arguments
+    3    The lump was not found (`get`, `head` and `delete` only)
+";
+
+const SERVER_ENV: &str = "CANNYLS_RPC_SERVER";
+
+fn main() {
+    let result = parse_args(env::args().skip(1)).and_then(|(options, command)| {
+        if let Command::Help = command {
+            print!("{}", USAGE);
+            return Ok(());
+        }
+        run(options, command)
+    });
+    match result {
+        Ok(()) => {}
+        Err(Failure::Usage(message)) => {
+            eprintln!("Error: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+        Err(Failure::Request(e)) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+        Err(Failure::NotFound) => {
+            eprintln!("Lump not found");
+            process::exit(3);
+        }
+    }
+}
+
+enum Failure {
+    Usage(String),
+    Request(Error),
+    NotFound,
+}
+impl From<Error> for Failure {
+    fn from(f: Error) -> Self {
+        Failure::Request(f)
+    }
+}
+impl From<io::Error> for Failure {
+    fn from(f: io::Error) -> Self {
+        Failure::Request(track!(Error::from(f)))
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    server: Option<String>,
+    deadline: Option<Deadline>,
+    timeout: Option<Duration>,
+    prioritized: bool,
+}
+
+enum Command {
+    Get(DeviceId, LumpId, Option<String>),
+    Put(DeviceId, LumpId, Option<String>),
+    Delete(DeviceId, LumpId),
+    Head(DeviceId, LumpId),
+    List(DeviceId, Option<Range<LumpId>>),
+    UsageRange(DeviceId, Range<LumpId>),
+    DeleteRange(DeviceId, Range<LumpId>),
+    Help,
+}
+
+fn parse_args<I>(args: I) -> Result<(Options, Command), Failure>
+where
+    I: Iterator<Item = String>,
+{
+    let mut options = Options::default();
+    let mut file = None;
+    let mut positionals = Vec::new();
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| Failure::Usage(format!("`{}` requires a value", name)))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok((options, Command::Help)),
+            "-s" | "--server" => options.server = Some(value(&arg)?),
+            "--deadline" => {
+                let millis = parse_number(&arg, &value(&arg)?)?;
+                options.deadline = Some(Deadline::Within(Duration::from_millis(millis)));
+            }
+            "--timeout" => {
+                let millis = parse_number(&arg, &value(&arg)?)?;
+                options.timeout = Some(Duration::from_millis(millis));
+            }
+            "--prioritized" => options.prioritized = true,
+            "-o" | "--output" | "-i" | "--input" => file = Some((arg.clone(), value(&arg)?)),
+            _ if arg.starts_with('-') => {
+                return Err(Failure::Usage(format!("Unknown option: {:?}", arg)));
+            }
+            _ => positionals.push(arg),
+        }
+    }
+
+    let mut positionals = positionals.into_iter();
+    let name = positionals
+        .next()
+        .ok_or_else(|| Failure::Usage("No command is specified".to_owned()))?;
+    let rest = positionals.collect::<Vec<_>>();
+    let arity = |n: usize| {
+        if rest.len() == n {
+            Ok(())
+        } else {
+            Err(Failure::Usage(format!(
+                "`{}` takes {} arguments, but {} given",
+                name,
+                n,
+                rest.len()
+            )))
+        }
+    };
+    let device_id = || DeviceId::new(rest[0].clone());
+    let range = || -> Result<Range<LumpId>, Failure> {
+        Ok(parse_lump_id(&rest[1])?..parse_lump_id(&rest[2])?)
+    };
+    let file_option = |allowed: &[&str]| match file {
+        Some((ref option, _)) if !allowed.contains(&option.as_str()) => Err(Failure::Usage(
+            format!("`{}` does not accept `{}`", name, option),
+        )),
+        _ => Ok(file.as_ref().map(|(_, path)| path.clone())),
+    };
+    let command = match name.as_str() {
+        "get" => {
+            arity(2)?;
+            let output = file_option(&["-o", "--output"])?;
+            Command::Get(device_id(), parse_lump_id(&rest[1])?, output)
+        }
+        "put" => {
+            arity(2)?;
+            let input = file_option(&["-i", "--input"])?;
+            Command::Put(device_id(), parse_lump_id(&rest[1])?, input)
+        }
+        "delete" | "head" => {
+            arity(2)?;
+            file_option(&[])?;
+            let lump_id = parse_lump_id(&rest[1])?;
+            if name == "delete" {
+                Command::Delete(device_id(), lump_id)
+            } else {
+                Command::Head(device_id(), lump_id)
+            }
+        }
+        "list" => {
+            if rest.len() != 1 && rest.len() != 3 {
+                let message = format!("`list` takes 1 or 3 arguments, but {} given", rest.len());
+                return Err(Failure::Usage(message));
+            }
+            file_option(&[])?;
+            let range = if rest.len() == 3 {
+                Some(range()?)
+            } else {
+                None
+            };
+            Command::List(device_id(), range)
+        }
+        "usage_range" | "delete_range" => {
+            arity(3)?;
+            file_option(&[])?;
+            if name == "usage_range" {
+                Command::UsageRange(device_id(), range()?)
+            } else {
+                Command::DeleteRange(device_id(), range()?)
+            }
+        }
+        _ => return Err(Failure::Usage(format!("Unknown command: {:?}", name))),
+    };
+    Ok((options, command))
+}
+
+fn parse_number(option: &str, value: &str) -> Result<u64, Failure> {
+    value
+        .parse()
+        .map_err(|_| Failure::Usage(format!("Invalid value for `{}`: {:?}", option, value)))
+}
+
+fn parse_lump_id(s: &str) -> Result<LumpId, Failure> {
+    s.parse()
+        .map_err(|_| Failure::Usage(format!("Invalid lump ID: {:?}", s)))
+}
+
+fn run(options: Options, command: Command) -> Result<(), Failure> {
+    let server = options
+        .server
+        .or_else(|| env::var(SERVER_ENV).ok())
+        .ok_or_else(|| Failure::Usage("No server is specified".to_owned()))?;
+
+    let mut executor = track!(InPlaceExecutor::new().map_err(Error::from))?;
+    let service = ClientService::new(executor.handle());
+    let client = track!(Client::with_endpoint(&server, service.handle()))?;
+    executor.spawn(service.map_err(|e| panic!("RPC client service terminated abnormally: {}", e)));
+
+    let mut request = client.request();
+    if let Some(deadline) = options.deadline {
+        request.deadline(deadline);
+    }
+    if let Some(timeout) = options.timeout {
+        request.rpc_options(fibers_rpc::client::Options {
+            timeout: Some(timeout),
+            ..Default::default()
+        });
+    }
+    if options.prioritized {
+        request.prioritized();
+    }
+    execute(&mut executor, &request, command)
+}
+
+fn execute(
+    executor: &mut InPlaceExecutor,
+    request: &RequestBuilder<'_>,
+    command: Command,
+) -> Result<(), Failure> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    match command {
+        Command::Get(device_id, lump_id, output) => {
+            let data =
+                wait(executor, request.get_lump(device_id, lump_id))?.ok_or(Failure::NotFound)?;
+            match output {
+                Some(path) => fs::write(path, data)?,
+                None => stdout.write_all(&data)?,
+            }
+        }
+        Command::Put(device_id, lump_id, input) => {
+            let data = match input {
+                Some(path) => fs::read(path)?,
+                None => {
+                    let mut data = Vec::new();
+                    io::stdin().read_to_end(&mut data)?;
+                    data
+                }
+            };
+            let data = track!(LumpData::new(data))?;
+            let created = wait(executor, request.put_lump(device_id, lump_id, data))?;
+            writeln!(
+                stdout,
+                "{}",
+                if created { "created" } else { "overwritten" }
+            )?;
+        }
+        Command::Delete(device_id, lump_id) => {
+            if !wait(executor, request.delete_lump(device_id, lump_id))? {
+                return Err(Failure::NotFound);
+            }
+            writeln!(stdout, "deleted")?;
+        }
+        Command::Head(device_id, lump_id) => {
+            let header =
+                wait(executor, request.head_lump(device_id, lump_id))?.ok_or(Failure::NotFound)?;
+            writeln!(stdout, "{}", header.approximate_data_size)?;
+        }
+        Command::List(device_id, range) => {
+            let lump_ids = match range {
+                Some(range) => wait(executor, request.list_lumps_range(device_id, range))?,
+                None => wait(executor, request.list_lumps(device_id))?,
+            };
+            for lump_id in lump_ids {
+                writeln!(stdout, "{}", lump_id)?;
+            }
+        }
+        Command::UsageRange(device_id, range) => {
+            match wait(executor, request.usage_range(device_id, range))? {
+                StorageUsage::Approximate(usage) => writeln!(stdout, "{}", usage)?,
+                StorageUsage::Unknown => writeln!(stdout, "unknown")?,
+            }
+        }
+        Command::DeleteRange(device_id, range) => {
+            for lump_id in wait(executor, request.delete_range(device_id, range))? {
+                writeln!(stdout, "{}", lump_id)?;
+            }
+        }
+        Command::Help => print!("{}", USAGE),
+    }
+    Ok(())
+}
+
+fn wait<F>(executor: &mut InPlaceExecutor, future: F) -> Result<F::Item, Failure>
+where
+    F: Future<Error = Error>,
+{
+    let result = track!(executor.run_future(future).map_err(Error::from))?;
+    Ok(track!(result)?)
+}
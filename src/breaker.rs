@@ -0,0 +1,136 @@
+//! サーバ毎のサーキットブレーカ.
+use cannyls::{Error, ErrorKind, Result};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use trackable::error::ErrorKindExt;
+
+/// サーキットブレーカの動作を決定するポリシー.
+///
+/// 詳細は`Client::enable_circuit_breaker`を参照のこと.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitBreakerPolicy {
+    /// ブレーカが作動するまでの、連続した通信エラーの回数.
+    ///
+    /// デフォルト値は`5`.
+    pub failure_threshold: usize,
+
+    /// ブレーカの作動後に、リクエストを即座に失敗させる期間.
+    ///
+    /// デフォルト値は`10s`.
+    pub cooldown: Duration,
+}
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        CircuitBreakerPolicy {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(10),
+        }
+    }
+}
+
+/// サーキットブレーカ.
+///
+/// インスタンスをクローンした場合には、同じ状態が共有される.
+#[derive(Debug, Clone)]
+pub(crate) struct CircuitBreaker {
+    policy: CircuitBreakerPolicy,
+    state: Arc<Mutex<State>>,
+}
+impl CircuitBreaker {
+    pub fn new(policy: CircuitBreakerPolicy) -> Self {
+        CircuitBreaker {
+            policy,
+            state: Arc::default(),
+        }
+    }
+
+    /// リクエストの発行が可能かどうかを確認する.
+    ///
+    /// ブレーカの作動中には`ErrorKind::Other`エラーが返される.
+    pub fn check(&self) -> Result<()> {
+        let state = self.lock();
+        if let Some(until) = state.open_until {
+            let now = Instant::now();
+            if now < until {
+                // `ClientError`で通信層のエラーとして分類されるように、`fibers_rpc`のエラーを原因とする
+                let e = fibers_rpc::ErrorKind::Unavailable.cause(format!(
+                    "Circuit breaker is open: remaining={:?}",
+                    until - now
+                ));
+                let e = ErrorKind::Other.cause(fibers_rpc::Error::from(e));
+                return Err(track!(Error::from(e)));
+            }
+        }
+        Ok(())
+    }
+
+    /// ブレーカが作動中かどうかを返す.
+    pub fn is_open(&self) -> bool {
+        self.check().is_err()
+    }
+
+    /// サーバからの応答の受信を記録する.
+    pub fn record_success(&self) {
+        let mut state = self.lock();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+
+    /// 通信エラーの発生を記録する.
+    ///
+    /// 待機期間の経過後も連続失敗の回数は維持されるので、その後の最初のリクエストが失敗した場合には、
+    /// ブレーカは即座に再作動する.
+    pub fn record_failure(&self) {
+        let mut state = self.lock();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if state.consecutive_failures >= self.policy.failure_threshold {
+            state.open_until = Some(Instant::now() + self.policy.cooldown);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        // 状態の更新中にパニックすることはないので、ポイズンは無視して構わない
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    consecutive_failures: usize,
+    open_until: Option<Instant>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn circuit_breaker_works() {
+        let breaker = CircuitBreaker::new(CircuitBreakerPolicy {
+            failure_threshold: 2,
+            cooldown: Duration::from_millis(50),
+        });
+        assert!(breaker.check().is_ok());
+
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+        breaker.record_success();
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+        breaker.record_failure();
+        assert!(breaker.is_open());
+        assert_eq!(*breaker.check().err().unwrap().kind(), ErrorKind::Other);
+
+        // 待機期間の経過後は、リクエストの発行が再開される
+        thread::sleep(Duration::from_millis(60));
+        assert!(!breaker.is_open());
+
+        // 再開後の最初の失敗で再作動する
+        breaker.record_failure();
+        assert!(breaker.is_open());
+        breaker.record_success();
+        assert!(!breaker.is_open());
+    }
+}
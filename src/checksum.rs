@@ -0,0 +1,23 @@
+//! lumpデータのチェックサム.
+use adler32::RollingAdler32;
+use cannyls::{ErrorKind, Result};
+
+/// lumpデータのチェックサム(Adler-32)を計算する.
+pub(crate) fn compute(data: &[u8]) -> u32 {
+    RollingAdler32::from_buffer(data).hash()
+}
+
+/// lumpデータが期待するチェックサムを持つかどうかを検証する.
+///
+/// 一致しない場合には`ErrorKind::StorageCorrupted`エラーが返される.
+pub(crate) fn verify(data: &[u8], expected: u32) -> Result<()> {
+    let actual = compute(data);
+    track_assert!(
+        actual == expected,
+        ErrorKind::StorageCorrupted,
+        "Checksum mismatch: actual={:08x}, expected={:08x}",
+        actual,
+        expected
+    );
+    Ok(())
+}
@@ -1,50 +1,551 @@
 use cannyls::deadline::Deadline;
 use cannyls::lump::{LumpData, LumpHeader, LumpId};
-use cannyls::storage::StorageUsage;
+use cannyls::storage::{StorageHeader, StorageUsage};
 use cannyls::{Error, ErrorKind, Result};
-use fibers_rpc::{self, Call};
-use futures::{Async, Future, Poll};
+use fibers::sync::mpsc;
+use fibers::time::timer::{self, Timeout};
+use fibers_rpc::{self, Call, Cast};
+use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use slog::Level;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read, Write};
 use std::net::SocketAddr;
 use std::ops::Range;
-use trackable::error::ErrorKindExt;
+use std::time::{Duration, Instant};
 
-use crate::device::DeviceId;
-use crate::rpc;
+use crate::breaker::{CircuitBreaker, CircuitBreakerPolicy};
+use crate::checksum;
+use crate::client_interceptor::{
+    CallContext, ClientInterceptor, ClientInterceptors, RequestOptionsMut,
+};
+use crate::compat::{CompatSink, CompatStream, Spawner};
+use crate::device::{DeviceId, DeviceLabels, DeviceSettings, DeviceSpec};
+use crate::error::from_rpc_error;
+use crate::info::{
+    DeviceMetricsSnapshot, DeviceReadiness, DeviceStatusReport, DeviceSummary, ImportSessionStatus,
+    InFlightRequest, JournalUsage, RequestStats, ServerInfo,
+};
+use crate::loopback::{LocalCall, LocalResponse};
+use crate::metrics::{ClientMetrics, MetricsRecorder};
+use crate::protobuf::GetLumpToWriterResponseDecoder;
+use crate::resolver::Resolver;
+use crate::retry::{BusyRetry, BusyRetryPolicy};
+use crate::rpc::{self, DeleteRangeChunk, Precondition, ScriptOp, ScriptOpResult};
+#[cfg(feature = "server")]
+use crate::server::Server;
+use crate::signing::{SignableRequest, SigningKey};
+#[cfg(feature = "tracing")]
+use crate::span::{self, Span};
+
+/// `Client::deadline_timeout_slack`のデフォルト値.
+const DEFAULT_DEADLINE_TIMEOUT_SLACK: Duration = Duration::from_millis(500);
 
 /// RPCクライアント.
 #[derive(Debug, Clone)]
 pub struct Client {
     server: SocketAddr,
+    resolver: Option<Resolver>,
     rpc_service: fibers_rpc::client::ClientServiceHandle,
+    retry_policy: BusyRetryPolicy,
+    default_options: CallOptions,
+    deadline_timeout_slack: Option<Duration>,
+    breaker: Option<CircuitBreaker>,
+    spawner: Option<Spawner>,
+    metrics: ClientMetrics,
+    interceptors: ClientInterceptors,
+    signing_key: Option<SigningKey>,
+    #[cfg(feature = "server")]
+    loopback: Option<Server>,
 }
 impl Client {
     /// 新しい`Client`インスタンスを生成する.
     pub fn new(server: SocketAddr, rpc_service: fibers_rpc::client::ClientServiceHandle) -> Self {
         Client {
             server,
+            resolver: None,
             rpc_service,
+            retry_policy: BusyRetryPolicy::default(),
+            default_options: CallOptions::default(),
+            deadline_timeout_slack: Some(DEFAULT_DEADLINE_TIMEOUT_SLACK),
+            breaker: None,
+            spawner: None,
+            metrics: ClientMetrics::new(),
+            interceptors: ClientInterceptors::default(),
+            signing_key: None,
+            #[cfg(feature = "server")]
+            loopback: None,
+        }
+    }
+
+    /// `"ホスト名:ポート番号"`形式のエンドポイントを接続先とする、新しい`Client`インスタンスを生成する.
+    ///
+    /// エンドポイントのアドレスは、生成時に解決された後も、バックグラウンドで定期的に再解決される.
+    /// そのため、コンテナ環境等でサーバのIPアドレスが変わった場合にも、以後のリクエストは新しいアドレスに発行されるようになる.
+    /// 再解決の間隔は`set_resolve_interval`メソッドで変更可能(デフォルト値は`30s`).
+    ///
+    /// 再解決に失敗した場合には、最後に解決されたアドレスが使用され続ける.
+    /// また、一つのエンドポイントに複数のアドレスが対応する場合には、現在のアドレスが含まれている限りは、それが使用され続ける.
+    ///
+    /// 生成時の解決に失敗した場合には`ErrorKind::InvalidInput`エラーが返される.
+    pub fn with_endpoint(
+        endpoint: &str,
+        rpc_service: fibers_rpc::client::ClientServiceHandle,
+    ) -> Result<Self> {
+        let resolver = track!(Resolver::new(endpoint))?;
+        let mut client = Client::new(resolver.addr(), rpc_service);
+        client.resolver = Some(resolver);
+        Ok(client)
+    }
+
+    /// `with_endpoint`で指定されたエンドポイントを返す.
+    ///
+    /// `Client::new`で生成されたインスタンスの場合には`None`が返される.
+    pub fn endpoint(&self) -> Option<&str> {
+        self.resolver.as_ref().map(|r| r.endpoint())
+    }
+
+    /// エンドポイントのアドレスを再解決する間隔を設定する.
+    ///
+    /// 設定はこのクライアントのクローン間で共有され、次回の再解決の後から適用される.
+    /// `Client::new`で生成されたインスタンスの場合には、何も行われない.
+    ///
+    /// デフォルト値は`30s`.
+    pub fn set_resolve_interval(&mut self, interval: Duration) -> &mut Self {
+        if let Some(ref resolver) = self.resolver {
+            resolver.set_interval(interval);
         }
+        self
+    }
+
+    /// このクライアントから生成されるリクエストビルダの、再試行ポリシーの初期値を設定する.
+    ///
+    /// 詳細は`RequestBuilder::retry`を参照のこと.
+    ///
+    /// デフォルト値は`BusyRetryPolicy::default()`.
+    pub fn set_retry_policy(&mut self, policy: BusyRetryPolicy) -> &mut Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// 再試行ポリシーの初期値を返す.
+    pub fn retry_policy(&self) -> &BusyRetryPolicy {
+        &self.retry_policy
+    }
+
+    /// このクライアントから生成されるリクエストビルダの、デッドライン・キューの長さ制限・RPCレベルのオプションの初期値を設定する.
+    ///
+    /// サービス全体で共通の設定を、リクエストの発行箇所毎に指定せずに済むようにするためのもの.
+    /// `None`のフィールドには、ライブラリのデフォルト値が使用される.
+    /// いずれの値も、個々のビルダで(`RequestBuilder::deadline`等により)上書き可能.
+    ///
+    /// 設定はこのメソッドの呼び出し以降に生成されたビルダにのみ適用される.
+    ///
+    /// デフォルト値は`CallOptions::default()`.
+    pub fn set_default_options(&mut self, options: CallOptions) -> &mut Self {
+        self.default_options = options;
+        self
+    }
+
+    /// リクエストビルダの設定の初期値を返す.
+    pub fn default_options(&self) -> &CallOptions {
+        &self.default_options
+    }
+
+    /// デッドラインからRPCのタイムアウトを導出する際の猶予を設定する.
+    ///
+    /// `RequestBuilder::deadline`に`Deadline::Within(d)`が指定され、かつ`RequestBuilder::rpc_options`で
+    /// タイムアウトが明示的に指定されていない場合には、RPCのタイムアウトとして`d + slack`が使用される.
+    /// デッドラインを過ぎて成功し得なくなったリクエストが、サーバとの接続を占有し続けることを避けるためのもの.
+    /// 猶予は、通信やサーバ側のキューイングに要する時間を見込んだ値を指定すること.
+    ///
+    /// `None`を指定した場合には、タイムアウトの導出は行われない.
+    ///
+    /// デフォルト値は`Some(500ms)`.
+    pub fn set_deadline_timeout_slack(&mut self, slack: Option<Duration>) -> &mut Self {
+        self.deadline_timeout_slack = slack;
+        self
+    }
+
+    /// デッドラインからRPCのタイムアウトを導出する際の猶予を返す.
+    pub fn deadline_timeout_slack(&self) -> Option<Duration> {
+        self.deadline_timeout_slack
     }
 
     /// RPCリクエスト発行用のビルダを返す.
-    pub fn request(&self) -> RequestBuilder {
+    pub fn request(&self) -> RequestBuilder<'_> {
         RequestBuilder::new(self)
     }
+
+    /// RPCサーバのアドレスを返す.
+    ///
+    /// `with_endpoint`で生成されたインスタンスの場合には、最後に解決されたアドレスが返される.
+    pub fn server(&self) -> SocketAddr {
+        self.resolver.as_ref().map_or(self.server, |r| r.addr())
+    }
+
+    /// RPCの発行に使用されるクライアントサービスのハンドルを返す.
+    pub fn rpc_service(&self) -> &fibers_rpc::client::ClientServiceHandle {
+        &self.rpc_service
+    }
+
+    /// RPCサーバのバージョン、および、サーバが対応しているRPCの一覧を取得する.
+    ///
+    /// ローリングアップグレード中等に、特定のRPCにサーバが対応しているかどうかを、
+    /// 発行前に確認するために利用できる(`ServerInfo::supports`を参照).
+    ///
+    /// なお、このRPC自体に対応していない古いサーバに対しては、エラーが返される.
+    pub fn server_info(&self) -> Response<ServerInfo> {
+        let client = rpc::ServerInfoRpc::client(&self.rpc_service);
+        self.response(client, ())
+    }
+
+    /// RPCサーバとの接続を確立し、その疎通を確認する.
+    ///
+    /// `self.request().connect()`の短縮形.
+    /// 詳細は`RequestBuilder::connect`を参照のこと.
+    pub fn connect(&self) -> ConnectFuture {
+        self.request().connect()
+    }
+
+    /// async/awaitからリクエストを発行する際に使用される、`fibers`のエグゼキュータを設定する.
+    ///
+    /// 設定した場合には、`RequestBuilder`の各メソッドが返す`Future`(`Response`、`GetLumpFuture`等)を、
+    /// async関数内で直接`.await`できるようになる.
+    /// その際、リクエストは指定のエグゼキュータ上のファイバとして実行され、結果が`.await`の呼び出し元に渡される.
+    /// 詳細は`Compat`を参照のこと.
+    ///
+    /// 設定せずに`.await`した場合には、`ErrorKind::Other`エラーが返される.
+    /// `export_lumps`等が返す`Stream`(および`put_pipeline`が返す`Sink`)は、`compat`メソッドで同様に変換できる.
+    ///
+    /// デフォルトでは未設定.
+    pub fn set_spawner<S>(&mut self, spawner: S) -> &mut Self
+    where
+        S: fibers::Spawn + Send + 'static,
+    {
+        self.spawner = Some(Spawner::new(spawner));
+        self
+    }
+
+    /// サーキットブレーカを有効にする.
+    ///
+    /// 有効にした場合には、サーバとの通信エラー(e.g., タイムアウトや接続断)が
+    /// `policy.failure_threshold`回連続した時点でブレーカが作動し、以後`policy.cooldown`の間は、
+    /// このクライアント(およびそのクローン)から発行されるリクエストが、送信されずに即座に`ErrorKind::Other`で失敗するようになる.
+    /// 停止したサーバへのリクエストが、タイムアウトまで待たされ続けるのを避けるためのもの.
+    ///
+    /// サーバから応答(エラー応答を含む)を受信した時点で、連続失敗の回数はリセットされる.
+    ///
+    /// デフォルトでは無効.
+    pub fn enable_circuit_breaker(&mut self, policy: CircuitBreakerPolicy) -> &mut Self {
+        self.breaker = Some(CircuitBreaker::new(policy));
+        self
+    }
+
+    /// サーキットブレーカが作動中かどうかを返す.
+    ///
+    /// ブレーカが有効になっていない場合には、常に`false`が返される.
+    pub fn is_circuit_open(&self) -> bool {
+        self.breaker.as_ref().is_some_and(|b| b.is_open())
+    }
+
+    /// このクライアントから発行されたリクエストの、RPCおよびサーバ単位のメトリクスを返す.
+    ///
+    /// `RequestBuilder`の各メソッドが返す`Future`(`Response`、`GetLumpFuture`等)の結果が、
+    /// その完了時点で記録される(完了前に破棄されたリクエストは記録されない).
+    /// サーキットブレーカないしインターセプタによって拒否されたリクエストも、失敗として記録される.
+    /// なお`RequestBuilder::call`等の任意のRPCや、応答を待たない`*_noack`系のメソッドは対象外.
+    ///
+    /// メトリクスはこのクライアントのクローン間で共有される.
+    pub fn metrics(&self) -> &ClientMetrics {
+        &self.metrics
+    }
+
+    /// メトリクスの集計器を設定する.
+    ///
+    /// 複数のクライアントで同じ集計器を共有し、まとめて参照するためのもの.
+    /// 設定はこのメソッドの呼び出し以降に発行されたリクエストにのみ適用される.
+    ///
+    /// デフォルトでは、クライアントの生成時に新しい集計器が作られる.
+    pub fn set_metrics(&mut self, metrics: ClientMetrics) -> &mut Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// RPC呼び出しのインターセプタを登録する.
+    ///
+    /// インターセプタは、登録順に呼び出される.
+    /// 詳細は`ClientInterceptor`を参照のこと.
+    ///
+    /// インターセプタはこのクライアントのクローン間で共有されるが、
+    /// 登録はこのメソッドの呼び出し以降に発行されたリクエストにのみ適用される.
+    pub fn add_interceptor<I: ClientInterceptor>(&mut self, interceptor: I) -> &mut Self {
+        self.interceptors.add(interceptor);
+        self
+    }
+
+    /// リクエストの署名に用いる鍵を設定する.
+    ///
+    /// 設定した場合には、lumpやデバイスに対するリクエストに、HMAC-SHA256による署名(`rpc::RequestSignature`)が付与される.
+    /// サーバ側での検証については`Server::verify_signatures`を参照のこと.
+    ///
+    /// 署名はRPCの発行毎に(i.e., 再試行やヘッジリクエストの場合にも)新たに作成される.
+    /// なお`RequestBuilder::call`等の任意のRPCには付与されない.
+    ///
+    /// デフォルトでは、署名は付与されない.
+    pub fn set_signing_key(&mut self, key: SigningKey) -> &mut Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// 接続先のサーバと同じプロセス内にある`Server`を設定し、ループバックを有効にする.
+    ///
+    /// 有効にした場合には、以下のRPCは、シリアライズやTCP通信を経由せずに、
+    /// `server`のハンドラに対して直接発行されるようになる:
+    /// - `get_lump`(`verify_checksums`が無効な場合)・`head_lump`・`list_lumps`・`list_lumps_range`
+    /// - `put_lump`・`delete_lump`
+    /// - `usage_range`・`delete_range`
+    ///
+    /// これら以外のRPCは、通常通りにサーバに発行される.
+    /// どちらの場合も、呼び出し側のコードを変更する必要はなく、メトリクスやインターセプタ、サーキットブレーカも同様に適用される.
+    ///
+    /// ループバックのリクエストも、サーバ側ではTCP経由のものと同じ処理を経由する.
+    /// 具体的には、署名の検証(`set_signing_key`で設定された鍵による署名も通常通りに付与される)やアクセス制御、
+    /// サーバ側のインターセプタ、アクセスログ、`MutationObserver`への通知、lump単位の排他制御、
+    /// 実行中のリクエストとしての登録、および、統計情報やメトリクスの記録は、いずれもループバックでも行われる.
+    /// また、`server`に登録されていないRPC(e.g., `Server::register_read_only`で除外された更新系のRPC)は、
+    /// ループバックでは処理されずに、通常通りにサーバに発行される.
+    ///
+    /// `server`は`Server::register`等で登録する前に複製したもので良い
+    /// (登録されたRPCの一覧は、複製間で共有される).
+    /// ただし、登録前の時点では、全てのRPCがサーバに発行される.
+    ///
+    /// デフォルトでは、ループバックは無効.
+    #[cfg(feature = "server")]
+    pub fn enable_loopback(&mut self, server: Server) -> &mut Self {
+        self.loopback = Some(server);
+        self
+    }
+
+    // インターセプタとサーキットブレーカを考慮しつつ、RPCを発行する.
+    fn response<C, T>(
+        &self,
+        mut client: fibers_rpc::client::CallClient<C>,
+        mut request: C::Req,
+    ) -> Response<T>
+    where
+        C: LocalCall<T>,
+        C::Req: RequestOptionsMut + SignableRequest,
+    {
+        let server = self.server();
+        let recorder = self.metrics.recorder(C::NAME, server);
+        #[cfg(feature = "tracing")]
+        let span = span::client_span(C::NAME, server);
+        #[cfg(feature = "tracing")]
+        let issued_at = Instant::now();
+        let rejected = |e: Error, recorder: MetricsRecorder| {
+            let result = Err(e);
+            recorder.record::<T>(&result);
+            #[cfg(feature = "tracing")]
+            span::record_result(&span, &result, issued_at.elapsed());
+            Response {
+                server,
+                inner: ResponseInner::Rejected(result.err()),
+                breaker: None,
+                hedge: None,
+                spawner: self.spawner.clone(),
+                metrics: None,
+                interceptors: None,
+                #[cfg(feature = "tracing")]
+                span: None,
+            }
+        };
+
+        let context = if self.interceptors.is_empty() {
+            None
+        } else {
+            let mut context = CallContext {
+                procedure: C::NAME,
+                server,
+                options: request.request_options_mut().cloned(),
+                rpc_options: client.options().clone(),
+            };
+            if let Err(e) = self.interceptors.before_call(&mut context) {
+                return rejected(e, recorder);
+            }
+            if let (Some(options), Some(ref new)) =
+                (request.request_options_mut(), &context.options)
+            {
+                *options = new.clone();
+            }
+            *client.options_mut() = context.rpc_options.clone();
+            Some(context)
+        };
+        if let Some(e) = self.check_circuit().err() {
+            if let Some(ref context) = context {
+                self.interceptors
+                    .after_call(context, Err(&e), Duration::default());
+            }
+            return rejected(e, recorder);
+        }
+        self.sign(C::NAME, &mut request);
+        #[cfg(feature = "server")]
+        let request = match self.loopback {
+            None => request,
+            Some(ref loopback) => match C::call_local(loopback, request) {
+                Err(request) => request,
+                Ok(future) => {
+                    return Response {
+                        server,
+                        inner: ResponseInner::Local(future),
+                        breaker: self.breaker.clone(),
+                        hedge: None,
+                        spawner: self.spawner.clone(),
+                        metrics: Some(recorder),
+                        interceptors: context
+                            .map(|c| (self.interceptors.clone(), c, Instant::now())),
+                        #[cfg(feature = "tracing")]
+                        span: Some((span, issued_at)),
+                    };
+                }
+            },
+        };
+        Response {
+            server,
+            inner: ResponseInner::Pending(client.call(server, request)),
+            breaker: self.breaker.clone(),
+            hedge: None,
+            spawner: self.spawner.clone(),
+            metrics: Some(recorder),
+            interceptors: context.map(|c| (self.interceptors.clone(), c, Instant::now())),
+            #[cfg(feature = "tracing")]
+            span: Some((span, issued_at)),
+        }
+    }
+
+    // 鍵が設定されている場合には、リクエストに署名する.
+    fn sign<R>(&self, procedure: &'static str, request: &mut R)
+    where
+        R: RequestOptionsMut + SignableRequest,
+    {
+        let key = if let Some(ref key) = self.signing_key {
+            key
+        } else {
+            return;
+        };
+        let signature = if let Some((device_id, target)) = request.signing_target() {
+            key.sign(
+                procedure,
+                device_id,
+                &target,
+                &request.params(),
+                &request.payload(),
+            )
+        } else {
+            return;
+        };
+        if let Some(options) = request.request_options_mut() {
+            options.signature = Some(signature);
+        }
+    }
+
+    fn check_circuit(&self) -> Result<()> {
+        if let Some(ref breaker) = self.breaker {
+            track!(breaker.check(); self.server())?;
+        }
+        Ok(())
+    }
+}
+
+/// 個々のリクエストに対して、ビルダの設定を上書きするためのオプション.
+///
+/// `RequestBuilder::get_lump_with_opts`等に渡すことで、デッドライン等のリクエスト毎に変わる設定のためだけに、
+/// 新しいビルダを構築し直すことを避けられる.
+/// また`Client::set_default_options`に渡すことで、ビルダの設定の初期値としても使用できる.
+///
+/// `None`のフィールドには、ビルダに指定されている値がそのまま使用される.
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    /// リクエスト処理のデッドライン.
+    ///
+    /// 詳細は`RequestBuilder::deadline`を参照のこと.
+    pub deadline: Option<Deadline>,
+
+    /// リクエスト処理時のデバイスのキューの長さ制限.
+    ///
+    /// 詳細は`RequestBuilder::max_queue_len`を参照のこと.
+    pub max_queue_len: Option<usize>,
+
+    /// RPCレベルのオプション.
+    ///
+    /// 詳細は`RequestBuilder::rpc_options`を参照のこと.
+    pub rpc_options: Option<fibers_rpc::client::Options>,
+}
+
+/// リクエストの設定を保持するテンプレート.
+///
+/// `RequestBuilder::to_template`で生成され、そのビルダに指定されていた
+/// デッドライン・キューの長さ制限・優先度・チェックサム検証の有無・RPCレベルのオプション・再試行ポリシー・
+/// ヘッジリクエストの設定を保持する.
+/// 事前条件(e.g., `RequestBuilder::if_exists`)とトレースID(`RequestBuilder::trace_id`)は
+/// 個々の操作に固有のものなので、保持されない.
+///
+/// テンプレートはクライアントを所有しているので、安価にクローンして、
+/// 複数のスレッドやタスク間で共有することが可能.
+#[derive(Debug, Clone)]
+pub struct RequestTemplate {
+    client: Client,
+    deadline: Option<Deadline>,
+    max_queue_len: Option<usize>,
+    prioritized: bool,
+    verbose_errors: bool,
+    verify_checksums: bool,
+    rpc_options: fibers_rpc::client::Options,
+    retry_policy: BusyRetryPolicy,
+    hedge: Option<(Client, Duration)>,
+}
+impl RequestTemplate {
+    /// テンプレートの設定が適用されたリクエストビルダを返す.
+    pub fn request(&self) -> RequestBuilder<'_> {
+        RequestBuilder {
+            client: Cow::Borrowed(&self.client),
+            deadline: self.deadline,
+            max_queue_len: self.max_queue_len,
+            prioritized: self.prioritized,
+            verbose_errors: self.verbose_errors,
+            verify_checksums: self.verify_checksums,
+            precondition: Precondition::default(),
+            trace_id: None,
+            rpc_options: self.rpc_options.clone(),
+            retry_policy: self.retry_policy.clone(),
+            hedge: self.hedge.clone(),
+        }
+    }
 }
 
 /// RPCリクエストビルダ.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RequestBuilder<'a> {
-    client: &'a Client,
+    client: Cow<'a, Client>,
     deadline: Option<Deadline>,
     max_queue_len: Option<usize>,
     prioritized: bool,
+    verbose_errors: bool,
+    verify_checksums: bool,
+    precondition: Precondition,
+    trace_id: Option<String>,
     rpc_options: fibers_rpc::client::Options,
+    retry_policy: BusyRetryPolicy,
+    hedge: Option<(Client, Duration)>,
 }
 impl<'a> RequestBuilder<'a> {
     /// リクエスト処理のデッドライン(優先度)を指定する.
     ///
-    /// デフォルト値は`Deadline::Infinity`.
+    /// `Deadline::Within`が指定された場合には、RPCのタイムアウトも自動で設定される
+    /// (詳細は`Client::set_deadline_timeout_slack`を参照のこと).
+    ///
+    /// デフォルト値は`Client::default_options`の値で、それも未指定の場合には`Deadline::Infinity`.
     pub fn deadline(&mut self, deadline: Deadline) -> &mut Self {
         self.deadline = Some(deadline);
         self
@@ -55,7 +556,7 @@ impl<'a> RequestBuilder<'a> {
     /// このリクエストの処理時に、デバイスの要求キューの長さが、指定された値を超えている場合には、
     /// リクエストは処理されずに`ErrorKind::DeviceBusy`エラーが返される.
     ///
-    /// デフォルトでは制限なし.
+    /// デフォルト値は`Client::default_options`の値で、それも未指定の場合には制限なし.
     pub fn max_queue_len(&mut self, n: usize) -> &mut Self {
         self.max_queue_len = Some(n);
         self
@@ -71,234 +572,2450 @@ impl<'a> RequestBuilder<'a> {
         self
     }
 
+    /// サーバの`Server::error_verbosity`の設定に関わらず、エラー応答に完全なエラー(履歴を含む)を含めるように要求する.
+    ///
+    /// lumpに対する操作やスクリプトRPC等、リクエストのオプションを伴うRPCに対してのみ有効.
+    /// また、サーバ側で`Server::allow_verbose_errors`が呼ばれていない場合には、この要求は無視される.
+    pub fn verbose_errors(&mut self) -> &mut Self {
+        self.verbose_errors = true;
+        self
+    }
+
+    /// 呼び出し元のリクエストを識別するためのID(e.g., 分散トレーシングのトレースID)を指定する.
+    ///
+    /// IDはサーバ側のログレコードに`trace_id`として付与され、またエラー応答の履歴にも記録されるので、
+    /// 上位のサービスのリクエストと、それによって発行されたRPCとを対応付けることができる.
+    ///
+    /// lumpに対する操作やスクリプトRPC等、リクエストのオプションを伴うRPCに対してのみ有効.
+    pub fn trace_id<T: Into<String>>(&mut self, id: T) -> &mut Self {
+        self.trace_id = Some(id.into());
+        self
+    }
+
+    /// lumpデータのチェックサム(Adler-32)による、エンドツーエンドの整合性検証を行うかどうかを指定する.
+    ///
+    /// 有効な場合には、`put_lump`では送信前にデータのチェックサムが計算されてリクエストに付与され、
+    /// サーバ側で保存前に検証される.
+    /// また`get_lump`(および`get_lumps_concurrent`)では、サーバがチェックサムと共にデータを返し
+    /// (`rpc::GetLumpWithChecksumRpc`)、クライアント側で受信したデータが検証される.
+    ///
+    /// 検証に失敗した場合には、いずれも`ErrorKind::StorageCorrupted`エラーが返される.
+    ///
+    /// ストリーミング版の操作(`put_lump_from_reader`と`get_lump_to_writer`)や、
+    /// 一括取得・保存(`get_lumps`と`put_lumps`)、スクリプトRPCは対象外.
+    /// また、サーバがこの機能に対応していない場合には、`put_lump`時の検証は行われず、
+    /// `get_lump`は失敗するので注意が必要.
+    ///
+    /// デフォルト値は`false`.
+    pub fn verify_checksums(&mut self, enabled: bool) -> &mut Self {
+        self.verify_checksums = enabled;
+        self
+    }
+
+    /// 読み込み系の操作(`get_lump`と`head_lump`)で、ヘッジリクエストを有効にする.
+    ///
+    /// 有効にした場合には、`delay`が経過しても応答が得られない(ないし失敗した)時点で、
+    /// 同じリクエストが`alternate`(e.g., 同じデータの複製を保持する別のサーバ)にも発行され、
+    /// 先に成功した方の結果が採用される.
+    /// 一部のサーバの遅延による、読み込みのテールレイテンシを削減するためのもの.
+    ///
+    /// 両方のリクエストが失敗した場合には、元のリクエストのエラーが返される.
+    ///
+    /// デフォルトでは無効.
+    pub fn hedge(&mut self, alternate: &Client, delay: Duration) -> &mut Self {
+        self.hedge = Some((alternate.clone(), delay));
+        self
+    }
+
+    /// 対象lumpが存在する場合にのみ操作を実行するようにする.
+    ///
+    /// `put_lump`と組み合わせた場合には、既存のlumpの上書きのみが許可される(i.e., overwrite-only).
+    ///
+    /// この条件は`put_lump`と`delete_lump`に対してのみ適用され、サーバ側で評価される.
+    /// 条件を満たさない場合には`ErrorKind::InvalidInput`エラーが返される.
+    /// そのエラーが事前条件の違反によるものかどうかは`rpc::Precondition::is_failure`で判定可能.
+    ///
+    /// 条件の評価と操作は、サーバ側で同じlumpに対する他の更新(`put_lump`や`delete_lump`に加えて、
+    /// スクリプトや`put_lumps`・`delete_range`等の複数lumpの一括操作を含む)と直列化されるため、
+    /// その間に他の更新が割り込むことはない.
+    pub fn if_exists(&mut self) -> &mut Self {
+        self.precondition.if_exists = true;
+        self
+    }
+
+    /// 対象lumpが存在しない場合にのみ操作を実行するようにする.
+    ///
+    /// `put_lump`と組み合わせた場合には、lumpの新規作成のみが許可される(i.e., create-only).
+    ///
+    /// 適用範囲やエラーについては`if_exists`メソッドと同様.
+    pub fn if_not_exists(&mut self) -> &mut Self {
+        self.precondition.if_not_exists = true;
+        self
+    }
+
+    /// 対象lumpのデータサイズが指定値と一致する場合にのみ操作を実行するようにする.
+    ///
+    /// サイズは`LumpHeader::approximate_data_size`と比較される.
+    /// 対象lumpが存在しない場合には、条件を満たさないものとして扱われる.
+    ///
+    /// 適用範囲やエラーについては`if_exists`メソッドと同様.
+    pub fn if_size_equals(&mut self, size: u32) -> &mut Self {
+        self.precondition.if_size_equals = Some(size);
+        self
+    }
+
     /// RPCレベルのオプションを指定する.
     ///
-    /// デフォルト値は`fibers_rpc::client::Options::default()`.
+    /// タイムアウトが指定されていない場合には、デッドラインから導出された値が使用されることがある
+    /// (詳細は`Client::set_deadline_timeout_slack`を参照のこと).
+    ///
+    /// デフォルト値は`Client::default_options`の値で、それも未指定の場合には`fibers_rpc::client::Options::default()`.
     pub fn rpc_options(&mut self, options: fibers_rpc::client::Options) -> &mut Self {
         self.rpc_options = options;
         self
     }
 
-    /// Lumpデータの取得を行う.
+    /// 任意のRPCを、このクライアントの接続先およびRPCレベルのオプションを使って発行する.
     ///
-    /// 指定されたlumpが存在しない場合には`Ok(None)`が返される.
+    /// アプリケーション独自のRPCを、`cannyls_rpc`のRPCと同じ接続・設定で発行するためのもの.
+    /// デッドライン等の、cannyls固有のオプションは適用されない(サーキットブレーカも考慮されない).
+    pub fn call<T>(&self, request: T::Req) -> fibers_rpc::client::Response<T::Res>
+    where
+        T: Call,
+        T::ReqEncoder: Default,
+        T::ResDecoder: Default,
+    {
+        let mut client = T::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+        client.call(self.client.server(), request)
+    }
+
+    /// 任意の通知RPCを、このクライアントの接続先およびRPCレベルのオプションを使って発行する.
     ///
-    /// # Errors
+    /// 扱いは`call`メソッドと同様.
+    pub fn cast<T>(&self, notification: T::Notification) -> fibers_rpc::Result<()>
+    where
+        T: Cast,
+        T::Encoder: Default,
+    {
+        let mut client = T::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+        track!(client.cast(self.client.server(), notification))
+    }
+
+    /// RPCサーバとの接続を確立し、その疎通を確認する.
     ///
-    /// 例えば、以下のようなエラーが返されることがある:
-    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
-    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
-    pub fn get_lump(
-        &self,
-        device_id: DeviceId,
-        lump_id: LumpId,
-    ) -> impl Future<Item = Option<Vec<u8>>, Error = Error> {
-        let mut client = rpc::GetLumpRpc::client(&self.client.rpc_service);
-        *client.options_mut() = self.rpc_options.clone();
+    /// 起動直後の最初のリクエストが、短いデッドラインの中で接続の確立を待たされることがないように、
+    /// 事前に接続を温めておくためのもの.
+    ///
+    /// 疎通の確認には`rpc::ServerInfoRpc`が使用され、サーバから何らかの応答(エラー応答を含む)が得られた時点で完了する.
+    /// 接続に失敗した場合(ないし`rpc_options`で指定したタイムアウトに達した場合)には、エラーが返される.
+    ///
+    /// なお、サーキットブレーカの状態は考慮されず、また更新もされない.
+    pub fn connect(&self) -> ConnectFuture {
+        let mut client = rpc::ServerInfoRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+        let server = self.client.server();
+        ConnectFuture {
+            server,
+            inner: client.call(server, ()),
+            spawner: self.client.spawner.clone(),
+        }
+    }
 
-        let request = self.lump_request(device_id, lump_id);
-        let future = Response::new(self.client.server, client.call(self.client.server, request));
-        future.map(|data| data.map(|d| d.into_bytes()))
+    /// ビルダの現在の設定を保持するテンプレートを生成する.
+    pub fn to_template(&self) -> RequestTemplate {
+        RequestTemplate {
+            client: (*self.client).clone(),
+            deadline: self.deadline,
+            max_queue_len: self.max_queue_len,
+            prioritized: self.prioritized,
+            verbose_errors: self.verbose_errors,
+            verify_checksums: self.verify_checksums,
+            rpc_options: self.rpc_options.clone(),
+            retry_policy: self.retry_policy.clone(),
+            hedge: self.hedge.clone(),
+        }
     }
 
-    /// Lumpヘッダ(要約情報)の取得を行う.
+    /// `retry`メソッドで使用される再試行ポリシーを指定する.
     ///
-    /// 指定されたlumpが存在しない場合には`Ok(None)`が返される.
+    /// デフォルト値は`Client::retry_policy`.
+    pub fn retry_policy(&mut self, policy: BusyRetryPolicy) -> &mut Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// 失敗したリクエストを、このビルダに設定されている再試行ポリシーに従って再試行する.
     ///
-    /// # Errors
+    /// ポリシーを引数で指定しない点を除いて、`retry_on_busy`と同様.
+    /// クライアント単位で再試行ポリシーを設定しておくことで、利用側毎に再試行の設定を持ち回る必要がなくなる.
     ///
-    /// 例えば、以下のようなエラーが返されることがある:
-    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
-    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
-    pub fn head_lump(
-        &self,
-        device_id: DeviceId,
-        lump_id: LumpId,
-    ) -> impl Future<Item = Option<LumpHeader>, Error = Error> {
-        let mut client = rpc::HeadLumpRpc::client(&self.client.rpc_service);
-        *client.options_mut() = self.rpc_options.clone();
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate cannyls;
+    /// # extern crate cannyls_rpc;
+    /// # extern crate fibers_rpc;
+    /// # extern crate futures;
+    /// # use cannyls::lump::LumpId;
+    /// # use cannyls::ErrorKind;
+    /// # use cannyls_rpc::{BusyRetryPolicy, Client, DeviceId};
+    /// # use futures::Future;
+    /// # fn main() {
+    /// # let mut client: Client = unimplemented!();
+    /// client.set_retry_policy(BusyRetryPolicy {
+    ///     max_retries: 3,
+    ///     retryable_kinds: vec![ErrorKind::DeviceBusy, ErrorKind::Other],
+    ///     ..Default::default()
+    /// });
+    /// let future = client
+    ///     .request()
+    ///     .retry(|request| request.head_lump(DeviceId::new("foo"), LumpId::new(0)));
+    /// # }
+    /// ```
+    pub fn retry<F, T>(&self, request: F) -> BusyRetry<'a, F, T>
+    where
+        F: for<'b> FnMut(&RequestBuilder<'b>) -> T,
+        T: Future<Error = Error>,
+    {
+        BusyRetry::new(self.clone(), self.retry_policy.clone(), request)
+    }
 
-        let request = self.lump_request(device_id, lump_id);
-        Response::new(self.client.server, client.call(self.client.server, request))
+    /// `ErrorKind::DeviceBusy`等で失敗したリクエストを、指定のポリシーに従って再試行する.
+    ///
+    /// `request`には、このビルダ(のコピー)を使ってリクエストを発行する関数を指定する.
+    /// この関数は、初回および再試行の度に呼び出される.
+    /// 再試行の対象となるエラーは`BusyRetryPolicy::retryable_kinds`および`BusyRetryPolicy::retry_transient`で指定される.
+    ///
+    /// `DeviceBusy`はデバイスのキューに追加される前に返されるエラーなので、
+    /// 更新系の操作であっても、再試行によって操作が重複して実行されることはない.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate cannyls;
+    /// # extern crate cannyls_rpc;
+    /// # extern crate fibers_rpc;
+    /// # extern crate futures;
+    /// # use cannyls::lump::LumpId;
+    /// # use cannyls_rpc::{BusyRetryPolicy, Client, DeviceId};
+    /// # use futures::Future;
+    /// # fn main() {
+    /// # let client: Client = unimplemented!();
+    /// let future = client
+    ///     .request()
+    ///     .max_queue_len(100)
+    ///     .retry_on_busy(BusyRetryPolicy::default(), |request| {
+    ///         request.get_lump(DeviceId::new("foo"), LumpId::new(0))
+    ///     });
+    /// # }
+    /// ```
+    pub fn retry_on_busy<F, T>(&self, policy: BusyRetryPolicy, request: F) -> BusyRetry<'a, F, T>
+    where
+        F: for<'b> FnMut(&RequestBuilder<'b>) -> T,
+        T: Future<Error = Error>,
+    {
+        BusyRetry::new(self.clone(), policy, request)
     }
 
-    /// Lumpの保存を行う.
+    /// Lumpデータの一部(バイト範囲)の取得を行う.
     ///
-    /// 返り値が`Ok(true)`の場合には新規作成が、`Ok(false)`の場合には上書きが、行われたことを表している.
+    /// 大きなlumpの先頭部分等のみが必要な場合に、データ全体の転送とメモリ確保を避けるためのもの.
+    /// 範囲の終端がデータの末尾を超える場合には、末尾までのデータが返される.
+    ///
+    /// 指定されたlumpが存在しない場合には`Ok(None)`が返される.
+    /// なお`verify_checksums`の指定は、このメソッドには適用されない.
     ///
     /// # Errors
     ///
     /// 例えば、以下のようなエラーが返されることがある:
-    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが存在しない場合や、範囲が不正な場合には`ErrorKind::InvalidInput`
     /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
-    /// - 指定されたデバイスの容量が満杯になっている場合には`ErrorKind::Full`
-    ///
-    /// # 注意
-    ///
-    /// 現在は指定されたデータをジャーナル領域に埋め込むかどうかはRPCサーバ側が決定するため
-    /// `LumpData::new_embedded`関数を用いて`LumpData`を生成しても意味はない.
-    pub fn put_lump(
+    pub fn get_lump_range(
         &self,
         device_id: DeviceId,
         lump_id: LumpId,
-        lump_data: LumpData,
-    ) -> impl Future<Item = bool, Error = Error> {
-        let mut client = rpc::PutLumpRpc::client(&self.client.rpc_service);
-        *client.options_mut() = self.rpc_options.clone();
+        byte_range: Range<u64>,
+    ) -> GetLumpFuture {
+        let mut client = rpc::GetLumpRangeRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
 
-        let request = rpc::PutLumpRequest {
+        let request = rpc::GetLumpRangeRequest {
             device_id,
             lump_id,
-            lump_data,
+            byte_range,
             options: self.request_options(),
         };
-        Response::new(self.client.server, client.call(self.client.server, request))
+        let future = self.client.response(client, request);
+        GetLumpFuture(GetLumpFutureInner::Plain(future))
     }
 
-    /// Lumpの削除を行う.
+    /// Lumpデータの取得を行う.
     ///
-    /// 返り値が`Ok(true)`の場合には削除が行われたことを、
-    /// `Ok(false)`の場合には対象lumpが存在しなかったことを、表している.
+    /// 指定されたlumpが存在しない場合には`Ok(None)`が返される.
     ///
     /// # Errors
     ///
     /// 例えば、以下のようなエラーが返されることがある:
     /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
     /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
-    pub fn delete_lump(
+    pub fn get_lump(&self, device_id: DeviceId, lump_id: LumpId) -> GetLumpFuture {
+        self.get_lump_inner(device_id, lump_id, None)
+    }
+
+    // `opts`が指定された場合には、その設定でビルダの設定を上書きして`get_lump`を実行する.
+    fn get_lump_inner(
         &self,
         device_id: DeviceId,
         lump_id: LumpId,
-    ) -> impl Future<Item = bool, Error = Error> {
-        let mut client = rpc::DeleteLumpRpc::client(&self.client.rpc_service);
-        *client.options_mut() = self.rpc_options.clone();
+        opts: Option<&CallOptions>,
+    ) -> GetLumpFuture {
+        let request = self.lump_request_with(device_id, lump_id, opts);
+        if self.verify_checksums {
+            let mut client = rpc::GetLumpWithChecksumRpc::client(&self.client.rpc_service);
+            *client.options_mut() = self.effective_rpc_options_with(opts);
 
-        let request = self.lump_request(device_id, lump_id);
-        Response::new(self.client.server, client.call(self.client.server, request))
+            let future = self.client.response(client, request.clone());
+            let future = self.hedge_read::<rpc::GetLumpWithChecksumRpc, _>(future, request, opts);
+            GetLumpFuture(GetLumpFutureInner::Checksummed(future))
+        } else {
+            let mut client = rpc::GetLumpRpc::client(&self.client.rpc_service);
+            *client.options_mut() = self.effective_rpc_options_with(opts);
+
+            let future = self.client.response(client, request.clone());
+            let future = self.hedge_read::<rpc::GetLumpRpc, _>(future, request, opts);
+            GetLumpFuture(GetLumpFutureInner::Plain(future))
+        }
     }
 
-    /// デバイスに保存されているlumpのID一覧を取得する.
-    ///
-    /// # Errors
+    /// `opts`でビルダの設定を上書きした上で、`get_lump`を実行する.
     ///
-    /// 例えば、以下のようなエラーが返されることがある:
-    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
-    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
-    pub fn list_lumps(
+    /// ビルダ自体の設定は変更されないので、同じビルダを使って、異なるオプションのリクエストを繰り返し発行できる.
+    pub fn get_lump_with_opts(
         &self,
         device_id: DeviceId,
-    ) -> impl Future<Item = Vec<LumpId>, Error = Error> {
-        let mut client = rpc::ListLumpRpc::client(&self.client.rpc_service);
-        *client.options_mut() = self.rpc_options.clone();
+        lump_id: LumpId,
+        opts: &CallOptions,
+    ) -> GetLumpFuture {
+        self.get_lump_inner(device_id, lump_id, Some(opts))
+    }
 
-        let request = rpc::DeviceRequest {
-            device_id,
-            options: self.request_options(),
+    /// `get_lump`と同様だが、サーバでの処理状況を表すメタ情報を併せて返す.
+    ///
+    /// メタ情報(`rpc::ResponseMeta`)は、サーバ側の処理時間やデバイスのキューの長さ等を含み、遅延の調査に利用できる.
+    /// メタ情報はリクエストが成功した場合にのみ返され、メタ情報に対応していないサーバの場合には`None`となる.
+    ///
+    /// なお`verify_checksums`の指定は、このメソッドには適用されない.
+    pub fn get_lump_with_meta(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+    ) -> Response<(Option<LumpData>, Option<rpc::ResponseMeta>)> {
+        let mut client = rpc::GetLumpWithMetaRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let mut request = self.lump_request(device_id, lump_id);
+        request.options.response_meta = true;
+        let future = self.client.response(client, request.clone());
+        self.hedge_read::<rpc::GetLumpWithMetaRpc, _>(future, request, None)
+    }
+
+    /// Lumpデータの取得を行い、その内容を`writer`に順次出力する.
+    ///
+    /// lumpデータは受信したそばから`writer`に書き込まれるため、全体がメモリ上に保持されることはない.
+    /// 巨大なlumpをファイルやソケットにエクスポートする場合に有用.
+    ///
+    /// 結果は`writer`に出力されたバイト数で、指定されたlumpが存在しない場合には`Ok(None)`が返される.
+    ///
+    /// なお`writer`への書き込みは、RPCクライアントの処理の中で同期的に行われるので、
+    /// 書き込みが長時間ブロックするような出力先の扱いには注意が必要.
+    ///
+    /// # Errors
+    ///
+    /// `get_lump`が返すエラーに加えて、`writer`への書き込みに失敗した場合にもエラーが返される.
+    /// エラー時には、`writer`にはlumpデータの途中までが出力されている可能性がある.
+    pub fn get_lump_to_writer<W>(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        writer: W,
+    ) -> GetLumpToWriterFuture
+    where
+        W: Write + Send + 'static,
+    {
+        let decoder = GetLumpToWriterResponseDecoder::new(writer);
+        let mut client =
+            rpc::GetLumpToWriterRpc::client_with_decoder(&self.client.rpc_service, decoder);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = self.lump_request(device_id, lump_id);
+        self.client.response(client, request)
+    }
+
+    /// 複数のlumpの取得を、個別の`get_lump`の並行発行によって行う.
+    ///
+    /// 同時に発行されるリクエストの数は、最大で`max_in_flight`個(`0`の場合は`1`として扱われる)に制限される.
+    /// 一括取得用のRPC(`get_lumps`)をサポートしていないサーバを対象とする場合や、
+    /// lumpのサイズのばらつきが大きい場合に有用.
+    ///
+    /// 結果は`LumpId`をキーとするマップとして返され、個々のlumpの取得結果は独立している.
+    /// (i.e., 一部のlumpの取得に失敗しても、他のlumpの取得は継続される)
+    /// `lump_ids`内で重複しているIDに対しては、一度だけ取得が行われる.
+    pub fn get_lumps_concurrent(
+        &self,
+        device_id: DeviceId,
+        mut lump_ids: Vec<LumpId>,
+        max_in_flight: usize,
+    ) -> GetLumpsConcurrentFuture<'a> {
+        lump_ids.sort();
+        lump_ids.dedup();
+        GetLumpsConcurrentFuture {
+            builder: self.clone(),
+            device_id,
+            pending: lump_ids.into_iter(),
+            in_flight: Vec::new(),
+            max_in_flight: max_in_flight.max(1),
+            results: BTreeMap::new(),
+        }
+    }
+
+    /// 大量のlumpの保存を、並行度を制限しつつパイプライン的に行うための`Sink`と`Stream`の組を返す.
+    ///
+    /// `Sink`に`(DeviceId, LumpId, LumpData)`を送る度に`put_lump`が発行され、
+    /// その結果は、対になる`Stream`から完了順に`(DeviceId, LumpId, Result<bool>)`として得られる.
+    /// 同時に発行されるリクエストの数は、最大で`max_in_flight`個(`0`の場合は`1`として扱われる)に制限され、
+    /// 上限に達している間は、`Sink`への送信は完了を待たされる.
+    ///
+    /// リクエストの完了は`Stream`側のポーリングによって検知されるので、
+    /// `Sink`への送信と並行して、`Stream`から結果を取り出し続ける必要がある点には注意が必要.
+    /// `Stream`は、`Sink`がクローズ(ないしドロップ)され、かつ、全ての結果を返した時点で終端に達する.
+    ///
+    /// 個々のリクエストには、このビルダの設定(事前条件等を含む)が適用される.
+    pub fn put_pipeline(&self, max_in_flight: usize) -> (PutPipelineSink<'a>, PutPipelineResults) {
+        let max_in_flight = max_in_flight.max(1);
+        let (request_tx, request_rx) = mpsc::channel();
+        let (credit_tx, credit_rx) = mpsc::channel();
+        let sink = PutPipelineSink {
+            builder: self.clone(),
+            credits: max_in_flight,
+            credit_rx,
+            request_tx: Some(request_tx),
+        };
+        let results = PutPipelineResults {
+            request_rx,
+            credit_tx,
+            in_flight: Vec::new(),
+            closed: false,
+            spawner: self.client.spawner.clone(),
         };
-        Response::new(self.client.server, client.call(self.client.server, request))
+        (sink, results)
     }
 
-    /// lumpの範囲を指定してデバイスのストレージ使用量を取得する.
+    /// Lumpヘッダ(要約情報)の取得を行う.
+    ///
+    /// 指定されたlumpが存在しない場合には`Ok(None)`が返される.
     ///
     /// # Errors
     ///
     /// 例えば、以下のようなエラーが返されることがある:
     /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
     /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
-    pub fn usage_range(
+    pub fn head_lump(&self, device_id: DeviceId, lump_id: LumpId) -> HeadLumpFuture {
+        self.head_lump_inner(device_id, lump_id, None)
+    }
+
+    fn head_lump_inner(
         &self,
         device_id: DeviceId,
-        range: Range<LumpId>,
-    ) -> impl Future<Item = StorageUsage, Error = Error> {
-        let mut client = rpc::UsageRangeRpc::client(&self.client.rpc_service);
-        *client.options_mut() = self.rpc_options.clone();
+        lump_id: LumpId,
+        opts: Option<&CallOptions>,
+    ) -> HeadLumpFuture {
+        let mut client = rpc::HeadLumpRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options_with(opts);
 
-        let request = rpc::UsageRangeRequest {
+        let request = self.lump_request_with(device_id, lump_id, opts);
+        let future = self.client.response(client, request.clone());
+        self.hedge_read::<rpc::HeadLumpRpc, _>(future, request, opts)
+    }
+
+    /// `opts`でビルダの設定を上書きした上で、`head_lump`を実行する.
+    ///
+    /// 詳細は`get_lump_with_opts`を参照のこと.
+    pub fn head_lump_with_opts(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        opts: &CallOptions,
+    ) -> HeadLumpFuture {
+        self.head_lump_inner(device_id, lump_id, Some(opts))
+    }
+
+    /// `head_lump`と同様だが、サーバでの処理状況を表すメタ情報を併せて返す.
+    ///
+    /// 詳細は`get_lump_with_meta`を参照のこと.
+    pub fn head_lump_with_meta(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+    ) -> Response<(Option<LumpHeader>, Option<rpc::ResponseMeta>)> {
+        let mut client = rpc::HeadLumpWithMetaRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let mut request = self.lump_request(device_id, lump_id);
+        request.options.response_meta = true;
+        let future = self.client.response(client, request.clone());
+        self.hedge_read::<rpc::HeadLumpWithMetaRpc, _>(future, request, None)
+    }
+
+    /// Lumpが存在するかどうかの判定を行う.
+    ///
+    /// 存在確認のみが必要な場合には、`head_lump`よりも応答が小さく済む.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn exists_lump(&self, device_id: DeviceId, lump_id: LumpId) -> ExistsLumpFuture {
+        let mut client = rpc::ExistsLumpRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = self.lump_request(device_id, lump_id);
+        self.client.response(client, request)
+    }
+
+    /// Lumpの保存を行う.
+    ///
+    /// 返り値が`Ok(true)`の場合には新規作成が、`Ok(false)`の場合には上書きが、行われたことを表している.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    /// - 指定されたデバイスの容量が満杯になっている場合には`ErrorKind::Full`
+    ///
+    /// # 注意
+    ///
+    /// 現在は指定されたデータをジャーナル領域に埋め込むかどうかはRPCサーバ側が決定するため
+    /// `LumpData::new_embedded`関数を用いて`LumpData`を生成しても意味はない.
+    pub fn put_lump(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        lump_data: LumpData,
+    ) -> PutLumpFuture {
+        self.put_lump_inner(device_id, lump_id, lump_data, None)
+    }
+
+    fn put_lump_inner(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        lump_data: LumpData,
+        opts: Option<&CallOptions>,
+    ) -> PutLumpFuture {
+        let mut client = rpc::PutLumpRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options_with(opts);
+
+        let request = self.put_lump_request_with(device_id, lump_id, lump_data, opts);
+        self.client.response(client, request)
+    }
+
+    /// `opts`でビルダの設定を上書きした上で、`put_lump`を実行する.
+    ///
+    /// 詳細は`get_lump_with_opts`を参照のこと.
+    pub fn put_lump_with_opts(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        lump_data: LumpData,
+        opts: &CallOptions,
+    ) -> PutLumpFuture {
+        self.put_lump_inner(device_id, lump_id, lump_data, Some(opts))
+    }
+
+    /// `put_lump`と同様だが、サーバでの処理状況を表すメタ情報を併せて返す.
+    ///
+    /// 詳細は`get_lump_with_meta`を参照のこと.
+    pub fn put_lump_with_meta(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        lump_data: LumpData,
+    ) -> Response<(bool, Option<rpc::ResponseMeta>)> {
+        let mut client = rpc::PutLumpWithMetaRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let mut request = self.put_lump_request(device_id, lump_id, lump_data);
+        request.options.response_meta = true;
+        self.client.response(client, request)
+    }
+
+    /// 応答を待たずに、lumpの保存を行う.
+    ///
+    /// 保存結果(新規作成か上書きか、および失敗したかどうか)は通知されないので、
+    /// 結果を必要としない書き込み(e.g., テレメトリ)にのみ使用すること.
+    /// 保存内容およびサーバ側の処理は`put_lump`と同様.
+    ///
+    /// # Errors
+    ///
+    /// 通知の送信自体に失敗した場合(e.g., 送信キューの溢れ、サーキットブレーカの作動中)にのみ、エラーが返される.
+    pub fn put_lump_noack(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        lump_data: LumpData,
+    ) -> Result<()> {
+        track!(self.client.check_circuit())?;
+        let mut client = rpc::PutLumpNoAckRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let mut request = self.put_lump_request(device_id, lump_id, lump_data);
+        self.client.sign(rpc::PutLumpNoAckRpc::NAME, &mut request);
+        client
+            .cast(self.client.server(), request)
+            .map_err(|e| from_rpc_error(e, self.client.server()))
+    }
+
+    /// Lumpの保存を行い、上書きされたlumpのヘッダを返す.
+    ///
+    /// 新規作成の場合には`Ok(None)`が返される.
+    /// ヘッダから、上書きによって置き換えられたデータのサイズ(`LumpHeader::approximate_data_size`)を知ることができる.
+    ///
+    /// それ以外の点は`put_lump`と同様.
+    /// ヘッダの取得と保存は、同じlumpに対する他の更新とは直列化される(`if_exists`の説明を参照).
+    pub fn put_lump_v2(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        lump_data: LumpData,
+    ) -> HeadLumpFuture {
+        let mut client = rpc::PutLumpV2Rpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = self.put_lump_request(device_id, lump_id, lump_data);
+        self.client.response(client, request)
+    }
+
+    /// `reader`から読み込んだ`len`バイトのデータを用いて、lumpの保存を行う.
+    ///
+    /// データは送信時に必要な分だけが`reader`から読み込まれるため、全体がメモリ上に保持されることはない.
+    /// 巨大なオブジェクトをファイル等から直接取り込む場合に有用.
+    ///
+    /// 返り値の意味は`put_lump`と同様.
+    ///
+    /// なお`reader`からの読み込みは、RPCクライアントの処理の中で同期的に行われるので、
+    /// 読み込みが長時間ブロックするような読み込み元の扱いには注意が必要.
+    ///
+    /// # Errors
+    ///
+    /// `put_lump`が返すエラーに加えて、`reader`からの読み込みに失敗した場合や、
+    /// `reader`から`len`バイトを読み込む前に終端に達した場合にもエラーが返される.
+    ///
+    /// これらの場合には、不完全なデータが保存されることを防ぐために、サーバとの接続が切断される.
+    /// (その際には、同じ接続を共有している他のリクエストも失敗する)
+    pub fn put_lump_from_reader<R>(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        reader: R,
+        len: u64,
+    ) -> PutLumpFuture
+    where
+        R: Read + Send + 'static,
+    {
+        let mut client = rpc::PutLumpFromReaderRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::PutLumpFromReaderRequest {
             device_id,
-            range,
+            lump_id,
+            reader: Box::new(reader),
+            data_size: len,
             options: self.request_options(),
+            precondition: self.precondition(),
         };
-        Response::new(self.client.server, client.call(self.client.server, request))
+        self.client.response(client, request)
     }
 
-    /// lump の範囲を指定して削除し対象となった lump の一覧を返す.
+    /// Lumpの削除を行う.
+    ///
+    /// 返り値が`Ok(true)`の場合には削除が行われたことを、
+    /// `Ok(false)`の場合には対象lumpが存在しなかったことを、表している.
     ///
     /// # Errors
     ///
     /// 例えば、以下のようなエラーが返されることがある:
     /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
     /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
-    pub fn delete_range(
+    pub fn delete_lump(&self, device_id: DeviceId, lump_id: LumpId) -> DeleteLumpFuture {
+        self.delete_lump_inner(device_id, lump_id, None)
+    }
+
+    fn delete_lump_inner(
         &self,
         device_id: DeviceId,
-        range: Range<LumpId>,
-    ) -> impl Future<Item = Vec<LumpId>, Error = Error> {
-        let mut client = rpc::DeleteRangeRpc::client(&self.client.rpc_service);
-        *client.options_mut() = self.rpc_options.clone();
+        lump_id: LumpId,
+        opts: Option<&CallOptions>,
+    ) -> DeleteLumpFuture {
+        let mut client = rpc::DeleteLumpRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options_with(opts);
+
+        let mut request = self.lump_request_with(device_id, lump_id, opts);
+        request.precondition = self.precondition();
+        self.client.response(client, request)
+    }
+
+    /// `opts`でビルダの設定を上書きした上で、`delete_lump`を実行する.
+    ///
+    /// 詳細は`get_lump_with_opts`を参照のこと.
+    pub fn delete_lump_with_opts(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        opts: &CallOptions,
+    ) -> DeleteLumpFuture {
+        self.delete_lump_inner(device_id, lump_id, Some(opts))
+    }
+
+    /// `delete_lump`と同様だが、サーバでの処理状況を表すメタ情報を併せて返す.
+    ///
+    /// 詳細は`get_lump_with_meta`を参照のこと.
+    pub fn delete_lump_with_meta(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+    ) -> Response<(bool, Option<rpc::ResponseMeta>)> {
+        let mut client = rpc::DeleteLumpWithMetaRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let mut request = self.lump_request(device_id, lump_id);
+        request.precondition = self.precondition();
+        request.options.response_meta = true;
+        self.client.response(client, request)
+    }
+
+    /// 応答を待たずに、lumpの削除を行う.
+    ///
+    /// 削除結果(対象lumpが存在したかどうか、および失敗したかどうか)は通知されないので、
+    /// ベストエフォートで良い削除(e.g., キャッシュの無効化)にのみ使用すること.
+    /// サーバ側の処理は`delete_lump`と同様.
+    ///
+    /// # Errors
+    ///
+    /// 通知の送信自体に失敗した場合(e.g., 送信キューの溢れ、サーキットブレーカの作動中)にのみ、エラーが返される.
+    pub fn delete_lump_noack(&self, device_id: DeviceId, lump_id: LumpId) -> Result<()> {
+        track!(self.client.check_circuit())?;
+        let mut client = rpc::DeleteLumpNoAckRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let mut request = self.lump_request(device_id, lump_id);
+        request.precondition = self.precondition();
+        self.client
+            .sign(rpc::DeleteLumpNoAckRpc::NAME, &mut request);
+        client
+            .cast(self.client.server(), request)
+            .map_err(|e| from_rpc_error(e, self.client.server()))
+    }
+
+    /// Lumpの削除を行い、削除されたlumpのヘッダを返す.
+    ///
+    /// 対象lumpが存在しなかった場合には`Ok(None)`が返される.
+    /// ヘッダから、削除によって解放されたデータのサイズ(`LumpHeader::approximate_data_size`)を知ることができる.
+    ///
+    /// それ以外の点は`delete_lump`と同様.
+    /// ヘッダの取得と削除は、同じlumpに対する他の更新とは直列化される(`if_exists`の説明を参照).
+    pub fn delete_lump_v2(&self, device_id: DeviceId, lump_id: LumpId) -> HeadLumpFuture {
+        let mut client = rpc::DeleteLumpV2Rpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let mut request = self.lump_request(device_id, lump_id);
+        request.precondition = self.precondition();
+        self.client.response(client, request)
+    }
+
+    /// デバイスに保存されているlumpのID一覧を取得する.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn list_lumps(&self, device_id: DeviceId) -> ListLumpsFuture {
+        let mut client = rpc::ListLumpRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::DeviceRequest {
+            device_id,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// lumpの範囲を指定して、その範囲内に保存されているlumpのID一覧を取得する.
+    ///
+    /// デバイス全体の一覧を取得する`list_lumps`とは異なり、範囲外のlumpのIDは転送されない.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn list_lumps_range(&self, device_id: DeviceId, range: Range<LumpId>) -> ListLumpsFuture {
+        let mut client = rpc::ListLumpRangeRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
 
         let request = rpc::RangeLumpRequest {
             device_id,
             range,
             options: self.request_options(),
         };
-        Response::new(self.client.server, client.call(self.client.server, request))
+        self.client.response(client, request)
     }
 
-    fn new(client: &'a Client) -> Self {
-        RequestBuilder {
-            client,
-            deadline: None,
-            max_queue_len: None,
-            prioritized: false,
-            rpc_options: fibers_rpc::client::Options::default(),
-        }
-    }
+    /// lumpの範囲を指定して、その範囲内の全てのlumpをサーバ側で読み込み、読み込みに失敗したlumpのID一覧を取得する.
+    ///
+    /// lumpのデータ自体は転送されないため、破損したlumpの検出を低コストで行うことができる.
+    /// 範囲内のlumpは一つずつ順番に読み込まれるため、範囲が広い場合には、
+    /// `RequestBuilder::deadline`や`Client`側のタイムアウト設定に注意すること.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn scrub_range(&self, device_id: DeviceId, range: Range<LumpId>) -> ListLumpsFuture {
+        let mut client = rpc::ScrubRangeRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
 
-    fn lump_request(&self, device_id: DeviceId, lump_id: LumpId) -> rpc::LumpRequest {
-        rpc::LumpRequest {
+        let request = rpc::RangeLumpRequest {
             device_id,
-            lump_id,
+            range,
             options: self.request_options(),
-        }
+        };
+        self.client.response(client, request)
     }
 
-    fn request_options(&self) -> rpc::RequestOptions {
-        rpc::RequestOptions {
-            deadline: self.deadline.unwrap_or_default(),
-            prioritized: self.prioritized,
-            max_queue_len: self.max_queue_len,
-        }
-    }
-}
+    /// lumpの範囲を指定して、その範囲内の全てのlumpを、サーバ上の別のデバイスにコピーする.
+    ///
+    /// データはサーバ内で直接コピーされ、クライアントを経由しない.
+    /// 結果はコピーされたlumpのID一覧(昇順).
+    /// 詳細は`rpc::CopyRangeRpc`を参照のこと.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - いずれかのデバイスが存在しない場合や、両者が同じデバイスの場合には`ErrorKind::InvalidInput`
+    /// - いずれかのデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn copy_range(
+        &self,
+        source_device_id: DeviceId,
+        destination_device_id: DeviceId,
+        range: Range<LumpId>,
+    ) -> ListLumpsFuture {
+        let mut client = rpc::CopyRangeRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
 
-#[derive(Debug)]
-struct Response<T> {
-    server: SocketAddr,
-    inner: fibers_rpc::client::Response<Result<T>>,
-}
-impl<T> Response<T> {
-    fn new(server: SocketAddr, inner: fibers_rpc::client::Response<Result<T>>) -> Self {
-        Response { server, inner }
+        let request = rpc::CopyRangeRequest {
+            source_device_id,
+            destination_device_id,
+            range,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
     }
-}
-impl<T> Future for Response<T> {
-    type Item = T;
-    type Error = Error;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        match self.inner.poll() {
-            Err(e) => {
-                let original_kind = *e.kind();
-                let kind = match original_kind {
-                    fibers_rpc::ErrorKind::InvalidInput => ErrorKind::InvalidInput,
-                    fibers_rpc::ErrorKind::Timeout
-                    | fibers_rpc::ErrorKind::Unavailable
-                    | fibers_rpc::ErrorKind::Other => ErrorKind::Other,
+    /// lumpの範囲を指定して、その範囲内の全てのlumpのIDとデータの組を取得する.
+    ///
+    /// lumpはサーバから最大`max_lumps_per_chunk`個(`0`の場合は`1`として扱われる)ずつのチャンク単位で取得され、
+    /// 結果の`Stream`の各要素は、一つのチャンクに含まれるlumpの一覧(IDの昇順)となる.
+    /// 次のチャンクの取得は、前のチャンクが`Stream`から取り出された後に行われる.
+    ///
+    /// なお、取得中にデバイスが更新された場合には、その内容が結果に反映されるかどうかは不定.
+    /// (i.e., デバイス全体の一貫したスナップショットが得られることは保証されない)
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn export_lumps(
+        &self,
+        device_id: DeviceId,
+        range: Range<LumpId>,
+        max_lumps_per_chunk: usize,
+    ) -> ExportLumpsStream<'a> {
+        ExportLumpsStream {
+            builder: self.clone(),
+            device_id,
+            next_start: Some(range.start),
+            end: range.end,
+            max_lumps: max_lumps_per_chunk.max(1),
+            in_flight: None,
+        }
+    }
+
+    /// lumpの範囲を指定して、その範囲内のlumpのID一覧を`Stream`として取得する.
+    ///
+    /// IDはサーバから最大`max_lumps_per_chunk`個(`0`の場合は`1`として扱われる)ずつのチャンク単位で取得され、
+    /// 結果の`Stream`からは昇順に一つずつ取り出される.
+    /// 次のチャンクの取得は、前のチャンクの全てのIDが取り出された後に行われるので、
+    /// `list_lumps`とは異なり、lumpの数が多いデバイスでも一度に全てのIDを保持する必要はない.
+    ///
+    /// 取得中にデバイスが更新された場合の扱いは`export_lumps`と同様.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn list_lumps_stream(
+        &self,
+        device_id: DeviceId,
+        range: Range<LumpId>,
+        max_lumps_per_chunk: usize,
+    ) -> ListLumpsStream<'a> {
+        ListLumpsStream {
+            builder: self.clone(),
+            device_id,
+            next_start: Some(range.start),
+            end: range.end,
+            max_lumps: max_lumps_per_chunk.max(1),
+            buffer: Vec::new().into_iter(),
+            in_flight: None,
+        }
+    }
+
+    /// lumpの範囲を指定してデバイスのストレージ使用量を取得する.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn usage_range(&self, device_id: DeviceId, range: Range<LumpId>) -> UsageRangeFuture {
+        let mut client = rpc::UsageRangeRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::UsageRangeRequest {
+            device_id,
+            range,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// lump の範囲を指定して削除し対象となった lump の一覧を返す.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn delete_range(&self, device_id: DeviceId, range: Range<LumpId>) -> DeleteRangeFuture {
+        let mut client = rpc::DeleteRangeRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::RangeLumpRequest {
+            device_id,
+            range,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// lump の範囲を指定して、その先頭から最大`max_lumps`個までの lump を削除する.
+    ///
+    /// 範囲内に残りの lump が存在する場合には、結果の`DeleteRangeChunk::next`にその先頭のIDが格納されるので、
+    /// それを開始位置として再度呼び出すことで、範囲全体を段階的に削除することができる.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合や、`max_lumps`が`0`の場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn delete_range_bounded(
+        &self,
+        device_id: DeviceId,
+        range: Range<LumpId>,
+        max_lumps: usize,
+    ) -> Response<DeleteRangeChunk> {
+        let mut client = rpc::DeleteRangeBoundedRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::DeleteRangeBoundedRequest {
+            device_id,
+            range,
+            max_lumps,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// 一つのデバイスに対する複数の操作を、一回のRPCでまとめて実行する.
+    ///
+    /// 操作はサーバ側で先頭から順番に実行され、結果も同じ順番で返される.
+    /// 実行中は対象の全てのlumpのロックが保持されるので、操作の合間に同じlumpに対する他の更新が割り込むことはない.
+    ///
+    /// いずれかの操作が失敗した場合には、その時点で実行が打ち切られる.
+    /// その場合、結果の末尾が失敗した操作のエラーとなり、後続の操作の結果は含まれない.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    pub fn execute_script(&self, device_id: DeviceId, ops: Vec<ScriptOp>) -> ExecuteScriptFuture {
+        let mut client = rpc::ScriptRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::ScriptRequest {
+            device_id,
+            ops,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// 一つのデバイスに対する複数の操作を、並行して実行する.
+    ///
+    /// 全ての操作は、先行する操作の完了を待たずにサーバ側で連続してデバイスに発行される.
+    /// 操作列はアトミックに実行され、操作の合間に同じlumpに対する他の更新が割り込むことはない.
+    /// 結果は`ops`と同じ順番で返される.
+    ///
+    /// `execute_script`とは異なり、いずれかの操作が失敗しても後続の操作は実行される
+    /// (失敗した操作より前の操作が取り消されることもない).
+    /// 詳細は`rpc::ConcurrentScriptRpc`を参照のこと.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    /// - 保存操作を含み、かつデバイスの使用量が書き込みの上限を超えている場合には`ErrorKind::StorageFull`
+    pub fn execute_concurrent_script(
+        &self,
+        device_id: DeviceId,
+        ops: Vec<ScriptOp>,
+    ) -> ExecuteScriptFuture {
+        let mut client = rpc::ConcurrentScriptRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::ScriptRequest {
+            device_id,
+            ops,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// 一つのデバイスから、複数のlumpのデータの取得を一回のRPCでまとめて行う.
+    ///
+    /// 小さなlumpを大量に取得する場合に、lump毎のRPCのオーバヘッドを削減するためのもの.
+    ///
+    /// 結果は`lump_ids`と同じ順番で返され、各要素の意味は`get_lump`の返り値と同様.
+    /// 個々のlumpの取得結果は独立しており、一部が失敗しても残りのlumpの取得は継続される.
+    ///
+    /// 応答は全てのlumpのデータをまとめたものとなるので、巨大なlumpを
+    /// 多数含む場合には`get_lumps_concurrent`の方が適している.
+    /// なお、チェックサムの検証は適用されない.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn get_lumps(&self, device_id: DeviceId, lump_ids: Vec<LumpId>) -> GetLumpsFuture {
+        let mut client = rpc::GetLumpsRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::GetLumpsRequest {
+            device_id,
+            lump_ids,
+            options: self.request_options(),
+        };
+        let future = self.client.response(client, request);
+        GetLumpsFuture(future)
+    }
+
+    /// 一つのデバイスに対して、複数のlumpの保存を一回のRPCでまとめて行う.
+    ///
+    /// 小さなlumpを大量に保存する場合に、lump毎のラウンドトリップを削減するためのもの.
+    ///
+    /// 個々のlumpの保存は互いに独立しており、一部が失敗しても残りのlumpの保存は継続される.
+    /// 結果は`lumps`と同じ順番で返され、各要素の意味は`put_lump`の返り値と同様.
+    ///
+    /// なお事前条件(e.g., `if_exists`)およびチェックサムの検証は適用されない.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - 指定されたデバイスが現在利用不可能な場合には`ErrorKind::DeviceBusy`
+    pub fn put_lumps(&self, device_id: DeviceId, lumps: Vec<(LumpId, LumpData)>) -> PutLumpsFuture {
+        let mut client = rpc::PutLumpsRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::PutLumpsRequest {
+            device_id,
+            lumps,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// 指定デバイスへのインポートセッションを開始する.
+    ///
+    /// 以後は`import_lumps`でlumpのバッチを順に送信し、最後に`commit_import_session`を呼び出す.
+    /// 詳細は`rpc::OpenImportSessionRpc`を参照のこと.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    ///
+    /// サーバで署名の検証が有効な場合には、セッションは`Client::set_signing_key`で設定された鍵に紐付けられ、
+    /// 以降のセッションに対するリクエストも、同じ鍵で署名されている必要がある.
+    pub fn open_import_session(&self, device_id: DeviceId) -> Response<ImportSessionStatus> {
+        let mut client = rpc::OpenImportSessionRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::DeviceRequest {
+            device_id,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// インポートセッションに、lumpのバッチを送信する.
+    ///
+    /// `batch_seq`には、セッション内でのバッチの番号(i.e., `ImportSessionStatus::next_batch_seq`)を指定する.
+    /// 処理済みの番号が指定された場合には、バッチの内容は無視され、セッションの現在の状態がそのまま返される.
+    ///
+    /// バッチ内のlumpの保存が一つでも失敗した場合にはエラーとなり、その場合には同じバッチを再送する必要がある.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - セッションが存在しない場合や、`batch_seq`が次の番号を飛び越えている場合には`ErrorKind::InvalidInput`
+    /// - 同じセッションの別のバッチが処理中の場合には`ErrorKind::DeviceBusy`
+    pub fn import_lumps(
+        &self,
+        session_id: u64,
+        batch_seq: u64,
+        lumps: Vec<(LumpId, LumpData)>,
+    ) -> Response<ImportSessionStatus> {
+        let mut client = rpc::ImportLumpsRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::ImportLumpsRequest {
+            session_id,
+            batch_seq,
+            lumps,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// インポートセッションの現在の状態を取得する.
+    ///
+    /// 中断したインポートを再開する場合には、この結果の`next_batch_seq`から送信を続ければ良い.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - セッションが存在しない場合には`ErrorKind::InvalidInput`
+    pub fn import_session_status(&self, session_id: u64) -> Response<ImportSessionStatus> {
+        let mut client = rpc::ImportSessionStatusRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::ImportSessionRequest {
+            session_id,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// インポートセッションを終了し、その結果の要約を取得する.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - セッションが存在しない場合には`ErrorKind::InvalidInput`
+    /// - バッチの処理中の場合には`ErrorKind::DeviceBusy`
+    pub fn commit_import_session(&self, session_id: u64) -> Response<ImportSessionStatus> {
+        let mut client = rpc::CommitImportSessionRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::ImportSessionRequest {
+            session_id,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// デバイスのジャーナル領域の使用状況を取得する.
+    ///
+    /// 対象デバイスは`DeviceRegistryHandle::put_device_with_storage_metrics`を使って
+    /// 登録されている必要がある.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - デバイスのストレージのメトリクスが登録されていない場合には`ErrorKind::Other`
+    pub fn journal_usage(&self, device_id: DeviceId) -> Response<JournalUsage> {
+        let mut client = rpc::JournalUsageRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::DeviceRequest {
+            device_id,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// デバイスのストレージのヘッダを取得する.
+    ///
+    /// ヘッダにはストレージのブロックサイズや、ジャーナル領域・データ領域のサイズ等が含まれる.
+    /// (e.g., 埋め込みやアライメントの判断にブロックサイズを使用する)
+    ///
+    /// 対象デバイスは`DeviceRegistryHandle::put_device_with_storage_metrics`を使って
+    /// 登録されている必要がある.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - デバイスのストレージのメトリクスが登録されていない場合には`ErrorKind::Other`
+    pub fn storage_header(&self, device_id: DeviceId) -> Response<StorageHeader> {
+        let mut client = rpc::StorageHeaderRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::DeviceRequest {
+            device_id,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// サーバに登録されているデバイスのメトリクスのスナップショットを取得する.
+    ///
+    /// `device_ids`が空の場合には、全ての登録デバイスが対象となる.
+    /// 未登録のデバイスのIDが含まれている場合には、単に無視される.
+    ///
+    /// ストレージのメトリクスは、デバイスが
+    /// `DeviceRegistryHandle::put_device_with_storage_metrics`を使って登録されている場合にのみ含まれる.
+    pub fn metrics_snapshot(
+        &self,
+        device_ids: Vec<DeviceId>,
+    ) -> Response<Vec<DeviceMetricsSnapshot>> {
+        let mut client = rpc::MetricsSnapshotRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+        self.client.response(client, device_ids)
+    }
+
+    /// 更新系の操作の度にジャーナルの同期を行うかどうかを、デバイスに設定する.
+    ///
+    /// 成功した場合には、更新後のデバイスの設定が返される.
+    ///
+    /// これは管理用のRPCであり、サーバ側で`Server::enable_admin_rpc`が呼ばれている必要がある.
+    /// (呼ばれていない場合には、RPCの呼び出し自体が失敗する)
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    pub fn set_journal_sync(
+        &self,
+        device_id: DeviceId,
+        journal_sync: bool,
+    ) -> Response<DeviceSettings> {
+        let mut client = rpc::SetJournalSyncRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::SetJournalSyncRequest {
+            device_id,
+            journal_sync,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// デバイスに対するリクエストの、キューの長さ制限に関する設定を変更する.
+    ///
+    /// `default_max_queue_len`は、リクエストで制限が指定されていない場合に適用される値であり、
+    /// `max_queue_len_limit`は、リクエストで指定可能な制限値の上限となる.
+    /// いずれも`None`の場合には、サーバ側での調整は行われない.
+    ///
+    /// 成功した場合には、更新後のデバイスの設定が返される.
+    ///
+    /// これは管理用のRPCであり、サーバ側で`Server::enable_admin_rpc`が呼ばれている必要がある.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    pub fn set_queue_limits(
+        &self,
+        device_id: DeviceId,
+        default_max_queue_len: Option<usize>,
+        max_queue_len_limit: Option<usize>,
+    ) -> Response<DeviceSettings> {
+        let mut client = rpc::SetQueueLimitsRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::SetQueueLimitsRequest {
+            device_id,
+            default_max_queue_len,
+            max_queue_len_limit,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// デバイスの書き込みを制限するデータ領域の使用率(パーセント単位)を変更する.
+    ///
+    /// 使用率がこの値を超えている間は、新規のPUTが`ErrorKind::StorageFull`で拒否される.
+    /// `None`の場合には、制限なしとなる.
+    ///
+    /// 成功した場合には、更新後のデバイスの設定が返される.
+    ///
+    /// これは管理用のRPCであり、サーバ側で`Server::enable_admin_rpc`が呼ばれている必要がある.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 指定されたデバイスが存在しない場合には`ErrorKind::InvalidInput`
+    /// - `100`よりも大きな値が指定された場合には`ErrorKind::InvalidInput`
+    pub fn set_write_watermark(
+        &self,
+        device_id: DeviceId,
+        write_watermark: Option<u8>,
+    ) -> Response<DeviceSettings> {
+        let mut client = rpc::SetWriteWatermarkRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::SetWriteWatermarkRequest {
+            device_id,
+            write_watermark,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// サーバ(およびそのデバイスレジストリ)のログ出力レベルを変更する.
+    ///
+    /// 成功した場合には、変更後のログ出力レベルが返される.
+    ///
+    /// これは管理用のRPCであり、サーバ側で`Server::enable_admin_rpc`が呼ばれている必要がある.
+    ///
+    /// 詳細は`DeviceRegistryHandle::set_log_level`のドキュメントを参照のこと.
+    pub fn set_log_level(&self, level: Level) -> Response<Level> {
+        let mut client = rpc::SetLogLevelRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+        self.client.response(client, level)
+    }
+
+    /// サーバ側で実行中のリクエスト一覧を取得する.
+    ///
+    /// `device_ids`が空ではない場合には、それらのデバイスに対するリクエストのみが対象となる.
+    ///
+    /// これは管理用のRPCであり、サーバ側で`Server::enable_admin_rpc`が呼ばれている必要がある.
+    pub fn list_in_flight_requests(
+        &self,
+        device_ids: Vec<DeviceId>,
+    ) -> Response<Vec<InFlightRequest>> {
+        let mut client = rpc::ListInFlightRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+        self.client.response(client, device_ids)
+    }
+
+    /// サーバ側で実行中のリクエストをキャンセルする.
+    ///
+    /// `request_id`には`list_in_flight_requests`で取得したIDを指定する.
+    /// キャンセルされたリクエストの発行元には`ErrorKind::RequestDropped`エラーが返される.
+    ///
+    /// 対象リクエストが存在した場合には`true`が、既に完了していた等で存在しなかった場合には`false`が返される.
+    ///
+    /// なお、キャンセルはサーバ側での後続の処理を打ち切るだけなので、
+    /// 既にデバイスに発行済みのコマンドは、キャンセル後も実行される可能性がある.
+    ///
+    /// これは管理用のRPCであり、サーバ側で`Server::enable_admin_rpc`が呼ばれている必要がある.
+    pub fn cancel_in_flight_request(&self, request_id: u64) -> Response<bool> {
+        let mut client = rpc::CancelInFlightRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+        self.client.response(client, request_id)
+    }
+
+    /// サーバ上の単一デバイスの状態を取得する.
+    ///
+    /// 指定されたデバイスが未登録の場合にも、エラーとはならずに、
+    /// `DeviceStatusReport::is_registered`が`false`となる値が返される.
+    pub fn device_status(&self, device_id: DeviceId) -> Response<DeviceStatusReport> {
+        let mut client = rpc::DeviceStatusRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::DeviceRequest {
+            device_id,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// サーバ上のデバイスに付与されたラベル群を取得する.
+    ///
+    /// 指定されたデバイスが未登録の場合には`ErrorKind::InvalidInput`エラーが返される.
+    pub fn device_labels(&self, device_id: DeviceId) -> Response<DeviceLabels> {
+        let mut client = rpc::DeviceLabelsRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::DeviceRequest {
+            device_id,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// サーバに登録されているデバイスの一覧を取得する.
+    ///
+    /// `with_status`に`true`が指定された場合には、各デバイスの稼働状態も合わせて取得される.
+    /// 結果はデバイスIDの昇順に並べられる.
+    pub fn list_devices(&self, with_status: bool) -> Response<Vec<DeviceSummary>> {
+        let mut client = rpc::ListDevicesRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+        self.client.response(client, with_status)
+    }
+
+    /// デバイスが実際にリクエストを処理可能かどうかを確認する.
+    ///
+    /// サーバは対象の各デバイスに対して、(存在しないことが想定される)lumpのHEADを発行し、
+    /// その成否と処理時間を返す.
+    /// デプロイ時等に、トラフィックを流す前に全てのデバイスが利用可能であることを確認するためのもの.
+    ///
+    /// `device_ids`が空の場合には、全ての登録デバイスが対象となる.
+    /// 結果はデバイスIDの昇順に並べられる.
+    pub fn check_readiness(&self, device_ids: Vec<DeviceId>) -> Response<Vec<DeviceReadiness>> {
+        let mut client = rpc::ReadinessRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+        self.client.response(client, device_ids)
+    }
+
+    /// サーバ側で集計されている、デバイスおよびRPC単位のリクエストの統計情報を取得する.
+    ///
+    /// `device_ids`が空ではない場合には、それらのデバイスの統計情報のみが対象となる.
+    pub fn request_stats(&self, device_ids: Vec<DeviceId>) -> Response<Vec<RequestStats>> {
+        let mut client = rpc::RequestStatsRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+        self.client.response(client, device_ids)
+    }
+
+    /// サーバ側で集計されているリクエストの統計情報をリセットする.
+    ///
+    /// 結果としては、リセット直前の統計情報が返される.
+    /// `device_ids`の扱いは`request_stats`と同様.
+    ///
+    /// これは管理用のRPCであり、サーバ側で`Server::enable_admin_rpc`が呼ばれている必要がある.
+    pub fn reset_request_stats(&self, device_ids: Vec<DeviceId>) -> Response<Vec<RequestStats>> {
+        let mut client = rpc::ResetRequestStatsRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+        self.client.response(client, device_ids)
+    }
+
+    /// 指定された仕様に従って、サーバ上でデバイスを構築(ないしオープン)し、登録する.
+    ///
+    /// 結果の値が`true`の場合にはストレージが新規に作成されたことを、
+    /// `false`の場合には既存のストレージが開かれたことを、表している.
+    /// ファイルのパスはサーバ側のファイルシステム上のものとして解釈される.
+    ///
+    /// なお、同じIDのデバイスが既に登録されている場合には、新しいデバイスで上書きされる.
+    ///
+    /// これは管理用のRPCであり、サーバ側で`Server::enable_admin_rpc`が呼ばれている必要がある.
+    ///
+    /// # Errors
+    ///
+    /// 例えば、以下のようなエラーが返されることがある:
+    /// - 容量が小さ過ぎる等の理由で、ストレージを作成できない場合には`ErrorKind::InvalidInput`
+    /// - 容量がサーバ側の上限(`Server::max_provision_capacity`)を超えている場合には`ErrorKind::InvalidInput`
+    /// - サーバ側で処理待ちの構築要求が多過ぎる場合には`ErrorKind::DeviceBusy`
+    pub fn provision_device(&self, spec: DeviceSpec) -> Response<bool> {
+        let mut client = rpc::ProvisionDeviceRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::ProvisionDeviceRequest {
+            spec,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// サーバのレジストリからデバイスを削除する.
+    ///
+    /// 結果の値は、指定されたデバイスが登録されていたかどうかを表している.
+    ///
+    /// 削除はサーバ側で非同期に処理されるため、このメソッドの完了直後に発行されたリクエストでは、
+    /// まだ削除前のデバイスが参照される可能性がある.
+    ///
+    /// これは管理用のRPCであり、サーバ側で`Server::enable_admin_rpc`が呼ばれている必要がある.
+    pub fn delete_device(&self, device_id: DeviceId) -> Response<bool> {
+        let mut client = rpc::DeleteDeviceRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::DeviceRequest {
+            device_id,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    /// サーバ上のデバイスに停止命令を発行する.
+    ///
+    /// 停止命令のデッドラインには`RequestBuilder::deadline`で指定された値が使われる.
+    /// デフォルトの`Deadline::Infinity`の場合には、処理待ちのリクエストが全て処理された後に、デバイスが停止する.
+    ///
+    /// 結果の値は、指定されたデバイスが登録されていたかどうかを表している.
+    /// なお、このメソッドはデバイスの停止完了を待たない.
+    /// 停止したデバイスはレジストリに残り続けるため、必要に応じて`delete_device`で削除すること.
+    ///
+    /// これは管理用のRPCであり、サーバ側で`Server::enable_admin_rpc`が呼ばれている必要がある.
+    pub fn stop_device(&self, device_id: DeviceId) -> Response<bool> {
+        let mut client = rpc::StopDeviceRpc::client(&self.client.rpc_service);
+        *client.options_mut() = self.effective_rpc_options();
+
+        let request = rpc::DeviceRequest {
+            device_id,
+            options: self.request_options(),
+        };
+        self.client.response(client, request)
+    }
+
+    fn new(client: &'a Client) -> Self {
+        let defaults = &client.default_options;
+        RequestBuilder {
+            client: Cow::Borrowed(client),
+            deadline: defaults.deadline,
+            max_queue_len: defaults.max_queue_len,
+            prioritized: false,
+            verbose_errors: false,
+            verify_checksums: false,
+            precondition: Precondition::default(),
+            trace_id: None,
+            rpc_options: defaults.rpc_options.clone().unwrap_or_default(),
+            retry_policy: client.retry_policy.clone(),
+            hedge: None,
+        }
+    }
+
+    // RPCの発行時に実際に使用されるオプションを返す.
+    //
+    // タイムアウトが未指定の場合には、`Deadline::Within`とクライアントの猶予から導出する.
+    fn effective_rpc_options(&self) -> fibers_rpc::client::Options {
+        self.effective_rpc_options_with(None)
+    }
+
+    // `effective_rpc_options`と同様だが、`opts`が指定された場合には、その設定を優先する.
+    fn effective_rpc_options_with(
+        &self,
+        opts: Option<&CallOptions>,
+    ) -> fibers_rpc::client::Options {
+        let mut options = opts
+            .and_then(|o| o.rpc_options.clone())
+            .unwrap_or_else(|| self.rpc_options.clone());
+        if options.timeout.is_none() {
+            if let (Some(Deadline::Within(d)), Some(slack)) =
+                (self.deadline_with(opts), self.client.deadline_timeout_slack)
+            {
+                options.timeout = Some(d + slack);
+            }
+        }
+        options
+    }
+
+    fn deadline_with(&self, opts: Option<&CallOptions>) -> Option<Deadline> {
+        opts.and_then(|o| o.deadline).or(self.deadline)
+    }
+
+    // ヘッジリクエストが有効な場合には、代替サーバへのリクエストの発行を予約する.
+    fn hedge_read<T, R>(
+        &self,
+        future: Response<R>,
+        request: T::Req,
+        opts: Option<&CallOptions>,
+    ) -> Response<R>
+    where
+        T: LocalCall<R>,
+        T::Req: RequestOptionsMut + SignableRequest,
+        T::ReqEncoder: Default,
+        T::ResDecoder: Default,
+        R: Send + 'static,
+    {
+        let (alternate, delay) = if let Some((ref alternate, delay)) = self.hedge {
+            (alternate.clone(), delay)
+        } else {
+            return future;
+        };
+        let rpc_options = self.effective_rpc_options_with(opts);
+        future.hedge(delay, move || {
+            let mut client = T::client(&alternate.rpc_service);
+            *client.options_mut() = rpc_options;
+            alternate.response(client, request)
+        })
+    }
+
+    pub(crate) fn escalate_max_queue_len(&mut self, cap: usize) {
+        if let Some(n) = self.max_queue_len {
+            self.max_queue_len = Some(n.saturating_mul(2).min(cap).max(n));
+        }
+    }
+
+    pub(crate) fn current_deadline(&self) -> Option<Deadline> {
+        self.deadline
+    }
+
+    /// デッドラインおよびRPCのタイムアウトを、最大で`remaining`に縮める.
+    pub(crate) fn shrink_deadline(&mut self, remaining: Duration) {
+        self.deadline = Some(Deadline::Within(remaining));
+        let timeout = self
+            .rpc_options
+            .timeout
+            .map_or(remaining, |t| t.min(remaining));
+        self.rpc_options.timeout = Some(timeout);
+    }
+
+    /// クライアントを借用しない(i.e., ファイバとして実行可能な)ビルダに変換する.
+    pub(crate) fn into_owned(self) -> RequestBuilder<'static> {
+        RequestBuilder {
+            client: Cow::Owned(self.client.into_owned()),
+            deadline: self.deadline,
+            max_queue_len: self.max_queue_len,
+            prioritized: self.prioritized,
+            verbose_errors: self.verbose_errors,
+            verify_checksums: self.verify_checksums,
+            precondition: self.precondition,
+            trace_id: self.trace_id,
+            rpc_options: self.rpc_options,
+            retry_policy: self.retry_policy,
+            hedge: self.hedge,
+        }
+    }
+
+    pub(crate) fn spawner(&self) -> Option<&Spawner> {
+        self.client.spawner.as_ref()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn current_max_queue_len(&self) -> Option<usize> {
+        self.max_queue_len
+    }
+
+    fn lump_request(&self, device_id: DeviceId, lump_id: LumpId) -> rpc::LumpRequest {
+        self.lump_request_with(device_id, lump_id, None)
+    }
+
+    fn lump_request_with(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        opts: Option<&CallOptions>,
+    ) -> rpc::LumpRequest {
+        rpc::LumpRequest {
+            device_id,
+            lump_id,
+            options: self.request_options_with(opts),
+            precondition: None,
+        }
+    }
+
+    fn put_lump_request(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        lump_data: LumpData,
+    ) -> rpc::PutLumpRequest {
+        self.put_lump_request_with(device_id, lump_id, lump_data, None)
+    }
+
+    fn put_lump_request_with(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        lump_data: LumpData,
+        opts: Option<&CallOptions>,
+    ) -> rpc::PutLumpRequest {
+        let mut request = rpc::PutLumpRequest {
+            device_id,
+            lump_id,
+            lump_data,
+            options: self.request_options_with(opts),
+            precondition: self.precondition(),
+            checksum: None,
+        };
+        if self.verify_checksums {
+            request.checksum = Some(checksum::compute(request.lump_data.as_bytes()));
+        }
+        request
+    }
+
+    fn precondition(&self) -> Option<Precondition> {
+        if self.precondition == Precondition::default() {
+            None
+        } else {
+            Some(self.precondition.clone())
+        }
+    }
+
+    fn request_options(&self) -> rpc::RequestOptions {
+        self.request_options_with(None)
+    }
+
+    // `request_options`と同様だが、`opts`が指定された場合には、その設定を優先する.
+    fn request_options_with(&self, opts: Option<&CallOptions>) -> rpc::RequestOptions {
+        rpc::RequestOptions {
+            deadline: self.deadline_with(opts).unwrap_or_default(),
+            prioritized: self.prioritized,
+            max_queue_len: opts.and_then(|o| o.max_queue_len).or(self.max_queue_len),
+            journal_sync: false,
+            verbose_errors: self.verbose_errors,
+            response_meta: false,
+            trace_id: self.trace_id.clone(),
+            signature: None,
+        }
+    }
+}
+
+/// `RequestBuilder::get_lump`が返す`Future`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct GetLumpFuture(GetLumpFutureInner);
+impl Future for GetLumpFuture {
+    type Item = Option<Vec<u8>>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.0 {
+            GetLumpFutureInner::Plain(ref mut f) => {
+                Ok(f.poll()?.map(|data| data.map(|d| d.into_bytes())))
+            }
+            GetLumpFutureInner::Checksummed(ref mut f) => {
+                if let Async::Ready(data) = f.poll()? {
+                    if let Some((ref data, expected)) = data {
+                        track!(checksum::verify(data.as_bytes(), expected))?;
+                    }
+                    Ok(Async::Ready(data.map(|(d, _)| d.into_bytes())))
+                } else {
+                    Ok(Async::NotReady)
+                }
+            }
+        }
+    }
+}
+
+impl GetLumpFuture {
+    pub(crate) fn spawner(&self) -> Option<&Spawner> {
+        match self.0 {
+            GetLumpFutureInner::Plain(ref f) => f.spawner(),
+            GetLumpFutureInner::Checksummed(ref f) => f.spawner(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum GetLumpFutureInner {
+    Plain(Response<Option<LumpData>>),
+    Checksummed(Response<Option<(LumpData, u32)>>),
+}
+
+/// `RequestBuilder::get_lumps`が返す`Future`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct GetLumpsFuture(Response<Vec<Result<Option<LumpData>>>>);
+impl GetLumpsFuture {
+    pub(crate) fn spawner(&self) -> Option<&Spawner> {
+        self.0.spawner()
+    }
+}
+impl Future for GetLumpsFuture {
+    type Item = Vec<Result<Option<Vec<u8>>>>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        Ok(self.0.poll()?.map(|results| {
+            results
+                .into_iter()
+                .map(|r| r.map(|data| data.map(|d| d.into_bytes())))
+                .collect()
+        }))
+    }
+}
+
+/// `RequestBuilder::get_lumps_concurrent`が返す`Future`.
+///
+/// 個々のlumpの取得結果は、結果のマップ内に保持されるので、この`Future`自体が失敗することはない.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct GetLumpsConcurrentFuture<'a> {
+    builder: RequestBuilder<'a>,
+    device_id: DeviceId,
+    pending: std::vec::IntoIter<LumpId>,
+    in_flight: Vec<(LumpId, GetLumpFuture)>,
+    max_in_flight: usize,
+    results: BTreeMap<LumpId, Result<Option<Vec<u8>>>>,
+}
+impl<'a> GetLumpsConcurrentFuture<'a> {
+    pub(crate) fn into_owned(self) -> GetLumpsConcurrentFuture<'static> {
+        GetLumpsConcurrentFuture {
+            builder: self.builder.into_owned(),
+            device_id: self.device_id,
+            pending: self.pending,
+            in_flight: self.in_flight,
+            max_in_flight: self.max_in_flight,
+            results: self.results,
+        }
+    }
+
+    pub(crate) fn spawner(&self) -> Option<&Spawner> {
+        self.builder.spawner()
+    }
+}
+impl<'a> Future for GetLumpsConcurrentFuture<'a> {
+    type Item = BTreeMap<LumpId, Result<Option<Vec<u8>>>>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            while self.in_flight.len() < self.max_in_flight {
+                if let Some(lump_id) = self.pending.next() {
+                    let future = self.builder.get_lump(self.device_id.clone(), lump_id);
+                    self.in_flight.push((lump_id, future));
+                } else {
+                    break;
+                }
+            }
+
+            let mut completed = false;
+            let mut i = 0;
+            while i < self.in_flight.len() {
+                let result = match self.in_flight[i].1.poll() {
+                    Ok(Async::NotReady) => {
+                        i += 1;
+                        continue;
+                    }
+                    Ok(Async::Ready(data)) => Ok(data),
+                    Err(e) => Err(e),
+                };
+                let (lump_id, _) = self.in_flight.swap_remove(i);
+                self.results.insert(lump_id, result);
+                completed = true;
+            }
+
+            if self.in_flight.is_empty() && self.pending.len() == 0 {
+                let results = std::mem::take(&mut self.results);
+                return Ok(Async::Ready(results));
+            }
+            if !completed || self.pending.len() == 0 {
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
+
+/// `RequestBuilder::export_lumps`が返す`Stream`.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct ExportLumpsStream<'a> {
+    builder: RequestBuilder<'a>,
+    device_id: DeviceId,
+    next_start: Option<LumpId>,
+    end: LumpId,
+    max_lumps: usize,
+    in_flight: Option<Response<rpc::ExportLumpsChunk>>,
+}
+impl<'a> ExportLumpsStream<'a> {
+    /// 標準ライブラリの`Future`を返す`next`メソッドで要素を取り出せるようにする.
+    ///
+    /// `Stream`は`Client::set_spawner`で設定されたエグゼキュータ上のファイバとして実行される.
+    /// 詳細は`CompatStream`を参照のこと.
+    pub fn compat(self) -> CompatStream<Vec<(LumpId, Vec<u8>)>> {
+        let spawner = self.builder.spawner().cloned();
+        let stream = ExportLumpsStream {
+            builder: self.builder.into_owned(),
+            device_id: self.device_id,
+            next_start: self.next_start,
+            end: self.end,
+            max_lumps: self.max_lumps,
+            in_flight: self.in_flight,
+        };
+        CompatStream::spawn_or_fail(spawner, stream)
+    }
+
+    fn request_chunk(&self, start: LumpId) -> Response<rpc::ExportLumpsChunk> {
+        let mut client = rpc::ExportLumpsRpc::client(&self.builder.client.rpc_service);
+        *client.options_mut() = self.builder.effective_rpc_options();
+
+        let request = rpc::ExportLumpsRequest {
+            device_id: self.device_id.clone(),
+            range: start..self.end,
+            max_lumps: self.max_lumps,
+            options: self.builder.request_options(),
+        };
+        self.builder.client.response(client, request)
+    }
+}
+impl<'a> Stream for ExportLumpsStream<'a> {
+    type Item = Vec<(LumpId, Vec<u8>)>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(mut future) = self.in_flight.take() {
+                let chunk = match future.poll() {
+                    Err(e) => {
+                        self.next_start = None;
+                        return Err(track!(e));
+                    }
+                    Ok(Async::NotReady) => {
+                        self.in_flight = Some(future);
+                        return Ok(Async::NotReady);
+                    }
+                    Ok(Async::Ready(chunk)) => chunk,
+                };
+                self.next_start = chunk.next;
+                if !chunk.lumps.is_empty() {
+                    let lumps = chunk
+                        .lumps
+                        .into_iter()
+                        .map(|(id, data)| (id, data.into_bytes()))
+                        .collect();
+                    return Ok(Async::Ready(Some(lumps)));
+                }
+            }
+
+            if let Some(start) = self.next_start {
+                self.in_flight = Some(self.request_chunk(start));
+            } else {
+                return Ok(Async::Ready(None));
+            }
+        }
+    }
+}
+
+/// `RequestBuilder::list_lumps_stream`が返す`Stream`.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct ListLumpsStream<'a> {
+    builder: RequestBuilder<'a>,
+    device_id: DeviceId,
+    next_start: Option<LumpId>,
+    end: LumpId,
+    max_lumps: usize,
+    buffer: std::vec::IntoIter<LumpId>,
+    in_flight: Option<Response<rpc::ListLumpsChunk>>,
+}
+impl<'a> ListLumpsStream<'a> {
+    /// 標準ライブラリの`Future`を返す`next`メソッドで要素を取り出せるようにする.
+    ///
+    /// `Stream`は`Client::set_spawner`で設定されたエグゼキュータ上のファイバとして実行される.
+    /// 詳細は`CompatStream`を参照のこと.
+    pub fn compat(self) -> CompatStream<LumpId> {
+        let spawner = self.builder.spawner().cloned();
+        let stream = ListLumpsStream {
+            builder: self.builder.into_owned(),
+            device_id: self.device_id,
+            next_start: self.next_start,
+            end: self.end,
+            max_lumps: self.max_lumps,
+            buffer: self.buffer,
+            in_flight: self.in_flight,
+        };
+        CompatStream::spawn_or_fail(spawner, stream)
+    }
+
+    fn request_chunk(&self, start: LumpId) -> Response<rpc::ListLumpsChunk> {
+        let mut client = rpc::ListLumpsChunkRpc::client(&self.builder.client.rpc_service);
+        *client.options_mut() = self.builder.effective_rpc_options();
+
+        let request = rpc::ExportLumpsRequest {
+            device_id: self.device_id.clone(),
+            range: start..self.end,
+            max_lumps: self.max_lumps,
+            options: self.builder.request_options(),
+        };
+        self.builder.client.response(client, request)
+    }
+}
+impl<'a> Stream for ListLumpsStream<'a> {
+    type Item = LumpId;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(lump_id) = self.buffer.next() {
+                return Ok(Async::Ready(Some(lump_id)));
+            }
+
+            if let Some(mut future) = self.in_flight.take() {
+                let chunk = match future.poll() {
+                    Err(e) => {
+                        self.next_start = None;
+                        return Err(track!(e));
+                    }
+                    Ok(Async::NotReady) => {
+                        self.in_flight = Some(future);
+                        return Ok(Async::NotReady);
+                    }
+                    Ok(Async::Ready(chunk)) => chunk,
                 };
-                Err(track!(kind.takes_over(e); original_kind, self.server).into())
+                self.next_start = chunk.next;
+                self.buffer = chunk.lump_ids.into_iter();
+                continue;
+            }
+
+            if let Some(start) = self.next_start {
+                self.in_flight = Some(self.request_chunk(start));
+            } else {
+                return Ok(Async::Ready(None));
+            }
+        }
+    }
+}
+
+/// `RequestBuilder::put_pipeline`が返す`Sink`.
+///
+/// 送信されたlumpは即座に`put_lump`で保存され、その結果は対になる`PutPipelineResults`から得られる.
+#[derive(Debug)]
+pub struct PutPipelineSink<'a> {
+    builder: RequestBuilder<'a>,
+    credits: usize, // 追加で発行可能なリクエストの数
+    credit_rx: mpsc::Receiver<()>,
+    request_tx: Option<mpsc::Sender<(DeviceId, LumpId, PutLumpFuture)>>,
+}
+impl<'a> PutPipelineSink<'a> {
+    /// 標準ライブラリの`Future`を返す`send`メソッドでlumpを送信できるようにする.
+    ///
+    /// `Sink`は`Client::set_spawner`で設定されたエグゼキュータ上のファイバとして実行される.
+    /// 詳細は`CompatSink`を参照のこと.
+    pub fn compat(self) -> CompatSink<(DeviceId, LumpId, LumpData)> {
+        let spawner = self.builder.spawner().cloned();
+        let sink = PutPipelineSink {
+            builder: self.builder.into_owned(),
+            credits: self.credits,
+            credit_rx: self.credit_rx,
+            request_tx: self.request_tx,
+        };
+        CompatSink::spawn_or_fail(spawner, sink)
+    }
+}
+impl<'a> Sink for PutPipelineSink<'a> {
+    type SinkItem = (DeviceId, LumpId, LumpData);
+    type SinkError = Error;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        let request_tx = track_assert_some!(
+            self.request_tx.as_ref(),
+            ErrorKind::InvalidInput,
+            "The sink has been closed"
+        );
+        while let Async::Ready(Some(())) = self.credit_rx.poll().expect("Never fails") {
+            self.credits += 1;
+        }
+        if self.credits == 0 {
+            return Ok(AsyncSink::NotReady(item));
+        }
+        let (device_id, lump_id, lump_data) = item;
+        let future = self.builder.put_lump(device_id.clone(), lump_id, lump_data);
+        track_assert!(
+            request_tx.send((device_id, lump_id, future)).is_ok(),
+            ErrorKind::Other,
+            "The paired results stream has been dropped"
+        );
+        self.credits -= 1;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        // リクエストは`start_send`の時点で発行済み
+        Ok(Async::Ready(()))
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.request_tx = None;
+        Ok(Async::Ready(()))
+    }
+}
+
+/// `RequestBuilder::put_pipeline`が返す`Stream`.
+///
+/// この`Stream`がエラーを返すことはない(個々のリクエストのエラーは、要素に含まれる).
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct PutPipelineResults {
+    request_rx: mpsc::Receiver<(DeviceId, LumpId, PutLumpFuture)>,
+    credit_tx: mpsc::Sender<()>,
+    in_flight: Vec<(DeviceId, LumpId, PutLumpFuture)>,
+    closed: bool,
+    spawner: Option<Spawner>,
+}
+impl PutPipelineResults {
+    /// 標準ライブラリの`Future`を返す`next`メソッドで結果を取り出せるようにする.
+    ///
+    /// `Stream`は`Client::set_spawner`で設定されたエグゼキュータ上のファイバとして実行される.
+    /// 詳細は`CompatStream`を参照のこと.
+    pub fn compat(self) -> CompatStream<(DeviceId, LumpId, Result<bool>)> {
+        CompatStream::spawn_or_fail(self.spawner.clone(), self)
+    }
+}
+impl Stream for PutPipelineResults {
+    type Item = (DeviceId, LumpId, Result<bool>);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        while !self.closed {
+            match self.request_rx.poll().expect("Never fails") {
+                Async::NotReady => break,
+                Async::Ready(None) => self.closed = true,
+                Async::Ready(Some(request)) => self.in_flight.push(request),
+            }
+        }
+
+        for i in 0..self.in_flight.len() {
+            let result = match self.in_flight[i].2.poll() {
+                Ok(Async::NotReady) => continue,
+                Ok(Async::Ready(created)) => Ok(created),
+                Err(e) => Err(e),
+            };
+            let (device_id, lump_id, _) = self.in_flight.swap_remove(i);
+
+            // `Sink`側が既にドロップされている場合には、通知は不要
+            let _ = self.credit_tx.send(());
+            return Ok(Async::Ready(Some((device_id, lump_id, result))));
+        }
+
+        if self.closed && self.in_flight.is_empty() {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+/// `RequestBuilder::get_lump_to_writer`が返す`Future`.
+pub type GetLumpToWriterFuture = Response<Option<u64>>;
+
+/// `RequestBuilder::head_lump`が返す`Future`.
+pub type HeadLumpFuture = Response<Option<LumpHeader>>;
+
+/// `RequestBuilder::exists_lump`が返す`Future`.
+pub type ExistsLumpFuture = Response<bool>;
+
+/// `RequestBuilder::put_lump`が返す`Future`.
+pub type PutLumpFuture = Response<bool>;
+
+/// `RequestBuilder::put_lumps`が返す`Future`.
+pub type PutLumpsFuture = Response<Vec<Result<bool>>>;
+
+/// `RequestBuilder::delete_lump`が返す`Future`.
+pub type DeleteLumpFuture = Response<bool>;
+
+/// `RequestBuilder::list_lumps`が返す`Future`.
+pub type ListLumpsFuture = Response<Vec<LumpId>>;
+
+/// `RequestBuilder::usage_range`が返す`Future`.
+pub type UsageRangeFuture = Response<StorageUsage>;
+
+/// `RequestBuilder::delete_range`が返す`Future`.
+pub type DeleteRangeFuture = Response<Vec<LumpId>>;
+
+/// `RequestBuilder::execute_script`が返す`Future`.
+pub type ExecuteScriptFuture = Response<Vec<Result<ScriptOpResult>>>;
+
+/// `Client::connect`が返す`Future`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct ConnectFuture {
+    server: SocketAddr,
+    inner: fibers_rpc::client::Response<Result<ServerInfo>>,
+    spawner: Option<Spawner>,
+}
+impl ConnectFuture {
+    pub(crate) fn spawner(&self) -> Option<&Spawner> {
+        self.spawner.as_ref()
+    }
+}
+impl Future for ConnectFuture {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // サーバ側のエラーは、疎通が取れていることを示すので無視する
+        match self.inner.poll() {
+            Err(e) => Err(from_rpc_error(e, self.server)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(_)) => Ok(Async::Ready(())),
+        }
+    }
+}
+
+/// RPCの応答を表す`Future`.
+///
+/// `RequestBuilder`の各メソッドは、(`get_lump`を除いて)このfutureを返す.
+/// 具体的な型として名前を付けられるので、構造体のフィールド等に保持することが可能.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Response<T> {
+    server: SocketAddr,
+    inner: ResponseInner<T>,
+    breaker: Option<CircuitBreaker>,
+    hedge: Option<Box<Hedge<T>>>,
+    spawner: Option<Spawner>,
+    metrics: Option<MetricsRecorder>,
+    interceptors: Option<(ClientInterceptors, CallContext, Instant)>, // (.., .., 発行時刻)
+    #[cfg(feature = "tracing")]
+    span: Option<(Span, Instant)>,     // (.., 発行時刻)
+}
+impl<T> Response<T> {
+    pub(crate) fn spawner(&self) -> Option<&Spawner> {
+        self.spawner.as_ref()
+    }
+
+    // 応答が`delay`以内に得られない(ないし失敗した)場合に、`issue`で代替のリクエストを発行するようにする.
+    fn hedge<F>(mut self, delay: Duration, issue: F) -> Self
+    where
+        F: FnOnce() -> Response<T> + Send + 'static,
+    {
+        self.hedge = Some(Box::new(Hedge {
+            timer: timer::timeout(delay),
+            issue: Some(Box::new(issue)),
+            alternate: None,
+            primary_error: None,
+        }));
+        self
+    }
+
+    fn poll_primary(&mut self) -> Poll<T, Error> {
+        #[cfg(feature = "tracing")]
+        let entered = self.span.as_ref().map(|(span, _)| span.clone().entered());
+        let result = self.poll_primary_inner();
+        #[cfg(feature = "tracing")]
+        drop(entered);
+        if let Ok(Async::NotReady) = result {
+            return result;
+        }
+        #[cfg(feature = "tracing")]
+        {
+            if let Some((span, issued_at)) = self.span.take() {
+                span::record_result(&span, &result, issued_at.elapsed());
+            }
+        }
+        if let Some(recorder) = self.metrics.take() {
+            recorder.record(&result);
+        }
+        if let Some((interceptors, context, start_time)) = self.interceptors.take() {
+            interceptors.after_call(&context, result.as_ref().map(|_| ()), start_time.elapsed());
+        }
+        result
+    }
+
+    fn poll_primary_inner(&mut self) -> Poll<T, Error> {
+        let polled = match self.inner {
+            ResponseInner::Rejected(ref mut e) => {
+                return Err(e.take().expect("Cannot poll Response twice after failure"))
+            }
+            ResponseInner::Pending(ref mut f) => f.poll(),
+            ResponseInner::Local(ref mut f) => {
+                // ループバックでの処理の失敗は、サーバとの通信の失敗ではない
+                let polled = track!(f.poll());
+                if let (Some(breaker), false) =
+                    (&self.breaker, matches!(polled, Ok(Async::NotReady)))
+                {
+                    breaker.record_success();
+                }
+                return polled;
+            }
+        };
+        match polled {
+            Err(e) => {
+                if let Some(ref breaker) = self.breaker {
+                    if *e.kind() != fibers_rpc::ErrorKind::InvalidInput {
+                        breaker.record_failure();
+                    }
+                }
+                Err(from_rpc_error(e, self.server))
             }
             Ok(Async::NotReady) => Ok(Async::NotReady),
-            Ok(Async::Ready(result)) => track!(result.map(Async::Ready); self.server),
+            Ok(Async::Ready(result)) => {
+                if let Some(ref breaker) = self.breaker {
+                    breaker.record_success();
+                }
+                track!(result.map(Async::Ready); self.server)
+            }
+        }
+    }
+}
+impl<T> Future for Response<T> {
+    type Item = T;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self
+            .hedge
+            .as_ref()
+            .is_none_or(|h| h.primary_error.is_none())
+        {
+            match self.poll_primary() {
+                Err(e) => {
+                    if let Some(ref mut hedge) = self.hedge {
+                        hedge.primary_error = Some(e);
+                    } else {
+                        return Err(e);
+                    }
+                }
+                Ok(Async::NotReady) => {}
+                Ok(ready) => return Ok(ready),
+            }
+        }
+        if let Some(ref mut hedge) = self.hedge {
+            track!(hedge.poll())
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+// ヘッジリクエスト(代替サーバへの同じリクエスト)の状態.
+struct Hedge<T> {
+    timer: Timeout,
+    issue: Option<Box<dyn FnOnce() -> Response<T> + Send>>,
+    alternate: Option<Response<T>>,
+    primary_error: Option<Error>,
+}
+impl<T> Hedge<T> {
+    fn poll(&mut self) -> Poll<T, Error> {
+        if self.issue.is_some() {
+            // タイマーのエラーは、タイムアウトしたものとして扱う
+            let expired =
+                self.primary_error.is_some() || !matches!(self.timer.poll(), Ok(Async::NotReady));
+            if expired {
+                let issue = self.issue.take().expect("Never fails");
+                self.alternate = Some(issue());
+            }
+        }
+        if let Some(mut alternate) = self.alternate.take() {
+            match alternate.poll() {
+                Ok(Async::NotReady) => self.alternate = Some(alternate),
+                Ok(ready) => return Ok(ready),
+                Err(_) => {}
+            }
+        }
+        if self.issue.is_none() && self.alternate.is_none() {
+            // 代替リクエストは失敗済みなので、元のリクエストの結果に従う
+            if let Some(e) = self.primary_error.take() {
+                return Err(e);
+            }
         }
+        Ok(Async::NotReady)
+    }
+}
+impl<T> fmt::Debug for Hedge<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hedge")
+            .field("issued", &self.issue.is_none())
+            .field("primary_error", &self.primary_error)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+enum ResponseInner<T> {
+    Pending(fibers_rpc::client::Response<Result<T>>),
+    Rejected(Option<Error>), // サーキットブレーカによって発行が拒否された
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    Local(LocalResponse<T>), // ループバックで処理された
+}
+
+#[cfg(test)]
+mod tests {
+    use fibers::{Executor, InPlaceExecutor};
+    use fibers_rpc::client::ClientService;
+
+    use super::*;
+
+    fn client() -> Client {
+        let executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+        let service = ClientService::new(executor.handle());
+        Client::new("127.0.0.1:1919".parse().unwrap(), service.handle())
+    }
+
+    #[test]
+    fn deadline_timeout_works() {
+        let mut client = client();
+        let timeout = |builder: &RequestBuilder| builder.effective_rpc_options().timeout;
+
+        // デッドラインが未指定(ないし`Within`以外)の場合には、タイムアウトは導出されない
+        assert_eq!(
+            client.deadline_timeout_slack(),
+            Some(Duration::from_millis(500))
+        );
+        let mut request = client.request();
+        assert_eq!(timeout(&request), None);
+        request.deadline(Deadline::Immediate);
+        assert_eq!(timeout(&request), None);
+
+        let mut request = client.request();
+        request.deadline(Deadline::Within(Duration::from_secs(1)));
+        assert_eq!(timeout(&request), Some(Duration::from_millis(1500)));
+
+        // 長いデッドラインの場合でも、タイムアウトがデッドラインより短くなることはない
+        let mut request = client.request();
+        request.deadline(Deadline::Within(Duration::from_secs(3600)));
+        assert_eq!(timeout(&request), Some(Duration::from_millis(3_600_500)));
+
+        // 明示的に指定されたタイムアウトが優先される
+        request.rpc_options(fibers_rpc::client::Options {
+            timeout: Some(Duration::from_secs(10)),
+            ..Default::default()
+        });
+        assert_eq!(timeout(&request), Some(Duration::from_secs(10)));
+
+        client.set_deadline_timeout_slack(Some(Duration::from_millis(100)));
+        let mut request = client.request();
+        request.deadline(Deadline::Within(Duration::from_secs(1)));
+        assert_eq!(timeout(&request), Some(Duration::from_millis(1100)));
+
+        client.set_deadline_timeout_slack(None);
+        let mut request = client.request();
+        request.deadline(Deadline::Within(Duration::from_secs(1)));
+        assert_eq!(timeout(&request), None);
+    }
+
+    #[test]
+    fn call_options_works() {
+        let mut client = client();
+        client.set_deadline_timeout_slack(Some(Duration::from_millis(500)));
+        let mut request = client.request();
+        request
+            .deadline(Deadline::Within(Duration::from_secs(1)))
+            .max_queue_len(10);
+
+        // 指定されたフィールドのみが上書きされる
+        let opts = CallOptions {
+            deadline: Some(Deadline::Within(Duration::from_secs(2))),
+            ..Default::default()
+        };
+        let options = request.request_options_with(Some(&opts));
+        assert_eq!(options.deadline, Deadline::Within(Duration::from_secs(2)));
+        assert_eq!(options.max_queue_len, Some(10));
+        assert_eq!(
+            request.effective_rpc_options_with(Some(&opts)).timeout,
+            Some(Duration::from_millis(2500))
+        );
+
+        // ビルダ自体の設定は変更されない
+        let options = request.request_options();
+        assert_eq!(options.deadline, Deadline::Within(Duration::from_secs(1)));
+        assert_eq!(
+            request.effective_rpc_options().timeout,
+            Some(Duration::from_millis(1500))
+        );
     }
 }
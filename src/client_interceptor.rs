@@ -0,0 +1,168 @@
+//! クライアント側のRPC呼び出しに割り込むためのインターセプタ.
+use cannyls::{Error, Result};
+use slog::Level;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::device::DeviceId;
+use crate::rpc::{self, RequestOptions};
+
+/// インターセプタに渡される、RPC呼び出しの情報.
+#[derive(Debug, Clone)]
+pub struct CallContext {
+    /// RPCの名前(e.g., `cannyls.lump.get`).
+    pub procedure: &'static str,
+
+    /// 接続先のサーバのアドレス.
+    pub server: SocketAddr,
+
+    /// リクエストのオプション.
+    ///
+    /// `before_call`内で変更した場合には、その内容でリクエストが発行される.
+    /// オプションを持たないリクエスト(e.g., `rpc::ServerInfoRpc`や`rpc::ListDevicesRpc`)の場合には`None`となり、
+    /// `Some`に変更しても無視される.
+    pub options: Option<RequestOptions>,
+
+    /// RPCレベルのオプション.
+    ///
+    /// デッドラインから導出されたタイムアウト(`Client::set_deadline_timeout_slack`を参照)は適用済み.
+    /// `before_call`内で変更した場合には、その内容でリクエストが発行される.
+    pub rpc_options: fibers_rpc::client::Options,
+}
+
+/// クライアント側のRPC呼び出しに割り込むためのインターセプタ.
+///
+/// `Client::add_interceptor`で登録され、リクエストの発行前と、その結果の確定後に呼び出される.
+/// 対象となるのは`Client::metrics`の記録対象と同じRPCで、
+/// トレースIDの付与や独自のメトリクスの収集等を、全てのRPCに対して一様に適用するためのもの.
+///
+/// 再試行(`RequestBuilder::retry`)やヘッジリクエスト(`RequestBuilder::hedge`)では、
+/// 個々のRPCの発行毎に呼び出される(ヘッジリクエストの場合は、代替サーバのクライアントに登録されたインターセプタが使用される).
+///
+/// インターセプタはRPCの発行およびポーリング中に同期的に呼び出されるので、重い処理は別スレッド等に委譲すべきである.
+pub trait ClientInterceptor: Send + Sync + 'static {
+    /// リクエストの発行前に呼び出される.
+    ///
+    /// `context`のオプションを変更することで、発行されるリクエストの内容を変更できる.
+    /// エラーを返した場合には、リクエストは発行されずに、そのエラーが結果として返される.
+    /// その場合、後続のインターセプタの`before_call`は呼び出されない.
+    ///
+    /// デフォルト実装は、何もせずに`Ok(())`を返す.
+    fn before_call(&self, context: &mut CallContext) -> Result<()> {
+        let _ = context;
+        Ok(())
+    }
+
+    /// リクエストの結果の確定後に呼び出される.
+    ///
+    /// `before_call`が呼び出された全てのリクエストに対して、(リクエストが拒否された場合も含めて)一度だけ呼び出される.
+    /// ただし、結果の確定前にリクエストの`Future`が破棄された場合には、呼び出されない.
+    ///
+    /// `context`は、全てのインターセプタの`before_call`による変更が適用された後のもの.
+    /// `elapsed`はリクエストの発行から結果の確定までの時間で、発行前に拒否された場合には`0`となる.
+    ///
+    /// デフォルト実装は、何もしない.
+    fn after_call(
+        &self,
+        context: &CallContext,
+        result: std::result::Result<(), &Error>,
+        elapsed: Duration,
+    ) {
+        let _ = (context, result, elapsed);
+    }
+}
+
+/// クライアントに登録されたインターセプタ群.
+#[derive(Clone, Default)]
+pub struct ClientInterceptors(Vec<Arc<dyn ClientInterceptor>>);
+impl ClientInterceptors {
+    pub fn add<I: ClientInterceptor>(&mut self, interceptor: I) {
+        self.0.push(Arc::new(interceptor));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // 登録順に`before_call`を呼び出す.
+    //
+    // 拒否された場合には、全てのインターセプタの`after_call`をそのエラーで呼び出す.
+    pub fn before_call(&self, context: &mut CallContext) -> Result<()> {
+        for interceptor in &self.0 {
+            if let Err(e) = interceptor.before_call(context) {
+                let e = track!(e);
+                self.after_call(context, Err(&e), Duration::default());
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn after_call(
+        &self,
+        context: &CallContext,
+        result: std::result::Result<(), &Error>,
+        elapsed: Duration,
+    ) {
+        for interceptor in &self.0 {
+            interceptor.after_call(context, result, elapsed);
+        }
+    }
+}
+impl fmt::Debug for ClientInterceptors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ClientInterceptors {{ len: {} }}", self.0.len())
+    }
+}
+
+/// インターセプタから、リクエストのオプションを参照・変更するためのトレイト.
+pub trait RequestOptionsMut {
+    /// リクエストのオプションへの可変参照を返す.
+    ///
+    /// オプションを持たないリクエストの場合には`None`が返される.
+    fn request_options_mut(&mut self) -> Option<&mut RequestOptions>;
+}
+
+macro_rules! impl_request_options_mut {
+    ($($request:ty),*) => {
+        $(impl RequestOptionsMut for $request {
+            fn request_options_mut(&mut self) -> Option<&mut RequestOptions> {
+                Some(&mut self.options)
+            }
+        })*
+    };
+}
+impl_request_options_mut!(
+    rpc::DeviceRequest,
+    rpc::LumpRequest,
+    rpc::PutLumpRequest,
+    rpc::GetLumpRangeRequest,
+    rpc::PutLumpFromReaderRequest,
+    rpc::UsageRangeRequest,
+    rpc::RangeLumpRequest,
+    rpc::CopyRangeRequest,
+    rpc::ScriptRequest,
+    rpc::GetLumpsRequest,
+    rpc::ExportLumpsRequest,
+    rpc::DeleteRangeBoundedRequest,
+    rpc::PutLumpsRequest,
+    rpc::ImportLumpsRequest,
+    rpc::ImportSessionRequest,
+    rpc::SetJournalSyncRequest,
+    rpc::SetQueueLimitsRequest,
+    rpc::SetWriteWatermarkRequest,
+    rpc::ProvisionDeviceRequest
+);
+
+macro_rules! impl_no_request_options {
+    ($($request:ty),*) => {
+        $(impl RequestOptionsMut for $request {
+            fn request_options_mut(&mut self) -> Option<&mut RequestOptions> {
+                None
+            }
+        })*
+    };
+}
+impl_no_request_options!((), bool, u64, Level, Vec<DeviceId>);
@@ -0,0 +1,479 @@
+//! 標準ライブラリの`Future`(i.e., async/await)との相互運用.
+//!
+//! このクレートのクライアントが返す`Future`群は`futures`0.1のもので、`fibers`のエグゼキュータ上で実行される必要がある.
+//! `Client::set_spawner`でエグゼキュータを設定しておけば、`RequestBuilder`の各メソッドが返す`Future`は、
+//! async関数内で直接`.await`することができる(この場合、リクエストはエグゼキュータ上のファイバとして実行される).
+//!
+//! ```no_run
+//! # extern crate cannyls_rpc;
+//! # extern crate fibers;
+//! # use cannyls_rpc::{Client, DeviceId, LumpId, Result};
+//! # use fibers::{Executor, ThreadPoolExecutor};
+//! # fn main() {
+//! # let mut client: Client = unimplemented!();
+//! let executor = ThreadPoolExecutor::new().unwrap();
+//! client.set_spawner(executor.handle());
+//! # }
+//!
+//! async fn get(client: &Client) -> Result<Option<Vec<u8>>> {
+//!     client
+//!         .request()
+//!         .get_lump(DeviceId::new("foo"), LumpId::new(0))
+//!         .await
+//! }
+//! ```
+//!
+//! `export_lumps`や`list_lumps_stream`等が返す`Stream`は、`compat`メソッドで`CompatStream`に変換した上で、
+//! `next().await`によって要素を一つずつ取り出すことができる.
+//! 同様に`put_pipeline`の`Sink`は`CompatSink`に変換できる.
+//!
+//! ```no_run
+//! # extern crate cannyls_rpc;
+//! # use cannyls_rpc::{Client, DeviceId, LumpId, Result};
+//! async fn count(client: &Client) -> Result<usize> {
+//!     let mut ids = client
+//!         .request()
+//!         .list_lumps_stream(DeviceId::new("foo"), LumpId::new(0)..LumpId::new(100), 16)
+//!         .compat();
+//!     let mut count = 0;
+//!     while let Some(_) = ids.next().await? {
+//!         count += 1;
+//!     }
+//!     Ok(count)
+//! }
+//! # fn main() {}
+//! ```
+use cannyls::lump::LumpId;
+use cannyls::{Error, ErrorKind, Result};
+use fibers::sync::mpsc;
+use fibers::{BoxSpawn, Spawn};
+use futures::future::{self, Either};
+use futures::{Future, Sink, Stream};
+use std::collections::BTreeMap;
+use std::future::IntoFuture;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use trackable::error::ErrorKindExt;
+
+use crate::client::{
+    ConnectFuture, GetLumpFuture, GetLumpsConcurrentFuture, GetLumpsFuture, RequestBuilder,
+    Response,
+};
+use crate::retry::BusyRetry;
+
+/// `futures`0.1の`Future`を`fibers`のエグゼキュータ上で実行し、その結果を返す標準ライブラリの`Future`.
+///
+/// `Client::set_spawner`が設定されたクライアントのリクエストを`.await`した場合や、
+/// `Compat::spawn`で任意の`Future`(e.g., コンビネータで合成したもの)を実行した場合に生成される.
+///
+/// ドロップしても、実行中のファイバは停止されない.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Compat<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+impl<T: Send + 'static> Compat<T> {
+    /// `future`を`spawner`上のファイバとして実行する.
+    ///
+    /// 結果を受け取る前にファイバが破棄された場合(e.g., エグゼキュータの停止)には、
+    /// `ErrorKind::Other`エラーが返される.
+    pub fn spawn<S, F>(spawner: &S, future: F) -> Self
+    where
+        S: Spawn + ?Sized,
+        F: Future<Item = T, Error = Error> + Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        let sender = Sender(Some(slot.clone()));
+        spawner.spawn(future.then(move |result| {
+            sender.send(result);
+            Ok(())
+        }));
+        Compat { slot }
+    }
+
+    // 既に結果が確定している`Compat`を生成する.
+    fn ready(result: Result<T>) -> Self {
+        let slot = Slot {
+            result: Some(result),
+            waker: None,
+        };
+        Compat {
+            slot: Arc::new(Mutex::new(slot)),
+        }
+    }
+}
+impl<T> std::future::Future for Compat<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = lock(&self.slot);
+        if let Some(result) = slot.result.take() {
+            Poll::Ready(result)
+        } else {
+            slot.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// `futures`0.1の`Stream`を`fibers`のエグゼキュータ上で実行し、その要素を標準ライブラリの`Future`として返すもの.
+///
+/// `ExportLumpsStream::compat`等によって生成される.
+/// `Stream`のポーリングは`next`の呼び出しに応じて行われ、先読みはされない.
+///
+/// `Stream`がエラーを返した場合には、以降は終端に達したものとして扱われる.
+/// ドロップされると、ファイバ(およびファイバが保持する`Stream`)も破棄される.
+#[derive(Debug)]
+pub struct CompatStream<T> {
+    request_tx: Option<mpsc::Sender<Sender<Option<T>>>>,
+}
+impl<T: Send + 'static> CompatStream<T> {
+    /// `stream`を`spawner`上のファイバとして実行する.
+    pub fn spawn<S, St>(spawner: &S, stream: St) -> Self
+    where
+        S: Spawn + ?Sized,
+        St: Stream<Item = T, Error = Error> + Send + 'static,
+    {
+        let (request_tx, request_rx) = mpsc::channel();
+        let fiber = request_rx.fold(Some(stream), |stream, reply: Sender<Option<T>>| {
+            let stream = if let Some(stream) = stream {
+                stream
+            } else {
+                reply.send(Ok(None));
+                return Either::A(future::ok(None));
+            };
+            Either::B(stream.into_future().then(move |result| {
+                let (result, stream) = match result {
+                    Ok((Some(item), stream)) => (Ok(Some(item)), Some(stream)),
+                    Ok((None, _)) => (Ok(None), None),
+                    Err((e, _)) => (Err(e), None),
+                };
+                reply.send(result);
+                Ok(stream)
+            }))
+        });
+        spawner.spawn(fiber.map(|_| ()));
+        CompatStream {
+            request_tx: Some(request_tx),
+        }
+    }
+
+    /// 次の要素を返す.
+    ///
+    /// `Stream`が終端に達している場合には`Ok(None)`が返される.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Compat<Option<T>> {
+        let request_tx = if let Some(ref request_tx) = self.request_tx {
+            request_tx
+        } else {
+            return Compat::ready(Err(no_spawner_error()));
+        };
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        // ファイバが既に破棄されている場合には、送信に失敗した`Sender`のドロップによってエラーが渡される
+        let _ = request_tx.send(Sender(Some(slot.clone())));
+        Compat { slot }
+    }
+
+    pub(crate) fn spawn_or_fail<St>(spawner: Option<Spawner>, stream: St) -> Self
+    where
+        St: Stream<Item = T, Error = Error> + Send + 'static,
+    {
+        if let Some(spawner) = spawner {
+            CompatStream::spawn(&spawner, stream)
+        } else {
+            CompatStream { request_tx: None }
+        }
+    }
+}
+
+/// `futures`0.1の`Sink`を`fibers`のエグゼキュータ上で実行し、
+/// 要素の送信を標準ライブラリの`Future`として扱えるようにするもの.
+///
+/// `PutPipelineSink::compat`によって生成される.
+///
+/// `Sink`がエラーを返した場合には、以降の送信は全て`ErrorKind::Other`で失敗する.
+/// ドロップされると、それまでに送信された要素の処理後に`Sink`がクローズされる.
+#[derive(Debug)]
+pub struct CompatSink<T> {
+    request_tx: Option<mpsc::Sender<(T, Sender<()>)>>,
+}
+impl<T: Send + 'static> CompatSink<T> {
+    /// `sink`を`spawner`上のファイバとして実行する.
+    pub fn spawn<S, Si>(spawner: &S, sink: Si) -> Self
+    where
+        S: Spawn + ?Sized,
+        Si: Sink<SinkItem = T, SinkError = Error> + Send + 'static,
+    {
+        let (request_tx, request_rx) = mpsc::channel();
+        let fiber = request_rx
+            .fold(Some(sink), |sink, (item, reply): (T, Sender<()>)| {
+                let sink = if let Some(sink) = sink {
+                    sink
+                } else {
+                    let e = ErrorKind::Other.cause("The sink has been terminated by an error");
+                    reply.send(Err(track!(Error::from(e))));
+                    return Either::A(future::ok(None));
+                };
+                Either::B(sink.send(item).then(move |result| match result {
+                    Ok(sink) => {
+                        reply.send(Ok(()));
+                        Ok(Some(sink))
+                    }
+                    Err(e) => {
+                        reply.send(Err(e));
+                        Ok(None)
+                    }
+                }))
+            })
+            .and_then(|sink| {
+                let mut sink = sink;
+                future::poll_fn(move || {
+                    if let Some(ref mut sink) = sink {
+                        sink.close().map_err(|_| ())
+                    } else {
+                        Ok(futures::Async::Ready(()))
+                    }
+                })
+            });
+        spawner.spawn(fiber);
+        CompatSink {
+            request_tx: Some(request_tx),
+        }
+    }
+
+    /// 要素を送信する.
+    ///
+    /// 返された`Future`は、`Sink`が要素を受け付けた(`Sink::send`が完了した)時点で完了する.
+    pub fn send(&mut self, item: T) -> Compat<()> {
+        let request_tx = if let Some(ref request_tx) = self.request_tx {
+            request_tx
+        } else {
+            return Compat::ready(Err(no_spawner_error()));
+        };
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        let _ = request_tx.send((item, Sender(Some(slot.clone()))));
+        Compat { slot }
+    }
+
+    pub(crate) fn spawn_or_fail<Si>(spawner: Option<Spawner>, sink: Si) -> Self
+    where
+        Si: Sink<SinkItem = T, SinkError = Error> + Send + 'static,
+    {
+        if let Some(spawner) = spawner {
+            CompatSink::spawn(&spawner, sink)
+        } else {
+            CompatSink { request_tx: None }
+        }
+    }
+}
+
+/// `Client::set_spawner`で設定されたエグゼキュータ.
+///
+/// クライアント間で共有できるように、`BoxSpawn`をラップしている.
+#[derive(Debug, Clone)]
+pub(crate) struct Spawner(Arc<Mutex<BoxSpawn>>);
+impl Spawner {
+    pub fn new<S: Spawn + Send + 'static>(spawner: S) -> Self {
+        Spawner(Arc::new(Mutex::new(spawner.boxed())))
+    }
+}
+impl Spawn for Spawner {
+    fn spawn_boxed(&self, fiber: Box<dyn Future<Item = (), Error = ()> + Send>) {
+        lock(&self.0).spawn_boxed(fiber);
+    }
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    result: Option<Result<T>>,
+    waker: Option<Waker>,
+}
+impl<T> Default for Slot<T> {
+    fn default() -> Self {
+        Slot {
+            result: None,
+            waker: None,
+        }
+    }
+}
+
+// 結果を`Compat`に渡すためのもの.
+//
+// 結果を渡さずにドロップされた場合には、代わりにエラーを渡す.
+struct Sender<T>(Option<Arc<Mutex<Slot<T>>>>);
+impl<T> Sender<T> {
+    fn send(mut self, result: Result<T>) {
+        self.complete(result);
+    }
+
+    fn complete(&mut self, result: Result<T>) {
+        if let Some(slot) = self.0.take() {
+            let mut slot = lock(&slot);
+            slot.result = Some(result);
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let e = ErrorKind::Other.cause("The fiber was dropped before completion");
+        self.complete(Err(track!(Error::from(e))));
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // 値の更新中にパニックすることはないので、ポイズンは無視して構わない
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// エグゼキュータが設定されていない場合には、即座に`ErrorKind::Other`で失敗する.
+fn spawn_or_fail<F>(spawner: Option<Spawner>, future: F) -> Compat<F::Item>
+where
+    F: Future<Error = Error> + Send + 'static,
+    F::Item: Send + 'static,
+{
+    if let Some(spawner) = spawner {
+        Compat::spawn(&spawner, future)
+    } else {
+        Compat::ready(Err(no_spawner_error()))
+    }
+}
+
+fn no_spawner_error() -> Error {
+    let e = ErrorKind::Other.cause("No executor is set (see `Client::set_spawner`)");
+    track!(Error::from(e))
+}
+
+macro_rules! impl_into_future {
+    ($([$($params:tt)*] $future:ty => $item:ty),* $(,)*) => {
+        $(impl<$($params)*> IntoFuture for $future {
+            type Output = Result<$item>;
+            type IntoFuture = Compat<$item>;
+
+            fn into_future(self) -> Self::IntoFuture {
+                spawn_or_fail(self.spawner().cloned(), self)
+            }
+        })*
+    };
+}
+
+impl_into_future!(
+    [T: Send + 'static] Response<T> => T,
+    [] GetLumpFuture => Option<Vec<u8>>,
+    [] GetLumpsFuture => Vec<Result<Option<Vec<u8>>>>,
+    [] ConnectFuture => (),
+);
+
+// ビルダを保持する`Future`は、クライアントを借用しないように変換してから実行する.
+impl<'a> IntoFuture for GetLumpsConcurrentFuture<'a> {
+    type Output = Result<BTreeMap<LumpId, Result<Option<Vec<u8>>>>>;
+    type IntoFuture = Compat<BTreeMap<LumpId, Result<Option<Vec<u8>>>>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        spawn_or_fail(self.spawner().cloned(), self.into_owned())
+    }
+}
+impl<'a, F, T> IntoFuture for BusyRetry<'a, F, T>
+where
+    F: for<'b> FnMut(&RequestBuilder<'b>) -> T + Send + 'static,
+    T: Future<Error = Error> + Send + 'static,
+    T::Item: Send + 'static,
+{
+    type Output = Result<T::Item>;
+    type IntoFuture = Compat<T::Item>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        spawn_or_fail(self.spawner().cloned(), self.into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fibers::{Executor, InPlaceExecutor};
+    use futures::future;
+    use std::future::Future as StdFuture;
+    use std::task::Wake;
+    use std::thread;
+
+    use super::*;
+
+    fn block_on<F: StdFuture>(future: F) -> F::Output {
+        struct ThreadWaker(thread::Thread);
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn compat_works() {
+        let mut executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+        let ok = Compat::spawn(&executor.handle(), future::ok(1));
+        let full = Error::from(ErrorKind::StorageFull.error());
+        let err = Compat::spawn(&executor.handle(), future::err::<(), _>(full));
+        let dropped = Compat::spawn(&executor.handle(), future::empty::<(), _>());
+        let handle = thread::spawn(move || {
+            for _ in 0..10 {
+                track_try_unwrap!(track_any_err!(executor.run_once()));
+            }
+        });
+        assert_eq!(block_on(ok).ok(), Some(1));
+        assert_eq!(*block_on(err).err().unwrap().kind(), ErrorKind::StorageFull);
+
+        // エグゼキュータと共にファイバが破棄された場合
+        handle.join().unwrap();
+        assert_eq!(*block_on(dropped).err().unwrap().kind(), ErrorKind::Other);
+    }
+
+    #[test]
+    fn no_spawner_works() {
+        let e = block_on(spawn_or_fail(None, future::ok(1))).err().unwrap();
+        assert_eq!(*e.kind(), ErrorKind::Other);
+
+        let mut stream = CompatStream::spawn_or_fail(None, futures::stream::iter_ok(vec![1]));
+        assert_eq!(
+            *block_on(stream.next()).err().unwrap().kind(),
+            ErrorKind::Other
+        );
+    }
+
+    #[test]
+    fn compat_stream_works() {
+        let executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+        let full = Error::from(ErrorKind::StorageFull.error());
+        let items = vec![Ok(1), Ok(2), Err(full), Ok(3)];
+        let mut stream =
+            CompatStream::spawn(&executor.handle(), futures::stream::iter_result(items));
+        let (tx, rx) = futures::sync::mpsc::unbounded();
+        let mut sink = CompatSink::spawn(&executor.handle(), tx.sink_map_err(|_| unreachable!()));
+        thread::spawn(move || {
+            let _ = executor.run();
+        });
+
+        assert_eq!(block_on(stream.next()).ok(), Some(Some(1)));
+        assert_eq!(block_on(stream.next()).ok(), Some(Some(2)));
+        let e = block_on(stream.next()).err().unwrap();
+        assert_eq!(*e.kind(), ErrorKind::StorageFull);
+
+        // エラー後は終端として扱われる
+        assert_eq!(block_on(stream.next()).ok(), Some(None));
+
+        assert!(block_on(sink.send(10)).is_ok());
+        assert!(block_on(sink.send(20)).is_ok());
+        drop(sink);
+        assert_eq!(rx.wait().collect::<Vec<_>>(), vec![Ok(10), Ok(20)]);
+    }
+}
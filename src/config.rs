@@ -0,0 +1,744 @@
+//! 設定ファイルの読み込み.
+//!
+//! 設定ファイルは[TOML]のサブセットで記述する(詳細は`NodeConfig`を参照のこと).
+//!
+//! [TOML]: https://toml.io/
+use cannyls::block::BlockSize;
+use cannyls::{Error, ErrorKind, Result};
+use slog::Level;
+use std::fs;
+use std::iter::Peekable;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::{Chars, FromStr};
+use trackable::error::ErrorKindExt;
+
+use crate::device::{DeviceId, DeviceLabels, DeviceNvmSpec, DeviceSpec};
+
+/// ストレージノード(e.g., `cannyls-rpc-server`)の設定.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate cannyls_rpc;
+/// use cannyls_rpc::NodeConfig;
+///
+/// let config: NodeConfig = r#"
+/// listen = "127.0.0.1:14278"
+/// admin_rpc = true
+///
+/// [[device]]
+/// id = "dev0"
+/// path = "/var/lib/cannyls/dev0.lusf"
+/// capacity = 10_737_418_240
+/// labels = { zone = "a" }
+///
+/// [[device]]
+/// id = "tmp"
+/// memory = true
+/// capacity = 1_048_576
+/// "#
+/// .parse()
+/// .unwrap();
+/// assert_eq!(config.devices.len(), 2);
+/// ```
+///
+/// # 設定項目
+///
+/// トップレベル:
+///
+/// - `listen` (必須): RPCサーバの待ち受けアドレス
+/// - `admin_rpc`: `true`の場合には、管理用のRPCを有効にする(`Server::enable_admin_rpc`). デフォルトは`false`
+/// - `log_level`: ログの出力レベル(e.g., `"debug"`). デフォルトは`"info"`
+///
+/// `[[device]]` (デバイス毎に一つ):
+///
+/// - `id` (必須): デバイスのID
+/// - `path`: lusfファイルのパス. 相対パスの場合には、プロセスのカレントディレクトリからのものとして扱われる
+/// - `memory`: `true`の場合には、メモリ上の領域を用いる(`path`とは排他)
+/// - `capacity` (必須): 容量(バイト単位). ファイルの場合には、新規作成時にのみ使用される
+/// - `block_size`: ストレージのブロックサイズ. デフォルトは`512`
+/// - `labels`: デバイスに付与するラベル群(値が文字列のインラインテーブル)
+///
+/// # 書式
+///
+/// 設定ファイルは[TOML]のサブセットで記述する.
+/// サポートしているのは、以下の要素のみ:
+///
+/// - コメント(`#`から行末まで)
+/// - テーブル(`[name]`)およびテーブルの配列(`[[name]]`)のヘッダ
+/// - 一行の`key = value`形式のエントリ
+/// - 値としての文字列(`"basic"`ないし`'literal'`)・十進数の整数(`1_024`のような区切りを含む)・真偽値・
+///   一行のインラインテーブル(`{ k = "v" }`)
+///
+/// 以下のようなTOMLの構文はサポートされておらず、含まれている場合には(該当する行番号と共に)
+/// `ErrorKind::InvalidInput`エラーとなる(i.e., 異なる意味に解釈されて読み込まれることはない):
+///
+/// - 配列(`[1, 2]`)
+/// - 浮動小数点数(`1.5`, `1e3`, `inf`)
+/// - 十進数以外や先頭に`0`を含む整数(`0x10`, `0o17`, `0b11`, `010`)
+/// - 日時(`1979-05-27T07:32:00Z`)
+/// - 複数行の文字列(`"""..."""`, `'''...'''`)および`\u`形式等のエスケープシーケンス
+/// - ドット区切りのキーおよびテーブル名(`a.b = 1`, `[a.b]`)
+/// - 複数行に渡るインラインテーブル
+///
+/// [TOML]: https://toml.io/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeConfig {
+    /// RPCサーバの待ち受けアドレス.
+    pub listen: SocketAddr,
+
+    /// `true`の場合には、管理用のRPCを有効にする.
+    pub admin_rpc: bool,
+
+    /// ログの出力レベル.
+    pub log_level: Level,
+
+    /// 構築するデバイスの仕様の一覧.
+    pub devices: Vec<DeviceSpec>,
+}
+impl NodeConfig {
+    /// 指定のパスの設定ファイルを読み込む.
+    ///
+    /// 設定ファイルの形式が不正な場合には`ErrorKind::InvalidInput`エラーが返される.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = track!(fs::read_to_string(path).map_err(Error::from); path)?;
+        track!(text.parse(); path)
+    }
+}
+impl FromStr for NodeConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut listen = None;
+        let mut admin_rpc = false;
+        let mut log_level = Level::Info;
+        let mut devices = Vec::new();
+        for section in track!(parse_document(s))? {
+            match (section.name.as_str(), section.is_array) {
+                ("", false) => {
+                    for entry in section.entries {
+                        match entry.key.as_str() {
+                            "listen" => {
+                                let addr = track!(entry.string())?;
+                                let addr = track!(addr
+                                    .parse()
+                                    .map_err(|_| entry.error("Invalid socket address")))?;
+                                listen = Some(addr);
+                            }
+                            "admin_rpc" => admin_rpc = track!(entry.boolean())?,
+                            "log_level" => {
+                                let level = track!(entry.string())?;
+                                log_level = track!(level
+                                    .parse()
+                                    .map_err(|()| entry.error("Unknown log level")))?;
+                            }
+                            _ => return Err(entry.error("Unknown key")),
+                        }
+                    }
+                }
+                ("device", true) => devices.push(track!(section.device_spec())?),
+                _ => track_panic!(
+                    ErrorKind::InvalidInput,
+                    "Line {}: Unknown section {:?}",
+                    section.line,
+                    section.name
+                ),
+            }
+        }
+        let listen =
+            track_assert_some!(listen, ErrorKind::InvalidInput, "`listen` is not specified");
+        track!(validate_device_specs(&devices))?;
+        Ok(NodeConfig {
+            listen,
+            admin_rpc,
+            log_level,
+            devices,
+        })
+    }
+}
+
+/// 指定のパスのファイルから、デバイスの仕様(`[[device]]`テーブル)の一覧を読み込む.
+///
+/// `NodeConfig`とは異なり、`[[device]]`以外のテーブルおよびトップレベルのエントリは無視される.
+pub(crate) fn load_device_specs<P: AsRef<Path>>(path: P) -> Result<Vec<DeviceSpec>> {
+    let path = path.as_ref();
+    let text = track!(fs::read_to_string(path).map_err(Error::from); path)?;
+    track!(parse_device_specs(&text); path)
+}
+
+fn parse_device_specs(s: &str) -> Result<Vec<DeviceSpec>> {
+    let devices = track!(parse_document(s))?
+        .into_iter()
+        .filter(|section| section.name == "device" && section.is_array)
+        .map(|section| track!(section.device_spec()))
+        .collect::<Result<Vec<_>>>()?;
+    track!(validate_device_specs(&devices))?;
+    Ok(devices)
+}
+
+// デバイスIDやファイルパスが、複数のデバイス間で重複していないかを確認する.
+pub(crate) fn validate_device_specs(specs: &[DeviceSpec]) -> Result<()> {
+    for (i, spec) in specs.iter().enumerate() {
+        for other in &specs[..i] {
+            track_assert_ne!(
+                spec.device_id,
+                other.device_id,
+                ErrorKind::InvalidInput,
+                "Duplicate device ID"
+            );
+            if let (DeviceNvmSpec::File(a), DeviceNvmSpec::File(b)) = (&spec.nvm, &other.nvm) {
+                track_assert_ne!(a, b, ErrorKind::InvalidInput, "Duplicate device path");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Table(Vec<(String, Value)>),
+}
+
+#[derive(Debug)]
+struct Entry {
+    key: String,
+    value: Value,
+    line: usize,
+}
+impl Entry {
+    fn error(&self, message: &str) -> Error {
+        ErrorKind::InvalidInput
+            .cause(format!("Line {}: {}: {:?}", self.line, message, self.key))
+            .into()
+    }
+
+    fn string(&self) -> Result<String> {
+        if let Value::String(ref s) = self.value {
+            Ok(s.clone())
+        } else {
+            Err(self.error("A string is expected"))
+        }
+    }
+
+    fn integer(&self) -> Result<i64> {
+        if let Value::Integer(n) = self.value {
+            Ok(n)
+        } else {
+            Err(self.error("An integer is expected"))
+        }
+    }
+
+    fn boolean(&self) -> Result<bool> {
+        if let Value::Boolean(b) = self.value {
+            Ok(b)
+        } else {
+            Err(self.error("A boolean is expected"))
+        }
+    }
+}
+
+// テーブル(トップレベルの場合には`name`は空文字列).
+#[derive(Debug)]
+struct Section {
+    name: String,
+    is_array: bool,
+    line: usize,
+    entries: Vec<Entry>,
+}
+impl Section {
+    fn device_spec(self) -> Result<DeviceSpec> {
+        let mut device_id = None;
+        let mut path = None;
+        let mut memory = false;
+        let mut capacity = None;
+        let mut block_size = BlockSize::min();
+        let mut labels = DeviceLabels::new();
+        for entry in &self.entries {
+            match entry.key.as_str() {
+                "id" => device_id = Some(DeviceId::new(track!(entry.string())?)),
+                "path" => path = Some(PathBuf::from(track!(entry.string())?)),
+                "memory" => memory = track!(entry.boolean())?,
+                "capacity" => {
+                    let n = track!(entry.integer())?;
+                    track_assert!(
+                        n > 0,
+                        ErrorKind::InvalidInput,
+                        "Line {}: `capacity` must be positive",
+                        entry.line
+                    );
+                    capacity = Some(n as u64);
+                }
+                "block_size" => {
+                    let n = track!(entry.integer())?;
+                    track_assert!(
+                        0 < n && n <= i64::from(u16::MAX),
+                        ErrorKind::InvalidInput,
+                        "Line {}: `block_size` is out of range",
+                        entry.line
+                    );
+                    block_size = track!(BlockSize::new(n as u16), "line={}", entry.line)?;
+                }
+                "labels" => {
+                    let table = match entry.value {
+                        Value::Table(ref table) => table,
+                        _ => return Err(entry.error("An inline table is expected")),
+                    };
+                    for (key, value) in table {
+                        match *value {
+                            Value::String(ref value) => {
+                                labels.insert(key.clone(), value.clone());
+                            }
+                            _ => return Err(entry.error("Label values must be strings")),
+                        }
+                    }
+                }
+                _ => return Err(entry.error("Unknown key")),
+            }
+        }
+        let line = self.line;
+        let device_id = track_assert_some!(
+            device_id,
+            ErrorKind::InvalidInput,
+            "Line {}: `id` is not specified",
+            line
+        );
+        let capacity = track_assert_some!(
+            capacity,
+            ErrorKind::InvalidInput,
+            "Line {}: `capacity` is not specified",
+            line
+        );
+        let nvm = match (path, memory) {
+            (Some(path), false) => DeviceNvmSpec::File(path),
+            (None, true) => DeviceNvmSpec::Memory,
+            _ => track_panic!(
+                ErrorKind::InvalidInput,
+                "Line {}: Exactly one of `path` or `memory = true` must be specified",
+                line
+            ),
+        };
+        Ok(DeviceSpec {
+            device_id,
+            nvm,
+            capacity,
+            block_size,
+            labels,
+        })
+    }
+}
+
+fn parse_document(s: &str) -> Result<Vec<Section>> {
+    let mut sections = vec![Section {
+        name: String::new(),
+        is_array: false,
+        line: 0,
+        entries: Vec::new(),
+    }];
+    for (i, line) in s.lines().enumerate() {
+        let line_number = i + 1;
+        let mut cursor = Cursor {
+            chars: line.chars().peekable(),
+            line: line_number,
+        };
+        cursor.skip_whitespaces();
+        match cursor.chars.peek() {
+            None | Some('#') => continue,
+            Some('[') => {
+                cursor.chars.next();
+                let is_array = cursor.eat('[');
+                let name = track!(cursor.key())?;
+                track!(cursor.expect(']'))?;
+                if is_array {
+                    track!(cursor.expect(']'))?;
+                }
+                track!(cursor.end())?;
+                track_assert!(
+                    is_array || sections.iter().all(|s| s.name != name),
+                    ErrorKind::InvalidInput,
+                    "Line {}: Duplicate table {:?}",
+                    line_number,
+                    name
+                );
+                sections.push(Section {
+                    name,
+                    is_array,
+                    line: line_number,
+                    entries: Vec::new(),
+                });
+            }
+            Some(_) => {
+                let (key, value) = track!(cursor.key_value())?;
+                track!(cursor.end())?;
+                let section = sections.last_mut().expect("Never fails");
+                track_assert!(
+                    section.entries.iter().all(|e| e.key != key),
+                    ErrorKind::InvalidInput,
+                    "Line {}: Duplicate key {:?}",
+                    line_number,
+                    key
+                );
+                section.entries.push(Entry {
+                    key,
+                    value,
+                    line: line_number,
+                });
+            }
+        }
+    }
+    Ok(sections)
+}
+
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+impl<'a> Cursor<'a> {
+    fn error(&self, message: &str) -> Error {
+        ErrorKind::InvalidInput
+            .cause(format!("Line {}: {}", self.line, message))
+            .into()
+    }
+
+    fn skip_whitespaces(&mut self) {
+        while self.chars.peek().is_some_and(|c| *c == ' ' || *c == '\t') {
+            self.chars.next();
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespaces();
+        if self.chars.peek() == Some(&expected) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("{:?} is expected", expected)))
+        }
+    }
+
+    // 行末(ないしコメント)に到達していることを確認する.
+    fn end(&mut self) -> Result<()> {
+        self.skip_whitespaces();
+        match self.chars.peek() {
+            None | Some('#') => Ok(()),
+            Some(_) => Err(self.error("Unexpected trailing characters")),
+        }
+    }
+
+    fn key(&mut self) -> Result<String> {
+        self.skip_whitespaces();
+        match self.chars.peek() {
+            Some('"') | Some('\'') => track!(self.string()),
+            _ => {
+                let mut key = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        break;
+                    }
+                    key.push(c);
+                    self.chars.next();
+                }
+                if key.is_empty() {
+                    Err(self.error("A key is expected"))
+                } else {
+                    Ok(key)
+                }
+            }
+        }
+    }
+
+    fn key_value(&mut self) -> Result<(String, Value)> {
+        let key = track!(self.key())?;
+        track!(self.expect('='))?;
+        let value = track!(self.value())?;
+        Ok((key, value))
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespaces();
+        match self.chars.peek() {
+            Some('"') | Some('\'') => track!(self.string()).map(Value::String),
+            Some('{') => track!(self.inline_table()).map(Value::Table),
+            Some(_) => {
+                let mut token = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '-') {
+                        break;
+                    }
+                    token.push(c);
+                    self.chars.next();
+                }
+                match token.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => {
+                        let digits = token.strip_prefix(['+', '-']).unwrap_or(&token);
+                        let leading_zero = digits.len() > 1 && digits.starts_with('0');
+                        let valid = !digits.starts_with('_')
+                            && !digits.ends_with('_')
+                            && !digits.contains("__")
+                            && !leading_zero;
+                        token
+                            .replace('_', "")
+                            .parse()
+                            .ok()
+                            .filter(|_| valid)
+                            .map(Value::Integer)
+                            .ok_or_else(|| self.error("A value is expected"))
+                    }
+                }
+            }
+            None => Err(self.error("A value is expected")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let quote = self.chars.next().expect("Never fails");
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                None => return Err(self.error("Unterminated string")),
+                Some(c) if c == quote => return Ok(s),
+                Some('\\') if quote == '"' => {
+                    let c = match self.chars.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        _ => return Err(self.error("Unsupported escape sequence")),
+                    };
+                    s.push(c);
+                }
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Vec<(String, Value)>> {
+        track!(self.expect('{'))?;
+        let mut table = Vec::new();
+        if self.eat('}') {
+            return Ok(table);
+        }
+        loop {
+            let (key, value) = track!(self.key_value())?;
+            track_assert!(
+                table.iter().all(|(k, _)| *k != key),
+                ErrorKind::InvalidInput,
+                "Line {}: Duplicate key {:?}",
+                self.line,
+                key
+            );
+            table.push((key, value));
+            if self.eat('}') {
+                return Ok(table);
+            }
+            track!(self.expect(','))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_document_works() {
+        let sections = track_try_unwrap!(parse_document(
+            r#"
+# comment
+a = "foo\"bar" # comment
+b = 'C:\path'
+c = -1_000
+d = true
+
+[[x]]
+e = { f = "g", "h i" = 1 }
+[[x]]
+[y]
+"#
+        ));
+        assert_eq!(sections.len(), 4);
+        let values = sections[0]
+            .entries
+            .iter()
+            .map(|e| e.value.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [
+                Value::String("foo\"bar".to_owned()),
+                Value::String("C:\\path".to_owned()),
+                Value::Integer(-1000),
+                Value::Boolean(true),
+            ]
+        );
+        assert_eq!(sections[1].name, "x");
+        assert!(sections[1].is_array);
+        assert_eq!(
+            sections[1].entries[0].value,
+            Value::Table(vec![
+                ("f".to_owned(), Value::String("g".to_owned())),
+                ("h i".to_owned(), Value::Integer(1)),
+            ])
+        );
+        assert_eq!(sections[3].name, "y");
+        assert!(!sections[3].is_array);
+
+        for invalid in &[
+            "a = ",
+            "a = \"foo",
+            "a = 1 2",
+            "a = 1\na = 2",
+            "a = 1__0",
+            "[y]\n[y]",
+            "[[x]",
+            "a = { b = 1",
+            "= 1",
+        ] {
+            assert!(parse_document(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn unsupported_syntax_is_rejected() {
+        for (line, unsupported) in [
+            "a = [1, 2]",
+            "a = 1.5",
+            "a = 1e3",
+            "a = inf",
+            "a = nan",
+            "a = 0x10",
+            "a = 0o17",
+            "a = 0b11",
+            "a = 010",
+            "a = -01",
+            "a = 1979-05-27",
+            "a = 1979-05-27T07:32:00Z",
+            "a = \"\"\"foo\"\"\"",
+            "a = '''foo'''",
+            "a = \"\\u00e9\"",
+            "a.b = 1",
+            "[a.b]",
+            "[[a.b]]",
+            "a = {\nb = 1 }",
+            "a = { b = 1, }",
+        ]
+        .iter()
+        .enumerate()
+        {
+            let text = format!("{}{}", "# padding\n".repeat(line), unsupported);
+            let e = parse_document(&text).expect_err(unsupported);
+            assert_eq!(*e.kind(), ErrorKind::InvalidInput, "{:?}", unsupported);
+            let message = e.to_string();
+            assert!(
+                message.contains(&format!("Line {}:", line + 1)),
+                "{:?}: {}",
+                unsupported,
+                message
+            );
+        }
+
+        // 符号付きの整数や`0`自体は許容される
+        for (text, expected) in &[("a = +1", 1), ("a = -1", -1), ("a = 0", 0), ("a = -0", 0)] {
+            let sections = track_try_unwrap!(parse_document(text));
+            assert_eq!(sections[0].entries[0].value, Value::Integer(*expected));
+        }
+    }
+
+    #[test]
+    fn node_config_works() {
+        let config: NodeConfig = track_try_unwrap!(r#"
+listen = "127.0.0.1:14278"
+log_level = "debug"
+
+[[device]]
+id = "dev0"
+path = "dev0.lusf"
+capacity = 1_024
+block_size = 4096
+labels = { zone = "a" }
+
+[[device]]
+id = "tmp"
+memory = true
+capacity = 512
+"#
+        .parse());
+        assert_eq!(config.listen, "127.0.0.1:14278".parse().unwrap());
+        assert!(!config.admin_rpc);
+        assert_eq!(config.log_level, Level::Debug);
+
+        let mut dev0 = DeviceSpec::file(DeviceId::new("dev0"), "dev0.lusf", 1024);
+        dev0.block_size = track_try_unwrap!(BlockSize::new(4096));
+        dev0.labels.insert("zone".to_owned(), "a".to_owned());
+        assert_eq!(
+            config.devices,
+            [dev0, DeviceSpec::memory(DeviceId::new("tmp"), 512)]
+        );
+
+        let listen = "listen = \"127.0.0.1:14278\"\n";
+        for invalid in &[
+            "",
+            "listen = \"foo\"",
+            "unknown = 1",
+            "[device]\nid = \"foo\"\ncapacity = 1\nmemory = true",
+            "[[device]]\nid = \"foo\"\ncapacity = 1",
+            "[[device]]\nid = \"foo\"\ncapacity = 1\nmemory = true\npath = \"foo\"",
+            "[[device]]\nid = \"foo\"\ncapacity = 0\nmemory = true",
+            "[[device]]\nid = \"foo\"\ncapacity = 1\nmemory = true\nblock_size = 100",
+            "[[device]]\nid = \"foo\"\ncapacity = 1\nmemory = true\nlabels = { a = 1 }",
+        ] {
+            let prefix = if invalid.starts_with("listen") || invalid.is_empty() {
+                ""
+            } else {
+                listen
+            };
+            let text = format!("{}{}", prefix, invalid);
+            assert!(text.parse::<NodeConfig>().is_err(), "{:?}", text);
+        }
+        // `listen`以外は省略可能
+        assert!(listen.parse::<NodeConfig>().is_ok());
+    }
+
+    #[test]
+    fn parse_device_specs_works() {
+        let specs = track_try_unwrap!(parse_device_specs(
+            r#"
+name = "my-application"
+
+[[device]]
+id = "foo"
+memory = true
+capacity = 512
+
+[application]
+workers = 4
+"#
+        ));
+        assert_eq!(specs, [DeviceSpec::memory(DeviceId::new("foo"), 512)]);
+
+        let device = "[[device]]\nid = \"foo\"\ncapacity = 1\npath = \"foo\"\n";
+        let other = "[[device]]\nid = \"bar\"\ncapacity = 1\npath = \"foo\"\n";
+        for invalid in &[
+            format!("{}{}", device, device),
+            format!("{}{}", device, other),
+            "[[device]]\nid = \"foo\"\n".to_owned(),
+        ] {
+            assert!(parse_device_specs(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -1,4 +1,14 @@
+use cannyls::block::BlockSize;
+#[cfg(any(feature = "server", all(feature = "client", feature = "registry")))]
+use cannyls::metrics::StorageMetrics;
+#[cfg(any(feature = "server", all(feature = "client", feature = "registry")))]
+use cannyls::{ErrorKind, Result};
 use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[cfg(any(feature = "server", all(feature = "client", feature = "registry")))]
+use crate::rpc::RequestOptions;
 
 /// RPCの対象となるデバイスのID.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -9,6 +19,12 @@ impl DeviceId {
         DeviceId(id.into())
     }
 
+    // 空のデバイスIDを返す(定数の初期化用).
+    #[cfg(any(feature = "client", feature = "server"))]
+    pub(crate) const fn empty() -> Self {
+        DeviceId(String::new())
+    }
+
     /// デバイスIDを文字列に変換する.
     pub fn as_str(&self) -> &str {
         &self.0
@@ -29,3 +45,142 @@ impl Borrow<str> for DeviceId {
         &self.0
     }
 }
+
+/// デバイスに付与されるラベル群.
+///
+/// キーおよび値は任意の文字列で、その解釈は利用側に委ねられる.
+/// (e.g., `{"zone": "a", "media": "ssd"}`)
+pub type DeviceLabels = BTreeMap<String, String>;
+
+/// デバイスの構築に使われる不揮発性メモリの種類.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceNvmSpec {
+    /// 指定パスのファイル(lusfファイル).
+    ///
+    /// ファイルが存在しない場合には新規に作成され、存在する場合には既存のストレージとして開かれる.
+    File(PathBuf),
+
+    /// メモリ上の領域.
+    ///
+    /// 常に新規のストレージとして初期化されるため、主にテスト用.
+    Memory,
+}
+
+/// 構築するデバイスの仕様.
+///
+/// `provision`関数ないし`ProvisionDeviceRpc`に渡される.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSpec {
+    /// 登録時のデバイスID.
+    pub device_id: DeviceId,
+
+    /// 使用する不揮発性メモリ.
+    pub nvm: DeviceNvmSpec,
+
+    /// 不揮発性メモリの容量(バイト単位).
+    ///
+    /// ファイルの場合には、新規作成時にのみ使用される.
+    pub capacity: u64,
+
+    /// ストレージのブロックサイズ.
+    ///
+    /// ストレージの新規作成時にのみ使用される(既存のストレージを開く場合には、ヘッダの値が使われる).
+    ///
+    /// デフォルト値は`BlockSize::min()`.
+    pub block_size: BlockSize,
+
+    /// デバイスに付与するラベル群.
+    pub labels: DeviceLabels,
+}
+impl DeviceSpec {
+    /// ファイルを用いるデバイスの仕様を生成する.
+    pub fn file<P: Into<PathBuf>>(device_id: DeviceId, path: P, capacity: u64) -> Self {
+        DeviceSpec {
+            device_id,
+            nvm: DeviceNvmSpec::File(path.into()),
+            capacity,
+            block_size: BlockSize::min(),
+            labels: DeviceLabels::new(),
+        }
+    }
+
+    /// メモリ上の領域を用いるデバイスの仕様を生成する.
+    pub fn memory(device_id: DeviceId, capacity: u64) -> Self {
+        DeviceSpec {
+            device_id,
+            nvm: DeviceNvmSpec::Memory,
+            capacity,
+            block_size: BlockSize::min(),
+            labels: DeviceLabels::new(),
+        }
+    }
+}
+
+/// デバイス毎の設定.
+///
+/// ここでの設定は、デバイスに対するリクエストの処理時に、サーバ側で適用される.
+///
+/// 設定は`DeviceRegistryHandle`ないし管理用RPC経由で、実行時に変更可能.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceSettings {
+    /// `true`の場合には、更新系の操作の度に、ジャーナルの同期(ディスクへの書き込み)が行われる.
+    ///
+    /// なお`cannyls`のデバイスAPIでは、ジャーナルの同期間隔自体を実行時に変更することはできないため、
+    /// その代わりに個々のリクエスト単位で同期を強制するようになっている.
+    pub journal_sync: bool,
+
+    /// リクエストでキューの長さ制限が指定されていない場合に適用される制限値.
+    ///
+    /// `None`の場合には、制限なしとなる.
+    pub default_max_queue_len: Option<usize>,
+
+    /// キューの長さ制限の上限値.
+    ///
+    /// リクエストで、これよりも大きな制限値が指定された(ないし制限なしの)場合には、この値が使用される.
+    ///
+    /// `None`の場合には、リクエストの指定がそのまま使用される.
+    pub max_queue_len_limit: Option<usize>,
+
+    /// 書き込みを制限するデータ領域の使用率(パーセント単位).
+    ///
+    /// データ領域の使用率がこの値を超えている場合には、新規のPUTは`ErrorKind::StorageFull`で拒否される.
+    /// DELETEや読み込み系の操作は、引き続き実行可能.
+    ///
+    /// ストレージが満杯になって、ジャーナルのGCが進まなくなることを防ぐためのもの.
+    /// なお、この設定はデバイスがストレージのメトリクスと共に登録されている場合にのみ有効となる.
+    ///
+    /// `None`の場合には、制限なしとなる.
+    pub write_watermark: Option<u8>,
+}
+#[cfg(any(feature = "server", all(feature = "client", feature = "registry")))]
+impl DeviceSettings {
+    pub(crate) fn apply(&self, options: &mut RequestOptions) {
+        if self.journal_sync {
+            options.journal_sync = true;
+        }
+        if options.max_queue_len.is_none() {
+            options.max_queue_len = self.default_max_queue_len;
+        }
+        if let Some(limit) = self.max_queue_len_limit {
+            let n = options.max_queue_len.map_or(limit, |n| n.min(limit));
+            options.max_queue_len = Some(n);
+        }
+    }
+
+    pub(crate) fn check_write_watermark(&self, storage: Option<&StorageMetrics>) -> Result<()> {
+        if let (Some(watermark), Some(storage)) = (self.write_watermark, storage) {
+            let region = storage.data_region();
+            let usage = u128::from(region.usage_bytes()) * 100;
+            let limit = u128::from(region.capacity_bytes()) * u128::from(watermark);
+            track_assert!(
+                usage <= limit,
+                ErrorKind::StorageFull,
+                "Data region usage exceeds the write watermark: usage_bytes={}, capacity_bytes={}, watermark={}%",
+                region.usage_bytes(),
+                region.capacity_bytes(),
+                watermark
+            );
+        }
+        Ok(())
+    }
+}
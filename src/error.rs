@@ -0,0 +1,238 @@
+//! クライアントが返すエラーの分類.
+use cannyls::{Error, ErrorKind};
+use std::fmt;
+use std::net::SocketAddr;
+use trackable::error::ErrorKindExt;
+
+/// クライアントが返すエラーを、発生箇所(通信層ないしサーバ)に応じて分類したもの.
+///
+/// クライアントの各メソッドが返すのは`cannyls::Error`で、通信層のエラーは
+/// (`fibers_rpc::ErrorKind::InvalidInput`を除いて)全て`ErrorKind::Other`に変換されてしまうため、
+/// 再試行の可否等を判断するために、このエラーに変換して利用することができる.
+///
+/// ```no_run
+/// # extern crate cannyls_rpc;
+/// # extern crate futures;
+/// # use cannyls_rpc::{Client, ClientError, DeviceId, LumpId};
+/// # use futures::Future;
+/// # fn main() {
+/// # let client: Client = unimplemented!();
+/// let future = client
+///     .request()
+///     .head_lump(DeviceId::new("foo"), LumpId::new(0))
+///     .map_err(ClientError::from);
+/// # }
+/// ```
+///
+/// いずれのバリアントも元の`cannyls::Error`を保持しており、その種類や履歴は失われない.
+#[derive(Debug, Clone)]
+pub enum ClientError {
+    /// RPCのタイムアウト(`fibers_rpc::ErrorKind::Timeout`).
+    Timeout(Error),
+
+    /// サーバとの通信の失敗(`fibers_rpc::ErrorKind::Unavailable`ないし`Other`).
+    ///
+    /// 接続の失敗や切断、送信キューの溢れ、およびサーキットブレーカによる拒否がこれに該当する.
+    Unreachable(Error),
+
+    /// RPCメッセージのエンコードないしデコードの失敗(`fibers_rpc::ErrorKind::InvalidInput`).
+    Codec(Error),
+
+    /// 通信層以外で発生したエラー.
+    ///
+    /// サーバ(ないしデバイス)から返されたエラーの他に、
+    /// クライアント側でのチェックサム検証の失敗等もこれに該当する.
+    Remote(Error),
+}
+impl ClientError {
+    /// 元のエラーの種類を返す.
+    pub fn kind(&self) -> &ErrorKind {
+        self.as_error().kind()
+    }
+
+    /// 通信層のエラーの場合には、その元の種類を返す.
+    ///
+    /// `Remote`の場合には`None`が返される.
+    /// また、サーキットブレーカによる拒否の場合には`fibers_rpc::ErrorKind::Unavailable`が返される.
+    pub fn transport_kind(&self) -> Option<fibers_rpc::ErrorKind> {
+        transport_kind(self.as_error())
+    }
+
+    /// 通信層のエラーかどうかを返す.
+    pub fn is_transport(&self) -> bool {
+        !matches!(self, ClientError::Remote(_))
+    }
+
+    /// 再試行によって成功し得るエラーかどうかの分類を返す.
+    ///
+    /// 詳細は`RetryClass`を参照のこと.
+    pub fn retry_class(&self) -> RetryClass {
+        match self {
+            ClientError::Timeout(_) | ClientError::Unreachable(_) => RetryClass::Transient,
+            ClientError::Codec(_) => RetryClass::Permanent,
+            ClientError::Remote(e) => {
+                if *e.kind() == ErrorKind::DeviceBusy {
+                    RetryClass::Transient
+                } else {
+                    RetryClass::Permanent
+                }
+            }
+        }
+    }
+
+    /// `self.retry_class() == RetryClass::Transient`の短縮形.
+    pub fn is_retriable(&self) -> bool {
+        self.retry_class() == RetryClass::Transient
+    }
+
+    /// 元のエラーへの参照を返す.
+    pub fn as_error(&self) -> &Error {
+        match self {
+            ClientError::Timeout(e)
+            | ClientError::Unreachable(e)
+            | ClientError::Codec(e)
+            | ClientError::Remote(e) => e,
+        }
+    }
+
+    /// 元のエラーを返す.
+    pub fn into_error(self) -> Error {
+        match self {
+            ClientError::Timeout(e)
+            | ClientError::Unreachable(e)
+            | ClientError::Codec(e)
+            | ClientError::Remote(e) => e,
+        }
+    }
+}
+impl From<Error> for ClientError {
+    fn from(e: Error) -> Self {
+        match transport_kind(&e) {
+            None => ClientError::Remote(e),
+            Some(fibers_rpc::ErrorKind::Timeout) => ClientError::Timeout(e),
+            Some(fibers_rpc::ErrorKind::InvalidInput) => ClientError::Codec(e),
+            Some(fibers_rpc::ErrorKind::Unavailable) | Some(fibers_rpc::ErrorKind::Other) => {
+                ClientError::Unreachable(e)
+            }
+        }
+    }
+}
+impl From<ClientError> for Error {
+    fn from(e: ClientError) -> Self {
+        e.into_error()
+    }
+}
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_error().fmt(f)
+    }
+}
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.as_error())
+    }
+}
+
+/// エラーの再試行可能性の分類.
+///
+/// 呼び出し側と、組み込みの再試行(`BusyRetryPolicy::retry_transient`)とで、同じ判定基準を使用するためのもの.
+///
+/// なお`Transient`に分類されるエラーであっても、通信層のエラーの場合には、
+/// サーバ側では処理済みの可能性があるので、更新系の操作を再試行する際には注意が必要.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryClass {
+    /// 一時的なエラー.
+    ///
+    /// 通信層のタイムアウトと通信の失敗(`ClientError::Timeout`と`ClientError::Unreachable`)、
+    /// および、サーバから返された`ErrorKind::DeviceBusy`がこれに該当する.
+    Transient,
+
+    /// 恒久的なエラー.
+    ///
+    /// 上記以外の全てのエラー(e.g., `ErrorKind::InvalidInput`や`ErrorKind::StorageCorrupted`)がこれに該当し、
+    /// 同じリクエストを再試行しても、成功は見込めない.
+    Permanent,
+}
+impl RetryClass {
+    /// クライアントが返したエラーを分類する.
+    pub fn of(e: &Error) -> Self {
+        ClientError::from(e.clone()).retry_class()
+    }
+}
+
+/// 通信層のエラーを`cannyls::Error`に変換する.
+///
+/// 元のエラーは、分類のために(`ClientError`を参照)変換後のエラーの原因として保持される.
+pub(crate) fn from_rpc_error(e: fibers_rpc::Error, server: SocketAddr) -> Error {
+    let original_kind = *e.kind();
+    let kind = match original_kind {
+        fibers_rpc::ErrorKind::InvalidInput => ErrorKind::InvalidInput,
+        fibers_rpc::ErrorKind::Timeout
+        | fibers_rpc::ErrorKind::Unavailable
+        | fibers_rpc::ErrorKind::Other => ErrorKind::Other,
+    };
+    track!(Error::from(kind.cause(e)); original_kind, server)
+}
+
+fn transport_kind(e: &Error) -> Option<fibers_rpc::ErrorKind> {
+    e.concrete_cause::<fibers_rpc::Error>().map(|e| *e.kind())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rpc_error(kind: fibers_rpc::ErrorKind) -> Error {
+        let e = fibers_rpc::Error::from(kind.error());
+        from_rpc_error(e, "127.0.0.1:1919".parse().unwrap())
+    }
+
+    #[test]
+    fn client_error_works() {
+        let e = ClientError::from(rpc_error(fibers_rpc::ErrorKind::Timeout));
+        assert!(matches!(e, ClientError::Timeout(_)));
+        assert_eq!(*e.kind(), ErrorKind::Other);
+        assert_eq!(e.transport_kind(), Some(fibers_rpc::ErrorKind::Timeout));
+        assert!(e.is_transport());
+
+        let e = ClientError::from(rpc_error(fibers_rpc::ErrorKind::Unavailable));
+        assert!(matches!(e, ClientError::Unreachable(_)));
+        let e = ClientError::from(rpc_error(fibers_rpc::ErrorKind::Other));
+        assert!(matches!(e, ClientError::Unreachable(_)));
+
+        let e = ClientError::from(rpc_error(fibers_rpc::ErrorKind::InvalidInput));
+        assert!(matches!(e, ClientError::Codec(_)));
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+        let e = ClientError::from(Error::from(ErrorKind::DeviceBusy.error()));
+        assert!(matches!(e, ClientError::Remote(_)));
+        assert_eq!(*e.kind(), ErrorKind::DeviceBusy);
+        assert_eq!(e.transport_kind(), None);
+        assert!(!e.is_transport());
+    }
+
+    #[test]
+    fn retry_class_works() {
+        let transient = [
+            rpc_error(fibers_rpc::ErrorKind::Timeout),
+            rpc_error(fibers_rpc::ErrorKind::Unavailable),
+            rpc_error(fibers_rpc::ErrorKind::Other),
+            Error::from(ErrorKind::DeviceBusy.error()),
+        ];
+        for e in &transient {
+            assert_eq!(RetryClass::of(e), RetryClass::Transient, "{}", e);
+            assert!(ClientError::from(e.clone()).is_retriable());
+        }
+
+        let permanent = [
+            rpc_error(fibers_rpc::ErrorKind::InvalidInput),
+            Error::from(ErrorKind::InvalidInput.error()),
+            Error::from(ErrorKind::StorageCorrupted.error()),
+            Error::from(ErrorKind::Other.error()),
+        ];
+        for e in &permanent {
+            assert_eq!(RetryClass::of(e), RetryClass::Permanent, "{}", e);
+            assert!(!ClientError::from(e.clone()).is_retriable());
+        }
+    }
+}
@@ -0,0 +1,432 @@
+//! 障害試験用のフォールトインジェクション.
+use cannyls::{ErrorKind, Result};
+use fibers_rpc::Call;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::device::DeviceId;
+
+/// リクエストに注入される障害.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// 応答を返さない.
+    ///
+    /// リクエスト自体は通常通りに処理されるが、その結果はクライアントに返されない
+    /// (i.e., クライアント側では、RPCのタイムアウト等で失敗することになる).
+    /// 応答が返されなかったリクエストは、実行中のリクエストとして残り続けるので、
+    /// 必要に応じて`rpc::CancelInFlightRpc`でキャンセルすること.
+    Drop,
+
+    /// 応答を、リクエストの処理完了後に指定時間だけ遅延させる.
+    Delay(Duration),
+
+    /// リクエストを処理せずに、指定種類のエラーを返す.
+    Error(ErrorKind),
+}
+
+/// サーバが処理するリクエストに対して、一定の割合で障害を注入するためのインジェクタ.
+///
+/// `Server::inject_faults`で使用され、lumpやデバイスに対するリクエスト(アクセスログの対象と同じ)に適用される.
+/// 対象デバイスの検索に失敗したリクエストには、適用されない.
+///
+/// 各リクエストに対しては、登録順にルールが評価され、最初に選択されたルールの障害が注入される.
+/// ルールの選択に用いられる擬似乱数列はシードによって決まるので、
+/// リクエストの発行順が同じであれば、同じリクエストに同じ障害が注入される.
+///
+/// RPCはサーバ側で処理される際の名前で判定されるので、
+/// 例えば`rpc::GetLumpRpc`を対象とした場合には、`rpc::GetLumpWithMetaRpc`も対象となる.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate cannyls;
+/// # extern crate cannyls_rpc;
+/// use cannyls::ErrorKind;
+/// use cannyls_rpc::rpc;
+/// use cannyls_rpc::{Fault, FaultInjector};
+/// use std::time::Duration;
+///
+/// let mut injector = FaultInjector::new(1234);
+/// injector
+///     .inject::<rpc::GetLumpRpc>(Fault::Error(ErrorKind::DeviceBusy), 0.1)
+///     .inject::<rpc::PutLumpRpc>(Fault::Drop, 0.01)
+///     .inject_all(Fault::Delay(Duration::from_millis(10)), 0.5);
+/// # let _ = injector;
+/// ```
+#[derive(Debug, Clone)]
+pub struct FaultInjector {
+    rules: Vec<Rule>,
+    random: Random,
+    injected: Arc<AtomicU64>,
+}
+impl FaultInjector {
+    /// 擬似乱数列のシードを指定して、新しい`FaultInjector`インスタンスを生成する.
+    ///
+    /// 初期状態では、ルールは一つも登録されていない.
+    pub fn new(seed: u64) -> Self {
+        FaultInjector {
+            rules: Vec::new(),
+            random: Random::new(seed),
+            injected: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// 指定されたRPCのリクエストの`ratio`の割合に、`fault`を注入するルールを追加する.
+    ///
+    /// `ratio`は`0.0`から`1.0`の範囲に丸められる.
+    pub fn inject<T: Call>(&mut self, fault: Fault, ratio: f64) -> &mut Self {
+        self.inject_procedure(T::NAME, fault, ratio)
+    }
+
+    /// 指定された名前のRPCのリクエストの`ratio`の割合に、`fault`を注入するルールを追加する.
+    ///
+    /// `ratio`は`0.0`から`1.0`の範囲に丸められる.
+    pub fn inject_procedure(&mut self, procedure: &str, fault: Fault, ratio: f64) -> &mut Self {
+        self.add_rule(Some(procedure.to_owned()), fault, ratio)
+    }
+
+    /// 全てのRPCのリクエストの`ratio`の割合に、`fault`を注入するルールを追加する.
+    ///
+    /// `ratio`は`0.0`から`1.0`の範囲に丸められる.
+    pub fn inject_all(&mut self, fault: Fault, ratio: f64) -> &mut Self {
+        self.add_rule(None, fault, ratio)
+    }
+
+    /// これまでに障害が注入されたリクエストの数を返す.
+    ///
+    /// このインジェクタのクローン間で共有される.
+    pub fn injected(&self) -> u64 {
+        self.injected.load(Ordering::SeqCst)
+    }
+
+    /// 指定のRPCのリクエストに注入する障害を選択する.
+    ///
+    /// 障害が`Fault::Error`の場合には、それに対応するエラーが返される.
+    pub(crate) fn select(&self, procedure: &str) -> Result<Option<Fault>> {
+        let fault = self
+            .rules
+            .iter()
+            .filter(|r| r.procedure.as_ref().is_none_or(|p| p == procedure))
+            .find(|r| self.random.next() < r.ratio)
+            .map(|r| r.fault.clone());
+        if fault.is_some() {
+            self.injected.fetch_add(1, Ordering::SeqCst);
+        }
+        if let Some(Fault::Error(kind)) = fault {
+            track_panic!(kind, "Injected fault: procedure={}", procedure);
+        }
+        Ok(fault)
+    }
+
+    fn add_rule(&mut self, procedure: Option<String>, fault: Fault, ratio: f64) -> &mut Self {
+        let ratio = if ratio.is_nan() {
+            0.0
+        } else {
+            ratio.clamp(0.0, 1.0)
+        };
+        self.rules.push(Rule {
+            procedure,
+            fault,
+            ratio,
+        });
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    procedure: Option<String>, // `None`は全てのRPCを対象とする
+    fault: Fault,
+    ratio: f64,
+}
+
+/// 注入される遅延時間の分布.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LatencyDistribution {
+    /// 常に指定の時間.
+    Constant(Duration),
+
+    /// `min`以上`max`未満の一様分布.
+    Uniform {
+        /// 最小値.
+        min: Duration,
+
+        /// 最大値.
+        max: Duration,
+    },
+
+    /// 正規分布.
+    ///
+    /// 負の値となった場合には、`0`に丸められる.
+    Normal {
+        /// 平均.
+        mean: Duration,
+
+        /// 標準偏差.
+        stddev: Duration,
+    },
+
+    /// 指数分布.
+    ///
+    /// 裾の長い遅延(e.g., 稀に非常に遅くなるディスク)を模擬するのに使用できる.
+    Exponential {
+        /// 平均.
+        mean: Duration,
+    },
+}
+impl LatencyDistribution {
+    fn sample(&self, random: &Random) -> Duration {
+        match *self {
+            LatencyDistribution::Constant(d) => d,
+            LatencyDistribution::Uniform { min, max } => {
+                let width = max.checked_sub(min).unwrap_or_default();
+                min + width.mul_f64(random.next())
+            }
+            LatencyDistribution::Normal { mean, stddev } => {
+                // Box-Muller法
+                let (u1, u2) = (1.0 - random.next(), random.next());
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                let secs = mean.as_secs_f64() + stddev.as_secs_f64() * z;
+                Duration::from_secs_f64(secs.max(0.0))
+            }
+            LatencyDistribution::Exponential { mean } => {
+                let u = 1.0 - random.next();
+                Duration::from_secs_f64(-mean.as_secs_f64() * u.ln())
+            }
+        }
+    }
+}
+
+/// 遅延を注入するリクエストの条件と、その遅延時間の分布.
+#[derive(Debug, Clone)]
+pub struct LatencyRule {
+    procedure: Option<String>,   // `None`は全てのRPCを対象とする
+    device_id: Option<DeviceId>, // `None`は全てのデバイスを対象とする
+    distribution: LatencyDistribution,
+}
+impl LatencyRule {
+    /// 全てのリクエストを対象とする`LatencyRule`を生成する.
+    pub fn new(distribution: LatencyDistribution) -> Self {
+        LatencyRule {
+            procedure: None,
+            device_id: None,
+            distribution,
+        }
+    }
+
+    /// 対象を、指定されたRPCのリクエストに限定する.
+    pub fn procedure<T: Call>(self) -> Self {
+        self.procedure_name(T::NAME)
+    }
+
+    /// 対象を、指定された名前のRPCのリクエストに限定する.
+    pub fn procedure_name(mut self, procedure: &str) -> Self {
+        self.procedure = Some(procedure.to_owned());
+        self
+    }
+
+    /// 対象を、指定されたデバイスに対するリクエストに限定する.
+    pub fn device(mut self, device_id: DeviceId) -> Self {
+        self.device_id = Some(device_id);
+        self
+    }
+
+    fn is_match(&self, procedure: &str, device_id: &DeviceId) -> bool {
+        self.procedure.as_ref().is_none_or(|p| p == procedure)
+            && self.device_id.as_ref().is_none_or(|d| d == device_id)
+    }
+}
+
+/// サーバが処理するリクエストに対して、人為的な遅延を注入するためのインジェクタ.
+///
+/// 低速なディスク等を模擬して、デッドラインやタイムアウト、ヘッジリクエスト等に関する挙動を、実機なしで検証するためのもの.
+/// なお、遅延はデバイスでの処理の完了後に注入されるので、デバイスのキューの長さには影響しない.
+///
+/// `Server::inject_latency`で使用され、`FaultInjector`と同じリクエストに適用される.
+/// 各リクエストに対しては、条件に合致する最初のルールの分布から遅延時間が決定され、
+/// リクエストの処理完了後、その時間だけ応答が遅延される
+/// (`Fault::Delay`も注入された場合には、両者の合計だけ遅延される).
+///
+/// 遅延時間の決定に用いられる擬似乱数列はシードによって決まる.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate cannyls_rpc;
+/// use cannyls_rpc::rpc;
+/// use cannyls_rpc::{DeviceId, LatencyDistribution, LatencyInjector, LatencyRule};
+/// use std::time::Duration;
+///
+/// let slow_disk = LatencyDistribution::Exponential {
+///     mean: Duration::from_millis(50),
+/// };
+/// let mut injector = LatencyInjector::new(1234);
+/// injector
+///     .add_rule(LatencyRule::new(slow_disk).device(DeviceId::new("slow")))
+///     .add_rule(
+///         LatencyRule::new(LatencyDistribution::Constant(Duration::from_millis(5)))
+///             .procedure::<rpc::PutLumpRpc>(),
+///     );
+/// # let _ = injector;
+/// ```
+#[derive(Debug, Clone)]
+pub struct LatencyInjector {
+    rules: Vec<LatencyRule>,
+    random: Random,
+    injected: Arc<AtomicU64>,
+}
+impl LatencyInjector {
+    /// 擬似乱数列のシードを指定して、新しい`LatencyInjector`インスタンスを生成する.
+    ///
+    /// 初期状態では、ルールは一つも登録されていない.
+    pub fn new(seed: u64) -> Self {
+        LatencyInjector {
+            rules: Vec::new(),
+            random: Random::new(seed),
+            injected: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// ルールを追加する.
+    pub fn add_rule(&mut self, rule: LatencyRule) -> &mut Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// これまでに遅延が注入されたリクエストの数を返す.
+    ///
+    /// このインジェクタのクローン間で共有される.
+    pub fn injected(&self) -> u64 {
+        self.injected.load(Ordering::SeqCst)
+    }
+
+    /// 指定のリクエストに注入する遅延時間を決定する.
+    pub(crate) fn select(&self, procedure: &str, device_id: &DeviceId) -> Option<Duration> {
+        let rule = self
+            .rules
+            .iter()
+            .find(|r| r.is_match(procedure, device_id))?;
+        self.injected.fetch_add(1, Ordering::SeqCst);
+        Some(rule.distribution.sample(&self.random))
+    }
+}
+
+// インジェクタのクローン間で共有される擬似乱数列(SplitMix64).
+#[derive(Debug, Clone)]
+struct Random(Arc<AtomicU64>);
+impl Random {
+    fn new(seed: u64) -> Self {
+        Random(Arc::new(AtomicU64::new(seed)))
+    }
+
+    // `[0.0, 1.0)`の範囲の擬似乱数を返す.
+    fn next(&self) -> f64 {
+        const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut z = self
+            .0
+            .fetch_add(GAMMA, Ordering::SeqCst)
+            .wrapping_add(GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rpc;
+
+    use super::*;
+
+    #[test]
+    fn fault_injector_works() {
+        let mut injector = FaultInjector::new(0);
+        injector
+            .inject::<rpc::GetLumpRpc>(Fault::Error(ErrorKind::DeviceBusy), 1.0)
+            .inject::<rpc::PutLumpRpc>(Fault::Drop, 0.0)
+            .inject_all(Fault::Delay(Duration::from_millis(1)), 1.0);
+
+        let e = injector.select(rpc::GetLumpRpc::NAME).err().unwrap();
+        assert_eq!(*e.kind(), ErrorKind::DeviceBusy);
+        assert_eq!(
+            track_try_unwrap!(injector.select(rpc::PutLumpRpc::NAME)),
+            Some(Fault::Delay(Duration::from_millis(1)))
+        );
+        assert_eq!(injector.injected(), 2);
+
+        assert_eq!(
+            track_try_unwrap!(FaultInjector::new(0).select(rpc::PutLumpRpc::NAME)),
+            None
+        );
+    }
+
+    #[test]
+    fn fault_ratio_works() {
+        let select = |seed| {
+            let mut injector = FaultInjector::new(seed);
+            injector.inject_all(Fault::Drop, 0.3);
+            (0..1000)
+                .map(|_| track_try_unwrap!(injector.select("foo")).is_some())
+                .collect::<Vec<_>>()
+        };
+
+        let selected = select(1);
+        let n = selected.iter().filter(|&&s| s).count();
+        assert!(250 < n && n < 350, "n={}", n);
+
+        // 同じシードであれば、同じリクエストが選択される
+        assert_eq!(select(1), selected);
+        assert_ne!(select(2), selected);
+    }
+
+    #[test]
+    fn latency_injector_works() {
+        let ms = Duration::from_millis;
+        let mut injector = LatencyInjector::new(0);
+        injector
+            .add_rule(
+                LatencyRule::new(LatencyDistribution::Constant(ms(10)))
+                    .procedure::<rpc::GetLumpRpc>()
+                    .device(DeviceId::new("foo")),
+            )
+            .add_rule(
+                LatencyRule::new(LatencyDistribution::Uniform {
+                    min: ms(5),
+                    max: ms(6),
+                })
+                .procedure::<rpc::GetLumpRpc>(),
+            );
+
+        let foo = DeviceId::new("foo");
+        let bar = DeviceId::new("bar");
+        assert_eq!(injector.select(rpc::GetLumpRpc::NAME, &foo), Some(ms(10)));
+        let latency = injector.select(rpc::GetLumpRpc::NAME, &bar).unwrap();
+        assert!(ms(5) <= latency && latency < ms(6), "latency={:?}", latency);
+        assert_eq!(injector.select(rpc::PutLumpRpc::NAME, &foo), None);
+        assert_eq!(injector.injected(), 2);
+    }
+
+    #[test]
+    fn latency_distribution_works() {
+        let random = Random::new(3);
+        let mean = |distribution: LatencyDistribution| {
+            let total = (0..10_000)
+                .map(|_| distribution.sample(&random))
+                .sum::<Duration>();
+            total.as_secs_f64() / 10_000.0
+        };
+
+        let normal = LatencyDistribution::Normal {
+            mean: Duration::from_millis(100),
+            stddev: Duration::from_millis(10),
+        };
+        assert!((mean(normal) - 0.1).abs() < 0.005);
+
+        let exponential = LatencyDistribution::Exponential {
+            mean: Duration::from_millis(100),
+        };
+        assert!((mean(exponential) - 0.1).abs() < 0.01);
+    }
+}
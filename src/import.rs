@@ -0,0 +1,253 @@
+//! サーバ側のインポートセッションの管理.
+use cannyls::{ErrorKind, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use trackable::error::ErrorKindExt;
+
+use crate::device::DeviceId;
+use crate::info::ImportSessionStatus;
+
+/// インポートセッション群.
+///
+/// インスタンスをクローンした場合には、同じセッション群が共有される.
+#[derive(Debug, Clone, Default)]
+pub struct ImportSessions(Arc<Inner>);
+impl ImportSessions {
+    /// 指定デバイスを対象とした、新しいセッションを開始する.
+    ///
+    /// `owner`には、セッションを開始したクライアントの(検証済みの)鍵のIDを指定する.
+    /// 以降のセッションに対するリクエストは、`authorize`によって同じ鍵のものかどうかが検査される.
+    pub fn open(&self, device_id: DeviceId, owner: Option<String>) -> Result<ImportSessionStatus> {
+        let session_id = self.0.next_id.fetch_add(1, Ordering::SeqCst);
+        let session = Session {
+            status: ImportSessionStatus {
+                session_id,
+                device_id,
+                next_batch_seq: 0,
+                imported_lumps: 0,
+                imported_bytes: 0,
+            },
+            batch_in_flight: false,
+            owner,
+        };
+        let status = session.status.clone();
+        track!(self.lock())?.insert(session_id, session);
+        Ok(status)
+    }
+
+    /// 指定の鍵のクライアントが、セッションを操作可能かどうかを検査し、セッションの対象デバイスを返す.
+    ///
+    /// 鍵のIDがセッションの開始時のものと異なる場合には`ErrorKind::InvalidInput`エラーとなる.
+    pub fn authorize(&self, session_id: u64, key_id: Option<&str>) -> Result<DeviceId> {
+        let sessions = track!(self.lock())?;
+        let session = track!(get_session(&sessions, session_id))?;
+        track_assert_eq!(
+            session.owner.as_deref(),
+            key_id,
+            ErrorKind::InvalidInput,
+            "The import session was opened by another client: session={}",
+            session_id
+        );
+        Ok(session.status.device_id.clone())
+    }
+
+    /// セッションの現在の状態を返す.
+    pub fn status(&self, session_id: u64) -> Result<ImportSessionStatus> {
+        let sessions = track!(self.lock())?;
+        let session = track!(get_session(&sessions, session_id))?;
+        Ok(session.status.clone())
+    }
+
+    /// バッチの処理を開始する.
+    ///
+    /// バッチを新規に処理すべき場合には、その完了を記録するためのガードが返される.
+    /// 既に処理済みのバッチ(i.e., 再送されたもの)の場合には`Ok(None)`が返される.
+    pub fn start_batch(&self, session_id: u64, batch_seq: u64) -> Result<Option<BatchGuard>> {
+        let mut sessions = track!(self.lock())?;
+        let session = track!(get_session_mut(&mut sessions, session_id))?;
+        if batch_seq < session.status.next_batch_seq {
+            return Ok(None);
+        }
+        track_assert_eq!(
+            batch_seq,
+            session.status.next_batch_seq,
+            ErrorKind::InvalidInput,
+            "Unexpected import batch sequence number: session={}",
+            session_id
+        );
+        track_assert!(
+            !session.batch_in_flight,
+            ErrorKind::DeviceBusy,
+            "Another import batch is in progress: session={}",
+            session_id
+        );
+        session.batch_in_flight = true;
+        Ok(Some(BatchGuard {
+            sessions: self.clone(),
+            session_id,
+            finished: false,
+        }))
+    }
+
+    /// セッションを終了し、その最終的な状態を返す.
+    ///
+    /// バッチの処理中の場合には`ErrorKind::DeviceBusy`エラーとなる.
+    pub fn close(&self, session_id: u64) -> Result<ImportSessionStatus> {
+        let mut sessions = track!(self.lock())?;
+        {
+            let session = track!(get_session(&sessions, session_id))?;
+            track_assert!(
+                !session.batch_in_flight,
+                ErrorKind::DeviceBusy,
+                "An import batch is in progress: session={}",
+                session_id
+            );
+        }
+        let session = sessions.remove(&session_id).expect("Never fails");
+        Ok(session.status)
+    }
+
+    fn finish_batch(
+        &self,
+        session_id: u64,
+        imported: Option<(u64, u64)>,
+    ) -> Result<ImportSessionStatus> {
+        let mut sessions = track!(self.lock())?;
+        let session = track!(get_session_mut(&mut sessions, session_id))?;
+        session.batch_in_flight = false;
+        if let Some((lumps, bytes)) = imported {
+            session.status.next_batch_seq += 1;
+            session.status.imported_lumps += lumps;
+            session.status.imported_bytes += bytes;
+        }
+        Ok(session.status.clone())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<u64, Session>>> {
+        self.0
+            .sessions
+            .lock()
+            .map_err(|e| ErrorKind::Other.cause(e.to_string()).into())
+    }
+}
+
+fn get_session(sessions: &HashMap<u64, Session>, session_id: u64) -> Result<&Session> {
+    let session = track_assert_some!(
+        sessions.get(&session_id),
+        ErrorKind::InvalidInput,
+        "No such import session: {}",
+        session_id
+    );
+    Ok(session)
+}
+
+fn get_session_mut(sessions: &mut HashMap<u64, Session>, session_id: u64) -> Result<&mut Session> {
+    let session = track_assert_some!(
+        sessions.get_mut(&session_id),
+        ErrorKind::InvalidInput,
+        "No such import session: {}",
+        session_id
+    );
+    Ok(session)
+}
+
+/// 処理中のバッチを表すガード.
+///
+/// `finish`が呼ばれずにドロップされた場合(e.g., 処理の失敗やキャンセル)には、
+/// セッションの状態は変化せず、同じバッチを再送することが可能となる.
+#[derive(Debug)]
+pub struct BatchGuard {
+    sessions: ImportSessions,
+    session_id: u64,
+    finished: bool,
+}
+impl BatchGuard {
+    /// バッチの処理の成功を記録し、更新後のセッションの状態を返す.
+    ///
+    /// `lumps`と`bytes`には、バッチで保存したlumpの数とデータの合計バイト数を指定する.
+    pub fn finish(mut self, lumps: u64, bytes: u64) -> Result<ImportSessionStatus> {
+        self.finished = true;
+        track!(self
+            .sessions
+            .finish_batch(self.session_id, Some((lumps, bytes))))
+    }
+}
+impl Drop for BatchGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.sessions.finish_batch(self.session_id, None);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: AtomicU64,
+    sessions: Mutex<HashMap<u64, Session>>,
+}
+
+#[derive(Debug)]
+struct Session {
+    status: ImportSessionStatus,
+    batch_in_flight: bool,
+    owner: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_session_works() {
+        let sessions = ImportSessions::default();
+        let id = track_try_unwrap!(sessions.open(DeviceId::new("foo"), None)).session_id;
+
+        let batch = track_try_unwrap!(sessions.start_batch(id, 0)).unwrap();
+        let e = sessions.start_batch(id, 0).err().unwrap();
+        assert_eq!(*e.kind(), ErrorKind::DeviceBusy);
+        let status = track_try_unwrap!(batch.finish(2, 10));
+        assert_eq!(status.next_batch_seq, 1);
+        assert_eq!(status.imported_lumps, 2);
+        assert_eq!(status.imported_bytes, 10);
+
+        // 処理済みのバッチの再送
+        assert!(track_try_unwrap!(sessions.start_batch(id, 0)).is_none());
+
+        // 番号の飛び
+        assert!(sessions.start_batch(id, 2).is_err());
+
+        // 失敗したバッチは再送可能
+        let batch = track_try_unwrap!(sessions.start_batch(id, 1));
+        assert!(batch.is_some());
+        let e = sessions.close(id).err().unwrap();
+        assert_eq!(*e.kind(), ErrorKind::DeviceBusy);
+        std::mem::drop(batch);
+        assert_eq!(track_try_unwrap!(sessions.status(id)).next_batch_seq, 1);
+        let batch = track_try_unwrap!(sessions.start_batch(id, 1)).unwrap();
+        track_try_unwrap!(batch.finish(1, 5));
+
+        let status = track_try_unwrap!(sessions.close(id));
+        assert_eq!(status.imported_lumps, 3);
+        assert!(sessions.status(id).is_err());
+    }
+
+    #[test]
+    fn authorize_works() {
+        let sessions = ImportSessions::default();
+        let owned =
+            track_try_unwrap!(sessions.open(DeviceId::new("foo"), Some("alice".to_owned())));
+        let anonymous = track_try_unwrap!(sessions.open(DeviceId::new("bar"), None));
+
+        let device_id = track_try_unwrap!(sessions.authorize(owned.session_id, Some("alice")));
+        assert_eq!(device_id, DeviceId::new("foo"));
+        assert!(sessions.authorize(owned.session_id, Some("bob")).is_err());
+        assert!(sessions.authorize(owned.session_id, None).is_err());
+
+        assert!(sessions.authorize(anonymous.session_id, None).is_ok());
+        assert!(sessions
+            .authorize(anonymous.session_id, Some("alice"))
+            .is_err());
+        assert!(sessions.authorize(100, None).is_err());
+    }
+}
@@ -0,0 +1,538 @@
+//! サーバ側で実行中のリクエストの管理.
+use cannyls::deadline::Deadline;
+use cannyls::metrics::DeviceMetrics;
+use cannyls::{ErrorKind, Result};
+use fibers::sync::oneshot;
+use fibers::time::timer::{self, Timeout};
+use futures::{Async, Future, Poll};
+use slog::Logger;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use trackable::error::ErrorKindExt;
+
+use crate::device::DeviceId;
+use crate::info::{InFlightRequest, RequestTarget};
+use crate::interceptor::{RequestContext, ServerInterceptors};
+use crate::rpc::{BusyHint, ResponseMeta};
+use crate::server::ErrorVerbosity;
+use crate::server_metrics::MetricsRecorder;
+#[cfg(feature = "tracing")]
+use crate::span::{self, Span};
+use crate::stats::StatsRecorder;
+
+/// 実行中のリクエスト群.
+///
+/// インスタンスをクローンした場合には、同じリクエスト群が共有される.
+#[derive(Debug, Clone, Default)]
+pub struct InFlightRequests(Arc<Inner>);
+impl InFlightRequests {
+    /// リクエストの実行開始を登録する.
+    ///
+    /// 返り値のガードがドロップされた時点で、リクエストの登録は解除される.
+    pub fn start(
+        &self,
+        procedure: &'static str,
+        device_id: DeviceId,
+        target: RequestTarget,
+        deadline: Deadline,
+    ) -> InFlightGuard {
+        let request_id = self.0.next_id.fetch_add(1, Ordering::SeqCst);
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let entry = Entry {
+            procedure,
+            device_id,
+            target,
+            deadline,
+            start_time: Instant::now(),
+            cancel_tx: Some(cancel_tx),
+        };
+        if let Ok(mut entries) = self.0.entries.lock() {
+            entries.insert(request_id, entry);
+        }
+        InFlightGuard {
+            request_id,
+            requests: self.clone(),
+            cancel_rx,
+            stats: None,
+            metrics: None,
+            interceptors: None,
+            error_verbosity: ErrorVerbosity::default(),
+            access_logger: None,
+            slow_log: None,
+            busy_hint: None,
+            response_meta: None,
+            trace_id: None,
+            response_delay: None,
+            drop_response: false,
+            #[cfg(feature = "tracing")]
+            span: None,
+            start_time: Instant::now(),
+        }
+    }
+
+    /// 実行中のリクエストをキャンセルする.
+    ///
+    /// 対象リクエストが存在した場合には`true`が、それ以外は`false`が返される.
+    pub fn cancel(&self, request_id: u64) -> Result<bool> {
+        let mut entries = track!(self
+            .0
+            .entries
+            .lock()
+            .map_err(|e| ErrorKind::Other.cause(e.to_string())))?;
+        if let Some(entry) = entries.get_mut(&request_id) {
+            if let Some(tx) = entry.cancel_tx.take() {
+                let _ = tx.send(());
+            }
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// 実行中のリクエスト一覧を、リクエストIDの昇順で返す.
+    ///
+    /// `device_ids`が空ではない場合には、それらのデバイスに対するリクエストのみが対象となる.
+    pub fn list(&self, device_ids: &[DeviceId]) -> Result<Vec<InFlightRequest>> {
+        let entries = track!(self
+            .0
+            .entries
+            .lock()
+            .map_err(|e| ErrorKind::Other.cause(e.to_string())))?;
+        let mut requests = entries
+            .iter()
+            .filter(|(_, e)| device_ids.is_empty() || device_ids.contains(&e.device_id))
+            .map(|(&request_id, e)| InFlightRequest {
+                request_id,
+                procedure: e.procedure.to_owned(),
+                device_id: e.device_id.clone(),
+                target: e.target.clone(),
+                elapsed: e.start_time.elapsed(),
+                deadline: e.deadline,
+            })
+            .collect::<Vec<_>>();
+        requests.sort_by_key(|r| r.request_id);
+        Ok(requests)
+    }
+
+    fn finish(&self, request_id: u64) {
+        if let Ok(mut entries) = self.0.entries.lock() {
+            entries.remove(&request_id);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: AtomicU64,
+    entries: Mutex<HashMap<u64, Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    procedure: &'static str,
+    device_id: DeviceId,
+    target: RequestTarget,
+    deadline: Deadline,
+    start_time: Instant,
+    cancel_tx: Option<oneshot::Sender<()>>,
+}
+
+/// 実行中のリクエストの登録を、ドロップ時に解除するためのガード.
+#[derive(Debug)]
+pub struct InFlightGuard {
+    request_id: u64,
+    requests: InFlightRequests,
+    cancel_rx: oneshot::Receiver<()>,
+    stats: Option<StatsRecorder>,
+    metrics: Option<MetricsRecorder>,
+    interceptors: Option<(ServerInterceptors, RequestContext)>,
+    error_verbosity: ErrorVerbosity,
+    access_logger: Option<(Logger, bool)>, // (ロガー, 成功時にも出力するかどうか)
+    slow_log: Option<(Logger, Duration, Deadline)>,
+    busy_hint: Option<(Arc<DeviceMetrics>, Duration)>,
+    response_meta: Option<u64>, // 開始時点でのデバイスのキューの長さ
+    trace_id: Option<String>,
+    response_delay: Option<Duration>,
+    drop_response: bool,
+    #[cfg(feature = "tracing")]
+    span: Option<(Span, u64)>, // (リクエスト全体のスパン, 開始時点でのデバイスのキューの長さ)
+    start_time: Instant,
+}
+impl InFlightGuard {
+    /// リクエストIDを返す.
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+
+    /// リクエストの完了時に、その結果を指定のレコーダに記録するようにする.
+    pub fn record_stats(mut self, recorder: StatsRecorder) -> Self {
+        self.stats = Some(recorder);
+        self
+    }
+
+    /// リクエストの完了時に、その結果を指定のメトリクスのレコーダに記録するようにする.
+    pub fn record_metrics(mut self, recorder: MetricsRecorder) -> Self {
+        self.metrics = Some(recorder);
+        self
+    }
+
+    /// リクエストの完了時に、その結果を指定のインターセプタ群に通知するようにする.
+    pub fn intercept(mut self, interceptors: ServerInterceptors, context: RequestContext) -> Self {
+        self.interceptors = Some((interceptors, context));
+        self
+    }
+
+    /// リクエストの結果がエラーの場合に、その詳細度を指定のものに調整するようにする.
+    ///
+    /// 統計情報には、調整前のエラーが記録される.
+    pub fn error_verbosity(mut self, verbosity: ErrorVerbosity) -> Self {
+        self.error_verbosity = verbosity;
+        self
+    }
+
+    /// リクエストが`ErrorKind::DeviceBusy`で失敗した場合に、そのエラーに`BusyHint`を付与するようにする.
+    ///
+    /// ヒントの`retry_after`は、デバイスのキューの長さに`per_command`を乗じた値となる.
+    pub fn busy_hint(mut self, metrics: Arc<DeviceMetrics>, per_command: Duration) -> Self {
+        self.busy_hint = Some((metrics, per_command));
+        self
+    }
+
+    /// `Tracked::with_response_meta`で変換したfutureが、成功時の結果に`ResponseMeta`を付与するようにする.
+    ///
+    /// メタ情報のキューの長さには、このメソッドの呼び出し時点のものが使用される.
+    pub fn response_meta(mut self, metrics: &DeviceMetrics) -> Self {
+        self.response_meta = Some(metrics.queue_len() as u64);
+        self
+    }
+
+    /// リクエストがエラーで終わった場合に、その履歴にトレースIDを記録するようにする.
+    ///
+    /// `None`の場合には何も記録されない.
+    pub fn trace_id(mut self, trace_id: Option<String>) -> Self {
+        self.trace_id = trace_id;
+        self
+    }
+
+    /// リクエストの完了時に、その結果をアクセスログとして指定のロガーに出力するようにする.
+    ///
+    /// `log_success`が`false`の場合には、リクエストが失敗した場合にのみ出力される.
+    pub fn access_log(mut self, logger: Logger, log_success: bool) -> Self {
+        self.access_logger = Some((logger, log_success));
+        self
+    }
+
+    /// リクエストの処理時間が`threshold`を超えた場合に、その旨を指定のロガーに`Warning`レベルで出力するようにする.
+    ///
+    /// `deadline`は出力時の情報としてのみ使用される.
+    pub fn slow_log(mut self, logger: Logger, threshold: Duration, deadline: Deadline) -> Self {
+        self.slow_log = Some((logger, threshold, deadline));
+        self
+    }
+
+    /// 指定のfutureの完了後、`delay`が経過するまで、その結果を返さないようにする.
+    ///
+    /// 複数回呼び出された場合には、それらの合計だけ遅延される.
+    #[cfg(feature = "fault_injection")]
+    pub fn delay_response(mut self, delay: Duration) -> Self {
+        let total = self.response_delay.unwrap_or_default() + delay;
+        self.response_delay = Some(total);
+        self
+    }
+
+    /// 指定のfutureの完了後も、その結果を返さないようにする.
+    ///
+    /// リクエストの登録は、キャンセル(ないしドロップ)されるまで維持される.
+    #[cfg(feature = "fault_injection")]
+    pub fn drop_response(mut self) -> Self {
+        self.drop_response = true;
+        self
+    }
+
+    /// リクエストの完了時に、その結果を指定のスパンに記録するようにする.
+    ///
+    /// `wrap`で変換したfutureのポーリングは、このスパンの子スパン(`cannyls_rpc.device`)内で行われる.
+    #[cfg(feature = "tracing")]
+    pub fn span(mut self, span: Span, metrics: &DeviceMetrics) -> Self {
+        span.record("request_id", self.request_id);
+        if let Some(ref trace_id) = self.trace_id {
+            span.record("trace_id", trace_id.as_str());
+        }
+        self.span = Some((span, metrics.queue_len() as u64));
+        self
+    }
+
+    /// 指定のfutureが完了(ないしドロップ)するまで、リクエストの登録を維持する.
+    pub fn wrap<F: Future>(self, future: F) -> Tracked<F> {
+        #[cfg(feature = "tracing")]
+        let device_span = self
+            .span
+            .as_ref()
+            .map(|(parent, queue_len)| span::device_span(parent, *queue_len));
+        Tracked {
+            future: Some(future),
+            delayed: None,
+            guard: self,
+            #[cfg(feature = "tracing")]
+            device_span,
+        }
+    }
+}
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.requests.finish(self.request_id);
+    }
+}
+
+/// 実行中のリクエストとして登録されているfuture.
+///
+/// リクエストがキャンセルされた場合には、内部のfutureは破棄され、
+/// `ErrorKind::RequestDropped`エラーが結果として返される.
+///
+/// なお、既にデバイスに発行済みのコマンドは、キャンセル後もデバイス側で実行される可能性がある.
+#[derive(Debug)]
+pub struct Tracked<F: Future> {
+    future: Option<F>,
+    delayed: Option<(Timeout, F::Item)>, // 遅延中の結果
+    guard: InFlightGuard,
+    #[cfg(feature = "tracing")]
+    device_span: Option<Span>,
+}
+impl<F: Future> Tracked<F> {
+    /// 結果とメタ情報の組を返すfutureに変換する.
+    ///
+    /// メタ情報は、ガードに対して`InFlightGuard::response_meta`が指定されており、
+    /// かつ、リクエストが成功した場合にのみ付与される.
+    pub fn with_response_meta(self) -> WithResponseMeta<F> {
+        WithResponseMeta(self)
+    }
+
+    fn response_meta(&self) -> Option<ResponseMeta> {
+        self.guard.response_meta.map(|queue_len| ResponseMeta {
+            processing_time: self.guard.start_time.elapsed(),
+            queue_len,
+            server_version: env!("CARGO_PKG_VERSION").to_owned(),
+        })
+    }
+
+    fn attach_busy_hint<T>(&self, result: Result<T>) -> Result<T> {
+        let (metrics, per_command) = match (&result, &self.guard.busy_hint) {
+            (Err(e), Some((metrics, per_command)))
+                if *e.kind() == ErrorKind::DeviceBusy && BusyHint::of(e).is_none() =>
+            {
+                (metrics, *per_command)
+            }
+            _ => return result,
+        };
+        let e = result.err().expect("Never fails");
+        let queue_len = metrics.queue_len() as u64;
+        let hint = BusyHint {
+            queue_len,
+            retry_after: per_command.saturating_mul(queue_len.clamp(1, u64::from(u32::MAX)) as u32),
+        };
+        Err(hint.into_error(&e))
+    }
+
+    fn attach_trace_id<T>(&self, result: Result<T>) -> Result<T> {
+        match (result, &self.guard.trace_id) {
+            (Err(e), Some(trace_id)) => Err(track!(e, "trace_id={}", trace_id)),
+            (result, _) => result,
+        }
+    }
+
+    fn record_result<T>(&mut self, result: &Result<T>) {
+        #[cfg(feature = "tracing")]
+        {
+            self.device_span = None;
+            if let Some((span, _)) = self.guard.span.take() {
+                span::record_result(&span, result, self.guard.start_time.elapsed());
+            }
+        }
+        if let Some(recorder) = self.guard.stats.take() {
+            recorder.record(result);
+        }
+        if let Some(recorder) = self.guard.metrics.take() {
+            recorder.record(result);
+        }
+        if let Some((interceptors, context)) = self.guard.interceptors.take() {
+            interceptors.after_reply(&context, result.as_ref().map(|_| ()));
+        }
+        if let Some((logger, threshold, deadline)) = self.guard.slow_log.take() {
+            let elapsed = self.guard.start_time.elapsed();
+            if elapsed > threshold {
+                warn!(
+                    logger,
+                    "Slow RPC: elapsed={:?}, deadline={:?}", elapsed, deadline;
+                    "elapsed_us" => elapsed_us(elapsed),
+                    "succeeded" => result.is_ok()
+                );
+            }
+        }
+        if let Some((logger, log_success)) = self.guard.access_logger.take() {
+            let elapsed = self.guard.start_time.elapsed();
+            let elapsed_us = elapsed_us(elapsed);
+            match *result {
+                Ok(_) if !log_success => {}
+                Ok(_) => info!(logger, "RPC succeeded"; "elapsed_us" => elapsed_us),
+                Err(ref e) => info!(
+                    logger,
+                    "RPC failed: {}", e;
+                    "elapsed_us" => elapsed_us,
+                    "error_kind" => format!("{:?}", e.kind())
+                ),
+            }
+        }
+    }
+}
+impl<F, T> Future for Tracked<F>
+where
+    F: Future<Item = Result<T>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Ok(Async::Ready(())) = self.guard.cancel_rx.poll() {
+            self.future = None;
+            let e = ErrorKind::RequestDropped.cause("The request was cancelled");
+            let result = self.attach_trace_id(Err(track!(e).into()));
+            self.record_result(&result);
+            return Ok(Async::Ready(self.guard.error_verbosity.apply(result)));
+        }
+        if let Some((ref mut timer, _)) = self.delayed {
+            if let Ok(Async::NotReady) = timer.poll() {
+                return Ok(Async::NotReady);
+            }
+            let (_, result) = self.delayed.take().expect("Never fails");
+            return Ok(Async::Ready(self.finish(result)));
+        }
+        #[cfg(feature = "tracing")]
+        let entered = self.device_span.as_ref().map(Span::enter);
+        let polled = self.future.as_mut().map(Future::poll);
+        #[cfg(feature = "tracing")]
+        drop(entered);
+        let result = if let Some(polled) = polled {
+            if let Async::Ready(result) = polled? {
+                result
+            } else {
+                return Ok(Async::NotReady);
+            }
+        } else {
+            return Ok(Async::NotReady);
+        };
+        if self.guard.drop_response {
+            self.future = None;
+            Ok(Async::NotReady)
+        } else if let Some(delay) = self.guard.response_delay.take() {
+            self.future = None;
+            self.delayed = Some((timer::timeout(delay), result));
+            self.poll()
+        } else {
+            Ok(Async::Ready(self.finish(result)))
+        }
+    }
+}
+impl<F, T> Tracked<F>
+where
+    F: Future<Item = Result<T>>,
+{
+    fn finish(&mut self, result: Result<T>) -> Result<T> {
+        let result = self.attach_busy_hint(result);
+        let result = self.attach_trace_id(result);
+        self.record_result(&result);
+        self.guard.error_verbosity.apply(result)
+    }
+}
+
+/// `Tracked::with_response_meta`が返すfuture.
+pub struct WithResponseMeta<F: Future>(Tracked<F>);
+impl<F: Future> fmt::Debug for WithResponseMeta<F>
+where
+    Tracked<F>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WithResponseMeta({:?})", self.0)
+    }
+}
+impl<F, T> Future for WithResponseMeta<F>
+where
+    F: Future<Item = Result<T>>,
+{
+    type Item = Result<(T, Option<ResponseMeta>)>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Async::Ready(result) = self.0.poll()? {
+            let meta = self.0.response_meta();
+            Ok(Async::Ready(result.map(|v| (v, meta))))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+fn elapsed_us(elapsed: Duration) -> u64 {
+    elapsed.as_secs() * 1_000_000 + u64::from(elapsed.subsec_micros())
+}
+
+#[cfg(test)]
+mod tests {
+    use cannyls::lump::LumpId;
+    use futures::executor::{self, Notify, NotifyHandle};
+    use futures::future;
+
+    use super::*;
+
+    struct NotifyNop;
+    impl Notify for NotifyNop {
+        fn notify(&self, _: usize) {}
+    }
+
+    #[test]
+    fn in_flight_requests_works() {
+        let requests = InFlightRequests::default();
+        let guard0 = requests.start(
+            "foo",
+            DeviceId::new("a"),
+            RequestTarget::Device,
+            Deadline::Infinity,
+        );
+        let guard1 = requests.start(
+            "bar",
+            DeviceId::new("b"),
+            RequestTarget::Lump(LumpId::new(1)),
+            Deadline::Immediate,
+        );
+
+        let list = track_try_unwrap!(requests.list(&[]));
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].procedure, "foo");
+        assert_eq!(list[1].target, RequestTarget::Lump(LumpId::new(1)));
+
+        let list = track_try_unwrap!(requests.list(&[DeviceId::new("b")]));
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].procedure, "bar");
+
+        drop(guard0);
+        let mut tracked = executor::spawn(guard1.wrap(future::empty::<Result<()>, ()>()));
+        let notify = NotifyHandle::from(Arc::new(NotifyNop));
+        assert_eq!(track_try_unwrap!(requests.list(&[])).len(), 1);
+        assert!(tracked
+            .poll_future_notify(&notify, 0)
+            .unwrap()
+            .is_not_ready());
+
+        let request_id = list[0].request_id;
+        assert!(track_try_unwrap!(requests.cancel(request_id)));
+        match tracked.poll_future_notify(&notify, 0) {
+            Ok(Async::Ready(Err(e))) => assert_eq!(*e.kind(), ErrorKind::RequestDropped),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        drop(tracked);
+        assert!(track_try_unwrap!(requests.list(&[])).is_empty());
+        assert!(!track_try_unwrap!(requests.cancel(request_id)));
+    }
+}
@@ -0,0 +1,402 @@
+//! RPC経由で取得可能な、デバイス等の状態情報.
+use cannyls::deadline::Deadline;
+use cannyls::device::DeviceStatus;
+use cannyls::lump::LumpId;
+#[cfg(feature = "registry")]
+use cannyls::metrics::{
+    DeviceCommandCounter, DeviceMetrics, JournalQueueMetrics, JournalRegionMetrics, StorageMetrics,
+};
+use cannyls::ErrorKind;
+use fibers_rpc::ProcedureId;
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::device::DeviceId;
+
+/// ジャーナル領域(リングバッファ)の使用状況.
+///
+/// `consumed_bytes`と`released_bytes`は、それぞれリングバッファの末尾と先頭が、
+/// デバイスの起動以降に進んだ量の累積値に相当する.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JournalUsage {
+    /// ジャーナル領域の容量(バイト単位).
+    pub capacity_bytes: u64,
+
+    /// ジャーナル領域の現在の使用量(バイト単位).
+    pub usage_bytes: u64,
+
+    /// ジャーナルへの追記によって消費されたバイト数の合計.
+    pub consumed_bytes: u64,
+
+    /// GC等によってジャーナルから解放されたバイト数の合計.
+    pub released_bytes: u64,
+
+    /// ジャーナルに保持されているレコードの数.
+    pub records: u64,
+
+    /// ジャーナルに保持されているレコードの、種類毎の数.
+    pub record_counts: JournalRecordCounts,
+}
+impl JournalUsage {
+    /// ジャーナル領域の使用率(`0.0`から`1.0`の範囲)を返す.
+    ///
+    /// 容量が`0`の場合には`0.0`が返される.
+    pub fn usage_ratio(&self) -> f64 {
+        if self.capacity_bytes == 0 {
+            0.0
+        } else {
+            self.usage_bytes as f64 / self.capacity_bytes as f64
+        }
+    }
+
+    #[cfg(feature = "registry")]
+    pub(crate) fn from_metrics(metrics: &JournalRegionMetrics) -> Self {
+        let queue = metrics.queue();
+        JournalUsage {
+            capacity_bytes: queue.capacity_bytes(),
+            usage_bytes: queue.usage_bytes(),
+            consumed_bytes: queue.consumed_bytes(),
+            released_bytes: queue.released_bytes(),
+            records: queue.queue_len(),
+            record_counts: JournalRecordCounts::from_metrics(queue),
+        }
+    }
+}
+
+/// ジャーナルに保持されているレコードの、種類毎の数.
+///
+/// 各値は、種類毎のレコードの追加数と削除数の差分として算出される.
+/// 個々のカウンタは独立して読み出されるため、合計値は`JournalUsage::records`と厳密には一致しないことがある.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JournalRecordCounts {
+    /// PUTレコードの数.
+    pub put: u64,
+
+    /// EMBEDレコード(データがジャーナルに埋め込まれたPUT)の数.
+    pub embed: u64,
+
+    /// DELETEレコードの数.
+    pub delete: u64,
+
+    /// DELETE_RANGEレコードの数.
+    pub delete_range: u64,
+}
+impl JournalRecordCounts {
+    /// 全種類のレコード数の合計を返す.
+    pub fn total(&self) -> u64 {
+        self.put + self.embed + self.delete + self.delete_range
+    }
+
+    #[cfg(feature = "registry")]
+    fn from_metrics(queue: &JournalQueueMetrics) -> Self {
+        let (starting, running) = queue.enqueued_records();
+        let dequeued = queue.dequeued_records();
+
+        // NOTE: 削除数を先に読み出さないとアンダーフローする可能性がある
+        let put = dequeued.put();
+        let embed = dequeued.embed();
+        let delete = dequeued.delete();
+        let delete_range = dequeued.delete_range();
+        JournalRecordCounts {
+            put: (starting.put() + running.put()).saturating_sub(put),
+            embed: (starting.embed() + running.embed()).saturating_sub(embed),
+            delete: (starting.delete() + running.delete()).saturating_sub(delete),
+            delete_range: (starting.delete_range() + running.delete_range())
+                .saturating_sub(delete_range),
+        }
+    }
+}
+
+/// デバイスのメトリクスのスナップショット.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceMetricsSnapshot {
+    /// デバイスのID.
+    pub device_id: DeviceId,
+
+    /// デバイスの稼働状態.
+    pub status: DeviceStatus,
+
+    /// デバイスのコマンドキューの長さ.
+    pub queue_len: u64,
+
+    /// キューに追加されたコマンドの数の合計.
+    pub enqueued_commands: u64,
+
+    /// キューから取り出されたコマンドの数の合計.
+    pub dequeued_commands: u64,
+
+    /// 失敗したコマンドの数の合計.
+    pub failed_commands: u64,
+
+    /// `ErrorKind::DeviceBusy`で拒否されたコマンドの数の合計.
+    pub busy_commands: u64,
+
+    /// ストレージのメトリクス.
+    ///
+    /// デバイスがストレージのメトリクスと共に登録されていない場合には`None`となる.
+    pub storage: Option<StorageMetricsSnapshot>,
+}
+#[cfg(feature = "registry")]
+impl DeviceMetricsSnapshot {
+    pub(crate) fn new(
+        device_id: DeviceId,
+        metrics: &DeviceMetrics,
+        storage: Option<&StorageMetrics>,
+    ) -> Self {
+        DeviceMetricsSnapshot {
+            device_id,
+            status: metrics.status(),
+            queue_len: metrics.queue_len() as u64,
+            enqueued_commands: command_total(metrics.enqueued_commands()),
+            dequeued_commands: command_total(metrics.dequeued_commands()),
+            failed_commands: command_total(metrics.failed_commands()),
+            busy_commands: command_total(metrics.busy_commands()),
+            storage: storage.map(StorageMetricsSnapshot::new),
+        }
+    }
+}
+
+/// ストレージのメトリクスのスナップショット.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageMetricsSnapshot {
+    /// ストレージに保存されているlumpの数.
+    pub lumps: u64,
+
+    /// ストレージに保存(PUT)されたlumpの数の合計.
+    pub put_lumps: u64,
+
+    /// ストレージから削除されたlumpの数の合計.
+    pub delete_lumps: u64,
+
+    /// データ領域の容量(バイト単位).
+    pub data_capacity_bytes: u64,
+
+    /// データ領域の使用量(バイト単位).
+    pub data_usage_bytes: u64,
+
+    /// ジャーナル領域の使用状況.
+    pub journal: JournalUsage,
+}
+#[cfg(feature = "registry")]
+impl StorageMetricsSnapshot {
+    fn new(metrics: &StorageMetrics) -> Self {
+        StorageMetricsSnapshot {
+            lumps: metrics.lumps() as u64,
+            put_lumps: metrics.put_lumps(),
+            delete_lumps: metrics.delete_lumps(),
+            data_capacity_bytes: metrics.data_region().capacity_bytes(),
+            data_usage_bytes: metrics.data_region().usage_bytes(),
+            journal: JournalUsage::from_metrics(metrics.journal_region()),
+        }
+    }
+}
+
+#[cfg(feature = "registry")]
+fn command_total(counter: &DeviceCommandCounter) -> u64 {
+    counter.put()
+        + counter.get()
+        + counter.head()
+        + counter.delete()
+        + counter.delete_range()
+        + counter.list()
+        + counter.list_range()
+        + counter.usage_range()
+        + counter.stop()
+}
+
+/// サーバ側で実行中のリクエストの情報.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InFlightRequest {
+    /// サーバ内でリクエストに割り当てられたID.
+    pub request_id: u64,
+
+    /// RPCの名前(e.g., `cannyls.lump.get`).
+    pub procedure: String,
+
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+
+    /// リクエストの操作対象.
+    pub target: RequestTarget,
+
+    /// リクエストの処理開始からの経過時間.
+    pub elapsed: Duration,
+
+    /// リクエストに指定されたデッドライン.
+    pub deadline: Deadline,
+}
+
+/// リクエストの操作対象.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestTarget {
+    /// デバイス全体(ないし複数のlump).
+    Device,
+
+    /// 単一のlump.
+    Lump(LumpId),
+
+    /// lumpの範囲.
+    Range(Range<LumpId>),
+}
+
+/// 登録デバイスの一覧の要素.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSummary {
+    /// デバイスのID.
+    pub device_id: DeviceId,
+
+    /// デバイスの稼働状態.
+    ///
+    /// 一覧の取得時に状態の取得が要求されなかった場合には`None`となる.
+    pub status: Option<DeviceStatus>,
+}
+
+/// 単一デバイスの状態.
+///
+/// 未登録のデバイスを対象とした場合にも、エラーとはならずに、その旨を示す値となる.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceStatusReport {
+    /// デバイスのID.
+    pub device_id: DeviceId,
+
+    /// デバイスの稼働状態.
+    ///
+    /// デバイスがレジストリに登録されていない場合には`None`となる.
+    pub status: Option<DeviceStatus>,
+
+    /// デバイスのコマンドキューの長さ.
+    pub queue_len: u64,
+
+    /// ストレージのメトリクス.
+    ///
+    /// デバイスがストレージのメトリクスと共に登録されていない場合には`None`となる.
+    pub storage: Option<StorageMetricsSnapshot>,
+}
+impl DeviceStatusReport {
+    /// デバイスがレジストリに登録されているかどうかを返す.
+    pub fn is_registered(&self) -> bool {
+        self.status.is_some()
+    }
+
+    /// デバイスが停止済みかどうかを返す.
+    ///
+    /// 未登録のデバイスの場合には`false`となる.
+    pub fn is_terminated(&self) -> bool {
+        self.status == Some(DeviceStatus::Stopped)
+    }
+}
+#[cfg(feature = "server")]
+impl DeviceStatusReport {
+    pub(crate) fn new(device_id: DeviceId, snapshot: Option<DeviceMetricsSnapshot>) -> Self {
+        match snapshot {
+            None => DeviceStatusReport {
+                device_id,
+                status: None,
+                queue_len: 0,
+                storage: None,
+            },
+            Some(snapshot) => DeviceStatusReport {
+                device_id,
+                status: Some(snapshot.status),
+                queue_len: snapshot.queue_len,
+                storage: snapshot.storage,
+            },
+        }
+    }
+}
+
+/// デバイスが実際にリクエストを処理可能かどうかの確認結果.
+///
+/// 確認は、デバイスに対して(存在しないことが想定される)lumpのHEADを発行することで行われる.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceReadiness {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+
+    /// 確認用のリクエストが失敗した場合には、そのエラーの種類.
+    ///
+    /// 対象デバイスが存在しない場合には`ErrorKind::InvalidInput`となる.
+    pub error: Option<ErrorKind>,
+
+    /// 確認用のリクエストの処理に要した時間.
+    pub latency: Duration,
+}
+impl DeviceReadiness {
+    /// デバイスがリクエストを処理可能な場合には`true`を返す.
+    pub fn is_ready(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// デバイスおよびRPC単位の、リクエストの統計情報.
+///
+/// 値はサーバの起動以降(ないし最後にリセットされて以降)の累積値となる.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestStats {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+
+    /// RPCの名前(e.g., `cannyls.lump.get`).
+    pub procedure: String,
+
+    /// 成功したリクエストの数.
+    pub succeeded: u64,
+
+    /// 失敗したリクエストの数を、エラーの種類毎に保持したもの.
+    ///
+    /// 一度も発生していない種類のエラーは含まれない.
+    pub failed: Vec<(ErrorKind, u64)>,
+
+    /// 集計期間(i.e., 集計の開始ないしリセットからの経過時間).
+    pub period: Duration,
+}
+impl RequestStats {
+    /// 失敗したリクエストの数の合計を返す.
+    pub fn failed_total(&self) -> u64 {
+        self.failed.iter().map(|&(_, n)| n).sum()
+    }
+
+    /// リクエストの数の合計を返す.
+    pub fn total(&self) -> u64 {
+        self.succeeded + self.failed_total()
+    }
+}
+
+/// サーバ側のインポートセッションの状態.
+///
+/// セッションの終了時には、インポート結果の要約としても使われる.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportSessionStatus {
+    /// サーバ内でセッションに割り当てられたID.
+    pub session_id: u64,
+
+    /// インポート先のデバイスのID.
+    pub device_id: DeviceId,
+
+    /// 次に送信すべきバッチの番号.
+    ///
+    /// 最初のバッチの番号は`0`で、以後バッチが処理される度に`1`ずつ増加する.
+    pub next_batch_seq: u64,
+
+    /// これまでにインポートされたlumpの数.
+    pub imported_lumps: u64,
+
+    /// これまでにインポートされたlumpのデータの合計バイト数.
+    pub imported_bytes: u64,
+}
+
+/// RPCサーバのバージョン、および、サーバが対応しているRPCの情報.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// サーバが使用している`cannyls_rpc`クレートのバージョン.
+    pub version: String,
+
+    /// サーバに登録されており、かつ、有効になっているRPCのID一覧(昇順).
+    pub procedures: Vec<ProcedureId>,
+}
+impl ServerInfo {
+    /// 指定のIDのRPCにサーバが対応している場合には`true`を返す.
+    pub fn supports(&self, procedure: ProcedureId) -> bool {
+        self.procedures.binary_search(&procedure).is_ok()
+    }
+}
@@ -0,0 +1,278 @@
+//! RPCメッセージのデバッグ用のデコーダ.
+//!
+//! パケットキャプチャ(e.g., `tcpdump`)等で取得したメッセージのバイト列を、
+//! このクレート自身のデコーダを用いてデコードし、人間が読める形式に変換する.
+//! 他の実装との相互運用性の問題を調査するためのもの.
+//!
+//! 入力はメッセージ本体(ProtocolBuffers形式)のバイト列で、`fibers_rpc`のフレームヘッダ等は含まない.
+//! RPCは名前(e.g., `cannyls.lump.get`)ないし`fibers_rpc`の手続きID(e.g., `0x00010001`)で指定する.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate cannyls_rpc;
+//! use cannyls_rpc::inspect::{self, MessageKind};
+//!
+//! let hex = "0a 03 66 6f 6f 12 12 09 00 00 00 00 00 00 00 00 11 05 00 00 00 00 00 00 00 1a 00";
+//! let bytes = inspect::parse_hex(hex).unwrap();
+//! let decoded = inspect::decode_message("cannyls.lump.head", MessageKind::Request, &bytes).unwrap();
+//! assert!(decoded.starts_with("LumpRequest {"));
+//! assert!(decoded.contains("\"foo\""));
+//! assert!(decoded.contains("\"00000000000000000000000000000005\""));
+//! ```
+use bytecodec::{Decode, DecodeExt};
+use cannyls::{Error, ErrorKind, Result};
+use fibers_rpc::{Call, Cast};
+use std::fmt;
+use trackable::error::ErrorKindExt;
+
+use crate::rpc;
+
+/// デコード対象のメッセージの種類.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    /// リクエストメッセージ(通知RPCの場合には通知メッセージ).
+    Request,
+
+    /// レスポンスメッセージ.
+    Response,
+}
+
+/// 指定のRPCのメッセージをデコードして、その内容を`Debug`形式の文字列で返す.
+///
+/// `procedure`にはRPCの名前ないし手続きID(10進数ないし`0x`で始まる16進数)を指定する.
+/// 手続きIDを共有するRPC(e.g., `rpc::GetLumpWithMetaRpc`)の場合には、手続きIDでの指定時には元のRPCが選ばれる.
+///
+/// # Errors
+///
+/// 以下の場合には`ErrorKind::InvalidInput`が返される:
+///
+/// - 未知のRPCが指定された場合
+/// - 通知RPCに対してレスポンスのデコードが要求された場合
+/// - バイト列がメッセージとしてデコードできない場合
+pub fn decode_message(procedure: &str, kind: MessageKind, bytes: &[u8]) -> Result<String> {
+    let entry = track_assert_some!(
+        lookup(procedure),
+        ErrorKind::InvalidInput,
+        "Unknown procedure: {:?}",
+        procedure
+    );
+    match kind {
+        MessageKind::Request => track!((entry.request)(bytes); entry.name),
+        MessageKind::Response => {
+            let decode = track_assert_some!(
+                entry.response,
+                ErrorKind::InvalidInput,
+                "{} has no response message",
+                entry.name
+            );
+            track!(decode(bytes); entry.name)
+        }
+    }
+}
+
+/// 16進数表記のバイト列をパースする.
+///
+/// 空白文字と`:`は区切りとして無視され、また先頭の`0x`は省略可能.
+///
+/// # Errors
+///
+/// 16進数として不正な文字が含まれている場合や、桁数が奇数の場合には`ErrorKind::InvalidInput`が返される.
+pub fn parse_hex(s: &str) -> Result<Vec<u8>> {
+    let s = s.trim();
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    let digits = s
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .map(|c| {
+            c.to_digit(16).map(|d| d as u8).ok_or_else(|| {
+                Error::from(ErrorKind::InvalidInput.cause(format!("Not a hex digit: {:?}", c)))
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    track_assert_eq!(
+        digits.len() % 2,
+        0,
+        ErrorKind::InvalidInput,
+        "Odd number of hex digits"
+    );
+    Ok(digits.chunks(2).map(|d| (d[0] << 4) | d[1]).collect())
+}
+
+/// デコードに対応しているRPCの名前と手続きIDの一覧を返す.
+pub fn procedures() -> Vec<(&'static str, u32)> {
+    PROCEDURES.iter().map(|p| (p.name, p.id)).collect()
+}
+
+type DecodeFn = fn(&[u8]) -> Result<String>;
+
+struct Procedure {
+    name: &'static str,
+    id: u32,
+    request: DecodeFn,
+    response: Option<DecodeFn>,
+}
+
+macro_rules! procedures {
+    (calls: [$($call:ty),*], casts: [$($cast:ty),*]) => {
+        &[
+            $(Procedure {
+                name: <$call as Call>::NAME,
+                id: <$call as Call>::ID.0,
+                request: decode::<<$call as Call>::ReqDecoder>,
+                response: Some(decode::<<$call as Call>::ResDecoder>),
+            },)*
+            $(Procedure {
+                name: <$cast as Cast>::NAME,
+                id: <$cast as Cast>::ID.0,
+                request: decode::<<$cast as Cast>::Decoder>,
+                response: None,
+            },)*
+        ]
+    };
+}
+
+const PROCEDURES: &[Procedure] = procedures!(
+    calls: [
+        rpc::GetLumpRpc,
+        rpc::GetLumpWithMetaRpc,
+        rpc::GetLumpRangeRpc,
+        rpc::GetLumpWithChecksumRpc,
+        rpc::HeadLumpRpc,
+        rpc::HeadLumpWithMetaRpc,
+        rpc::ExistsLumpRpc,
+        rpc::PutLumpRpc,
+        rpc::PutLumpWithMetaRpc,
+        rpc::PutLumpV2Rpc,
+        rpc::DeleteLumpRpc,
+        rpc::DeleteLumpWithMetaRpc,
+        rpc::DeleteLumpV2Rpc,
+        rpc::ListLumpRpc,
+        rpc::ListLumpRangeRpc,
+        rpc::ScrubRangeRpc,
+        rpc::CopyRangeRpc,
+        rpc::ExportLumpsRpc,
+        rpc::ListLumpsChunkRpc,
+        rpc::OpenImportSessionRpc,
+        rpc::ImportLumpsRpc,
+        rpc::ImportSessionStatusRpc,
+        rpc::CommitImportSessionRpc,
+        rpc::UsageRangeRpc,
+        rpc::DeleteRangeRpc,
+        rpc::ScriptRpc,
+        rpc::ConcurrentScriptRpc,
+        rpc::DeleteRangeBoundedRpc,
+        rpc::PutLumpsRpc,
+        rpc::GetLumpsRpc,
+        rpc::JournalUsageRpc,
+        rpc::StorageHeaderRpc,
+        rpc::MetricsSnapshotRpc,
+        rpc::RequestStatsRpc,
+        rpc::ReadinessRpc,
+        rpc::ListDevicesRpc,
+        rpc::DeviceStatusRpc,
+        rpc::DeviceLabelsRpc,
+        rpc::DeleteDeviceRpc,
+        rpc::StopDeviceRpc,
+        rpc::SetJournalSyncRpc,
+        rpc::SetQueueLimitsRpc,
+        rpc::SetWriteWatermarkRpc,
+        rpc::SetLogLevelRpc,
+        rpc::ListInFlightRpc,
+        rpc::CancelInFlightRpc,
+        rpc::ResetRequestStatsRpc,
+        rpc::ProvisionDeviceRpc,
+        rpc::ServerInfoRpc
+    ],
+    casts: [rpc::PutLumpNoAckRpc, rpc::DeleteLumpNoAckRpc]
+);
+
+fn lookup(procedure: &str) -> Option<&'static Procedure> {
+    let id = match procedure.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => procedure.parse().ok(),
+    };
+    PROCEDURES
+        .iter()
+        .find(|p| p.name == procedure || Some(p.id) == id)
+}
+
+fn decode<D>(bytes: &[u8]) -> Result<String>
+where
+    D: Decode + Default,
+    D::Item: fmt::Debug,
+{
+    let item = track!(D::default()
+        .decode_from_bytes(bytes)
+        .map_err(|e| ErrorKind::InvalidInput.takes_over(e)))?;
+    Ok(format!("{:#?}", item))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytecodec::EncodeExt;
+    use cannyls::deadline::Deadline;
+    use cannyls::lump::LumpId;
+
+    use super::*;
+    use crate::device::DeviceId;
+
+    #[test]
+    fn decode_message_works() {
+        let request = rpc::LumpRequest {
+            device_id: DeviceId::new("foo"),
+            lump_id: LumpId::new(5),
+            options: rpc::RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: true,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
+            },
+            precondition: None,
+        };
+        let bytes = track_try_unwrap!(
+            <rpc::GetLumpRpc as Call>::ReqEncoder::default().encode_into_bytes(request.clone())
+        );
+        let decoded = track_try_unwrap!(decode_message(
+            rpc::GetLumpRpc::NAME,
+            MessageKind::Request,
+            &bytes
+        ));
+        assert_eq!(decoded, format!("{:#?}", request));
+
+        let id = format!("{:#x}", <rpc::GetLumpRpc as Call>::ID.0);
+        let decoded = track_try_unwrap!(decode_message(&id, MessageKind::Request, &bytes));
+        assert_eq!(decoded, format!("{:#?}", request));
+
+        let bytes = track_try_unwrap!(
+            <rpc::HeadLumpRpc as Call>::ResEncoder::default().encode_into_bytes(Ok(None))
+        );
+        let decoded = track_try_unwrap!(decode_message(
+            rpc::HeadLumpRpc::NAME,
+            MessageKind::Response,
+            &bytes
+        ));
+        assert_eq!(decoded, format!("{:#?}", Ok::<_, ()>(None::<()>)));
+
+        assert!(decode_message("unknown", MessageKind::Request, &[]).is_err());
+        assert!(decode_message(rpc::PutLumpNoAckRpc::NAME, MessageKind::Response, &[]).is_err());
+        assert!(decode_message(rpc::GetLumpRpc::NAME, MessageKind::Request, &[0xff]).is_err());
+        let id = <rpc::ServerInfoRpc as Call>::ID.0;
+        assert!(procedures().contains(&(rpc::ServerInfoRpc::NAME, id)));
+    }
+
+    #[test]
+    fn parse_hex_works() {
+        assert_eq!(track_try_unwrap!(parse_hex("0x0a03")), vec![0x0a, 0x03]);
+        assert_eq!(
+            track_try_unwrap!(parse_hex("0a:03 FF\n")),
+            vec![0x0a, 0x03, 0xff]
+        );
+        assert!(parse_hex("0a0").is_err());
+        assert!(parse_hex("zz").is_err());
+    }
+}
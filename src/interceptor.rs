@@ -0,0 +1,106 @@
+//! サーバ側のリクエスト処理に割り込むためのインターセプタ.
+use cannyls::{Error, Result};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::device::DeviceId;
+use crate::info::RequestTarget;
+use crate::rpc::RequestOptions;
+
+/// インターセプタに渡される、リクエストの情報.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// RPCの名前(e.g., `cannyls.lump.get`).
+    pub procedure: &'static str,
+
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+
+    /// リクエストの操作対象.
+    pub target: RequestTarget,
+
+    /// リクエストのオプション.
+    ///
+    /// デバイスの設定(e.g., `DeviceSettings::max_queue_len`)は適用済み.
+    pub options: RequestOptions,
+
+    /// 署名の検証対象となるペイロード(e.g., lumpのデータ)の合計バイト数.
+    ///
+    /// ペイロードを伴わないリクエストの場合には`0`となる.
+    pub payload_size: usize,
+
+    /// サーバがリクエストの処理を開始した時刻.
+    pub received_at: Instant,
+}
+
+/// サーバ側のリクエスト処理に割り込むためのインターセプタ.
+///
+/// `Server::add_interceptor`で登録され、lumpやデバイスに対するリクエスト(アクセスログの対象と同じ)の処理の
+/// 開始前と、結果の確定後(応答の送信前)に呼び出される.
+/// 認証やレート制限、独自のメトリクスやログ出力等を、ハンドラに手を加えずにサーバ上に構築するためのもの.
+///
+/// 対象デバイスの検索に失敗したリクエストに対しては、呼び出されない.
+///
+/// インターセプタはRPCの処理中に同期的に呼び出されるので、重い処理は別スレッド等に委譲すべきである.
+pub trait ServerInterceptor: Send + Sync + 'static {
+    /// リクエストの処理の開始前に呼び出される.
+    ///
+    /// エラーを返した場合には、リクエストは処理されずに、そのエラーが応答として返される.
+    /// その場合、後続のインターセプタの`before_dispatch`は呼び出されない.
+    ///
+    /// デフォルト実装は、何もせずに`Ok(())`を返す.
+    fn before_dispatch(&self, context: &RequestContext) -> Result<()> {
+        let _ = context;
+        Ok(())
+    }
+
+    /// リクエストの結果の確定後に呼び出される.
+    ///
+    /// `before_dispatch`が呼び出された全てのリクエストに対して、(リクエストが拒否された場合も含めて)一度だけ呼び出される.
+    /// ただし、結果の確定前にリクエストが破棄された場合(e.g., サーバの停止)には、呼び出されない.
+    /// 結果のエラーは、応答用に詳細度が調整される前のもの(`ErrorVerbosity`を参照).
+    ///
+    /// デフォルト実装は、何もしない.
+    fn after_reply(&self, context: &RequestContext, result: std::result::Result<(), &Error>) {
+        let _ = (context, result);
+    }
+}
+
+/// サーバに登録されたインターセプタ群.
+#[derive(Clone, Default)]
+pub struct ServerInterceptors(Vec<Arc<dyn ServerInterceptor>>);
+impl ServerInterceptors {
+    pub fn add<I: ServerInterceptor>(&mut self, interceptor: I) {
+        self.0.push(Arc::new(interceptor));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // 登録順に`before_dispatch`を呼び出す.
+    //
+    // 拒否された場合には、全てのインターセプタの`after_reply`をそのエラーで呼び出す.
+    pub fn before_dispatch(&self, context: &RequestContext) -> Result<()> {
+        for interceptor in &self.0 {
+            if let Err(e) = interceptor.before_dispatch(context) {
+                let e = track!(e);
+                self.after_reply(context, Err(&e));
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn after_reply(&self, context: &RequestContext, result: std::result::Result<(), &Error>) {
+        for interceptor in &self.0 {
+            interceptor.after_reply(context, result);
+        }
+    }
+}
+impl fmt::Debug for ServerInterceptors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ServerInterceptors {{ len: {} }}", self.0.len())
+    }
+}
@@ -6,32 +6,173 @@
 //! - [protobuf_codec][protobuf_codec]: RPCメッセージをProtocolBuffers形式でエンコード・デコードするためのライブラリ
 //! - [fibers_rpc][fibers_rpc]: RPCの通信層用のライブラリ
 //!
+//! # Feature flags
+//!
+//! - `client`: RPCクライアント(`Client`)を有効にする (デフォルトで有効)
+//! - `registry`: デバイスレジストリ(`DeviceRegistry`)を有効にする
+//! - `server`: RPCサーバ(`Server`)を有効にする (デフォルトで有効、`registry`を含む)
+//! - `fault_injection`: 障害試験用の障害や遅延の注入(`FaultInjector`, `LatencyInjector`)を有効にする (`server`を含む)
+//! - `tracing`: [tracing]クレート用のスパンを、サーバでの各リクエストの処理やクライアントでの各呼び出しに対して作成する
+//!
+//! クライアントのみが必要な場合には`default-features = false, features = ["client"]`を指定することで、
+//! サーバおよびデバイスレジストリ関連のコードとその依存クレートをビルド対象から外すことができる.
+//!
 //! [cannyls]: https://github.com/frugalos/cannyls
 //! [cannyls_rpc.proto]: https://github.com/frugalos/cannyls_rpc/blob/master/protobuf/cannyls_rpc.proto
 //! [protobuf_codec]: https://crates.io/crates/protobuf_codec
 //! [fibers_rpc]: https://crates.io/crates/fibers_rpc
+//! [tracing]: https://crates.io/crates/tracing
 #![warn(missing_docs)]
+extern crate adler32;
+#[cfg(feature = "registry")]
 extern crate atomic_immut;
 extern crate bytecodec;
 extern crate cannyls;
+#[cfg(feature = "server")]
 extern crate factory;
 extern crate fibers;
 extern crate fibers_rpc;
 extern crate futures;
+#[cfg(feature = "registry")]
+extern crate prometrics;
 extern crate protobuf_codec;
-#[macro_use]
+#[cfg_attr(feature = "registry", macro_use)]
 extern crate slog;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 #[macro_use]
 extern crate trackable;
+extern crate uuid;
+
+// クライアントの利用者が`cannyls`に直接依存せずに済むように、APIに現れる型を再エクスポートしておく.
+pub use cannyls::deadline::Deadline;
+pub use cannyls::lump::{LumpData, LumpHeader, LumpId};
+pub use cannyls::storage::{StorageHeader, StorageUsage};
+pub use cannyls::{Error, ErrorKind, Result};
 
-pub use crate::client::{Client, RequestBuilder};
-pub use crate::device::DeviceId;
-pub use crate::registry::{DeviceRegistry, DeviceRegistryHandle};
-pub use crate::server::Server;
+#[cfg(feature = "server")]
+pub use crate::acl::{AccessControl, Permissions};
+#[cfg(feature = "client")]
+pub use crate::breaker::CircuitBreakerPolicy;
+#[cfg(feature = "client")]
+pub use crate::client::{
+    CallOptions, Client, ConnectFuture, DeleteLumpFuture, DeleteRangeFuture, ExecuteScriptFuture,
+    ExistsLumpFuture, ExportLumpsStream, GetLumpFuture, GetLumpToWriterFuture,
+    GetLumpsConcurrentFuture, GetLumpsFuture, HeadLumpFuture, ListLumpsFuture, ListLumpsStream,
+    PutLumpFuture, PutLumpsFuture, PutPipelineResults, PutPipelineSink, RequestBuilder,
+    RequestTemplate, Response, UsageRangeFuture,
+};
+#[cfg(feature = "client")]
+pub use crate::client_interceptor::{CallContext, ClientInterceptor};
+#[cfg(feature = "client")]
+pub use crate::compat::{Compat, CompatSink, CompatStream};
+#[cfg(feature = "registry")]
+pub use crate::config::NodeConfig;
+pub use crate::device::{DeviceId, DeviceLabels, DeviceNvmSpec, DeviceSettings, DeviceSpec};
+#[cfg(feature = "client")]
+pub use crate::error::{ClientError, RetryClass};
+#[cfg(feature = "fault_injection")]
+pub use crate::fault::{Fault, FaultInjector, LatencyDistribution, LatencyInjector, LatencyRule};
+pub use crate::info::{
+    DeviceMetricsSnapshot, DeviceReadiness, DeviceStatusReport, DeviceSummary, ImportSessionStatus,
+    InFlightRequest, JournalRecordCounts, JournalUsage, RequestStats, RequestTarget, ServerInfo,
+    StorageMetricsSnapshot,
+};
+#[cfg(feature = "server")]
+pub use crate::interceptor::{RequestContext, ServerInterceptor};
+#[cfg(feature = "client")]
+pub use crate::metrics::{ClientMetrics, LatencyHistogram, ProcedureMetrics, LATENCY_BUCKETS};
+#[cfg(feature = "server")]
+pub use crate::observer::{Mutation, MutationObserver};
+#[cfg(feature = "registry")]
+pub use crate::provision::provision;
+#[cfg(feature = "registry")]
+pub use crate::registry::{
+    ConfigReload, DeviceRegistry, DeviceRegistryHandle, DevicesSnapshot, RegistryEvent,
+    RegistryEvents, RegistryMetrics, ReloadConfigFuture,
+};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use crate::replay::RecordedRequest;
+#[cfg(feature = "server")]
+pub use crate::replay::TrafficRecorder;
+#[cfg(feature = "client")]
+pub use crate::replay::{ReplayFuture, ReplaySummary, Replayer};
+#[cfg(feature = "client")]
+pub use crate::replica::{BalancePolicy, Balanced, ReplicaSet};
+#[cfg(feature = "client")]
+pub use crate::retry::{BusyRetry, BusyRetryPolicy};
+#[cfg(feature = "client")]
+pub use crate::router::{Router, DEFAULT_VIRTUAL_NODES};
+pub use crate::rpc::{DeleteRangeChunk, ScriptOp, ScriptOpResult};
+#[cfg(feature = "server")]
+pub use crate::server::{ErrorVerbosity, ProcedureConfig, Server};
+#[cfg(feature = "server")]
+pub use crate::signing::SignatureVerifier;
+#[cfg(any(feature = "client", feature = "server"))]
+pub use crate::signing::SigningKey;
 
+#[cfg(feature = "server")]
+mod acl;
+#[cfg(feature = "client")]
+mod breaker;
+#[cfg(any(feature = "client", feature = "server"))]
+mod checksum;
+#[cfg(feature = "client")]
 mod client;
+#[cfg(feature = "client")]
+mod client_interceptor;
+#[cfg(feature = "client")]
+mod compat;
+#[cfg(feature = "registry")]
+mod config;
 mod device;
+#[cfg(feature = "client")]
+mod error;
+#[cfg(feature = "fault_injection")]
+mod fault;
+#[cfg(feature = "server")]
+mod import;
+#[cfg(feature = "server")]
+mod in_flight;
+mod info;
+pub mod inspect;
+#[cfg(feature = "server")]
+mod interceptor;
+#[cfg(feature = "registry")]
+mod log;
+#[cfg(feature = "client")]
+mod loopback;
+#[cfg(feature = "server")]
+mod lump_lock;
+#[cfg(feature = "client")]
+mod metrics;
+#[cfg(feature = "server")]
+mod observer;
 mod protobuf;
+#[cfg(feature = "registry")]
+mod provision;
+#[cfg(feature = "registry")]
 mod registry;
-mod rpc;
+#[cfg(any(feature = "client", feature = "server"))]
+mod replay;
+#[cfg(feature = "client")]
+mod replica;
+#[cfg(feature = "client")]
+mod resolver;
+#[cfg(feature = "client")]
+mod retry;
+#[cfg(feature = "client")]
+mod router;
+pub mod rpc;
+#[cfg(feature = "server")]
 mod server;
+#[cfg(feature = "server")]
+mod server_metrics;
+#[cfg(any(feature = "client", feature = "server"))]
+mod signing;
+#[cfg(all(feature = "tracing", any(feature = "client", feature = "server")))]
+mod span;
+#[cfg(feature = "server")]
+mod stats;
+#[cfg(all(feature = "client", feature = "server"))]
+pub mod testing;
@@ -0,0 +1,52 @@
+//! ログ出力レベルを実行時に変更するための補助コンポーネント群.
+use slog::{Drain, Level, Logger, Never, OwnedKVList, Record};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// 共有可能なログ出力レベル.
+#[derive(Debug, Clone)]
+pub struct LogLevel(Arc<AtomicUsize>);
+impl LogLevel {
+    pub fn new(level: Level) -> Self {
+        LogLevel(Arc::new(AtomicUsize::new(level.as_usize())))
+    }
+
+    pub fn get(&self) -> Level {
+        Level::from_usize(self.0.load(Ordering::SeqCst)).expect("Never fails")
+    }
+
+    pub fn set(&self, level: Level) {
+        self.0.store(level.as_usize(), Ordering::SeqCst);
+    }
+}
+
+/// `LogLevel`に従って、ログレコードをフィルタリングする`Drain`の実装.
+///
+/// フィルタリングを通過したレコードは、内部のロガーにそのまま渡される.
+/// そのため、実際に出力されるレベルは、内部のロガー側の設定との兼ね合いで決まる.
+#[derive(Debug)]
+pub struct LevelFilter {
+    inner: Logger,
+    level: LogLevel,
+}
+impl LevelFilter {
+    pub fn new(inner: Logger, level: LogLevel) -> Self {
+        LevelFilter { inner, level }
+    }
+}
+impl Drain for LevelFilter {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        if record.level().is_at_least(self.level.get()) {
+            Drain::log(&self.inner, record, values)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_enabled(&self, level: Level) -> bool {
+        level.is_at_least(self.level.get()) && self.inner.is_enabled(level)
+    }
+}
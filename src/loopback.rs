@@ -0,0 +1,154 @@
+//! 同一プロセス内のサーバに対して、シリアライズやTCP通信を経由せずにリクエストを発行するためのループバック.
+#[cfg(feature = "server")]
+use bytecodec::marker::Never;
+use cannyls::lump::{LumpData, LumpHeader, LumpId};
+use cannyls::storage::StorageUsage;
+use cannyls::{Error, Result};
+use fibers_rpc::Call;
+#[cfg(feature = "server")]
+use futures::future;
+use futures::{Future, Poll};
+use std::fmt;
+
+use crate::rpc;
+#[cfg(feature = "server")]
+use crate::server::{ErrorVerbosity, Server};
+
+/// ループバックで処理されたリクエストの結果を表す`Future`.
+pub struct LocalResponse<T>(Box<dyn Future<Item = T, Error = Error> + Send + 'static>);
+impl<T: Send + 'static> LocalResponse<T> {
+    // サーバのハンドラの処理結果から、`LocalResponse`を生成する.
+    #[cfg(feature = "server")]
+    fn new<F>(verbosity: ErrorVerbosity, future: Result<F>) -> Self
+    where
+        F: Future<Item = Result<T>, Error = Never> + Send + 'static,
+    {
+        match future {
+            Err(e) => LocalResponse(Box::new(future::result(verbosity.apply(Err(track!(e)))))),
+            Ok(future) => LocalResponse(Box::new(
+                future.then(|result| result.unwrap_or_else(|_| unreachable!())),
+            )),
+        }
+    }
+}
+impl<T> Future for LocalResponse<T> {
+    type Item = T;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        track!(self.0.poll())
+    }
+}
+impl<T> fmt::Debug for LocalResponse<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LocalResponse(_)")
+    }
+}
+
+/// ループバックでの処理に対応しているかどうかを、RPC毎に定義するためのトレイト.
+///
+/// デフォルト実装は、リクエストをそのまま返す(i.e., 通常通りにサーバにRPCを発行する).
+pub trait LocalCall<T>: Call<Res = Result<T>> {
+    /// 指定のサーバのハンドラを、リクエストに対して直接呼び出す.
+    ///
+    /// ループバックでは処理できないリクエストの場合には、`Err`でリクエストを返す.
+    #[cfg(feature = "server")]
+    fn call_local(
+        server: &Server,
+        request: Self::Req,
+    ) -> std::result::Result<LocalResponse<T>, Self::Req> {
+        let _ = server;
+        Err(request)
+    }
+}
+
+macro_rules! impl_remote_only {
+    ($($rpc:ty),*) => {
+        $(impl<T> LocalCall<T> for $rpc where $rpc: Call<Res = Result<T>> {})*
+    };
+}
+impl_remote_only!(
+    rpc::ServerInfoRpc,
+    rpc::GetLumpRangeRpc,
+    rpc::GetLumpWithChecksumRpc,
+    rpc::GetLumpWithMetaRpc,
+    rpc::GetLumpsRpc,
+    rpc::HeadLumpWithMetaRpc,
+    rpc::ExistsLumpRpc,
+    rpc::PutLumpWithMetaRpc,
+    rpc::PutLumpV2Rpc,
+    rpc::PutLumpFromReaderRpc,
+    rpc::PutLumpsRpc,
+    rpc::DeleteLumpWithMetaRpc,
+    rpc::DeleteLumpV2Rpc,
+    rpc::DeleteRangeBoundedRpc,
+    rpc::ScrubRangeRpc,
+    rpc::CopyRangeRpc,
+    rpc::ExportLumpsRpc,
+    rpc::ListLumpsChunkRpc,
+    rpc::ScriptRpc,
+    rpc::ConcurrentScriptRpc,
+    rpc::OpenImportSessionRpc,
+    rpc::ImportLumpsRpc,
+    rpc::ImportSessionStatusRpc,
+    rpc::CommitImportSessionRpc,
+    rpc::JournalUsageRpc,
+    rpc::StorageHeaderRpc,
+    rpc::MetricsSnapshotRpc,
+    rpc::SetJournalSyncRpc,
+    rpc::SetQueueLimitsRpc,
+    rpc::SetWriteWatermarkRpc,
+    rpc::SetLogLevelRpc,
+    rpc::ListInFlightRpc,
+    rpc::CancelInFlightRpc,
+    rpc::DeviceStatusRpc,
+    rpc::DeviceLabelsRpc,
+    rpc::ListDevicesRpc,
+    rpc::ReadinessRpc,
+    rpc::RequestStatsRpc,
+    rpc::ResetRequestStatsRpc,
+    rpc::ProvisionDeviceRpc,
+    rpc::DeleteDeviceRpc,
+    rpc::StopDeviceRpc,
+    rpc::GetLumpToWriterRpc
+);
+
+// サーバ側の処理を行うメソッドを呼び出すことで、`LocalCall`を実装する.
+//
+// サーバにRPCが登録されていない場合(e.g., `Server::register_read_only`で除外された)には、
+// ループバックでは処理せずに、通常通りにサーバに発行する.
+macro_rules! impl_local_call {
+    ($rpc:ty, $item:ty, |$server:ident, $request:ident| $call:expr) => {
+        impl LocalCall<$item> for $rpc {
+            #[cfg(feature = "server")]
+            fn call_local(
+                $server: &Server,
+                $request: <Self as Call>::Req,
+            ) -> std::result::Result<LocalResponse<$item>, <Self as Call>::Req> {
+                if !$server.is_registered(<Self as Call>::ID) {
+                    return Err($request);
+                }
+                let verbosity = $server.error_verbosity_for(&$request.options);
+                Ok(LocalResponse::new(verbosity, $call))
+            }
+        }
+    };
+}
+impl_local_call!(rpc::GetLumpRpc, Option<LumpData>, |server, request| server
+    .get_lump(request));
+impl_local_call!(rpc::HeadLumpRpc, Option<LumpHeader>, |server, request| {
+    server.head_lump(request)
+});
+impl_local_call!(rpc::PutLumpRpc, bool, |server, request| server
+    .put_lump(rpc::PutLumpRpc::NAME, request));
+impl_local_call!(rpc::DeleteLumpRpc, bool, |server, request| server
+    .delete_lump(rpc::DeleteLumpRpc::NAME, request));
+impl_local_call!(rpc::ListLumpRpc, Vec<LumpId>, |server, request| server
+    .list_lumps(request));
+impl_local_call!(rpc::ListLumpRangeRpc, Vec<LumpId>, |server, request| {
+    server.list_lumps_range(request)
+});
+impl_local_call!(rpc::UsageRangeRpc, StorageUsage, |server, request| server
+    .usage_range(request));
+impl_local_call!(rpc::DeleteRangeRpc, Vec<LumpId>, |server, request| server
+    .delete_range(request));
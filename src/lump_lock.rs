@@ -0,0 +1,199 @@
+//! サーバ側での、lump単位の更新の直列化.
+use cannyls::lump::LumpId;
+use cannyls::{Error, ErrorKind};
+use fibers::sync::oneshot;
+use futures::future::{self, Either, Loop};
+use futures::Future;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use trackable::error::ErrorKindExt;
+
+use crate::device::DeviceId;
+
+type LumpKey = (DeviceId, LumpId);
+type Waiters = VecDeque<oneshot::Sender<LumpLockGuard>>;
+
+/// lump毎のロック群.
+///
+/// 事前条件の評価とそれに続く更新との間に、同じlumpに対する他の更新が割り込まないようにするために使用される.
+/// そのため、サーバ上の更新系の操作(範囲削除や一括保存、スクリプトを含む)は、全て対象lumpのロックを保持した状態で行われる.
+///
+/// インスタンスをクローンした場合には、同じロック群が共有される.
+#[derive(Debug, Clone, Default)]
+pub struct LumpLocks(Arc<Mutex<HashMap<LumpKey, Waiters>>>);
+impl LumpLocks {
+    /// 指定lumpのロックを獲得する.
+    ///
+    /// 既に獲得されている場合には、解放されるまで待機する(待機中の獲得要求は、要求された順に処理される).
+    /// ロックは、返された`LumpLockGuard`がドロップされた時点で解放される.
+    pub fn acquire(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+    ) -> impl Future<Item = LumpLockGuard, Error = Error> + Send {
+        let key = (device_id, lump_id);
+        let mut locks = self.lock();
+        if let Some(waiters) = locks.get_mut(&key) {
+            let (tx, rx) = oneshot::channel();
+            waiters.push_back(tx);
+            Either::A(rx.map_err(|_| {
+                track!(Error::from(
+                    ErrorKind::Other.cause("The lump lock was abandoned")
+                ))
+            }))
+        } else {
+            locks.insert(key.clone(), Waiters::new());
+            Either::B(future::ok(LumpLockGuard {
+                locks: self.clone(),
+                key,
+            }))
+        }
+    }
+
+    /// 指定lump群のロックを全て獲得する.
+    ///
+    /// デッドロックを避けるために、ロックはlump IDの昇順に一つずつ獲得される(重複するIDは一つにまとめられる).
+    /// 複数のロックを同時に保持する場合には、必ずこのメソッドを使用すること.
+    pub fn acquire_all<I>(
+        &self,
+        device_id: DeviceId,
+        lump_ids: I,
+    ) -> impl Future<Item = Vec<LumpLockGuard>, Error = Error> + Send
+    where
+        I: IntoIterator<Item = LumpId>,
+    {
+        let mut lump_ids = lump_ids.into_iter().collect::<Vec<_>>();
+        lump_ids.sort();
+        lump_ids.dedup();
+        lump_ids.reverse(); // `pop`で昇順に取り出せるようにする
+        let locks = self.clone();
+        let guards = Vec::with_capacity(lump_ids.len());
+        future::loop_fn((lump_ids, guards), move |(mut lump_ids, mut guards)| {
+            if let Some(lump_id) = lump_ids.pop() {
+                Either::A(locks.acquire(device_id.clone(), lump_id).map(move |guard| {
+                    guards.push(guard);
+                    Loop::Continue((lump_ids, guards))
+                }))
+            } else {
+                Either::B(future::ok(Loop::Break(guards)))
+            }
+        })
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    // 保持中に更新が中断されることはない(i.e., 常に整合した状態にある)ので、毒状態は無視する.
+    fn lock(&self) -> MutexGuard<'_, HashMap<LumpKey, Waiters>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// 獲得済みのlumpのロックを表すガード.
+///
+/// ドロップ時に、待機中の獲得要求があればその先頭にロックが引き渡される.
+#[derive(Debug)]
+pub struct LumpLockGuard {
+    locks: LumpLocks,
+    key: LumpKey,
+}
+impl Drop for LumpLockGuard {
+    fn drop(&mut self) {
+        let next = {
+            let mut locks = self.locks.lock();
+            let next = locks.get_mut(&self.key).and_then(|w| w.pop_front());
+            if next.is_none() {
+                locks.remove(&self.key);
+            }
+            next
+        };
+        if let Some(tx) = next {
+            // 待機側が既にキャンセルされている場合には、返送された(送信に失敗した)ガードの
+            // ドロップによって、次の待機者にロックが引き渡される
+            let guard = LumpLockGuard {
+                locks: self.locks.clone(),
+                key: self.key.clone(),
+            };
+            let _ = tx.send(guard);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Async;
+
+    use super::*;
+
+    fn poll_guard<F>(future: &mut F) -> Option<LumpLockGuard>
+    where
+        F: Future<Item = LumpLockGuard, Error = Error>,
+    {
+        match track_try_unwrap!(future.poll()) {
+            Async::Ready(guard) => Some(guard),
+            Async::NotReady => None,
+        }
+    }
+
+    #[test]
+    fn lump_lock_works() {
+        let locks = LumpLocks::default();
+        let device_id = || DeviceId::new("foo");
+        let lump_id = LumpId::new(1);
+
+        let first = poll_guard(&mut locks.acquire(device_id(), lump_id)).unwrap();
+
+        // 別のlumpのロックは独立して獲得できる
+        let other = poll_guard(&mut locks.acquire(device_id(), LumpId::new(2))).unwrap();
+
+        let mut second = locks.acquire(device_id(), lump_id);
+        let cancelled = locks.acquire(device_id(), lump_id);
+        let mut third = locks.acquire(device_id(), lump_id);
+        assert!(poll_guard(&mut second).is_none());
+        assert!(poll_guard(&mut third).is_none());
+
+        // 解放されると、待機中の先頭に引き渡される
+        drop(first);
+        let second = poll_guard(&mut second).unwrap();
+        assert!(poll_guard(&mut third).is_none());
+
+        // キャンセルされた待機者は飛ばされる
+        drop(cancelled);
+        drop(second);
+        let third = poll_guard(&mut third).unwrap();
+
+        drop(third);
+        drop(other);
+        assert_eq!(locks.len(), 0);
+    }
+
+    #[test]
+    fn acquire_all_works() {
+        let locks = LumpLocks::default();
+        let device_id = || DeviceId::new("foo");
+        let ids = |ids: &[u128]| ids.iter().map(|&id| LumpId::new(id)).collect::<Vec<_>>();
+
+        let held = poll_guard(&mut locks.acquire(device_id(), LumpId::new(2))).unwrap();
+
+        // 獲得済みのロックを含む場合には、それが解放されるまで待機する
+        let mut all = locks.acquire_all(device_id(), ids(&[3, 1, 2, 1]));
+        assert!(track_try_unwrap!(all.poll()).is_not_ready());
+
+        // 待機中のものより大きいIDのロックは、まだ獲得されていない
+        let other = poll_guard(&mut locks.acquire(device_id(), LumpId::new(3))).unwrap();
+        drop(other);
+
+        drop(held);
+        let guards = match track_try_unwrap!(all.poll()) {
+            Async::Ready(guards) => guards,
+            Async::NotReady => panic!(),
+        };
+        assert_eq!(guards.len(), 3); // 重複は一つにまとめられる
+        assert_eq!(locks.len(), 3);
+
+        drop(guards);
+        assert_eq!(locks.len(), 0);
+    }
+}
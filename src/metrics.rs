@@ -0,0 +1,384 @@
+//! クライアント側で集計されるRPC単位のメトリクス.
+use cannyls::{ErrorKind, Result};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::ClientError;
+
+/// レイテンシのヒストグラムの各バケットの上限値.
+///
+/// 最後のバケットよりも大きい値は、上限のないバケットに数えられる.
+pub const LATENCY_BUCKETS: [Duration; 14] = [
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_millis(2),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(20),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(200),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+];
+
+/// RPCおよびサーバ単位の、クライアント側のメトリクスの集計器.
+///
+/// `Client::metrics`で取得でき、そのクライアント(およびそのクローン)から発行されたリクエストの結果が記録される.
+/// `Client::set_metrics`を使えば、複数のクライアント(e.g., `Router`の配置先群)で集計器を共有することも可能.
+///
+/// インスタンスをクローンした場合には、同じ集計結果が共有される.
+#[derive(Debug, Clone, Default)]
+pub struct ClientMetrics(Arc<Mutex<HashMap<(&'static str, SocketAddr), Entry>>>);
+impl ClientMetrics {
+    /// 新しい`ClientMetrics`インスタンスを生成する.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// メトリクスを、RPC名およびサーバのアドレスの昇順で返す.
+    pub fn list(&self) -> Vec<ProcedureMetrics> {
+        let entries = lock(&self.0);
+        let mut metrics = entries
+            .iter()
+            .map(|(&(procedure, server), e)| e.to_metrics(procedure, server))
+            .collect::<Vec<_>>();
+        sort(&mut metrics);
+        metrics
+    }
+
+    /// 指定のRPCおよびサーバのメトリクスを返す.
+    ///
+    /// 該当するリクエストが一度も発行されていない場合には`None`が返される.
+    pub fn get(&self, procedure: &str, server: SocketAddr) -> Option<ProcedureMetrics> {
+        lock(&self.0)
+            .iter()
+            .find(|(&(p, s), _)| p == procedure && s == server)
+            .map(|(&(procedure, server), e)| e.to_metrics(procedure, server))
+    }
+
+    /// メトリクスをリセットする.
+    ///
+    /// 結果としては、リセット直前のメトリクスが`list`と同じ形式で返される.
+    pub fn reset(&self) -> Vec<ProcedureMetrics> {
+        let mut metrics = lock(&self.0)
+            .drain()
+            .map(|((procedure, server), e)| e.to_metrics(procedure, server))
+            .collect::<Vec<_>>();
+        sort(&mut metrics);
+        metrics
+    }
+
+    /// 指定のRPCおよびサーバに対するリクエストの結果を記録するためのレコーダを返す.
+    ///
+    /// レイテンシは、このメソッドの呼び出し時点から計測される.
+    pub(crate) fn recorder(&self, procedure: &'static str, server: SocketAddr) -> MetricsRecorder {
+        MetricsRecorder {
+            metrics: self.clone(),
+            procedure,
+            server,
+            start_time: Instant::now(),
+        }
+    }
+
+    fn record<T>(
+        &self,
+        procedure: &'static str,
+        server: SocketAddr,
+        latency: Duration,
+        result: &Result<T>,
+    ) {
+        let mut entries = lock(&self.0);
+        let entry = entries
+            .entry((procedure, server))
+            .or_insert_with(Entry::new);
+        entry.latency.observe(latency);
+        match result {
+            Ok(_) => entry.succeeded += 1,
+            Err(e) => {
+                if let Some(f) = entry.failed.iter_mut().find(|(k, _)| k == e.kind()) {
+                    f.1 += 1;
+                } else {
+                    entry.failed.push((*e.kind(), 1));
+                }
+                if ClientError::from(e.clone()).is_transport() {
+                    entry.transport_failed += 1;
+                }
+            }
+        }
+    }
+}
+
+/// 単一のリクエストの結果を記録するためのレコーダ.
+#[derive(Debug)]
+pub(crate) struct MetricsRecorder {
+    metrics: ClientMetrics,
+    procedure: &'static str,
+    server: SocketAddr,
+    start_time: Instant,
+}
+impl MetricsRecorder {
+    /// リクエストの結果を記録する.
+    pub fn record<T>(self, result: &Result<T>) {
+        let latency = self.start_time.elapsed();
+        self.metrics
+            .record(self.procedure, self.server, latency, result);
+    }
+}
+
+/// RPCおよびサーバ単位の、クライアント側のメトリクス.
+///
+/// 値は集計の開始(ないし最後にリセットされて以降)の累積値となる.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcedureMetrics {
+    /// RPCの名前(e.g., `cannyls.lump.get`).
+    pub procedure: String,
+
+    /// リクエストの発行先のサーバのアドレス.
+    pub server: SocketAddr,
+
+    /// 成功したリクエストの数.
+    pub succeeded: u64,
+
+    /// 失敗したリクエストの数を、エラーの種類毎に保持したもの.
+    ///
+    /// 一度も発生していない種類のエラーは含まれない.
+    pub failed: Vec<(ErrorKind, u64)>,
+
+    /// 失敗したリクエストの内、通信層のエラー(`ClientError::is_transport`を参照)によるものの数.
+    ///
+    /// 通信層のエラーは`ErrorKind::Other`(ないし`InvalidInput`)として`failed`にも数えられる.
+    pub transport_failed: u64,
+
+    /// リクエストの発行から、結果が得られるまでのレイテンシの分布.
+    pub latency: LatencyHistogram,
+
+    /// 集計期間(i.e., 集計の開始ないしリセットからの経過時間).
+    pub period: Duration,
+}
+impl ProcedureMetrics {
+    /// 失敗したリクエストの数の合計を返す.
+    pub fn failed_total(&self) -> u64 {
+        self.failed.iter().map(|&(_, n)| n).sum()
+    }
+
+    /// リクエストの数の合計を返す.
+    pub fn total(&self) -> u64 {
+        self.succeeded + self.failed_total()
+    }
+}
+
+/// レイテンシのヒストグラム.
+///
+/// バケットの上限値は`LATENCY_BUCKETS`で固定されている.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>, // 末尾は上限のないバケット
+    sum: Duration,
+    max: Duration,
+}
+impl LatencyHistogram {
+    fn new() -> Self {
+        LatencyHistogram {
+            counts: vec![0; LATENCY_BUCKETS.len() + 1],
+            sum: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+        }
+    }
+
+    fn observe(&mut self, latency: Duration) {
+        let i = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| latency <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.counts[i] += 1;
+        self.sum += latency;
+        self.max = self.max.max(latency);
+    }
+
+    /// 各バケットの上限値と、そのバケットに含まれる観測値の数を返す.
+    ///
+    /// 結果は上限値の昇順で、値は累積ではない.
+    /// 最後の要素は上限のないバケットで、その上限値は`None`となる.
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        LATENCY_BUCKETS
+            .iter()
+            .map(|&bound| Some(bound))
+            .chain(Some(None))
+            .zip(self.counts.iter().cloned())
+            .collect()
+    }
+
+    /// 観測値の数を返す.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// 観測値の合計を返す.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// 観測値の最大値を返す.
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// 観測値の平均を返す.
+    ///
+    /// 観測値が存在しない場合には`None`が返される.
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            None
+        } else {
+            Some(Duration::from_nanos(
+                (self.sum.as_nanos() / u128::from(count)) as u64,
+            ))
+        }
+    }
+
+    /// 指定の分位点(e.g., 99パーセンタイルなら`0.99`)の近似値を返す.
+    ///
+    /// 結果は、その分位点を含むバケットの上限値(ただし観測値の最大値を超えることはない)となる.
+    /// 観測値が存在しない場合には`None`が返される.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((count as f64 * q.clamp(0.0, 1.0)).ceil() as u64).max(1);
+        let mut cumulative = 0;
+        for (bound, n) in self.buckets() {
+            cumulative += n;
+            if cumulative >= rank {
+                return Some(bound.map_or(self.max, |b| b.min(self.max)));
+            }
+        }
+        Some(self.max)
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    succeeded: u64,
+    failed: Vec<(ErrorKind, u64)>,
+    transport_failed: u64,
+    latency: LatencyHistogram,
+    start_time: Instant,
+}
+impl Entry {
+    fn new() -> Self {
+        Entry {
+            succeeded: 0,
+            failed: Vec::new(),
+            transport_failed: 0,
+            latency: LatencyHistogram::new(),
+            start_time: Instant::now(),
+        }
+    }
+
+    fn to_metrics(&self, procedure: &str, server: SocketAddr) -> ProcedureMetrics {
+        ProcedureMetrics {
+            procedure: procedure.to_owned(),
+            server,
+            succeeded: self.succeeded,
+            failed: self.failed.clone(),
+            transport_failed: self.transport_failed,
+            latency: self.latency.clone(),
+            period: self.start_time.elapsed(),
+        }
+    }
+}
+
+fn sort(metrics: &mut [ProcedureMetrics]) {
+    metrics.sort_by(|a, b| (&a.procedure, a.server).cmp(&(&b.procedure, b.server)));
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // 値の更新中にパニックすることはないので、ポイズンは無視して構わない
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use trackable::error::ErrorKindExt;
+
+    use super::*;
+
+    #[test]
+    fn client_metrics_works() {
+        let metrics = ClientMetrics::new();
+        let a: SocketAddr = "127.0.0.1:1919".parse().unwrap();
+        let b: SocketAddr = "127.0.0.2:1919".parse().unwrap();
+        let ms = Duration::from_millis;
+
+        metrics.record("foo", a, ms(3), &Ok(()));
+        metrics.record("foo", a, ms(30), &Ok(()));
+        metrics.record::<()>("foo", a, ms(1), &Err(ErrorKind::DeviceBusy.into()));
+        metrics.record::<()>("bar", b, ms(1), &Err(ErrorKind::InvalidInput.into()));
+        metrics.recorder("foo", b).record(&Ok(()));
+
+        let list = metrics.list();
+        assert_eq!(list.len(), 3);
+        assert_eq!((list[0].procedure.as_str(), list[0].server), ("bar", b));
+        assert_eq!(list[0].failed, vec![(ErrorKind::InvalidInput, 1)]);
+        assert_eq!(list[0].transport_failed, 0);
+        assert_eq!((list[1].procedure.as_str(), list[1].server), ("foo", a));
+        assert_eq!(list[1].succeeded, 2);
+        assert_eq!(list[1].failed_total(), 1);
+        assert_eq!(list[1].total(), 3);
+        assert_eq!(list[1].latency.count(), 3);
+        assert_eq!(list[1].latency.sum(), ms(34));
+        assert_eq!(list[1].latency.max(), ms(30));
+        assert_eq!((list[2].procedure.as_str(), list[2].server), ("foo", b));
+
+        assert_eq!(metrics.get("foo", a).map(|m| m.succeeded), Some(2));
+        assert!(metrics.get("bar", a).is_none());
+
+        // 通信層のエラー
+        let e = fibers_rpc::Error::from(fibers_rpc::ErrorKind::Timeout.error());
+        let e = crate::error::from_rpc_error(e, a);
+        metrics.record::<()>("bar", b, ms(1), &Err(e));
+        let m = metrics.get("bar", b).unwrap();
+        assert_eq!(m.failed_total(), 2);
+        assert_eq!(m.transport_failed, 1);
+
+        assert_eq!(metrics.reset().len(), 3);
+        assert!(metrics.list().is_empty());
+
+        // クローン間で共有される
+        metrics.clone().record("foo", a, ms(1), &Ok(()));
+        assert_eq!(metrics.list().len(), 1);
+    }
+
+    #[test]
+    fn latency_histogram_works() {
+        let ms = Duration::from_millis;
+        let mut h = LatencyHistogram::new();
+        assert_eq!(h.mean(), None);
+        assert_eq!(h.quantile(0.5), None);
+
+        for &latency in &[ms(1), ms(1), ms(3), ms(4), ms(15), ms(20_000)] {
+            h.observe(latency);
+        }
+        let buckets = h.buckets();
+        assert_eq!(buckets.len(), LATENCY_BUCKETS.len() + 1);
+        assert_eq!(buckets[1], (Some(ms(1)), 2));
+        assert_eq!(buckets[3], (Some(ms(5)), 2));
+        assert_eq!(buckets[5], (Some(ms(20)), 1));
+        assert_eq!(buckets[LATENCY_BUCKETS.len()], (None, 1));
+        assert_eq!(h.count(), 6);
+
+        assert_eq!(h.quantile(0.0), Some(ms(1)));
+        assert_eq!(h.quantile(0.5), Some(ms(5)));
+        assert_eq!(h.quantile(0.8), Some(ms(20)));
+        assert_eq!(h.quantile(1.0), Some(ms(20_000)));
+        assert_eq!(h.mean(), Some(ms(20_024) / 6));
+    }
+}
@@ -0,0 +1,73 @@
+//! 更新系の操作の観測者.
+use cannyls::lump::LumpId;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::device::DeviceId;
+
+/// 成功した更新系の操作の内容.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mutation {
+    /// lumpの保存.
+    Put {
+        /// 保存されたlumpのID.
+        lump_id: LumpId,
+
+        /// 保存されたデータのサイズ(バイト単位).
+        size: usize,
+
+        /// `true`の場合には新規に作成されたlump、`false`の場合には上書きされたlump.
+        created: bool,
+    },
+
+    /// lumpの削除.
+    Delete {
+        /// 削除対象のlumpのID.
+        lump_id: LumpId,
+
+        /// `false`の場合には、対象lumpは存在しなかった.
+        deleted: bool,
+    },
+
+    /// 範囲指定でのlumpの削除.
+    DeleteRange {
+        /// 削除対象の範囲.
+        range: Range<LumpId>,
+
+        /// 実際に削除されたlumpのID群.
+        deleted: Vec<LumpId>,
+    },
+}
+
+/// 更新系の操作の観測者.
+///
+/// `Server::add_mutation_observer`で登録され、PUT・DELETE・DELETE_RANGE(スクリプトRPC内のものを含む)が
+/// 成功した後に呼び出される.
+/// キャッシュの無効化や、レプリケーションの起動、変更通知等をサーバ上に構築するためのもの.
+///
+/// 観測者はRPCの処理中に同期的に呼び出されるので、重い処理は別スレッド等に委譲すべきである.
+pub trait MutationObserver: Send + Sync + 'static {
+    /// `device_id`のデバイスに対する更新系の操作が成功した際に呼び出される.
+    fn on_mutation(&self, device_id: &DeviceId, mutation: &Mutation);
+}
+
+/// サーバに登録された観測者群.
+#[derive(Clone, Default)]
+pub struct MutationObservers(Vec<Arc<dyn MutationObserver>>);
+impl MutationObservers {
+    pub fn add<O: MutationObserver>(&mut self, observer: O) {
+        self.0.push(Arc::new(observer));
+    }
+
+    pub fn notify(&self, device_id: &DeviceId, mutation: &Mutation) {
+        for observer in &self.0 {
+            observer.on_mutation(device_id, mutation);
+        }
+    }
+}
+impl fmt::Debug for MutationObservers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MutationObservers {{ len: {} }}", self.0.len())
+    }
+}
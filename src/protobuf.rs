@@ -3,39 +3,62 @@
 //! メッセージ定義は[cannyls_rpc.proto]を参照のこと.
 //!
 //! [cannyls_rpc.proto]: https://github.com/frugalos/cannyls_rpc/blob/master/protobuf/cannyls_rpc.proto
-#![cfg_attr(feature = "cargo-clippy", allow(clippy::type_complexity))]
+#![allow(clippy::type_complexity)]
 use ::trackable::error::{ErrorKindExt, TrackableError};
+use ::trackable::{Location, Trackable};
 use bytecodec::bytes::BytesDecoder as BytecodecBytesDecoder;
 use bytecodec::combinator::{Peekable, PreEncode};
 use bytecodec::{self, ByteCount, Decode, Encode, Eos, ErrorKind, Result, SizedEncode};
 use cannyls::block::BlockSize;
 use cannyls::deadline::Deadline;
-use cannyls::device::DeviceHandle;
+use cannyls::device::{DeviceHandle, DeviceStatus};
 use cannyls::lump::{LumpData, LumpHeader, LumpId};
-use cannyls::storage::StorageUsage;
+use cannyls::storage::{StorageHeader, StorageUsage};
+#[cfg(feature = "server")]
 use factory::Factory;
-use protobuf_codec::field::branch::Branch2;
-use protobuf_codec::field::num::{F1, F2, F3, F4};
+use fibers_rpc::ProcedureId;
+use protobuf_codec::field::branch::{Branch2, Branch4, Branch5};
+use protobuf_codec::field::num::{F1, F2, F3, F4, F5, F6, F7, F8};
 use protobuf_codec::field::{
     FieldDecode, FieldDecoder, FieldEncoder, Fields, MaybeDefault, MessageFieldDecoder,
     MessageFieldEncoder, Oneof, Optional, Repeated,
 };
 use protobuf_codec::message::{MessageDecode, MessageDecoder, MessageEncode, MessageEncoder};
 use protobuf_codec::scalar::{
-    BoolDecoder, BoolEncoder, BytesDecoder, BytesEncoder, CustomBytesDecoder, Fixed64Decoder,
-    Fixed64Encoder, StringDecoder, StringEncoder, Uint32Decoder, Uint32Encoder, Uint64Decoder,
-    Uint64Encoder,
+    BoolDecoder, BoolEncoder, BytesDecoder, BytesEncoder, CustomBytesDecoder, CustomBytesEncoder,
+    Fixed32Decoder, Fixed32Encoder, Fixed64Decoder, Fixed64Encoder, StringDecoder, StringEncoder,
+    Uint32Decoder, Uint32Encoder, Uint64Decoder, Uint64Encoder,
+};
+use protobuf_codec::wellknown::google::protobuf::{
+    EmptyMessageDecoder, EmptyMessageEncoder, StdDurationDecoder, StdDurationEncoder,
 };
-use protobuf_codec::wellknown::google::protobuf::{StdDurationDecoder, StdDurationEncoder};
 use protobuf_codec::wellknown::protobuf_codec::protobuf::trackable;
 use protobuf_codec::wire::Tag;
+use slog::Level;
+use std::fmt;
+use std::io::{self, Read, Write};
 use std::ops::Range;
+use std::path::PathBuf;
 use std::str::FromStr;
+use uuid::Uuid;
 
+use crate::info::{
+    DeviceMetricsSnapshot, DeviceReadiness, DeviceStatusReport, DeviceSummary, ImportSessionStatus,
+    InFlightRequest, JournalRecordCounts, JournalUsage, RequestStats, RequestTarget, ServerInfo,
+    StorageMetricsSnapshot,
+};
 use crate::rpc::{
-    DeviceRequest, LumpRequest, PutLumpRequest, RangeLumpRequest, RequestOptions, UsageRangeRequest,
+    error_with_history, BusyHint, CopyRangeRequest, DeleteRangeBoundedRequest, DeleteRangeChunk,
+    DeviceRequest, ExportLumpsChunk, ExportLumpsRequest, GetLumpRangeRequest, GetLumpsRequest,
+    ImportLumpsRequest, ImportSessionRequest, ListLumpsChunk, LumpRequest, Precondition,
+    ProvisionDeviceRequest, PutLumpFromReaderRequest, PutLumpRequest, PutLumpsRequest,
+    RangeLumpRequest, RemoteCause, RequestOptions, RequestSignature, ResponseMeta, ScriptOp,
+    ScriptOpResult, ScriptRequest, SetJournalSyncRequest, SetQueueLimitsRequest,
+    SetWriteWatermarkRequest, UsageRangeRequest,
 };
-use crate::{DeviceId, DeviceRegistryHandle};
+#[cfg(feature = "server")]
+use crate::DeviceRegistryHandle;
+use crate::{DeviceId, DeviceLabels, DeviceNvmSpec, DeviceSettings, DeviceSpec};
 
 macro_rules! impl_message_decode {
     ($decoder:ty, $item:ty, $map:expr) => {
@@ -207,6 +230,10 @@ pub struct RequestOptionsDecoder {
             MaybeDefault<MessageFieldDecoder<F1, DeadlineDecoder>>,
             MaybeDefault<FieldDecoder<F2, Uint32Decoder>>,
             MaybeDefault<FieldDecoder<F3, BoolDecoder>>,
+            MaybeDefault<FieldDecoder<F4, BoolDecoder>>,
+            MaybeDefault<FieldDecoder<F5, BoolDecoder>>,
+            MaybeDefault<FieldDecoder<F6, StringDecoder>>,
+            Optional<MessageFieldDecoder<F7, RequestSignatureDecoder>>,
         )>,
     >,
 }
@@ -214,16 +241,32 @@ impl_message_decode!(RequestOptionsDecoder, RequestOptions, |(
     deadline,
     queue_size_limit,
     prioritized,
+    verbose_errors,
+    response_meta,
+    trace_id,
+    signature,
+): (
+    Deadline,
+    u32,
+    bool,
+    bool,
+    bool,
+    String,
+    Option<RequestSignature>
 )| {
-    let max_queue_len = if queue_size_limit == 0 {
-        None
-    } else {
-        Some(queue_size_limit as usize - 1)
-    };
     Ok(RequestOptions {
         deadline,
-        max_queue_len,
+        max_queue_len: decode_queue_len(queue_size_limit),
         prioritized,
+        journal_sync: false,
+        verbose_errors,
+        response_meta,
+        trace_id: if trace_id.is_empty() {
+            None
+        } else {
+            Some(trace_id)
+        },
+        signature,
     })
 });
 
@@ -234,14 +277,139 @@ pub struct RequestOptionsEncoder {
             MessageFieldEncoder<F1, DeadlineEncoder>,
             MaybeDefault<FieldEncoder<F2, Uint32Encoder>>,
             MaybeDefault<FieldEncoder<F3, BoolEncoder>>,
+            MaybeDefault<FieldEncoder<F4, BoolEncoder>>,
+            MaybeDefault<FieldEncoder<F5, BoolEncoder>>,
+            MaybeDefault<FieldEncoder<F6, StringEncoder>>,
+            Optional<MessageFieldEncoder<F7, RequestSignatureEncoder>>,
         )>,
     >,
 }
 impl_sized_message_encode!(RequestOptionsEncoder, RequestOptions, |item: Self::Item| {
-    let queue_size_limit = item.max_queue_len.map_or(0, |n| n + 1);
-    (item.deadline, queue_size_limit as u32, item.prioritized)
+    (
+        item.deadline,
+        encode_queue_len(item.max_queue_len),
+        item.prioritized,
+        item.verbose_errors,
+        item.response_meta,
+        item.trace_id.unwrap_or_default(),
+        item.signature,
+    )
 });
 
+#[derive(Debug, Default)]
+pub struct RequestSignatureDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F4, BytesDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(RequestSignatureDecoder, RequestSignature, |(
+    key_id,
+    timestamp,
+    nonce,
+    mac,
+)| Ok(
+    RequestSignature {
+        key_id,
+        timestamp,
+        nonce,
+        mac,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct RequestSignatureEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F2, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F4, BytesEncoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    RequestSignatureEncoder,
+    RequestSignature,
+    |item: Self::Item| (item.key_id, item.timestamp, item.nonce, item.mac)
+);
+
+#[derive(Debug, Default)]
+pub struct ResponseMetaDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<MessageFieldDecoder<F1, StdDurationDecoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F3, StringDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(ResponseMetaDecoder, ResponseMeta, |(
+    processing_time,
+    queue_len,
+    server_version,
+)| Ok(ResponseMeta {
+    processing_time,
+    queue_len,
+    server_version,
+}));
+
+#[derive(Debug, Default)]
+pub struct ResponseMetaEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MessageFieldEncoder<F1, StdDurationEncoder>,
+            MaybeDefault<FieldEncoder<F2, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F3, StringEncoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(ResponseMetaEncoder, ResponseMeta, |item: Self::Item| (
+    item.processing_time,
+    item.queue_len,
+    item.server_version
+));
+
+#[derive(Debug, Default)]
+pub struct PreconditionDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, BoolDecoder>>,
+            Optional<FieldDecoder<F2, Uint32Decoder>>,
+            MaybeDefault<FieldDecoder<F3, BoolDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(PreconditionDecoder, Precondition, |(
+    if_exists,
+    if_size_equals,
+    if_not_exists,
+)| Ok(Precondition {
+    if_exists,
+    if_size_equals,
+    if_not_exists,
+}));
+
+#[derive(Debug, Default)]
+pub struct PreconditionEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, BoolEncoder>>,
+            Optional<FieldEncoder<F2, Uint32Encoder>>,
+            MaybeDefault<FieldEncoder<F3, BoolEncoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(PreconditionEncoder, Precondition, |item: Self::Item| (
+    item.if_exists,
+    item.if_size_equals,
+    item.if_not_exists,
+));
+
 #[derive(Debug, Default)]
 pub struct LumpRequestDecoder {
     inner: MessageDecoder<
@@ -249,6 +417,7 @@ pub struct LumpRequestDecoder {
             FieldDecoder<F1, StringDecoder>,
             MessageFieldDecoder<F2, LumpIdDecoder>,
             MessageFieldDecoder<F3, RequestOptionsDecoder>,
+            Optional<MessageFieldDecoder<F4, PreconditionDecoder>>,
         )>,
     >,
 }
@@ -256,10 +425,12 @@ impl_message_decode!(LumpRequestDecoder, LumpRequest, |(
     device_id,
     lump_id,
     options,
+    precondition,
 )| Ok(LumpRequest {
     device_id: DeviceId::new(device_id),
     lump_id,
     options,
+    precondition,
 }));
 
 #[derive(Debug, Default)]
@@ -269,24 +440,29 @@ pub struct LumpRequestEncoder {
             FieldEncoder<F1, StringEncoder>,
             MessageFieldEncoder<F2, LumpIdEncoder>,
             MessageFieldEncoder<F3, RequestOptionsEncoder>,
+            Optional<MessageFieldEncoder<F4, PreconditionEncoder>>,
         )>,
     >,
 }
 impl_sized_message_encode!(LumpRequestEncoder, LumpRequest, |item: Self::Item| (
     item.device_id.into_string(),
     item.lump_id,
-    item.options
+    item.options,
+    item.precondition,
 ));
 
+#[cfg(feature = "server")]
 #[derive(Debug)]
 pub struct PutLumpRequestDecoderFactory {
     registry: DeviceRegistryHandle,
 }
+#[cfg(feature = "server")]
 impl PutLumpRequestDecoderFactory {
     pub fn new(registry: DeviceRegistryHandle) -> Self {
         PutLumpRequestDecoderFactory { registry }
     }
 }
+#[cfg(feature = "server")]
 impl Factory for PutLumpRequestDecoderFactory {
     type Item = PutLumpRequestDecoder;
 
@@ -300,9 +476,20 @@ pub struct PutLumpRequestDecoder {
     inner: MessageDecoder<PutLumpRequestFieldsDecoder>,
 }
 impl PutLumpRequestDecoder {
+    #[cfg(feature = "server")]
     fn new(registry: DeviceRegistryHandle) -> Self {
+        let lump_data = LumpDataDecoder::with_registry(registry);
+        PutLumpRequestDecoder {
+            inner: MessageDecoder::new(PutLumpRequestFieldsDecoder::new(lump_data)),
+        }
+    }
+}
+/// デバイスレジストリを参照せずにデコードを行う.
+impl Default for PutLumpRequestDecoder {
+    fn default() -> Self {
+        let lump_data = LumpDataDecoder::new();
         PutLumpRequestDecoder {
-            inner: MessageDecoder::new(PutLumpRequestFieldsDecoder::new(registry)),
+            inner: MessageDecoder::new(PutLumpRequestFieldsDecoder::new(lump_data)),
         }
     }
 }
@@ -314,18 +501,19 @@ struct PutLumpRequestFieldsDecoder {
     lump_id: MessageFieldDecoder<F2, LumpIdDecoder>,
     lump_data: FieldDecoder<F3, CustomBytesDecoder<LumpDataDecoder>>,
     options: MessageFieldDecoder<F4, RequestOptionsDecoder>,
+    precondition: Optional<MessageFieldDecoder<F5, PreconditionDecoder>>,
+    checksum: Optional<FieldDecoder<F6, Fixed32Decoder>>,
     index: usize,
 }
 impl PutLumpRequestFieldsDecoder {
-    fn new(registry: DeviceRegistryHandle) -> Self {
+    fn new(lump_data: LumpDataDecoder) -> Self {
         PutLumpRequestFieldsDecoder {
             device_id: Default::default(),
             lump_id: Default::default(),
-            lump_data: FieldDecoder::new(
-                F3,
-                CustomBytesDecoder::new(LumpDataDecoder::new(registry)),
-            ),
+            lump_data: FieldDecoder::new(F3, CustomBytesDecoder::new(lump_data)),
             options: Default::default(),
+            precondition: Default::default(),
+            checksum: Default::default(),
             index: 0,
         }
     }
@@ -338,6 +526,7 @@ impl Decode for PutLumpRequestFieldsDecoder {
             0 => Ok(0),
             1 => {
                 let size = track!(self.device_id.decode(buf, eos))?;
+                #[cfg(feature = "server")]
                 if let Some(device_id) = self.device_id.value_decoder_ref().peek() {
                     if self.device_id.is_idle() {
                         self.lump_data
@@ -351,6 +540,8 @@ impl Decode for PutLumpRequestFieldsDecoder {
             2 => track!(self.lump_id.decode(buf, eos)),
             3 => track!(self.lump_data.decode(buf, eos)),
             4 => track!(self.options.decode(buf, eos)),
+            5 => track!(self.precondition.decode(buf, eos)),
+            6 => track!(self.checksum.decode(buf, eos)),
             _ => unreachable!(),
         }
     }
@@ -361,11 +552,15 @@ impl Decode for PutLumpRequestFieldsDecoder {
         let lump_id = track!(self.lump_id.finish_decoding())?;
         let lump_data = track!(self.lump_data.finish_decoding())?;
         let options = track!(self.options.finish_decoding())?;
+        let precondition = track!(self.precondition.finish_decoding())?;
+        let checksum = track!(self.checksum.finish_decoding())?;
         Ok(PutLumpRequest {
             device_id: DeviceId::new(device_id),
             lump_id,
             lump_data,
             options,
+            precondition,
+            checksum,
         })
     }
 
@@ -376,6 +571,8 @@ impl Decode for PutLumpRequestFieldsDecoder {
             2 => self.lump_id.is_idle(),
             3 => self.lump_data.is_idle(),
             4 => self.options.is_idle(),
+            5 => self.precondition.is_idle(),
+            6 => self.checksum.is_idle(),
             _ => unreachable!(),
         }
     }
@@ -387,6 +584,8 @@ impl Decode for PutLumpRequestFieldsDecoder {
             2 => self.lump_id.requiring_bytes(),
             3 => self.lump_data.requiring_bytes(),
             4 => self.options.requiring_bytes(),
+            5 => self.precondition.requiring_bytes(),
+            6 => self.checksum.requiring_bytes(),
             _ => unreachable!(),
         }
     }
@@ -417,6 +616,18 @@ impl FieldDecode for PutLumpRequestFieldsDecoder {
             return Ok(true);
         }
 
+        let started = track!(self.precondition.start_decoding(tag))?;
+        if started {
+            self.index = 5;
+            return Ok(true);
+        }
+
+        let started = track!(self.checksum.start_decoding(tag))?;
+        if started {
+            self.index = 6;
+            return Ok(true);
+        }
+
         Ok(false)
     }
 }
@@ -429,6 +640,8 @@ pub struct PutLumpRequestEncoder {
             MessageFieldEncoder<F2, LumpIdEncoder>,
             FieldEncoder<F3, BytesEncoder<LumpData>>,
             MessageFieldEncoder<F4, RequestOptionsEncoder>,
+            Optional<MessageFieldEncoder<F5, PreconditionEncoder>>,
+            Optional<FieldEncoder<F6, Fixed32Encoder>>,
         )>,
     >,
 }
@@ -437,37 +650,167 @@ impl_sized_message_encode!(PutLumpRequestEncoder, PutLumpRequest, |item: Self::I
     item.lump_id,
     item.lump_data,
     item.options,
+    item.precondition,
+    item.checksum,
 ));
 
+/// `PutLumpFromReaderRpc`用のリクエストデコーダ.
+///
+/// `PutLumpFromReaderRpc`はクライアント専用のRPCなので、このデコーダが実際に使われることはない.
+/// (指定された場合には、常にエラーを返す)
+#[derive(Debug, Default)]
+pub struct PutLumpFromReaderRequestDecoder;
+impl Decode for PutLumpFromReaderRequestDecoder {
+    type Item = PutLumpFromReaderRequest;
+
+    fn decode(&mut self, _buf: &[u8], _eos: Eos) -> Result<usize> {
+        track_panic!(
+            ErrorKind::Other,
+            "`PutLumpFromReaderRpc` is a client-only RPC"
+        )
+    }
+
+    fn finish_decoding(&mut self) -> Result<Self::Item> {
+        track_panic!(
+            ErrorKind::Other,
+            "`PutLumpFromReaderRpc` is a client-only RPC"
+        )
+    }
+
+    fn is_idle(&self) -> bool {
+        false
+    }
+
+    fn requiring_bytes(&self) -> ByteCount {
+        ByteCount::Unknown
+    }
+}
+
+/// `PutLumpFromReaderRpc`用のリクエストエンコーダ.
+///
+/// lumpデータは、エンコード時に読み込み元から必要な分だけが読み込まれる.
+#[derive(Debug, Default)]
+pub struct PutLumpFromReaderRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            FieldEncoder<F1, StringEncoder>,
+            MessageFieldEncoder<F2, LumpIdEncoder>,
+            FieldEncoder<F3, CustomBytesEncoder<LumpDataReader>>,
+            MessageFieldEncoder<F4, RequestOptionsEncoder>,
+            Optional<MessageFieldEncoder<F5, PreconditionEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    PutLumpFromReaderRequestEncoder,
+    PutLumpFromReaderRequest,
+    |item: Self::Item| (
+        item.device_id.into_string(),
+        item.lump_id,
+        (item.reader, item.data_size),
+        item.options,
+        item.precondition,
+    )
+);
+
+#[derive(Default)]
+struct LumpDataReader {
+    reader: Option<Box<dyn Read + Send>>,
+    remaining: u64,
+}
+impl Encode for LumpDataReader {
+    type Item = (Box<dyn Read + Send>, u64);
+
+    fn encode(&mut self, buf: &mut [u8], _eos: Eos) -> Result<usize> {
+        let reader = if let Some(ref mut reader) = self.reader {
+            reader
+        } else {
+            return Ok(0);
+        };
+
+        let limit = std::cmp::min(buf.len() as u64, self.remaining) as usize;
+        let size = match reader.read(&mut buf[..limit]) {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => 0,
+            Err(e) => return Err(track!(bytecodec::Error::from(e))),
+            Ok(0) if limit > 0 => {
+                track_panic!(ErrorKind::UnexpectedEos; self.remaining);
+            }
+            Ok(size) => size,
+        };
+        self.remaining -= size as u64;
+        if self.remaining == 0 {
+            self.reader = None;
+        }
+        Ok(size)
+    }
+
+    fn start_encoding(&mut self, (reader, size): Self::Item) -> Result<()> {
+        track_assert!(self.is_idle(), ErrorKind::EncoderFull);
+        if size > 0 {
+            self.reader = Some(reader);
+            self.remaining = size;
+        }
+        Ok(())
+    }
+
+    fn is_idle(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn requiring_bytes(&self) -> ByteCount {
+        ByteCount::Finite(self.remaining)
+    }
+}
+impl SizedEncode for LumpDataReader {
+    fn exact_requiring_bytes(&self) -> u64 {
+        self.remaining
+    }
+}
+impl fmt::Debug for LumpDataReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LumpDataReader")
+            .field("remaining", &self.remaining)
+            .finish()
+    }
+}
+
 #[derive(Debug)]
 struct LumpDataDecoder {
     is_first: bool,
     bytes: BytecodecBytesDecoder<LumpData>,
-    registry: DeviceRegistryHandle,
+    #[cfg(feature = "server")]
+    registry: Option<DeviceRegistryHandle>,
     device_hint: Option<DeviceHandle>,
 }
 impl LumpDataDecoder {
-    fn new(registry: DeviceRegistryHandle) -> Self {
-        let empty = LumpData::new_embedded(Vec::new()).expect("never fails");
+    fn new() -> Self {
         LumpDataDecoder {
             is_first: true,
-            bytes: BytecodecBytesDecoder::new(empty),
-            registry,
+            bytes: BytecodecBytesDecoder::new(empty_lump_data()),
+            #[cfg(feature = "server")]
+            registry: None,
             device_hint: None,
         }
     }
 
+    #[cfg(feature = "server")]
+    fn with_registry(registry: DeviceRegistryHandle) -> Self {
+        LumpDataDecoder {
+            registry: Some(registry),
+            ..Self::new()
+        }
+    }
+
     fn is_data_to_be_embedded(&self, data_size: usize) -> bool {
-        let block_size = self
-            .device_hint
-            .as_ref()
-            .and_then(|device| device.metrics().storage().map(|s| s.header().block_size))
-            .unwrap_or_else(BlockSize::min);
-        data_size <= block_size.as_u16() as usize
+        data_size <= max_embedded_data_size(self.device_hint.as_ref())
     }
 
+    #[cfg(feature = "server")]
     fn device_hint(&mut self, device_id: &str) {
-        self.device_hint = self.registry.get_device(device_id).ok();
+        self.device_hint = self
+            .registry
+            .as_ref()
+            .and_then(|registry| registry.get_device(device_id).ok());
     }
 }
 impl Decode for LumpDataDecoder {
@@ -517,27 +860,227 @@ impl Decode for LumpDataDecoder {
     }
 }
 
+fn empty_lump_data() -> LumpData {
+    LumpData::new_embedded(Vec::new()).expect("never fails")
+}
+
+/// ジャーナル領域に埋め込まれるデータの最大サイズを返す.
+///
+/// デバイスのブロックサイズが不明な場合には、最小のブロックサイズが使われる.
+pub fn max_embedded_data_size(device: Option<&DeviceHandle>) -> usize {
+    let block_size = device
+        .and_then(|device| device.metrics().storage().map(|s| s.header().block_size))
+        .unwrap_or_else(BlockSize::min);
+    block_size.as_u16() as usize
+}
+
+#[derive(Debug, Default)]
+pub struct BusyHintDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, Uint64Decoder>>,
+            MaybeDefault<MessageFieldDecoder<F2, StdDurationDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(BusyHintDecoder, BusyHint, |(queue_len, retry_after)| Ok(
+    BusyHint {
+        queue_len,
+        retry_after,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct BusyHintEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            FieldEncoder<F1, Uint64Encoder>,
+            MessageFieldEncoder<F2, StdDurationEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(BusyHintEncoder, BusyHint, |item: Self::Item| (
+    item.queue_len,
+    item.retry_after
+));
+
 #[derive(Debug, Default)]
 pub struct ErrorDecoder {
-    inner: MessageDecoder<MessageFieldDecoder<F1, trackable::ErrorDecoder>>,
+    inner: MessageDecoder<
+        Fields<(
+            MessageFieldDecoder<F1, trackable::ErrorDecoder>,
+            Optional<MessageFieldDecoder<F2, BusyHintDecoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint32Decoder>>,
+            Repeated<MessageFieldDecoder<F4, ErrorCauseDecoder>, Vec<ErrorCause>>,
+        )>,
+    >,
 }
-impl_message_decode!(ErrorDecoder, cannyls::Error, |e: TrackableError<String>| {
-    let kind = match cannyls::ErrorKind::from_str(e.kind().as_str()) {
-        Ok(kind) => kind,
-        Err(()) => cannyls::ErrorKind::Other,
-    };
-    Ok(kind.takes_over(e).into())
+impl_message_decode!(ErrorDecoder, cannyls::Error, |(
+    e,
+    busy_hint,
+    code,
+    causes,
+): (
+    TrackableError<String>,
+    Option<BusyHint>,
+    u32,
+    Vec<ErrorCause>
+)| {
+    // 数値のコードを優先し、それが未知(ないし未指定)の場合には、文字列表現で判定する
+    let kind = error_kind_from_code(code)
+        .or_else(|| cannyls::ErrorKind::from_str(e.kind().as_str()).ok())
+        .unwrap_or(cannyls::ErrorKind::Other);
+    if let Some(hint) = busy_hint.filter(|_| kind == cannyls::ErrorKind::DeviceBusy) {
+        return Ok(hint.into_error(&e));
+    }
+
+    // 原因の連鎖に対応していないサーバからのエラーの場合には、原因は単なる文字列となる
+    match RemoteCause::from_chain(causes.into_iter()) {
+        Some(cause) => Ok(error_with_history(kind, cause, &e)),
+        None => Ok(kind.takes_over(e).into()),
+    }
 });
 
+// エラーの原因の連鎖の一要素.
+//
+// 順に、種類(`trackable`のエラー以外の場合は空)、メッセージ(`trackable`のエラーの場合は空)、履歴.
+type ErrorCause = (String, String, Vec<Location>);
+
+// `protobuf_codec.protobuf.trackable.Error`形式のメッセージを`ErrorCause`としてデコードするためのもの.
+type ErrorCauseDecoder = MessageDecoder<
+    Fields<(
+        MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+        MaybeDefault<FieldDecoder<F2, StringDecoder>>,
+        Repeated<MessageFieldDecoder<F3, trackable::LocationDecoder>, Vec<Location>>,
+    )>,
+>;
+
+// `ErrorCause`を`protobuf_codec.protobuf.trackable.Error`形式のメッセージとしてエンコードするためのもの.
+type ErrorCauseEncoder = MessageEncoder<
+    Fields<(
+        MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+        MaybeDefault<FieldEncoder<F2, StringEncoder>>,
+        Repeated<MessageFieldEncoder<F3, trackable::LocationEncoder>, Vec<Location>>,
+    )>,
+>;
+
+// エラーの原因の連鎖を、直接の原因から順に`chain`に追加する.
+//
+// `trackable`のエラーについては、このクレートが扱う種類(cannyls・fibers_rpc・bytecodec)のもののみを辿り、
+// それ以外の原因は、その文字列表現を末尾の要素とする.
+fn push_cause_chain<K>(error: &TrackableError<K>, chain: &mut Vec<ErrorCause>)
+where
+    K: ::trackable::error::ErrorKind + fmt::Debug,
+{
+    fn push<K>(error: &TrackableError<K>, chain: &mut Vec<ErrorCause>)
+    where
+        K: ::trackable::error::ErrorKind + fmt::Debug,
+    {
+        let history = error
+            .history()
+            .map(|h| h.events().to_owned())
+            .unwrap_or_default();
+        chain.push((format!("{:?}", error.kind()), String::new(), history));
+        push_cause_chain(error, chain);
+    }
+
+    if let Some(cause) = error.concrete_cause::<cannyls::Error>() {
+        push(cause, chain);
+    } else if let Some(cause) = error.concrete_cause::<TrackableError<cannyls::ErrorKind>>() {
+        push(cause, chain);
+    } else if let Some(cause) = error.concrete_cause::<fibers_rpc::Error>() {
+        push(cause, chain);
+    } else if let Some(cause) = error.concrete_cause::<bytecodec::Error>() {
+        push(cause, chain);
+    } else if let Some(cause) = error.concrete_cause::<RemoteCause>() {
+        cause.to_chain(chain);
+    } else {
+        #[allow(deprecated)]
+        let cause = std::error::Error::cause(error);
+        if let Some(cause) = cause {
+            chain.push((String::new(), cause.to_string(), Vec::new()));
+        }
+    }
+}
+
+// `protobuf_codec.protobuf.trackable.Error`のエンコーダ.
+//
+// `trackable::ErrorEncoder`はエラーの原因を`Error::source`経由で取得するが、
+// `TrackableError`はそれを実装していないため、原因のメッセージが常に失われてしまう.
+// そのため、同じ形式のメッセージを、原因を含めてエンコードしている.
+//
+// `BusyHint`が付与されている場合には、それも別のフィールドとしてエンコードする.
+// また、原因の連鎖(`RemoteCause`を参照)も、各要素の種類と履歴を含めてエンコードする.
 #[derive(Debug, Default)]
 pub struct ErrorEncoder {
-    inner: MessageEncoder<MessageFieldEncoder<F1, PreEncode<trackable::ErrorEncoder>>>,
+    inner: PreEncode<
+        MessageEncoder<
+            Fields<(
+                MessageFieldEncoder<F1, PreEncode<ErrorCauseEncoder>>,
+                Optional<MessageFieldEncoder<F2, BusyHintEncoder>>,
+                MaybeDefault<FieldEncoder<F3, Uint32Encoder>>,
+                Repeated<MessageFieldEncoder<F4, PreEncode<ErrorCauseEncoder>>, Vec<ErrorCause>>,
+            )>,
+        >,
+    >,
 }
 impl_sized_message_encode!(ErrorEncoder, cannyls::Error, |item: Self::Item| {
-    let kind = item.kind().to_string();
-    kind.takes_over(item)
+    #[allow(deprecated)]
+    let cause = std::error::Error::cause(&*item)
+        .map(|c| c.to_string())
+        .unwrap_or_default();
+    let history = item
+        .history()
+        .map(|h| h.events().to_owned())
+        .unwrap_or_default();
+    let busy_hint = BusyHint::of(&item).cloned();
+    let code = error_kind_to_code(*item.kind());
+    let mut causes = Vec::new();
+    push_cause_chain(&item, &mut causes);
+    (
+        (item.kind().to_string(), cause, history),
+        busy_hint,
+        code,
+        causes,
+    )
 });
 
+// `cannyls::ErrorKind`と、`Error.code`フィールドの値との対応.
+//
+// 一度割り当てた値は変更してはいけない.
+// `0`は「未指定」を表し、コードに対応していない古いサーバからのエラーや、このクレートが未知の種類の場合に使用される.
+const ERROR_KIND_CODES: &[(cannyls::ErrorKind, u32)] = &[
+    (cannyls::ErrorKind::Other, 1),
+    (cannyls::ErrorKind::DeviceBusy, 2),
+    (cannyls::ErrorKind::DeviceTerminated, 3),
+    (cannyls::ErrorKind::StorageFull, 4),
+    (cannyls::ErrorKind::StorageCorrupted, 5),
+    (cannyls::ErrorKind::InvalidInput, 6),
+    (cannyls::ErrorKind::InconsistentState, 7),
+    (cannyls::ErrorKind::RequestDropped, 8),
+    (cannyls::ErrorKind::RequestRefused, 9),
+];
+
+fn error_kind_to_code(kind: cannyls::ErrorKind) -> u32 {
+    ERROR_KIND_CODES
+        .iter()
+        .find(|&&(k, _)| k == kind)
+        .map_or(0, |&(_, code)| code)
+}
+
+fn error_kind_from_code(code: u32) -> Option<cannyls::ErrorKind> {
+    ERROR_KIND_CODES
+        .iter()
+        .find(|&&(_, c)| c == code && code != 0)
+        .map(|&(kind, _)| kind)
+}
+
+pub type PutLumpV2ResponseDecoder = HeadLumpResponseDecoder;
+pub type PutLumpV2ResponseEncoder = HeadLumpResponseEncoder;
+
+pub type DeleteLumpV2ResponseDecoder = HeadLumpResponseDecoder;
+pub type DeleteLumpV2ResponseEncoder = HeadLumpResponseEncoder;
+
 #[derive(Debug, Default)]
 pub struct PutLumpResponseDecoder {
     inner: MessageDecoder<
@@ -566,9 +1109,60 @@ impl_sized_message_encode!(
     |item: Self::Item| result_into_branch(item)
 );
 
+#[derive(Debug, Default)]
+pub struct PutLumpWithMetaResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Optional<MessageFieldDecoder<F3, ResponseMetaDecoder>>,
+            Oneof<(
+                FieldDecoder<F1, BoolDecoder>,
+                MessageFieldDecoder<F2, ErrorDecoder>,
+            )>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    PutLumpWithMetaResponseDecoder,
+    cannyls::Result<(bool, Option<ResponseMeta>)>,
+    |(meta, item)| Ok(join_meta(branch_into_result(item), meta))
+);
+
+#[derive(Debug, Default)]
+pub struct PutLumpWithMetaResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Optional<MessageFieldEncoder<F3, ResponseMetaEncoder>>,
+            Oneof<(
+                FieldEncoder<F1, BoolEncoder>,
+                MessageFieldEncoder<F2, ErrorEncoder>,
+            )>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    PutLumpWithMetaResponseEncoder,
+    cannyls::Result<(bool, Option<ResponseMeta>)>,
+    |item: Self::Item| {
+        let (item, meta) = split_meta(item);
+        (meta, result_into_branch(item))
+    }
+);
+
+pub type DeleteLumpWithMetaResponseDecoder = PutLumpWithMetaResponseDecoder;
+pub type DeleteLumpWithMetaResponseEncoder = PutLumpWithMetaResponseEncoder;
+
 pub type DeleteLumpRequestDecoder = PutLumpResponseDecoder;
 pub type DeleteLumpRequestEncoder = PutLumpResponseEncoder;
 
+pub type ExistsLumpResponseDecoder = PutLumpResponseDecoder;
+pub type ExistsLumpResponseEncoder = PutLumpResponseEncoder;
+
+pub type DeleteDeviceResponseDecoder = PutLumpResponseDecoder;
+pub type DeleteDeviceResponseEncoder = PutLumpResponseEncoder;
+
+pub type StopDeviceResponseDecoder = PutLumpResponseDecoder;
+pub type StopDeviceResponseEncoder = PutLumpResponseEncoder;
+
 #[derive(Debug, Default)]
 pub struct HeadLumpResponseDecoder {
     inner: MessageDecoder<
@@ -612,6 +1206,57 @@ impl_sized_message_encode!(
     )
 );
 
+#[derive(Debug, Default)]
+pub struct HeadLumpWithMetaResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Optional<MessageFieldDecoder<F3, ResponseMetaDecoder>>,
+            Optional<
+                Oneof<(
+                    FieldDecoder<F1, Uint32Decoder>,
+                    MessageFieldDecoder<F2, ErrorDecoder>,
+                )>,
+            >,
+        )>,
+    >,
+}
+impl_message_decode!(
+    HeadLumpWithMetaResponseDecoder,
+    cannyls::Result<(Option<LumpHeader>, Option<ResponseMeta>)>,
+    |(meta, item)| {
+        let item = branch_into_optional_result(item).map(|v| {
+            v.map(|n| LumpHeader {
+                approximate_data_size: n,
+            })
+        });
+        Ok(join_meta(item, meta))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct HeadLumpWithMetaResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Optional<MessageFieldEncoder<F3, ResponseMetaEncoder>>,
+            Optional<
+                Oneof<(
+                    FieldEncoder<F1, Uint32Encoder>,
+                    MessageFieldEncoder<F2, ErrorEncoder>,
+                )>,
+            >,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    HeadLumpWithMetaResponseEncoder,
+    cannyls::Result<(Option<LumpHeader>, Option<ResponseMeta>)>,
+    |item: Self::Item| {
+        let (item, meta) = split_meta(item);
+        let item = item.map(|h| h.map(|h| h.approximate_data_size));
+        (meta, optional_result_into_branch(item))
+    }
+);
+
 #[derive(Debug, Default)]
 pub struct GetLumpResponseDecoder {
     inner: MessageDecoder<
@@ -658,15 +1303,269 @@ impl_sized_message_encode!(
 );
 
 #[derive(Debug, Default)]
-pub struct DeviceRequestDecoder {
+pub struct GetLumpWithMetaResponseDecoder {
     inner: MessageDecoder<
         Fields<(
-            FieldDecoder<F1, StringDecoder>,
-            MessageFieldDecoder<F2, RequestOptionsDecoder>,
+            Optional<MessageFieldDecoder<F3, ResponseMetaDecoder>>,
+            Optional<
+                Oneof<(
+                    FieldDecoder<F1, BytesDecoder>,
+                    MessageFieldDecoder<F2, ErrorDecoder>,
+                )>,
+            >,
         )>,
     >,
 }
-impl_message_decode!(DeviceRequestDecoder, DeviceRequest, |(
+impl_message_decode!(
+    GetLumpWithMetaResponseDecoder,
+    cannyls::Result<(Option<LumpData>, Option<ResponseMeta>)>,
+    |(meta, item)| {
+        let item = match branch_into_optional_result(item) {
+            Ok(Some(data)) => {
+                let data = track!(LumpData::new(data))
+                    .map_err(|e| bytecodec::ErrorKind::InvalidInput.takes_over(e))?;
+                Ok(Some(data))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+        Ok(join_meta(item, meta))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct GetLumpWithMetaResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Optional<MessageFieldEncoder<F3, ResponseMetaEncoder>>,
+            Optional<
+                Oneof<(
+                    FieldEncoder<F1, BytesEncoder<LumpData>>,
+                    MessageFieldEncoder<F2, ErrorEncoder>,
+                )>,
+            >,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    GetLumpWithMetaResponseEncoder,
+    cannyls::Result<(Option<LumpData>, Option<ResponseMeta>)>,
+    |item: Self::Item| {
+        let (item, meta) = split_meta(item);
+        (meta, optional_result_into_branch(item))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct ChecksummedLumpDataDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            FieldDecoder<F1, BytesDecoder>,
+            MaybeDefault<FieldDecoder<F2, Fixed32Decoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(ChecksummedLumpDataDecoder, (LumpData, u32), |(
+    data,
+    checksum,
+)| {
+    let data = track!(LumpData::new(data))
+        .map_err(|e| bytecodec::ErrorKind::InvalidInput.takes_over(e))?;
+    Ok((data, checksum))
+});
+
+#[derive(Debug, Default)]
+pub struct ChecksummedLumpDataEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            FieldEncoder<F1, BytesEncoder<LumpData>>,
+            MaybeDefault<FieldEncoder<F2, Fixed32Encoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    ChecksummedLumpDataEncoder,
+    (LumpData, u32),
+    |item: Self::Item| item
+);
+
+#[derive(Debug, Default)]
+pub struct GetLumpWithChecksumResponseDecoder {
+    inner: MessageDecoder<
+        Optional<
+            Oneof<(
+                MessageFieldDecoder<F1, ChecksummedLumpDataDecoder>,
+                MessageFieldDecoder<F2, ErrorDecoder>,
+            )>,
+        >,
+    >,
+}
+impl_message_decode!(
+    GetLumpWithChecksumResponseDecoder,
+    cannyls::Result<Option<(LumpData, u32)>>,
+    |item| Ok(branch_into_optional_result(item))
+);
+
+#[derive(Debug, Default)]
+pub struct GetLumpWithChecksumResponseEncoder {
+    inner: MessageEncoder<
+        Optional<
+            Oneof<(
+                MessageFieldEncoder<F1, ChecksummedLumpDataEncoder>,
+                MessageFieldEncoder<F2, ErrorEncoder>,
+            )>,
+        >,
+    >,
+}
+impl_sized_message_encode!(
+    GetLumpWithChecksumResponseEncoder,
+    cannyls::Result<Option<(LumpData, u32)>>,
+    |item: Self::Item| optional_result_into_branch(item)
+);
+
+/// `GetLumpToWriterRpc`用の応答デコーダ.
+///
+/// lumpデータをメモリ上に保持せずに、受信したそばから書き込み先に出力する.
+#[derive(Debug)]
+pub struct GetLumpToWriterResponseDecoder {
+    inner: MessageDecoder<
+        Optional<
+            Oneof<(
+                FieldDecoder<F1, CustomBytesDecoder<LumpDataWriter>>,
+                MessageFieldDecoder<F2, ErrorDecoder>,
+            )>,
+        >,
+    >,
+}
+impl GetLumpToWriterResponseDecoder {
+    /// lumpデータの出力先として`writer`を用いるデコーダを生成する.
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        let data = FieldDecoder::new(F1, CustomBytesDecoder::new(LumpDataWriter::new(writer)));
+        GetLumpToWriterResponseDecoder {
+            inner: MessageDecoder::new(Optional::new(Oneof::new((data, Default::default())))),
+        }
+    }
+}
+impl_message_decode!(
+    GetLumpToWriterResponseDecoder,
+    cannyls::Result<Option<u64>>,
+    |item| {
+        let item = branch_into_optional_result(item);
+        match item {
+            Ok(Some(Ok(written))) => Ok(Ok(Some(written))),
+            Ok(Some(Err(e))) => Ok(Err(track!(cannyls::ErrorKind::Other.cause(e)).into())),
+            Ok(None) => Ok(Ok(None)),
+            Err(e) => Ok(Err(e)),
+        }
+    }
+);
+
+/// `GetLumpToWriterRpc`用の応答エンコーダ.
+///
+/// `GetLumpToWriterRpc`はクライアント専用のRPCなので、このエンコーダが実際に使われることはない.
+/// (指定された場合には、常にエラーを返す)
+#[derive(Debug, Default)]
+pub struct GetLumpToWriterResponseEncoder;
+impl Encode for GetLumpToWriterResponseEncoder {
+    type Item = cannyls::Result<Option<u64>>;
+
+    fn encode(&mut self, _buf: &mut [u8], _eos: Eos) -> Result<usize> {
+        Ok(0)
+    }
+
+    fn start_encoding(&mut self, _item: Self::Item) -> Result<()> {
+        track_panic!(
+            ErrorKind::Other,
+            "`GetLumpToWriterRpc` is a client-only RPC"
+        )
+    }
+
+    fn is_idle(&self) -> bool {
+        true
+    }
+
+    fn requiring_bytes(&self) -> ByteCount {
+        ByteCount::Finite(0)
+    }
+}
+
+// 書き込み先でのエラーは、デコード処理自体のエラーとはせずに(i.e., 接続を切断させずに)、
+// 残りのデータを読み捨てた上で、デコード結果として返す.
+struct LumpDataWriter {
+    writer: Box<dyn Write + Send>,
+    written: u64,
+    error: Option<io::Error>,
+    eos: bool,
+}
+impl LumpDataWriter {
+    fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        LumpDataWriter {
+            writer: Box::new(writer),
+            written: 0,
+            error: None,
+            eos: false,
+        }
+    }
+}
+impl Decode for LumpDataWriter {
+    type Item = io::Result<u64>;
+
+    fn decode(&mut self, buf: &[u8], eos: Eos) -> Result<usize> {
+        if self.eos {
+            return Ok(0);
+        }
+        if self.error.is_none() {
+            if let Err(e) = self.writer.write_all(buf) {
+                self.error = Some(e);
+            }
+            self.written += buf.len() as u64;
+        }
+        self.eos = eos.is_reached();
+        Ok(buf.len())
+    }
+
+    fn finish_decoding(&mut self) -> Result<Self::Item> {
+        track_assert!(self.eos, ErrorKind::IncompleteDecoding);
+        self.eos = false;
+        let written = std::mem::take(&mut self.written);
+        if let Some(e) = self.error.take() {
+            return Ok(Err(e));
+        }
+        Ok(self.writer.flush().map(|()| written))
+    }
+
+    fn requiring_bytes(&self) -> ByteCount {
+        if self.eos {
+            ByteCount::Finite(0)
+        } else {
+            ByteCount::Infinite
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.eos
+    }
+}
+impl fmt::Debug for LumpDataWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LumpDataWriter")
+            .field("written", &self.written)
+            .field("error", &self.error)
+            .field("eos", &self.eos)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DeviceRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            FieldDecoder<F1, StringDecoder>,
+            MessageFieldDecoder<F2, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(DeviceRequestDecoder, DeviceRequest, |(
     device_id,
     options,
 )| Ok(DeviceRequest {
@@ -810,92 +1709,2494 @@ impl_sized_message_encode!(
 );
 
 #[derive(Debug, Default)]
-pub struct RangeLumpRequestDecoder {
+pub struct JournalUsageDecoder {
     inner: MessageDecoder<
         Fields<(
-            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
-            MessageFieldDecoder<F2, LumpIdDecoder>,
-            MessageFieldDecoder<F3, LumpIdDecoder>,
-            MessageFieldDecoder<F4, RequestOptionsDecoder>,
+            MaybeDefault<FieldDecoder<F1, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F4, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F5, Uint64Decoder>>,
+            MaybeDefault<MessageFieldDecoder<F6, JournalRecordCountsDecoder>>,
         )>,
     >,
 }
-impl_message_decode!(RangeLumpRequestDecoder, RangeLumpRequest, |(
-    device_id,
-    start,
-    end,
-    options,
+impl_message_decode!(JournalUsageDecoder, JournalUsage, |(
+    capacity_bytes,
+    usage_bytes,
+    consumed_bytes,
+    released_bytes,
+    records,
+    record_counts,
+)| Ok(JournalUsage {
+    capacity_bytes,
+    usage_bytes,
+    consumed_bytes,
+    released_bytes,
+    records,
+    record_counts,
+}));
+
+#[derive(Debug, Default)]
+pub struct JournalUsageEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F2, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F4, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F5, Uint64Encoder>>,
+            MaybeDefault<MessageFieldEncoder<F6, JournalRecordCountsEncoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(JournalUsageEncoder, JournalUsage, |item: Self::Item| (
+    item.capacity_bytes,
+    item.usage_bytes,
+    item.consumed_bytes,
+    item.released_bytes,
+    item.records,
+    item.record_counts,
+));
+
+#[derive(Debug, Default)]
+pub struct JournalRecordCountsDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F4, Uint64Decoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(JournalRecordCountsDecoder, JournalRecordCounts, |(
+    put,
+    embed,
+    delete,
+    delete_range,
 )| Ok(
-    RangeLumpRequest {
-        device_id: DeviceId::new(device_id),
-        range: Range { start, end },
-        options
+    JournalRecordCounts {
+        put,
+        embed,
+        delete,
+        delete_range,
     }
 ));
 
 #[derive(Debug, Default)]
-pub struct RangeLumpRequestEncoder {
+pub struct JournalRecordCountsEncoder {
     inner: MessageEncoder<
         Fields<(
-            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
-            MessageFieldEncoder<F2, LumpIdEncoder>,
-            MessageFieldEncoder<F3, LumpIdEncoder>,
-            MessageFieldEncoder<F4, RequestOptionsEncoder>,
+            MaybeDefault<FieldEncoder<F1, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F2, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F4, Uint64Encoder>>,
         )>,
     >,
 }
 impl_sized_message_encode!(
-    RangeLumpRequestEncoder,
-    RangeLumpRequest,
-    |item: Self::Item| (
-        item.device_id.into_string(),
-        item.range.start,
-        item.range.end,
-        item.options,
-    )
+    JournalRecordCountsEncoder,
+    JournalRecordCounts,
+    |item: Self::Item| (item.put, item.embed, item.delete, item.delete_range)
 );
 
-pub type DeleteRangeResponseDecoder = ListLumpResponseDecoder;
-pub type DeleteRangeResponseEncoder = ListLumpResponseEncoder;
-
-fn result_into_branch<T, E>(result: std::result::Result<T, E>) -> Branch2<T, E> {
-    match result {
-        Ok(a) => Branch2::A(a),
-        Err(b) => Branch2::B(b),
-    }
+#[derive(Debug, Default)]
+pub struct JournalUsageResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<MessageFieldDecoder<F1, JournalUsageDecoder>>,
+            Optional<MessageFieldDecoder<F2, ErrorDecoder>>,
+        )>,
+    >,
 }
-
-fn optional_result_into_branch<T, E>(
-    result: std::result::Result<Option<T>, E>,
-) -> Option<Branch2<T, E>> {
-    match result {
-        Ok(Some(a)) => Some(Branch2::A(a)),
-        Ok(None) => None,
-        Err(b) => Some(Branch2::B(b)),
+impl_message_decode!(
+    JournalUsageResponseDecoder,
+    cannyls::Result<JournalUsage>,
+    |(usage, error)| if let Some(error) = error {
+        Ok(Err(error))
+    } else {
+        Ok(Ok(usage))
     }
-}
+);
 
-fn branch_into_result<T, E>(branch: Branch2<T, E>) -> std::result::Result<T, E> {
-    match branch {
-        Branch2::A(a) => Ok(a),
-        Branch2::B(b) => Err(b),
-    }
+#[derive(Debug, Default)]
+pub struct JournalUsageResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MessageFieldEncoder<F1, JournalUsageEncoder>,
+            Optional<MessageFieldEncoder<F2, ErrorEncoder>>,
+        )>,
+    >,
 }
+impl_sized_message_encode!(
+    JournalUsageResponseEncoder,
+    cannyls::Result<JournalUsage>,
+    |item: Self::Item| match item {
+        Err(e) => (Default::default(), Some(e)),
+        Ok(usage) => (usage, None),
+    }
+);
 
-fn branch_into_optional_result<T, E>(
-    branch: Option<Branch2<T, E>>,
-) -> std::result::Result<Option<T>, E> {
-    match branch {
-        None => Ok(None),
-        Some(Branch2::A(a)) => Ok(Some(a)),
-        Some(Branch2::B(b)) => Err(b),
+#[derive(Debug, Default)]
+pub struct StorageMetricsSnapshotDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F4, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F5, Uint64Decoder>>,
+            MaybeDefault<MessageFieldDecoder<F6, JournalUsageDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(StorageMetricsSnapshotDecoder, StorageMetricsSnapshot, |(
+    lumps,
+    put_lumps,
+    delete_lumps,
+    data_capacity_bytes,
+    data_usage_bytes,
+    journal,
+)| Ok(
+    StorageMetricsSnapshot {
+        lumps,
+        put_lumps,
+        delete_lumps,
+        data_capacity_bytes,
+        data_usage_bytes,
+        journal,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct StorageMetricsSnapshotEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F2, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F4, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F5, Uint64Encoder>>,
+            MessageFieldEncoder<F6, JournalUsageEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    StorageMetricsSnapshotEncoder,
+    StorageMetricsSnapshot,
+    |item: Self::Item| (
+        item.lumps,
+        item.put_lumps,
+        item.delete_lumps,
+        item.data_capacity_bytes,
+        item.data_usage_bytes,
+        item.journal,
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct DeviceMetricsSnapshotDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint32Decoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F4, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F5, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F6, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F7, Uint64Decoder>>,
+            Optional<MessageFieldDecoder<F8, StorageMetricsSnapshotDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(DeviceMetricsSnapshotDecoder, DeviceMetricsSnapshot, |(
+    device_id,
+    status,
+    queue_len,
+    enqueued_commands,
+    dequeued_commands,
+    failed_commands,
+    busy_commands,
+    storage,
+)| {
+    let status = track!(decode_device_status(status))?;
+    Ok(DeviceMetricsSnapshot {
+        device_id: DeviceId::new(device_id),
+        status,
+        queue_len,
+        enqueued_commands,
+        dequeued_commands,
+        failed_commands,
+        busy_commands,
+        storage,
+    })
+});
+
+#[derive(Debug, Default)]
+pub struct DeviceMetricsSnapshotEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F2, Uint32Encoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F4, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F5, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F6, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F7, Uint64Encoder>>,
+            Optional<MessageFieldEncoder<F8, StorageMetricsSnapshotEncoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    DeviceMetricsSnapshotEncoder,
+    DeviceMetricsSnapshot,
+    |item: Self::Item| (
+        item.device_id.into_string(),
+        item.status as u32,
+        item.queue_len,
+        item.enqueued_commands,
+        item.dequeued_commands,
+        item.failed_commands,
+        item.busy_commands,
+        item.storage,
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct StorageHeaderDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, Uint32Decoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint32Decoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint32Decoder>>,
+            MaybeDefault<FieldDecoder<F4, BytesDecoder>>,
+            MaybeDefault<FieldDecoder<F5, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F6, Uint64Decoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(StorageHeaderDecoder, StorageHeader, |(
+    major_version,
+    minor_version,
+    block_size,
+    instance_uuid,
+    journal_region_size,
+    data_region_size,
+): (
+    u32,
+    u32,
+    u32,
+    Vec<u8>,
+    u64,
+    u64
+)| {
+    track_assert!(major_version <= u32::from(u16::MAX), ErrorKind::InvalidInput; major_version);
+    track_assert!(minor_version <= u32::from(u16::MAX), ErrorKind::InvalidInput; minor_version);
+    let instance_uuid =
+        track!(Uuid::from_slice(&instance_uuid)
+            .map_err(|e| ErrorKind::InvalidInput.cause(e.to_string())))?;
+    Ok(StorageHeader {
+        major_version: major_version as u16,
+        minor_version: minor_version as u16,
+        block_size: track!(decode_block_size(block_size))?,
+        instance_uuid,
+        journal_region_size,
+        data_region_size,
+    })
+});
+
+#[derive(Debug, Default)]
+pub struct StorageHeaderEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, Uint32Encoder>>,
+            MaybeDefault<FieldEncoder<F2, Uint32Encoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint32Encoder>>,
+            MaybeDefault<FieldEncoder<F4, BytesEncoder>>,
+            MaybeDefault<FieldEncoder<F5, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F6, Uint64Encoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(StorageHeaderEncoder, StorageHeader, |item: Self::Item| (
+    u32::from(item.major_version),
+    u32::from(item.minor_version),
+    u32::from(item.block_size.as_u16()),
+    item.instance_uuid.as_bytes().to_vec(),
+    item.journal_region_size,
+    item.data_region_size,
+));
+
+#[derive(Debug, Default)]
+pub struct StorageHeaderResponseDecoder {
+    inner: MessageDecoder<
+        Oneof<(
+            MessageFieldDecoder<F1, StorageHeaderDecoder>,
+            MessageFieldDecoder<F2, ErrorDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    StorageHeaderResponseDecoder,
+    cannyls::Result<StorageHeader>,
+    |item| Ok(branch_into_result(item))
+);
+
+#[derive(Debug, Default)]
+pub struct StorageHeaderResponseEncoder {
+    inner: MessageEncoder<
+        Oneof<(
+            MessageFieldEncoder<F1, StorageHeaderEncoder>,
+            MessageFieldEncoder<F2, ErrorEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    StorageHeaderResponseEncoder,
+    cannyls::Result<StorageHeader>,
+    |item: Self::Item| result_into_branch(item)
+);
+
+#[derive(Debug, Default)]
+pub struct MetricsSnapshotRequestDecoder {
+    inner: MessageDecoder<Repeated<FieldDecoder<F1, StringDecoder>, Vec<String>>>,
+}
+impl_message_decode!(MetricsSnapshotRequestDecoder, Vec<DeviceId>, |ids: Vec<
+    String,
+>| Ok(ids
+    .into_iter()
+    .map(DeviceId::new)
+    .collect()));
+
+#[derive(Debug, Default)]
+pub struct MetricsSnapshotRequestEncoder {
+    inner: MessageEncoder<Repeated<FieldEncoder<F1, StringEncoder>, Vec<String>>>,
+}
+impl_message_encode!(
+    MetricsSnapshotRequestEncoder,
+    Vec<DeviceId>,
+    |item: Self::Item| item.into_iter().map(DeviceId::into_string).collect()
+);
+
+#[derive(Debug, Default)]
+pub struct MetricsSnapshotResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<
+                MessageFieldDecoder<F1, DeviceMetricsSnapshotDecoder>,
+                Vec<DeviceMetricsSnapshot>,
+            >,
+            Optional<MessageFieldDecoder<F2, ErrorDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    MetricsSnapshotResponseDecoder,
+    cannyls::Result<Vec<DeviceMetricsSnapshot>>,
+    |(snapshots, error)| if let Some(error) = error {
+        Ok(Err(error))
+    } else {
+        Ok(Ok(snapshots))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct MetricsSnapshotResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<
+                MessageFieldEncoder<F1, DeviceMetricsSnapshotEncoder>,
+                Vec<DeviceMetricsSnapshot>,
+            >,
+            Optional<MessageFieldEncoder<F2, ErrorEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    MetricsSnapshotResponseEncoder,
+    cannyls::Result<Vec<DeviceMetricsSnapshot>>,
+    |item: Self::Item| match item {
+        Err(e) => (Vec::new(), Some(e)),
+        Ok(snapshots) => (snapshots, None),
+    }
+);
+
+pub type ListInFlightRequestDecoder = MetricsSnapshotRequestDecoder;
+pub type ListInFlightRequestEncoder = MetricsSnapshotRequestEncoder;
+
+#[derive(Debug, Default)]
+pub struct InFlightRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F2, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F3, StringDecoder>>,
+            Optional<MessageFieldDecoder<F4, LumpIdDecoder>>,
+            Optional<MessageFieldDecoder<F5, LumpIdDecoder>>,
+            Optional<MessageFieldDecoder<F6, LumpIdDecoder>>,
+            MaybeDefault<MessageFieldDecoder<F7, StdDurationDecoder>>,
+            MaybeDefault<MessageFieldDecoder<F8, DeadlineDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(InFlightRequestDecoder, InFlightRequest, |(
+    request_id,
+    procedure,
+    device_id,
+    lump_id,
+    range_start,
+    range_end,
+    elapsed,
+    deadline,
+)| {
+    let target = match (lump_id, range_start, range_end) {
+        (None, None, None) => RequestTarget::Device,
+        (Some(lump_id), None, None) => RequestTarget::Lump(lump_id),
+        (None, Some(start), Some(end)) => RequestTarget::Range(Range { start, end }),
+        _ => track_panic!(ErrorKind::InvalidInput, "Malformed request target"),
+    };
+    Ok(InFlightRequest {
+        request_id,
+        procedure,
+        device_id: DeviceId::new(device_id),
+        target,
+        elapsed,
+        deadline,
+    })
+});
+
+#[derive(Debug, Default)]
+pub struct InFlightRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F2, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F3, StringEncoder>>,
+            Optional<MessageFieldEncoder<F4, LumpIdEncoder>>,
+            Optional<MessageFieldEncoder<F5, LumpIdEncoder>>,
+            Optional<MessageFieldEncoder<F6, LumpIdEncoder>>,
+            MessageFieldEncoder<F7, StdDurationEncoder>,
+            MessageFieldEncoder<F8, DeadlineEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    InFlightRequestEncoder,
+    InFlightRequest,
+    |item: Self::Item| {
+        let (lump_id, range_start, range_end) = match item.target {
+            RequestTarget::Device => (None, None, None),
+            RequestTarget::Lump(lump_id) => (Some(lump_id), None, None),
+            RequestTarget::Range(range) => (None, Some(range.start), Some(range.end)),
+        };
+        (
+            item.request_id,
+            item.procedure,
+            item.device_id.into_string(),
+            lump_id,
+            range_start,
+            range_end,
+            item.elapsed,
+            item.deadline,
+        )
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct ListInFlightResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<MessageFieldDecoder<F1, InFlightRequestDecoder>, Vec<InFlightRequest>>,
+            Optional<MessageFieldDecoder<F2, ErrorDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    ListInFlightResponseDecoder,
+    cannyls::Result<Vec<InFlightRequest>>,
+    |(requests, error)| if let Some(error) = error {
+        Ok(Err(error))
+    } else {
+        Ok(Ok(requests))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct ListInFlightResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<MessageFieldEncoder<F1, InFlightRequestEncoder>, Vec<InFlightRequest>>,
+            Optional<MessageFieldEncoder<F2, ErrorEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    ListInFlightResponseEncoder,
+    cannyls::Result<Vec<InFlightRequest>>,
+    |item: Self::Item| match item {
+        Err(e) => (Vec::new(), Some(e)),
+        Ok(requests) => (requests, None),
+    }
+);
+
+pub type RequestStatsRequestDecoder = MetricsSnapshotRequestDecoder;
+pub type RequestStatsRequestEncoder = MetricsSnapshotRequestEncoder;
+
+#[derive(Debug, Default)]
+pub struct ErrorCountDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint64Decoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(ErrorCountDecoder, (cannyls::ErrorKind, u64), |(
+    kind,
+    count,
+): (
+    String,
+    _
+)| {
+    let kind = cannyls::ErrorKind::from_str(&kind).unwrap_or(cannyls::ErrorKind::Other);
+    Ok((kind, count))
+});
+
+#[derive(Debug, Default)]
+pub struct ErrorCountEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F2, Uint64Encoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    ErrorCountEncoder,
+    (cannyls::ErrorKind, u64),
+    |item: Self::Item| (item.0.to_string(), item.1)
+);
+
+#[derive(Debug, Default)]
+pub struct RequestStatsDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F2, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint64Decoder>>,
+            Repeated<MessageFieldDecoder<F4, ErrorCountDecoder>, Vec<(cannyls::ErrorKind, u64)>>,
+            MaybeDefault<MessageFieldDecoder<F5, StdDurationDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(RequestStatsDecoder, RequestStats, |(
+    device_id,
+    procedure,
+    succeeded,
+    failed,
+    period,
+)| Ok(RequestStats {
+    device_id: DeviceId::new(device_id),
+    procedure,
+    succeeded,
+    failed,
+    period,
+}));
+
+#[derive(Debug, Default)]
+pub struct RequestStatsEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F2, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint64Encoder>>,
+            Repeated<MessageFieldEncoder<F4, ErrorCountEncoder>, Vec<(cannyls::ErrorKind, u64)>>,
+            MessageFieldEncoder<F5, StdDurationEncoder>,
+        )>,
+    >,
+}
+impl_message_encode!(RequestStatsEncoder, RequestStats, |item: Self::Item| (
+    item.device_id.into_string(),
+    item.procedure,
+    item.succeeded,
+    item.failed,
+    item.period,
+));
+
+#[derive(Debug, Default)]
+pub struct RequestStatsResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<MessageFieldDecoder<F1, RequestStatsDecoder>, Vec<RequestStats>>,
+            Optional<MessageFieldDecoder<F2, ErrorDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    RequestStatsResponseDecoder,
+    cannyls::Result<Vec<RequestStats>>,
+    |(stats, error)| if let Some(error) = error {
+        Ok(Err(error))
+    } else {
+        Ok(Ok(stats))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct RequestStatsResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<MessageFieldEncoder<F1, PreEncode<RequestStatsEncoder>>, Vec<RequestStats>>,
+            Optional<MessageFieldEncoder<F2, ErrorEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    RequestStatsResponseEncoder,
+    cannyls::Result<Vec<RequestStats>>,
+    |item: Self::Item| match item {
+        Err(e) => (Vec::new(), Some(e)),
+        Ok(stats) => (stats, None),
+    }
+);
+
+pub type ReadinessRequestDecoder = MetricsSnapshotRequestDecoder;
+pub type ReadinessRequestEncoder = MetricsSnapshotRequestEncoder;
+
+#[derive(Debug, Default)]
+pub struct ListDevicesRequestDecoder {
+    inner: MessageDecoder<MaybeDefault<FieldDecoder<F1, BoolDecoder>>>,
+}
+impl_message_decode!(ListDevicesRequestDecoder, bool, Ok);
+
+#[derive(Debug, Default)]
+pub struct ListDevicesRequestEncoder {
+    inner: MessageEncoder<MaybeDefault<FieldEncoder<F1, BoolEncoder>>>,
+}
+impl_sized_message_encode!(ListDevicesRequestEncoder, bool, |item: Self::Item| item);
+
+#[derive(Debug, Default)]
+pub struct DeviceSummaryDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            Optional<FieldDecoder<F2, Uint32Decoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(DeviceSummaryDecoder, DeviceSummary, |(
+    device_id,
+    status,
+): (
+    String,
+    Option<u32>
+)| {
+    let status = match status {
+        None => None,
+        Some(status) => Some(track!(decode_device_status(status))?),
+    };
+    Ok(DeviceSummary {
+        device_id: DeviceId::new(device_id),
+        status,
+    })
+});
+
+#[derive(Debug, Default)]
+pub struct DeviceSummaryEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            Optional<FieldEncoder<F2, Uint32Encoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(DeviceSummaryEncoder, DeviceSummary, |item: Self::Item| (
+    item.device_id.into_string(),
+    item.status.map(|s| s as u32),
+));
+
+#[derive(Debug, Default)]
+pub struct ListDevicesResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<MessageFieldDecoder<F1, DeviceSummaryDecoder>, Vec<DeviceSummary>>,
+            Optional<MessageFieldDecoder<F2, ErrorDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    ListDevicesResponseDecoder,
+    cannyls::Result<Vec<DeviceSummary>>,
+    |(devices, error)| if let Some(error) = error {
+        Ok(Err(error))
+    } else {
+        Ok(Ok(devices))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct ListDevicesResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<MessageFieldEncoder<F1, DeviceSummaryEncoder>, Vec<DeviceSummary>>,
+            Optional<MessageFieldEncoder<F2, ErrorEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    ListDevicesResponseEncoder,
+    cannyls::Result<Vec<DeviceSummary>>,
+    |item: Self::Item| match item {
+        Err(e) => (Vec::new(), Some(e)),
+        Ok(devices) => (devices, None),
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct DeviceStatusReportDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            Optional<FieldDecoder<F2, Uint32Decoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint64Decoder>>,
+            Optional<MessageFieldDecoder<F4, StorageMetricsSnapshotDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(DeviceStatusReportDecoder, DeviceStatusReport, |(
+    device_id,
+    status,
+    queue_len,
+    storage,
+): (
+    String,
+    Option<u32>,
+    u64,
+    Option<StorageMetricsSnapshot>
+)| {
+    let status = match status {
+        None => None,
+        Some(status) => Some(track!(decode_device_status(status))?),
+    };
+    Ok(DeviceStatusReport {
+        device_id: DeviceId::new(device_id),
+        status,
+        queue_len,
+        storage,
+    })
+});
+
+#[derive(Debug, Default)]
+pub struct DeviceStatusReportEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            Optional<FieldEncoder<F2, Uint32Encoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint64Encoder>>,
+            Optional<MessageFieldEncoder<F4, StorageMetricsSnapshotEncoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    DeviceStatusReportEncoder,
+    DeviceStatusReport,
+    |item: Self::Item| (
+        item.device_id.into_string(),
+        item.status.map(|s| s as u32),
+        item.queue_len,
+        item.storage,
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct DeviceStatusResponseDecoder {
+    inner: MessageDecoder<
+        Oneof<(
+            MessageFieldDecoder<F1, DeviceStatusReportDecoder>,
+            MessageFieldDecoder<F2, ErrorDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    DeviceStatusResponseDecoder,
+    cannyls::Result<DeviceStatusReport>,
+    |item| Ok(branch_into_result(item))
+);
+
+#[derive(Debug, Default)]
+pub struct DeviceStatusResponseEncoder {
+    inner: MessageEncoder<
+        Oneof<(
+            MessageFieldEncoder<F1, DeviceStatusReportEncoder>,
+            MessageFieldEncoder<F2, ErrorEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    DeviceStatusResponseEncoder,
+    cannyls::Result<DeviceStatusReport>,
+    |item: Self::Item| result_into_branch(item)
+);
+
+#[derive(Debug, Default)]
+pub struct DeviceReadinessDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F2, StringDecoder>>,
+            MaybeDefault<MessageFieldDecoder<F3, StdDurationDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(DeviceReadinessDecoder, DeviceReadiness, |(
+    device_id,
+    error,
+    latency,
+): (
+    String,
+    String,
+    _
+)| {
+    let error = if error.is_empty() {
+        None
+    } else {
+        Some(cannyls::ErrorKind::from_str(&error).unwrap_or(cannyls::ErrorKind::Other))
+    };
+    Ok(DeviceReadiness {
+        device_id: DeviceId::new(device_id),
+        error,
+        latency,
+    })
+});
+
+#[derive(Debug, Default)]
+pub struct DeviceReadinessEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F2, StringEncoder>>,
+            MessageFieldEncoder<F3, StdDurationEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    DeviceReadinessEncoder,
+    DeviceReadiness,
+    |item: Self::Item| (
+        item.device_id.into_string(),
+        item.error.map(|e| e.to_string()).unwrap_or_default(),
+        item.latency,
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct ReadinessResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<MessageFieldDecoder<F1, DeviceReadinessDecoder>, Vec<DeviceReadiness>>,
+            Optional<MessageFieldDecoder<F2, ErrorDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    ReadinessResponseDecoder,
+    cannyls::Result<Vec<DeviceReadiness>>,
+    |(devices, error)| if let Some(error) = error {
+        Ok(Err(error))
+    } else {
+        Ok(Ok(devices))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct ReadinessResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<MessageFieldEncoder<F1, DeviceReadinessEncoder>, Vec<DeviceReadiness>>,
+            Optional<MessageFieldEncoder<F2, ErrorEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    ReadinessResponseEncoder,
+    cannyls::Result<Vec<DeviceReadiness>>,
+    |item: Self::Item| match item {
+        Err(e) => (Vec::new(), Some(e)),
+        Ok(devices) => (devices, None),
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct CancelInFlightRequestDecoder {
+    inner: MessageDecoder<MaybeDefault<FieldDecoder<F1, Uint64Decoder>>>,
+}
+impl_message_decode!(CancelInFlightRequestDecoder, u64, Ok);
+
+#[derive(Debug, Default)]
+pub struct CancelInFlightRequestEncoder {
+    inner: MessageEncoder<MaybeDefault<FieldEncoder<F1, Uint64Encoder>>>,
+}
+impl_sized_message_encode!(CancelInFlightRequestEncoder, u64, |item: Self::Item| item);
+
+pub type CancelInFlightResponseDecoder = PutLumpResponseDecoder;
+pub type CancelInFlightResponseEncoder = PutLumpResponseEncoder;
+
+#[derive(Debug, Default)]
+pub struct DeviceSettingsDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, BoolDecoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint32Decoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint32Decoder>>,
+            MaybeDefault<FieldDecoder<F4, Uint32Decoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(DeviceSettingsDecoder, DeviceSettings, |(
+    journal_sync,
+    default_max_queue_len,
+    max_queue_len_limit,
+    write_watermark,
+)| Ok(
+    DeviceSettings {
+        journal_sync,
+        default_max_queue_len: decode_queue_len(default_max_queue_len),
+        max_queue_len_limit: decode_queue_len(max_queue_len_limit),
+        write_watermark: decode_write_watermark(write_watermark),
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct DeviceSettingsEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, BoolEncoder>>,
+            MaybeDefault<FieldEncoder<F2, Uint32Encoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint32Encoder>>,
+            MaybeDefault<FieldEncoder<F4, Uint32Encoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(DeviceSettingsEncoder, DeviceSettings, |item: Self::Item| (
+    item.journal_sync,
+    encode_queue_len(item.default_max_queue_len),
+    encode_queue_len(item.max_queue_len_limit),
+    encode_write_watermark(item.write_watermark),
+));
+
+#[derive(Debug, Default)]
+pub struct DeviceSettingsResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<MessageFieldDecoder<F1, DeviceSettingsDecoder>>,
+            Optional<MessageFieldDecoder<F2, ErrorDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    DeviceSettingsResponseDecoder,
+    cannyls::Result<DeviceSettings>,
+    |(settings, error)| if let Some(error) = error {
+        Ok(Err(error))
+    } else {
+        Ok(Ok(settings))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct DeviceSettingsResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MessageFieldEncoder<F1, DeviceSettingsEncoder>,
+            Optional<MessageFieldEncoder<F2, ErrorEncoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    DeviceSettingsResponseEncoder,
+    cannyls::Result<DeviceSettings>,
+    |item: Self::Item| match item {
+        Err(e) => (Default::default(), Some(e)),
+        Ok(settings) => (settings, None),
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct SetJournalSyncRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F2, BoolDecoder>>,
+            MessageFieldDecoder<F3, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(SetJournalSyncRequestDecoder, SetJournalSyncRequest, |(
+    device_id,
+    journal_sync,
+    options,
+)| Ok(
+    SetJournalSyncRequest {
+        device_id: DeviceId::new(device_id),
+        journal_sync,
+        options,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct SetJournalSyncRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F2, BoolEncoder>>,
+            MessageFieldEncoder<F3, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    SetJournalSyncRequestEncoder,
+    SetJournalSyncRequest,
+    |item: Self::Item| (
+        item.device_id.into_string(),
+        item.journal_sync,
+        item.options
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct SetQueueLimitsRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint32Decoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint32Decoder>>,
+            MessageFieldDecoder<F4, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(SetQueueLimitsRequestDecoder, SetQueueLimitsRequest, |(
+    device_id,
+    default_max_queue_len,
+    max_queue_len_limit,
+    options,
+)| Ok(
+    SetQueueLimitsRequest {
+        device_id: DeviceId::new(device_id),
+        default_max_queue_len: decode_queue_len(default_max_queue_len),
+        max_queue_len_limit: decode_queue_len(max_queue_len_limit),
+        options,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct SetQueueLimitsRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F2, Uint32Encoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint32Encoder>>,
+            MessageFieldEncoder<F4, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    SetQueueLimitsRequestEncoder,
+    SetQueueLimitsRequest,
+    |item: Self::Item| (
+        item.device_id.into_string(),
+        encode_queue_len(item.default_max_queue_len),
+        encode_queue_len(item.max_queue_len_limit),
+        item.options,
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct SetWriteWatermarkRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint32Decoder>>,
+            MessageFieldDecoder<F3, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    SetWriteWatermarkRequestDecoder,
+    SetWriteWatermarkRequest,
+    |(device_id, write_watermark, options)| Ok(SetWriteWatermarkRequest {
+        device_id: DeviceId::new(device_id),
+        write_watermark: decode_write_watermark(write_watermark),
+        options,
+    })
+);
+
+#[derive(Debug, Default)]
+pub struct SetWriteWatermarkRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F2, Uint32Encoder>>,
+            MessageFieldEncoder<F3, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    SetWriteWatermarkRequestEncoder,
+    SetWriteWatermarkRequest,
+    |item: Self::Item| (
+        item.device_id.into_string(),
+        encode_write_watermark(item.write_watermark),
+        item.options,
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct DeviceLabelDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F2, StringDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(DeviceLabelDecoder, (String, String), Ok);
+
+#[derive(Debug, Default)]
+pub struct DeviceLabelEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F2, StringEncoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(DeviceLabelEncoder, (String, String), |item: Self::Item| {
+    item
+});
+
+fn decode_device_spec(
+    device_id: String,
+    file_path: String,
+    capacity: u64,
+    block_size: u32,
+    labels: DeviceLabels,
+) -> Result<DeviceSpec> {
+    let nvm = if file_path.is_empty() {
+        DeviceNvmSpec::Memory
+    } else {
+        DeviceNvmSpec::File(PathBuf::from(file_path))
+    };
+    Ok(DeviceSpec {
+        device_id: DeviceId::new(device_id),
+        nvm,
+        capacity,
+        block_size: track!(decode_block_size(block_size))?,
+        labels,
+    })
+}
+
+fn encode_device_spec(spec: DeviceSpec) -> (String, String, u64, u32, DeviceLabels) {
+    // NOTE: UTF-8として解釈できないパスは、損失のある変換を経て送信される
+    let file_path = match spec.nvm {
+        DeviceNvmSpec::File(path) => path.to_string_lossy().into_owned(),
+        DeviceNvmSpec::Memory => String::new(),
+    };
+    (
+        spec.device_id.into_string(),
+        file_path,
+        spec.capacity,
+        u32::from(spec.block_size.as_u16()),
+        spec.labels,
+    )
+}
+
+// `DeviceSpec`のフィールドに、リクエストのオプションを追加したもの.
+#[derive(Debug, Default)]
+pub struct ProvisionDeviceRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F2, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F4, Uint32Decoder>>,
+            Repeated<MessageFieldDecoder<F5, DeviceLabelDecoder>, DeviceLabels>,
+            MessageFieldDecoder<F6, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(ProvisionDeviceRequestDecoder, ProvisionDeviceRequest, |(
+    device_id,
+    file_path,
+    capacity,
+    block_size,
+    labels,
+    options,
+)| Ok(
+    ProvisionDeviceRequest {
+        spec: track!(decode_device_spec(
+            device_id, file_path, capacity, block_size, labels
+        ))?,
+        options,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct ProvisionDeviceRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F2, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F4, Uint32Encoder>>,
+            Repeated<MessageFieldEncoder<F5, DeviceLabelEncoder>, DeviceLabels>,
+            MessageFieldEncoder<F6, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    ProvisionDeviceRequestEncoder,
+    ProvisionDeviceRequest,
+    |item: Self::Item| {
+        let (device_id, file_path, capacity, block_size, labels) = encode_device_spec(item.spec);
+        (
+            device_id,
+            file_path,
+            capacity,
+            block_size,
+            labels,
+            item.options,
+        )
+    }
+);
+
+pub type ProvisionDeviceResponseDecoder = PutLumpResponseDecoder;
+pub type ProvisionDeviceResponseEncoder = PutLumpResponseEncoder;
+
+#[derive(Debug, Default)]
+pub struct DeviceLabelsResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<MessageFieldDecoder<F1, DeviceLabelDecoder>, DeviceLabels>,
+            Optional<MessageFieldDecoder<F2, ErrorDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    DeviceLabelsResponseDecoder,
+    cannyls::Result<DeviceLabels>,
+    |(labels, error)| if let Some(error) = error {
+        Ok(Err(error))
+    } else {
+        Ok(Ok(labels))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct DeviceLabelsResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<MessageFieldEncoder<F1, DeviceLabelEncoder>, DeviceLabels>,
+            Optional<MessageFieldEncoder<F2, ErrorEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    DeviceLabelsResponseEncoder,
+    cannyls::Result<DeviceLabels>,
+    |item: Self::Item| match item {
+        Err(e) => (DeviceLabels::new(), Some(e)),
+        Ok(labels) => (labels, None),
+    }
+);
+
+fn decode_block_size(block_size: u32) -> Result<BlockSize> {
+    if block_size == 0 {
+        return Ok(BlockSize::min());
+    }
+    track_assert!(
+        block_size <= u32::from(u16::MAX),
+        ErrorKind::InvalidInput,
+        "Too large block size: {}",
+        block_size
+    );
+    track!(BlockSize::new(block_size as u16))
+        .map_err(|e| bytecodec::ErrorKind::InvalidInput.takes_over(e).into())
+}
+
+#[derive(Debug, Default)]
+pub struct LogLevelDecoder {
+    inner: MessageDecoder<MaybeDefault<FieldDecoder<F1, Uint32Decoder>>>,
+}
+impl_message_decode!(LogLevelDecoder, Level, decode_log_level);
+
+#[derive(Debug, Default)]
+pub struct LogLevelEncoder {
+    inner: MessageEncoder<MaybeDefault<FieldEncoder<F1, Uint32Encoder>>>,
+}
+impl_sized_message_encode!(LogLevelEncoder, Level, |item: Self::Item| item.as_usize()
+    as u32);
+
+#[derive(Debug, Default)]
+pub struct LogLevelResponseDecoder {
+    inner: MessageDecoder<
+        Oneof<(
+            FieldDecoder<F1, Uint32Decoder>,
+            MessageFieldDecoder<F2, ErrorDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    LogLevelResponseDecoder,
+    cannyls::Result<Level>,
+    |item| match item {
+        Branch2::A(level) => decode_log_level(level).map(Ok),
+        Branch2::B(e) => Ok(Err(e)),
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct LogLevelResponseEncoder {
+    inner: MessageEncoder<
+        Oneof<(
+            FieldEncoder<F1, Uint32Encoder>,
+            MessageFieldEncoder<F2, ErrorEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    LogLevelResponseEncoder,
+    cannyls::Result<Level>,
+    |item: Self::Item| result_into_branch(item.map(|level| level.as_usize() as u32))
+);
+
+fn decode_log_level(level: u32) -> Result<Level> {
+    let level = track_assert_some!(
+        Level::from_usize(level as usize),
+        ErrorKind::InvalidInput,
+        "Unknown log level: {}",
+        level
+    );
+    Ok(level)
+}
+
+#[derive(Debug, Default)]
+pub struct RangeLumpRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MessageFieldDecoder<F2, LumpIdDecoder>,
+            MessageFieldDecoder<F3, LumpIdDecoder>,
+            MessageFieldDecoder<F4, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(RangeLumpRequestDecoder, RangeLumpRequest, |(
+    device_id,
+    start,
+    end,
+    options,
+)| Ok(
+    RangeLumpRequest {
+        device_id: DeviceId::new(device_id),
+        range: Range { start, end },
+        options
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct RangeLumpRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MessageFieldEncoder<F2, LumpIdEncoder>,
+            MessageFieldEncoder<F3, LumpIdEncoder>,
+            MessageFieldEncoder<F4, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    RangeLumpRequestEncoder,
+    RangeLumpRequest,
+    |item: Self::Item| (
+        item.device_id.into_string(),
+        item.range.start,
+        item.range.end,
+        item.options,
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct CopyRangeRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F2, StringDecoder>>,
+            MessageFieldDecoder<F3, LumpIdDecoder>,
+            MessageFieldDecoder<F4, LumpIdDecoder>,
+            MessageFieldDecoder<F5, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(CopyRangeRequestDecoder, CopyRangeRequest, |(
+    source_device_id,
+    destination_device_id,
+    start,
+    end,
+    options,
+)| Ok(
+    CopyRangeRequest {
+        source_device_id: DeviceId::new(source_device_id),
+        destination_device_id: DeviceId::new(destination_device_id),
+        range: Range { start, end },
+        options
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct CopyRangeRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F2, StringEncoder>>,
+            MessageFieldEncoder<F3, LumpIdEncoder>,
+            MessageFieldEncoder<F4, LumpIdEncoder>,
+            MessageFieldEncoder<F5, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    CopyRangeRequestEncoder,
+    CopyRangeRequest,
+    |item: Self::Item| (
+        item.source_device_id.into_string(),
+        item.destination_device_id.into_string(),
+        item.range.start,
+        item.range.end,
+        item.options,
+    )
+);
+
+pub type DeleteRangeResponseDecoder = ListLumpResponseDecoder;
+pub type DeleteRangeResponseEncoder = ListLumpResponseEncoder;
+
+#[derive(Debug, Default)]
+pub struct ScriptPutOpDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MessageFieldDecoder<F1, LumpIdDecoder>,
+            FieldDecoder<F2, BytesDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(ScriptPutOpDecoder, (LumpId, LumpData), |(
+    lump_id,
+    lump_data,
+)| {
+    let lump_data = track!(LumpData::new(lump_data))
+        .map_err(|e| bytecodec::ErrorKind::InvalidInput.takes_over(e))?;
+    Ok((lump_id, lump_data))
+});
+
+#[derive(Debug, Default)]
+pub struct ScriptPutOpEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MessageFieldEncoder<F1, LumpIdEncoder>,
+            FieldEncoder<F2, BytesEncoder<LumpData>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    ScriptPutOpEncoder,
+    (LumpId, LumpData),
+    |item: Self::Item| item
+);
+
+#[derive(Debug, Default)]
+pub struct ScriptOpDecoder {
+    inner: MessageDecoder<
+        Oneof<(
+            MessageFieldDecoder<F1, LumpIdDecoder>,
+            MessageFieldDecoder<F2, LumpIdDecoder>,
+            MessageFieldDecoder<F3, ScriptPutOpDecoder>,
+            MessageFieldDecoder<F4, LumpIdDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(ScriptOpDecoder, ScriptOp, |item| Ok(match item {
+    Branch4::A(lump_id) => ScriptOp::Head(lump_id),
+    Branch4::B(lump_id) => ScriptOp::Get(lump_id),
+    Branch4::C((lump_id, lump_data)) => ScriptOp::Put(lump_id, lump_data),
+    Branch4::D(lump_id) => ScriptOp::Delete(lump_id),
+}));
+
+#[derive(Debug, Default)]
+pub struct ScriptOpEncoder {
+    inner: MessageEncoder<
+        Oneof<(
+            MessageFieldEncoder<F1, LumpIdEncoder>,
+            MessageFieldEncoder<F2, LumpIdEncoder>,
+            MessageFieldEncoder<F3, ScriptPutOpEncoder>,
+            MessageFieldEncoder<F4, LumpIdEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(ScriptOpEncoder, ScriptOp, |item: Self::Item| match item {
+    ScriptOp::Head(lump_id) => Branch4::A(lump_id),
+    ScriptOp::Get(lump_id) => Branch4::B(lump_id),
+    ScriptOp::Put(lump_id, lump_data) => Branch4::C((lump_id, lump_data)),
+    ScriptOp::Delete(lump_id) => Branch4::D(lump_id),
+});
+
+#[derive(Debug, Default)]
+pub struct ScriptRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            FieldDecoder<F1, StringDecoder>,
+            Repeated<MessageFieldDecoder<F2, ScriptOpDecoder>, Vec<ScriptOp>>,
+            MessageFieldDecoder<F3, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(ScriptRequestDecoder, ScriptRequest, |(
+    device_id,
+    ops,
+    options,
+)| Ok(ScriptRequest {
+    device_id: DeviceId::new(device_id),
+    ops,
+    options,
+}));
+
+#[derive(Debug, Default)]
+pub struct ScriptRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            FieldEncoder<F1, StringEncoder>,
+            Repeated<MessageFieldEncoder<F2, ScriptOpEncoder>, Vec<ScriptOp>>,
+            MessageFieldEncoder<F3, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_message_encode!(ScriptRequestEncoder, ScriptRequest, |item: Self::Item| (
+    item.device_id.into_string(),
+    item.ops,
+    item.options
+));
+
+#[derive(Debug, Default)]
+pub struct ScriptOpResultDecoder {
+    inner: MessageDecoder<
+        Oneof<(
+            MessageFieldDecoder<F1, HeadLumpResponseDecoder>,
+            MessageFieldDecoder<F2, GetLumpResponseDecoder>,
+            MessageFieldDecoder<F3, PutLumpResponseDecoder>,
+            MessageFieldDecoder<F4, DeleteLumpRequestDecoder>,
+            MessageFieldDecoder<F5, ErrorDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    ScriptOpResultDecoder,
+    cannyls::Result<ScriptOpResult>,
+    |item| Ok(branch_into_script_op_result(item))
+);
+
+#[derive(Debug, Default)]
+pub struct ScriptOpResultEncoder {
+    inner: MessageEncoder<
+        Oneof<(
+            MessageFieldEncoder<F1, HeadLumpResponseEncoder>,
+            MessageFieldEncoder<F2, GetLumpResponseEncoder>,
+            MessageFieldEncoder<F3, PutLumpResponseEncoder>,
+            MessageFieldEncoder<F4, DeleteLumpRequestEncoder>,
+            MessageFieldEncoder<F5, ErrorEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    ScriptOpResultEncoder,
+    cannyls::Result<ScriptOpResult>,
+    |item: Self::Item| match item {
+        Ok(ScriptOpResult::Head(header)) => Branch5::A(Ok(header)),
+        Ok(ScriptOpResult::Get(data)) => Branch5::B(Ok(data)),
+        Ok(ScriptOpResult::Put(created)) => Branch5::C(Ok(created)),
+        Ok(ScriptOpResult::Delete(deleted)) => Branch5::D(Ok(deleted)),
+        Err(e) => Branch5::E(e),
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct ScriptResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<
+                MessageFieldDecoder<F1, ScriptOpResultDecoder>,
+                Vec<cannyls::Result<ScriptOpResult>>,
+            >,
+            Optional<MessageFieldDecoder<F2, ErrorDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    ScriptResponseDecoder,
+    cannyls::Result<Vec<cannyls::Result<ScriptOpResult>>>,
+    |(results, error)| if let Some(error) = error {
+        Ok(Err(error))
+    } else {
+        Ok(Ok(results))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct ScriptResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<
+                MessageFieldEncoder<F1, ScriptOpResultEncoder>,
+                Vec<cannyls::Result<ScriptOpResult>>,
+            >,
+            Optional<MessageFieldEncoder<F2, ErrorEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    ScriptResponseEncoder,
+    cannyls::Result<Vec<cannyls::Result<ScriptOpResult>>>,
+    |item: Self::Item| match item {
+        Err(e) => (Vec::new(), Some(e)),
+        Ok(results) => (results, None),
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct GetLumpsRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            FieldDecoder<F1, StringDecoder>,
+            Repeated<MessageFieldDecoder<F2, LumpIdDecoder>, Vec<LumpId>>,
+            MessageFieldDecoder<F3, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(GetLumpsRequestDecoder, GetLumpsRequest, |(
+    device_id,
+    lump_ids,
+    options,
+)| Ok(
+    GetLumpsRequest {
+        device_id: DeviceId::new(device_id),
+        lump_ids,
+        options,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct GetLumpsRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            FieldEncoder<F1, StringEncoder>,
+            Repeated<MessageFieldEncoder<F2, LumpIdEncoder>, Vec<LumpId>>,
+            MessageFieldEncoder<F3, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    GetLumpsRequestEncoder,
+    GetLumpsRequest,
+    |item: Self::Item| (item.device_id.into_string(), item.lump_ids, item.options)
+);
+
+#[derive(Debug, Default)]
+pub struct GetLumpsResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<
+                MessageFieldDecoder<F1, GetLumpResponseDecoder>,
+                Vec<cannyls::Result<Option<LumpData>>>,
+            >,
+            Optional<MessageFieldDecoder<F2, ErrorDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    GetLumpsResponseDecoder,
+    cannyls::Result<Vec<cannyls::Result<Option<LumpData>>>>,
+    |(results, error)| if let Some(error) = error {
+        Ok(Err(error))
+    } else {
+        Ok(Ok(results))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct GetLumpsResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<
+                MessageFieldEncoder<F1, GetLumpResponseEncoder>,
+                Vec<cannyls::Result<Option<LumpData>>>,
+            >,
+            Optional<MessageFieldEncoder<F2, ErrorEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    GetLumpsResponseEncoder,
+    cannyls::Result<Vec<cannyls::Result<Option<LumpData>>>>,
+    |item: Self::Item| match item {
+        Err(e) => (Vec::new(), Some(e)),
+        Ok(results) => (results, None),
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct PutLumpsRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            FieldDecoder<F1, StringDecoder>,
+            Repeated<MessageFieldDecoder<F2, ScriptPutOpDecoder>, Vec<(LumpId, LumpData)>>,
+            MessageFieldDecoder<F3, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(PutLumpsRequestDecoder, PutLumpsRequest, |(
+    device_id,
+    lumps,
+    options,
+)| Ok(
+    PutLumpsRequest {
+        device_id: DeviceId::new(device_id),
+        lumps,
+        options,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct PutLumpsRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            FieldEncoder<F1, StringEncoder>,
+            Repeated<MessageFieldEncoder<F2, ScriptPutOpEncoder>, Vec<(LumpId, LumpData)>>,
+            MessageFieldEncoder<F3, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    PutLumpsRequestEncoder,
+    PutLumpsRequest,
+    |item: Self::Item| (item.device_id.into_string(), item.lumps, item.options)
+);
+
+#[derive(Debug, Default)]
+pub struct PutLumpsResponseDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<MessageFieldDecoder<F1, PutLumpResponseDecoder>, Vec<cannyls::Result<bool>>>,
+            Optional<MessageFieldDecoder<F2, ErrorDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    PutLumpsResponseDecoder,
+    cannyls::Result<Vec<cannyls::Result<bool>>>,
+    |(results, error)| if let Some(error) = error {
+        Ok(Err(error))
+    } else {
+        Ok(Ok(results))
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct PutLumpsResponseEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<MessageFieldEncoder<F1, PutLumpResponseEncoder>, Vec<cannyls::Result<bool>>>,
+            Optional<MessageFieldEncoder<F2, ErrorEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    PutLumpsResponseEncoder,
+    cannyls::Result<Vec<cannyls::Result<bool>>>,
+    |item: Self::Item| match item {
+        Err(e) => (Vec::new(), Some(e)),
+        Ok(results) => (results, None),
+    }
+);
+
+#[derive(Debug, Default)]
+pub struct DeleteRangeBoundedRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MessageFieldDecoder<F2, LumpIdDecoder>,
+            MessageFieldDecoder<F3, LumpIdDecoder>,
+            MaybeDefault<FieldDecoder<F4, Uint32Decoder>>,
+            MessageFieldDecoder<F5, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    DeleteRangeBoundedRequestDecoder,
+    DeleteRangeBoundedRequest,
+    |(device_id, start, end, max_lumps, options)| Ok(DeleteRangeBoundedRequest {
+        device_id: DeviceId::new(device_id),
+        range: Range { start, end },
+        max_lumps: max_lumps as usize,
+        options,
+    })
+);
+
+#[derive(Debug, Default)]
+pub struct DeleteRangeBoundedRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MessageFieldEncoder<F2, LumpIdEncoder>,
+            MessageFieldEncoder<F3, LumpIdEncoder>,
+            MaybeDefault<FieldEncoder<F4, Uint32Encoder>>,
+            MessageFieldEncoder<F5, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    DeleteRangeBoundedRequestEncoder,
+    DeleteRangeBoundedRequest,
+    |item: Self::Item| (
+        item.device_id.into_string(),
+        item.range.start,
+        item.range.end,
+        item.max_lumps.min(u32::MAX as usize) as u32,
+        item.options,
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct DeleteRangeChunkDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<MessageFieldDecoder<F1, LumpIdDecoder>, Vec<LumpId>>,
+            Optional<MessageFieldDecoder<F2, LumpIdDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(DeleteRangeChunkDecoder, DeleteRangeChunk, |(
+    deleted,
+    next,
+)| Ok(
+    DeleteRangeChunk { deleted, next }
+));
+
+#[derive(Debug, Default)]
+pub struct DeleteRangeChunkEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<MessageFieldEncoder<F1, LumpIdEncoder>, Vec<LumpId>>,
+            Optional<MessageFieldEncoder<F2, LumpIdEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    DeleteRangeChunkEncoder,
+    DeleteRangeChunk,
+    |item: Self::Item| (item.deleted, item.next)
+);
+
+#[derive(Debug, Default)]
+pub struct DeleteRangeBoundedResponseDecoder {
+    inner: MessageDecoder<
+        Oneof<(
+            MessageFieldDecoder<F1, DeleteRangeChunkDecoder>,
+            MessageFieldDecoder<F2, ErrorDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    DeleteRangeBoundedResponseDecoder,
+    cannyls::Result<DeleteRangeChunk>,
+    |item| Ok(branch_into_result(item))
+);
+
+#[derive(Debug, Default)]
+pub struct DeleteRangeBoundedResponseEncoder {
+    inner: MessageEncoder<
+        Oneof<(
+            MessageFieldEncoder<F1, PreEncode<DeleteRangeChunkEncoder>>,
+            MessageFieldEncoder<F2, ErrorEncoder>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    DeleteRangeBoundedResponseEncoder,
+    cannyls::Result<DeleteRangeChunk>,
+    |item: Self::Item| result_into_branch(item)
+);
+
+#[derive(Debug, Default)]
+pub struct GetLumpRangeRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MessageFieldDecoder<F2, LumpIdDecoder>,
+            MaybeDefault<FieldDecoder<F3, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F4, Uint64Decoder>>,
+            MessageFieldDecoder<F5, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(GetLumpRangeRequestDecoder, GetLumpRangeRequest, |(
+    device_id,
+    lump_id,
+    start,
+    end,
+    options,
+)| Ok(
+    GetLumpRangeRequest {
+        device_id: DeviceId::new(device_id),
+        lump_id,
+        byte_range: Range { start, end },
+        options,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct GetLumpRangeRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MessageFieldEncoder<F2, LumpIdEncoder>,
+            MaybeDefault<FieldEncoder<F3, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F4, Uint64Encoder>>,
+            MessageFieldEncoder<F5, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    GetLumpRangeRequestEncoder,
+    GetLumpRangeRequest,
+    |item: Self::Item| (
+        item.device_id.into_string(),
+        item.lump_id,
+        item.byte_range.start,
+        item.byte_range.end,
+        item.options,
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct ExportLumpsRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            MessageFieldDecoder<F2, LumpIdDecoder>,
+            MessageFieldDecoder<F3, LumpIdDecoder>,
+            MaybeDefault<FieldDecoder<F4, Uint32Decoder>>,
+            MessageFieldDecoder<F5, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(ExportLumpsRequestDecoder, ExportLumpsRequest, |(
+    device_id,
+    start,
+    end,
+    max_lumps,
+    options,
+)| Ok(
+    ExportLumpsRequest {
+        device_id: DeviceId::new(device_id),
+        range: Range { start, end },
+        max_lumps: max_lumps as usize,
+        options,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct ExportLumpsRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            MessageFieldEncoder<F2, LumpIdEncoder>,
+            MessageFieldEncoder<F3, LumpIdEncoder>,
+            MaybeDefault<FieldEncoder<F4, Uint32Encoder>>,
+            MessageFieldEncoder<F5, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    ExportLumpsRequestEncoder,
+    ExportLumpsRequest,
+    |item: Self::Item| (
+        item.device_id.into_string(),
+        item.range.start,
+        item.range.end,
+        item.max_lumps.min(u32::MAX as usize) as u32,
+        item.options,
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct ExportLumpsChunkDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<MessageFieldDecoder<F1, ScriptPutOpDecoder>, Vec<(LumpId, LumpData)>>,
+            Optional<MessageFieldDecoder<F2, LumpIdDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(ExportLumpsChunkDecoder, ExportLumpsChunk, |(
+    lumps,
+    next,
+)| Ok(
+    ExportLumpsChunk { lumps, next }
+));
+
+#[derive(Debug, Default)]
+pub struct ExportLumpsChunkEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<MessageFieldEncoder<F1, ScriptPutOpEncoder>, Vec<(LumpId, LumpData)>>,
+            Optional<MessageFieldEncoder<F2, LumpIdEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    ExportLumpsChunkEncoder,
+    ExportLumpsChunk,
+    |item: Self::Item| (item.lumps, item.next)
+);
+
+#[derive(Debug, Default)]
+pub struct ExportLumpsResponseDecoder {
+    inner: MessageDecoder<
+        Oneof<(
+            MessageFieldDecoder<F1, ExportLumpsChunkDecoder>,
+            MessageFieldDecoder<F2, ErrorDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    ExportLumpsResponseDecoder,
+    cannyls::Result<ExportLumpsChunk>,
+    |item| Ok(branch_into_result(item))
+);
+
+#[derive(Debug, Default)]
+pub struct ExportLumpsResponseEncoder {
+    inner: MessageEncoder<
+        Oneof<(
+            MessageFieldEncoder<F1, PreEncode<ExportLumpsChunkEncoder>>,
+            MessageFieldEncoder<F2, ErrorEncoder>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    ExportLumpsResponseEncoder,
+    cannyls::Result<ExportLumpsChunk>,
+    |item: Self::Item| result_into_branch(item)
+);
+
+#[derive(Debug, Default)]
+pub struct ListLumpsChunkDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            Repeated<MessageFieldDecoder<F1, LumpIdDecoder>, Vec<LumpId>>,
+            Optional<MessageFieldDecoder<F2, LumpIdDecoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    ListLumpsChunkDecoder,
+    ListLumpsChunk,
+    |(lump_ids, next)| Ok(ListLumpsChunk { lump_ids, next })
+);
+
+#[derive(Debug, Default)]
+pub struct ListLumpsChunkEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            Repeated<MessageFieldEncoder<F1, LumpIdEncoder>, Vec<LumpId>>,
+            Optional<MessageFieldEncoder<F2, LumpIdEncoder>>,
+        )>,
+    >,
+}
+impl_message_encode!(ListLumpsChunkEncoder, ListLumpsChunk, |item: Self::Item| (
+    item.lump_ids,
+    item.next
+));
+
+#[derive(Debug, Default)]
+pub struct ListLumpsChunkResponseDecoder {
+    inner: MessageDecoder<
+        Oneof<(
+            MessageFieldDecoder<F1, ListLumpsChunkDecoder>,
+            MessageFieldDecoder<F2, ErrorDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    ListLumpsChunkResponseDecoder,
+    cannyls::Result<ListLumpsChunk>,
+    |item| Ok(branch_into_result(item))
+);
+
+#[derive(Debug, Default)]
+pub struct ListLumpsChunkResponseEncoder {
+    inner: MessageEncoder<
+        Oneof<(
+            MessageFieldEncoder<F1, PreEncode<ListLumpsChunkEncoder>>,
+            MessageFieldEncoder<F2, ErrorEncoder>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    ListLumpsChunkResponseEncoder,
+    cannyls::Result<ListLumpsChunk>,
+    |item: Self::Item| result_into_branch(item)
+);
+
+#[derive(Debug, Default)]
+pub struct ImportLumpsRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F2, Uint64Decoder>>,
+            Repeated<MessageFieldDecoder<F3, ScriptPutOpDecoder>, Vec<(LumpId, LumpData)>>,
+            MessageFieldDecoder<F4, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(ImportLumpsRequestDecoder, ImportLumpsRequest, |(
+    session_id,
+    batch_seq,
+    lumps,
+    options,
+)| Ok(
+    ImportLumpsRequest {
+        session_id,
+        batch_seq,
+        lumps,
+        options,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct ImportLumpsRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F2, Uint64Encoder>>,
+            Repeated<MessageFieldEncoder<F3, ScriptPutOpEncoder>, Vec<(LumpId, LumpData)>>,
+            MessageFieldEncoder<F4, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    ImportLumpsRequestEncoder,
+    ImportLumpsRequest,
+    |item: Self::Item| (item.session_id, item.batch_seq, item.lumps, item.options)
+);
+
+#[derive(Debug, Default)]
+pub struct ImportSessionRequestDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, Uint64Decoder>>,
+            MessageFieldDecoder<F2, RequestOptionsDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(ImportSessionRequestDecoder, ImportSessionRequest, |(
+    session_id,
+    options,
+)| Ok(
+    ImportSessionRequest {
+        session_id,
+        options
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct ImportSessionRequestEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, Uint64Encoder>>,
+            MessageFieldEncoder<F2, RequestOptionsEncoder>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    ImportSessionRequestEncoder,
+    ImportSessionRequest,
+    |item: Self::Item| (item.session_id, item.options)
+);
+
+#[derive(Debug, Default)]
+pub struct ImportSessionStatusDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F2, StringDecoder>>,
+            MaybeDefault<FieldDecoder<F3, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F4, Uint64Decoder>>,
+            MaybeDefault<FieldDecoder<F5, Uint64Decoder>>,
+        )>,
+    >,
+}
+impl_message_decode!(ImportSessionStatusDecoder, ImportSessionStatus, |(
+    session_id,
+    device_id,
+    next_batch_seq,
+    imported_lumps,
+    imported_bytes,
+)| Ok(
+    ImportSessionStatus {
+        session_id,
+        device_id: DeviceId::new(device_id),
+        next_batch_seq,
+        imported_lumps,
+        imported_bytes,
+    }
+));
+
+#[derive(Debug, Default)]
+pub struct ImportSessionStatusEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F2, StringEncoder>>,
+            MaybeDefault<FieldEncoder<F3, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F4, Uint64Encoder>>,
+            MaybeDefault<FieldEncoder<F5, Uint64Encoder>>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    ImportSessionStatusEncoder,
+    ImportSessionStatus,
+    |item: Self::Item| (
+        item.session_id,
+        item.device_id.into_string(),
+        item.next_batch_seq,
+        item.imported_lumps,
+        item.imported_bytes,
+    )
+);
+
+#[derive(Debug, Default)]
+pub struct ImportSessionResponseDecoder {
+    inner: MessageDecoder<
+        Oneof<(
+            MessageFieldDecoder<F1, ImportSessionStatusDecoder>,
+            MessageFieldDecoder<F2, ErrorDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    ImportSessionResponseDecoder,
+    cannyls::Result<ImportSessionStatus>,
+    |item| Ok(branch_into_result(item))
+);
+
+#[derive(Debug, Default)]
+pub struct ImportSessionResponseEncoder {
+    inner: MessageEncoder<
+        Oneof<(
+            MessageFieldEncoder<F1, ImportSessionStatusEncoder>,
+            MessageFieldEncoder<F2, ErrorEncoder>,
+        )>,
+    >,
+}
+impl_sized_message_encode!(
+    ImportSessionResponseEncoder,
+    cannyls::Result<ImportSessionStatus>,
+    |item: Self::Item| result_into_branch(item)
+);
+
+pub type ServerInfoRequestDecoder = EmptyMessageDecoder;
+pub type ServerInfoRequestEncoder = EmptyMessageEncoder;
+
+#[derive(Debug, Default)]
+pub struct ServerInfoDecoder {
+    inner: MessageDecoder<
+        Fields<(
+            MaybeDefault<FieldDecoder<F1, StringDecoder>>,
+            Repeated<FieldDecoder<F2, Uint32Decoder>, Vec<u32>>,
+        )>,
+    >,
+}
+impl_message_decode!(ServerInfoDecoder, ServerInfo, |(version, procedures): (
+    String,
+    Vec<u32>
+)| {
+    let mut procedures = procedures.into_iter().map(ProcedureId).collect::<Vec<_>>();
+    procedures.sort();
+    Ok(ServerInfo {
+        version,
+        procedures,
+    })
+});
+
+#[derive(Debug, Default)]
+pub struct ServerInfoEncoder {
+    inner: MessageEncoder<
+        Fields<(
+            MaybeDefault<FieldEncoder<F1, StringEncoder>>,
+            Repeated<FieldEncoder<F2, Uint32Encoder>, Vec<u32>>,
+        )>,
+    >,
+}
+impl_message_encode!(ServerInfoEncoder, ServerInfo, |item: Self::Item| (
+    item.version,
+    item.procedures.into_iter().map(|p| p.0).collect()
+));
+
+#[derive(Debug, Default)]
+pub struct ServerInfoResponseDecoder {
+    inner: MessageDecoder<
+        Oneof<(
+            MessageFieldDecoder<F1, ServerInfoDecoder>,
+            MessageFieldDecoder<F2, ErrorDecoder>,
+        )>,
+    >,
+}
+impl_message_decode!(
+    ServerInfoResponseDecoder,
+    cannyls::Result<ServerInfo>,
+    |item| Ok(branch_into_result(item))
+);
+
+#[derive(Debug, Default)]
+pub struct ServerInfoResponseEncoder {
+    inner: MessageEncoder<
+        Oneof<(
+            MessageFieldEncoder<F1, PreEncode<ServerInfoEncoder>>,
+            MessageFieldEncoder<F2, ErrorEncoder>,
+        )>,
+    >,
+}
+impl_message_encode!(
+    ServerInfoResponseEncoder,
+    cannyls::Result<ServerInfo>,
+    |item: Self::Item| result_into_branch(item)
+);
+
+fn branch_into_script_op_result(
+    branch: Branch5<
+        cannyls::Result<Option<LumpHeader>>,
+        cannyls::Result<Option<LumpData>>,
+        cannyls::Result<bool>,
+        cannyls::Result<bool>,
+        cannyls::Error,
+    >,
+) -> cannyls::Result<ScriptOpResult> {
+    match branch {
+        Branch5::A(result) => result.map(ScriptOpResult::Head),
+        Branch5::B(result) => result.map(ScriptOpResult::Get),
+        Branch5::C(result) => result.map(ScriptOpResult::Put),
+        Branch5::D(result) => result.map(ScriptOpResult::Delete),
+        Branch5::E(error) => Err(error),
+    }
+}
+
+// キューの長さ制限は、`0`が「制限なし」を表すように、実際の値に`1`を加えて送受信される.
+fn encode_queue_len(max_queue_len: Option<usize>) -> u32 {
+    max_queue_len.map_or(0, |n| n as u32 + 1)
+}
+
+fn decode_queue_len(queue_size_limit: u32) -> Option<usize> {
+    if queue_size_limit == 0 {
+        None
+    } else {
+        Some(queue_size_limit as usize - 1)
+    }
+}
+
+fn encode_write_watermark(write_watermark: Option<u8>) -> u32 {
+    write_watermark.map_or(0, |n| u32::from(n) + 1)
+}
+
+fn decode_write_watermark(write_watermark: u32) -> Option<u8> {
+    if write_watermark == 0 {
+        None
+    } else {
+        // 範囲外の値の検証はサーバ側で行われる
+        Some((write_watermark - 1).min(u32::from(u8::MAX)) as u8)
+    }
+}
+
+fn result_into_branch<T, E>(result: std::result::Result<T, E>) -> Branch2<T, E> {
+    match result {
+        Ok(a) => Branch2::A(a),
+        Err(b) => Branch2::B(b),
+    }
+}
+
+fn decode_device_status(status: u32) -> Result<DeviceStatus> {
+    Ok(match status {
+        0 => DeviceStatus::Stopped,
+        1 => DeviceStatus::Starting,
+        2 => DeviceStatus::Running,
+        _ => track_panic!(ErrorKind::InvalidInput, "Unknown device status: {}", status),
+    })
+}
+
+fn optional_result_into_branch<T, E>(
+    result: std::result::Result<Option<T>, E>,
+) -> Option<Branch2<T, E>> {
+    match result {
+        Ok(Some(a)) => Some(Branch2::A(a)),
+        Ok(None) => None,
+        Err(b) => Some(Branch2::B(b)),
+    }
+}
+
+// `*WithMetaRpc`の応答の、結果とメタ情報とを結合する(エラー時にはメタ情報は付与されない).
+//
+// なお`Oneof`のデコーダは、後続の別のフィールドを検知した時点で、それまでのデコード結果を破棄してしまうため、
+// これらの応答では、メタ情報のフィールドを`oneof`よりも前に配置(エンコード)している.
+fn join_meta<T>(
+    result: cannyls::Result<T>,
+    meta: Option<ResponseMeta>,
+) -> cannyls::Result<(T, Option<ResponseMeta>)> {
+    result.map(|v| (v, meta))
+}
+
+// `join_meta`の逆変換.
+fn split_meta<T>(
+    result: cannyls::Result<(T, Option<ResponseMeta>)>,
+) -> (cannyls::Result<T>, Option<ResponseMeta>) {
+    match result {
+        Ok((v, meta)) => (Ok(v), meta),
+        Err(e) => (Err(e), None),
+    }
+}
+
+fn branch_into_result<T, E>(branch: Branch2<T, E>) -> std::result::Result<T, E> {
+    match branch {
+        Branch2::A(a) => Ok(a),
+        Branch2::B(b) => Err(b),
+    }
+}
+
+fn branch_into_optional_result<T, E>(
+    branch: Option<Branch2<T, E>>,
+) -> std::result::Result<Option<T>, E> {
+    match branch {
+        None => Ok(None),
+        Some(Branch2::A(a)) => Ok(Some(a)),
+        Some(Branch2::B(b)) => Err(b),
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use ::trackable::Trackable;
+    use bytecodec::io::IoEncodeExt;
     use bytecodec::{DecodeExt, EncodeExt};
     use cannyls::deadline::Deadline;
+    use std::sync::{Arc, Mutex};
     use std::time::Duration;
 
     use super::*;
@@ -905,18 +4206,164 @@ mod tests {
             let mut encoder: $encoder = Default::default();
             let mut decoder: $decoder = Default::default();
 
-            let bytes = track_try_unwrap!(encoder.encode_into_bytes($value()));
-            let decoded = track_try_unwrap!(track!(decoder.decode_from_bytes(&bytes); bytes));
-            assert_eq!(decoded, $value());
+            let bytes = track_try_unwrap!(encoder.encode_into_bytes($value()));
+            let decoded = track_try_unwrap!(track!(decoder.decode_from_bytes(&bytes); bytes));
+            assert_eq!(decoded, $value());
+        };
+    }
+
+    #[test]
+    fn lump_id_encdec_works() {
+        assert_encdec!(LumpIdEncoder, LumpIdDecoder, || LumpId::new(0));
+        assert_encdec!(LumpIdEncoder, LumpIdDecoder, || LumpId::new(
+            (123 << 64) | 345
+        ));
+    }
+
+    #[test]
+    fn error_encdec_works() {
+        let mut encoder = ErrorEncoder::default();
+        let mut decoder = ErrorDecoder::default();
+
+        let e = cannyls::Error::from(cannyls::ErrorKind::InvalidInput.cause("foo"));
+        let bytes = track_try_unwrap!(encoder.encode_into_bytes(e));
+        let decoded = track_try_unwrap!(decoder.decode_from_bytes(&bytes));
+        assert_eq!(*decoded.kind(), cannyls::ErrorKind::InvalidInput);
+        assert!(BusyHint::of(&decoded).is_none());
+
+        let hint = BusyHint {
+            queue_len: 10,
+            retry_after: Duration::from_millis(10),
         };
+        let e = track!(hint
+            .clone()
+            .into_error(&cannyls::ErrorKind::DeviceBusy.error()));
+        let bytes = track_try_unwrap!(encoder.encode_into_bytes(e));
+        let decoded = track_try_unwrap!(decoder.decode_from_bytes(&bytes));
+        assert_eq!(*decoded.kind(), cannyls::ErrorKind::DeviceBusy);
+        assert_eq!(BusyHint::of(&decoded), Some(&hint));
+        assert_eq!(decoded.history().map(|h| h.events().len()), Some(1));
     }
 
     #[test]
-    fn lump_id_encdec_works() {
-        assert_encdec!(LumpIdEncoder, LumpIdDecoder, || LumpId::new(0));
-        assert_encdec!(LumpIdEncoder, LumpIdDecoder, || LumpId::new(
-            (123 << 64) | 345
+    fn error_cause_chain_encdec_works() {
+        let mut encoder = ErrorEncoder::default();
+        let mut decoder = ErrorDecoder::default();
+
+        // 通常のエラー: 原因の文字列は、従来通り末尾の要素として保持される
+        let e = track!(cannyls::Error::from(
+            cannyls::ErrorKind::InvalidInput.cause("foo")
         ));
+        let bytes = track_try_unwrap!(encoder.encode_into_bytes(e));
+        let decoded = track_try_unwrap!(decoder.decode_from_bytes(&bytes));
+        let cause = RemoteCause::of(&decoded).unwrap();
+        assert_eq!(cause.kind(), None);
+        assert_eq!(cause.message(), "foo");
+        assert!(cause.cause().is_none());
+        assert_eq!(decoded.history().map(|h| h.events().len()), Some(1));
+
+        // 入れ子になったエラー
+        let leaf = cannyls::Error::from(cannyls::ErrorKind::StorageFull.cause("no space"));
+        let leaf = track!(leaf);
+        let e = fibers_rpc::Error::from(fibers_rpc::ErrorKind::Unavailable.cause(leaf));
+        let e = track!(cannyls::Error::from(cannyls::ErrorKind::Other.cause(e)));
+        let bytes = track_try_unwrap!(encoder.encode_into_bytes(e.clone()));
+        let decoded = track_try_unwrap!(decoder.decode_from_bytes(&bytes));
+        assert_eq!(*decoded.kind(), cannyls::ErrorKind::Other);
+
+        let mut kinds = Vec::new();
+        let mut cause = RemoteCause::of(&decoded);
+        while let Some(c) = cause {
+            kinds.push(c.kind().map(|k| k.to_owned()));
+            if c.cause().is_none() {
+                assert_eq!(c.message(), "no space");
+            }
+            cause = c.cause();
+        }
+        assert_eq!(
+            kinds,
+            [Some("Unavailable"), Some("StorageFull"), None]
+                .iter()
+                .map(|k| k.map(|k| k.to_owned()))
+                .collect::<Vec<_>>()
+        );
+        let storage_full = RemoteCause::of(&decoded).and_then(|c| c.cause()).unwrap();
+        assert_eq!(storage_full.history().len(), 1);
+
+        // 表示形式は元のエラーと同様
+        assert_eq!(decoded.to_string(), e.to_string());
+
+        // 再エンコードしても連鎖は失われない(e.g., プロキシ越しの場合)
+        let bytes = track_try_unwrap!(encoder.encode_into_bytes(decoded.clone()));
+        let redecoded = track_try_unwrap!(decoder.decode_from_bytes(&bytes));
+        assert_eq!(redecoded.to_string(), decoded.to_string());
+    }
+
+    #[test]
+    fn error_code_works() {
+        for &(kind, code) in ERROR_KIND_CODES {
+            assert_eq!(error_kind_to_code(kind), code);
+            assert_eq!(error_kind_from_code(code), Some(kind));
+        }
+        assert_eq!(error_kind_from_code(0), None);
+        assert_eq!(error_kind_from_code(100), None);
+
+        // コードが未指定(e.g., 古いサーバ)の場合には、文字列表現で判定される
+        let mut decoder = ErrorDecoder::default();
+        let mut bytes = vec![0x0A, 13, 0x0A, 11]; // `Error.error.kind`のみを含むメッセージ
+        bytes.extend_from_slice(b"StorageFull");
+        let decoded = track_try_unwrap!(decoder.decode_from_bytes(&bytes));
+        assert_eq!(*decoded.kind(), cannyls::ErrorKind::StorageFull);
+    }
+
+    #[test]
+    fn response_meta_encdec_works() {
+        let meta = || ResponseMeta {
+            processing_time: Duration::from_micros(1234),
+            queue_len: 3,
+            server_version: "1.2.3".to_owned(),
+        };
+        assert_encdec!(ResponseMetaEncoder, ResponseMetaDecoder, meta);
+
+        let mut encoder = GetLumpWithMetaResponseEncoder::default();
+        let mut decoder = GetLumpWithMetaResponseDecoder::default();
+        let data = track_try_unwrap!(LumpData::new(vec![1, 2]));
+        let bytes = track_try_unwrap!(encoder.encode_into_bytes(Ok((Some(data), Some(meta())))));
+        let (data, decoded_meta) = track_try_unwrap!(decoder.decode_from_bytes(&bytes)).unwrap();
+        assert_eq!(data.map(|d| d.into_bytes()), Some(vec![1, 2]));
+        assert_eq!(decoded_meta, Some(meta()));
+
+        let mut encoder = HeadLumpWithMetaResponseEncoder::default();
+        let mut decoder = HeadLumpWithMetaResponseDecoder::default();
+        let bytes = track_try_unwrap!(encoder.encode_into_bytes(Ok((None, Some(meta())))));
+        let (header, decoded_meta) = track_try_unwrap!(decoder.decode_from_bytes(&bytes)).unwrap();
+        assert!(header.is_none());
+        assert_eq!(decoded_meta, Some(meta()));
+
+        // エラー時にはメタ情報は付与されない
+        let mut encoder = PutLumpWithMetaResponseEncoder::default();
+        let mut decoder = PutLumpWithMetaResponseDecoder::default();
+        let e = cannyls::Error::from(cannyls::ErrorKind::StorageFull.error());
+        let bytes = track_try_unwrap!(encoder.encode_into_bytes(Err(e)));
+        let decoded = track_try_unwrap!(decoder.decode_from_bytes(&bytes));
+        assert_eq!(
+            *decoded.err().unwrap().kind(),
+            cannyls::ErrorKind::StorageFull
+        );
+
+        // メタ情報に対応していないクライアントは、メタ情報を無視する
+        let mut encoder = PutLumpWithMetaResponseEncoder::default();
+        let bytes = track_try_unwrap!(encoder.encode_into_bytes(Ok((false, Some(meta())))));
+        let mut decoder = PutLumpResponseDecoder::default();
+        let decoded = track_try_unwrap!(decoder.decode_from_bytes(&bytes));
+        assert_eq!(decoded.ok(), Some(false));
+
+        // メタ情報に対応していないサーバからの応答では、メタ情報は`None`となる
+        let mut encoder = GetLumpResponseEncoder::default();
+        let bytes = track_try_unwrap!(encoder.encode_into_bytes(Ok(None)));
+        let mut decoder = GetLumpWithMetaResponseDecoder::default();
+        let decoded = track_try_unwrap!(decoder.decode_from_bytes(&bytes));
+        assert_eq!(decoded.ok(), Some((None, None)));
     }
 
     #[test]
@@ -938,6 +4385,11 @@ mod tests {
                 deadline: Deadline::Immediate,
                 max_queue_len: None,
                 prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
             }
         });
         assert_encdec!(RequestOptionsEncoder, RequestOptionsDecoder, || {
@@ -945,6 +4397,11 @@ mod tests {
                 deadline: Deadline::Infinity,
                 max_queue_len: Some(0),
                 prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
             }
         });
         assert_encdec!(RequestOptionsEncoder, RequestOptionsDecoder, || {
@@ -952,10 +4409,74 @@ mod tests {
                 deadline: Deadline::Infinity,
                 max_queue_len: Some(123),
                 prioritized: true,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
+            }
+        });
+        assert_encdec!(RequestOptionsEncoder, RequestOptionsDecoder, || {
+            RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: false,
+                journal_sync: false,
+                verbose_errors: true,
+                response_meta: true,
+                trace_id: Some("foo".to_owned()),
+                signature: None,
+            }
+        });
+        assert_encdec!(RequestOptionsEncoder, RequestOptionsDecoder, || {
+            RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: Some(RequestSignature {
+                    key_id: "foo".to_owned(),
+                    timestamp: 1_234_567_890_123,
+                    nonce: u64::MAX,
+                    mac: vec![1; 32],
+                }),
             }
         });
     }
 
+    #[test]
+    fn lump_request_encdec_works() {
+        let request = LumpRequest {
+            device_id: DeviceId::new("device"),
+            lump_id: LumpId::new(1),
+            options: RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
+            },
+            precondition: None,
+        };
+        assert_encdec!(LumpRequestEncoder, LumpRequestDecoder, || request.clone());
+
+        let request = LumpRequest {
+            precondition: Some(Precondition {
+                if_exists: true,
+                if_size_equals: Some(0),
+                if_not_exists: false,
+            }),
+            ..request
+        };
+        assert_encdec!(LumpRequestEncoder, LumpRequestDecoder, || request.clone());
+    }
+
     #[test]
     fn usage_range_request_encdec_works() {
         let request = UsageRangeRequest {
@@ -968,6 +4489,11 @@ mod tests {
                 deadline: Deadline::Infinity,
                 max_queue_len: Some(123),
                 prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
             },
         };
         assert_encdec!(UsageRangeRequestEncoder, UsageRangeRequestDecoder, || {
@@ -987,10 +4513,595 @@ mod tests {
                 deadline: Deadline::Infinity,
                 max_queue_len: Some(123),
                 prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
             },
         };
         assert_encdec!(RangeLumpRequestEncoder, RangeLumpRequestDecoder, || {
             request.clone()
         });
     }
+
+    #[test]
+    fn copy_range_request_encdec_works() {
+        let request = CopyRangeRequest {
+            source_device_id: DeviceId::new("src"),
+            destination_device_id: DeviceId::new("dst"),
+            range: Range {
+                start: LumpId::new(1),
+                end: LumpId::new(3),
+            },
+            options: RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: true,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
+            },
+        };
+        assert_encdec!(CopyRangeRequestEncoder, CopyRangeRequestDecoder, || {
+            request.clone()
+        });
+    }
+
+    #[test]
+    fn delete_range_bounded_encdec_works() {
+        let request = DeleteRangeBoundedRequest {
+            device_id: DeviceId::new("device"),
+            range: Range {
+                start: LumpId::new(1),
+                end: LumpId::new(300),
+            },
+            max_lumps: 100,
+            options: RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
+            },
+        };
+        assert_encdec!(
+            DeleteRangeBoundedRequestEncoder,
+            DeleteRangeBoundedRequestDecoder,
+            || request.clone()
+        );
+
+        let chunk = DeleteRangeChunk {
+            deleted: vec![LumpId::new(1), LumpId::new(2)],
+            next: Some(LumpId::new(3)),
+        };
+        assert_encdec!(DeleteRangeChunkEncoder, DeleteRangeChunkDecoder, || chunk
+            .clone());
+    }
+
+    #[test]
+    fn device_metrics_snapshot_encdec_works() {
+        let snapshot = DeviceMetricsSnapshot {
+            device_id: DeviceId::new("device"),
+            status: DeviceStatus::Running,
+            queue_len: 1,
+            enqueued_commands: 10,
+            dequeued_commands: 9,
+            failed_commands: 2,
+            busy_commands: 1,
+            storage: None,
+        };
+        assert_encdec!(
+            DeviceMetricsSnapshotEncoder,
+            DeviceMetricsSnapshotDecoder,
+            || snapshot.clone()
+        );
+
+        let snapshot = DeviceMetricsSnapshot {
+            status: DeviceStatus::Stopped,
+            storage: Some(StorageMetricsSnapshot {
+                lumps: 3,
+                put_lumps: 4,
+                delete_lumps: 1,
+                data_capacity_bytes: 1024,
+                data_usage_bytes: 512,
+                journal: JournalUsage {
+                    capacity_bytes: 100,
+                    usage_bytes: 10,
+                    consumed_bytes: 20,
+                    released_bytes: 10,
+                    records: 2,
+                    record_counts: JournalRecordCounts {
+                        put: 1,
+                        embed: 0,
+                        delete: 1,
+                        delete_range: 0,
+                    },
+                },
+            }),
+            ..snapshot
+        };
+        assert_encdec!(
+            DeviceMetricsSnapshotEncoder,
+            DeviceMetricsSnapshotDecoder,
+            || snapshot.clone()
+        );
+    }
+
+    #[test]
+    fn in_flight_request_encdec_works() {
+        let request = InFlightRequest {
+            request_id: 10,
+            procedure: "cannyls.lump.get".to_owned(),
+            device_id: DeviceId::new("device"),
+            target: RequestTarget::Lump(LumpId::new(3)),
+            elapsed: Duration::from_millis(12),
+            deadline: Deadline::Within(Duration::from_secs(1)),
+        };
+        assert_encdec!(InFlightRequestEncoder, InFlightRequestDecoder, || request
+            .clone());
+
+        let request = InFlightRequest {
+            target: RequestTarget::Range(Range {
+                start: LumpId::new(1),
+                end: LumpId::new(5),
+            }),
+            deadline: Deadline::Infinity,
+            ..request
+        };
+        assert_encdec!(InFlightRequestEncoder, InFlightRequestDecoder, || request
+            .clone());
+
+        let request = InFlightRequest {
+            target: RequestTarget::Device,
+            ..request
+        };
+        assert_encdec!(InFlightRequestEncoder, InFlightRequestDecoder, || request
+            .clone());
+    }
+
+    #[test]
+    fn device_settings_encdec_works() {
+        assert_encdec!(DeviceSettingsEncoder, DeviceSettingsDecoder, || {
+            DeviceSettings::default()
+        });
+        assert_encdec!(DeviceSettingsEncoder, DeviceSettingsDecoder, || {
+            DeviceSettings {
+                journal_sync: true,
+                default_max_queue_len: Some(0),
+                max_queue_len_limit: Some(100),
+                write_watermark: Some(0),
+            }
+        });
+        assert_encdec!(DeviceSettingsEncoder, DeviceSettingsDecoder, || {
+            DeviceSettings {
+                write_watermark: Some(100),
+                ..DeviceSettings::default()
+            }
+        });
+    }
+
+    #[test]
+    fn request_stats_encdec_works() {
+        let stats = RequestStats {
+            device_id: DeviceId::new("device"),
+            procedure: "cannyls.lump.put".to_owned(),
+            succeeded: 100,
+            failed: vec![
+                (cannyls::ErrorKind::DeviceBusy, 3),
+                (cannyls::ErrorKind::InvalidInput, 1),
+            ],
+            period: Duration::from_secs(60),
+        };
+        assert_encdec!(RequestStatsEncoder, RequestStatsDecoder, || stats.clone());
+    }
+
+    #[test]
+    fn device_readiness_encdec_works() {
+        assert_encdec!(DeviceReadinessEncoder, DeviceReadinessDecoder, || {
+            DeviceReadiness {
+                device_id: DeviceId::new("device"),
+                error: None,
+                latency: Duration::from_millis(3),
+            }
+        });
+        assert_encdec!(DeviceReadinessEncoder, DeviceReadinessDecoder, || {
+            DeviceReadiness {
+                device_id: DeviceId::new("device"),
+                error: Some(cannyls::ErrorKind::DeviceBusy),
+                latency: Duration::from_millis(0),
+            }
+        });
+    }
+
+    #[test]
+    fn put_lump_from_reader_request_encoder_works() {
+        let options = || RequestOptions {
+            deadline: Deadline::Infinity,
+            max_queue_len: Some(10),
+            prioritized: false,
+            journal_sync: false,
+            verbose_errors: false,
+            response_meta: false,
+            trace_id: None,
+            signature: None,
+        };
+        let precondition = || Precondition {
+            if_exists: false,
+            if_size_equals: Some(1024),
+            if_not_exists: true,
+        };
+        let bytes = vec![3; 1024];
+
+        let mut encoder = PutLumpRequestEncoder::default();
+        let expected = track_try_unwrap!(encoder.encode_into_bytes(PutLumpRequest {
+            device_id: DeviceId::new("device"),
+            lump_id: LumpId::new(1),
+            lump_data: LumpData::new(bytes.clone()).unwrap(),
+            options: options(),
+            precondition: Some(precondition()),
+            checksum: None,
+        }));
+
+        let request = |data_size| PutLumpFromReaderRequest {
+            device_id: DeviceId::new("device"),
+            lump_id: LumpId::new(1),
+            reader: Box::new(std::io::Cursor::new(bytes.clone())),
+            data_size,
+            options: options(),
+            precondition: Some(precondition()),
+        };
+        let mut encoder = PutLumpFromReaderRequestEncoder::default();
+        let actual = track_try_unwrap!(encoder.encode_into_bytes(request(1024)));
+        assert_eq!(actual, expected);
+
+        // 読み込み元のデータが足りない
+        let mut encoder = PutLumpFromReaderRequestEncoder::default();
+        track_try_unwrap!(encoder.start_encoding(request(1025)));
+        let e = encoder.encode_all(&mut Vec::new()).err().unwrap();
+        assert_eq!(*e.kind(), ErrorKind::UnexpectedEos);
+    }
+
+    #[test]
+    fn get_lump_to_writer_response_decoder_works() {
+        #[derive(Clone, Default)]
+        struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+        impl Write for SharedBuf {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let decode = |response: cannyls::Result<Option<LumpData>>| {
+            let buf = SharedBuf::default();
+            let mut encoder = GetLumpResponseEncoder::default();
+            let mut decoder = GetLumpToWriterResponseDecoder::new(buf.clone());
+            let bytes = track_try_unwrap!(encoder.encode_into_bytes(response));
+            let decoded = track_try_unwrap!(decoder.decode_from_bytes(&bytes));
+            let written = buf.0.lock().unwrap().clone();
+            (decoded, written)
+        };
+
+        let data = LumpData::new(vec![7; 1024]).unwrap();
+        let (decoded, written) = decode(Ok(Some(data)));
+        assert_eq!(decoded.ok(), Some(Some(1024)));
+        assert_eq!(written, vec![7; 1024]);
+
+        let data = LumpData::new(Vec::new()).unwrap();
+        let (decoded, written) = decode(Ok(Some(data)));
+        assert_eq!(decoded.ok(), Some(Some(0)));
+        assert!(written.is_empty());
+
+        let (decoded, written) = decode(Ok(None));
+        assert_eq!(decoded.ok(), Some(None));
+        assert!(written.is_empty());
+
+        let (decoded, _) = decode(Err(cannyls::ErrorKind::InvalidInput.into()));
+        assert_eq!(
+            *decoded.err().unwrap().kind(),
+            cannyls::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn checksummed_lump_data_encdec_works() {
+        assert_encdec!(
+            ChecksummedLumpDataEncoder,
+            ChecksummedLumpDataDecoder,
+            || (LumpData::new(vec![1, 2, 3]).unwrap(), 0x1234_5678)
+        );
+        assert_encdec!(
+            ChecksummedLumpDataEncoder,
+            ChecksummedLumpDataDecoder,
+            || (LumpData::new(Vec::new()).unwrap(), 0)
+        );
+    }
+
+    #[test]
+    fn script_request_encdec_works() {
+        let request = ScriptRequest {
+            device_id: DeviceId::new("device"),
+            ops: vec![
+                ScriptOp::Put(LumpId::new(1), LumpData::new(b"foo".to_vec()).unwrap()),
+                ScriptOp::Head(LumpId::new(1)),
+                ScriptOp::Get(LumpId::new(2)),
+                ScriptOp::Delete(LumpId::new(3)),
+            ],
+            options: RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
+            },
+        };
+        assert_encdec!(ScriptRequestEncoder, ScriptRequestDecoder, || {
+            request.clone()
+        });
+    }
+
+    #[test]
+    fn get_lumps_request_encdec_works() {
+        let request = GetLumpsRequest {
+            device_id: DeviceId::new("device"),
+            lump_ids: vec![LumpId::new(1), LumpId::new(3), LumpId::new(2)],
+            options: RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
+            },
+        };
+        assert_encdec!(GetLumpsRequestEncoder, GetLumpsRequestDecoder, || {
+            request.clone()
+        });
+    }
+
+    #[test]
+    fn put_lumps_request_encdec_works() {
+        let request = PutLumpsRequest {
+            device_id: DeviceId::new("device"),
+            lumps: vec![
+                (LumpId::new(1), LumpData::new(b"foo".to_vec()).unwrap()),
+                (LumpId::new(2), LumpData::new(Vec::new()).unwrap()),
+            ],
+            options: RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
+            },
+        };
+        assert_encdec!(PutLumpsRequestEncoder, PutLumpsRequestDecoder, || {
+            request.clone()
+        });
+    }
+
+    #[test]
+    fn get_lump_range_request_encdec_works() {
+        let request = GetLumpRangeRequest {
+            device_id: DeviceId::new("device"),
+            lump_id: LumpId::new(7),
+            byte_range: Range {
+                start: 10,
+                end: 1 << 40,
+            },
+            options: RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
+            },
+        };
+        assert_encdec!(
+            GetLumpRangeRequestEncoder,
+            GetLumpRangeRequestDecoder,
+            || { request.clone() }
+        );
+    }
+
+    #[test]
+    fn export_lumps_request_encdec_works() {
+        let request = ExportLumpsRequest {
+            device_id: DeviceId::new("device"),
+            range: Range {
+                start: LumpId::new(1),
+                end: LumpId::new(30),
+            },
+            max_lumps: 10,
+            options: RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
+            },
+        };
+        assert_encdec!(ExportLumpsRequestEncoder, ExportLumpsRequestDecoder, || {
+            request.clone()
+        });
+
+        let chunk = ExportLumpsChunk {
+            lumps: vec![(LumpId::new(1), LumpData::new(b"foo".to_vec()).unwrap())],
+            next: Some(LumpId::new(5)),
+        };
+        assert_encdec!(ExportLumpsChunkEncoder, ExportLumpsChunkDecoder, || chunk
+            .clone());
+
+        let chunk = ExportLumpsChunk {
+            lumps: Vec::new(),
+            next: None,
+        };
+        assert_encdec!(ExportLumpsChunkEncoder, ExportLumpsChunkDecoder, || chunk
+            .clone());
+    }
+
+    #[test]
+    fn list_lumps_chunk_encdec_works() {
+        let chunk = ListLumpsChunk {
+            lump_ids: vec![LumpId::new(1), LumpId::new(3)],
+            next: Some(LumpId::new(5)),
+        };
+        assert_encdec!(ListLumpsChunkEncoder, ListLumpsChunkDecoder, || chunk
+            .clone());
+
+        let chunk = ListLumpsChunk {
+            lump_ids: Vec::new(),
+            next: None,
+        };
+        assert_encdec!(ListLumpsChunkEncoder, ListLumpsChunkDecoder, || chunk
+            .clone());
+    }
+
+    #[test]
+    fn import_lumps_request_encdec_works() {
+        let request = ImportLumpsRequest {
+            session_id: 3,
+            batch_seq: 10,
+            lumps: vec![(LumpId::new(1), LumpData::new(b"foo".to_vec()).unwrap())],
+            options: RequestOptions {
+                deadline: Deadline::Infinity,
+                max_queue_len: None,
+                prioritized: false,
+                journal_sync: false,
+                verbose_errors: false,
+                response_meta: false,
+                trace_id: None,
+                signature: None,
+            },
+        };
+        assert_encdec!(ImportLumpsRequestEncoder, ImportLumpsRequestDecoder, || {
+            request.clone()
+        });
+
+        let request = ImportSessionRequest {
+            session_id: 3,
+            options: request.options.clone(),
+        };
+        assert_encdec!(
+            ImportSessionRequestEncoder,
+            ImportSessionRequestDecoder,
+            || request.clone()
+        );
+
+        let status = ImportSessionStatus {
+            session_id: 3,
+            device_id: DeviceId::new("device"),
+            next_batch_seq: 11,
+            imported_lumps: 100,
+            imported_bytes: 12345,
+        };
+        assert_encdec!(
+            ImportSessionStatusEncoder,
+            ImportSessionStatusDecoder,
+            || { status.clone() }
+        );
+    }
+
+    #[test]
+    fn provision_device_request_encdec_works() {
+        let options = RequestOptions {
+            deadline: Deadline::Infinity,
+            max_queue_len: None,
+            prioritized: false,
+            journal_sync: false,
+            verbose_errors: false,
+            response_meta: false,
+            trace_id: None,
+            signature: None,
+        };
+        let mut spec = DeviceSpec::file(DeviceId::new("file"), "/tmp/foo.lusf", 1024 * 1024);
+        spec.block_size = track_try_unwrap!(BlockSize::new(4096));
+        spec.labels.insert("zone".to_owned(), "a".to_owned());
+        spec.labels.insert("media".to_owned(), "ssd".to_owned());
+        assert_encdec!(
+            ProvisionDeviceRequestEncoder,
+            ProvisionDeviceRequestDecoder,
+            || ProvisionDeviceRequest {
+                spec: spec.clone(),
+                options: options.clone(),
+            }
+        );
+
+        assert_encdec!(
+            ProvisionDeviceRequestEncoder,
+            ProvisionDeviceRequestDecoder,
+            || ProvisionDeviceRequest {
+                spec: DeviceSpec::memory(DeviceId::new("mem"), 0),
+                options: options.clone(),
+            }
+        );
+    }
+
+    #[test]
+    fn device_status_report_encdec_works() {
+        let report = DeviceStatusReport {
+            device_id: DeviceId::new("device"),
+            status: Some(DeviceStatus::Running),
+            queue_len: 3,
+            storage: Some(StorageMetricsSnapshot::default()),
+        };
+        assert_encdec!(DeviceStatusReportEncoder, DeviceStatusReportDecoder, || {
+            report.clone()
+        });
+
+        let report = DeviceStatusReport {
+            device_id: DeviceId::new("unknown"),
+            status: None,
+            queue_len: 0,
+            storage: None,
+        };
+        assert_encdec!(DeviceStatusReportEncoder, DeviceStatusReportDecoder, || {
+            report.clone()
+        });
+    }
+
+    #[test]
+    fn storage_header_encdec_works() {
+        let header = StorageHeader {
+            major_version: 1,
+            minor_version: 2,
+            block_size: track_try_unwrap!(BlockSize::new(4096)),
+            instance_uuid: Uuid::from_bytes([7; 16]),
+            journal_region_size: 1024,
+            data_region_size: 4096,
+        };
+        let mut encoder = StorageHeaderEncoder::default();
+        let mut decoder = StorageHeaderDecoder::default();
+        let bytes = track_try_unwrap!(encoder.encode_into_bytes(header.clone()));
+        let decoded = track_try_unwrap!(decoder.decode_from_bytes(&bytes));
+        assert_eq!(decoded.major_version, header.major_version);
+        assert_eq!(decoded.minor_version, header.minor_version);
+        assert_eq!(decoded.block_size, header.block_size);
+        assert_eq!(decoded.instance_uuid, header.instance_uuid);
+        assert_eq!(decoded.journal_region_size, header.journal_region_size);
+        assert_eq!(decoded.data_region_size, header.data_region_size);
+    }
 }
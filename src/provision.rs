@@ -0,0 +1,185 @@
+//! 仕様の一覧に基づくデバイスの一括構築.
+use cannyls::device::DeviceBuilder;
+use cannyls::nvm::{FileNvm, MemoryNvm, NonVolatileMemory};
+use cannyls::storage::{Storage, StorageBuilder};
+use cannyls::{ErrorKind, Result};
+#[cfg(feature = "server")]
+use fibers::sync::oneshot;
+#[cfg(feature = "server")]
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+#[cfg(feature = "server")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "server")]
+use std::thread;
+#[cfg(feature = "server")]
+use trackable::error::ErrorKindExt;
+
+use crate::device::{DeviceId, DeviceNvmSpec, DeviceSpec};
+use crate::registry::DeviceRegistryHandle;
+
+/// 仕様の一覧に従ってデバイス群を構築(ないしオープン)し、レジストリに登録する.
+///
+/// デバイスはストレージのメトリクスおよびラベルと共に登録される
+/// (`DeviceRegistryHandle::put_device_with_labels`を参照).
+///
+/// 結果は仕様の一覧と同じ順序で返され、各要素の値が`Ok(true)`の場合にはストレージが新規に作成されたことを、
+/// `Ok(false)`の場合には既存のストレージが開かれたことを、表している.
+/// 個々のデバイスの構築は独立しており、一部が失敗しても、残りのデバイスの構築・登録は継続される.
+///
+/// なお、同じIDのデバイスが既に登録されている場合には、`put_device`と同様に上書きされる.
+///
+/// この関数で登録されたデバイスは、設定ファイル由来のものとしては扱われない
+/// (i.e., `DeviceRegistryHandle::reload_config`による削除の対象とはならない).
+pub fn provision(
+    registry: &DeviceRegistryHandle,
+    specs: Vec<DeviceSpec>,
+) -> Vec<(DeviceId, Result<bool>)> {
+    provision_specs(registry, specs, false)
+}
+
+// `from_config`が`true`の場合には、設定ファイル由来のデバイスとして登録する.
+pub(crate) fn provision_specs(
+    registry: &DeviceRegistryHandle,
+    specs: Vec<DeviceSpec>,
+    from_config: bool,
+) -> Vec<(DeviceId, Result<bool>)> {
+    specs
+        .into_iter()
+        .map(|spec| {
+            let device_id = spec.device_id.clone();
+            let result = track!(provision_device(registry, spec, from_config));
+            (device_id, result)
+        })
+        .collect()
+}
+
+pub(crate) fn provision_device(
+    registry: &DeviceRegistryHandle,
+    spec: DeviceSpec,
+    from_config: bool,
+) -> Result<bool> {
+    let mut builder = StorageBuilder::new();
+    builder.block_size(spec.block_size);
+
+    match spec.nvm {
+        DeviceNvmSpec::File(ref path) => {
+            let (nvm, created) = track!(FileNvm::create_if_absent(path, spec.capacity))?;
+            let storage = if created {
+                track!(builder.create(nvm))?
+            } else {
+                track!(builder.open(nvm))?
+            };
+            track!(register(registry, spec, storage, from_config))?;
+            Ok(created)
+        }
+        DeviceNvmSpec::Memory => {
+            track_assert!(
+                spec.capacity <= usize::MAX as u64,
+                ErrorKind::InvalidInput;
+                spec.capacity
+            );
+            let nvm = MemoryNvm::new(vec![0; spec.capacity as usize]);
+            let storage = track!(builder.create(nvm))?;
+            track!(register(registry, spec, storage, from_config))?;
+            Ok(true)
+        }
+    }
+}
+
+/// `ProvisionWorker`のキューに積める、処理待ちの構築要求の最大数.
+#[cfg(feature = "server")]
+pub(crate) const PROVISION_QUEUE_LEN: usize = 8;
+
+#[cfg(feature = "server")]
+type ProvisionJob = (DeviceSpec, oneshot::Sender<Result<bool>>);
+
+// 管理用RPC経由のデバイスの構築を、専用のスレッドで一つずつ実行するためのワーカ.
+//
+// ストレージの作成(初期化)には時間が掛かることがあるため、RPCサーバのスレッドとは別に実行する.
+// スレッドは最初の要求時に起動され、(複製を含む)全てのワーカが破棄された時点で終了する.
+// キューが満杯の場合には、要求は`ErrorKind::DeviceBusy`で拒否される.
+#[cfg(feature = "server")]
+#[derive(Debug, Clone)]
+pub(crate) struct ProvisionWorker {
+    registry: DeviceRegistryHandle,
+    queue: Arc<Mutex<Option<SyncSender<ProvisionJob>>>>,
+}
+#[cfg(feature = "server")]
+impl ProvisionWorker {
+    pub fn new(registry: DeviceRegistryHandle) -> Self {
+        ProvisionWorker {
+            registry,
+            queue: Arc::default(),
+        }
+    }
+
+    // 仕様に従ったデバイスの構築をキューに積み、その結果を受け取るためのチャンネルを返す.
+    pub fn submit(&self, spec: DeviceSpec) -> Result<oneshot::Receiver<Result<bool>>> {
+        let (tx, rx) = oneshot::channel();
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        let sender = queue.get_or_insert_with(|| self.spawn());
+        match sender.try_send((spec, tx)) {
+            Ok(()) => Ok(rx),
+            Err(TrySendError::Full(_)) => track_panic!(
+                ErrorKind::DeviceBusy,
+                "Too many pending provisioning requests (max={})",
+                PROVISION_QUEUE_LEN
+            ),
+            Err(TrySendError::Disconnected(_)) => {
+                // ワーカスレッドがパニックした場合には、次回の要求時に起動し直す
+                *queue = None;
+                Err(
+                    track!(ErrorKind::Other.cause("Provisioning thread terminated unexpectedly"))
+                        .into(),
+                )
+            }
+        }
+    }
+
+    fn spawn(&self) -> SyncSender<ProvisionJob> {
+        let (tx, rx) = mpsc::sync_channel::<ProvisionJob>(PROVISION_QUEUE_LEN);
+        let registry = self.registry.clone();
+        thread::spawn(move || {
+            for (spec, reply) in rx {
+                let device_id = spec.device_id.clone();
+                let result = track!(provision_device(&registry, spec, false));
+                match result {
+                    Ok(created) => info!(
+                        registry.logger(),
+                        "Device {:?} was provisioned: created={}",
+                        device_id.as_str(),
+                        created
+                    ),
+                    Err(ref e) => warn!(
+                        registry.logger(),
+                        "Cannot provision device {:?}: {}",
+                        device_id.as_str(),
+                        e
+                    ),
+                }
+                let _ = reply.send(result);
+            }
+        });
+        tx
+    }
+}
+
+fn register<N>(
+    registry: &DeviceRegistryHandle,
+    spec: DeviceSpec,
+    storage: Storage<N>,
+    from_config: bool,
+) -> Result<()>
+where
+    N: NonVolatileMemory + Send + 'static,
+{
+    let storage_metrics = storage.metrics().clone();
+    let device = DeviceBuilder::new().spawn(move || Ok(storage));
+    track!(registry.register_device(
+        spec.device_id,
+        device,
+        Some(storage_metrics),
+        spec.labels,
+        from_config
+    ))
+}
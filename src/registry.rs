@@ -1,20 +1,34 @@
 use atomic_immut::AtomicImmut;
 use cannyls::deadline::Deadline;
 use cannyls::device::{Device, DeviceHandle};
+use cannyls::metrics::StorageMetrics;
 use cannyls::{Error, ErrorKind, Result};
-use fibers::sync::mpsc;
+use fibers::sync::{mpsc, oneshot};
+use fibers::time::timer::{self, Timeout};
 use futures::{Async, Future, Poll, Stream};
-use slog::Logger;
+use prometrics::metrics::{Counter, Gauge, MetricBuilder};
+use slog::{Level, Logger};
 use std::borrow::Borrow;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 use trackable::error::ErrorKindExt;
 
-use crate::device::DeviceId;
+use crate::config;
+use crate::device::{DeviceId, DeviceLabels, DeviceSettings, DeviceSpec};
+use crate::info::DeviceMetricsSnapshot;
+use crate::log::{LevelFilter, LogLevel};
+use crate::provision::provision_specs;
 
-type DeviceHandles = Arc<AtomicImmut<HashMap<DeviceId, Mutex<DeviceHandle>>>>;
+type DeviceHandles = Arc<AtomicImmut<DeviceMap>>;
+
+// `DeviceMap`のシャード数.
+const DEVICE_MAP_SHARDS: usize = 64;
 
 /// デバイスレジストリ.
 ///
@@ -24,10 +38,16 @@ type DeviceHandles = Arc<AtomicImmut<HashMap<DeviceId, Mutex<DeviceHandle>>>>;
 #[derive(Debug)]
 pub struct DeviceRegistry {
     logger: Logger,
+    log_level: LogLevel,
 
     // 登録デバイス群.
     devices: HashMap<DeviceId, DeviceState>,
 
+    // `devices`の内、まだ停止していないものの数.
+    //
+    // メトリクスの更新の度に`devices`を走査しなくて済むように、別途管理している.
+    running_devices: usize,
+
     // デバイスの検索やそれに対する操作は、
     // (性能上の理由から)レジストリに対するコマンド送受信を経由せずに直接行いたいので、
     // デバイスハンドルは別で管理する.
@@ -40,29 +60,91 @@ pub struct DeviceRegistry {
 
     // レジストリが停止中かどうかを示すためのフラグ.
     being_stopped: bool,
+
+    // `Some`の場合には、停止したデバイスを、この期間の経過後にレジストリから削除する.
+    deregistration_grace_period: Option<Duration>,
+
+    // `DeviceRegistryHandle::events`によって登録された、イベントの購読者群.
+    subscribers: Vec<mpsc::Sender<RegistryEvent>>,
+
+    metrics: RegistryMetrics,
 }
 impl DeviceRegistry {
     /// 新しいレジストリインスタンスを生成する.
+    ///
+    /// `logger`は、レジストリおよび(このレジストリを使う)RPCサーバのログ出力に使用される.
+    /// その出力レベルは`DeviceRegistryHandle::set_log_level`によって、実行時に変更可能.
+    ///
+    /// レジストリのメトリクスは、デフォルトのレジストリに登録される(`with_metric_builder`を参照).
     pub fn new(logger: Logger) -> Self {
+        Self::with_metric_builder(logger, MetricBuilder::new())
+    }
+
+    /// メトリクスの登録に使用するビルダを指定して、新しいレジストリインスタンスを生成する.
+    ///
+    /// 登録されるメトリクスの一覧は`RegistryMetrics`を参照のこと.
+    /// ビルダはラベルの追加や、登録先のレジストリの変更に利用できる(名前空間とサブシステムは上書きされる).
+    ///
+    /// それ以外の挙動は`new`と同様.
+    pub fn with_metric_builder(logger: Logger, builder: MetricBuilder) -> Self {
         let (command_tx, command_rx) = mpsc::channel();
+        let log_level = LogLevel::new(Level::Trace);
+        let logger = Logger::root(LevelFilter::new(logger, log_level.clone()), o!());
         DeviceRegistry {
             logger,
+            log_level,
             devices: HashMap::new(),
-            device_handles: DeviceHandles::default(),
+            running_devices: 0,
+            device_handles: Arc::new(AtomicImmut::new(DeviceMap::new())),
             command_tx,
             command_rx,
             being_stopped: false,
+            deregistration_grace_period: None,
+            subscribers: Vec::new(),
+            metrics: RegistryMetrics::new(builder),
         }
     }
 
+    /// 停止したデバイスを、指定の猶予期間の経過後に、自動でレジストリから削除するようにする.
+    ///
+    /// デフォルトでは、停止したデバイスは明示的に削除されるまではレジストリに登録されたままとなり、
+    /// そのデバイス宛のリクエストは全て`ErrorKind::DeviceTerminated`エラーとなる.
+    /// 自動削除を有効にした場合には、猶予期間の経過後は、デバイスが存在しない旨のエラー(`ErrorKind::InvalidInput`)が返されるようになる.
+    ///
+    /// 正常終了と異常終了のいずれの場合にも削除が行われる.
+    /// なお、猶予期間中に同じIDのデバイスが登録された場合には、新しいデバイスは削除されない.
+    pub fn deregister_terminated_devices(&mut self, grace_period: Duration) -> &mut Self {
+        self.deregistration_grace_period = Some(grace_period);
+        self
+    }
+
     /// レジストリを操作するためのハンドルを返す.
     pub fn handle(&self) -> DeviceRegistryHandle {
         DeviceRegistryHandle {
+            logger: self.logger.clone(),
+            log_level: self.log_level.clone(),
             command_tx: self.command_tx.clone(),
             device_handles: Arc::clone(&self.device_handles),
+            metrics: self.metrics.clone(),
         }
     }
 
+    /// 設定ファイルに記述されたデバイス群を構築(ないしオープン)し、このレジストリに登録する.
+    ///
+    /// 設定ファイルの形式は`NodeConfig`の`[[device]]`テーブルと同様.
+    /// それ以外のテーブルおよびトップレベルのエントリは無視されるので、
+    /// アプリケーション自身の設定ファイルの中にデバイスの定義を含めることができる.
+    ///
+    /// ファイルの読み込みや内容の検証(e.g., 必須項目の欠如、デバイスIDやパスの重複)に失敗した場合には、
+    /// デバイスは一つも構築されずに、エラーが返される.
+    /// 検証に成功した場合の結果は`provision`関数と同様で、一部のデバイスの構築に失敗しても、残りのデバイスは登録される.
+    ///
+    /// 登録されたデバイスは設定ファイル由来のものとして扱われ、`DeviceRegistryHandle::reload_config`による削除の対象となる.
+    pub fn load_config<P: AsRef<Path>>(&self, path: P) -> Result<Vec<(DeviceId, Result<bool>)>> {
+        let specs = track!(config::load_device_specs(path))?;
+        Ok(provision_specs(&self.handle(), specs, true))
+    }
+
     /// レジストリの停止処理を開始する.
     ///
     /// 単に止めたいだけであれば、レジストリインスタンスを破棄するだけでも良いが、
@@ -88,21 +170,7 @@ impl DeviceRegistry {
     /// デバイスを止める。
     /// 対象とするデバイスが見つかった場合は true を、見つからなかった場合は false を返す。
     pub fn stop_device(&mut self, device_id: &DeviceId) -> bool {
-        info!(self.logger, "Stopping device: {:?}", device_id);
-        if let Some(state) = self.devices.get(device_id) {
-            if state.terminated {
-                info!(
-                    self.logger,
-                    "stop_device: Device {:?} has already been terminated", device_id
-                );
-            } else {
-                state.device.stop(Deadline::Immediate);
-            }
-            true
-        } else {
-            info!(self.logger, "Invalid device ID: {:?}", device_id);
-            false
-        }
+        self.handle_stop_device(device_id, Deadline::Immediate)
     }
 
     /// デバイスが動いているかどうかを返す。
@@ -117,13 +185,36 @@ impl DeviceRegistry {
     }
 
     fn handle_command(&mut self, command: Command) {
+        self.metrics.commands.increment();
         match command {
-            Command::PutDevice(id, device) => self.handle_put_device(&id, device),
+            Command::PutDevice(id, device, storage_metrics, labels, from_config) => self
+                .handle_put_device(
+                    &id,
+                    device,
+                    storage_metrics.map(|m| *m),
+                    labels,
+                    from_config,
+                ),
             Command::DeleteDevice(id) => self.handle_delete_device(&id),
+            Command::StopDevice(id, deadline) => {
+                self.handle_stop_device(&id, deadline);
+            }
+            Command::RetireDevice(id, deadline) => self.handle_retire_device(&id, deadline),
+            Command::Subscribe(tx) => self.subscribers.push(tx),
+            Command::ReloadDevices(device_ids, reply) => {
+                let _ = reply.send(self.handle_reload_devices(&device_ids));
+            }
         }
     }
 
-    fn handle_put_device(&mut self, id: &DeviceId, device: Device) {
+    fn handle_put_device(
+        &mut self,
+        id: &DeviceId,
+        device: Device,
+        storage_metrics: Option<StorageMetrics>,
+        labels: DeviceLabels,
+        from_config: bool,
+    ) {
         if self.being_stopped {
             warn!(
                 self.logger,
@@ -133,29 +224,142 @@ impl DeviceRegistry {
         }
 
         info!(self.logger, "PUT device: {:?}", id);
-        let old = self.devices.insert(id.clone(), DeviceState::new(device));
+        let mut state = DeviceState::new(device, storage_metrics, labels);
+        state.from_config = from_config;
+        let old = self.insert_device_state(id.clone(), state);
         if old.is_some() {
             warn!(self.logger, "Old device was removed: {:?}", id);
+            self.emit(RegistryEvent::DeviceRemoved(id.clone()));
+        }
+        self.refresh_device_handle(id);
+        self.emit(RegistryEvent::DeviceAdded(id.clone()));
+    }
+
+    fn handle_stop_device(&mut self, device_id: &DeviceId, deadline: Deadline) -> bool {
+        info!(
+            self.logger,
+            "Stopping device: {:?} (deadline={:?})", device_id, deadline
+        );
+        if let Some(state) = self.devices.get(device_id) {
+            if state.terminated {
+                info!(
+                    self.logger,
+                    "stop_device: Device {:?} has already been terminated", device_id
+                );
+            } else {
+                state.device.stop(deadline);
+            }
+            true
+        } else {
+            info!(self.logger, "Invalid device ID: {:?}", device_id);
+            false
+        }
+    }
+
+    fn handle_retire_device(&mut self, id: &DeviceId, deadline: Deadline) {
+        info!(
+            self.logger,
+            "RETIRE device: {:?} (deadline={:?})", id, deadline
+        );
+        match self.devices.get_mut(id) {
+            None => warn!(self.logger, "No such device: {:?}", id),
+            Some(state) if state.terminated => self.handle_delete_device(id),
+            Some(state) => {
+                state.device.stop(deadline);
+                state.retiring = true;
+            }
+        }
+    }
+
+    // 設定ファイル由来のデバイスの内、`device_ids`に含まれないものを停止後に削除し、
+    // `device_ids`の内、未登録のもの(i.e., 新規に構築すべきもの)を返す.
+    //
+    // 停止後に削除される予定のデバイスは、未登録のものとして扱われる.
+    // その場合には、削除を待ってから再構築できるように、削除イベントの購読者も登録される.
+    fn handle_reload_devices(&mut self, device_ids: &[DeviceId]) -> ReloadPlan {
+        let removed = self
+            .devices
+            .iter()
+            .filter(|(id, state)| state.from_config && !state.retiring && !device_ids.contains(id))
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for id in &removed {
+            self.handle_retire_device(id, Deadline::Infinity);
+        }
+        let added = device_ids
+            .iter()
+            .filter(|id| self.devices.get(*id).is_none_or(|s| s.retiring))
+            .cloned()
+            .collect::<Vec<_>>();
+        let retiring = added
+            .iter()
+            .filter(|id| self.devices.contains_key(*id))
+            .cloned()
+            .collect::<Vec<_>>();
+        let removal_events = if retiring.is_empty() {
+            None
+        } else {
+            let (tx, rx) = mpsc::channel();
+            self.subscribers.push(tx);
+            Some(rx)
+        };
+        ReloadPlan {
+            added,
+            removed,
+            retiring,
+            removal_events,
         }
-        self.refresh_device_handles();
     }
 
     fn handle_delete_device(&mut self, id: &DeviceId) {
         info!(self.logger, "DELETE device: {:?}", id);
-        if self.devices.remove(id).is_some() {
-            self.refresh_device_handles();
+        if self.remove_device_state(id).is_some() {
+            self.refresh_device_handle(id);
+            self.emit(RegistryEvent::DeviceRemoved(id.clone()));
         } else {
             warn!(self.logger, "No such device: {:?}", id);
         }
     }
 
-    fn refresh_device_handles(&mut self) {
-        let device_handles = self
-            .devices
-            .iter()
-            .map(|(id, s)| (id.clone(), Mutex::new(s.device.handle())))
-            .collect();
+    fn emit(&mut self, event: RegistryEvent) {
+        self.subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    // 指定デバイスの状態を、ハンドル経由で参照されるマップに反映する.
+    //
+    // マップを更新するのはレジストリのみなので、読み込みと書き込みの間に他の更新が割り込むことはない.
+    fn refresh_device_handle(&mut self, id: &DeviceId) {
+        let entry = self.devices.get(id).map(DeviceState::entry);
+        let device_handles = self.device_handles.load().with_entry(id, entry);
         self.device_handles.store(device_handles);
+        self.update_device_gauges();
+    }
+
+    fn update_device_gauges(&self) {
+        self.metrics.devices.set(self.devices.len() as f64);
+        self.metrics
+            .running_devices
+            .set(self.running_devices as f64);
+    }
+
+    // 以下の二つは、`running_devices`を更新しつつ、`devices`を操作するためのヘルパ.
+    fn insert_device_state(&mut self, id: DeviceId, state: DeviceState) -> Option<DeviceState> {
+        if !state.terminated {
+            self.running_devices += 1;
+        }
+        let old = self.devices.insert(id, state);
+        if old.as_ref().is_some_and(|old| !old.terminated) {
+            self.running_devices -= 1;
+        }
+        old
+    }
+
+    fn remove_device_state(&mut self, id: &DeviceId) -> Option<DeviceState> {
+        let old = self.devices.remove(id);
+        if old.as_ref().is_some_and(|old| !old.terminated) {
+            self.running_devices -= 1;
+        }
+        old
     }
 }
 impl Future for DeviceRegistry {
@@ -167,6 +371,7 @@ impl Future for DeviceRegistry {
             self.handle_command(command);
         }
 
+        let mut terminated = Vec::new();
         for (id, state) in &mut self.devices {
             if state.terminated {
                 continue;
@@ -174,16 +379,59 @@ impl Future for DeviceRegistry {
             match track!(state.device.poll()) {
                 Err(e) => {
                     error!(self.logger, "Device {:?} terminated abnormally: {}", id, e);
+                    self.metrics.abnormal_terminations.increment();
                     state.terminated = true;
+                    self.running_devices -= 1;
+                    terminated.push((id.clone(), Some(e)));
                 }
                 Ok(Async::Ready(())) => {
                     info!(self.logger, "Device {:?} terminated normally", id);
+                    self.metrics.normal_terminations.increment();
                     state.terminated = true;
+                    self.running_devices -= 1;
+                    terminated.push((id.clone(), None));
                 }
                 Ok(Async::NotReady) => {}
             }
         }
-        if self.being_stopped && self.devices.values().all(|d| d.terminated) {
+        for (device_id, error) in &terminated {
+            self.emit(RegistryEvent::DeviceTerminated {
+                device_id: device_id.clone(),
+                error: error.clone(),
+            });
+        }
+        if let Some(grace_period) = self.deregistration_grace_period {
+            for (id, _) in &terminated {
+                if let Some(state) = self.devices.get_mut(id) {
+                    state.deregistration_timer = Some(timer::timeout(grace_period));
+                }
+            }
+        }
+
+        let removed = self
+            .devices
+            .iter_mut()
+            .filter(|(_, s)| s.terminated)
+            .filter_map(|(id, s)| {
+                if s.is_removable() {
+                    Some(id.clone())
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        for id in &removed {
+            info!(self.logger, "Terminated device is removed: {:?}", id);
+            self.remove_device_state(id);
+            self.refresh_device_handle(id);
+        }
+        if removed.is_empty() && !terminated.is_empty() {
+            self.update_device_gauges();
+        }
+        for id in removed {
+            self.emit(RegistryEvent::DeviceRemoved(id));
+        }
+        if self.being_stopped && self.running_devices == 0 {
             info!(self.logger, "All devices have stopped");
             Ok(Async::Ready(()))
         } else {
@@ -193,15 +441,165 @@ impl Future for DeviceRegistry {
 }
 impl Drop for DeviceRegistry {
     fn drop(&mut self) {
-        self.device_handles.store(HashMap::new());
+        self.device_handles.store(DeviceMap::new());
     }
 }
 
+/// レジストリで発生するイベント.
+///
+/// `DeviceRegistryHandle::events`を参照.
+#[derive(Debug, Clone)]
+pub enum RegistryEvent {
+    /// デバイスが登録された.
+    ///
+    /// 同じIDのデバイスが既に登録されていた場合には、このイベントの直前に`DeviceRemoved`が発生する.
+    DeviceAdded(DeviceId),
+
+    /// デバイスがレジストリから削除された.
+    DeviceRemoved(DeviceId),
+
+    /// デバイスが停止した.
+    ///
+    /// 停止したデバイスは、削除されるまではレジストリに登録されたままとなる.
+    DeviceTerminated {
+        /// 停止したデバイスのID.
+        device_id: DeviceId,
+
+        /// 異常終了の場合には、その原因となったエラー(正常終了の場合には`None`).
+        error: Option<Error>,
+    },
+}
+
+/// レジストリで発生したイベントのストリーム.
+///
+/// `DeviceRegistryHandle::events`によって生成される.
+/// エラーを返すことはない.
+#[derive(Debug)]
+pub struct RegistryEvents(mpsc::Receiver<RegistryEvent>);
+impl Stream for RegistryEvents {
+    type Item = RegistryEvent;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        Ok(self.0.poll().expect("Never fails"))
+    }
+}
+
+/// `DeviceRegistryHandle::reload_config`の結果.
+#[derive(Debug)]
+pub struct ConfigReload {
+    /// 新規に登録されたデバイスの一覧.
+    ///
+    /// 各要素の値の意味は`provision`関数の結果と同様.
+    pub added: Vec<(DeviceId, Result<bool>)>,
+
+    /// 停止後に削除されるデバイスの一覧.
+    pub removed: Vec<DeviceId>,
+}
+
+/// `DeviceRegistryHandle::reload_config`および`DeviceRegistryHandle::reload_devices`が返す`Future`.
+///
+/// 結果は、レジストリのコマンド処理と新規デバイスの構築が完了した時点で得られる.
+/// なお`ConfigReload::removed`のデバイスは、この時点では停止処理中の可能性がある.
+///
+/// 停止処理中のデバイスが再び追加された場合には、その停止と削除を待ってから構築が行われるので、
+/// 結果が得られるまでの時間は、停止処理の所要時間に左右される.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct ReloadConfigFuture(ReloadPhase);
+impl Future for ReloadConfigFuture {
+    type Item = ConfigReload;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next = match self.0 {
+                ReloadPhase::Failed(ref mut e) => {
+                    let e = std::mem::replace(e, ErrorKind::Other.error().into());
+                    return Err(e);
+                }
+                ReloadPhase::Planning(ref handle, ref mut specs, ref mut rx) => {
+                    let plan = match rx.poll() {
+                        Err(_) => track_panic!(ErrorKind::Other, "The registry has been dropped"),
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(plan)) => plan,
+                    };
+
+                    // ストレージの作成(初期化)には時間が掛かることがあるため、別スレッドで実行する
+                    let ReloadPlan {
+                        added,
+                        removed,
+                        retiring,
+                        removal_events,
+                    } = plan;
+                    let specs = std::mem::take(specs)
+                        .into_iter()
+                        .filter(|s| added.contains(&s.device_id))
+                        .collect();
+                    let (tx, rx) = oneshot::channel();
+                    let handle = handle.clone();
+                    thread::spawn(move || {
+                        // 同じストレージを新旧のデバイスが同時に使用することがないように、旧デバイスの削除を待つ
+                        if let Some(events) = removal_events {
+                            wait_for_removal(events, retiring);
+                        }
+                        let _ = tx.send(provision_specs(&handle, specs, true));
+                    });
+                    ReloadPhase::Provisioning(removed, rx)
+                }
+                ReloadPhase::Provisioning(ref mut removed, ref mut rx) => {
+                    let added = match rx.poll() {
+                        Err(_) => {
+                            track_panic!(ErrorKind::Other, "The provisioning thread panicked")
+                        }
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(added)) => added,
+                    };
+                    let removed = std::mem::take(removed);
+                    return Ok(Async::Ready(ConfigReload { added, removed }));
+                }
+            };
+            self.0 = next;
+        }
+    }
+}
+
+// `devices`の全てがレジストリから削除されるまで待機する.
+//
+// レジストリが破棄された場合には、その時点で待機を終了する(後続のデバイスの登録は失敗する).
+fn wait_for_removal(events: mpsc::Receiver<RegistryEvent>, mut devices: Vec<DeviceId>) {
+    let mut events = events.wait();
+    while !devices.is_empty() {
+        match events.next() {
+            Some(Ok(RegistryEvent::DeviceRemoved(id))) => devices.retain(|d| *d != id),
+            Some(Ok(_)) => {}
+            Some(Err(())) | None => break,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ReloadPhase {
+    Failed(Error),
+    Planning(
+        DeviceRegistryHandle,
+        Vec<DeviceSpec>,
+        oneshot::Receiver<ReloadPlan>,
+    ),
+    Provisioning(
+        Vec<DeviceId>,
+        oneshot::Receiver<Vec<(DeviceId, Result<bool>)>>,
+    ),
+}
+
 /// デバイスレジストリを操作するためのハンドル.
 #[derive(Debug, Clone)]
 pub struct DeviceRegistryHandle {
+    logger: Logger,
+    log_level: LogLevel,
     command_tx: mpsc::Sender<Command>,
     device_handles: DeviceHandles,
+    metrics: RegistryMetrics,
 }
 impl DeviceRegistryHandle {
     /// レジストリにデバイスを登録する.
@@ -214,7 +612,77 @@ impl DeviceRegistryHandle {
     ///
     /// 対象レジストリインスタンスがドロップしている場合には、`ErrorKind::Other`エラーが返る.
     pub fn put_device(&self, device_id: DeviceId, device: Device) -> Result<()> {
-        let command = Command::PutDevice(device_id, device);
+        let command = Command::PutDevice(device_id, device, None, DeviceLabels::new(), false);
+        track_assert!(self.command_tx.send(command).is_ok(), ErrorKind::Other);
+        Ok(())
+    }
+
+    /// ストレージのメトリクスと共に、レジストリにデバイスを登録する.
+    ///
+    /// `cannyls`のデバイスハンドル経由ではストレージのメトリクスが参照できないため、
+    /// それを必要とする操作(e.g., ジャーナル領域の使用状況の取得)を利用したい場合には、
+    /// `put_device`の代わりにこのメソッドを使用する必要がある.
+    ///
+    /// `storage_metrics`には、デバイスに渡すストレージの`Storage::metrics`の値を指定すること.
+    ///
+    /// それ以外の挙動は`put_device`と同様.
+    ///
+    /// # Errors
+    ///
+    /// 対象レジストリインスタンスがドロップしている場合には、`ErrorKind::Other`エラーが返る.
+    pub fn put_device_with_storage_metrics(
+        &self,
+        device_id: DeviceId,
+        device: Device,
+        storage_metrics: StorageMetrics,
+    ) -> Result<()> {
+        let command = Command::PutDevice(
+            device_id,
+            device,
+            Some(Box::new(storage_metrics)),
+            DeviceLabels::new(),
+            false,
+        );
+        track_assert!(self.command_tx.send(command).is_ok(), ErrorKind::Other);
+        Ok(())
+    }
+
+    /// ラベルと共に、レジストリにデバイスを登録する.
+    ///
+    /// ラベルは、デバイスの用途や配置等を表す任意のキー・値の組で、
+    /// 登録後は`get_device_labels`で参照可能(レジストリ自体がラベルを解釈することはない).
+    ///
+    /// `storage_metrics`の扱いは`put_device_with_storage_metrics`と、それ以外の挙動は`put_device`と同様.
+    ///
+    /// # Errors
+    ///
+    /// 対象レジストリインスタンスがドロップしている場合には、`ErrorKind::Other`エラーが返る.
+    pub fn put_device_with_labels(
+        &self,
+        device_id: DeviceId,
+        device: Device,
+        storage_metrics: Option<StorageMetrics>,
+        labels: DeviceLabels,
+    ) -> Result<()> {
+        track!(self.register_device(device_id, device, storage_metrics, labels, false))
+    }
+
+    // `from_config`が`true`の場合には、設定ファイル由来のデバイスとして登録する.
+    pub(crate) fn register_device(
+        &self,
+        device_id: DeviceId,
+        device: Device,
+        storage_metrics: Option<StorageMetrics>,
+        labels: DeviceLabels,
+        from_config: bool,
+    ) -> Result<()> {
+        let command = Command::PutDevice(
+            device_id,
+            device,
+            storage_metrics.map(Box::new),
+            labels,
+            from_config,
+        );
         track_assert!(self.command_tx.send(command).is_ok(), ErrorKind::Other);
         Ok(())
     }
@@ -232,6 +700,98 @@ impl DeviceRegistryHandle {
         Ok(())
     }
 
+    /// レジストリに登録されているデバイスに、停止命令を発行する.
+    ///
+    /// `DeviceRegistry::stop_device`とは異なり、停止命令に付与するデッドラインを指定可能.
+    /// (e.g., `Deadline::Infinity`を指定した場合には、処理待ちのコマンドが全て処理された後に停止する)
+    ///
+    /// 停止後のデバイスは、削除されるまではレジストリに登録されたままとなる.
+    /// 指定されたデバイスが存在しない場合には、単に無視される.
+    ///
+    /// # Errors
+    ///
+    /// 対象レジストリインスタンスがドロップしている場合には、`ErrorKind::Other`エラーが返る.
+    pub fn stop_device(&self, device_id: DeviceId, deadline: Deadline) -> Result<()> {
+        let command = Command::StopDevice(device_id, deadline);
+        track_assert!(self.command_tx.send(command).is_ok(), ErrorKind::Other);
+        Ok(())
+    }
+
+    /// レジストリで発生したイベントを購読するためのストリームを返す.
+    ///
+    /// ストリームには、レジストリが購読要求を処理した時点以降に発生したイベントが、発生順に流れてくる.
+    /// 購読要求はチャンネル経由で処理されるため、この呼び出しの直後に発生したイベントが含まれない可能性がある.
+    ///
+    /// レジストリインスタンスがドロップした場合には、ストリームは終端する.
+    /// なお、購読者が消費しない限りイベントはメモリ上に溜まり続けるので、不要になったストリームは破棄すること.
+    pub fn events(&self) -> RegistryEvents {
+        let (tx, rx) = mpsc::channel();
+        let _ = self.command_tx.send(Command::Subscribe(tx));
+        RegistryEvents(rx)
+    }
+
+    /// レジストリに登録されているデバイスに停止命令を発行し、停止した時点でレジストリから削除する.
+    ///
+    /// `stop_device`と`delete_device`を組み合わせたもので、デバイスの停止を待たずに登録を削除する`delete_device`とは異なり、
+    /// デッドラインまでに処理されたコマンドの結果が失われることはない.
+    /// 停止するまでの間は、デバイスはレジストリに登録されたままとなる.
+    ///
+    /// 既に停止しているデバイスが指定された場合には、即座に削除される.
+    /// 指定されたデバイスが存在しない場合には、単に無視される.
+    ///
+    /// # Errors
+    ///
+    /// 対象レジストリインスタンスがドロップしている場合には、`ErrorKind::Other`エラーが返る.
+    pub fn retire_device(&self, device_id: DeviceId, deadline: Deadline) -> Result<()> {
+        let command = Command::RetireDevice(device_id, deadline);
+        track_assert!(self.command_tx.send(command).is_ok(), ErrorKind::Other);
+        Ok(())
+    }
+
+    /// 設定ファイルを読み直して、その内容とレジストリの登録デバイス群との差分を反映する.
+    ///
+    /// 設定ファイルの形式は`DeviceRegistry::load_config`と同様で、以下の処理が行われる:
+    ///
+    /// - 設定ファイルにのみ存在するデバイスは、構築されて登録される
+    ///   - `retire_device`によって停止処理中のデバイスは未登録とみなされ、その削除を待ってから構築し直される
+    /// - 設定ファイル由来の登録デバイスの内、設定ファイルから取り除かれたものは、`retire_device`によって停止後に削除される
+    ///   - デッドラインは`Deadline::Infinity`で、処理待ちのコマンドが全て処理されてから停止する
+    /// - 両方に存在するデバイスは、仕様が変更されていたとしても、そのまま維持される
+    ///
+    /// 設定ファイル由来のデバイスとは、`DeviceRegistry::load_config`およびこのメソッド(ないし`reload_devices`)で
+    /// 登録されたものを指す.
+    /// それ以外の経路(e.g., `put_device`や管理用RPC)で登録されたデバイスが、削除の対象となることはない.
+    ///
+    /// 差分の判定と削除はレジストリ内で(他のコマンドと直列に)行われるので、`put_device`等の直後に呼び出した場合でも、
+    /// その結果は反映される.
+    /// 新規のデバイスの構築は、レジストリとは別のスレッドで行われる.
+    ///
+    /// ファイルの読み込みや内容の検証に失敗した場合には、レジストリは一切変更されずに、エラーが返される.
+    pub fn reload_config<P: AsRef<Path>>(&self, path: P) -> ReloadConfigFuture {
+        match track!(config::load_device_specs(path)) {
+            Err(e) => ReloadConfigFuture(ReloadPhase::Failed(e)),
+            Ok(specs) => self.reload_devices(specs),
+        }
+    }
+
+    /// デバイスの仕様の一覧を設定ファイルの内容とみなして、レジストリの登録デバイス群との差分を反映する.
+    ///
+    /// 詳細は`reload_config`を参照のこと.
+    /// `NodeConfig::devices`のように、既に読み込み済みの設定を適用する場合に使用する.
+    ///
+    /// 仕様の検証(e.g., デバイスIDやパスの重複)に失敗した場合には、レジストリは一切変更されずに、エラーが返される.
+    pub fn reload_devices(&self, specs: Vec<DeviceSpec>) -> ReloadConfigFuture {
+        if let Err(e) = track!(config::validate_device_specs(&specs)) {
+            return ReloadConfigFuture(ReloadPhase::Failed(e));
+        }
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let device_ids = specs.iter().map(|s| s.device_id.clone()).collect();
+        let _ = self
+            .command_tx
+            .send(Command::ReloadDevices(device_ids, reply_tx));
+        ReloadConfigFuture(ReloadPhase::Planning(self.clone(), specs, reply_rx))
+    }
+
     /// レジストリに登録されているデバイスを取得する.
     ///
     /// 未登録のデバイスが指定された場合には`None`が返される.
@@ -256,10 +816,84 @@ impl DeviceRegistryHandle {
     where
         T: Hash + Eq + Debug + ?Sized,
         DeviceId: Borrow<T>,
+    {
+        track!(self.snapshot().get_device(device_id))
+    }
+
+    /// レジストリに登録されているデバイスのストレージのメトリクスを取得する.
+    ///
+    /// `get_device`と同様に、この操作はチャンネルを経由せずに行われる.
+    ///
+    /// # Errors
+    ///
+    /// 存在しないデバイスが指定された場合には、`ErrorKind::InvalidInput`エラーが返される.
+    ///
+    /// デバイスが`put_device_with_storage_metrics`以外の方法で登録されている場合には、
+    /// `ErrorKind::Other`エラーが返される.
+    pub fn get_storage_metrics<T>(&self, device_id: &T) -> Result<StorageMetrics>
+    where
+        T: Hash + Eq + Debug + ?Sized,
+        DeviceId: Borrow<T>,
+    {
+        track!(self.snapshot().get_storage_metrics(device_id))
+    }
+
+    /// レジストリに登録されているデバイスの設定を取得する.
+    ///
+    /// `get_device`と同様に、この操作はチャンネルを経由せずに行われる.
+    ///
+    /// # Errors
+    ///
+    /// 存在しないデバイスが指定された場合には、`ErrorKind::InvalidInput`エラーが返される.
+    pub fn get_device_settings<T>(&self, device_id: &T) -> Result<DeviceSettings>
+    where
+        T: Hash + Eq + Debug + ?Sized,
+        DeviceId: Borrow<T>,
+    {
+        track!(self.snapshot().get_device_settings(device_id))
+    }
+
+    /// レジストリに登録されているデバイスのラベルを取得する.
+    ///
+    /// `get_device`と同様に、この操作はチャンネルを経由せずに行われる.
+    ///
+    /// `put_device_with_labels`以外の方法で登録されたデバイスの場合には、空のラベル群が返される.
+    ///
+    /// # Errors
+    ///
+    /// 存在しないデバイスが指定された場合には、`ErrorKind::InvalidInput`エラーが返される.
+    pub fn get_device_labels<T>(&self, device_id: &T) -> Result<DeviceLabels>
+    where
+        T: Hash + Eq + Debug + ?Sized,
+        DeviceId: Borrow<T>,
+    {
+        track!(self.snapshot().get_device_labels(device_id))
+    }
+
+    /// レジストリに登録されているデバイスの設定を更新する.
+    ///
+    /// 更新は、現在の設定に対して`f`を適用することで行われ、更新後の設定が返される.
+    /// なお、他の更新と競合した場合には`f`は複数回呼び出される可能性がある.
+    ///
+    /// 設定はデバイスに紐付いて保持されるため、
+    /// `put_device`によってデバイスが置き換えられた場合には、デフォルト値に戻る.
+    ///
+    /// # Errors
+    ///
+    /// 存在しないデバイスが指定された場合には、`ErrorKind::InvalidInput`エラーが返される.
+    pub fn update_device_settings<T, F>(&self, device_id: &T, f: F) -> Result<DeviceSettings>
+    where
+        T: Hash + Eq + Debug + ?Sized,
+        DeviceId: Borrow<T>,
+        F: Fn(&mut DeviceSettings),
     {
         if let Some(d) = self.device_handles.load().get(device_id) {
-            let d = track!(d.lock().map_err(|e| ErrorKind::Other.cause(e.to_string())))?;
-            Ok(d.clone())
+            d.settings.update(|old| {
+                let mut new = old.clone();
+                f(&mut new);
+                new
+            });
+            Ok((*d.settings.load()).clone())
         } else {
             track_panic!(ErrorKind::InvalidInput, "No such device: {:?}", device_id);
         }
@@ -267,7 +901,75 @@ impl DeviceRegistryHandle {
 
     /// レジストリにデバイスが登録されているかどうかを判定する.
     pub fn contains_device(&self, device_id: &DeviceId) -> bool {
-        self.device_handles.load().contains_key(device_id)
+        self.snapshot().contains_device(device_id)
+    }
+
+    /// 現時点での登録デバイス群のスナップショットを取得する.
+    ///
+    /// 一貫した状態に対して、多数の検索をまとめて行いたい場合に有用.
+    /// 詳細は`DevicesSnapshot`のドキュメントを参照のこと.
+    pub fn snapshot(&self) -> DevicesSnapshot {
+        DevicesSnapshot(self.device_handles.load())
+    }
+
+    /// レジストリに登録されているデバイスのメトリクスのスナップショットを取得する.
+    ///
+    /// `device_ids`が空の場合には、全ての登録デバイスが対象となる.
+    /// 未登録のデバイスのIDが含まれている場合には、単に無視される.
+    ///
+    /// 結果はデバイスIDの昇順に並べられる.
+    ///
+    /// # Errors
+    ///
+    /// デバイス用のロック獲得に失敗した場合には、`ErrorKind::Other`エラーが返される.
+    pub fn metrics_snapshot(&self, device_ids: &[DeviceId]) -> Result<Vec<DeviceMetricsSnapshot>> {
+        let mut snapshots = Vec::new();
+        for (id, d) in self.device_handles.load().iter() {
+            if !device_ids.is_empty() && !device_ids.contains(id) {
+                continue;
+            }
+            let handle = track!(d
+                .handle
+                .lock()
+                .map_err(|e| ErrorKind::Other.cause(e.to_string())))?;
+            snapshots.push(DeviceMetricsSnapshot::new(
+                id.clone(),
+                handle.metrics(),
+                d.storage_metrics.as_ref(),
+            ));
+        }
+        snapshots.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        Ok(snapshots)
+    }
+
+    /// レジストリ自体のメトリクスを返す.
+    ///
+    /// デバイスの異常終了の回数等を参照できるので、デバイスの再起動の繰り返し等の検知に利用できる.
+    pub fn metrics(&self) -> &RegistryMetrics {
+        &self.metrics
+    }
+
+    /// レジストリおよびRPCサーバのログ出力レベルを返す.
+    pub fn log_level(&self) -> Level {
+        self.log_level.get()
+    }
+
+    /// レジストリおよびRPCサーバのログ出力レベルを変更する.
+    ///
+    /// 指定されたレベル未満の重要度のログは出力されなくなる.
+    /// ただし、ここでの設定は`DeviceRegistry::new`に渡したロガー側のフィルタリングよりも
+    /// 優先されることはないので、例えば、デバッグログを出力するためには、
+    /// 元のロガーもそれを許容している必要がある.
+    ///
+    /// デフォルトは`Level::Trace`(i.e., フィルタリングなし).
+    pub fn set_log_level(&self, level: Level) {
+        info!(self.logger, "Log level is changed: {:?}", level);
+        self.log_level.set(level);
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn logger(&self) -> &Logger {
+        &self.logger
     }
 
     /// レジストリに登録されているデバイス一覧を取得する.
@@ -278,29 +980,345 @@ impl DeviceRegistryHandle {
     pub fn list_devices(&self) -> Result<Vec<(DeviceId, DeviceHandle)>> {
         let mut devices = Vec::new();
         for (id, d) in self.device_handles.load().iter() {
-            let d = track!(d.lock().map_err(|e| ErrorKind::Other.cause(e.to_string())))?;
+            let d = track!(d
+                .handle
+                .lock()
+                .map_err(|e| ErrorKind::Other.cause(e.to_string())))?;
             devices.push((id.clone(), d.clone()));
         }
         Ok(devices)
     }
 }
 
+/// ある時点でのレジストリの登録デバイス群のスナップショット.
+///
+/// `DeviceRegistryHandle::snapshot`によって取得される.
+///
+/// スナップショットは取得時点の登録デバイス群を保持しており、その後のデバイスの登録・削除の影響を受けない.
+/// 個々の検索の度にレジストリの最新の状態を読み込むことはないので、
+/// 多数の検索を、一貫した状態に対してまとめて行うことができる.
+/// (ただし、デバイスハンドルの取得時には、デバイス単位のロックが獲得される)
+///
+/// なお、デバイスの設定は、スナップショット取得後に更新されたものも参照される.
+///
+/// 各メソッドの挙動やエラーは、`DeviceRegistryHandle`の同名のメソッドと同様.
+#[derive(Debug, Clone)]
+pub struct DevicesSnapshot(Arc<DeviceMap>);
+impl DevicesSnapshot {
+    /// スナップショットに含まれるデバイスを取得する.
+    pub fn get_device<T>(&self, device_id: &T) -> Result<DeviceHandle>
+    where
+        T: Hash + Eq + Debug + ?Sized,
+        DeviceId: Borrow<T>,
+    {
+        let d = track!(self.get_entry(device_id))?;
+        let handle = track!(d
+            .handle
+            .lock()
+            .map_err(|e| ErrorKind::Other.cause(e.to_string())))?;
+        Ok(handle.clone())
+    }
+
+    /// スナップショットに含まれるデバイスのストレージのメトリクスを取得する.
+    pub fn get_storage_metrics<T>(&self, device_id: &T) -> Result<StorageMetrics>
+    where
+        T: Hash + Eq + Debug + ?Sized,
+        DeviceId: Borrow<T>,
+    {
+        let d = track!(self.get_entry(device_id))?;
+        let metrics = track_assert_some!(
+            d.storage_metrics.clone(),
+            ErrorKind::Other,
+            "Storage metrics are not registered: {:?}",
+            device_id
+        );
+        Ok(metrics)
+    }
+
+    /// スナップショットに含まれるデバイスの設定を取得する.
+    pub fn get_device_settings<T>(&self, device_id: &T) -> Result<DeviceSettings>
+    where
+        T: Hash + Eq + Debug + ?Sized,
+        DeviceId: Borrow<T>,
+    {
+        let d = track!(self.get_entry(device_id))?;
+        Ok((*d.settings.load()).clone())
+    }
+
+    /// スナップショットに含まれるデバイスのラベルを取得する.
+    pub fn get_device_labels<T>(&self, device_id: &T) -> Result<DeviceLabels>
+    where
+        T: Hash + Eq + Debug + ?Sized,
+        DeviceId: Borrow<T>,
+    {
+        let d = track!(self.get_entry(device_id))?;
+        Ok((*d.labels).clone())
+    }
+
+    /// スナップショットにデバイスが含まれているかどうかを判定する.
+    pub fn contains_device<T>(&self, device_id: &T) -> bool
+    where
+        T: Hash + Eq + ?Sized,
+        DeviceId: Borrow<T>,
+    {
+        self.0.get(device_id).is_some()
+    }
+
+    /// スナップショットに含まれるデバイスのIDを走査するためのイテレータを返す.
+    ///
+    /// 走査順は不定.
+    pub fn device_ids(&self) -> impl Iterator<Item = &DeviceId> {
+        self.0.iter().map(|(id, _)| id)
+    }
+
+    /// スナップショットに含まれるデバイスの数を返す.
+    pub fn len(&self) -> usize {
+        self.0.len
+    }
+
+    /// スナップショットにデバイスが一つも含まれていない場合には`true`を返す.
+    pub fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+
+    fn get_entry<T>(&self, device_id: &T) -> Result<&DeviceEntry>
+    where
+        T: Hash + Eq + Debug + ?Sized,
+        DeviceId: Borrow<T>,
+    {
+        if let Some(d) = self.0.get(device_id) {
+            Ok(d)
+        } else {
+            track_panic!(ErrorKind::InvalidInput, "No such device: {:?}", device_id);
+        }
+    }
+}
+
+/// デバイスレジストリのメトリクス.
+///
+/// `DeviceRegistryHandle::metrics`によって取得される.
+/// 値は全て、レジストリの生成以降の累積値(ないし現在値)となる.
+#[derive(Debug, Clone)]
+pub struct RegistryMetrics {
+    devices: Gauge,
+    running_devices: Gauge,
+    abnormal_terminations: Counter,
+    normal_terminations: Counter,
+    commands: Counter,
+}
+impl RegistryMetrics {
+    /// 登録されているデバイスの数.
+    ///
+    /// 終了済みのデバイスも、削除されるまでは含まれる.
+    ///
+    /// Metric: `cannyls_rpc_registry_devices <GAUGE>`.
+    pub fn devices(&self) -> u64 {
+        self.devices.value() as u64
+    }
+
+    /// 登録されているデバイスの内、実行中のものの数.
+    ///
+    /// Metric: `cannyls_rpc_registry_running_devices <GAUGE>`.
+    pub fn running_devices(&self) -> u64 {
+        self.running_devices.value() as u64
+    }
+
+    /// 異常終了したデバイスの数.
+    ///
+    /// Metric: `cannyls_rpc_registry_device_terminations_total { result="abnormal" } <COUNTER>`.
+    pub fn abnormal_terminations(&self) -> u64 {
+        self.abnormal_terminations.value() as u64
+    }
+
+    /// 正常終了したデバイスの数.
+    ///
+    /// Metric: `cannyls_rpc_registry_device_terminations_total { result="normal" } <COUNTER>`.
+    pub fn normal_terminations(&self) -> u64 {
+        self.normal_terminations.value() as u64
+    }
+
+    /// レジストリが処理したコマンド(デバイスの登録・削除・停止)の数.
+    ///
+    /// Metric: `cannyls_rpc_registry_commands_total <COUNTER>`.
+    pub fn commands(&self) -> u64 {
+        self.commands.value() as u64
+    }
+
+    fn new(mut builder: MetricBuilder) -> Self {
+        builder.namespace("cannyls_rpc").subsystem("registry");
+        RegistryMetrics {
+            devices: builder
+                .gauge("devices")
+                .help("Number of registered devices")
+                .finish()
+                .expect("Never fails"),
+            running_devices: builder
+                .gauge("running_devices")
+                .help("Number of registered devices that are running")
+                .finish()
+                .expect("Never fails"),
+            abnormal_terminations: builder
+                .counter("device_terminations_total")
+                .help("Number of terminated devices")
+                .label("result", "abnormal")
+                .finish()
+                .expect("Never fails"),
+            normal_terminations: builder
+                .counter("device_terminations_total")
+                .help("Number of terminated devices")
+                .label("result", "normal")
+                .finish()
+                .expect("Never fails"),
+            commands: builder
+                .counter("commands_total")
+                .help("Number of processed registry commands")
+                .finish()
+                .expect("Never fails"),
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 enum Command {
-    PutDevice(DeviceId, Device),
+    PutDevice(
+        DeviceId,
+        Device,
+        Option<Box<StorageMetrics>>,
+        DeviceLabels,
+        bool,
+    ), // 最後の要素は設定ファイル由来かどうか
     DeleteDevice(DeviceId),
+    StopDevice(DeviceId, Deadline),
+    RetireDevice(DeviceId, Deadline),
+    Subscribe(mpsc::Sender<RegistryEvent>),
+    ReloadDevices(Vec<DeviceId>, oneshot::Sender<ReloadPlan>),
+}
+
+// `Command::ReloadDevices`の結果.
+#[derive(Debug)]
+struct ReloadPlan {
+    added: Vec<DeviceId>,
+    removed: Vec<DeviceId>,
+
+    // `added`の内、停止後に削除される予定のもの.
+    retiring: Vec<DeviceId>,
+
+    // `retiring`が空ではない場合に、その削除を待つために使用されるイベントの受信口.
+    removal_events: Option<mpsc::Receiver<RegistryEvent>>,
 }
 
 #[derive(Debug)]
 struct DeviceState {
     device: Device,
+    storage_metrics: Option<StorageMetrics>,
+    settings: Arc<AtomicImmut<DeviceSettings>>,
+    labels: Arc<DeviceLabels>,
     terminated: bool,
+
+    // `true`の場合には、停止した時点でレジストリから削除される.
+    retiring: bool,
+
+    // 設定ファイル由来のデバイスかどうか(`DeviceRegistryHandle::reload_config`を参照).
+    from_config: bool,
+
+    // 停止後の自動削除(`DeviceRegistry::deregister_terminated_devices`)までの猶予期間を計るタイマー.
+    deregistration_timer: Option<Timeout>,
 }
 impl DeviceState {
-    fn new(device: Device) -> Self {
+    fn new(device: Device, storage_metrics: Option<StorageMetrics>, labels: DeviceLabels) -> Self {
         DeviceState {
             device,
+            storage_metrics,
+            settings: Arc::default(),
+            labels: Arc::new(labels),
             terminated: false,
+            retiring: false,
+            from_config: false,
+            deregistration_timer: None,
+        }
+    }
+
+    fn entry(&self) -> DeviceEntry {
+        DeviceEntry {
+            handle: Mutex::new(self.device.handle()),
+            storage_metrics: self.storage_metrics.clone(),
+            settings: Arc::clone(&self.settings),
+            labels: Arc::clone(&self.labels),
+        }
+    }
+
+    // 停止済みのデバイスを、レジストリから削除して良いかどうかを判定する.
+    fn is_removable(&mut self) -> bool {
+        if self.retiring {
+            return true;
         }
+        self.deregistration_timer
+            .as_mut()
+            .is_some_and(|t| !matches!(t.poll(), Ok(Async::NotReady)))
     }
 }
+
+// ハンドル経由で参照される登録デバイス群のマップ.
+//
+// デバイスの登録・削除の度に全体を再構築すると、そのコストが登録デバイス数に比例してしまうので、
+// デバイスIDのハッシュ値に基づいてシャードに分割し、変更のあったシャードのみを再構築するようにしている.
+// 全体は単一の`AtomicImmut`に格納されるので、読み込み側からは(シャードを跨いでも)一貫した状態が見える.
+#[derive(Debug)]
+struct DeviceMap {
+    shards: Vec<Arc<HashMap<DeviceId, Arc<DeviceEntry>>>>,
+    len: usize,
+}
+impl DeviceMap {
+    fn new() -> Self {
+        DeviceMap {
+            shards: (0..DEVICE_MAP_SHARDS).map(|_| Arc::default()).collect(),
+            len: 0,
+        }
+    }
+
+    fn shard_index<T: Hash + ?Sized>(device_id: &T) -> usize {
+        let mut hasher = DefaultHasher::new();
+        device_id.hash(&mut hasher);
+        (hasher.finish() % DEVICE_MAP_SHARDS as u64) as usize
+    }
+
+    fn get<T>(&self, device_id: &T) -> Option<&DeviceEntry>
+    where
+        T: Hash + Eq + ?Sized,
+        DeviceId: Borrow<T>,
+    {
+        self.shards[Self::shard_index(device_id)]
+            .get(device_id)
+            .map(|e| &**e)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&DeviceId, &DeviceEntry)> {
+        self.shards
+            .iter()
+            .flat_map(|s| s.iter().map(|(id, e)| (id, &**e)))
+    }
+
+    // 指定デバイスのエントリを置き換えた(`None`の場合には削除した)マップを返す.
+    fn with_entry(&self, device_id: &DeviceId, entry: Option<DeviceEntry>) -> Self {
+        let index = Self::shard_index(device_id);
+        let mut shard = (*self.shards[index]).clone();
+        if let Some(entry) = entry {
+            shard.insert(device_id.clone(), Arc::new(entry));
+        } else {
+            shard.remove(device_id);
+        }
+        let len = self.len + shard.len() - self.shards[index].len();
+        let mut shards = self.shards.clone();
+        shards[index] = Arc::new(shard);
+        DeviceMap { shards, len }
+    }
+}
+
+#[derive(Debug)]
+struct DeviceEntry {
+    handle: Mutex<DeviceHandle>,
+    storage_metrics: Option<StorageMetrics>,
+    settings: Arc<AtomicImmut<DeviceSettings>>,
+    labels: Arc<DeviceLabels>,
+}
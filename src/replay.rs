@@ -0,0 +1,600 @@
+//! リクエストの記録(`TrafficRecorder`)と再生(`Replayer`).
+//!
+//! 本番環境のトラフィックのパターンを、ベンチマークやバグ報告用に再現するためのもの.
+//!
+//! 記録は一行一リクエストのテキスト形式で、各行はタブ区切りの以下のフィールドから構成される:
+//!
+//! 1. 記録開始からリクエストの処理開始までの経過時間(マイクロ秒)
+//! 2. RPCの名前(サーバ側で処理される際のもの)
+//! 3. 対象デバイスのID
+//! 4. 操作対象(`device`, `lump:${LUMP_ID}`, `range:${START}..${END}`のいずれか)
+//! 5. ペイロード(e.g., lumpのデータ)のバイト数
+//! 6. デッドライン(`infinity`, `immediate`, `within:${MICROS}`のいずれか)
+//! 7. 優先的に処理するかどうか(`true`ないし`false`)
+//! 8. 結果(`ok`ないしエラーの種類 e.g., `DeviceBusy`)
+//! 9. 処理に要した時間(マイクロ秒)
+//!
+//! `#`で始まる行および空行は無視される.
+//!
+//! lumpのデータ自体は記録されず、再生時にはゼロ埋めされた同じサイズのデータが使用される.
+use cannyls::deadline::Deadline;
+use cannyls::lump::LumpId;
+use cannyls::{Error, ErrorKind, Result};
+use std::fmt;
+use std::io::BufRead;
+use std::str::FromStr;
+use std::time::Duration;
+use trackable::error::ErrorKindExt;
+
+use crate::device::DeviceId;
+use crate::info::RequestTarget;
+
+#[cfg(feature = "server")]
+pub use self::recorder::TrafficRecorder;
+#[cfg(feature = "client")]
+pub use self::replayer::{ReplayFuture, ReplaySummary, Replayer};
+
+/// 記録されたリクエスト(とその結果).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedRequest {
+    /// 記録開始から、サーバがリクエストの処理を開始するまでの経過時間.
+    pub offset: Duration,
+
+    /// RPCの名前(e.g., `cannyls.lump.get`).
+    pub procedure: String,
+
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+
+    /// リクエストの操作対象.
+    pub target: RequestTarget,
+
+    /// ペイロード(e.g., lumpのデータ)のバイト数.
+    pub payload_size: usize,
+
+    /// リクエスト処理のデッドライン.
+    pub deadline: Deadline,
+
+    /// `true`の場合には、過負荷時でもリクエストが優先的に処理される.
+    pub prioritized: bool,
+
+    /// リクエストが失敗した場合には、そのエラーの種類.
+    pub error: Option<ErrorKind>,
+
+    /// リクエストの処理に要した時間.
+    pub elapsed: Duration,
+}
+impl RecordedRequest {
+    /// 記録を読み込む.
+    ///
+    /// 不正な形式の行が含まれている場合には`ErrorKind::InvalidInput`エラーが返される.
+    pub fn read_all<R: BufRead>(reader: R) -> Result<Vec<Self>> {
+        let mut records = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = track!(line.map_err(Error::from))?;
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            records.push(track!(line.parse(), "line={}", i + 1)?);
+        }
+        Ok(records)
+    }
+}
+impl fmt::Display for RecordedRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t",
+            self.offset.as_micros(),
+            self.procedure,
+            self.device_id.as_str()
+        )?;
+        match self.target {
+            RequestTarget::Device => write!(f, "device")?,
+            RequestTarget::Lump(ref lump_id) => write!(f, "lump:{}", lump_id)?,
+            RequestTarget::Range(ref range) => write!(f, "range:{}..{}", range.start, range.end)?,
+        }
+        write!(f, "\t{}\t", self.payload_size)?;
+        match self.deadline {
+            Deadline::Infinity => write!(f, "infinity")?,
+            Deadline::Immediate => write!(f, "immediate")?,
+            Deadline::Within(d) => write!(f, "within:{}", d.as_micros())?,
+        }
+        write!(f, "\t{}\t", self.prioritized)?;
+        match self.error {
+            None => write!(f, "ok")?,
+            Some(kind) => write!(f, "{}", kind)?,
+        }
+        write!(f, "\t{}", self.elapsed.as_micros())
+    }
+}
+impl FromStr for RecordedRequest {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields = s.split('\t').collect::<Vec<_>>();
+        track_assert_eq!(fields.len(), 9, ErrorKind::InvalidInput; s);
+        Ok(RecordedRequest {
+            offset: track!(parse_micros(fields[0]))?,
+            procedure: fields[1].to_owned(),
+            device_id: DeviceId::new(fields[2]),
+            target: track!(parse_target(fields[3]))?,
+            payload_size: track!(parse_field(fields[4]))?,
+            deadline: track!(parse_deadline(fields[5]))?,
+            prioritized: track!(parse_field(fields[6]))?,
+            error: match fields[7] {
+                "ok" => None,
+                kind => Some(track!(kind.parse().map_err(|()| {
+                    ErrorKind::InvalidInput.cause(format!("Unknown error kind: {:?}", kind))
+                }))?),
+            },
+            elapsed: track!(parse_micros(fields[8]))?,
+        })
+    }
+}
+
+fn parse_field<T>(s: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let n = track!(s
+        .parse()
+        .map_err(|e: T::Err| ErrorKind::InvalidInput.cause(format!("{}: {:?}", e, s))))?;
+    Ok(n)
+}
+
+fn parse_micros(s: &str) -> Result<Duration> {
+    track!(parse_field(s)).map(Duration::from_micros)
+}
+
+fn parse_lump_id(s: &str) -> Result<LumpId> {
+    track!(s.parse())
+}
+
+fn parse_target(s: &str) -> Result<RequestTarget> {
+    if s == "device" {
+        Ok(RequestTarget::Device)
+    } else if let Some(lump_id) = s.strip_prefix("lump:") {
+        track!(parse_lump_id(lump_id)).map(RequestTarget::Lump)
+    } else if let Some(range) = s.strip_prefix("range:") {
+        let (start, end) = track_assert_some!(
+            range.split_once(".."),
+            ErrorKind::InvalidInput,
+            "Malformed range: {:?}",
+            s
+        );
+        let start = track!(parse_lump_id(start))?;
+        let end = track!(parse_lump_id(end))?;
+        Ok(RequestTarget::Range(start..end))
+    } else {
+        track_panic!(ErrorKind::InvalidInput, "Unknown target: {:?}", s)
+    }
+}
+
+fn parse_deadline(s: &str) -> Result<Deadline> {
+    match s {
+        "infinity" => Ok(Deadline::Infinity),
+        "immediate" => Ok(Deadline::Immediate),
+        _ => {
+            let micros = track_assert_some!(
+                s.strip_prefix("within:"),
+                ErrorKind::InvalidInput,
+                "Unknown deadline: {:?}",
+                s
+            );
+            track!(parse_micros(micros)).map(Deadline::Within)
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+mod recorder {
+    use cannyls::{Error, Result};
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use super::RecordedRequest;
+    use crate::interceptor::{RequestContext, ServerInterceptor};
+
+    /// サーバが処理したリクエストを記録するためのインターセプタ.
+    ///
+    /// `Server::add_interceptor`で登録すると、インターセプタの対象となる全てのリクエストが、
+    /// 結果の確定時に`RecordedRequest`の形式で書き出される.
+    /// 書き出しには内部でバッファリングが行われるので、記録を読み込む前には`flush`を呼び出すこと.
+    ///
+    /// デバイスIDにタブや改行を含むリクエストは記録されない.
+    ///
+    /// `TrafficRecorder`を複製した場合には、同じ書き出し先を共有する.
+    #[derive(Clone)]
+    pub struct TrafficRecorder {
+        writer: Arc<Mutex<Box<dyn Write + Send>>>,
+        started_at: Instant,
+    }
+    impl TrafficRecorder {
+        /// 新しい`TrafficRecorder`インスタンスを生成する.
+        ///
+        /// 記録されるリクエストの経過時間(`RecordedRequest::offset`)は、この呼び出し時点からのものとなる.
+        pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+            TrafficRecorder {
+                writer: Arc::new(Mutex::new(Box::new(BufWriter::new(writer)))),
+                started_at: Instant::now(),
+            }
+        }
+
+        /// 指定のパスのファイルに記録を書き出す`TrafficRecorder`を生成する.
+        ///
+        /// 既にファイルが存在する場合には、その内容は破棄される.
+        pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+            let file = track!(File::create(path).map_err(Error::from))?;
+            Ok(Self::new(file))
+        }
+
+        /// バッファリングされている記録を書き出す.
+        pub fn flush(&self) -> Result<()> {
+            let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+            track!(writer.flush().map_err(Error::from))
+        }
+    }
+    impl ServerInterceptor for TrafficRecorder {
+        fn after_reply(&self, context: &RequestContext, result: std::result::Result<(), &Error>) {
+            let device_id = context.device_id.as_str();
+            if device_id.contains(['\t', '\n', '\r']) {
+                return;
+            }
+            let record = RecordedRequest {
+                offset: context
+                    .received_at
+                    .saturating_duration_since(self.started_at),
+                procedure: context.procedure.to_owned(),
+                device_id: context.device_id.clone(),
+                target: context.target.clone(),
+                payload_size: context.payload_size,
+                deadline: context.options.deadline,
+                prioritized: context.options.prioritized,
+                error: result.err().map(|e| *e.kind()),
+                elapsed: context.received_at.elapsed(),
+            };
+            let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+            // 記録に失敗しても、リクエストの処理には影響させない
+            let _ = writeln!(writer, "{}", record);
+        }
+    }
+    impl std::fmt::Debug for TrafficRecorder {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "TrafficRecorder {{ started_at: {:?} }}", self.started_at)
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+mod replayer {
+    use cannyls::lump::LumpData;
+    use cannyls::{Error, ErrorKind, Result};
+    use fibers::time::timer::{self, Timeout};
+    use fibers_rpc::{Call, Cast};
+    use futures::{Async, Future, Poll};
+    use std::fmt;
+    use std::time::Instant;
+
+    use super::RecordedRequest;
+    use crate::client::Client;
+    use crate::info::RequestTarget;
+    use crate::rpc;
+
+    type ReplayResponse = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    // リクエストの発行結果.
+    enum Issued {
+        // 応答を待機中.
+        Pending(ReplayResponse),
+
+        // 通知RPCの送信済み.
+        Sent,
+
+        // 再生に対応していない.
+        Skipped,
+    }
+
+    /// 記録されたリクエスト(`RecordedRequest`)を、サーバに対して再発行するためのもの.
+    ///
+    /// 再発行されるのは、lumpの取得・ヘッダの取得・存在確認・保存・削除と、一覧の取得・使用量の取得・範囲削除・スクラブのRPC.
+    /// それ以外のRPCや、操作対象が想定外のリクエストは、発行されずにスキップされる.
+    ///
+    /// リクエストのオプションの内、デッドラインと優先度は記録されたものが使用される.
+    #[derive(Debug, Clone)]
+    pub struct Replayer {
+        client: Client,
+        records: Vec<RecordedRequest>,
+        speed: Option<f64>,
+    }
+    impl Replayer {
+        /// 新しい`Replayer`インスタンスを生成する.
+        pub fn new(client: Client, records: Vec<RecordedRequest>) -> Self {
+            Replayer {
+                client,
+                records,
+                speed: None,
+            }
+        }
+
+        /// 記録された時間間隔を保って、リクエストを発行するようにする.
+        ///
+        /// `speed`は再生速度の倍率で、例えば`2.0`の場合には記録の倍の速さでリクエストが発行される.
+        /// この場合、前のリクエストの完了を待たずに次のリクエストが発行されるので、記録時の並行度も再現される.
+        ///
+        /// デフォルトでは、リクエストは一つずつ、前のリクエストの完了を待ってから発行される.
+        pub fn speed(&mut self, speed: f64) -> &mut Self {
+            self.speed = Some(speed.max(f64::MIN_POSITIVE));
+            self
+        }
+
+        /// 再生を開始する.
+        pub fn replay(&self) -> ReplayFuture {
+            ReplayFuture {
+                client: self.client.clone(),
+                records: self.records.clone(),
+                speed: self.speed,
+                next: 0,
+                started_at: None,
+                timer: None,
+                in_flight: Vec::new(),
+                summary: ReplaySummary::default(),
+            }
+        }
+    }
+
+    /// 再生結果の集計.
+    #[derive(Debug, Default, Clone, PartialEq, Eq)]
+    pub struct ReplaySummary {
+        /// 発行されたリクエストの数.
+        pub issued: usize,
+
+        /// 成功したリクエストの数.
+        pub succeeded: usize,
+
+        /// 失敗したリクエストの数.
+        pub failed: usize,
+
+        /// 再生に対応していないためにスキップされたリクエストの数.
+        pub skipped: usize,
+
+        /// 結果(成功したかどうか、およびエラーの種類)が記録時と異なったリクエストの数.
+        ///
+        /// 応答を伴わない通知RPCは対象外.
+        pub mismatched: usize,
+    }
+
+    /// 再生の完了を表す`Future`.
+    ///
+    /// 個々のリクエストの失敗は`ReplaySummary`に集計され、この`Future`自体は失敗しない.
+    pub struct ReplayFuture {
+        client: Client,
+        records: Vec<RecordedRequest>,
+        speed: Option<f64>,
+        next: usize,
+        started_at: Option<Instant>,
+        timer: Option<Timeout>,
+        in_flight: Vec<(ReplayResponse, Option<ErrorKind>)>,
+        summary: ReplaySummary,
+    }
+    impl ReplayFuture {
+        // 次のリクエストを発行可能かどうかを判定する.
+        fn is_next_due(&mut self) -> bool {
+            let speed = match self.speed {
+                None => return self.in_flight.is_empty(),
+                Some(speed) => speed,
+            };
+            let started_at = *self.started_at.get_or_insert_with(Instant::now);
+            let due = self.records[self.next].offset.div_f64(speed);
+            let elapsed = started_at.elapsed();
+            if elapsed >= due {
+                self.timer = None;
+                return true;
+            }
+            let timer = self
+                .timer
+                .get_or_insert_with(|| timer::timeout(due - elapsed));
+            // タイマーのエラーは、満了したものとして扱う
+            if matches!(timer.poll(), Ok(Async::NotReady)) {
+                false
+            } else {
+                self.timer = None;
+                true
+            }
+        }
+
+        fn issue(&mut self, record: &RecordedRequest) {
+            match self.start(record) {
+                Ok(Issued::Skipped) => self.summary.skipped += 1,
+                Ok(Issued::Sent) => {
+                    self.summary.issued += 1;
+                    self.summary.succeeded += 1;
+                }
+                Ok(Issued::Pending(response)) => {
+                    self.summary.issued += 1;
+                    self.in_flight.push((response, record.error));
+                }
+                Err(_) => {
+                    self.summary.issued += 1;
+                    self.summary.failed += 1;
+                }
+            }
+        }
+
+        // 記録に対応するリクエストを発行する.
+        fn start(&self, record: &RecordedRequest) -> Result<Issued> {
+            let mut request = self.client.request();
+            request.deadline(record.deadline);
+            if record.prioritized {
+                request.prioritized();
+            }
+            let device_id = record.device_id.clone();
+            let procedure = record.procedure.as_str();
+            let response: ReplayResponse = match record.target {
+                RequestTarget::Lump(lump_id) => {
+                    if procedure == rpc::GetLumpRpc::NAME
+                        || procedure == rpc::GetLumpWithChecksumRpc::NAME
+                    {
+                        Box::new(request.get_lump(device_id, lump_id).map(|_| ()))
+                    } else if procedure == rpc::HeadLumpRpc::NAME {
+                        Box::new(request.head_lump(device_id, lump_id).map(|_| ()))
+                    } else if procedure == rpc::ExistsLumpRpc::NAME {
+                        Box::new(request.exists_lump(device_id, lump_id).map(|_| ()))
+                    } else if procedure == rpc::PutLumpRpc::NAME
+                        || procedure == rpc::PutLumpV2Rpc::NAME
+                    {
+                        let data = track!(LumpData::new(vec![0; record.payload_size]))?;
+                        Box::new(request.put_lump(device_id, lump_id, data).map(|_| ()))
+                    } else if procedure == rpc::PutLumpNoAckRpc::NAME {
+                        let data = track!(LumpData::new(vec![0; record.payload_size]))?;
+                        track!(request.put_lump_noack(device_id, lump_id, data))?;
+                        return Ok(Issued::Sent);
+                    } else if procedure == rpc::DeleteLumpRpc::NAME
+                        || procedure == rpc::DeleteLumpV2Rpc::NAME
+                    {
+                        Box::new(request.delete_lump(device_id, lump_id).map(|_| ()))
+                    } else if procedure == rpc::DeleteLumpNoAckRpc::NAME {
+                        track!(request.delete_lump_noack(device_id, lump_id))?;
+                        return Ok(Issued::Sent);
+                    } else {
+                        return Ok(Issued::Skipped);
+                    }
+                }
+                RequestTarget::Range(ref range) => {
+                    let range = range.clone();
+                    if procedure == rpc::ListLumpRangeRpc::NAME {
+                        Box::new(request.list_lumps_range(device_id, range).map(|_| ()))
+                    } else if procedure == rpc::UsageRangeRpc::NAME {
+                        Box::new(request.usage_range(device_id, range).map(|_| ()))
+                    } else if procedure == rpc::DeleteRangeRpc::NAME {
+                        Box::new(request.delete_range(device_id, range).map(|_| ()))
+                    } else if procedure == rpc::ScrubRangeRpc::NAME {
+                        Box::new(request.scrub_range(device_id, range).map(|_| ()))
+                    } else {
+                        return Ok(Issued::Skipped);
+                    }
+                }
+                RequestTarget::Device => {
+                    if procedure == rpc::ListLumpRpc::NAME {
+                        Box::new(request.list_lumps(device_id).map(|_| ()))
+                    } else {
+                        return Ok(Issued::Skipped);
+                    }
+                }
+            };
+            Ok(Issued::Pending(response))
+        }
+
+        fn poll_in_flight(&mut self) {
+            let summary = &mut self.summary;
+            self.in_flight.retain_mut(|(response, expected)| {
+                let actual = match response.poll() {
+                    Ok(Async::NotReady) => return true,
+                    Ok(Async::Ready(())) => {
+                        summary.succeeded += 1;
+                        None
+                    }
+                    Err(e) => {
+                        summary.failed += 1;
+                        Some(*e.kind())
+                    }
+                };
+                if *expected != actual {
+                    summary.mismatched += 1;
+                }
+                false
+            });
+        }
+    }
+    impl Future for ReplayFuture {
+        type Item = ReplaySummary;
+        type Error = Error;
+
+        fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+            loop {
+                self.poll_in_flight();
+                if self.next == self.records.len() {
+                    if self.in_flight.is_empty() {
+                        return Ok(Async::Ready(self.summary.clone()));
+                    }
+                    return Ok(Async::NotReady);
+                }
+                if !self.is_next_due() {
+                    return Ok(Async::NotReady);
+                }
+                let record = self.records[self.next].clone();
+                self.next += 1;
+                self.issue(&record);
+            }
+        }
+    }
+    impl fmt::Debug for ReplayFuture {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "ReplayFuture {{ next: {}, records: {}, in_flight: {}, summary: {:?} }}",
+                self.next,
+                self.records.len(),
+                self.in_flight.len(),
+                self.summary
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_format_works() {
+        let record = RecordedRequest {
+            offset: Duration::from_micros(1500),
+            procedure: "cannyls.lump.put".to_owned(),
+            device_id: DeviceId::new("foo"),
+            target: RequestTarget::Lump(LumpId::new(0x12)),
+            payload_size: 3,
+            deadline: Deadline::Within(Duration::from_millis(10)),
+            prioritized: true,
+            error: None,
+            elapsed: Duration::from_micros(200),
+        };
+        let line = record.to_string();
+        assert_eq!(
+            line,
+            "1500\tcannyls.lump.put\tfoo\tlump:00000000000000000000000000000012\t3\twithin:10000\ttrue\tok\t200"
+        );
+        assert_eq!(track_try_unwrap!(line.parse::<RecordedRequest>()), record);
+
+        let record = RecordedRequest {
+            target: RequestTarget::Range(LumpId::new(1)..LumpId::new(5)),
+            deadline: Deadline::Infinity,
+            prioritized: false,
+            error: Some(ErrorKind::DeviceBusy),
+            ..record
+        };
+        assert_eq!(
+            track_try_unwrap!(record.to_string().parse::<RecordedRequest>()),
+            record
+        );
+    }
+
+    #[test]
+    fn read_all_works() {
+        let input =
+            b"# comment\n\n0\tcannyls.lump.list\tfoo\tdevice\t0\timmediate\tfalse\tok\t10\n";
+        let records = track_try_unwrap!(RecordedRequest::read_all(&input[..]));
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].target, RequestTarget::Device);
+        assert_eq!(records[0].deadline, Deadline::Immediate);
+
+        assert!(RecordedRequest::read_all(&b"0\tfoo\n"[..]).is_err());
+        let input = b"0\tcannyls.lump.list\tfoo\tdevice\t0\timmediate\tfalse\tUnknown\t10\n";
+        assert!(RecordedRequest::read_all(&input[..]).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! 同じデバイスの複製を保持する複数のサーバに対する、読み込みの負荷分散.
+use cannyls::lump::{LumpData, LumpId};
+use futures::{Async, Future, Poll};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::client::{
+    Client, DeleteLumpFuture, GetLumpFuture, HeadLumpFuture, ListLumpsFuture, PutLumpFuture,
+    RequestBuilder,
+};
+use crate::device::DeviceId;
+
+/// 読み込み系のリクエストの発行先を選択する方法.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BalancePolicy {
+    /// 全てのサーバを順番に使用する.
+    #[default]
+    RoundRobin,
+
+    /// 応答待ちのリクエストが最も少ないサーバを使用する.
+    ///
+    /// 同数のサーバが複数ある場合には、それらが順番に使用される.
+    LeastOutstandingRequests,
+}
+
+/// 同じデバイスの複製を保持する複数のサーバ群を表すクライアント.
+///
+/// 読み込み系のリクエスト(`get_lump`等)は、プライマリを含む全てのサーバに`BalancePolicy`に従って分散され、
+/// 更新系のリクエスト(`put_lump`等)は、常にプライマリに発行される.
+///
+/// サーバ間の複製自体は、このクライアントの管轄外なので、
+/// 複製の遅延によって、更新直後の読み込みで古いデータが返される可能性がある点には注意が必要.
+///
+/// インスタンスをクローンした場合には、負荷分散のための状態が共有される.
+#[derive(Debug, Clone)]
+pub struct ReplicaSet {
+    servers: Arc<Vec<Client>>, // 先頭がプライマリ
+    policy: BalancePolicy,
+    next: Arc<AtomicUsize>,
+    outstanding: Arc<Vec<Arc<AtomicUsize>>>,
+}
+impl ReplicaSet {
+    /// 新しい`ReplicaSet`インスタンスを生成する.
+    pub fn new(primary: Client, replicas: Vec<Client>, policy: BalancePolicy) -> Self {
+        let mut servers = vec![primary];
+        servers.extend(replicas);
+        let outstanding = servers.iter().map(|_| Arc::default()).collect();
+        ReplicaSet {
+            servers: Arc::new(servers),
+            policy,
+            next: Arc::default(),
+            outstanding: Arc::new(outstanding),
+        }
+    }
+
+    /// 更新系のリクエストの発行先となる、プライマリのクライアントを返す.
+    pub fn primary(&self) -> &Client {
+        &self.servers[0]
+    }
+
+    /// プライマリを含む、全てのサーバのクライアントを返す.
+    pub fn servers(&self) -> &[Client] {
+        &self.servers
+    }
+
+    /// 負荷分散のポリシーに従って選択したサーバに、読み込み系のリクエストを発行する.
+    ///
+    /// `request`には、渡されたビルダを使ってリクエストを発行する関数を指定する.
+    /// 返された`Future`が完了(ないしドロップ)するまでの間、リクエストは選択したサーバの応答待ちとして扱われる.
+    ///
+    /// 更新系のリクエストの発行に使用してはいけない(その場合には`primary`を使用すること).
+    pub fn read<'a, F, T>(&'a self, request: F) -> Balanced<T>
+    where
+        F: FnOnce(&RequestBuilder<'a>) -> T,
+    {
+        let i = self.select();
+        let outstanding = self.outstanding[i].clone();
+        outstanding.fetch_add(1, Ordering::SeqCst);
+        let future = request(&self.servers[i].request());
+        Balanced {
+            future,
+            outstanding: Some(OutstandingGuard(outstanding)),
+        }
+    }
+
+    /// 選択したサーバからlumpデータを取得する.
+    ///
+    /// 詳細は`RequestBuilder::get_lump`を参照のこと.
+    pub fn get_lump(&self, device_id: DeviceId, lump_id: LumpId) -> Balanced<GetLumpFuture> {
+        self.read(|request| request.get_lump(device_id, lump_id))
+    }
+
+    /// 選択したサーバからlumpヘッダを取得する.
+    ///
+    /// 詳細は`RequestBuilder::head_lump`を参照のこと.
+    pub fn head_lump(&self, device_id: DeviceId, lump_id: LumpId) -> Balanced<HeadLumpFuture> {
+        self.read(|request| request.head_lump(device_id, lump_id))
+    }
+
+    /// 選択したサーバから、デバイス内のlumpのID一覧を取得する.
+    ///
+    /// 詳細は`RequestBuilder::list_lumps`を参照のこと.
+    pub fn list_lumps(&self, device_id: DeviceId) -> Balanced<ListLumpsFuture> {
+        self.read(|request| request.list_lumps(device_id))
+    }
+
+    /// プライマリにlumpを保存する.
+    ///
+    /// 詳細は`RequestBuilder::put_lump`を参照のこと.
+    pub fn put_lump(
+        &self,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        lump_data: LumpData,
+    ) -> PutLumpFuture {
+        self.primary()
+            .request()
+            .put_lump(device_id, lump_id, lump_data)
+    }
+
+    /// プライマリからlumpを削除する.
+    ///
+    /// 詳細は`RequestBuilder::delete_lump`を参照のこと.
+    pub fn delete_lump(&self, device_id: DeviceId, lump_id: LumpId) -> DeleteLumpFuture {
+        self.primary().request().delete_lump(device_id, lump_id)
+    }
+
+    fn select(&self) -> usize {
+        let n = self.servers.len();
+        let start = self.next.fetch_add(1, Ordering::SeqCst) % n;
+        match self.policy {
+            BalancePolicy::RoundRobin => start,
+            BalancePolicy::LeastOutstandingRequests => (0..n)
+                .map(|offset| (start + offset) % n)
+                .min_by_key(|&i| self.outstanding[i].load(Ordering::SeqCst))
+                .expect("Never fails"),
+        }
+    }
+}
+
+/// `ReplicaSet::read`が返す`Future`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Balanced<F> {
+    future: F,
+    outstanding: Option<OutstandingGuard>,
+}
+impl<F: Future> Future for Balanced<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let polled = self.future.poll();
+        if !matches!(polled, Ok(Async::NotReady)) {
+            self.outstanding.take();
+        }
+        polled
+    }
+}
+
+// 応答待ちのリクエスト数を、ドロップ時に減らすためのガード.
+#[derive(Debug)]
+struct OutstandingGuard(Arc<AtomicUsize>);
+impl Drop for OutstandingGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fibers::{Executor, InPlaceExecutor};
+    use fibers_rpc::client::ClientService;
+    use futures::future;
+
+    use super::*;
+
+    fn replica_set(policy: BalancePolicy) -> ReplicaSet {
+        let executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+        let service = ClientService::new(executor.handle());
+        let client = |port| {
+            let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+            Client::new(addr, service.handle())
+        };
+        ReplicaSet::new(client(1919), vec![client(1920), client(1921)], policy)
+    }
+
+    fn server(set: &ReplicaSet) -> u16 {
+        let i = set.select();
+        set.servers()[i].server().port()
+    }
+
+    #[test]
+    fn round_robin_works() {
+        let set = replica_set(BalancePolicy::RoundRobin);
+        let ports = (0..4).map(|_| server(&set)).collect::<Vec<_>>();
+        assert_eq!(ports, vec![1919, 1920, 1921, 1919]);
+    }
+
+    #[test]
+    fn least_outstanding_requests_works() {
+        let set = replica_set(BalancePolicy::LeastOutstandingRequests);
+
+        // 応答待ちのリクエストが無い間は、順番に選択される
+        let first = set.read(|_| future::ok::<(), ()>(()));
+        let second = set.read(|_| future::ok::<(), ()>(()));
+        assert_eq!(server(&set), 1921);
+
+        // 応答待ちが最も少ないサーバが選択される
+        std::mem::drop(second);
+        assert_eq!(server(&set), 1920);
+        std::mem::drop(first);
+        assert!(set
+            .outstanding
+            .iter()
+            .all(|n| n.load(Ordering::SeqCst) == 0));
+    }
+}
@@ -0,0 +1,135 @@
+//! ホスト名で指定されたRPCサーバのアドレスの、定期的な再解決.
+use cannyls::{ErrorKind, Result};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+use trackable::error::ErrorKindExt;
+
+/// 再解決間隔のデフォルト値.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// `"ホスト名:ポート番号"`形式のエンドポイントのアドレスを保持し、定期的に再解決するためのもの.
+///
+/// 再解決はバックグラウンドのスレッドで行われ、全てのクローンがドロップされた後の、
+/// 最初の再解決のタイミングでスレッドは終了する.
+///
+/// インスタンスをクローンした場合には、同じ状態が共有される.
+#[derive(Debug, Clone)]
+pub(crate) struct Resolver(Arc<Inner>);
+impl Resolver {
+    /// エンドポイントのアドレスを解決した上で、新しい`Resolver`インスタンスを生成する.
+    ///
+    /// 解決に失敗した場合には`ErrorKind::InvalidInput`エラーが返される.
+    pub fn new(endpoint: &str) -> Result<Self> {
+        let addr = track!(resolve(endpoint, None); endpoint)?;
+        let inner = Arc::new(Inner {
+            endpoint: endpoint.to_owned(),
+            addr: Mutex::new(addr),
+            interval: Mutex::new(DEFAULT_INTERVAL),
+        });
+        let weak = Arc::downgrade(&inner);
+        track!(thread::Builder::new()
+            .name(format!("cannyls_rpc_resolver({})", endpoint))
+            .spawn(move || run(weak))
+            .map_err(|e| ErrorKind::Other.cause(e)))?;
+        Ok(Resolver(inner))
+    }
+
+    /// エンドポイントを返す.
+    pub fn endpoint(&self) -> &str {
+        &self.0.endpoint
+    }
+
+    /// 最後に解決されたアドレスを返す.
+    pub fn addr(&self) -> SocketAddr {
+        *lock(&self.0.addr)
+    }
+
+    /// 再解決の間隔を設定する.
+    ///
+    /// 新しい間隔は、次回の再解決の後から適用される.
+    pub fn set_interval(&self, interval: Duration) {
+        *lock(&self.0.interval) = interval;
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    endpoint: String,
+    addr: Mutex<SocketAddr>,
+    interval: Mutex<Duration>,
+}
+
+fn run(inner: Weak<Inner>) {
+    loop {
+        let interval = match inner.upgrade() {
+            None => return,
+            Some(inner) => *lock(&inner.interval),
+        };
+        thread::sleep(interval);
+
+        let inner = match inner.upgrade() {
+            None => return,
+            Some(inner) => inner,
+        };
+        let current = *lock(&inner.addr);
+
+        // 一時的な解決の失敗時には、最後に解決されたアドレスを使い続ける
+        if let Ok(addr) = resolve(&inner.endpoint, Some(current)) {
+            *lock(&inner.addr) = addr;
+        }
+    }
+}
+
+// 複数のアドレスが得られた場合には、接続先が不必要に切り替わらないように`current`を優先する.
+fn resolve(endpoint: &str, current: Option<SocketAddr>) -> Result<SocketAddr> {
+    let addrs = track!(endpoint
+        .to_socket_addrs()
+        .map_err(|e| ErrorKind::InvalidInput.cause(e)))?
+    .collect::<Vec<_>>();
+    let addr = track_assert_some!(
+        select(&addrs, current),
+        ErrorKind::InvalidInput,
+        "No address is associated with the endpoint"
+    );
+    Ok(addr)
+}
+
+fn select(addrs: &[SocketAddr], current: Option<SocketAddr>) -> Option<SocketAddr> {
+    current
+        .filter(|addr| addrs.contains(addr))
+        .or_else(|| addrs.first().cloned())
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // 値の更新中にパニックすることはないので、ポイズンは無視して構わない
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_works() {
+        let addr = track_try_unwrap!(resolve("127.0.0.1:1919", None));
+        assert_eq!(addr, "127.0.0.1:1919".parse().unwrap());
+
+        // ポート番号の指定は必須
+        let e = resolve("localhost", None).err().unwrap();
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn select_works() {
+        let a: SocketAddr = "127.0.0.1:1919".parse().unwrap();
+        let b: SocketAddr = "127.0.0.2:1919".parse().unwrap();
+        let c: SocketAddr = "127.0.0.3:1919".parse().unwrap();
+
+        assert_eq!(select(&[a, b], None), Some(a));
+        assert_eq!(select(&[a, b], Some(b)), Some(b));
+        assert_eq!(select(&[a, b], Some(c)), Some(a));
+        assert_eq!(select(&[], Some(c)), None);
+    }
+}
@@ -0,0 +1,533 @@
+//! `ErrorKind::DeviceBusy`等で失敗したリクエストの再試行.
+use cannyls::deadline::Deadline;
+use cannyls::{Error, ErrorKind};
+use fibers::time::timer::{self, Timeout};
+use futures::{Async, Future, Poll};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::client::RequestBuilder;
+use crate::compat::Spawner;
+use crate::error::RetryClass;
+use crate::rpc::BusyHint;
+
+/// `ErrorKind::DeviceBusy`等で失敗したリクエストを再試行する際のポリシー.
+///
+/// 過負荷時に即座に再試行を行うと、過負荷の原因となっているキューの詰まりを悪化させてしまうため、
+/// 再試行の前には、指数的に増加する待機時間(ジッター付き)が挿入される.
+///
+/// エラーにサーバからの`rpc::BusyHint`が付与されている場合には、その`retry_after`が待機時間の下限となる.
+/// (その場合も`budget`等による制限は適用される)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusyRetryPolicy {
+    /// 再試行の最大回数.
+    ///
+    /// デフォルト値は`5`.
+    pub max_retries: usize,
+
+    /// 最初の再試行の前の待機時間の基準値.
+    ///
+    /// 再試行の度に二倍になり、実際の待機時間は基準値の半分から基準値までの間でランダムに決定される.
+    ///
+    /// デフォルト値は`10ms`.
+    pub initial_backoff: Duration,
+
+    /// 待機時間の基準値の上限.
+    ///
+    /// デフォルト値は`1s`.
+    pub max_backoff: Duration,
+
+    /// 再試行のための待機時間の合計の上限.
+    ///
+    /// 次の待機によってこの値を超えてしまう場合には、再試行は行われずにエラーが返される.
+    ///
+    /// デフォルト値は`5s`.
+    pub budget: Duration,
+
+    /// 再試行の際に`RequestBuilder::max_queue_len`を緩和する場合の上限値.
+    ///
+    /// `Some`の場合には、再試行の度に、キューの長さ制限が直前の値の二倍(ただし、この値が上限)に引き上げられる.
+    /// リクエストビルダでキューの長さ制限が指定されていない場合には、この値は無視される.
+    ///
+    /// デフォルト値は`None`.
+    pub max_queue_len_cap: Option<usize>,
+
+    /// `true`の場合には、リクエストビルダで指定された`Deadline::Within`の期間を、再試行を含めた全体の時間予算として扱う.
+    ///
+    /// 各試行のデッドラインおよびRPCのタイムアウトは、その時点での残り時間に縮められ、
+    /// 待機後に残り時間が無くなってしまう場合には、再試行は行われずにエラーが返される.
+    /// そのため、呼び出し側が指定したデッドラインよりも長く待たされることはない.
+    /// (`budget`による制限も、引き続き適用される)
+    ///
+    /// リクエストビルダのデッドラインが`Deadline::Within`以外の場合には、この値は無視される.
+    ///
+    /// デフォルト値は`false`.
+    pub deadline_aware: bool,
+
+    /// 再試行の対象となるエラーの種類.
+    ///
+    /// `ErrorKind::DeviceBusy`以外(e.g., 通信エラーを表す`ErrorKind::Other`)を含めた場合には、
+    /// サーバ側で処理済みのリクエストが再送される可能性があるので、更新系の操作では注意が必要.
+    ///
+    /// デフォルト値は`vec![ErrorKind::DeviceBusy]`.
+    pub retryable_kinds: Vec<ErrorKind>,
+
+    /// `true`の場合には、`retryable_kinds`に含まれない種類のエラーであっても、
+    /// `RetryClass::of`で`RetryClass::Transient`に分類されるもの(e.g., 通信層のタイムアウト)は再試行の対象とする.
+    ///
+    /// `retryable_kinds`に通信エラー(`ErrorKind::Other`)を含めた場合と同様の注意が必要.
+    ///
+    /// デフォルト値は`false`.
+    pub retry_transient: bool,
+}
+impl Default for BusyRetryPolicy {
+    fn default() -> Self {
+        BusyRetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+            budget: Duration::from_secs(5),
+            max_queue_len_cap: None,
+            deadline_aware: false,
+            retryable_kinds: vec![ErrorKind::DeviceBusy],
+            retry_transient: false,
+        }
+    }
+}
+impl BusyRetryPolicy {
+    /// このポリシーにおいて、指定のエラーが再試行の対象となるかどうかを返す.
+    pub fn is_retriable(&self, e: &Error) -> bool {
+        self.retryable_kinds.contains(e.kind())
+            || (self.retry_transient && RetryClass::of(e) == RetryClass::Transient)
+    }
+}
+
+/// `ErrorKind::DeviceBusy`等で失敗したリクエストを、ポリシーに従って再試行する`Future`.
+///
+/// `RequestBuilder::retry_on_busy`ないし`RequestBuilder::retry`によって生成される.
+#[must_use = "futures do nothing unless polled"]
+pub struct BusyRetry<'a, F, T> {
+    builder: RequestBuilder<'a>,
+    policy: BusyRetryPolicy,
+    request: F,
+    phase: Phase<T>,
+    retries: usize,
+    waited: Duration,
+    deadline_budget: Option<(Instant, Duration)>,
+    rand: XorShift,
+}
+impl<'a, F, T> BusyRetry<'a, F, T>
+where
+    F: for<'b> FnMut(&RequestBuilder<'b>) -> T,
+    T: Future<Error = Error>,
+{
+    pub(crate) fn new(
+        mut builder: RequestBuilder<'a>,
+        policy: BusyRetryPolicy,
+        mut request: F,
+    ) -> Self {
+        let deadline_budget = match builder.current_deadline() {
+            Some(Deadline::Within(d)) if policy.deadline_aware => Some((Instant::now(), d)),
+            _ => None,
+        };
+        if let Some((_, budget)) = deadline_budget {
+            builder.shrink_deadline(budget);
+        }
+        let phase = Phase::Requesting(request(&builder));
+        BusyRetry {
+            builder,
+            policy,
+            request,
+            phase,
+            retries: 0,
+            waited: Duration::from_secs(0),
+            deadline_budget,
+            rand: XorShift::new(),
+        }
+    }
+
+    /// これまでに行われた再試行の回数を返す.
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    pub(crate) fn into_owned(self) -> BusyRetry<'static, F, T> {
+        BusyRetry {
+            builder: self.builder.into_owned(),
+            policy: self.policy,
+            request: self.request,
+            phase: self.phase,
+            retries: self.retries,
+            waited: self.waited,
+            deadline_budget: self.deadline_budget,
+            rand: self.rand,
+        }
+    }
+
+    pub(crate) fn spawner(&self) -> Option<&Spawner> {
+        self.builder.spawner()
+    }
+
+    fn next_backoff(&mut self, hint: Option<&BusyHint>) -> Option<Duration> {
+        if self.retries >= self.policy.max_retries {
+            return None;
+        }
+        let base = (0..self.retries)
+            .fold(self.policy.initial_backoff, |d, _| d * 2)
+            .min(self.policy.max_backoff);
+        let base_nanos = duration_to_nanos(base);
+        let jitter = if base_nanos < 2 {
+            0
+        } else {
+            self.rand.next() % (base_nanos / 2 + 1)
+        };
+        let mut backoff = Duration::from_nanos(base_nanos - jitter);
+        if let Some(hint) = hint {
+            backoff = backoff.max(hint.retry_after);
+        }
+        if self.waited + backoff > self.policy.budget {
+            return None;
+        }
+        if let Some(remaining) = self.remaining_deadline() {
+            if backoff >= remaining {
+                return None;
+            }
+        }
+        self.retries += 1;
+        self.waited += backoff;
+        Some(backoff)
+    }
+
+    fn remaining_deadline(&self) -> Option<Duration> {
+        self.deadline_budget.map(|(started, budget)| {
+            budget
+                .checked_sub(started.elapsed())
+                .unwrap_or_else(|| Duration::from_secs(0))
+        })
+    }
+
+    fn shrink_deadline(&mut self) -> bool {
+        if let Some(remaining) = self.remaining_deadline() {
+            if remaining == Duration::from_secs(0) {
+                return false;
+            }
+            self.builder.shrink_deadline(remaining);
+        }
+        true
+    }
+
+    fn escalate_max_queue_len(&mut self) {
+        if let Some(cap) = self.policy.max_queue_len_cap {
+            self.builder.escalate_max_queue_len(cap);
+        }
+    }
+}
+impl<'a, F, T> Future for BusyRetry<'a, F, T>
+where
+    F: for<'b> FnMut(&RequestBuilder<'b>) -> T,
+    T: Future<Error = Error>,
+{
+    type Item = T::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next = match self.phase {
+                Phase::Requesting(ref mut f) => match f.poll() {
+                    Err(e) => {
+                        if !self.policy.is_retriable(&e) {
+                            return Err(e);
+                        }
+                        if let Some(backoff) = self.next_backoff(BusyHint::of(&e)) {
+                            Phase::Waiting(timer::timeout(backoff))
+                        } else {
+                            return Err(track!(e; self.retries, self.waited));
+                        }
+                    }
+                    Ok(polled) => return Ok(polled),
+                },
+                Phase::Waiting(ref mut f) => {
+                    if let Ok(Async::NotReady) = f.poll() {
+                        return Ok(Async::NotReady);
+                    }
+                    if !self.shrink_deadline() {
+                        track_panic!(
+                            ErrorKind::DeviceBusy,
+                            "Deadline exceeded while waiting to retry";
+                            self.retries,
+                            self.waited
+                        );
+                    }
+                    self.escalate_max_queue_len();
+                    Phase::Requesting((self.request)(&self.builder))
+                }
+            };
+            self.phase = next;
+        }
+    }
+}
+impl<'a, F, T> std::fmt::Debug for BusyRetry<'a, F, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BusyRetry")
+            .field("builder", &self.builder)
+            .field("policy", &self.policy)
+            .field("retries", &self.retries)
+            .field("waited", &self.waited)
+            .finish()
+    }
+}
+
+enum Phase<T> {
+    Requesting(T),
+    Waiting(Timeout),
+}
+
+// ジッター生成用の簡易な疑似乱数生成器.
+#[derive(Debug)]
+struct XorShift(u64);
+impl XorShift {
+    fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| u64::from(d.subsec_nanos()) ^ d.as_secs())
+            .unwrap_or(0);
+        XorShift(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn duration_to_nanos(d: Duration) -> u64 {
+    d.as_secs()
+        .saturating_mul(1_000_000_000)
+        .saturating_add(u64::from(d.subsec_nanos()))
+}
+
+#[cfg(test)]
+mod tests {
+    use fibers::{Executor, InPlaceExecutor};
+    use fibers_rpc::client::ClientService;
+    use futures::future;
+    use std::cell::{Cell, RefCell};
+    use std::thread;
+    use trackable::error::ErrorKindExt;
+
+    use super::*;
+    use crate::client::Client;
+
+    fn client() -> Client {
+        let executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+        let service = ClientService::new(executor.handle());
+        Client::new("127.0.0.1:1919".parse().unwrap(), service.handle())
+    }
+
+    fn no_wait_policy() -> BusyRetryPolicy {
+        BusyRetryPolicy {
+            initial_backoff: Duration::from_millis(0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn busy_retry_works() {
+        let client = client();
+        let attempts = Cell::new(0);
+        let queue_lens = RefCell::new(Vec::new());
+        let mut policy = no_wait_policy();
+        policy.max_queue_len_cap = Some(25);
+
+        let mut future = client
+            .request()
+            .max_queue_len(10)
+            .retry_on_busy(policy, |request| {
+                queue_lens
+                    .borrow_mut()
+                    .push(request.current_max_queue_len());
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 4 {
+                    future::err(ErrorKind::DeviceBusy.into())
+                } else {
+                    future::ok(attempts.get())
+                }
+            });
+        assert_eq!(track_try_unwrap!(future.poll()), Async::Ready(4));
+        assert_eq!(future.retries(), 3);
+        assert_eq!(
+            *queue_lens.borrow(),
+            vec![Some(10), Some(20), Some(25), Some(25)]
+        );
+    }
+
+    #[test]
+    fn busy_retry_gives_up() {
+        let client = client();
+
+        // 回数制限
+        let attempts = Cell::new(0);
+        let mut future = client.request().retry_on_busy(no_wait_policy(), |_| {
+            attempts.set(attempts.get() + 1);
+            future::err::<(), _>(ErrorKind::DeviceBusy.into())
+        });
+        let e = future.poll().err().unwrap();
+        assert_eq!(*e.kind(), ErrorKind::DeviceBusy);
+        assert_eq!(attempts.get(), 6);
+
+        // 待機時間の合計の上限
+        let mut policy = no_wait_policy();
+        policy.initial_backoff = Duration::from_secs(10);
+        policy.max_backoff = Duration::from_secs(10);
+        policy.budget = Duration::from_secs(1);
+        let mut future = client.request().retry_on_busy(policy, |_| {
+            future::err::<(), _>(ErrorKind::DeviceBusy.into())
+        });
+        assert_eq!(*future.poll().err().unwrap().kind(), ErrorKind::DeviceBusy);
+        assert_eq!(future.retries(), 0);
+
+        // `DeviceBusy`以外のエラーは再試行されない
+        let mut future = client.request().retry_on_busy(no_wait_policy(), |_| {
+            future::err::<(), _>(ErrorKind::InvalidInput.into())
+        });
+        assert_eq!(
+            *future.poll().err().unwrap().kind(),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(future.retries(), 0);
+    }
+
+    #[test]
+    fn retryable_kinds_works() {
+        let mut client = client();
+        let mut policy = no_wait_policy();
+        policy.retryable_kinds = vec![ErrorKind::Other];
+        client.set_retry_policy(policy);
+
+        let attempts = Cell::new(0);
+        let mut future = client.request().retry(|_| {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                future::err(ErrorKind::Other.into())
+            } else {
+                future::ok(attempts.get())
+            }
+        });
+        assert_eq!(track_try_unwrap!(future.poll()), Async::Ready(3));
+        assert_eq!(future.retries(), 2);
+
+        // 対象外となった`DeviceBusy`は再試行されない
+        let mut future = client
+            .request()
+            .retry(|_| future::err::<(), _>(ErrorKind::DeviceBusy.into()));
+        assert_eq!(*future.poll().err().unwrap().kind(), ErrorKind::DeviceBusy);
+        assert_eq!(future.retries(), 0);
+    }
+
+    #[test]
+    fn busy_hint_works() {
+        let client = client();
+        let busy = |retry_after| {
+            let hint = BusyHint {
+                queue_len: 100,
+                retry_after,
+            };
+            hint.into_error(&ErrorKind::DeviceBusy.error())
+        };
+
+        // ヒントの待機時間が予算を超える場合には、再試行は行われない
+        let mut future = client.request().retry_on_busy(no_wait_policy(), |_| {
+            future::err::<(), _>(busy(Duration::from_secs(10)))
+        });
+        let e = future.poll().err().unwrap();
+        assert_eq!(*e.kind(), ErrorKind::DeviceBusy);
+        assert!(BusyHint::of(&e).is_some());
+        assert_eq!(future.retries(), 0);
+
+        let mut future = client.request().retry_on_busy(no_wait_policy(), |_| {
+            future::err::<(), _>(busy(Duration::from_millis(0)))
+        });
+        assert!(future.poll().is_err());
+        assert_eq!(future.retries(), 5);
+    }
+
+    #[test]
+    fn retry_transient_works() {
+        let timeout = || {
+            let e = fibers_rpc::Error::from(fibers_rpc::ErrorKind::Timeout.error());
+            crate::error::from_rpc_error(e, "127.0.0.1:1919".parse().unwrap())
+        };
+        let mut policy = no_wait_policy();
+        assert!(!policy.is_retriable(&timeout()));
+        policy.retry_transient = true;
+        assert!(policy.is_retriable(&timeout()));
+        assert!(policy.is_retriable(&ErrorKind::DeviceBusy.into()));
+        assert!(!policy.is_retriable(&ErrorKind::StorageCorrupted.into()));
+
+        let client = client();
+        let attempts = Cell::new(0);
+        let mut future = client.request().retry_on_busy(policy, |_| {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                future::err(timeout())
+            } else {
+                future::ok(attempts.get())
+            }
+        });
+        assert_eq!(track_try_unwrap!(future.poll()), Async::Ready(3));
+        assert_eq!(future.retries(), 2);
+    }
+
+    #[test]
+    fn deadline_aware_busy_retry_works() {
+        let client = client();
+
+        // 各試行のデッドラインは、残り時間に縮められる
+        let deadlines = RefCell::new(Vec::new());
+        let mut policy = no_wait_policy();
+        policy.deadline_aware = true;
+        let mut future = client
+            .request()
+            .deadline(Deadline::Within(Duration::from_secs(10)))
+            .retry_on_busy(policy.clone(), |request| {
+                deadlines.borrow_mut().push(request.current_deadline());
+                if deadlines.borrow().len() < 3 {
+                    future::err(ErrorKind::DeviceBusy.into())
+                } else {
+                    future::ok(())
+                }
+            });
+        assert_eq!(track_try_unwrap!(future.poll()), Async::Ready(()));
+        let deadlines = deadlines.into_inner();
+        assert_eq!(deadlines.len(), 3);
+        let mut last = Duration::from_secs(10);
+        for deadline in deadlines {
+            if let Some(Deadline::Within(d)) = deadline {
+                assert!(d <= last);
+                last = d;
+            } else {
+                panic!("Unexpected deadline: {:?}", deadline);
+            }
+        }
+
+        // デッドラインを超えて待機することはない
+        policy.initial_backoff = Duration::from_millis(20);
+        policy.max_backoff = Duration::from_millis(20);
+        let mut future = client
+            .request()
+            .deadline(Deadline::Within(Duration::from_millis(30)))
+            .retry_on_busy(policy, |_| {
+                future::err::<(), _>(ErrorKind::DeviceBusy.into())
+            });
+        let e = loop {
+            match future.poll() {
+                Err(e) => break e,
+                Ok(Async::Ready(())) => panic!(),
+                Ok(Async::NotReady) => thread::sleep(Duration::from_millis(1)),
+            }
+        };
+        assert_eq!(*e.kind(), ErrorKind::DeviceBusy);
+        assert!(future.retries() <= 2);
+    }
+}
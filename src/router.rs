@@ -0,0 +1,187 @@
+//! コンシステントハッシュに基づく、lumpの配置先(サーバとデバイス)の決定.
+use cannyls::lump::{LumpData, LumpId};
+
+use crate::client::{Client, DeleteLumpFuture, GetLumpFuture, HeadLumpFuture, PutLumpFuture};
+use crate::device::DeviceId;
+
+/// 各配置先に割り当てる仮想ノード数のデフォルト値.
+pub const DEFAULT_VIRTUAL_NODES: usize = 100;
+
+/// Lump(ないしアプリケーション固有のキー)を、コンシステントハッシュによって配置先に振り分けるルータ.
+///
+/// 配置先は「サーバとそのデバイス」の組で、各配置先はハッシュリング上の複数の仮想ノードとして配置される.
+/// 配置先の追加・削除時に、振り分け先が変わるのは一部のキーのみとなる.
+///
+/// ハッシュ値はサーバのアドレスとデバイスのIDのみから決定されるので、
+/// 同じ配置先の一覧を与えれば、(配置先の順番やプロセスに関わらず)同じ振り分け結果が得られる.
+#[derive(Debug, Clone)]
+pub struct Router {
+    targets: Vec<(Client, DeviceId)>,
+    ring: Vec<(u64, usize)>, // (ハッシュ値, `targets`のインデックス)
+}
+impl Router {
+    /// 新しい`Router`インスタンスを生成する.
+    ///
+    /// 各配置先には`DEFAULT_VIRTUAL_NODES`個の仮想ノードが割り当てられる.
+    pub fn new(targets: Vec<(Client, DeviceId)>) -> Self {
+        Self::with_virtual_nodes(targets, DEFAULT_VIRTUAL_NODES)
+    }
+
+    /// 各配置先に割り当てる仮想ノード数を指定して、新しい`Router`インスタンスを生成する.
+    ///
+    /// 仮想ノード数を増やすほど、キーの分布の偏りは小さくなる.
+    pub fn with_virtual_nodes(targets: Vec<(Client, DeviceId)>, virtual_nodes: usize) -> Self {
+        let mut ring = Vec::with_capacity(targets.len() * virtual_nodes);
+        for (i, (client, device_id)) in targets.iter().enumerate() {
+            for vnode in 0..virtual_nodes {
+                let node = format!("{}/{}#{}", client.server(), device_id.as_str(), vnode);
+                ring.push((hash(node.as_bytes()), i));
+            }
+        }
+        ring.sort();
+        Router { targets, ring }
+    }
+
+    /// 配置先の一覧を返す.
+    pub fn targets(&self) -> &[(Client, DeviceId)] {
+        &self.targets
+    }
+
+    /// 指定lumpの配置先を返す.
+    ///
+    /// 配置先が一つも存在しない場合には`None`が返される.
+    pub fn route(&self, lump_id: LumpId) -> Option<(&Client, &DeviceId)> {
+        self.route_key(&lump_id.as_u128().to_be_bytes())
+    }
+
+    /// アプリケーション固有のキーの配置先を返す.
+    ///
+    /// 配置先が一つも存在しない場合には`None`が返される.
+    pub fn route_key(&self, key: &[u8]) -> Option<(&Client, &DeviceId)> {
+        if self.ring.is_empty() {
+            return None;
+        }
+        let h = hash(key);
+        let i = match self.ring.binary_search_by(|&(x, _)| x.cmp(&h)) {
+            Ok(i) => i,
+            Err(i) => i % self.ring.len(),
+        };
+        let (ref client, ref device_id) = self.targets[self.ring[i].1];
+        Some((client, device_id))
+    }
+
+    /// 配置先からlumpデータを取得する.
+    ///
+    /// 詳細は`RequestBuilder::get_lump`を参照のこと.
+    ///
+    /// # Panics
+    ///
+    /// 配置先が一つも存在しない場合にはパニックする(以降のメソッドも同様).
+    pub fn get_lump(&self, lump_id: LumpId) -> GetLumpFuture {
+        let (client, device_id) = self.route_or_panic(lump_id);
+        client.request().get_lump(device_id.clone(), lump_id)
+    }
+
+    /// 配置先からlumpヘッダを取得する.
+    ///
+    /// 詳細は`RequestBuilder::head_lump`を参照のこと.
+    pub fn head_lump(&self, lump_id: LumpId) -> HeadLumpFuture {
+        let (client, device_id) = self.route_or_panic(lump_id);
+        client.request().head_lump(device_id.clone(), lump_id)
+    }
+
+    /// 配置先にlumpを保存する.
+    ///
+    /// 詳細は`RequestBuilder::put_lump`を参照のこと.
+    pub fn put_lump(&self, lump_id: LumpId, lump_data: LumpData) -> PutLumpFuture {
+        let (client, device_id) = self.route_or_panic(lump_id);
+        client
+            .request()
+            .put_lump(device_id.clone(), lump_id, lump_data)
+    }
+
+    /// 配置先からlumpを削除する.
+    ///
+    /// 詳細は`RequestBuilder::delete_lump`を参照のこと.
+    pub fn delete_lump(&self, lump_id: LumpId) -> DeleteLumpFuture {
+        let (client, device_id) = self.route_or_panic(lump_id);
+        client.request().delete_lump(device_id.clone(), lump_id)
+    }
+
+    fn route_or_panic(&self, lump_id: LumpId) -> (&Client, &DeviceId) {
+        self.route(lump_id).expect("No routing target")
+    }
+}
+
+// 実装(ないしバージョン)に依存せずに、同一の値が得られるハッシュ関数.
+//
+// FNV-1a(64bit)の結果を、分布の偏りを減らすためにSplitMix64の最終処理で撹拌している.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        h ^= u64::from(b);
+        h = h.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use fibers::{Executor, InPlaceExecutor};
+    use fibers_rpc::client::ClientService;
+
+    use super::*;
+
+    fn targets(ports: &[u16]) -> Vec<(Client, DeviceId)> {
+        let executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+        let service = ClientService::new(executor.handle());
+        ports
+            .iter()
+            .map(|port| {
+                let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+                (Client::new(addr, service.handle()), DeviceId::new("foo"))
+            })
+            .collect()
+    }
+
+    fn port(router: &Router, lump_id: u128) -> u16 {
+        router
+            .route(LumpId::new(lump_id))
+            .unwrap()
+            .0
+            .server()
+            .port()
+    }
+
+    #[test]
+    fn router_works() {
+        assert!(Router::new(Vec::new()).route(LumpId::new(0)).is_none());
+
+        let router = Router::new(targets(&[1919, 1920, 1921]));
+        let mut counts = [0; 3];
+        for i in 0..3000 {
+            counts[usize::from(port(&router, i) - 1919)] += 1;
+        }
+        for &count in &counts {
+            assert!(count > 500, "{:?}", counts);
+        }
+
+        // 配置先の順番は、振り分け結果に影響しない
+        let reversed = Router::new(targets(&[1921, 1920, 1919]));
+        assert!((0..1000).all(|i| port(&router, i) == port(&reversed, i)));
+
+        // 配置先を追加しても、既存の配置先間でキーが移動することはない
+        let extended = Router::new(targets(&[1919, 1920, 1921, 1922]));
+        let mut moved = 0;
+        for i in 0..3000 {
+            let after = port(&extended, i);
+            if after != port(&router, i) {
+                assert_eq!(after, 1922);
+                moved += 1;
+            }
+        }
+        assert!(moved > 0 && moved < 1500, "{}", moved);
+    }
+}
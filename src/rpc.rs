@@ -1,24 +1,89 @@
+//! RPCの定義.
+//!
+//! 通常は`Client`および`Server`経由で利用されるため、これらを直接扱う必要はないが、
+//! 個々のRPCのハンドラをラップする場合等のために公開されている(`Server::register_except`を参照).
 use cannyls::deadline::Deadline;
+#[cfg(any(feature = "server", all(feature = "client", feature = "registry")))]
 use cannyls::device::{self, DeviceHandle};
 use cannyls::lump::{LumpData, LumpHeader, LumpId};
-use cannyls::storage::StorageUsage;
+use cannyls::storage::{StorageHeader, StorageUsage};
+#[cfg(feature = "server")]
+use cannyls::ErrorKind;
 use cannyls::Result;
-use fibers_rpc::{Call, ProcedureId};
+use fibers_rpc::{Call, Cast, ProcedureId};
+use slog::Level;
+use std::fmt;
+use std::io::Read;
 use std::ops::Range;
+use std::time::Duration;
+use trackable::error::TrackableError;
+use trackable::{History, Location, Trackable};
 
-use crate::device::DeviceId;
+use crate::device::{DeviceId, DeviceLabels, DeviceSettings, DeviceSpec};
+use crate::info::{
+    DeviceMetricsSnapshot, DeviceReadiness, DeviceStatusReport, DeviceSummary, ImportSessionStatus,
+    InFlightRequest, JournalUsage, RequestStats, ServerInfo,
+};
 use crate::protobuf::{
-    DeleteLumpRequestDecoder, DeleteLumpRequestEncoder, DeleteRangeResponseDecoder,
-    DeleteRangeResponseEncoder, DeviceRequestDecoder, DeviceRequestEncoder, GetLumpResponseDecoder,
-    GetLumpResponseEncoder, HeadLumpResponseDecoder, HeadLumpResponseEncoder,
-    ListLumpResponseDecoder, ListLumpResponseEncoder, LumpRequestDecoder, LumpRequestEncoder,
-    PutLumpRequestDecoder, PutLumpRequestEncoder, PutLumpResponseDecoder, PutLumpResponseEncoder,
-    RangeLumpRequestDecoder, RangeLumpRequestEncoder, UsageRangeRequestDecoder,
+    CancelInFlightRequestDecoder, CancelInFlightRequestEncoder, CancelInFlightResponseDecoder,
+    CancelInFlightResponseEncoder, CopyRangeRequestDecoder, CopyRangeRequestEncoder,
+    DeleteDeviceResponseDecoder, DeleteDeviceResponseEncoder, DeleteLumpRequestDecoder,
+    DeleteLumpRequestEncoder, DeleteLumpV2ResponseDecoder, DeleteLumpV2ResponseEncoder,
+    DeleteLumpWithMetaResponseDecoder, DeleteLumpWithMetaResponseEncoder,
+    DeleteRangeBoundedRequestDecoder, DeleteRangeBoundedRequestEncoder,
+    DeleteRangeBoundedResponseDecoder, DeleteRangeBoundedResponseEncoder,
+    DeleteRangeResponseDecoder, DeleteRangeResponseEncoder, DeviceLabelsResponseDecoder,
+    DeviceLabelsResponseEncoder, DeviceRequestDecoder, DeviceRequestEncoder,
+    DeviceSettingsResponseDecoder, DeviceSettingsResponseEncoder, DeviceStatusResponseDecoder,
+    DeviceStatusResponseEncoder, ExistsLumpResponseDecoder, ExistsLumpResponseEncoder,
+    ExportLumpsRequestDecoder, ExportLumpsRequestEncoder, ExportLumpsResponseDecoder,
+    ExportLumpsResponseEncoder, GetLumpRangeRequestDecoder, GetLumpRangeRequestEncoder,
+    GetLumpResponseDecoder, GetLumpResponseEncoder, GetLumpToWriterResponseDecoder,
+    GetLumpToWriterResponseEncoder, GetLumpWithChecksumResponseDecoder,
+    GetLumpWithChecksumResponseEncoder, GetLumpWithMetaResponseDecoder,
+    GetLumpWithMetaResponseEncoder, GetLumpsRequestDecoder, GetLumpsRequestEncoder,
+    GetLumpsResponseDecoder, GetLumpsResponseEncoder, HeadLumpResponseDecoder,
+    HeadLumpResponseEncoder, HeadLumpWithMetaResponseDecoder, HeadLumpWithMetaResponseEncoder,
+    ImportLumpsRequestDecoder, ImportLumpsRequestEncoder, ImportSessionRequestDecoder,
+    ImportSessionRequestEncoder, ImportSessionResponseDecoder, ImportSessionResponseEncoder,
+    JournalUsageResponseDecoder, JournalUsageResponseEncoder, ListDevicesRequestDecoder,
+    ListDevicesRequestEncoder, ListDevicesResponseDecoder, ListDevicesResponseEncoder,
+    ListInFlightRequestDecoder, ListInFlightRequestEncoder, ListInFlightResponseDecoder,
+    ListInFlightResponseEncoder, ListLumpResponseDecoder, ListLumpResponseEncoder,
+    ListLumpsChunkResponseDecoder, ListLumpsChunkResponseEncoder, LogLevelDecoder, LogLevelEncoder,
+    LogLevelResponseDecoder, LogLevelResponseEncoder, LumpRequestDecoder, LumpRequestEncoder,
+    MetricsSnapshotRequestDecoder, MetricsSnapshotRequestEncoder, MetricsSnapshotResponseDecoder,
+    MetricsSnapshotResponseEncoder, ProvisionDeviceRequestDecoder, ProvisionDeviceRequestEncoder,
+    ProvisionDeviceResponseDecoder, ProvisionDeviceResponseEncoder,
+    PutLumpFromReaderRequestDecoder, PutLumpFromReaderRequestEncoder, PutLumpRequestDecoder,
+    PutLumpRequestEncoder, PutLumpResponseDecoder, PutLumpResponseEncoder,
+    PutLumpV2ResponseDecoder, PutLumpV2ResponseEncoder, PutLumpWithMetaResponseDecoder,
+    PutLumpWithMetaResponseEncoder, PutLumpsRequestDecoder, PutLumpsRequestEncoder,
+    PutLumpsResponseDecoder, PutLumpsResponseEncoder, RangeLumpRequestDecoder,
+    RangeLumpRequestEncoder, ReadinessRequestDecoder, ReadinessRequestEncoder,
+    ReadinessResponseDecoder, ReadinessResponseEncoder, RequestStatsRequestDecoder,
+    RequestStatsRequestEncoder, RequestStatsResponseDecoder, RequestStatsResponseEncoder,
+    ScriptRequestDecoder, ScriptRequestEncoder, ScriptResponseDecoder, ScriptResponseEncoder,
+    ServerInfoRequestDecoder, ServerInfoRequestEncoder, ServerInfoResponseDecoder,
+    ServerInfoResponseEncoder, SetJournalSyncRequestDecoder, SetJournalSyncRequestEncoder,
+    SetQueueLimitsRequestDecoder, SetQueueLimitsRequestEncoder, SetWriteWatermarkRequestDecoder,
+    SetWriteWatermarkRequestEncoder, StopDeviceResponseDecoder, StopDeviceResponseEncoder,
+    StorageHeaderResponseDecoder, StorageHeaderResponseEncoder, UsageRangeRequestDecoder,
     UsageRangeRequestEncoder, UsageRangeResponseDecoder, UsageRangeResponseEncoder,
 };
 
 const NS_CANNYLS: u32 = 0x0001_0000; // cannyls用のRPCの名前空間(ID範囲)
 
+// 事前条件の違反を示すエラーメッセージ.
+const PRECONDITION_FAILED: &str = "Precondition failed";
+
+// 名前空間内のID割り当て:
+// - `0x00xx`: lumpに対する操作
+// - `0x01xx`: デバイス自体に対する操作
+// - `0x02xx`: 管理用の操作(サーバ側で明示的に有効にされている場合にのみ利用可能)
+// - `0x03xx`: サーバ自体に対する操作
+
+/// Lumpデータを取得するRPC.
 #[derive(Debug)]
 pub struct GetLumpRpc;
 impl Call for GetLumpRpc {
@@ -34,6 +99,91 @@ impl Call for GetLumpRpc {
     type ResEncoder = GetLumpResponseEncoder;
 }
 
+/// `GetLumpRpc`の応答に`ResponseMeta`を含めたもの.
+///
+/// `GetLumpRpc`と同じIDを用いる、同じRPCの別の定義.
+/// サーバ側ではこちらが登録され、リクエストのオプションで`RequestOptions::response_meta`が指定されている場合には、
+/// 成功時の応答にメタ情報が付与される(`GetLumpRpc`として受信した場合には、単に無視される).
+///
+/// メタ情報に対応していないサーバからの応答の場合には、メタ情報は`None`となる.
+/// 以降の`*WithMetaRpc`も同様.
+#[derive(Debug)]
+pub struct GetLumpWithMetaRpc;
+impl Call for GetLumpWithMetaRpc {
+    const ID: ProcedureId = GetLumpRpc::ID;
+    const NAME: &'static str = "cannyls.lump.get_with_meta";
+
+    type Req = LumpRequest;
+    type ReqDecoder = LumpRequestDecoder;
+    type ReqEncoder = LumpRequestEncoder;
+
+    type Res = Result<(Option<LumpData>, Option<ResponseMeta>)>;
+    type ResDecoder = GetLumpWithMetaResponseDecoder;
+    type ResEncoder = GetLumpWithMetaResponseEncoder;
+}
+
+/// Lumpデータの一部(バイト範囲)を取得するRPC.
+///
+/// 範囲の終端がデータの末尾を超える場合には、末尾までのデータが返される
+/// (開始位置も末尾を超える場合には空となる).
+///
+/// なお、デバイスからはデータ全体が読み込まれるため、削減されるのは転送量とクライアント側のメモリ使用量のみ.
+#[derive(Debug)]
+pub struct GetLumpRangeRpc;
+impl Call for GetLumpRangeRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0018);
+    const NAME: &'static str = "cannyls.lump.get_range";
+
+    type Req = GetLumpRangeRequest;
+    type ReqDecoder = GetLumpRangeRequestDecoder;
+    type ReqEncoder = GetLumpRangeRequestEncoder;
+
+    type Res = Result<Option<LumpData>>;
+    type ResDecoder = GetLumpResponseDecoder;
+    type ResEncoder = GetLumpResponseEncoder;
+}
+
+/// Lumpデータを取得し、メモリ上に保持せずに書き込み先に出力するRPC.
+///
+/// `GetLumpRpc`と同じIDを用いるクライアント専用の定義で、応答のデコーダとして
+/// `GetLumpToWriterResponseDecoder::new`で生成したものを指定して利用する.
+/// サーバ側では`GetLumpRpc`として処理されるため、このRPCをサーバに登録してはいけない.
+///
+/// 応答は、書き込み先に出力されたバイト数.
+#[derive(Debug)]
+pub struct GetLumpToWriterRpc;
+impl Call for GetLumpToWriterRpc {
+    const ID: ProcedureId = GetLumpRpc::ID;
+    const NAME: &'static str = "cannyls.lump.get_to_writer";
+
+    type Req = LumpRequest;
+    type ReqDecoder = LumpRequestDecoder;
+    type ReqEncoder = LumpRequestEncoder;
+
+    type Res = Result<Option<u64>>;
+    type ResDecoder = GetLumpToWriterResponseDecoder;
+    type ResEncoder = GetLumpToWriterResponseEncoder;
+}
+
+/// Lumpデータを、そのチェックサム(Adler-32)と共に取得するRPC.
+///
+/// 応答のチェックサムは、サーバがデバイスから読み込んだデータに対して計算したもの.
+#[derive(Debug)]
+pub struct GetLumpWithChecksumRpc;
+impl Call for GetLumpWithChecksumRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0009);
+    const NAME: &'static str = "cannyls.lump.get_with_checksum";
+
+    type Req = LumpRequest;
+    type ReqDecoder = LumpRequestDecoder;
+    type ReqEncoder = LumpRequestEncoder;
+
+    type Res = Result<Option<(LumpData, u32)>>;
+    type ResDecoder = GetLumpWithChecksumResponseDecoder;
+    type ResEncoder = GetLumpWithChecksumResponseEncoder;
+}
+
+/// Lumpヘッダを取得するRPC.
 #[derive(Debug)]
 pub struct HeadLumpRpc;
 impl Call for HeadLumpRpc {
@@ -49,6 +199,43 @@ impl Call for HeadLumpRpc {
     type ResEncoder = HeadLumpResponseEncoder;
 }
 
+/// `HeadLumpRpc`の応答に`ResponseMeta`を含めたもの.
+///
+/// 詳細は`GetLumpWithMetaRpc`を参照のこと.
+#[derive(Debug)]
+pub struct HeadLumpWithMetaRpc;
+impl Call for HeadLumpWithMetaRpc {
+    const ID: ProcedureId = HeadLumpRpc::ID;
+    const NAME: &'static str = "cannyls.lump.head_with_meta";
+
+    type Req = LumpRequest;
+    type ReqDecoder = LumpRequestDecoder;
+    type ReqEncoder = LumpRequestEncoder;
+
+    type Res = Result<(Option<LumpHeader>, Option<ResponseMeta>)>;
+    type ResDecoder = HeadLumpWithMetaResponseDecoder;
+    type ResEncoder = HeadLumpWithMetaResponseEncoder;
+}
+
+/// Lumpが存在するかどうかを判定するRPC.
+///
+/// `HeadLumpRpc`よりも応答が小さく、存在確認のみが必要な場合に用いる.
+#[derive(Debug)]
+pub struct ExistsLumpRpc;
+impl Call for ExistsLumpRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x000D);
+    const NAME: &'static str = "cannyls.lump.exists";
+
+    type Req = LumpRequest;
+    type ReqDecoder = LumpRequestDecoder;
+    type ReqEncoder = LumpRequestEncoder;
+
+    type Res = Result<bool>;
+    type ResDecoder = ExistsLumpResponseDecoder;
+    type ResEncoder = ExistsLumpResponseEncoder;
+}
+
+/// Lumpを保存するRPC.
 #[derive(Debug)]
 pub struct PutLumpRpc;
 impl Call for PutLumpRpc {
@@ -64,6 +251,81 @@ impl Call for PutLumpRpc {
     type ResEncoder = PutLumpResponseEncoder;
 }
 
+/// `PutLumpRpc`の応答に`ResponseMeta`を含めたもの.
+///
+/// 詳細は`GetLumpWithMetaRpc`を参照のこと.
+#[derive(Debug)]
+pub struct PutLumpWithMetaRpc;
+impl Call for PutLumpWithMetaRpc {
+    const ID: ProcedureId = PutLumpRpc::ID;
+    const NAME: &'static str = "cannyls.lump.put_with_meta";
+
+    type Req = PutLumpRequest;
+    type ReqDecoder = PutLumpRequestDecoder;
+    type ReqEncoder = PutLumpRequestEncoder;
+
+    type Res = Result<(bool, Option<ResponseMeta>)>;
+    type ResDecoder = PutLumpWithMetaResponseDecoder;
+    type ResEncoder = PutLumpWithMetaResponseEncoder;
+}
+
+/// 応答を返さずにlumpを保存する通知RPC.
+///
+/// リクエストの内容とサーバ側の処理は`PutLumpRpc`と同様だが、処理結果(失敗を含む)はクライアントには通知されない.
+/// 結果を必要としない、遅延に寛容な書き込み(e.g., テレメトリ)向け.
+#[derive(Debug)]
+pub struct PutLumpNoAckRpc;
+impl Cast for PutLumpNoAckRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x001A);
+    const NAME: &'static str = "cannyls.lump.put_noack";
+
+    type Notification = PutLumpRequest;
+    type Decoder = PutLumpRequestDecoder;
+    type Encoder = PutLumpRequestEncoder;
+}
+
+/// Lumpを保存し、上書きされたlumpのヘッダを取得するRPC.
+///
+/// リクエストは`PutLumpRpc`と同じで、応答は保存前の対象lumpのヘッダとなる
+/// (新規作成の場合には`None`).
+///
+/// ヘッダの取得と保存は別々のコマンドとしてデバイスに発行されるが、
+/// その間、同じlumpに対する単一lumpの更新(PUT・DELETE)は待機させられる(`Precondition`も参照).
+#[derive(Debug)]
+pub struct PutLumpV2Rpc;
+impl Call for PutLumpV2Rpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0016);
+    const NAME: &'static str = "cannyls.lump.put_v2";
+
+    type Req = PutLumpRequest;
+    type ReqDecoder = PutLumpRequestDecoder;
+    type ReqEncoder = PutLumpRequestEncoder;
+
+    type Res = Result<Option<LumpHeader>>;
+    type ResDecoder = PutLumpV2ResponseDecoder;
+    type ResEncoder = PutLumpV2ResponseEncoder;
+}
+
+/// Lumpデータを読み込み元から順次読み込みながら、lumpを保存するRPC.
+///
+/// `PutLumpRpc`と同じIDを用いるクライアント専用の定義で、サーバ側では`PutLumpRpc`として処理される.
+/// そのため、このRPCをサーバに登録してはいけない.
+#[derive(Debug)]
+pub struct PutLumpFromReaderRpc;
+impl Call for PutLumpFromReaderRpc {
+    const ID: ProcedureId = PutLumpRpc::ID;
+    const NAME: &'static str = "cannyls.lump.put_from_reader";
+
+    type Req = PutLumpFromReaderRequest;
+    type ReqDecoder = PutLumpFromReaderRequestDecoder;
+    type ReqEncoder = PutLumpFromReaderRequestEncoder;
+
+    type Res = Result<bool>;
+    type ResDecoder = PutLumpResponseDecoder;
+    type ResEncoder = PutLumpResponseEncoder;
+}
+
+/// Lumpを削除するRPC.
 #[derive(Debug)]
 pub struct DeleteLumpRpc;
 impl Call for DeleteLumpRpc {
@@ -79,6 +341,62 @@ impl Call for DeleteLumpRpc {
     type ResEncoder = DeleteLumpRequestEncoder;
 }
 
+/// `DeleteLumpRpc`の応答に`ResponseMeta`を含めたもの.
+///
+/// 詳細は`GetLumpWithMetaRpc`を参照のこと.
+#[derive(Debug)]
+pub struct DeleteLumpWithMetaRpc;
+impl Call for DeleteLumpWithMetaRpc {
+    const ID: ProcedureId = DeleteLumpRpc::ID;
+    const NAME: &'static str = "cannyls.lump.delete_with_meta";
+
+    type Req = LumpRequest;
+    type ReqDecoder = LumpRequestDecoder;
+    type ReqEncoder = LumpRequestEncoder;
+
+    type Res = Result<(bool, Option<ResponseMeta>)>;
+    type ResDecoder = DeleteLumpWithMetaResponseDecoder;
+    type ResEncoder = DeleteLumpWithMetaResponseEncoder;
+}
+
+/// 応答を返さずにlumpを削除する通知RPC.
+///
+/// リクエストの内容とサーバ側の処理は`DeleteLumpRpc`と同様だが、処理結果(失敗を含む)はクライアントには通知されない.
+/// 確認応答を必要としない、ベストエフォートの削除(e.g., キャッシュの無効化)向け.
+#[derive(Debug)]
+pub struct DeleteLumpNoAckRpc;
+impl Cast for DeleteLumpNoAckRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x001B);
+    const NAME: &'static str = "cannyls.lump.delete_noack";
+
+    type Notification = LumpRequest;
+    type Decoder = LumpRequestDecoder;
+    type Encoder = LumpRequestEncoder;
+}
+
+/// Lumpを削除し、削除されたlumpのヘッダを取得するRPC.
+///
+/// リクエストは`DeleteLumpRpc`と同じで、応答は削除されたlumpのヘッダとなる
+/// (対象lumpが存在しなかった場合には`None`).
+///
+/// ヘッダの取得と削除は別々のコマンドとしてデバイスに発行されるが、
+/// その間、同じlumpに対する単一lumpの更新(PUT・DELETE)は待機させられる(`Precondition`も参照).
+#[derive(Debug)]
+pub struct DeleteLumpV2Rpc;
+impl Call for DeleteLumpV2Rpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0017);
+    const NAME: &'static str = "cannyls.lump.delete_v2";
+
+    type Req = LumpRequest;
+    type ReqDecoder = LumpRequestDecoder;
+    type ReqEncoder = LumpRequestEncoder;
+
+    type Res = Result<Option<LumpHeader>>;
+    type ResDecoder = DeleteLumpV2ResponseDecoder;
+    type ResEncoder = DeleteLumpV2ResponseEncoder;
+}
+
+/// デバイスに保存されているlumpのID一覧を取得するRPC.
 #[derive(Debug)]
 pub struct ListLumpRpc;
 impl Call for ListLumpRpc {
@@ -94,6 +412,208 @@ impl Call for ListLumpRpc {
     type ResEncoder = ListLumpResponseEncoder;
 }
 
+/// lumpの範囲を指定して、その範囲内に保存されているlumpのID一覧を取得するRPC.
+#[derive(Debug)]
+pub struct ListLumpRangeRpc;
+impl Call for ListLumpRangeRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x000C);
+    const NAME: &'static str = "cannyls.lump.list_range";
+
+    type Req = RangeLumpRequest;
+    type ReqDecoder = RangeLumpRequestDecoder;
+    type ReqEncoder = RangeLumpRequestEncoder;
+
+    type Res = Result<Vec<LumpId>>;
+    type ResDecoder = ListLumpResponseDecoder;
+    type ResEncoder = ListLumpResponseEncoder;
+}
+
+/// lumpの範囲を指定して、その範囲内の全てのlumpの読み込みを試行し、失敗したlumpのID一覧を取得するRPC.
+///
+/// データを転送せずに、破損したlumpを検出するために使われる.
+///
+/// 個々のlumpの読み込みは順番に行われるため、範囲内のlumpの数によっては処理に時間が掛かる.
+/// なお、デバイスが一時的に利用不可能な場合等(e.g., `ErrorKind::DeviceBusy`)には、
+/// 処理全体が中断されて、そのエラーが返される.
+#[derive(Debug)]
+pub struct ScrubRangeRpc;
+impl Call for ScrubRangeRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x000E);
+    const NAME: &'static str = "cannyls.lump.scrub_range";
+
+    type Req = RangeLumpRequest;
+    type ReqDecoder = RangeLumpRequestDecoder;
+    type ReqEncoder = RangeLumpRequestEncoder;
+
+    type Res = Result<Vec<LumpId>>;
+    type ResDecoder = ListLumpResponseDecoder;
+    type ResEncoder = ListLumpResponseEncoder;
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
+}
+
+/// lumpの範囲を指定して、その範囲内の全てのlumpを、サーバ上の別のデバイスにコピーするRPC.
+///
+/// 結果はコピーされたlumpのID一覧(昇順).
+/// コピー先に同じIDのlumpが存在する場合には、それは上書きされる.
+///
+/// 個々のlumpのコピーは順番に行われ、一つでも失敗した場合には、その時点で処理全体が中断される
+/// (それまでにコピーされたlumpは、コピー先に残ったままとなる).
+#[derive(Debug)]
+pub struct CopyRangeRpc;
+impl Call for CopyRangeRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0014);
+    const NAME: &'static str = "cannyls.lump.copy_range";
+
+    type Req = CopyRangeRequest;
+    type ReqDecoder = CopyRangeRequestDecoder;
+    type ReqEncoder = CopyRangeRequestEncoder;
+
+    type Res = Result<Vec<LumpId>>;
+    type ResDecoder = ListLumpResponseDecoder;
+    type ResEncoder = ListLumpResponseEncoder;
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
+}
+
+/// lumpの範囲を指定して、その範囲内のlumpのIDとデータの組を、チャンク単位で取得するRPC.
+///
+/// 一回の呼び出しで返されるのは、範囲の先頭から最大で`ExportLumpsRequest::max_lumps`個までのlumpとなる.
+/// 範囲内に残りのlumpが存在する場合には、応答の`ExportLumpsChunk::next`に、次回の範囲の開始位置が設定される.
+///
+/// クライアントからは`RequestBuilder::export_lumps`経由で、`Stream`として利用される.
+#[derive(Debug)]
+pub struct ExportLumpsRpc;
+impl Call for ExportLumpsRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x000F);
+    const NAME: &'static str = "cannyls.lump.export";
+
+    type Req = ExportLumpsRequest;
+    type ReqDecoder = ExportLumpsRequestDecoder;
+    type ReqEncoder = ExportLumpsRequestEncoder;
+
+    type Res = Result<ExportLumpsChunk>;
+    type ResDecoder = ExportLumpsResponseDecoder;
+    type ResEncoder = ExportLumpsResponseEncoder;
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
+}
+
+/// lumpの範囲を指定して、その範囲内のlumpのID一覧を、チャンク単位で取得するRPC.
+///
+/// リクエストは`ExportLumpsRpc`と同じで、一回の呼び出しで返されるのは、範囲の先頭から最大で`max_lumps`個までのIDとなる.
+/// 範囲内に残りのlumpが存在する場合には、応答の`ListLumpsChunk::next`に、次回の範囲の開始位置が設定される.
+///
+/// クライアントからは`RequestBuilder::list_lumps_stream`経由で、`Stream`として利用される.
+#[derive(Debug)]
+pub struct ListLumpsChunkRpc;
+impl Call for ListLumpsChunkRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x001C);
+    const NAME: &'static str = "cannyls.lump.list_chunk";
+
+    type Req = ExportLumpsRequest;
+    type ReqDecoder = ExportLumpsRequestDecoder;
+    type ReqEncoder = ExportLumpsRequestEncoder;
+
+    type Res = Result<ListLumpsChunk>;
+    type ResDecoder = ListLumpsChunkResponseDecoder;
+    type ResEncoder = ListLumpsChunkResponseEncoder;
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
+}
+
+/// 一つのデバイスを対象とした、インポートセッションを開始するRPC.
+///
+/// インポートセッションを使うと、大量のlumpをバッチ単位で順番に保存することができる
+/// (i.e., バックアップからのデバイスの復元やデータの移行用).
+/// 各バッチには、セッション内での連番(最初は`0`)が付与され、
+/// 処理済みの番号のバッチが再送された場合には、それは無視される.
+/// そのため、通信エラー等でバッチの結果が不明となった場合には、同じバッチを再送することで、
+/// インポートを途中から再開することができる(`ImportSessionStatusRpc`を参照).
+///
+/// セッションはサーバのメモリ上にのみ保持され、`CommitImportSessionRpc`の呼び出しで終了する.
+///
+/// サーバで署名の検証が有効な場合には、セッションはこのRPCの署名の鍵に紐付けられ、
+/// 以降のセッションに対するリクエストは、同じ鍵で署名されている必要がある.
+#[derive(Debug)]
+pub struct OpenImportSessionRpc;
+impl Call for OpenImportSessionRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0010);
+    const NAME: &'static str = "cannyls.lump.import.open";
+
+    type Req = DeviceRequest;
+    type ReqDecoder = DeviceRequestDecoder;
+    type ReqEncoder = DeviceRequestEncoder;
+
+    type Res = Result<ImportSessionStatus>;
+    type ResDecoder = ImportSessionResponseDecoder;
+    type ResEncoder = ImportSessionResponseEncoder;
+}
+
+/// インポートセッションに、lumpのバッチを送信するRPC.
+///
+/// バッチ内のlumpの保存に一つでも失敗した場合には、バッチ全体が失敗したものとして扱われ、
+/// 同じ番号のバッチを再送する必要がある.
+#[derive(Debug)]
+pub struct ImportLumpsRpc;
+impl Call for ImportLumpsRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0011);
+    const NAME: &'static str = "cannyls.lump.import.batch";
+
+    type Req = ImportLumpsRequest;
+    type ReqDecoder = ImportLumpsRequestDecoder;
+    type ReqEncoder = ImportLumpsRequestEncoder;
+
+    type Res = Result<ImportSessionStatus>;
+    type ResDecoder = ImportSessionResponseDecoder;
+    type ResEncoder = ImportSessionResponseEncoder;
+
+    fn enable_async_request(_: &Self::Req) -> bool {
+        true
+    }
+}
+
+/// インポートセッションの現在の状態を取得するRPC.
+#[derive(Debug)]
+pub struct ImportSessionStatusRpc;
+impl Call for ImportSessionStatusRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0012);
+    const NAME: &'static str = "cannyls.lump.import.status";
+
+    type Req = ImportSessionRequest;
+    type ReqDecoder = ImportSessionRequestDecoder;
+    type ReqEncoder = ImportSessionRequestEncoder;
+
+    type Res = Result<ImportSessionStatus>;
+    type ResDecoder = ImportSessionResponseDecoder;
+    type ResEncoder = ImportSessionResponseEncoder;
+}
+
+/// インポートセッションを終了し、その結果の要約を取得するRPC.
+#[derive(Debug)]
+pub struct CommitImportSessionRpc;
+impl Call for CommitImportSessionRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0013);
+    const NAME: &'static str = "cannyls.lump.import.commit";
+
+    type Req = ImportSessionRequest;
+    type ReqDecoder = ImportSessionRequestDecoder;
+    type ReqEncoder = ImportSessionRequestEncoder;
+
+    type Res = Result<ImportSessionStatus>;
+    type ResDecoder = ImportSessionResponseDecoder;
+    type ResEncoder = ImportSessionResponseEncoder;
+}
+
+/// lumpの範囲を指定してストレージ使用量を取得するRPC.
 #[derive(Debug)]
 pub struct UsageRangeRpc;
 impl Call for UsageRangeRpc {
@@ -109,6 +629,7 @@ impl Call for UsageRangeRpc {
     type ResEncoder = UsageRangeResponseEncoder;
 }
 
+/// lumpの範囲を指定して削除するRPC.
 #[derive(Debug)]
 pub struct DeleteRangeRpc;
 impl Call for DeleteRangeRpc {
@@ -128,57 +649,1131 @@ impl Call for DeleteRangeRpc {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct RequestOptions {
-    pub deadline: Deadline,
-    pub max_queue_len: Option<usize>,
-    pub prioritized: bool,
-}
-impl RequestOptions {
-    pub fn with<'a>(&self, device: &'a DeviceHandle) -> device::DeviceRequest<'a> {
-        let mut request = device.request();
-        request.deadline(self.deadline);
-        if self.prioritized {
-            request.prioritized();
-        }
-        if let Some(n) = self.max_queue_len {
-            request.max_queue_len(n);
-        }
-        request
+/// 一つのデバイスに対する複数の操作をまとめて実行するRPC.
+#[derive(Debug)]
+pub struct ScriptRpc;
+impl Call for ScriptRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0008);
+    const NAME: &'static str = "cannyls.lump.script";
+
+    type Req = ScriptRequest;
+    type ReqDecoder = ScriptRequestDecoder;
+    type ReqEncoder = ScriptRequestEncoder;
+
+    type Res = Result<Vec<Result<ScriptOpResult>>>;
+    type ResDecoder = ScriptResponseDecoder;
+    type ResEncoder = ScriptResponseEncoder;
+
+    fn enable_async_request(_: &Self::Req) -> bool {
+        true
+    }
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
     }
 }
 
+/// 一つのデバイスに対する複数の操作を、並行して実行するRPC.
+///
+/// `ScriptRpc`とは異なり、先行する操作の完了を待たずに、全ての操作が連続してデバイスのキューに投入される.
+/// 操作同士は指定された順番で処理され、操作列は一つの単位としてアトミックに実行される.
+/// つまり、サーバは操作対象の全てのlumpのロックを獲得してから操作を投入し、全ての操作が完了するまでそれらを保持するので、
+/// 操作の合間に同じlumpに対する他の更新リクエストが割り込むことはない.
+/// ただし、一部の操作が失敗した場合にも、他の操作の結果が取り消されることはない(各操作の結果は応答に個別に格納される).
+///
+/// なお操作の投入前に、全ての操作の検証(e.g., 書き込みの可否)が行われ、
+/// 一つでも検証に失敗した場合には、いずれの操作も実行されずにリクエスト全体がエラーとなる.
 #[derive(Debug)]
-pub struct DeviceRequest {
-    pub device_id: DeviceId,
-    pub options: RequestOptions,
+pub struct ConcurrentScriptRpc;
+impl Call for ConcurrentScriptRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0019);
+    const NAME: &'static str = "cannyls.lump.concurrent_script";
+
+    type Req = ScriptRequest;
+    type ReqDecoder = ScriptRequestDecoder;
+    type ReqEncoder = ScriptRequestEncoder;
+
+    type Res = Result<Vec<Result<ScriptOpResult>>>;
+    type ResDecoder = ScriptResponseDecoder;
+    type ResEncoder = ScriptResponseEncoder;
+
+    fn enable_async_request(_: &Self::Req) -> bool {
+        true
+    }
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
 }
 
+/// lumpの範囲を指定して、その先頭から最大`max_lumps`個までのlumpを削除するRPC.
+///
+/// 巨大な範囲の削除によって、デバイスのコマンドキューが長時間占有されてしまうのを避けるためのもの.
+/// 応答に含まれる`DeleteRangeChunk::next`を開始位置として繰り返し呼び出すことで、
+/// 他のリクエストを挟みながら、範囲全体を段階的に削除することができる.
 #[derive(Debug)]
-pub struct LumpRequest {
-    pub device_id: DeviceId,
-    pub lump_id: LumpId,
-    pub options: RequestOptions,
+pub struct DeleteRangeBoundedRpc;
+impl Call for DeleteRangeBoundedRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0015);
+    const NAME: &'static str = "cannyls.lump.delete_range_bounded";
+
+    type Req = DeleteRangeBoundedRequest;
+    type ReqDecoder = DeleteRangeBoundedRequestDecoder;
+    type ReqEncoder = DeleteRangeBoundedRequestEncoder;
+
+    type Res = Result<DeleteRangeChunk>;
+    type ResDecoder = DeleteRangeBoundedResponseDecoder;
+    type ResEncoder = DeleteRangeBoundedResponseEncoder;
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
 }
 
+/// 一つのデバイスに対して、複数のlumpをまとめて保存するRPC.
 #[derive(Debug)]
-pub struct PutLumpRequest {
-    pub device_id: DeviceId,
-    pub lump_id: LumpId,
-    pub lump_data: LumpData,
-    pub options: RequestOptions,
+pub struct PutLumpsRpc;
+impl Call for PutLumpsRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x000A);
+    const NAME: &'static str = "cannyls.lump.put_batch";
+
+    type Req = PutLumpsRequest;
+    type ReqDecoder = PutLumpsRequestDecoder;
+    type ReqEncoder = PutLumpsRequestEncoder;
+
+    type Res = Result<Vec<Result<bool>>>;
+    type ResDecoder = PutLumpsResponseDecoder;
+    type ResEncoder = PutLumpsResponseEncoder;
+
+    fn enable_async_request(_: &Self::Req) -> bool {
+        true
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct UsageRangeRequest {
-    pub device_id: DeviceId,
-    pub range: Range<LumpId>,
-    pub options: RequestOptions,
+/// 一つのデバイスから、複数のlumpのデータをまとめて取得するRPC.
+#[derive(Debug)]
+pub struct GetLumpsRpc;
+impl Call for GetLumpsRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x000B);
+    const NAME: &'static str = "cannyls.lump.get_batch";
+
+    type Req = GetLumpsRequest;
+    type ReqDecoder = GetLumpsRequestDecoder;
+    type ReqEncoder = GetLumpsRequestEncoder;
+
+    type Res = Result<Vec<Result<Option<LumpData>>>>;
+    type ResDecoder = GetLumpsResponseDecoder;
+    type ResEncoder = GetLumpsResponseEncoder;
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct RangeLumpRequest {
-    pub device_id: DeviceId,
+/// デバイスのジャーナル領域の使用状況を取得するRPC.
+#[derive(Debug)]
+pub struct JournalUsageRpc;
+impl Call for JournalUsageRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0101);
+    const NAME: &'static str = "cannyls.device.journal_usage";
+
+    type Req = DeviceRequest;
+    type ReqDecoder = DeviceRequestDecoder;
+    type ReqEncoder = DeviceRequestEncoder;
+
+    type Res = Result<JournalUsage>;
+    type ResDecoder = JournalUsageResponseDecoder;
+    type ResEncoder = JournalUsageResponseEncoder;
+}
+
+/// デバイスのストレージのヘッダ(ブロックサイズや各領域のサイズ等)を取得するRPC.
+///
+/// 対象デバイスは`DeviceRegistryHandle::put_device_with_storage_metrics`を使って
+/// 登録されている必要がある.
+#[derive(Debug)]
+pub struct StorageHeaderRpc;
+impl Call for StorageHeaderRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0109);
+    const NAME: &'static str = "cannyls.device.storage_header";
+
+    type Req = DeviceRequest;
+    type ReqDecoder = DeviceRequestDecoder;
+    type ReqEncoder = DeviceRequestEncoder;
+
+    type Res = Result<StorageHeader>;
+    type ResDecoder = StorageHeaderResponseDecoder;
+    type ResEncoder = StorageHeaderResponseEncoder;
+}
+
+/// デバイスのメトリクスのスナップショットを取得するRPC.
+#[derive(Debug)]
+pub struct MetricsSnapshotRpc;
+impl Call for MetricsSnapshotRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0102);
+    const NAME: &'static str = "cannyls.device.metrics_snapshot";
+
+    type Req = Vec<DeviceId>;
+    type ReqDecoder = MetricsSnapshotRequestDecoder;
+    type ReqEncoder = MetricsSnapshotRequestEncoder;
+
+    type Res = Result<Vec<DeviceMetricsSnapshot>>;
+    type ResDecoder = MetricsSnapshotResponseDecoder;
+    type ResEncoder = MetricsSnapshotResponseEncoder;
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
+}
+
+/// リクエストの統計情報を取得するRPC.
+#[derive(Debug)]
+pub struct RequestStatsRpc;
+impl Call for RequestStatsRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0103);
+    const NAME: &'static str = "cannyls.device.request_stats";
+
+    type Req = Vec<DeviceId>;
+    type ReqDecoder = RequestStatsRequestDecoder;
+    type ReqEncoder = RequestStatsRequestEncoder;
+
+    type Res = Result<Vec<RequestStats>>;
+    type ResDecoder = RequestStatsResponseDecoder;
+    type ResEncoder = RequestStatsResponseEncoder;
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
+}
+
+/// デバイスがリクエストを処理可能かどうかを確認するRPC.
+#[derive(Debug)]
+pub struct ReadinessRpc;
+impl Call for ReadinessRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0104);
+    const NAME: &'static str = "cannyls.device.readiness";
+
+    type Req = Vec<DeviceId>;
+    type ReqDecoder = ReadinessRequestDecoder;
+    type ReqEncoder = ReadinessRequestEncoder;
+
+    type Res = Result<Vec<DeviceReadiness>>;
+    type ResDecoder = ReadinessResponseDecoder;
+    type ResEncoder = ReadinessResponseEncoder;
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
+}
+
+/// 登録デバイスの一覧を取得するRPC.
+///
+/// リクエストの値が`true`の場合には、各デバイスの稼働状態も合わせて返される.
+#[derive(Debug)]
+pub struct ListDevicesRpc;
+impl Call for ListDevicesRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0105);
+    const NAME: &'static str = "cannyls.device.list";
+
+    type Req = bool;
+    type ReqDecoder = ListDevicesRequestDecoder;
+    type ReqEncoder = ListDevicesRequestEncoder;
+
+    type Res = Result<Vec<DeviceSummary>>;
+    type ResDecoder = ListDevicesResponseDecoder;
+    type ResEncoder = ListDevicesResponseEncoder;
+}
+
+/// 単一デバイスの状態(登録の有無、稼働状態、キューの長さ、ストレージの使用状況)を取得するRPC.
+///
+/// 対象デバイスが未登録の場合にも、エラーとはならない(`DeviceStatusReport::is_registered`を参照).
+#[derive(Debug)]
+pub struct DeviceStatusRpc;
+impl Call for DeviceStatusRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0108);
+    const NAME: &'static str = "cannyls.device.status";
+
+    type Req = DeviceRequest;
+    type ReqDecoder = DeviceRequestDecoder;
+    type ReqEncoder = DeviceRequestEncoder;
+
+    type Res = Result<DeviceStatusReport>;
+    type ResDecoder = DeviceStatusResponseDecoder;
+    type ResEncoder = DeviceStatusResponseEncoder;
+}
+
+/// デバイスに付与されたラベル群を取得するRPC.
+///
+/// ラベルはデバイスの登録時に指定されたもの(`DeviceRegistryHandle::put_device_with_labels`を参照).
+/// 対象デバイスが未登録の場合には`ErrorKind::InvalidInput`エラーが返される.
+#[derive(Debug)]
+pub struct DeviceLabelsRpc;
+impl Call for DeviceLabelsRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x010A);
+    const NAME: &'static str = "cannyls.device.labels";
+
+    type Req = DeviceRequest;
+    type ReqDecoder = DeviceRequestDecoder;
+    type ReqEncoder = DeviceRequestEncoder;
+
+    type Res = Result<DeviceLabels>;
+    type ResDecoder = DeviceLabelsResponseDecoder;
+    type ResEncoder = DeviceLabelsResponseEncoder;
+}
+
+/// デバイスをレジストリから削除する管理用RPC.
+///
+/// 応答の値は、削除対象のデバイスが登録されていたかどうかを表している.
+///
+/// 他の管理用RPCと同様に、`Server::enable_admin_rpc`が呼ばれている場合にのみ登録される.
+#[derive(Debug)]
+pub struct DeleteDeviceRpc;
+impl Call for DeleteDeviceRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0106);
+    const NAME: &'static str = "cannyls.device.delete";
+
+    type Req = DeviceRequest;
+    type ReqDecoder = DeviceRequestDecoder;
+    type ReqEncoder = DeviceRequestEncoder;
+
+    type Res = Result<bool>;
+    type ResDecoder = DeleteDeviceResponseDecoder;
+    type ResEncoder = DeleteDeviceResponseEncoder;
+}
+
+/// デバイスに停止命令を発行する管理用RPC.
+///
+/// 停止命令のデッドラインには、リクエストのオプションで指定されたものが使われる.
+/// 応答の値は、対象デバイスが登録されていたかどうかを表している.
+///
+/// 処理の詳細は`DeviceRegistryHandle::stop_device`を参照のこと.
+#[derive(Debug)]
+pub struct StopDeviceRpc;
+impl Call for StopDeviceRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0107);
+    const NAME: &'static str = "cannyls.device.stop";
+
+    type Req = DeviceRequest;
+    type ReqDecoder = DeviceRequestDecoder;
+    type ReqEncoder = DeviceRequestEncoder;
+
+    type Res = Result<bool>;
+    type ResDecoder = StopDeviceResponseDecoder;
+    type ResEncoder = StopDeviceResponseEncoder;
+}
+
+/// デバイスのジャーナル同期の設定を変更する管理用RPC.
+#[derive(Debug)]
+pub struct SetJournalSyncRpc;
+impl Call for SetJournalSyncRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0201);
+    const NAME: &'static str = "cannyls.admin.device.set_journal_sync";
+
+    type Req = SetJournalSyncRequest;
+    type ReqDecoder = SetJournalSyncRequestDecoder;
+    type ReqEncoder = SetJournalSyncRequestEncoder;
+
+    type Res = Result<DeviceSettings>;
+    type ResDecoder = DeviceSettingsResponseDecoder;
+    type ResEncoder = DeviceSettingsResponseEncoder;
+}
+
+/// デバイスのキューの長さ制限を変更する管理用RPC.
+#[derive(Debug)]
+pub struct SetQueueLimitsRpc;
+impl Call for SetQueueLimitsRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0202);
+    const NAME: &'static str = "cannyls.admin.device.set_queue_limits";
+
+    type Req = SetQueueLimitsRequest;
+    type ReqDecoder = SetQueueLimitsRequestDecoder;
+    type ReqEncoder = SetQueueLimitsRequestEncoder;
+
+    type Res = Result<DeviceSettings>;
+    type ResDecoder = DeviceSettingsResponseDecoder;
+    type ResEncoder = DeviceSettingsResponseEncoder;
+}
+
+/// デバイスの書き込みを制限する使用率を変更する管理用RPC.
+#[derive(Debug)]
+pub struct SetWriteWatermarkRpc;
+impl Call for SetWriteWatermarkRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0207);
+    const NAME: &'static str = "cannyls.admin.device.set_write_watermark";
+
+    type Req = SetWriteWatermarkRequest;
+    type ReqDecoder = SetWriteWatermarkRequestDecoder;
+    type ReqEncoder = SetWriteWatermarkRequestEncoder;
+
+    type Res = Result<DeviceSettings>;
+    type ResDecoder = DeviceSettingsResponseDecoder;
+    type ResEncoder = DeviceSettingsResponseEncoder;
+}
+
+/// ログ出力レベルを変更する管理用RPC.
+#[derive(Debug)]
+pub struct SetLogLevelRpc;
+impl Call for SetLogLevelRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0203);
+    const NAME: &'static str = "cannyls.admin.set_log_level";
+
+    type Req = Level;
+    type ReqDecoder = LogLevelDecoder;
+    type ReqEncoder = LogLevelEncoder;
+
+    type Res = Result<Level>;
+    type ResDecoder = LogLevelResponseDecoder;
+    type ResEncoder = LogLevelResponseEncoder;
+}
+
+/// 実行中のリクエスト一覧を取得する管理用RPC.
+#[derive(Debug)]
+pub struct ListInFlightRpc;
+impl Call for ListInFlightRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0204);
+    const NAME: &'static str = "cannyls.admin.in_flight.list";
+
+    type Req = Vec<DeviceId>;
+    type ReqDecoder = ListInFlightRequestDecoder;
+    type ReqEncoder = ListInFlightRequestEncoder;
+
+    type Res = Result<Vec<InFlightRequest>>;
+    type ResDecoder = ListInFlightResponseDecoder;
+    type ResEncoder = ListInFlightResponseEncoder;
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
+}
+
+/// 実行中のリクエストをキャンセルする管理用RPC.
+#[derive(Debug)]
+pub struct CancelInFlightRpc;
+impl Call for CancelInFlightRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0205);
+    const NAME: &'static str = "cannyls.admin.in_flight.cancel";
+
+    type Req = u64;
+    type ReqDecoder = CancelInFlightRequestDecoder;
+    type ReqEncoder = CancelInFlightRequestEncoder;
+
+    type Res = Result<bool>;
+    type ResDecoder = CancelInFlightResponseDecoder;
+    type ResEncoder = CancelInFlightResponseEncoder;
+}
+
+/// リクエストの統計情報をリセットする管理用RPC.
+#[derive(Debug)]
+pub struct ResetRequestStatsRpc;
+impl Call for ResetRequestStatsRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0206);
+    const NAME: &'static str = "cannyls.admin.request_stats.reset";
+
+    type Req = Vec<DeviceId>;
+    type ReqDecoder = RequestStatsRequestDecoder;
+    type ReqEncoder = RequestStatsRequestEncoder;
+
+    type Res = Result<Vec<RequestStats>>;
+    type ResDecoder = RequestStatsResponseDecoder;
+    type ResEncoder = RequestStatsResponseEncoder;
+
+    fn enable_async_response(_: &Self::Res) -> bool {
+        true
+    }
+}
+
+/// 仕様に従ってデバイスを構築(ないしオープン)し、レジストリに登録する管理用RPC.
+///
+/// 応答の値が`true`の場合にはストレージが新規に作成されたことを、
+/// `false`の場合には既存のストレージが開かれたことを、表している.
+///
+/// 処理の詳細は`provision`関数を参照のこと.
+/// 構築はサーバ内の専用のスレッドで一つずつ行われ、処理待ちの要求が多過ぎる場合には`ErrorKind::DeviceBusy`で拒否される.
+/// 指定可能な容量の上限は`Server::max_provision_capacity`で設定される.
+#[derive(Debug)]
+pub struct ProvisionDeviceRpc;
+impl Call for ProvisionDeviceRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0208);
+    const NAME: &'static str = "cannyls.admin.device.provision";
+
+    type Req = ProvisionDeviceRequest;
+    type ReqDecoder = ProvisionDeviceRequestDecoder;
+    type ReqEncoder = ProvisionDeviceRequestEncoder;
+
+    type Res = Result<bool>;
+    type ResDecoder = ProvisionDeviceResponseDecoder;
+    type ResEncoder = ProvisionDeviceResponseEncoder;
+}
+
+/// RPCサーバのバージョン、および、サーバが対応しているRPCの一覧を取得するRPC.
+///
+/// このRPCは、クライアントがサーバの対応状況を確認するためのものなので、
+/// `Server::enable_admin_rpc`の指定に関わらず登録される.
+///
+/// 応答のRPC一覧には、`Server`自身が登録したもののみが含まれる.
+/// (`Server::register_except`で除外され、別途独自に登録されたハンドラは含まれない)
+#[derive(Debug)]
+pub struct ServerInfoRpc;
+impl Call for ServerInfoRpc {
+    const ID: ProcedureId = ProcedureId(NS_CANNYLS | 0x0301);
+    const NAME: &'static str = "cannyls.server.info";
+
+    type Req = ();
+    type ReqDecoder = ServerInfoRequestDecoder;
+    type ReqEncoder = ServerInfoRequestEncoder;
+
+    type Res = Result<ServerInfo>;
+    type ResDecoder = ServerInfoResponseDecoder;
+    type ResEncoder = ServerInfoResponseEncoder;
+}
+
+/// デバイスに対するリクエストのオプション.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestOptions {
+    /// リクエスト処理のデッドライン.
+    pub deadline: Deadline,
+    /// リクエスト処理時のデバイスのキューの長さ制限.
+    pub max_queue_len: Option<usize>,
+    /// `true`の場合には、過負荷時でもリクエストが優先的に処理される.
+    pub prioritized: bool,
+
+    /// `true`の場合には、更新系の操作の度に、ジャーナルの同期が行われる.
+    ///
+    /// この値は送受信されずに、サーバ側でデバイスの設定に従って決定される.
+    pub journal_sync: bool,
+
+    /// `true`の場合には、サーバの設定(`Server::error_verbosity`)に関わらず、完全なエラーが返される.
+    ///
+    /// ただし、サーバ側で`Server::allow_verbose_errors`が呼ばれていない場合には無視される.
+    pub verbose_errors: bool,
+
+    /// `true`の場合には、成功時の応答に`ResponseMeta`が付与される.
+    ///
+    /// メタ情報を返すのは`*WithMetaRpc`として定義されているRPCのみで、それ以外のRPCでは無視される.
+    pub response_meta: bool,
+
+    /// 呼び出し元のリクエストを識別するためのID(e.g., 分散トレーシングのトレースID).
+    ///
+    /// 指定された場合には、サーバ側でのこのリクエストに関するログレコードに`trace_id`として付与され、
+    /// また、エラー応答の履歴にも記録される.
+    pub trace_id: Option<String>,
+
+    /// リクエストの署名.
+    ///
+    /// `Client::set_signing_key`が指定されている場合に、クライアントによって付与される.
+    /// サーバが署名の検証を要求している場合(`Server::verify_signatures`)には、署名のないリクエストは拒否される.
+    pub signature: Option<RequestSignature>,
+}
+#[cfg(any(feature = "server", all(feature = "client", feature = "registry")))]
+impl RequestOptions {
+    pub(crate) fn with<'a>(&self, device: &'a DeviceHandle) -> device::DeviceRequest<'a> {
+        let mut request = device.request();
+        request.deadline(self.deadline);
+        if self.prioritized {
+            request.prioritized();
+        }
+        if self.journal_sync {
+            request.journal_sync();
+        }
+        if let Some(n) = self.max_queue_len {
+            request.max_queue_len(n);
+        }
+        request
+    }
+}
+
+/// HMAC-SHA256によるリクエストの署名.
+///
+/// 署名の対象の詳細は`SigningKey`を参照のこと.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestSignature {
+    /// 署名に用いた鍵のID.
+    pub key_id: String,
+
+    /// 署名の作成時刻(UNIXエポックからのミリ秒).
+    pub timestamp: u64,
+
+    /// 同一時刻の署名を区別するための値.
+    pub nonce: u64,
+
+    /// 署名の値.
+    pub mac: Vec<u8>,
+}
+
+/// サーバでのリクエストの処理状況に関するメタ情報.
+///
+/// `RequestOptions::response_meta`が指定された場合に、`*WithMetaRpc`の応答に付与される.
+/// 遅延の調査時に、サーバ側での処理時間とネットワーク等のそれ以外の時間とを切り分けるためのもの.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseMeta {
+    /// サーバがリクエストの処理を開始してから、完了するまでの時間.
+    ///
+    /// デバイスのキューでの待機時間を含むが、リクエストのデコードや応答の送信に要した時間は含まない.
+    pub processing_time: Duration,
+
+    /// リクエストをデバイスに発行する時点での、デバイスのキューの長さ.
+    pub queue_len: u64,
+
+    /// サーバのバージョン(`ServerInfo::version`と同じ).
+    pub server_version: String,
+}
+
+/// 更新系の操作(PUT・DELETE)の事前条件.
+///
+/// 条件はサーバ側で、対象lumpの現在のヘッダに対して評価される.
+/// 評価から操作の完了までの間、同じlumpに対する単一lumpの更新(PUT・DELETE)は待機させられるので、
+/// 条件の評価と操作はそれらに対してアトミックとなる.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Precondition {
+    /// `true`の場合には、対象lumpが存在する場合にのみ操作が実行される.
+    pub if_exists: bool,
+
+    /// 指定された場合には、対象lumpが存在し、かつ、そのデータサイズ
+    /// (`LumpHeader::approximate_data_size`)が一致する場合にのみ操作が実行される.
+    pub if_size_equals: Option<u32>,
+
+    /// `true`の場合には、対象lumpが存在しない場合にのみ操作が実行される.
+    ///
+    /// PUTと組み合わせることで、新規作成のみを許可する(i.e., 既存のlumpを上書きしない)ことができる.
+    pub if_not_exists: bool,
+}
+impl Precondition {
+    /// エラーが、事前条件を満たさなかったことによるものかどうかを判定する.
+    ///
+    /// 事前条件の違反は`ErrorKind::InvalidInput`として返されるため、
+    /// それ以外の不正な入力(e.g., 存在しないデバイスの指定)と区別したい場合に使用する.
+    /// 判定はエラーの原因のメッセージに基づいて行われるため、
+    /// サーバ側の`ErrorVerbosity`の設定に関わらず利用可能.
+    #[allow(deprecated)]
+    pub fn is_failure(error: &cannyls::Error) -> bool {
+        *error.kind() == cannyls::ErrorKind::InvalidInput
+            && std::error::Error::cause(&**error)
+                .is_some_and(|c| c.to_string().contains(PRECONDITION_FAILED))
+    }
+}
+/// 過負荷によって`ErrorKind::DeviceBusy`で拒否されたリクエストのエラーに付与される、再試行のためのヒント.
+///
+/// サーバが付与したヒントはエラーの原因として保持され、`BusyHint::of`で取得できる.
+/// 組み込みの再試行(`RequestBuilder::retry`等)では、次の再試行までの待機時間の下限として利用される.
+///
+/// なお、ヒントに対応していないサーバからのエラーや、サーバ側で検知される前に発生したエラーには付与されない.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusyHint {
+    /// 拒否された時点での、デバイスのキューの長さ.
+    pub queue_len: u64,
+
+    /// サーバが推奨する、再試行までの待機時間.
+    pub retry_after: Duration,
+}
+impl BusyHint {
+    /// `ErrorKind::DeviceBusy`エラーに付与されているヒントを返す.
+    pub fn of(error: &cannyls::Error) -> Option<&BusyHint> {
+        if *error.kind() == cannyls::ErrorKind::DeviceBusy {
+            error.concrete_cause::<BusyHint>()
+        } else {
+            None
+        }
+    }
+
+    // このヒントを原因とする`ErrorKind::DeviceBusy`エラーを生成する(履歴は`from`から引き継ぐ).
+    pub(crate) fn into_error<E>(self, from: &E) -> cannyls::Error
+    where
+        E: Trackable<Event = Location>,
+    {
+        error_with_history(cannyls::ErrorKind::DeviceBusy, self, from)
+    }
+}
+impl fmt::Display for BusyHint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The device is busy: queue_len={}, retry_after={:?}",
+            self.queue_len, self.retry_after
+        )
+    }
+}
+impl std::error::Error for BusyHint {}
+
+/// サーバから返されたエラーの、サーバ側での原因の連鎖の一要素.
+///
+/// サーバ側のエラーの原因(`std::error::Error::cause`)は、その連鎖を含めてクライアントに送られ、
+/// 受信したエラーの原因として保持される(`RemoteCause::of`で取得可能).
+/// `cause`メソッドで連鎖を辿ることで、例えば失敗したPUTの原因が、サーバ側のデバイスやストレージのどこで発生したのかを特定できる.
+///
+/// 表示形式は`trackable`のエラーと同様.
+/// なお、原因の連鎖に対応していないサーバから返されたエラーの原因は、(従来通り)単なる文字列となる.
+#[derive(Debug, Clone, Default)]
+pub struct RemoteCause {
+    kind: Option<String>,
+    message: String,
+    history: History<Location>,
+    cause: Option<Box<RemoteCause>>,
+}
+impl RemoteCause {
+    /// サーバから返されたエラーの、直接の原因を返す.
+    pub fn of(error: &cannyls::Error) -> Option<&RemoteCause> {
+        error.concrete_cause::<RemoteCause>()
+    }
+
+    /// 原因が`trackable`のエラーだった場合には、その種類(e.g., `"StorageFull"`)を返す.
+    pub fn kind(&self) -> Option<&str> {
+        self.kind.as_deref()
+    }
+
+    /// 原因が`trackable`のエラー以外だった場合には、そのメッセージを返す.
+    ///
+    /// `trackable`のエラーの場合には空文字列が返される.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// 原因が`trackable`のエラーだった場合には、その履歴を返す.
+    pub fn history(&self) -> &[Location] {
+        self.history.events()
+    }
+
+    /// さらにその原因を返す.
+    pub fn cause(&self) -> Option<&RemoteCause> {
+        self.cause.as_deref()
+    }
+
+    // 連鎖の各要素(種類、メッセージ、履歴)から、先頭の要素を生成する.
+    pub(crate) fn from_chain<I>(chain: I) -> Option<Self>
+    where
+        I: DoubleEndedIterator<Item = (String, String, Vec<Location>)>,
+    {
+        chain.rev().fold(None, |cause, (kind, message, events)| {
+            let mut history = History::new();
+            for event in events {
+                history.add(event);
+            }
+            Some(RemoteCause {
+                kind: if kind.is_empty() { None } else { Some(kind) },
+                message,
+                history,
+                cause: cause.map(Box::new),
+            })
+        })
+    }
+
+    // `from_chain`の逆変換.
+    pub(crate) fn to_chain(&self, chain: &mut Vec<(String, String, Vec<Location>)>) {
+        chain.push((
+            self.kind.clone().unwrap_or_default(),
+            self.message.clone(),
+            self.history.events().to_owned(),
+        ));
+        if let Some(ref cause) = self.cause {
+            cause.to_chain(chain);
+        }
+    }
+}
+impl fmt::Display for RemoteCause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref kind) = self.kind {
+            write!(f, "{}", kind)?;
+            if let Some(ref cause) = self.cause {
+                write!(f, " (cause; {})", cause)?;
+            }
+            write!(f, "\n{}", self.history)
+        } else {
+            write!(f, "{}", self.message)
+        }
+    }
+}
+impl std::error::Error for RemoteCause {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.cause
+            .as_ref()
+            .map(|c| &**c as &(dyn std::error::Error + 'static))
+    }
+}
+
+// `cause`を原因とするエラーを生成する(履歴は`from`から引き継ぐ).
+pub(crate) fn error_with_history<C, E>(
+    kind: cannyls::ErrorKind,
+    cause: C,
+    from: &E,
+) -> cannyls::Error
+where
+    C: std::error::Error + Send + Sync + 'static,
+    E: Trackable<Event = Location>,
+{
+    let mut error = TrackableError::new(kind, cause);
+    if let (Some(from), Some(to)) = (from.history(), error.history_mut()) {
+        for event in from.events() {
+            to.add(event.clone());
+        }
+    }
+    error.into()
+}
+
+#[cfg(feature = "server")]
+impl Precondition {
+    pub(crate) fn check(&self, header: Option<&LumpHeader>) -> Result<()> {
+        if self.if_not_exists {
+            track_assert!(
+                header.is_none(),
+                ErrorKind::InvalidInput,
+                "{}: lump already exists",
+                PRECONDITION_FAILED
+            );
+        }
+        if !self.if_exists && self.if_size_equals.is_none() {
+            return Ok(());
+        }
+        let header = track_assert_some!(
+            header,
+            ErrorKind::InvalidInput,
+            "{}: no such lump",
+            PRECONDITION_FAILED
+        );
+        if let Some(size) = self.if_size_equals {
+            track_assert_eq!(
+                header.approximate_data_size,
+                size,
+                ErrorKind::InvalidInput,
+                "{}: size mismatch",
+                PRECONDITION_FAILED
+            );
+        }
+        Ok(())
+    }
+}
+
+/// デバイス全体を対象とするリクエスト.
+#[derive(Debug)]
+pub struct DeviceRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+}
+
+/// 単一のlumpを対象とするリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LumpRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// 対象lumpのID.
+    pub lump_id: LumpId,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+
+    /// 操作の事前条件.
+    ///
+    /// 現在は`DeleteLumpRpc`でのみ参照される.
+    pub precondition: Option<Precondition>,
+}
+
+/// `PutLumpRpc`のリクエスト.
+#[derive(Debug)]
+pub struct PutLumpRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// 対象lumpのID.
+    pub lump_id: LumpId,
+    /// 保存するデータ.
+    pub lump_data: LumpData,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+    /// 操作の事前条件.
+    pub precondition: Option<Precondition>,
+    /// 保存するデータのチェックサム(Adler-32).
+    ///
+    /// 指定された場合には、サーバ側で保存前に検証が行われ、
+    /// 一致しない場合には`ErrorKind::StorageCorrupted`エラーが返される.
+    pub checksum: Option<u32>,
+}
+
+/// `GetLumpRangeRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetLumpRangeRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// 対象lumpのID.
+    pub lump_id: LumpId,
+    /// 取得するデータのバイト範囲.
+    ///
+    /// 開始位置が終了位置よりも大きい場合には`ErrorKind::InvalidInput`エラーとなる.
+    pub byte_range: Range<u64>,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+}
+
+/// `PutLumpFromReaderRpc`のリクエスト.
+pub struct PutLumpFromReaderRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// 対象lumpのID.
+    pub lump_id: LumpId,
+    /// 保存するデータの読み込み元.
+    pub reader: Box<dyn Read + Send>,
+    /// 保存するデータのサイズ(バイト単位).
+    ///
+    /// `reader`からは、ちょうどこのサイズ分のデータが読み込まれる.
+    pub data_size: u64,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+    /// 操作の事前条件.
+    pub precondition: Option<Precondition>,
+}
+impl fmt::Debug for PutLumpFromReaderRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PutLumpFromReaderRequest")
+            .field("device_id", &self.device_id)
+            .field("lump_id", &self.lump_id)
+            .field("data_size", &self.data_size)
+            .field("options", &self.options)
+            .field("precondition", &self.precondition)
+            .finish()
+    }
+}
+
+/// `UsageRangeRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageRangeRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// 対象lumpの範囲.
     pub range: Range<LumpId>,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+}
+
+/// lumpの範囲を対象とするリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeLumpRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// 対象lumpの範囲.
+    pub range: Range<LumpId>,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+}
+
+/// `CopyRangeRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyRangeRequest {
+    /// コピー元デバイスのID.
+    pub source_device_id: DeviceId,
+    /// コピー先デバイスのID.
+    pub destination_device_id: DeviceId,
+    /// 対象lumpの範囲.
+    pub range: Range<LumpId>,
+    /// リクエストのオプション.
+    ///
+    /// コピー元とコピー先の両方のデバイスに対する操作に適用される.
+    pub options: RequestOptions,
+}
+
+/// `ScriptRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// 実行する操作列.
+    pub ops: Vec<ScriptOp>,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+}
+
+/// `GetLumpsRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetLumpsRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// 取得対象のlumpのID一覧.
+    pub lump_ids: Vec<LumpId>,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+}
+
+/// `ExportLumpsRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportLumpsRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// 対象lumpの範囲.
+    pub range: Range<LumpId>,
+    /// 一回の応答に含めるlumpの最大数.
+    ///
+    /// `0`が指定された場合には`ErrorKind::InvalidInput`エラーとなる.
+    pub max_lumps: usize,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+}
+
+/// `ExportLumpsRpc`の応答.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportLumpsChunk {
+    /// lumpのIDとデータの一覧(IDの昇順).
+    pub lumps: Vec<(LumpId, LumpData)>,
+    /// 範囲内に残りのlumpが存在する場合には、その先頭のID.
+    ///
+    /// `None`の場合には、範囲内の全てのlumpが返されたことを表している.
+    pub next: Option<LumpId>,
+}
+
+/// `ListLumpsChunkRpc`の応答.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListLumpsChunk {
+    /// lumpのID一覧(昇順).
+    pub lump_ids: Vec<LumpId>,
+    /// 範囲内に残りのlumpが存在する場合には、その先頭のID.
+    ///
+    /// `None`の場合には、範囲内の全てのlumpが返されたことを表している.
+    pub next: Option<LumpId>,
+}
+
+/// `DeleteRangeBoundedRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeleteRangeBoundedRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// 対象lumpの範囲.
+    pub range: Range<LumpId>,
+    /// 一回のリクエストで削除するlumpの最大数.
+    ///
+    /// `0`が指定された場合には`ErrorKind::InvalidInput`エラーとなる.
+    pub max_lumps: usize,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+}
+
+/// `DeleteRangeBoundedRpc`の応答.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeleteRangeChunk {
+    /// 削除されたlumpのID一覧(昇順).
+    pub deleted: Vec<LumpId>,
+    /// 範囲内に残りのlumpが存在する場合には、その先頭のID.
+    ///
+    /// `None`の場合には、範囲内の全てのlumpが削除されたことを表している.
+    pub next: Option<LumpId>,
+}
+
+/// `PutLumpsRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PutLumpsRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// 保存するlumpのIDとデータの一覧.
+    pub lumps: Vec<(LumpId, LumpData)>,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+}
+
+/// `ImportSessionStatusRpc`および`CommitImportSessionRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportSessionRequest {
+    /// インポートセッションのID.
+    pub session_id: u64,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+}
+
+/// `ImportLumpsRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportLumpsRequest {
+    /// インポートセッションのID.
+    pub session_id: u64,
+    /// セッション内でのバッチの番号.
+    pub batch_seq: u64,
+    /// 保存するlumpのIDとデータの一覧.
+    pub lumps: Vec<(LumpId, LumpData)>,
+    /// リクエストのオプション.
+    pub options: RequestOptions,
+}
+
+/// スクリプトRPCで実行される個々の操作.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptOp {
+    /// Lumpヘッダの取得.
+    Head(LumpId),
+
+    /// Lumpデータの取得.
+    Get(LumpId),
+
+    /// Lumpの保存.
+    Put(LumpId, LumpData),
+
+    /// Lumpの削除.
+    Delete(LumpId),
+}
+
+/// `ScriptOp`の実行結果.
+///
+/// 各バリアントは`ScriptOp`の同名のバリアントに対応している.
+#[derive(Debug, Clone)]
+pub enum ScriptOpResult {
+    /// `ScriptOp::Head`の結果.
+    ///
+    /// 対象lumpが存在しない場合には`None`となる.
+    Head(Option<LumpHeader>),
+
+    /// `ScriptOp::Get`の結果.
+    ///
+    /// 対象lumpが存在しない場合には`None`となる.
+    Get(Option<LumpData>),
+
+    /// `ScriptOp::Put`の結果.
+    ///
+    /// 新規作成なら`true`、上書きなら`false`となる.
+    Put(bool),
+
+    /// `ScriptOp::Delete`の結果.
+    ///
+    /// 削除されたなら`true`、存在しなかったなら`false`となる.
+    Delete(bool),
+}
+
+/// `SetJournalSyncRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetJournalSyncRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// `DeviceSettings::journal_sync`の値.
+    pub journal_sync: bool,
+    /// リクエストのオプション.
+    ///
+    /// 署名の検証とアクセス制御にのみ使用される.
+    pub options: RequestOptions,
+}
+
+/// `SetQueueLimitsRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetQueueLimitsRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// `DeviceSettings::default_max_queue_len`の値.
+    pub default_max_queue_len: Option<usize>,
+    /// `DeviceSettings::max_queue_len_limit`の値.
+    pub max_queue_len_limit: Option<usize>,
+    /// リクエストのオプション.
+    ///
+    /// 署名の検証とアクセス制御にのみ使用される.
+    pub options: RequestOptions,
+}
+
+/// `SetWriteWatermarkRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetWriteWatermarkRequest {
+    /// 対象デバイスのID.
+    pub device_id: DeviceId,
+    /// `DeviceSettings::write_watermark`の値.
+    pub write_watermark: Option<u8>,
+    /// リクエストのオプション.
+    ///
+    /// 署名の検証とアクセス制御にのみ使用される.
+    pub options: RequestOptions,
+}
+
+/// `ProvisionDeviceRpc`のリクエスト.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProvisionDeviceRequest {
+    /// 構築するデバイスの仕様.
+    pub spec: DeviceSpec,
+    /// リクエストのオプション.
+    ///
+    /// 署名の検証とアクセス制御にのみ使用される.
     pub options: RequestOptions,
 }
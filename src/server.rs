@@ -1,111 +1,2846 @@
-use fibers_rpc::server::{HandleCall, Reply, ServerBuilder};
+use atomic_immut::AtomicImmut;
+use bytecodec::marker::Never;
+use cannyls::deadline::Deadline;
+use cannyls::device::DeviceHandle;
+use cannyls::lump::{LumpData, LumpHeader, LumpId};
+use cannyls::storage::StorageUsage;
+use fibers_rpc::server::{HandleCall, HandleCast, NoReply, Reply, ServerBuilder};
+use fibers_rpc::{Call, Cast, ProcedureId};
+use futures::future::{self, Either, Loop};
 use futures::Future;
+use prometrics::metrics::MetricBuilder;
+use slog::{Level, Logger};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use trackable::error::ErrorKindExt;
 
-use crate::protobuf::PutLumpRequestDecoderFactory;
+use crate::acl::AccessControl;
+use crate::checksum;
+use crate::device::{DeviceId, DeviceSettings, DeviceSpec};
+#[cfg(feature = "fault_injection")]
+use crate::fault::{Fault, FaultInjector, LatencyInjector};
+use crate::import::ImportSessions;
+use crate::in_flight::{InFlightGuard, InFlightRequests, Tracked};
+use crate::info::{
+    DeviceReadiness, DeviceStatusReport, DeviceSummary, JournalUsage, RequestTarget, ServerInfo,
+};
+use crate::interceptor::{RequestContext, ServerInterceptor, ServerInterceptors};
+use crate::lump_lock::LumpLocks;
+use crate::observer::{Mutation, MutationObserver, MutationObservers};
+use crate::protobuf::{self, PutLumpRequestDecoderFactory};
+use crate::provision::ProvisionWorker;
 use crate::registry::DeviceRegistryHandle;
-use crate::rpc;
+use crate::rpc::{self, Precondition, ScriptOp, ScriptOpResult};
+use crate::server_metrics::ServerMetrics;
+use crate::signing::{self, SignatureVerifier, SignedParams};
+#[cfg(feature = "tracing")]
+use crate::span;
+use crate::stats::RequestStatsCollector;
+
+// `ReadinessRpc`の確認用のHEADの対象となるlumpのID.
+//
+// 存在しないことが想定されているが、存在していても結果には影響しない.
+const READINESS_PROBE_LUMP_ID: u128 = 0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF;
+
+// `Server::busy_retry_after_per_command`のデフォルト値.
+const DEFAULT_BUSY_RETRY_AFTER_PER_COMMAND: Duration = Duration::from_millis(1);
+
+// `Server::max_provision_capacity`のデフォルト値(1GiB).
+const DEFAULT_MAX_PROVISION_CAPACITY: u64 = 1 << 30;
 
 macro_rules! rpc_try {
-    ($expr:expr) => {
+    ($verbosity:expr, $expr:expr) => {
         match $expr {
-            Err(e) => return Reply::done(Err(track!(e))),
+            Err(e) => return Reply::done($verbosity.apply(Err(track!(e)))),
             Ok(v) => v,
         }
     };
 }
 
 /// RPCサーバ.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Server {
     registry: DeviceRegistryHandle,
+    admin_rpc_enabled: bool,
+    procedures: ProcedureConfig,
+    error_verbosity: ErrorVerbosity,
+    verbose_errors_allowed: bool,
+    access_log: bool,
+    access_log_sampling: u64,
+    slow_request_threshold: Option<Duration>,
+    busy_retry_after_per_command: Duration,
+    max_provision_capacity: u64,
+    provision_worker: ProvisionWorker,
+    observers: MutationObservers,
+    interceptors: ServerInterceptors,
+    signature_verifier: Option<SignatureVerifier>,
+    access_control: Option<AccessControl>,
+    #[cfg(feature = "fault_injection")]
+    fault_injector: Option<FaultInjector>,
+    #[cfg(feature = "fault_injection")]
+    latency_injector: Option<LatencyInjector>,
+    in_flight: InFlightRequests,
+    imports: ImportSessions,
+    lump_locks: LumpLocks,
+    stats: RequestStatsCollector,
+    metrics: ServerMetrics,
+    registered: Arc<AtomicImmut<Vec<ProcedureId>>>, // `register`等で登録された(有効な)RPC群
 }
 impl Server {
     /// 指定されたレジストリを操作するための、新しいRPCサーバインスタンスを生成する.
     pub fn new(registry: DeviceRegistryHandle) -> Self {
-        Server { registry }
+        Server {
+            provision_worker: ProvisionWorker::new(registry.clone()),
+            registry,
+            admin_rpc_enabled: false,
+            procedures: ProcedureConfig::default(),
+            error_verbosity: ErrorVerbosity::default(),
+            verbose_errors_allowed: false,
+            access_log: false,
+            access_log_sampling: 1,
+            slow_request_threshold: None,
+            busy_retry_after_per_command: DEFAULT_BUSY_RETRY_AFTER_PER_COMMAND,
+            max_provision_capacity: DEFAULT_MAX_PROVISION_CAPACITY,
+            observers: MutationObservers::default(),
+            interceptors: ServerInterceptors::default(),
+            signature_verifier: None,
+            access_control: None,
+            #[cfg(feature = "fault_injection")]
+            fault_injector: None,
+            #[cfg(feature = "fault_injection")]
+            latency_injector: None,
+            in_flight: InFlightRequests::default(),
+            imports: ImportSessions::default(),
+            lump_locks: LumpLocks::default(),
+            stats: RequestStatsCollector::default(),
+            metrics: ServerMetrics::new(MetricBuilder::new()),
+            registered: Arc::new(AtomicImmut::new(Vec::new())),
+        }
+    }
+
+    /// サーバが使用しているデバイスレジストリのハンドルを返す.
+    ///
+    /// 同じプロセス内でデバイスを直接操作する場合等に使用される.
+    pub fn registry(&self) -> &DeviceRegistryHandle {
+        &self.registry
+    }
+
+    // 指定のRPCが(このサーバないしその複製によって)登録済みかどうかを判定する.
+    //
+    // 除外されたり、無効化されたRPCは、登録済みとは扱われない.
+    #[cfg(feature = "client")]
+    pub(crate) fn is_registered(&self, procedure: ProcedureId) -> bool {
+        self.registered.load().contains(&procedure)
+    }
+
+    /// 管理用のRPC(e.g., デバイスの設定変更)を有効にする.
+    ///
+    /// デフォルトでは無効となっており、その場合には管理用のRPCはサーバに登録されない.
+    pub fn enable_admin_rpc(&mut self) -> &mut Self {
+        self.admin_rpc_enabled = true;
+        self
+    }
+
+    /// 個々のRPCの有効・無効を設定する.
+    ///
+    /// 設定は`register`等の呼び出し時に参照される.
+    /// 無効にされたRPCのハンドラは、処理を行わずに`ErrorKind::InvalidInput`エラーを返すようになる.
+    pub fn configure_procedures(&mut self, config: ProcedureConfig) -> &mut Self {
+        self.procedures = config;
+        self
+    }
+
+    /// エラー応答に含める情報の詳細度を設定する.
+    ///
+    /// デフォルトは`ErrorVerbosity::Full`.
+    ///
+    /// リクエストのオプションの`RequestOptions::verbose_errors`は、`allow_verbose_errors`が呼ばれている場合にのみ考慮される.
+    pub fn error_verbosity(&mut self, verbosity: ErrorVerbosity) -> &mut Self {
+        self.error_verbosity = verbosity;
+        self
+    }
+
+    /// リクエストのオプションで`RequestOptions::verbose_errors`が指定された場合に、
+    /// `error_verbosity`の設定に関わらず、そのリクエストに対しては完全なエラーを返すようにする.
+    ///
+    /// 完全なエラーにはサーバ内部の情報(e.g., ソースコード上の位置)が含まれるので、
+    /// 信頼できないクライアントが接続し得る環境では、有効にしないこと.
+    ///
+    /// デフォルトでは無効で、その場合には`verbose_errors`の指定は無視される.
+    pub fn allow_verbose_errors(&mut self) -> &mut Self {
+        self.verbose_errors_allowed = true;
+        self
+    }
+
+    /// アクセスログの出力を有効にする.
+    ///
+    /// 有効にした場合には、lumpやデバイスに対するリクエスト(スクリプトRPCを含む)の完了ないし失敗の度に、
+    /// その結果がレジストリのロガーに`Info`レベルで出力される.
+    ///
+    /// アクセスログを含む、リクエストに関連するログレコードには、以下のキー・値の組が付与される:
+    /// - `procedure`: RPCの名前
+    /// - `device_id`: 対象デバイスのID
+    /// - `lump_id`ないし`range_start`と`range_end`: 操作対象のlump(ないしその範囲)
+    /// - `request_id`: 実行中のリクエストとしてのID (リクエストの処理開始前に失敗した場合には付与されない)
+    /// - `trace_id`: リクエストのオプションで指定されたトレースID (`rpc::RequestOptions::trace_id`を参照、未指定の場合には付与されない)
+    ///
+    /// アクセスログ自体には、さらに以下が付与される:
+    /// - `deadline`: リクエストのデッドライン(デバイスの設定の適用後のもの)
+    /// - `elapsed_us`: リクエストの処理時間 (リクエストの処理開始前に失敗した場合には付与されない)
+    /// - `error_kind`: 失敗時のエラーの種類
+    ///
+    /// 成功したリクエストのログを間引きたい場合には`access_log_sampling`を使用すること.
+    ///
+    /// デフォルトでは無効.
+    pub fn enable_access_log(&mut self) -> &mut Self {
+        self.access_log = true;
+        self
+    }
+
+    /// 成功したリクエストのアクセスログを、`one_in`件に一件の割合で出力するようにする.
+    ///
+    /// 対象の選択は実行中のリクエストとしてのIDに基づいて決定的に行われる.
+    /// 失敗したリクエストのアクセスログは、この設定に関わらず全て出力される.
+    /// `0`が指定された場合には`1`として扱われる.
+    ///
+    /// アクセスログ自体が有効になっていない場合には、何の影響もない.
+    ///
+    /// デフォルト値は`1`(i.e., 間引きなし).
+    pub fn access_log_sampling(&mut self, one_in: u64) -> &mut Self {
+        self.access_log_sampling = one_in.max(1);
+        self
+    }
+
+    /// 処理時間が`threshold`を超えたリクエストを、レジストリのロガーに`Warning`レベルで出力するようにする.
+    ///
+    /// 対象となるのはアクセスログと同じリクエストで、処理時間はデバイスの検索の完了から、結果が確定するまでとなる.
+    /// ログレコードには、アクセスログと同じキー・値の組(`enable_access_log`を参照)に加えて、
+    /// 処理時間(`elapsed_us`)とリクエストの成否(`succeeded`)が付与され、メッセージにはデッドラインが含まれる.
+    ///
+    /// アクセスログの有効・無効とは独立に設定可能.
+    ///
+    /// デフォルトでは無効.
+    pub fn slow_request_threshold(&mut self, threshold: Duration) -> &mut Self {
+        self.slow_request_threshold = Some(threshold);
+        self
+    }
+
+    /// 過負荷による`ErrorKind::DeviceBusy`エラーに付与する`rpc::BusyHint`の、再試行までの推奨待機時間の算出方法を設定する.
+    ///
+    /// 推奨待機時間は、拒否された時点でのデバイスのキューの長さ(ただし最低でも`1`)に`per_command`を乗じた値となる.
+    /// `per_command`には、デバイスがコマンド一つの処理に要する時間の目安を指定すること.
+    ///
+    /// デフォルト値は`1ms`.
+    pub fn busy_retry_after_per_command(&mut self, per_command: Duration) -> &mut Self {
+        self.busy_retry_after_per_command = per_command;
+        self
+    }
+
+    /// `rpc::ProvisionDeviceRpc`で構築可能なデバイスの容量(バイト単位)の上限を設定する.
+    ///
+    /// 上限を超える容量が指定された場合には、デバイスは構築されずに`ErrorKind::InvalidInput`エラーが返される.
+    /// メモリ上のデバイスの場合には、容量分のメモリが確保されるので、サーバのメモリ量を考慮して設定すること.
+    ///
+    /// デフォルト値は`1GiB`.
+    pub fn max_provision_capacity(&mut self, capacity: u64) -> &mut Self {
+        self.max_provision_capacity = capacity;
+        self
+    }
+
+    /// Prometheus形式のメトリクスの登録に使用するビルダを設定する.
+    ///
+    /// 以下のメトリクスが、RPCの名前を`procedure`ラベルとして、各RPCの初回のリクエストの受信時に登録される:
+    /// - `cannyls_rpc_server_requests_total <COUNTER>`: リクエスト数
+    /// - `cannyls_rpc_server_errors_total { kind="..." } <COUNTER>`: 失敗したリクエスト数 (`kind`はエラーの種類)
+    /// - `cannyls_rpc_server_handler_duration_seconds <HISTOGRAM>`: リクエストの処理時間
+    /// - `cannyls_rpc_server_payload_bytes <HISTOGRAM>`: lumpデータのサイズ (書き込み系ではリクエストの、読み込み系では応答のもの)
+    ///
+    /// 対象となるのは、lumpやデバイスに対するリクエスト(アクセスログの対象と同じ)のみ.
+    /// ビルダはラベルの追加や、登録先のレジストリの変更に利用できる(名前空間とサブシステムは上書きされる).
+    ///
+    /// デフォルト値は`MetricBuilder::new()`(i.e., デフォルトのレジストリに登録される).
+    pub fn metric_builder(&mut self, builder: MetricBuilder) -> &mut Self {
+        self.metrics = ServerMetrics::new(builder);
+        self
+    }
+
+    /// 更新系の操作の観測者を登録する.
+    ///
+    /// 観測者は、登録順に呼び出される.
+    pub fn add_mutation_observer<O: MutationObserver>(&mut self, observer: O) -> &mut Self {
+        self.observers.add(observer);
+        self
+    }
+
+    /// リクエスト処理のインターセプタを登録する.
+    ///
+    /// インターセプタは、登録順に呼び出される.
+    /// 詳細は`ServerInterceptor`を参照のこと.
+    pub fn add_interceptor<I: ServerInterceptor>(&mut self, interceptor: I) -> &mut Self {
+        self.interceptors.add(interceptor);
+        self
+    }
+
+    /// リクエストの署名の検証を有効にする.
+    ///
+    /// 有効にした場合には、lumpやデバイスに対するリクエスト(アクセスログの対象と同じ)のうち、
+    /// 署名がない、ないし検証に失敗したものは、処理されずに`ErrorKind::InvalidInput`で拒否される.
+    /// 署名の付与には`Client::set_signing_key`を使用する.
+    ///
+    /// 管理用のRPCのうち、特定のデバイスを対象とするもの(e.g., `rpc::DeleteDeviceRpc`)も検証の対象となる.
+    /// インポートセッションは、その開始時(`rpc::OpenImportSessionRpc`)の署名の鍵に紐付けられ、
+    /// 以降のセッションに対するリクエストは、同じ鍵で署名されていない場合には拒否される.
+    /// なお、読み込み元から順次送信される書き込み(`RequestBuilder::put_lump_from_reader`)は署名できないので、
+    /// 検証が有効な場合には常に拒否される.
+    ///
+    /// デフォルトでは、検証は行われない.
+    pub fn verify_signatures(&mut self, verifier: SignatureVerifier) -> &mut Self {
+        self.signature_verifier = Some(verifier);
+        self
+    }
+
+    /// クライアント毎のアクセス制御を有効にする.
+    ///
+    /// 許可されていないRPCの呼び出しや、許可されていないデバイスに対するリクエストは、
+    /// 処理されずに`ErrorKind::InvalidInput`で拒否される.
+    /// クライアントの識別には署名の鍵のIDが使用されるので、通常は`verify_signatures`と併用する.
+    /// 詳細は`AccessControl`を参照のこと.
+    ///
+    /// 管理用のRPCも対象となり、その呼び出しには`Permissions::allow_admin`等で明示的に許可されている必要がある
+    /// (なお、管理用のRPCが登録されるのは`enable_admin_rpc`が呼ばれている場合のみ).
+    ///
+    /// デフォルトでは、アクセス制御は行われない.
+    pub fn access_control(&mut self, acl: AccessControl) -> &mut Self {
+        self.access_control = Some(acl);
+        self
+    }
+
+    /// 障害試験用に、リクエストへの障害の注入を有効にする.
+    ///
+    /// 障害は、署名の検証・アクセス制御・対象デバイスの検索の後、インターセプタの`before_dispatch`の前に注入される.
+    /// `Fault::Error`で失敗したリクエストは、ハンドラで処理されずに拒否されたリクエストとして扱われる.
+    /// 詳細は`FaultInjector`を参照のこと.
+    ///
+    /// デフォルトでは、障害は注入されない.
+    #[cfg(feature = "fault_injection")]
+    pub fn inject_faults(&mut self, injector: FaultInjector) -> &mut Self {
+        self.fault_injector = Some(injector);
+        self
+    }
+
+    /// 障害試験用に、リクエストへの人為的な遅延の注入を有効にする.
+    ///
+    /// 遅延は`inject_faults`の障害と同じリクエストに対して注入される.
+    /// 詳細は`LatencyInjector`を参照のこと.
+    ///
+    /// デフォルトでは、遅延は注入されない.
+    #[cfg(feature = "fault_injection")]
+    pub fn inject_latency(&mut self, injector: LatencyInjector) -> &mut Self {
+        self.latency_injector = Some(injector);
+        self
     }
 
     /// RPCサーバを登録して、利用可能な状態にする.
     pub fn register(self, builder: &mut ServerBuilder) {
-        let registry = self.registry.clone();
-        let clone = move || Server {
-            registry: registry.clone(),
+        self.register_except(builder, &[]);
+    }
+
+    /// 指定されたRPC群を除いて、RPCサーバを登録する.
+    ///
+    /// 一部のRPCのハンドラをラップ(ないし置換)したい場合には、それらをここで除外した上で、
+    /// 独自のハンドラを別途`ServerBuilder`に登録すれば良い.
+    /// 独自のハンドラからは、`HandleCall`の実装を通して、このサーバの処理を呼び出すことができる.
+    ///
+    /// なお`rpc::PutLumpRpc`、`rpc::PutLumpV2Rpc`および`rpc::PutLumpNoAckRpc`のハンドラを登録する場合には、
+    /// デコーダとして`put_lump_decoder_factory`の結果を指定する必要がある.
+    ///
+    /// また`rpc::GetLumpRpc`等の、対応する`*WithMetaRpc`が定義されているRPCは、そちらの定義で登録される.
+    /// これらを独自のハンドラに置き換えた場合には、応答に`rpc::ResponseMeta`は付与されなくなる.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate cannyls_rpc;
+    /// # extern crate fibers_rpc;
+    /// use cannyls_rpc::rpc::{self, LumpRequest};
+    /// use cannyls_rpc::Server;
+    /// use fibers_rpc::server::{HandleCall, Reply};
+    /// use fibers_rpc::{Call, ProcedureId};
+    ///
+    /// // `GetLumpRpc`の処理をラップするハンドラ.
+    /// struct GetLumpHandler(Server);
+    /// impl HandleCall<rpc::GetLumpRpc> for GetLumpHandler {
+    ///     fn handle_call(&self, request: LumpRequest) -> Reply<rpc::GetLumpRpc> {
+    ///         // 独自の処理を行った上で、元の処理を呼び出す
+    ///         self.0.handle_call(request)
+    ///     }
+    /// }
+    ///
+    /// # fn register(server: Server, builder: &mut fibers_rpc::server::ServerBuilder) {
+    /// builder.add_call_handler(GetLumpHandler(server.clone()));
+    /// server.register_except(builder, &[rpc::GetLumpRpc::ID]);
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub fn register_except(self, builder: &mut ServerBuilder, excluded: &[ProcedureId]) {
+        let mut add = Registrar {
+            server: &self,
+            builder,
+            excluded,
+            procedures: Vec::new(),
+        };
+        add.put_call::<rpc::PutLumpWithMetaRpc>();
+        add.put_call::<rpc::PutLumpV2Rpc>();
+        add.put_cast::<rpc::PutLumpNoAckRpc>();
+        add.call::<rpc::GetLumpWithMetaRpc>();
+        add.call::<rpc::GetLumpWithChecksumRpc>();
+        add.call::<rpc::GetLumpRangeRpc>();
+        add.call::<rpc::GetLumpsRpc>();
+        add.call::<rpc::HeadLumpWithMetaRpc>();
+        add.call::<rpc::ExistsLumpRpc>();
+        add.call::<rpc::DeleteLumpWithMetaRpc>();
+        add.call::<rpc::DeleteLumpV2Rpc>();
+        add.cast::<rpc::DeleteLumpNoAckRpc>();
+        add.call::<rpc::ListLumpRpc>();
+        add.call::<rpc::ListLumpRangeRpc>();
+        add.call::<rpc::ScrubRangeRpc>();
+        add.call::<rpc::ExportLumpsRpc>();
+        add.call::<rpc::ListLumpsChunkRpc>();
+        add.call::<rpc::UsageRangeRpc>();
+        add.call::<rpc::DeleteRangeRpc>();
+        add.call::<rpc::DeleteRangeBoundedRpc>();
+        add.call::<rpc::CopyRangeRpc>();
+        add.call::<rpc::ScriptRpc>();
+        add.call::<rpc::ConcurrentScriptRpc>();
+        add.call::<rpc::PutLumpsRpc>();
+        add.call::<rpc::OpenImportSessionRpc>();
+        add.call::<rpc::ImportLumpsRpc>();
+        add.call::<rpc::ImportSessionStatusRpc>();
+        add.call::<rpc::CommitImportSessionRpc>();
+        add.call::<rpc::JournalUsageRpc>();
+        add.call::<rpc::StorageHeaderRpc>();
+        add.call::<rpc::MetricsSnapshotRpc>();
+        add.call::<rpc::RequestStatsRpc>();
+        add.call::<rpc::ReadinessRpc>();
+        add.call::<rpc::ListDevicesRpc>();
+        add.call::<rpc::DeviceStatusRpc>();
+        add.call::<rpc::DeviceLabelsRpc>();
+        if self.admin_rpc_enabled {
+            add.call::<rpc::SetJournalSyncRpc>();
+            add.call::<rpc::SetQueueLimitsRpc>();
+            add.call::<rpc::SetWriteWatermarkRpc>();
+            add.call::<rpc::SetLogLevelRpc>();
+            add.call::<rpc::ListInFlightRpc>();
+            add.call::<rpc::CancelInFlightRpc>();
+            add.call::<rpc::ResetRequestStatsRpc>();
+            add.call::<rpc::ProvisionDeviceRpc>();
+            add.call::<rpc::DeleteDeviceRpc>();
+            add.call::<rpc::StopDeviceRpc>();
+        }
+        add.server_info();
+    }
+
+    /// 読み込み系のRPCのみを登録する.
+    ///
+    /// lumpの取得・一覧・使用量の取得、および、デバイスの状態情報の取得用のRPCのみが登録され、
+    /// 更新系の操作(スクリプトRPCを含む)や管理用のRPCは、`enable_admin_rpc`の指定に関わらず登録されない.
+    ///
+    /// レプリカや分析用のエンドポイント等、更新を一切受け付けてはいけないサーバ向け.
+    pub fn register_read_only(self, builder: &mut ServerBuilder) {
+        let mut add = Registrar {
+            server: &self,
+            builder,
+            excluded: &[],
+            procedures: Vec::new(),
+        };
+        add.call::<rpc::GetLumpWithMetaRpc>();
+        add.call::<rpc::GetLumpWithChecksumRpc>();
+        add.call::<rpc::GetLumpRangeRpc>();
+        add.call::<rpc::GetLumpsRpc>();
+        add.call::<rpc::HeadLumpWithMetaRpc>();
+        add.call::<rpc::ExistsLumpRpc>();
+        add.call::<rpc::ListLumpRpc>();
+        add.call::<rpc::ListLumpRangeRpc>();
+        add.call::<rpc::ScrubRangeRpc>();
+        add.call::<rpc::ExportLumpsRpc>();
+        add.call::<rpc::ListLumpsChunkRpc>();
+        add.call::<rpc::UsageRangeRpc>();
+        add.call::<rpc::JournalUsageRpc>();
+        add.call::<rpc::StorageHeaderRpc>();
+        add.call::<rpc::MetricsSnapshotRpc>();
+        add.call::<rpc::RequestStatsRpc>();
+        add.call::<rpc::ReadinessRpc>();
+        add.call::<rpc::ListDevicesRpc>();
+        add.call::<rpc::DeviceStatusRpc>();
+        add.call::<rpc::DeviceLabelsRpc>();
+        add.server_info();
+    }
+
+    /// `rpc::PutLumpRpc`のリクエストのデコーダを生成するためのファクトリを返す.
+    ///
+    /// このデコーダは、受信したデータを、対象デバイスに適した形式で直接確保するために、
+    /// デバイスレジストリを参照する.
+    pub fn put_lump_decoder_factory(&self) -> PutLumpRequestDecoderFactory {
+        PutLumpRequestDecoderFactory::new(self.registry.clone())
+    }
+
+    // デバイスに対するリクエストの処理を開始する.
+    //
+    // 対象デバイスを取得し、その設定をリクエストのオプションに反映した上で、
+    // リクエストを実行中のものとして登録する.
+    // リクエストの結果は、完了時に統計情報として記録される.
+    //
+    // `params`は、署名の検証対象となるリクエストのパラメータ(`signing::SignedParams`を参照).
+    fn start<T: Call>(
+        &self,
+        device_id: &DeviceId,
+        target: RequestTarget,
+        params: &[u8],
+        options: &mut rpc::RequestOptions,
+    ) -> cannyls::Result<(DeviceHandle, InFlightGuard)> {
+        track!(self.start_procedure(T::NAME, device_id, target, params, &[], options))
+    }
+
+    // `start`と同様だが、署名の検証対象となるペイロード(`signing`モジュールを参照)を伴うリクエスト用.
+    fn start_with_payload<T: Call>(
+        &self,
+        device_id: &DeviceId,
+        target: RequestTarget,
+        params: &[u8],
+        payload: &[&[u8]],
+        options: &mut rpc::RequestOptions,
+    ) -> cannyls::Result<(DeviceHandle, InFlightGuard)> {
+        track!(self.start_procedure(T::NAME, device_id, target, params, payload, options))
+    }
+
+    // `start_with_payload`と同様だが、RPCを名前で指定する(通知RPC用).
+    fn start_procedure(
+        &self,
+        procedure: &'static str,
+        device_id: &DeviceId,
+        target: RequestTarget,
+        params: &[u8],
+        payload: &[&[u8]],
+        options: &mut rpc::RequestOptions,
+    ) -> cannyls::Result<(DeviceHandle, InFlightGuard)> {
+        let authorize = |target: &RequestTarget, options: &rpc::RequestOptions| {
+            self.authorize_procedure(procedure, device_id, target, params, payload, options)
+        };
+        track!(self.start_with_authorizer(procedure, device_id, target, payload, options, authorize))
+    }
+
+    // `start_with_payload`と同様だが、署名の検証とアクセス制御は呼び出し元で実施済みのリクエスト用.
+    fn start_authorized<T: Call>(
+        &self,
+        device_id: &DeviceId,
+        target: RequestTarget,
+        payload: &[&[u8]],
+        options: &mut rpc::RequestOptions,
+    ) -> cannyls::Result<(DeviceHandle, InFlightGuard)> {
+        let authorize = |_: &RequestTarget, _: &rpc::RequestOptions| Ok(());
+        track!(self.start_with_authorizer(T::NAME, device_id, target, payload, options, authorize))
+    }
+
+    fn start_with_authorizer<F>(
+        &self,
+        procedure: &'static str,
+        device_id: &DeviceId,
+        target: RequestTarget,
+        payload: &[&[u8]],
+        options: &mut rpc::RequestOptions,
+        authorize: F,
+    ) -> cannyls::Result<(DeviceHandle, InFlightGuard)>
+    where
+        F: FnOnce(&RequestTarget, &rpc::RequestOptions) -> cannyls::Result<()>,
+    {
+        let received_at = Instant::now();
+        #[cfg(feature = "tracing")]
+        let span = span::server_span(procedure, device_id.as_str());
+        #[cfg(feature = "tracing")]
+        let _dispatch = span::dispatch_span(&span).entered();
+        let logger = self.procedure_logger(procedure, device_id, &target, options);
+        if let Err(e) = authorize(&target, options) {
+            return Err(self.reject(&logger, procedure, options, e));
+        }
+        let (device, settings) = match self.lookup_device(device_id) {
+            Err(e) => return Err(self.reject(&logger, procedure, options, e)),
+            Ok(v) => v,
+        };
+        settings.apply(options);
+
+        #[cfg(feature = "fault_injection")]
+        let fault = match self.fault_injector.as_ref().map(|f| f.select(procedure)) {
+            Some(Err(e)) => return Err(self.reject(&logger, procedure, options, e)),
+            Some(Ok(fault)) => fault,
+            None => None,
+        };
+
+        let context = if self.interceptors.is_empty() {
+            None
+        } else {
+            let context = RequestContext {
+                procedure,
+                device_id: device_id.clone(),
+                target: target.clone(),
+                options: options.clone(),
+                payload_size: payload.iter().map(|p| p.len()).sum(),
+                received_at,
+            };
+            if let Err(e) = self.interceptors.before_dispatch(&context) {
+                return Err(self.reject(&logger, procedure, options, e));
+            }
+            Some(context)
+        };
+
+        let guard = self
+            .in_flight
+            .start(
+                procedure,
+                device_id.clone(),
+                target.clone(),
+                options.deadline,
+            )
+            .record_stats(self.stats.recorder(procedure, device_id.clone()))
+            .record_metrics(self.metrics.recorder(procedure))
+            .error_verbosity(self.error_verbosity_for(options))
+            .busy_hint(device.metrics().clone(), self.busy_retry_after_per_command)
+            .trace_id(options.trace_id.clone());
+        #[cfg(feature = "tracing")]
+        let guard = guard.span(span, device.metrics());
+        let guard = match context {
+            Some(context) => guard.intercept(self.interceptors.clone(), context),
+            None => guard,
+        };
+        #[cfg(feature = "fault_injection")]
+        let guard = match fault {
+            Some(Fault::Drop) => guard.drop_response(),
+            Some(Fault::Delay(delay)) => guard.delay_response(delay),
+            _ => guard,
+        };
+        #[cfg(feature = "fault_injection")]
+        let guard = match self
+            .latency_injector
+            .as_ref()
+            .and_then(|l| l.select(procedure, device_id))
+        {
+            Some(latency) => guard.delay_response(latency),
+            None => guard,
+        };
+        let logger = logger.new(o!("request_id" => guard.request_id()));
+        debug!(
+            logger,
+            "RPC {}: device={:?}, target={:?}, options={:?}", procedure, device_id, target, options
+        );
+        let guard = if options.response_meta {
+            guard.response_meta(device.metrics())
+        } else {
+            guard
         };
-        builder.add_call_handler::<rpc::GetLumpRpc, _>(clone());
-        builder.add_call_handler::<rpc::HeadLumpRpc, _>(clone());
-        builder.add_call_handler_with_decoder::<rpc::PutLumpRpc, _, _>(
-            clone(),
-            PutLumpRequestDecoderFactory::new(self.registry),
+        let guard = match self.slow_request_threshold {
+            Some(threshold) => guard.slow_log(logger.clone(), threshold, options.deadline),
+            None => guard,
+        };
+        let guard = if self.access_log {
+            let sampled = guard.request_id() % self.access_log_sampling == 0;
+            let logger = logger.new(o!("deadline" => format!("{:?}", options.deadline)));
+            guard.access_log(logger, sampled)
+        } else {
+            guard
+        };
+        Ok((device, guard))
+    }
+
+    // 署名の検証およびアクセス制御の検査のみを行う.
+    //
+    // 実行中のリクエストとしての登録等は行わないので、デバイスの情報を参照するだけのRPC用.
+    // 対象デバイスが登録されているかどうかは検査しない.
+    fn authorize<T: Call>(
+        &self,
+        device_id: &DeviceId,
+        params: &[u8],
+        options: &rpc::RequestOptions,
+    ) -> cannyls::Result<()> {
+        let target = RequestTarget::Device;
+        let result = self.authorize_procedure(T::NAME, device_id, &target, params, &[], options);
+        result.map_err(|e| {
+            let logger = self.procedure_logger(T::NAME, device_id, &target, options);
+            self.reject(&logger, T::NAME, options, e)
+        })
+    }
+
+    fn authorize_procedure(
+        &self,
+        procedure: &'static str,
+        device_id: &DeviceId,
+        target: &RequestTarget,
+        params: &[u8],
+        payload: &[&[u8]],
+        options: &rpc::RequestOptions,
+    ) -> cannyls::Result<()> {
+        if let Some(ref verifier) = self.signature_verifier {
+            let signature = options.signature.as_ref();
+            track!(verifier.verify(procedure, device_id, target, params, payload, signature))?;
+        }
+        track!(self.check_access(procedure, device_id, options))
+    }
+
+    // インポートセッション経由のリクエストを認可し、セッションの対象デバイスを返す.
+    //
+    // 署名の検証が有効な場合には、リクエストはセッションの開始時と同じ鍵で署名されている必要がある
+    // (対象デバイスの代わりに`signing::IMPORT_SESSION_DEVICE_ID`で署名される).
+    // アクセス制御は、セッションの対象デバイスに対して行われる.
+    fn authorize_import_session<T: Call>(
+        &self,
+        session_id: u64,
+        params: &[u8],
+        payload: &[&[u8]],
+        options: &rpc::RequestOptions,
+    ) -> cannyls::Result<DeviceId> {
+        let target = RequestTarget::Device;
+        let result = (|| {
+            if let Some(ref verifier) = self.signature_verifier {
+                let signature = options.signature.as_ref();
+                let device_id = &signing::IMPORT_SESSION_DEVICE_ID;
+                track!(verifier.verify(T::NAME, device_id, &target, params, payload, signature))?;
+            }
+            let key_id = self.verified_key_id(options);
+            let device_id = track!(self.imports.authorize(session_id, key_id))?;
+            track!(self.check_access(T::NAME, &device_id, options))?;
+            Ok(device_id)
+        })();
+        result.map_err(|e| {
+            let device_id = &signing::IMPORT_SESSION_DEVICE_ID;
+            let logger = self.procedure_logger(T::NAME, device_id, &target, options);
+            let logger = logger.new(o!("session_id" => session_id));
+            self.reject(&logger, T::NAME, options, e)
+        })
+    }
+
+    fn procedure_logger(
+        &self,
+        procedure: &'static str,
+        device_id: &DeviceId,
+        target: &RequestTarget,
+        options: &rpc::RequestOptions,
+    ) -> Logger {
+        let logger = request_logger(self.registry.logger(), procedure, device_id, target);
+        match options.trace_id {
+            Some(ref trace_id) => logger.new(o!("trace_id" => trace_id.clone())),
+            None => logger,
+        }
+    }
+
+    // リクエストのオプションを伴わない(i.e., 署名を検証できない)RPCの呼び出し元に、
+    // 指定デバイスの情報を返して良いかどうかを判定する.
+    //
+    // アクセス制御が有効な場合には、呼び出し元は常に匿名のクライアントとして扱われる.
+    fn is_visible(&self, procedure: &str, device_id: &DeviceId) -> bool {
+        self.access_control.as_ref().is_none_or(|acl| {
+            let permissions = acl.permissions(None);
+            permissions.is_allowed(procedure) && permissions.is_device_allowed(device_id)
+        })
+    }
+
+    // 特定のデバイスを対象としない管理用のRPCが、匿名のクライアントに許可されているかどうかを検査する.
+    //
+    // これらのRPCのリクエストはオプションを伴わない(i.e., 署名を検証できない)ため.
+    fn check_server_wide_access(&self, procedure: &str) -> cannyls::Result<()> {
+        if let Some(ref acl) = self.access_control {
+            track!(acl.check_server_wide(None, procedure))?;
+        }
+        Ok(())
+    }
+
+    // `rpc::ProvisionDeviceRpc`で指定された容量が、上限以下かどうかを検査する.
+    fn check_provision_capacity(&self, spec: &DeviceSpec) -> cannyls::Result<()> {
+        track_assert!(
+            spec.capacity <= self.max_provision_capacity,
+            cannyls::ErrorKind::InvalidInput,
+            "Too large capacity: {} (max={})",
+            spec.capacity,
+            self.max_provision_capacity
         );
-        builder.add_call_handler::<rpc::DeleteLumpRpc, _>(clone());
-        builder.add_call_handler::<rpc::ListLumpRpc, _>(clone());
-        builder.add_call_handler::<rpc::UsageRangeRpc, _>(clone());
-        builder.add_call_handler::<rpc::DeleteRangeRpc, _>(clone());
+        Ok(())
     }
+
+    // アクセス制御が有効な場合には、リクエストが許可されているかどうかを検査する.
+    fn check_access(
+        &self,
+        procedure: &str,
+        device_id: &DeviceId,
+        options: &rpc::RequestOptions,
+    ) -> cannyls::Result<()> {
+        if let Some(ref acl) = self.access_control {
+            track!(acl.check(self.verified_key_id(options), procedure, device_id))?;
+        }
+        Ok(())
+    }
+
+    // リクエストの署名の鍵のIDを返す.
+    //
+    // 署名が検証されていない場合には、鍵のIDは信頼できないので、匿名のリクエストとして扱う.
+    fn verified_key_id<'a>(&self, options: &'a rpc::RequestOptions) -> Option<&'a str> {
+        options
+            .signature
+            .as_ref()
+            .filter(|_| self.signature_verifier.is_some())
+            .map(|s| s.key_id.as_str())
+    }
+
+    // 処理の開始前に失敗したリクエストの結果を記録して、応答用のエラーを返す.
+    fn reject(
+        &self,
+        logger: &Logger,
+        procedure: &'static str,
+        options: &rpc::RequestOptions,
+        e: cannyls::Error,
+    ) -> cannyls::Error {
+        let e = match options.trace_id {
+            Some(ref trace_id) => track!(e, "trace_id={}", trace_id),
+            None => track!(e),
+        };
+        if self.access_log {
+            info!(
+                logger,
+                "RPC failed: {}", e;
+                "deadline" => format!("{:?}", options.deadline),
+                "error_kind" => format!("{:?}", e.kind())
+            );
+        }
+        self.metrics.recorder(procedure).record_error(&e);
+        e
+    }
+
+    fn lookup_device(
+        &self,
+        device_id: &DeviceId,
+    ) -> cannyls::Result<(DeviceHandle, DeviceSettings)> {
+        let device = track!(self.registry.get_device(device_id))?;
+        let settings = track!(self.registry.get_device_settings(device_id))?;
+        Ok((device, settings))
+    }
+
+    // リクエストのオプションを考慮して、エラー応答の詳細度を決定する.
+    pub(crate) fn error_verbosity_for(&self, options: &rpc::RequestOptions) -> ErrorVerbosity {
+        if options.verbose_errors && self.verbose_errors_allowed {
+            ErrorVerbosity::Full
+        } else {
+            self.error_verbosity
+        }
+    }
+
+    // デバイスの設定(`DeviceSettings::write_watermark`)に従って、新規の書き込みが可能かどうかを判定する.
+    fn check_write_watermark(&self, device_id: &DeviceId) -> cannyls::Result<()> {
+        let settings = track!(self.registry.get_device_settings(device_id))?;
+        let storage = self.registry.get_storage_metrics(device_id).ok();
+        track!(settings.check_write_watermark(storage.as_ref()))
+    }
+
+    // `GetLumpRpc`および`GetLumpWithMetaRpc`に共通の、lumpの取得処理を開始する.
+    pub(crate) fn get_lump(
+        &self,
+        mut request: rpc::LumpRequest,
+    ) -> cannyls::Result<
+        Tracked<impl Future<Item = cannyls::Result<Option<LumpData>>, Error = Never> + Send>,
+    > {
+        let target = RequestTarget::Lump(request.lump_id);
+        let (device, guard) = track!(self.start::<rpc::GetLumpRpc>(
+            &request.device_id,
+            target,
+            &request.signed_params(),
+            &mut request.options
+        ))?;
+        let metrics = self.metrics.clone();
+        let future = request
+            .options
+            .with(&device)
+            .get(request.lump_id)
+            .map(move |data| {
+                if let Some(ref data) = data {
+                    metrics.observe_payload(rpc::GetLumpRpc::NAME, data.as_bytes().len());
+                }
+                data
+            })
+            .then(Ok);
+        Ok(guard.wrap(future))
+    }
+
+    // `HeadLumpRpc`および`HeadLumpWithMetaRpc`に共通の、lumpヘッダの取得処理を開始する.
+    pub(crate) fn head_lump(
+        &self,
+        mut request: rpc::LumpRequest,
+    ) -> cannyls::Result<
+        Tracked<impl Future<Item = cannyls::Result<Option<LumpHeader>>, Error = Never> + Send>,
+    > {
+        let target = RequestTarget::Lump(request.lump_id);
+        let (device, guard) = track!(self.start::<rpc::HeadLumpRpc>(
+            &request.device_id,
+            target,
+            &request.signed_params(),
+            &mut request.options
+        ))?;
+        let future = request.options.with(&device).head(request.lump_id).then(Ok);
+        Ok(guard.wrap(future))
+    }
+
+    // `PutLumpRpc`・`PutLumpWithMetaRpc`および`PutLumpNoAckRpc`に共通の、lumpの保存処理を開始する.
+    pub(crate) fn put_lump(
+        &self,
+        procedure: &'static str,
+        mut request: rpc::PutLumpRequest,
+    ) -> cannyls::Result<Tracked<impl Future<Item = cannyls::Result<bool>, Error = Never> + Send>>
+    {
+        let target = RequestTarget::Lump(request.lump_id);
+        let (device, guard) = track!(self.start_procedure(
+            procedure,
+            &request.device_id,
+            target,
+            &request.signed_params(),
+            &[request.lump_data.as_bytes()],
+            &mut request.options
+        ))?;
+        let lump_id = request.lump_id;
+        let lump_data = request.lump_data;
+        let size = lump_data.as_bytes().len();
+        self.metrics.observe_payload(procedure, size);
+        let precondition = request.precondition;
+        let options = request.options;
+        let observers = self.observers.clone();
+        let device_id = request.device_id;
+        let expected_checksum = request.checksum;
+        let locks = self.lump_locks.clone();
+        let lock_key = device_id.clone();
+        let future = future::result(self.check_write_watermark(&device_id))
+            .and_then(move |()| match expected_checksum {
+                Some(expected) => {
+                    track!(checksum::verify(lump_data.as_bytes(), expected)).map(|()| lump_data)
+                }
+                None => Ok(lump_data),
+            })
+            .and_then(move |lump_data| {
+                locks
+                    .acquire(lock_key, lump_id)
+                    .map(move |guard| (guard, lump_data))
+            })
+            .and_then(move |(guard, lump_data)| {
+                check_precondition(&device, &options, lump_id, precondition)
+                    .and_then(move |()| options.with(&device).put(lump_id, lump_data))
+                    .then(move |result| {
+                        drop(guard);
+                        result
+                    })
+            })
+            .then(move |result| {
+                if let Ok(created) = result {
+                    let mutation = Mutation::Put {
+                        lump_id,
+                        size,
+                        created,
+                    };
+                    observers.notify(&device_id, &mutation);
+                }
+                Ok(result)
+            });
+        Ok(guard.wrap(future))
+    }
+
+    // `DeleteLumpRpc`・`DeleteLumpWithMetaRpc`および`DeleteLumpNoAckRpc`に共通の、lumpの削除処理を開始する.
+    pub(crate) fn delete_lump(
+        &self,
+        procedure: &'static str,
+        mut request: rpc::LumpRequest,
+    ) -> cannyls::Result<Tracked<impl Future<Item = cannyls::Result<bool>, Error = Never> + Send>>
+    {
+        let target = RequestTarget::Lump(request.lump_id);
+        let (device, guard) = track!(self.start_procedure(
+            procedure,
+            &request.device_id,
+            target,
+            &request.signed_params(),
+            &[],
+            &mut request.options
+        ))?;
+        let lump_id = request.lump_id;
+        let options = request.options;
+        let observers = self.observers.clone();
+        let device_id = request.device_id;
+        let precondition = request.precondition;
+        let future = self
+            .lump_locks
+            .acquire(device_id.clone(), lump_id)
+            .and_then(move |guard| {
+                check_precondition(&device, &options, lump_id, precondition)
+                    .and_then(move |()| options.with(&device).delete(lump_id))
+                    .then(move |result| {
+                        drop(guard);
+                        result
+                    })
+            })
+            .then(move |result| {
+                if let Ok(deleted) = result {
+                    observers.notify(&device_id, &Mutation::Delete { lump_id, deleted });
+                }
+                Ok(result)
+            });
+        Ok(guard.wrap(future))
+    }
+
+    // `ListLumpRpc`の処理を開始する.
+    pub(crate) fn list_lumps(
+        &self,
+        mut request: rpc::DeviceRequest,
+    ) -> cannyls::Result<
+        Tracked<impl Future<Item = cannyls::Result<Vec<LumpId>>, Error = Never> + Send>,
+    > {
+        let (device, guard) = track!(self.start::<rpc::ListLumpRpc>(
+            &request.device_id,
+            RequestTarget::Device,
+            &request.signed_params(),
+            &mut request.options
+        ))?;
+        let future = request.options.with(&device).list().then(Ok);
+        Ok(guard.wrap(future))
+    }
+
+    // `ListLumpRangeRpc`の処理を開始する.
+    pub(crate) fn list_lumps_range(
+        &self,
+        mut request: rpc::RangeLumpRequest,
+    ) -> cannyls::Result<
+        Tracked<impl Future<Item = cannyls::Result<Vec<LumpId>>, Error = Never> + Send>,
+    > {
+        let target = RequestTarget::Range(request.range.clone());
+        let (device, guard) = track!(self.start::<rpc::ListLumpRangeRpc>(
+            &request.device_id,
+            target,
+            &request.signed_params(),
+            &mut request.options
+        ))?;
+        let future = request
+            .options
+            .with(&device)
+            .list_range(request.range)
+            .then(Ok);
+        Ok(guard.wrap(future))
+    }
+
+    // `UsageRangeRpc`の処理を開始する.
+    pub(crate) fn usage_range(
+        &self,
+        mut request: rpc::UsageRangeRequest,
+    ) -> cannyls::Result<
+        Tracked<impl Future<Item = cannyls::Result<StorageUsage>, Error = Never> + Send>,
+    > {
+        let target = RequestTarget::Range(request.range.clone());
+        let (device, guard) = track!(self.start::<rpc::UsageRangeRpc>(
+            &request.device_id,
+            target,
+            &request.signed_params(),
+            &mut request.options
+        ))?;
+        let future = request
+            .options
+            .with(&device)
+            .usage_range(request.range)
+            .then(Ok);
+        Ok(guard.wrap(future))
+    }
+
+    // `DeleteRangeRpc`の処理を開始する.
+    pub(crate) fn delete_range(
+        &self,
+        mut request: rpc::RangeLumpRequest,
+    ) -> cannyls::Result<
+        Tracked<impl Future<Item = cannyls::Result<Vec<LumpId>>, Error = Never> + Send>,
+    > {
+        let target = RequestTarget::Range(request.range.clone());
+        let (device, guard) = track!(self.start::<rpc::DeleteRangeRpc>(
+            &request.device_id,
+            target,
+            &request.signed_params(),
+            &mut request.options
+        ))?;
+        let range = request.range;
+        let options = request.options;
+        let observers = self.observers.clone();
+        let device_id = request.device_id;
+        let locks = self.lump_locks.clone();
+        let lock_key = device_id.clone();
+
+        // 削除対象のlumpのロックを獲得した上で削除する
+        // (一覧の取得後に追加されたlumpは、ロックなしで削除され得るが、それは追加の後に削除が行われた場合と区別できない)
+        let future = options
+            .with(&device)
+            .list_range(range.clone())
+            .and_then(move |lump_ids| locks.acquire_all(lock_key, lump_ids))
+            .and_then(move |lock_guards| {
+                options
+                    .with(&device)
+                    .delete_range(range.clone())
+                    .map(move |deleted| {
+                        drop(lock_guards);
+                        (range, deleted)
+                    })
+            })
+            .then(move |result| {
+                Ok(result.map(|(range, deleted)| {
+                    let mutation = Mutation::DeleteRange {
+                        range,
+                        deleted: deleted.clone(),
+                    };
+                    observers.notify(&device_id, &mutation);
+                    deleted
+                }))
+            });
+        Ok(guard.wrap(future))
+    }
+
+    // 通知RPCの処理を実行するための`NoReply`を生成する.
+    //
+    // 処理の失敗はクライアントには通知できないので、ログに出力するのみとなる.
+    fn no_reply<F, T>(
+        &self,
+        procedure: &'static str,
+        device_id: DeviceId,
+        lump_id: LumpId,
+        future: cannyls::Result<F>,
+    ) -> NoReply
+    where
+        F: Future<Item = cannyls::Result<T>, Error = Never> + Send + 'static,
+    {
+        let logger = self.registry.logger().clone();
+        match future {
+            Err(e) => {
+                debug!(
+                    logger,
+                    "Failed to start {}: device={:?}, lump_id={}, error={}",
+                    procedure,
+                    device_id,
+                    lump_id,
+                    e
+                );
+                NoReply::done()
+            }
+            Ok(future) => NoReply::future(future.map(move |result| {
+                if let Err(e) = result {
+                    debug!(
+                        logger,
+                        "Failed to {}: device={:?}, lump_id={}, error={}",
+                        procedure,
+                        device_id,
+                        lump_id,
+                        e
+                    );
+                }
+            })),
+        }
+    }
+}
+/// RPCのエラー応答に含める情報の詳細度.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorVerbosity {
+    /// エラーの履歴を含む、完全なエラーを返す.
+    #[default]
+    Full,
+
+    /// エラーの種類と原因のメッセージのみを返す.
+    ///
+    /// `trackable`のエラー履歴(ソースファイルの位置等)は取り除かれる.
+    /// 応答サイズを抑えたり、外部のクライアントに内部の情報を公開しないようにするためのもの.
+    Summary,
 }
+impl ErrorVerbosity {
+    // `TrackableError`は`Error::source`を実装していないので、代わりに(非推奨の)`Error::cause`を使っている.
+    #[allow(deprecated)]
+    pub(crate) fn apply<T>(self, result: cannyls::Result<T>) -> cannyls::Result<T> {
+        match result {
+            Err(e) if self == ErrorVerbosity::Summary => {
+                let kind = *e.kind();
+                let summary = if let Some(hint) = rpc::BusyHint::of(&e) {
+                    kind.cause(hint.clone())
+                } else if let Some(cause) = std::error::Error::cause(&*e) {
+                    kind.cause(cause.to_string())
+                } else {
+                    kind.error()
+                };
+                Err(summary.into())
+            }
+            _ => result,
+        }
+    }
+}
+
+/// 個々のRPCの有効・無効の設定.
+///
+/// デフォルトでは、全てのRPCが有効となっている.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate cannyls_rpc;
+/// use cannyls_rpc::rpc;
+/// use cannyls_rpc::ProcedureConfig;
+///
+/// let mut config = ProcedureConfig::new();
+/// config.disable::<rpc::DeleteRangeRpc>();
+/// # let _ = config;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ProcedureConfig {
+    disabled: Vec<ProcedureId>,
+}
+impl ProcedureConfig {
+    /// 新しい`ProcedureConfig`インスタンスを生成する.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 指定されたRPCを無効にする.
+    pub fn disable<T: Call>(&mut self) -> &mut Self {
+        self.disable_procedure(T::ID)
+    }
+
+    /// 指定されたIDのRPCを無効にする.
+    pub fn disable_procedure(&mut self, procedure: ProcedureId) -> &mut Self {
+        if !self.disabled.contains(&procedure) {
+            self.disabled.push(procedure);
+        }
+        self
+    }
+
+    /// 指定されたIDのRPCが有効かどうかを判定する.
+    pub fn is_enabled(&self, procedure: ProcedureId) -> bool {
+        !self.disabled.contains(&procedure)
+    }
+}
+
+// 無効にされたRPCに対して、エラーを返すハンドラ.
+#[derive(Debug, Clone, Copy)]
+struct Disabled(ErrorVerbosity);
+impl<T, V> HandleCall<T> for Disabled
+where
+    T: Call<Res = cannyls::Result<V>>,
+{
+    fn handle_call(&self, _: T::Req) -> Reply<T> {
+        let e = cannyls::ErrorKind::InvalidInput.cause(format!(
+            "Unsupported procedure: {} is disabled on this server",
+            T::NAME
+        ));
+        Reply::done(self.0.apply(Err(track!(cannyls::Error::from(e)))))
+    }
+}
+
+impl<T: Cast> HandleCast<T> for Disabled {
+    // 通知RPCには応答がないので、単に無視する
+    fn handle_cast(&self, _: T::Notification) -> NoReply {
+        NoReply::done()
+    }
+}
+
+// `rpc::ServerInfoRpc`のハンドラ.
+//
+// 応答内容は登録時に確定するため、`Server`とは別のハンドラとしている.
+struct ServerInfoHandler(ServerInfo);
+impl HandleCall<rpc::ServerInfoRpc> for ServerInfoHandler {
+    fn handle_call(&self, (): ()) -> Reply<rpc::ServerInfoRpc> {
+        Reply::done(Ok(self.0.clone()))
+    }
+}
+
+// 除外対象を考慮しつつ、ハンドラを登録するためのヘルパ.
+struct Registrar<'a> {
+    server: &'a Server,
+    builder: &'a mut ServerBuilder,
+    excluded: &'a [ProcedureId],
+    procedures: Vec<ProcedureId>, // 登録済みの(有効な)RPC群
+}
+impl<'a> Registrar<'a> {
+    fn call<T>(&mut self)
+    where
+        T: Call,
+        T::ReqDecoder: Default,
+        T::ResEncoder: Default,
+        Server: HandleCall<T>,
+        Disabled: HandleCall<T>,
+    {
+        if self.excluded.contains(&T::ID) {
+            return;
+        }
+        if self.server.procedures.is_enabled(T::ID) {
+            self.builder.add_call_handler::<T, _>(self.server.clone());
+            self.procedures.push(T::ID);
+        } else {
+            self.builder
+                .add_call_handler::<T, _>(Disabled(self.server.error_verbosity));
+        }
+    }
+
+    fn cast<T>(&mut self)
+    where
+        T: Cast,
+        T::Decoder: Default,
+        Server: HandleCast<T>,
+    {
+        if self.excluded.contains(&T::ID) {
+            return;
+        }
+        if self.server.procedures.is_enabled(T::ID) {
+            self.builder.add_cast_handler::<T, _>(self.server.clone());
+            self.procedures.push(T::ID);
+        } else {
+            self.builder
+                .add_cast_handler::<T, _>(Disabled(self.server.error_verbosity));
+        }
+    }
+
+    // リクエストのデコードに`put_lump_decoder_factory`を使う通知RPCを登録する.
+    fn put_cast<T>(&mut self)
+    where
+        T: Cast<Decoder = protobuf::PutLumpRequestDecoder>,
+        Server: HandleCast<T>,
+    {
+        if self.excluded.contains(&T::ID) {
+            return;
+        }
+        let factory = self.server.put_lump_decoder_factory();
+        if self.server.procedures.is_enabled(T::ID) {
+            self.builder
+                .add_cast_handler_with_decoder::<T, _, _>(self.server.clone(), factory);
+            self.procedures.push(T::ID);
+        } else {
+            let handler = Disabled(self.server.error_verbosity);
+            self.builder
+                .add_cast_handler_with_decoder::<T, _, _>(handler, factory);
+        }
+    }
+
+    // リクエストのデコードに`put_lump_decoder_factory`を使うRPCを登録する.
+    fn put_call<T>(&mut self)
+    where
+        T: Call<ReqDecoder = protobuf::PutLumpRequestDecoder>,
+        T::ResEncoder: Default,
+        Server: HandleCall<T>,
+        Disabled: HandleCall<T>,
+    {
+        if self.excluded.contains(&T::ID) {
+            return;
+        }
+        let factory = self.server.put_lump_decoder_factory();
+        if self.server.procedures.is_enabled(T::ID) {
+            self.builder
+                .add_call_handler_with_decoder::<T, _, _>(self.server.clone(), factory);
+            self.procedures.push(T::ID);
+        } else {
+            let handler = Disabled(self.server.error_verbosity);
+            self.builder
+                .add_call_handler_with_decoder::<T, _, _>(handler, factory);
+        }
+    }
+
+    // それまでに登録されたRPCの一覧を応答する`rpc::ServerInfoRpc`を登録する.
+    //
+    // そのため、最後に呼び出す必要がある.
+    fn server_info(mut self) {
+        type T = rpc::ServerInfoRpc;
+        self.server.registered.store(self.procedures.clone());
+        if self.excluded.contains(&T::ID) {
+            return;
+        }
+        if !self.server.procedures.is_enabled(T::ID) {
+            self.builder
+                .add_call_handler::<T, _>(Disabled(self.server.error_verbosity));
+            return;
+        }
+        self.procedures.push(T::ID);
+        self.procedures.sort();
+        let info = ServerInfo {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            procedures: self.procedures,
+        };
+        self.builder
+            .add_call_handler::<T, _>(ServerInfoHandler(info));
+    }
+}
+
 impl HandleCall<rpc::GetLumpRpc> for Server {
     fn handle_call(&self, request: rpc::LumpRequest) -> Reply<rpc::GetLumpRpc> {
-        let device = rpc_try!(self.registry.get_device(&request.device_id));
-        let future = request.options.with(&device).get(request.lump_id).then(Ok);
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(verbosity, self.get_lump(request));
         Reply::future(future)
     }
 }
+impl HandleCall<rpc::GetLumpWithMetaRpc> for Server {
+    fn handle_call(&self, request: rpc::LumpRequest) -> Reply<rpc::GetLumpWithMetaRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(verbosity, self.get_lump(request));
+        Reply::future(future.with_response_meta())
+    }
+}
+impl HandleCall<rpc::GetLumpRangeRpc> for Server {
+    fn handle_call(&self, mut request: rpc::GetLumpRangeRequest) -> Reply<rpc::GetLumpRangeRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let byte_range = request.byte_range.clone();
+        if byte_range.start > byte_range.end {
+            let e = cannyls::ErrorKind::InvalidInput
+                .cause(format!("Invalid byte range: {:?}", byte_range));
+            return Reply::done(verbosity.apply(Err(track!(cannyls::Error::from(e)))));
+        }
+
+        let target = RequestTarget::Lump(request.lump_id);
+        let (device, guard) = rpc_try!(
+            verbosity,
+            self.start::<rpc::GetLumpRangeRpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &mut request.options
+            )
+        );
+        let future = request
+            .options
+            .with(&device)
+            .get(request.lump_id)
+            .and_then(move |data| {
+                let data = if let Some(data) = data {
+                    data
+                } else {
+                    return Ok(None);
+                };
+                let bytes = data.as_bytes();
+                let len = bytes.len() as u64;
+                let start = byte_range.start.min(len) as usize;
+                let end = byte_range.end.min(len) as usize;
+                track!(LumpData::new(bytes[start..end].to_vec())).map(Some)
+            })
+            .then(Ok);
+        Reply::future(guard.wrap(future))
+    }
+}
+impl HandleCall<rpc::GetLumpWithChecksumRpc> for Server {
+    fn handle_call(&self, mut request: rpc::LumpRequest) -> Reply<rpc::GetLumpWithChecksumRpc> {
+        let target = RequestTarget::Lump(request.lump_id);
+        let (device, guard) = rpc_try!(
+            self.error_verbosity_for(&request.options),
+            self.start::<rpc::GetLumpWithChecksumRpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &mut request.options
+            )
+        );
+        let future = request
+            .options
+            .with(&device)
+            .get(request.lump_id)
+            .then(|result| {
+                Ok(result.map(|data| {
+                    data.map(|data| {
+                        let checksum = checksum::compute(data.as_bytes());
+                        (data, checksum)
+                    })
+                }))
+            });
+        Reply::future(guard.wrap(future))
+    }
+}
 impl HandleCall<rpc::HeadLumpRpc> for Server {
     fn handle_call(&self, request: rpc::LumpRequest) -> Reply<rpc::HeadLumpRpc> {
-        let device = rpc_try!(self.registry.get_device(&request.device_id));
-        let future = request.options.with(&device).head(request.lump_id).then(Ok);
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(verbosity, self.head_lump(request));
         Reply::future(future)
     }
 }
-impl HandleCall<rpc::PutLumpRpc> for Server {
-    fn handle_call(&self, request: rpc::PutLumpRequest) -> Reply<rpc::PutLumpRpc> {
-        let device = rpc_try!(self.registry.get_device(&request.device_id));
-        let lump_data = request.lump_data;
+impl HandleCall<rpc::HeadLumpWithMetaRpc> for Server {
+    fn handle_call(&self, request: rpc::LumpRequest) -> Reply<rpc::HeadLumpWithMetaRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(verbosity, self.head_lump(request));
+        Reply::future(future.with_response_meta())
+    }
+}
+impl HandleCall<rpc::ExistsLumpRpc> for Server {
+    fn handle_call(&self, mut request: rpc::LumpRequest) -> Reply<rpc::ExistsLumpRpc> {
+        let target = RequestTarget::Lump(request.lump_id);
+        let (device, guard) = rpc_try!(
+            self.error_verbosity_for(&request.options),
+            self.start::<rpc::ExistsLumpRpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &mut request.options
+            )
+        );
         let future = request
             .options
             .with(&device)
-            .put(request.lump_id, lump_data)
+            .head(request.lump_id)
+            .map(|header| header.is_some())
             .then(Ok);
+        Reply::future(guard.wrap(future))
+    }
+}
+impl HandleCall<rpc::PutLumpRpc> for Server {
+    fn handle_call(&self, request: rpc::PutLumpRequest) -> Reply<rpc::PutLumpRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(verbosity, self.put_lump(rpc::PutLumpRpc::NAME, request));
         Reply::future(future)
     }
 }
+impl HandleCall<rpc::PutLumpWithMetaRpc> for Server {
+    fn handle_call(&self, request: rpc::PutLumpRequest) -> Reply<rpc::PutLumpWithMetaRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(verbosity, self.put_lump(rpc::PutLumpRpc::NAME, request));
+        Reply::future(future.with_response_meta())
+    }
+}
+impl HandleCast<rpc::PutLumpNoAckRpc> for Server {
+    fn handle_cast(&self, request: rpc::PutLumpRequest) -> NoReply {
+        type T = rpc::PutLumpNoAckRpc;
+        let device_id = request.device_id.clone();
+        let lump_id = request.lump_id;
+        let future = self.put_lump(T::NAME, request);
+        self.no_reply(T::NAME, device_id, lump_id, future)
+    }
+}
+impl HandleCall<rpc::PutLumpV2Rpc> for Server {
+    fn handle_call(&self, mut request: rpc::PutLumpRequest) -> Reply<rpc::PutLumpV2Rpc> {
+        let target = RequestTarget::Lump(request.lump_id);
+        let (device, guard) = rpc_try!(
+            self.error_verbosity_for(&request.options),
+            self.start_with_payload::<rpc::PutLumpV2Rpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &[request.lump_data.as_bytes()],
+                &mut request.options
+            )
+        );
+        let lump_id = request.lump_id;
+        let lump_data = request.lump_data;
+        let size = lump_data.as_bytes().len();
+        self.metrics.observe_payload(rpc::PutLumpV2Rpc::NAME, size);
+        let precondition = request.precondition;
+        let options = request.options;
+        let observers = self.observers.clone();
+        let device_id = request.device_id;
+        let expected_checksum = request.checksum;
+        let locks = self.lump_locks.clone();
+        let lock_key = device_id.clone();
+        let future = future::result(self.check_write_watermark(&device_id))
+            .and_then(move |()| match expected_checksum {
+                Some(expected) => {
+                    track!(checksum::verify(lump_data.as_bytes(), expected)).map(|()| lump_data)
+                }
+                None => Ok(lump_data),
+            })
+            .and_then(move |lump_data| {
+                locks
+                    .acquire(lock_key, lump_id)
+                    .map(move |guard| (guard, lump_data))
+            })
+            .and_then(move |(guard, lump_data)| {
+                // 事前条件の評価にも、上書き前のヘッダを使う
+                options.with(&device).head(lump_id).and_then(move |header| {
+                    if let Some(precondition) = precondition {
+                        track!(precondition.check(header.as_ref()))?;
+                    }
+                    Ok((guard, device, options, lump_data, header))
+                })
+            })
+            .and_then(move |(guard, device, options, lump_data, header)| {
+                options
+                    .with(&device)
+                    .put(lump_id, lump_data)
+                    .map(move |created| {
+                        drop(guard);
+                        (created, header)
+                    })
+            })
+            .then(move |result| {
+                if let Ok((created, _)) = result {
+                    let mutation = Mutation::Put {
+                        lump_id,
+                        size,
+                        created,
+                    };
+                    observers.notify(&device_id, &mutation);
+                }
+                Ok(result.map(|(created, header)| if created { None } else { header }))
+            });
+        Reply::future(guard.wrap(future))
+    }
+}
 impl HandleCall<rpc::DeleteLumpRpc> for Server {
     fn handle_call(&self, request: rpc::LumpRequest) -> Reply<rpc::DeleteLumpRpc> {
-        let device = rpc_try!(self.registry.get_device(&request.device_id));
-        let future = request
-            .options
-            .with(&device)
-            .delete(request.lump_id)
-            .then(Ok);
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(
+            verbosity,
+            self.delete_lump(rpc::DeleteLumpRpc::NAME, request)
+        );
         Reply::future(future)
     }
 }
+impl HandleCall<rpc::DeleteLumpWithMetaRpc> for Server {
+    fn handle_call(&self, request: rpc::LumpRequest) -> Reply<rpc::DeleteLumpWithMetaRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(
+            verbosity,
+            self.delete_lump(rpc::DeleteLumpRpc::NAME, request)
+        );
+        Reply::future(future.with_response_meta())
+    }
+}
+impl HandleCast<rpc::DeleteLumpNoAckRpc> for Server {
+    fn handle_cast(&self, request: rpc::LumpRequest) -> NoReply {
+        type T = rpc::DeleteLumpNoAckRpc;
+        let device_id = request.device_id.clone();
+        let lump_id = request.lump_id;
+        let future = self.delete_lump(T::NAME, request);
+        self.no_reply(T::NAME, device_id, lump_id, future)
+    }
+}
+impl HandleCall<rpc::DeleteLumpV2Rpc> for Server {
+    fn handle_call(&self, mut request: rpc::LumpRequest) -> Reply<rpc::DeleteLumpV2Rpc> {
+        let target = RequestTarget::Lump(request.lump_id);
+        let (device, guard) = rpc_try!(
+            self.error_verbosity_for(&request.options),
+            self.start::<rpc::DeleteLumpV2Rpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &mut request.options
+            )
+        );
+        let lump_id = request.lump_id;
+        let precondition = request.precondition;
+        let options = request.options;
+        let observers = self.observers.clone();
+        let device_id = request.device_id;
+        let future = self
+            .lump_locks
+            .acquire(device_id.clone(), lump_id)
+            .and_then(move |guard| {
+                options.with(&device).head(lump_id).and_then(move |header| {
+                    // 事前条件の評価にも、削除前のヘッダを使う
+                    if let Some(precondition) = precondition {
+                        track!(precondition.check(header.as_ref()))?;
+                    }
+                    Ok((guard, device, options, header))
+                })
+            })
+            .and_then(move |(guard, device, options, header)| {
+                options.with(&device).delete(lump_id).map(move |deleted| {
+                    drop(guard);
+                    (deleted, header)
+                })
+            })
+            .then(move |result| {
+                if let Ok((deleted, _)) = result {
+                    observers.notify(&device_id, &Mutation::Delete { lump_id, deleted });
+                }
+                Ok(result.map(|(deleted, header)| if deleted { header } else { None }))
+            });
+        Reply::future(guard.wrap(future))
+    }
+}
 impl HandleCall<rpc::ListLumpRpc> for Server {
     fn handle_call(&self, request: rpc::DeviceRequest) -> Reply<rpc::ListLumpRpc> {
-        let device = rpc_try!(self.registry.get_device(&request.device_id));
-        let future = request.options.with(&device).list().then(Ok);
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(verbosity, self.list_lumps(request));
         Reply::future(future)
     }
 }
-impl HandleCall<rpc::UsageRangeRpc> for Server {
-    fn handle_call(&self, request: rpc::UsageRangeRequest) -> Reply<rpc::UsageRangeRpc> {
-        let device = rpc_try!(self.registry.get_device(&request.device_id));
+impl HandleCall<rpc::ListLumpRangeRpc> for Server {
+    fn handle_call(&self, request: rpc::RangeLumpRequest) -> Reply<rpc::ListLumpRangeRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(verbosity, self.list_lumps_range(request));
+        Reply::future(future)
+    }
+}
+impl HandleCall<rpc::ScrubRangeRpc> for Server {
+    fn handle_call(&self, mut request: rpc::RangeLumpRequest) -> Reply<rpc::ScrubRangeRpc> {
+        let target = RequestTarget::Range(request.range.clone());
+        let (device, guard) = rpc_try!(
+            self.error_verbosity_for(&request.options),
+            self.start::<rpc::ScrubRangeRpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &mut request.options
+            )
+        );
+        let options = request.options;
+        let logger = self.registry.logger().clone();
+        let device_id = request.device_id;
+        let future = options
+            .with(&device)
+            .list_range(request.range)
+            .and_then(move |lump_ids| {
+                future::loop_fn(
+                    (lump_ids.into_iter(), Vec::new()),
+                    move |(mut lump_ids, mut failed)| {
+                        let lump_id = if let Some(lump_id) = lump_ids.next() {
+                            lump_id
+                        } else {
+                            return Either::A(future::ok(Loop::Break(failed)));
+                        };
+                        let logger = logger.clone();
+                        let device_id = device_id.clone();
+                        let future = options.with(&device).get(lump_id).then(move |result| {
+                            if let Err(e) = result {
+                                match *e.kind() {
+                                    cannyls::ErrorKind::DeviceBusy
+                                    | cannyls::ErrorKind::DeviceTerminated
+                                    | cannyls::ErrorKind::RequestDropped => return Err(e),
+                                    _ => {}
+                                }
+                                warn!(
+                                    logger,
+                                    "Scrub detected a broken lump: device={:?}, lump={}, reason={}",
+                                    device_id.as_str(),
+                                    lump_id,
+                                    e
+                                );
+                                failed.push(lump_id);
+                            }
+                            Ok(Loop::Continue((lump_ids, failed)))
+                        });
+                        Either::B(future)
+                    },
+                )
+            })
+            .then(Ok);
+        Reply::future(guard.wrap(future))
+    }
+}
+impl HandleCall<rpc::CopyRangeRpc> for Server {
+    fn handle_call(&self, mut request: rpc::CopyRangeRequest) -> Reply<rpc::CopyRangeRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        if request.source_device_id == request.destination_device_id {
+            let e = cannyls::ErrorKind::InvalidInput
+                .cause("The source and destination devices must be different");
+            return Reply::done(verbosity.apply(Err(track!(cannyls::Error::from(e)))));
+        }
+
+        let target = RequestTarget::Range(request.range.clone());
+        let (src, guard) = rpc_try!(
+            verbosity,
+            self.start_with_payload::<rpc::CopyRangeRpc>(
+                &request.source_device_id,
+                target,
+                &request.signed_params(),
+                &[request.destination_device_id.as_str().as_bytes()],
+                &mut request.options
+            )
+        );
+        let dst_id = request.destination_device_id;
+        let procedure = rpc::CopyRangeRpc::NAME;
+        let dst = match track!(self.check_access(procedure, &dst_id, &request.options))
+            .and_then(|()| track!(self.lookup_device(&dst_id)))
+            .and_then(|(dst, _)| track!(self.check_write_watermark(&dst_id)).map(|()| dst))
+        {
+            Err(e) => return Reply::future(guard.wrap(future::ok(Err(e)))),
+            Ok(dst) => dst,
+        };
+
+        let options = request.options;
+        let observers = self.observers.clone();
+        let locks = self.lump_locks.clone();
+        let future = options
+            .with(&src)
+            .list_range(request.range)
+            .and_then(move |lump_ids| {
+                future::loop_fn(
+                    (lump_ids.into_iter(), Vec::new()),
+                    move |(mut lump_ids, mut copied)| {
+                        let lump_id = if let Some(lump_id) = lump_ids.next() {
+                            lump_id
+                        } else {
+                            return Either::A(future::ok(Loop::Break(copied)));
+                        };
+                        let dst = dst.clone();
+                        let dst_id = dst_id.clone();
+                        let observers = observers.clone();
+                        let options = options.clone();
+                        let locks = locks.clone();
+                        let future = options.with(&src).get(lump_id).and_then(move |data| {
+                            // NOTE: 一覧の取得後に削除されたlumpは、単にコピー対象から除外される
+                            let data = if let Some(data) = data {
+                                data
+                            } else {
+                                return Either::A(future::ok(Loop::Continue((lump_ids, copied))));
+                            };
+                            let size = data.as_bytes().len();
+                            let future = future::result(track!(to_device_lump_data(&dst, data)))
+                                .and_then(move |data| {
+                                    locks
+                                        .acquire(dst_id.clone(), lump_id)
+                                        .map(move |lock_guard| (lock_guard, data, dst_id))
+                                })
+                                .and_then(move |(lock_guard, data, dst_id)| {
+                                    options.with(&dst).put(lump_id, data).map(move |created| {
+                                        drop(lock_guard);
+                                        (created, dst_id)
+                                    })
+                                })
+                                .map(move |(created, dst_id)| {
+                                    let mutation = Mutation::Put {
+                                        lump_id,
+                                        size,
+                                        created,
+                                    };
+                                    observers.notify(&dst_id, &mutation);
+                                    copied.push(lump_id);
+                                    Loop::Continue((lump_ids, copied))
+                                });
+                            Either::B(future)
+                        });
+                        Either::B(future)
+                    },
+                )
+            })
+            .then(Ok);
+        Reply::future(guard.wrap(future))
+    }
+}
+impl HandleCall<rpc::ExportLumpsRpc> for Server {
+    fn handle_call(&self, mut request: rpc::ExportLumpsRequest) -> Reply<rpc::ExportLumpsRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        if request.max_lumps == 0 {
+            let e = cannyls::ErrorKind::InvalidInput.cause("`max_lumps` must be positive");
+            return Reply::done(verbosity.apply(Err(track!(cannyls::Error::from(e)))));
+        }
+
+        let target = RequestTarget::Range(request.range.clone());
+        let (device, guard) = rpc_try!(
+            verbosity,
+            self.start::<rpc::ExportLumpsRpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &mut request.options
+            )
+        );
+        let options = request.options;
+        let max_lumps = request.max_lumps;
+        let future = options
+            .with(&device)
+            .list_range(request.range)
+            .and_then(move |mut lump_ids| {
+                let next = if lump_ids.len() > max_lumps {
+                    let next = lump_ids[max_lumps];
+                    lump_ids.truncate(max_lumps);
+                    Some(next)
+                } else {
+                    None
+                };
+                let futures = lump_ids
+                    .into_iter()
+                    .map(|lump_id| {
+                        options
+                            .with(&device)
+                            .get(lump_id)
+                            .map(move |data| data.map(|data| (lump_id, data)))
+                    })
+                    .collect::<Vec<_>>();
+
+                // NOTE: 一覧の取得後に削除されたlumpは、単に結果から除外される
+                future::join_all(futures).map(move |lumps| rpc::ExportLumpsChunk {
+                    lumps: lumps.into_iter().flatten().collect(),
+                    next,
+                })
+            })
+            .then(Ok);
+        Reply::future(guard.wrap(future))
+    }
+}
+impl HandleCall<rpc::ListLumpsChunkRpc> for Server {
+    fn handle_call(&self, mut request: rpc::ExportLumpsRequest) -> Reply<rpc::ListLumpsChunkRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        if request.max_lumps == 0 {
+            let e = cannyls::ErrorKind::InvalidInput.cause("`max_lumps` must be positive");
+            return Reply::done(verbosity.apply(Err(track!(cannyls::Error::from(e)))));
+        }
+
+        let target = RequestTarget::Range(request.range.clone());
+        let (device, guard) = rpc_try!(
+            verbosity,
+            self.start::<rpc::ListLumpsChunkRpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &mut request.options
+            )
+        );
+        let max_lumps = request.max_lumps;
         let future = request
             .options
             .with(&device)
-            .usage_range(request.range)
+            .list_range(request.range)
+            .map(move |mut lump_ids| {
+                let next = if lump_ids.len() > max_lumps {
+                    let next = lump_ids[max_lumps];
+                    lump_ids.truncate(max_lumps);
+                    Some(next)
+                } else {
+                    None
+                };
+                rpc::ListLumpsChunk { lump_ids, next }
+            })
             .then(Ok);
+        Reply::future(guard.wrap(future))
+    }
+}
+impl HandleCall<rpc::UsageRangeRpc> for Server {
+    fn handle_call(&self, request: rpc::UsageRangeRequest) -> Reply<rpc::UsageRangeRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(verbosity, self.usage_range(request));
         Reply::future(future)
     }
 }
 impl HandleCall<rpc::DeleteRangeRpc> for Server {
     fn handle_call(&self, request: rpc::RangeLumpRequest) -> Reply<rpc::DeleteRangeRpc> {
-        let device = rpc_try!(self.registry.get_device(&request.device_id));
-        let future = request
-            .options
+        let verbosity = self.error_verbosity_for(&request.options);
+        let future = rpc_try!(verbosity, self.delete_range(request));
+        Reply::future(future)
+    }
+}
+impl HandleCall<rpc::DeleteRangeBoundedRpc> for Server {
+    fn handle_call(
+        &self,
+        mut request: rpc::DeleteRangeBoundedRequest,
+    ) -> Reply<rpc::DeleteRangeBoundedRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        if request.max_lumps == 0 {
+            let e = cannyls::ErrorKind::InvalidInput.cause("`max_lumps` must be positive");
+            return Reply::done(verbosity.apply(Err(track!(cannyls::Error::from(e)))));
+        }
+
+        let target = RequestTarget::Range(request.range.clone());
+        let (device, guard) = rpc_try!(
+            verbosity,
+            self.start::<rpc::DeleteRangeBoundedRpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &mut request.options
+            )
+        );
+        let options = request.options;
+        let max_lumps = request.max_lumps;
+        let range = request.range;
+        let observers = self.observers.clone();
+        let device_id = request.device_id;
+        let locks = self.lump_locks.clone();
+        let future = options
             .with(&device)
-            .delete_range(request.range)
+            .list_range(range.clone())
+            .and_then(move |mut lump_ids| {
+                // 削除対象の範囲を、先頭から`max_lumps`個のlumpを含む部分に縮める
+                let next = lump_ids.get(max_lumps).cloned();
+                let range = range.start..next.unwrap_or(range.end);
+                lump_ids.truncate(max_lumps);
+                locks
+                    .acquire_all(device_id.clone(), lump_ids)
+                    .map(move |lock_guards| (lock_guards, range, next, device_id))
+            })
+            .and_then(move |(lock_guards, range, next, device_id)| {
+                options
+                    .with(&device)
+                    .delete_range(range.clone())
+                    .map(move |deleted| {
+                        drop(lock_guards);
+                        let mutation = Mutation::DeleteRange {
+                            range,
+                            deleted: deleted.clone(),
+                        };
+                        observers.notify(&device_id, &mutation);
+                        rpc::DeleteRangeChunk { deleted, next }
+                    })
+            })
             .then(Ok);
+        Reply::future(guard.wrap(future))
+    }
+}
+impl HandleCall<rpc::JournalUsageRpc> for Server {
+    fn handle_call(&self, request: rpc::DeviceRequest) -> Reply<rpc::JournalUsageRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        rpc_try!(
+            verbosity,
+            self.authorize::<rpc::JournalUsageRpc>(
+                &request.device_id,
+                &request.signed_params(),
+                &request.options
+            )
+        );
+        let metrics = rpc_try!(
+            verbosity,
+            self.registry.get_storage_metrics(&request.device_id)
+        );
+        Reply::done(Ok(JournalUsage::from_metrics(metrics.journal_region())))
+    }
+}
+impl HandleCall<rpc::StorageHeaderRpc> for Server {
+    fn handle_call(&self, request: rpc::DeviceRequest) -> Reply<rpc::StorageHeaderRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        rpc_try!(
+            verbosity,
+            self.authorize::<rpc::StorageHeaderRpc>(
+                &request.device_id,
+                &request.signed_params(),
+                &request.options
+            )
+        );
+        let metrics = rpc_try!(
+            verbosity,
+            self.registry.get_storage_metrics(&request.device_id)
+        );
+        Reply::done(Ok(metrics.header().clone()))
+    }
+}
+impl HandleCall<rpc::MetricsSnapshotRpc> for Server {
+    fn handle_call(&self, device_ids: Vec<DeviceId>) -> Reply<rpc::MetricsSnapshotRpc> {
+        let result = track!(self.registry.metrics_snapshot(&device_ids)).map(|mut snapshots| {
+            snapshots.retain(|s| self.is_visible(rpc::MetricsSnapshotRpc::NAME, &s.device_id));
+            snapshots
+        });
+        Reply::done(self.error_verbosity.apply(result))
+    }
+}
+impl HandleCall<rpc::RequestStatsRpc> for Server {
+    fn handle_call(&self, device_ids: Vec<DeviceId>) -> Reply<rpc::RequestStatsRpc> {
+        let result = track!(self.stats.list(&device_ids)).map(|mut stats| {
+            stats.retain(|s| self.is_visible(rpc::RequestStatsRpc::NAME, &s.device_id));
+            stats
+        });
+        Reply::done(self.error_verbosity.apply(result))
+    }
+}
+impl HandleCall<rpc::ListDevicesRpc> for Server {
+    fn handle_call(&self, with_status: bool) -> Reply<rpc::ListDevicesRpc> {
+        let devices = self.registry.snapshot();
+        let mut summaries = devices
+            .device_ids()
+            .filter(|device_id| self.is_visible(rpc::ListDevicesRpc::NAME, device_id))
+            .map(|device_id| {
+                let status = if with_status {
+                    devices
+                        .get_device(device_id)
+                        .ok()
+                        .map(|device| device.metrics().status())
+                } else {
+                    None
+                };
+                DeviceSummary {
+                    device_id: device_id.clone(),
+                    status,
+                }
+            })
+            .collect::<Vec<_>>();
+        summaries.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        Reply::done(Ok(summaries))
+    }
+}
+impl HandleCall<rpc::DeviceStatusRpc> for Server {
+    fn handle_call(&self, request: rpc::DeviceRequest) -> Reply<rpc::DeviceStatusRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        rpc_try!(
+            verbosity,
+            self.authorize::<rpc::DeviceStatusRpc>(
+                &request.device_id,
+                &request.signed_params(),
+                &request.options
+            )
+        );
+        let device_id = request.device_id;
+        let result = track!(self
+            .registry
+            .metrics_snapshot(std::slice::from_ref(&device_id)))
+        .map(|mut snapshots| DeviceStatusReport::new(device_id, snapshots.pop()));
+        Reply::done(verbosity.apply(result))
+    }
+}
+impl HandleCall<rpc::DeviceLabelsRpc> for Server {
+    fn handle_call(&self, request: rpc::DeviceRequest) -> Reply<rpc::DeviceLabelsRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        rpc_try!(
+            verbosity,
+            self.authorize::<rpc::DeviceLabelsRpc>(
+                &request.device_id,
+                &request.signed_params(),
+                &request.options
+            )
+        );
+        let result = track!(self.registry.get_device_labels(&request.device_id));
+        Reply::done(verbosity.apply(result))
+    }
+}
+impl HandleCall<rpc::ReadinessRpc> for Server {
+    fn handle_call(&self, mut device_ids: Vec<DeviceId>) -> Reply<rpc::ReadinessRpc> {
+        let devices = self.registry.snapshot();
+        if device_ids.is_empty() {
+            device_ids = devices.device_ids().cloned().collect();
+        }
+        device_ids.retain(|device_id| self.is_visible(rpc::ReadinessRpc::NAME, device_id));
+        device_ids.sort();
+        device_ids.dedup();
+
+        let futures = device_ids.into_iter().map(move |device_id| {
+            let start_time = Instant::now();
+            let future = match devices.get_device(&device_id) {
+                Err(e) => Either::A(future::err(e)),
+                Ok(device) => Either::B(
+                    device
+                        .request()
+                        .deadline(Deadline::Immediate)
+                        .head(LumpId::new(READINESS_PROBE_LUMP_ID)),
+                ),
+            };
+            future.then(move |result| {
+                Ok(DeviceReadiness {
+                    device_id,
+                    error: result.err().map(|e| *e.kind()),
+                    latency: start_time.elapsed(),
+                })
+            })
+        });
+        Reply::future(future::join_all(futures).map(Ok))
+    }
+}
+impl HandleCall<rpc::SetJournalSyncRpc> for Server {
+    fn handle_call(&self, request: rpc::SetJournalSyncRequest) -> Reply<rpc::SetJournalSyncRpc> {
+        rpc_try!(
+            self.error_verbosity,
+            self.authorize::<rpc::SetJournalSyncRpc>(
+                &request.device_id,
+                &request.signed_params(),
+                &request.options
+            )
+        );
+        let journal_sync = request.journal_sync;
+        let result = track!(self
+            .registry
+            .update_device_settings(&request.device_id, |s| s.journal_sync = journal_sync));
+        Reply::done(self.error_verbosity.apply(result))
+    }
+}
+impl HandleCall<rpc::SetQueueLimitsRpc> for Server {
+    fn handle_call(&self, request: rpc::SetQueueLimitsRequest) -> Reply<rpc::SetQueueLimitsRpc> {
+        rpc_try!(
+            self.error_verbosity,
+            self.authorize::<rpc::SetQueueLimitsRpc>(
+                &request.device_id,
+                &request.signed_params(),
+                &request.options
+            )
+        );
+        let default_max_queue_len = request.default_max_queue_len;
+        let max_queue_len_limit = request.max_queue_len_limit;
+        let result = track!(self
+            .registry
+            .update_device_settings(&request.device_id, |s| {
+                s.default_max_queue_len = default_max_queue_len;
+                s.max_queue_len_limit = max_queue_len_limit;
+            }));
+        Reply::done(self.error_verbosity.apply(result))
+    }
+}
+impl HandleCall<rpc::SetWriteWatermarkRpc> for Server {
+    fn handle_call(
+        &self,
+        request: rpc::SetWriteWatermarkRequest,
+    ) -> Reply<rpc::SetWriteWatermarkRpc> {
+        rpc_try!(
+            self.error_verbosity,
+            self.authorize::<rpc::SetWriteWatermarkRpc>(
+                &request.device_id,
+                &request.signed_params(),
+                &request.options
+            )
+        );
+        let write_watermark = request.write_watermark;
+        if let Some(watermark) = write_watermark.filter(|&n| n > 100) {
+            let e = cannyls::ErrorKind::InvalidInput
+                .cause(format!("Too large write watermark: {}", watermark));
+            return Reply::done(
+                self.error_verbosity
+                    .apply(Err(track!(cannyls::Error::from(e)))),
+            );
+        }
+        let result = track!(self
+            .registry
+            .update_device_settings(&request.device_id, |s| s.write_watermark = write_watermark));
+        Reply::done(self.error_verbosity.apply(result))
+    }
+}
+impl HandleCall<rpc::SetLogLevelRpc> for Server {
+    fn handle_call(&self, level: Level) -> Reply<rpc::SetLogLevelRpc> {
+        rpc_try!(
+            self.error_verbosity,
+            self.check_server_wide_access(rpc::SetLogLevelRpc::NAME)
+        );
+        self.registry.set_log_level(level);
+        Reply::done(Ok(self.registry.log_level()))
+    }
+}
+impl HandleCall<rpc::ListInFlightRpc> for Server {
+    fn handle_call(&self, device_ids: Vec<DeviceId>) -> Reply<rpc::ListInFlightRpc> {
+        rpc_try!(
+            self.error_verbosity,
+            self.check_server_wide_access(rpc::ListInFlightRpc::NAME)
+        );
+        Reply::done(
+            self.error_verbosity
+                .apply(track!(self.in_flight.list(&device_ids))),
+        )
+    }
+}
+impl HandleCall<rpc::CancelInFlightRpc> for Server {
+    fn handle_call(&self, request_id: u64) -> Reply<rpc::CancelInFlightRpc> {
+        rpc_try!(
+            self.error_verbosity,
+            self.check_server_wide_access(rpc::CancelInFlightRpc::NAME)
+        );
+        let result = track!(self.in_flight.cancel(request_id));
+        if let Ok(true) = result {
+            info!(
+                self.registry.logger(),
+                "In-flight request {} was cancelled", request_id
+            );
+        }
+        Reply::done(self.error_verbosity.apply(result))
+    }
+}
+impl HandleCall<rpc::ResetRequestStatsRpc> for Server {
+    fn handle_call(&self, device_ids: Vec<DeviceId>) -> Reply<rpc::ResetRequestStatsRpc> {
+        rpc_try!(
+            self.error_verbosity,
+            self.check_server_wide_access(rpc::ResetRequestStatsRpc::NAME)
+        );
+        let result = track!(self.stats.reset(&device_ids));
+        if result.is_ok() {
+            info!(
+                self.registry.logger(),
+                "Request statistics were reset: devices={:?}", device_ids
+            );
+        }
+        Reply::done(self.error_verbosity.apply(result))
+    }
+}
+impl HandleCall<rpc::ProvisionDeviceRpc> for Server {
+    fn handle_call(&self, request: rpc::ProvisionDeviceRequest) -> Reply<rpc::ProvisionDeviceRpc> {
+        rpc_try!(
+            self.error_verbosity,
+            self.authorize::<rpc::ProvisionDeviceRpc>(
+                &request.spec.device_id,
+                &request.signed_params(),
+                &request.options
+            )
+        );
+        let spec = request.spec;
+        rpc_try!(self.error_verbosity, self.check_provision_capacity(&spec));
+        let rx = rpc_try!(self.error_verbosity, self.provision_worker.submit(spec));
+
+        let verbosity = self.error_verbosity;
+        let future = rx.then(move |result| {
+            let result = result.unwrap_or_else(|_| {
+                Err(track!(
+                    cannyls::ErrorKind::Other.cause("Provisioning thread terminated unexpectedly")
+                )
+                .into())
+            });
+            Ok(verbosity.apply(result))
+        });
         Reply::future(future)
     }
 }
+impl HandleCall<rpc::DeleteDeviceRpc> for Server {
+    fn handle_call(&self, request: rpc::DeviceRequest) -> Reply<rpc::DeleteDeviceRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        rpc_try!(
+            verbosity,
+            self.authorize::<rpc::DeleteDeviceRpc>(
+                &request.device_id,
+                &request.signed_params(),
+                &request.options
+            )
+        );
+        let device_id = request.device_id;
+
+        // NOTE: 削除コマンドは非同期に処理されるため、存在確認は送信前のスナップショットに基づいて行われる
+        let existed = self.registry.contains_device(&device_id);
+        let result = track!(self.registry.delete_device(device_id.clone())).map(|()| existed);
+        if let Ok(true) = result {
+            info!(
+                self.registry.logger(),
+                "Device {:?} was deregistered",
+                device_id.as_str()
+            );
+        }
+        Reply::done(verbosity.apply(result))
+    }
+}
+impl HandleCall<rpc::StopDeviceRpc> for Server {
+    fn handle_call(&self, request: rpc::DeviceRequest) -> Reply<rpc::StopDeviceRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        rpc_try!(
+            verbosity,
+            self.authorize::<rpc::StopDeviceRpc>(
+                &request.device_id,
+                &request.signed_params(),
+                &request.options
+            )
+        );
+        let device_id = request.device_id;
+        let deadline = request.options.deadline;
+
+        let existed = self.registry.contains_device(&device_id);
+        let result =
+            track!(self.registry.stop_device(device_id.clone(), deadline)).map(|()| existed);
+        if let Ok(true) = result {
+            info!(
+                self.registry.logger(),
+                "Stop command was issued to device {:?}: deadline={:?}",
+                device_id.as_str(),
+                deadline
+            );
+        }
+        Reply::done(verbosity.apply(result))
+    }
+}
+impl HandleCall<rpc::ScriptRpc> for Server {
+    fn handle_call(&self, mut request: rpc::ScriptRequest) -> Reply<rpc::ScriptRpc> {
+        let target = RequestTarget::Device;
+        let (device, guard) = rpc_try!(
+            self.error_verbosity_for(&request.options),
+            self.start_with_payload::<rpc::ScriptRpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &signing::script_payload(&request.ops),
+                &mut request.options
+            )
+        );
+        let options = request.options;
+        let server = self.clone();
+        let device_id = request.device_id;
+
+        // スクリプトの実行中は、対象の全lumpのロックを保持する
+        let lump_ids = request
+            .ops
+            .iter()
+            .map(script_op_lump_id)
+            .collect::<Vec<_>>();
+        let ops = request.ops;
+        let future = self
+            .lump_locks
+            .acquire_all(device_id.clone(), lump_ids)
+            .then(move |result| match result {
+                Err(e) => Either::A(future::ok(vec![Err(e)])),
+                Ok(lock_guards) => Either::B(
+                    execute_script(server, options, device, device_id, ops).map(move |results| {
+                        drop(lock_guards);
+                        results
+                    }),
+                ),
+            });
+        Reply::future(guard.wrap(future.map(Ok)))
+    }
+}
+
+// `ScriptRpc`の操作群を、先頭から順に実行する.
+//
+// いずれかの操作が失敗した場合には、それ以降の操作は実行されない.
+fn execute_script(
+    server: Server,
+    options: rpc::RequestOptions,
+    device: DeviceHandle,
+    device_id: DeviceId,
+    ops: Vec<ScriptOp>,
+) -> impl Future<Item = Vec<cannyls::Result<ScriptOpResult>>, Error = Never> + Send {
+    future::loop_fn(
+        (ops.into_iter(), Vec::new()),
+        move |(mut ops, mut results)| {
+            let op = if let Some(op) = ops.next() {
+                op
+            } else {
+                return Either::A(future::ok(Loop::Break(results)));
+            };
+            let writable = if let ScriptOp::Put(..) = op {
+                server.check_write_watermark(&device_id)
+            } else {
+                Ok(())
+            };
+            let (lump_id, size) = match op {
+                ScriptOp::Put(lump_id, ref lump_data) => (lump_id, lump_data.as_bytes().len()),
+                ScriptOp::Head(lump_id) | ScriptOp::Get(lump_id) | ScriptOp::Delete(lump_id) => {
+                    (lump_id, 0)
+                }
+            };
+            let observers = server.observers.clone();
+            let device_id = device_id.clone();
+            let future = execute_script_op(&options, &device, op, writable).then(move |result| {
+                if let Ok(Some(mutation)) = result
+                    .as_ref()
+                    .map(|r| script_op_mutation(lump_id, size, r))
+                {
+                    observers.notify(&device_id, &mutation);
+                }
+                let aborted = result.is_err();
+                results.push(result);
+                if aborted {
+                    Ok(Loop::Break(results))
+                } else {
+                    Ok(Loop::Continue((ops, results)))
+                }
+            });
+            Either::B(future)
+        },
+    )
+}
+
+impl HandleCall<rpc::ConcurrentScriptRpc> for Server {
+    fn handle_call(&self, mut request: rpc::ScriptRequest) -> Reply<rpc::ConcurrentScriptRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let target = RequestTarget::Device;
+        let (device, guard) = rpc_try!(
+            verbosity,
+            self.start_with_payload::<rpc::ConcurrentScriptRpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &signing::script_payload(&request.ops),
+                &mut request.options
+            )
+        );
+
+        // いずれかの操作が実行できない場合には、何も発行せずにリクエスト全体を失敗させる
+        let has_put = request.ops.iter().any(|op| matches!(op, ScriptOp::Put(..)));
+        if has_put {
+            if let Err(e) = track!(self.check_write_watermark(&request.device_id)) {
+                return Reply::future(guard.wrap(future::ok(Err(e))));
+            }
+        }
+        let mut ops = Vec::with_capacity(request.ops.len());
+        for op in request.ops {
+            let (op, lump_id, size) = match op {
+                ScriptOp::Put(lump_id, lump_data) => {
+                    let size = lump_data.as_bytes().len();
+                    match track!(to_device_lump_data(&device, lump_data)) {
+                        Err(e) => return Reply::future(guard.wrap(future::ok(Err(e)))),
+                        Ok(lump_data) => (ScriptOp::Put(lump_id, lump_data), lump_id, size),
+                    }
+                }
+                ScriptOp::Head(lump_id) | ScriptOp::Get(lump_id) | ScriptOp::Delete(lump_id) => {
+                    (op, lump_id, 0)
+                }
+            };
+            ops.push((op, lump_id, size));
+        }
+
+        // 対象の全lumpのロックを獲得した上で、全ての操作を完了を待たずに連続してデバイスに発行する
+        // (ロックは全ての操作の完了まで保持されるので、他の更新が操作の合間に割り込むことはない)
+        let options = request.options;
+        let device_id = request.device_id;
+        let observers = self.observers.clone();
+        let lump_ids = ops
+            .iter()
+            .map(|&(_, lump_id, _)| lump_id)
+            .collect::<Vec<_>>();
+        let future = self
+            .lump_locks
+            .acquire_all(device_id.clone(), lump_ids)
+            .and_then(move |lock_guards| {
+                let futures = ops
+                    .into_iter()
+                    .map(|(op, lump_id, size)| {
+                        let observers = observers.clone();
+                        let device_id = device_id.clone();
+                        execute_script_op(&options, &device, op, Ok(())).then(move |result| {
+                            if let Ok(Some(mutation)) = result
+                                .as_ref()
+                                .map(|r| script_op_mutation(lump_id, size, r))
+                            {
+                                observers.notify(&device_id, &mutation);
+                            }
+                            Ok(verbosity.apply(result))
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                future::join_all(futures).map(move |results| {
+                    drop(lock_guards);
+                    results
+                })
+            })
+            .then(Ok);
+        Reply::future(guard.wrap(future))
+    }
+}
+
+impl HandleCall<rpc::GetLumpsRpc> for Server {
+    fn handle_call(&self, mut request: rpc::GetLumpsRequest) -> Reply<rpc::GetLumpsRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let target = RequestTarget::Device;
+        let (device, guard) = rpc_try!(
+            verbosity,
+            self.start::<rpc::GetLumpsRpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &mut request.options
+            )
+        );
+        let options = request.options;
+        let futures = request
+            .lump_ids
+            .into_iter()
+            .map(|lump_id| {
+                options
+                    .with(&device)
+                    .get(lump_id)
+                    .then(move |result| Ok(verbosity.apply(result)))
+            })
+            .collect::<Vec<_>>();
+        Reply::future(guard.wrap(future::join_all(futures).map(Ok)))
+    }
+}
+impl HandleCall<rpc::PutLumpsRpc> for Server {
+    fn handle_call(&self, mut request: rpc::PutLumpsRequest) -> Reply<rpc::PutLumpsRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let target = RequestTarget::Device;
+        let (device, guard) = rpc_try!(
+            verbosity,
+            self.start_with_payload::<rpc::PutLumpsRpc>(
+                &request.device_id,
+                target,
+                &request.signed_params(),
+                &request
+                    .lumps
+                    .iter()
+                    .map(|(_, data)| data.as_bytes())
+                    .collect::<Vec<_>>(),
+                &mut request.options
+            )
+        );
+        if let Err(e) = track!(self.check_write_watermark(&request.device_id)) {
+            return Reply::future(guard.wrap(future::ok(Err(e))));
+        }
+
+        // 個々のlumpの保存は互いに独立しているので、対象の全lumpのロックを獲得した上で、
+        // コマンドはまとめてデバイスに発行してしまう
+        let options = request.options;
+        let device_id = request.device_id;
+        let observers = self.observers.clone();
+        let lumps = request.lumps;
+        let lump_ids = lumps
+            .iter()
+            .map(|&(lump_id, _)| lump_id)
+            .collect::<Vec<_>>();
+        let future = self
+            .lump_locks
+            .acquire_all(device_id.clone(), lump_ids)
+            .and_then(move |lock_guards| {
+                let futures = lumps
+                    .into_iter()
+                    .map(|(lump_id, lump_data)| {
+                        let size = lump_data.as_bytes().len();
+                        let future = match track!(to_device_lump_data(&device, lump_data)) {
+                            Err(e) => Either::A(future::err(e)),
+                            Ok(lump_data) => {
+                                Either::B(options.with(&device).put(lump_id, lump_data))
+                            }
+                        };
+                        let observers = observers.clone();
+                        let device_id = device_id.clone();
+                        future.then(move |result| {
+                            if let Ok(created) = result {
+                                let mutation = Mutation::Put {
+                                    lump_id,
+                                    size,
+                                    created,
+                                };
+                                observers.notify(&device_id, &mutation);
+                            }
+                            Ok(verbosity.apply(result))
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                future::join_all(futures).map(move |results| {
+                    drop(lock_guards);
+                    results
+                })
+            })
+            .then(Ok);
+        Reply::future(guard.wrap(future))
+    }
+}
+
+impl HandleCall<rpc::OpenImportSessionRpc> for Server {
+    fn handle_call(&self, request: rpc::DeviceRequest) -> Reply<rpc::OpenImportSessionRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        rpc_try!(
+            verbosity,
+            self.authorize::<rpc::OpenImportSessionRpc>(
+                &request.device_id,
+                &request.signed_params(),
+                &request.options
+            )
+        );
+        let owner = self
+            .verified_key_id(&request.options)
+            .map(ToOwned::to_owned);
+        let result = track!(self.registry.get_device(&request.device_id))
+            .and_then(|_| track!(self.imports.open(request.device_id, owner)));
+        if let Ok(ref status) = result {
+            info!(
+                self.registry.logger(),
+                "Import session {} was opened", status.session_id;
+                "device_id" => status.device_id.as_str()
+            );
+        }
+        Reply::done(verbosity.apply(result))
+    }
+}
+
+impl HandleCall<rpc::ImportLumpsRpc> for Server {
+    fn handle_call(&self, mut request: rpc::ImportLumpsRequest) -> Reply<rpc::ImportLumpsRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let session_id = request.session_id;
+        let payload = request
+            .lumps
+            .iter()
+            .map(|(_, data)| data.as_bytes())
+            .collect::<Vec<_>>();
+        let device_id = rpc_try!(
+            verbosity,
+            self.authorize_import_session::<rpc::ImportLumpsRpc>(
+                session_id,
+                &request.signed_params(),
+                &payload,
+                &request.options
+            )
+        );
+        let batch = match rpc_try!(
+            verbosity,
+            self.imports.start_batch(session_id, request.batch_seq)
+        ) {
+            None => {
+                // 処理済みのバッチの再送なので、何もせずに現在の状態を返す
+                let result = track!(self.imports.status(session_id));
+                return Reply::done(verbosity.apply(result));
+            }
+            Some(batch) => batch,
+        };
+
+        let target = RequestTarget::Device;
+        let (device, guard) = rpc_try!(
+            verbosity,
+            self.start_authorized::<rpc::ImportLumpsRpc>(
+                &device_id,
+                target,
+                &payload,
+                &mut request.options
+            )
+        );
+        if let Err(e) = track!(self.check_write_watermark(&device_id)) {
+            return Reply::future(guard.wrap(future::ok(Err(e))));
+        }
+
+        let options = request.options;
+        let lumps = request.lumps.len() as u64;
+        let bytes = request
+            .lumps
+            .iter()
+            .map(|(_, data)| data.as_bytes().len() as u64)
+            .sum();
+        let observers = self.observers.clone();
+        let batch_lumps = request.lumps;
+        let lump_ids = batch_lumps
+            .iter()
+            .map(|&(lump_id, _)| lump_id)
+            .collect::<Vec<_>>();
+        let future = self
+            .lump_locks
+            .acquire_all(device_id.clone(), lump_ids)
+            .and_then(move |lock_guards| {
+                let futures = batch_lumps
+                    .into_iter()
+                    .map(|(lump_id, lump_data)| {
+                        let size = lump_data.as_bytes().len();
+                        let future = match track!(to_device_lump_data(&device, lump_data)) {
+                            Err(e) => Either::A(future::err(e)),
+                            Ok(lump_data) => {
+                                Either::B(options.with(&device).put(lump_id, lump_data))
+                            }
+                        };
+                        let observers = observers.clone();
+                        let device_id = device_id.clone();
+                        future.map(move |created| {
+                            let mutation = Mutation::Put {
+                                lump_id,
+                                size,
+                                created,
+                            };
+                            observers.notify(&device_id, &mutation);
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                future::join_all(futures).then(move |result| {
+                    drop(lock_guards);
+                    result
+                })
+            });
+
+        // 失敗ないしキャンセルされた場合には`batch`がドロップされ、同じバッチの再送が可能となる
+        let future = future.then(move |result| {
+            track!(result)?;
+            track!(batch.finish(lumps, bytes))
+        });
+        Reply::future(guard.wrap(future.then(Ok)))
+    }
+}
+
+impl HandleCall<rpc::ImportSessionStatusRpc> for Server {
+    fn handle_call(
+        &self,
+        request: rpc::ImportSessionRequest,
+    ) -> Reply<rpc::ImportSessionStatusRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let session_id = request.session_id;
+        let result = track!(
+            self.authorize_import_session::<rpc::ImportSessionStatusRpc>(
+                session_id,
+                &request.signed_params(),
+                &[],
+                &request.options
+            )
+        )
+        .and_then(|_| track!(self.imports.status(session_id)));
+        Reply::done(verbosity.apply(result))
+    }
+}
+
+impl HandleCall<rpc::CommitImportSessionRpc> for Server {
+    fn handle_call(
+        &self,
+        request: rpc::ImportSessionRequest,
+    ) -> Reply<rpc::CommitImportSessionRpc> {
+        let verbosity = self.error_verbosity_for(&request.options);
+        let session_id = request.session_id;
+        let result = track!(
+            self.authorize_import_session::<rpc::CommitImportSessionRpc>(
+                session_id,
+                &request.signed_params(),
+                &[],
+                &request.options
+            )
+        )
+        .and_then(|_| track!(self.imports.close(session_id)));
+        if let Ok(ref status) = result {
+            info!(
+                self.registry.logger(),
+                "Import session {} was committed: lumps={}, bytes={}",
+                session_id,
+                status.imported_lumps,
+                status.imported_bytes;
+                "device_id" => status.device_id.as_str()
+            );
+        }
+        Reply::done(verbosity.apply(result))
+    }
+}
+
+// 事前条件が指定されている場合には、対象lumpのヘッダを取得して評価する.
+//
+// 評価と後続の操作は別々のコマンドとしてデバイスに発行されるため、
+// 呼び出し元は、その間に同じlumpへの更新が割り込まないように、対象lumpのロック(`LumpLocks`)を保持しておく必要がある.
+fn check_precondition(
+    device: &DeviceHandle,
+    options: &rpc::RequestOptions,
+    lump_id: LumpId,
+    precondition: Option<Precondition>,
+) -> Box<dyn Future<Item = (), Error = cannyls::Error> + Send> {
+    if let Some(precondition) = precondition {
+        let future = options
+            .with(device)
+            .head(lump_id)
+            .and_then(move |header| track!(precondition.check(header.as_ref())));
+        Box::new(future)
+    } else {
+        Box::new(future::ok(()))
+    }
+}
+
+fn execute_script_op(
+    options: &rpc::RequestOptions,
+    device: &DeviceHandle,
+    op: ScriptOp,
+    writable: cannyls::Result<()>,
+) -> Box<dyn Future<Item = ScriptOpResult, Error = cannyls::Error> + Send> {
+    let request = options.with(device);
+    match op {
+        ScriptOp::Head(lump_id) => Box::new(request.head(lump_id).map(ScriptOpResult::Head)),
+        ScriptOp::Get(lump_id) => Box::new(request.get(lump_id).map(ScriptOpResult::Get)),
+        ScriptOp::Put(lump_id, lump_data) => {
+            match track!(writable).and_then(|()| track!(to_device_lump_data(device, lump_data))) {
+                Err(e) => Box::new(future::err(e)),
+                Ok(lump_data) => Box::new(request.put(lump_id, lump_data).map(ScriptOpResult::Put)),
+            }
+        }
+        ScriptOp::Delete(lump_id) => Box::new(request.delete(lump_id).map(ScriptOpResult::Delete)),
+    }
+}
+
+// リクエストに関連するログレコード用に、対象を表すキー・値の組を付与したロガーを生成する.
+fn request_logger(
+    logger: &Logger,
+    procedure: &'static str,
+    device_id: &DeviceId,
+    target: &RequestTarget,
+) -> Logger {
+    let logger = logger.new(o!(
+        "procedure" => procedure,
+        "device_id" => device_id.as_str().to_owned()
+    ));
+    match *target {
+        RequestTarget::Device => logger,
+        RequestTarget::Lump(lump_id) => logger.new(o!("lump_id" => lump_id.to_string())),
+        RequestTarget::Range(ref range) => logger.new(o!(
+            "range_start" => range.start.to_string(),
+            "range_end" => range.end.to_string()
+        )),
+    }
+}
+
+// スクリプトの操作結果を、観測者に通知するための形式に変換する.
+//
+// 更新系ではない操作の場合には`None`が返される.
+// スクリプトの操作の対象のlumpのIDを返す.
+fn script_op_lump_id(op: &ScriptOp) -> LumpId {
+    match *op {
+        ScriptOp::Put(lump_id, _)
+        | ScriptOp::Head(lump_id)
+        | ScriptOp::Get(lump_id)
+        | ScriptOp::Delete(lump_id) => lump_id,
+    }
+}
+
+fn script_op_mutation(lump_id: LumpId, size: usize, result: &ScriptOpResult) -> Option<Mutation> {
+    match *result {
+        ScriptOpResult::Put(created) => Some(Mutation::Put {
+            lump_id,
+            size,
+            created,
+        }),
+        ScriptOpResult::Delete(deleted) => Some(Mutation::Delete { lump_id, deleted }),
+        ScriptOpResult::Head(_) | ScriptOpResult::Get(_) => None,
+    }
+}
+
+// `PutLumpRpc`のデコーダと同じ基準で、データをジャーナル領域に埋め込むかどうかを決定する.
+fn to_device_lump_data(device: &DeviceHandle, lump_data: LumpData) -> cannyls::Result<LumpData> {
+    if lump_data.as_bytes().len() <= protobuf::max_embedded_data_size(Some(device)) {
+        track!(LumpData::new_embedded(lump_data.into_bytes()))
+    } else {
+        track!(device.allocate_lump_data_with_bytes(lump_data.as_bytes()))
+    }
+}
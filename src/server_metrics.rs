@@ -0,0 +1,202 @@
+//! サーバ側でPrometheus形式で公開されるメトリクス.
+use cannyls::{Error, ErrorKind, Result};
+use prometrics::metrics::{Counter, Histogram, MetricBuilder};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// ハンドラの処理時間のヒストグラムのバケットの上限値(秒).
+const DURATION_BUCKETS: [f64; 14] = [
+    0.0005, 0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.2, 0.5, 1.0, 2.0, 5.0, 10.0,
+];
+
+// ペイロードサイズのヒストグラムのバケットの上限値(バイト).
+const PAYLOAD_BUCKETS: [f64; 10] = [
+    256.0,
+    1024.0,
+    4096.0,
+    16_384.0,
+    65_536.0,
+    262_144.0,
+    1_048_576.0,
+    4_194_304.0,
+    16_777_216.0,
+    67_108_864.0,
+];
+
+/// RPC単位の、サーバ側のメトリクス.
+///
+/// 登録されるメトリクスの一覧は`Server::metric_builder`を参照のこと.
+///
+/// インスタンスをクローンした場合には、同じメトリクスが共有される.
+#[derive(Debug, Clone)]
+pub(crate) struct ServerMetrics {
+    builder: MetricBuilder,
+    procedures: Arc<Mutex<HashMap<&'static str, ProcedureMetrics>>>,
+}
+impl ServerMetrics {
+    /// 指定のビルダを使ってメトリクスを登録する、新しい`ServerMetrics`インスタンスを生成する.
+    ///
+    /// メトリクスの名前空間とサブシステムには、ビルダの指定に関わらず`cannyls_rpc`と`server`が使用される.
+    pub fn new(builder: MetricBuilder) -> Self {
+        let mut builder = builder;
+        builder.namespace("cannyls_rpc").subsystem("server");
+        ServerMetrics {
+            builder,
+            procedures: Arc::default(),
+        }
+    }
+
+    /// 指定のRPCに対するリクエストの結果を記録するためのレコーダを返す.
+    ///
+    /// 処理時間は、このメソッドの呼び出し時点から計測される.
+    pub(crate) fn recorder(&self, procedure: &'static str) -> MetricsRecorder {
+        MetricsRecorder {
+            metrics: self.clone(),
+            procedure,
+            start_time: Instant::now(),
+        }
+    }
+
+    /// 指定のRPCで扱われたlumpデータのサイズを記録する.
+    pub(crate) fn observe_payload(&self, procedure: &'static str, size: usize) {
+        self.with_procedure(procedure, |m| m.payload_bytes.observe(size as f64));
+    }
+
+    fn with_procedure<F>(&self, procedure: &'static str, f: F)
+    where
+        F: FnOnce(&mut ProcedureMetrics),
+    {
+        let mut procedures = lock(&self.procedures);
+        let metrics = procedures
+            .entry(procedure)
+            .or_insert_with(|| ProcedureMetrics::new(&self.builder, procedure));
+        f(metrics);
+    }
+}
+
+/// 単一のリクエストの結果を記録するためのレコーダ.
+#[derive(Debug)]
+pub(crate) struct MetricsRecorder {
+    metrics: ServerMetrics,
+    procedure: &'static str,
+    start_time: Instant,
+}
+impl MetricsRecorder {
+    /// リクエストの結果を記録する.
+    pub fn record<T>(self, result: &Result<T>) {
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        let builder = self.metrics.builder.clone();
+        let procedure = self.procedure;
+        self.metrics.with_procedure(procedure, |m| {
+            m.requests.increment();
+            m.duration_seconds.observe(elapsed);
+            if let Err(ref e) = *result {
+                m.increment_errors(&builder, procedure, e);
+            }
+        });
+    }
+
+    /// リクエストが処理の開始前に失敗したことを記録する.
+    pub fn record_error(self, e: &Error) {
+        let builder = self.metrics.builder.clone();
+        let procedure = self.procedure;
+        self.metrics.with_procedure(procedure, |m| {
+            m.requests.increment();
+            m.increment_errors(&builder, procedure, e);
+        });
+    }
+}
+
+#[derive(Debug)]
+struct ProcedureMetrics {
+    requests: Counter,
+    errors: Vec<(ErrorKind, Counter)>,
+    duration_seconds: Histogram,
+    payload_bytes: Histogram,
+}
+impl ProcedureMetrics {
+    fn new(builder: &MetricBuilder, procedure: &str) -> Self {
+        ProcedureMetrics {
+            requests: builder
+                .counter("requests_total")
+                .help("Number of received requests")
+                .label("procedure", procedure)
+                .finish()
+                .expect("Never fails"),
+            errors: Vec::new(),
+            duration_seconds: builder
+                .histogram("handler_duration_seconds")
+                .help("Time taken to handle requests")
+                .label("procedure", procedure)
+                .buckets(DURATION_BUCKETS.iter().cloned())
+                .finish()
+                .expect("Never fails"),
+            payload_bytes: builder
+                .histogram("payload_bytes")
+                .help("Size of lump data in requests and responses")
+                .label("procedure", procedure)
+                .buckets(PAYLOAD_BUCKETS.iter().cloned())
+                .finish()
+                .expect("Never fails"),
+        }
+    }
+
+    fn increment_errors(&mut self, builder: &MetricBuilder, procedure: &str, e: &Error) {
+        let kind = *e.kind();
+        if let Some((_, counter)) = self.errors.iter().find(|(k, _)| *k == kind) {
+            counter.increment();
+            return;
+        }
+        let counter = builder
+            .counter("errors_total")
+            .help("Number of failed requests")
+            .label("procedure", procedure)
+            .label("kind", &format!("{:?}", kind))
+            .finish()
+            .expect("Never fails");
+        counter.increment();
+        self.errors.push((kind, counter));
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // 値の更新中にパニックすることはないので、ポイズンは無視して構わない
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use prometrics::Gatherer;
+    use trackable::error::ErrorKindExt;
+
+    use super::*;
+
+    #[test]
+    fn server_metrics_works() {
+        let mut gatherer = Gatherer::new();
+        let metrics = ServerMetrics::new(MetricBuilder::with_registry(gatherer.registry()));
+
+        metrics.recorder("foo").record(&Ok(()));
+        metrics
+            .recorder("foo")
+            .record::<()>(&Err(ErrorKind::DeviceBusy.into()));
+        metrics
+            .recorder("bar")
+            .record_error(&ErrorKind::InvalidInput.cause("foo").into());
+        metrics.observe_payload("foo", 1000);
+
+        let text = gatherer.gather().to_text();
+        assert!(text.contains(r#"cannyls_rpc_server_requests_total{procedure="foo"} 2"#));
+        assert!(text.contains(r#"cannyls_rpc_server_requests_total{procedure="bar"} 1"#));
+        assert!(text
+            .contains(r#"cannyls_rpc_server_errors_total{kind="DeviceBusy",procedure="foo"} 1"#));
+        assert!(text
+            .contains(r#"cannyls_rpc_server_errors_total{kind="InvalidInput",procedure="bar"} 1"#));
+        assert!(text
+            .contains(r#"cannyls_rpc_server_handler_duration_seconds_count{procedure="foo"} 2"#));
+        assert!(text
+            .contains(r#"cannyls_rpc_server_payload_bytes_bucket{le="1024",procedure="foo"} 1"#));
+        assert!(text.contains(r#"cannyls_rpc_server_payload_bytes_sum{procedure="foo"} 1000"#));
+    }
+}
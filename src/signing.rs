@@ -0,0 +1,980 @@
+//! HMAC-SHA256によるリクエストの署名.
+//!
+//! 署名の対象(エンベロープ)は、RPCの名前・対象デバイス・操作対象(`RequestTarget`)・パラメータ・
+//! ペイロードのダイジェスト・鍵のID・タイムスタンプ・ノンスからなる.
+//! パラメータは、操作対象以外のリクエストのフィールド(e.g., 事前条件、lump IDの列、スクリプトの操作種別)を
+//! 正規化したもの(`SignedParams`を参照).
+//! ペイロードのダイジェストは、リクエストに含まれるlumpデータ(コピー系のRPCの場合は、コピー先のデバイスID)を
+//! 長さ付きで連結したもののSHA-256で、ペイロードを持たないリクエストの場合には空の列に対するもの.
+use cannyls::lump::LumpId;
+#[cfg(feature = "server")]
+use cannyls::{ErrorKind, Result};
+#[cfg(feature = "client")]
+use fibers_rpc::Call;
+#[cfg(feature = "server")]
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+#[cfg(feature = "client")]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "server")]
+use std::sync::Mutex;
+#[cfg(feature = "server")]
+use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::device::{DeviceId, DeviceNvmSpec};
+use crate::info::RequestTarget;
+use crate::rpc::{self, RequestSignature};
+
+/// リクエストの署名に用いる共有鍵.
+///
+/// クライアントでは`Client::set_signing_key`で、サーバでは`SignatureVerifier::add_key`で使用される.
+///
+/// インスタンスをクローンした場合には、同じ鍵(およびノンスの系列)が共有される.
+#[derive(Clone)]
+pub struct SigningKey {
+    key_id: String,
+    secret: Arc<Vec<u8>>,
+    #[cfg(feature = "client")]
+    nonce: Arc<AtomicU64>,
+}
+impl SigningKey {
+    /// 新しい`SigningKey`インスタンスを生成する.
+    ///
+    /// `key_id`はサーバ側で鍵を選択するために、署名と共に平文で送信される.
+    pub fn new<T: Into<String>>(key_id: T, secret: &[u8]) -> Self {
+        SigningKey {
+            key_id: key_id.into(),
+            secret: Arc::new(secret.to_vec()),
+            #[cfg(feature = "client")]
+            nonce: Arc::new(AtomicU64::new(random_u64())),
+        }
+    }
+
+    /// 鍵のIDを返す.
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// リクエストに署名する.
+    ///
+    /// サーバ側で別のRPCとして処理されるRPC(e.g., `rpc::GetLumpWithMetaRpc`)の場合には、
+    /// `procedure`にはサーバ側での名前(e.g., `rpc::GetLumpRpc::NAME`)が使用される.
+    #[cfg(feature = "client")]
+    pub(crate) fn sign(
+        &self,
+        procedure: &str,
+        device_id: &DeviceId,
+        target: &RequestTarget,
+        params: &[u8],
+        payload: &[&[u8]],
+    ) -> RequestSignature {
+        let procedure = PROCEDURE_ALIASES
+            .iter()
+            .find(|(name, _)| *name == procedure)
+            .map_or(procedure, |(_, alias)| alias);
+        let mut signature = RequestSignature {
+            key_id: self.key_id.clone(),
+            timestamp: unix_millis(),
+            nonce: self.nonce.fetch_add(1, Ordering::SeqCst),
+            mac: Vec::new(),
+        };
+        signature.mac = self.mac(procedure, device_id, target, params, payload, &signature);
+        signature
+    }
+
+    fn mac(
+        &self,
+        procedure: &str,
+        device_id: &DeviceId,
+        target: &RequestTarget,
+        params: &[u8],
+        payload: &[&[u8]],
+        signature: &RequestSignature,
+    ) -> Vec<u8> {
+        let envelope = envelope(procedure, device_id, target, params, payload, signature);
+        hmac_sha256(&self.secret, &envelope).to_vec()
+    }
+}
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 秘密鍵は出力しない
+        f.debug_struct("SigningKey")
+            .field("key_id", &self.key_id)
+            .finish()
+    }
+}
+
+// サーバ側では別のRPCの名前で処理されるRPCの一覧.
+#[cfg(feature = "client")]
+const PROCEDURE_ALIASES: [(&str, &str); 6] = [
+    (rpc::GetLumpWithMetaRpc::NAME, rpc::GetLumpRpc::NAME),
+    (rpc::GetLumpToWriterRpc::NAME, rpc::GetLumpRpc::NAME),
+    (rpc::HeadLumpWithMetaRpc::NAME, rpc::HeadLumpRpc::NAME),
+    (rpc::PutLumpWithMetaRpc::NAME, rpc::PutLumpRpc::NAME),
+    (rpc::PutLumpFromReaderRpc::NAME, rpc::PutLumpRpc::NAME),
+    (rpc::DeleteLumpWithMetaRpc::NAME, rpc::DeleteLumpRpc::NAME),
+];
+
+/// サーバ側で、リクエストの署名を検証するためのもの.
+///
+/// `Server::verify_signatures`で使用される.
+///
+/// 署名のタイムスタンプとサーバの時刻との差が、許容幅(`replay_window`)を超えるリクエストは拒否される.
+/// また、許容幅の間は受理済みの署名が記憶され、同じ署名を持つリクエスト(i.e., 再送攻撃)も拒否される.
+///
+/// インスタンスをクローンした場合には、同じ鍵および受理済みの署名の集合が共有される.
+#[cfg(feature = "server")]
+#[derive(Debug, Clone)]
+pub struct SignatureVerifier {
+    keys: HashMap<String, SigningKey>,
+    replay_window: Duration,
+    seen: Arc<Mutex<BTreeSet<(u64, String, u64)>>>, // (タイムスタンプ, 鍵のID, ノンス)
+}
+#[cfg(feature = "server")]
+impl SignatureVerifier {
+    /// 新しい`SignatureVerifier`インスタンスを生成する.
+    ///
+    /// 鍵は`add_key`で登録する必要がある.
+    pub fn new(replay_window: Duration) -> Self {
+        SignatureVerifier {
+            keys: HashMap::new(),
+            replay_window,
+            seen: Arc::default(),
+        }
+    }
+
+    /// 署名の検証に用いる鍵を登録する.
+    ///
+    /// 同じIDの鍵が既に登録されている場合には、上書きされる.
+    /// 複数の鍵を登録しておくことで、鍵の切り替えを無停止で行うことができる.
+    pub fn add_key(&mut self, key: SigningKey) -> &mut Self {
+        self.keys.insert(key.key_id.clone(), key);
+        self
+    }
+
+    /// タイムスタンプの許容幅を返す.
+    pub fn replay_window(&self) -> Duration {
+        self.replay_window
+    }
+
+    /// リクエストの署名を検証する.
+    ///
+    /// 署名がない場合や、検証に失敗した場合には`ErrorKind::InvalidInput`が返される.
+    pub(crate) fn verify(
+        &self,
+        procedure: &str,
+        device_id: &DeviceId,
+        target: &RequestTarget,
+        params: &[u8],
+        payload: &[&[u8]],
+        signature: Option<&RequestSignature>,
+    ) -> Result<()> {
+        let signature = track_assert_some!(
+            signature,
+            ErrorKind::InvalidInput,
+            "The request is not signed"
+        );
+        let key = track_assert_some!(
+            self.keys.get(&signature.key_id),
+            ErrorKind::InvalidInput,
+            "Unknown signing key: {:?}",
+            signature.key_id
+        );
+        let expected = key.mac(procedure, device_id, target, params, payload, signature);
+        track_assert!(
+            constant_time_eq(&expected, &signature.mac),
+            ErrorKind::InvalidInput,
+            "Invalid request signature"
+        );
+
+        let now = unix_millis();
+        let window = self.replay_window.as_millis() as u64;
+        track_assert!(
+            now.abs_diff(signature.timestamp) <= window,
+            ErrorKind::InvalidInput,
+            "The request signature is out of the replay window: timestamp={}, now={}",
+            signature.timestamp,
+            now
+        );
+
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let expired = (now.saturating_sub(window), String::new(), 0);
+        *seen = seen.split_off(&expired);
+        let entry = (
+            signature.timestamp,
+            signature.key_id.clone(),
+            signature.nonce,
+        );
+        track_assert!(
+            seen.insert(entry),
+            ErrorKind::InvalidInput,
+            "The request signature has already been used"
+        );
+        Ok(())
+    }
+}
+
+/// 署名の対象となるリクエスト.
+#[cfg(feature = "client")]
+pub trait SignableRequest {
+    /// 署名の対象となるデバイスと操作対象を返す.
+    ///
+    /// 署名できない(ないし署名の対象外の)リクエストの場合には`None`が返される.
+    fn signing_target(&self) -> Option<(&DeviceId, RequestTarget)> {
+        None
+    }
+
+    /// 署名の対象となるパラメータを返す.
+    fn params(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// ペイロードを返す.
+    fn payload(&self) -> Vec<&[u8]> {
+        Vec::new()
+    }
+}
+#[cfg(feature = "client")]
+mod signable {
+    use super::*;
+
+    macro_rules! impl_signable {
+        ($request:ty, $target:expr) => {
+            impl SignableRequest for $request {
+                fn signing_target(&self) -> Option<(&DeviceId, RequestTarget)> {
+                    Some((&self.device_id, $target(self)))
+                }
+
+                fn params(&self) -> Vec<u8> {
+                    self.signed_params()
+                }
+            }
+        };
+    }
+    impl_signable!(rpc::DeviceRequest, |_| RequestTarget::Device);
+    impl_signable!(rpc::LumpRequest, |r: &rpc::LumpRequest| {
+        RequestTarget::Lump(r.lump_id)
+    });
+    impl_signable!(rpc::GetLumpRangeRequest, |r: &rpc::GetLumpRangeRequest| {
+        RequestTarget::Lump(r.lump_id)
+    });
+    impl_signable!(rpc::RangeLumpRequest, |r: &rpc::RangeLumpRequest| {
+        RequestTarget::Range(r.range.clone())
+    });
+    impl_signable!(rpc::UsageRangeRequest, |r: &rpc::UsageRangeRequest| {
+        RequestTarget::Range(r.range.clone())
+    });
+    impl_signable!(rpc::ExportLumpsRequest, |r: &rpc::ExportLumpsRequest| {
+        RequestTarget::Range(r.range.clone())
+    });
+    impl_signable!(
+        rpc::DeleteRangeBoundedRequest,
+        |r: &rpc::DeleteRangeBoundedRequest| RequestTarget::Range(r.range.clone())
+    );
+    impl_signable!(rpc::GetLumpsRequest, |_| RequestTarget::Device);
+    impl_signable!(rpc::SetJournalSyncRequest, |_| RequestTarget::Device);
+    impl_signable!(rpc::SetQueueLimitsRequest, |_| RequestTarget::Device);
+    impl_signable!(rpc::SetWriteWatermarkRequest, |_| RequestTarget::Device);
+
+    impl SignableRequest for rpc::ProvisionDeviceRequest {
+        fn signing_target(&self) -> Option<(&DeviceId, RequestTarget)> {
+            Some((&self.spec.device_id, RequestTarget::Device))
+        }
+
+        fn params(&self) -> Vec<u8> {
+            self.signed_params()
+        }
+    }
+
+    impl SignableRequest for rpc::PutLumpRequest {
+        fn signing_target(&self) -> Option<(&DeviceId, RequestTarget)> {
+            Some((&self.device_id, RequestTarget::Lump(self.lump_id)))
+        }
+
+        fn params(&self) -> Vec<u8> {
+            self.signed_params()
+        }
+
+        fn payload(&self) -> Vec<&[u8]> {
+            vec![self.lump_data.as_bytes()]
+        }
+    }
+    impl SignableRequest for rpc::PutLumpsRequest {
+        fn signing_target(&self) -> Option<(&DeviceId, RequestTarget)> {
+            Some((&self.device_id, RequestTarget::Device))
+        }
+
+        fn params(&self) -> Vec<u8> {
+            self.signed_params()
+        }
+
+        fn payload(&self) -> Vec<&[u8]> {
+            self.lumps.iter().map(|(_, data)| data.as_bytes()).collect()
+        }
+    }
+    impl SignableRequest for rpc::ScriptRequest {
+        fn signing_target(&self) -> Option<(&DeviceId, RequestTarget)> {
+            Some((&self.device_id, RequestTarget::Device))
+        }
+
+        fn params(&self) -> Vec<u8> {
+            self.signed_params()
+        }
+
+        fn payload(&self) -> Vec<&[u8]> {
+            script_payload(&self.ops)
+        }
+    }
+    impl SignableRequest for rpc::CopyRangeRequest {
+        fn signing_target(&self) -> Option<(&DeviceId, RequestTarget)> {
+            Some((
+                &self.source_device_id,
+                RequestTarget::Range(self.range.clone()),
+            ))
+        }
+
+        fn params(&self) -> Vec<u8> {
+            self.signed_params()
+        }
+
+        fn payload(&self) -> Vec<&[u8]> {
+            vec![self.destination_device_id.as_str().as_bytes()]
+        }
+    }
+
+    // インポートセッション経由のリクエストは、対象デバイスの代わりに`IMPORT_SESSION_DEVICE_ID`で署名される.
+    impl SignableRequest for rpc::ImportLumpsRequest {
+        fn signing_target(&self) -> Option<(&DeviceId, RequestTarget)> {
+            Some((&IMPORT_SESSION_DEVICE_ID, RequestTarget::Device))
+        }
+
+        fn params(&self) -> Vec<u8> {
+            self.signed_params()
+        }
+
+        fn payload(&self) -> Vec<&[u8]> {
+            self.lumps.iter().map(|(_, data)| data.as_bytes()).collect()
+        }
+    }
+    impl SignableRequest for rpc::ImportSessionRequest {
+        fn signing_target(&self) -> Option<(&DeviceId, RequestTarget)> {
+            Some((&IMPORT_SESSION_DEVICE_ID, RequestTarget::Device))
+        }
+
+        fn params(&self) -> Vec<u8> {
+            self.signed_params()
+        }
+    }
+
+    // lumpデータを読み込み元から順次送信するリクエストは、送信前にペイロードが定まらないので、署名できない.
+    impl SignableRequest for rpc::PutLumpFromReaderRequest {}
+
+    // 以下は、署名の対象外のリクエスト.
+    impl SignableRequest for () {}
+    impl SignableRequest for bool {}
+    impl SignableRequest for u64 {}
+    impl SignableRequest for slog::Level {}
+    impl SignableRequest for Vec<DeviceId> {}
+}
+
+/// インポートセッション経由のリクエスト(e.g., `rpc::ImportLumpsRpc`)の署名で、対象デバイスの代わりに使用されるID.
+///
+/// これらのリクエストはセッションのIDのみを伴い、クライアント側では対象デバイスが定まらないため.
+/// 対象デバイスは、セッションとその開始時の鍵の組によって、サーバ側で決定される.
+pub(crate) static IMPORT_SESSION_DEVICE_ID: DeviceId = DeviceId::empty();
+
+/// 署名の対象となる、操作対象(`RequestTarget`)とペイロード以外のリクエストのフィールド.
+///
+/// クライアントとサーバの双方で同じ正規化表現が得られるように、両者はこのトレイトを共有する.
+/// オプション(`rpc::RequestOptions`)のうち、デバイスでの処理内容に影響するもの(e.g., `journal_sync`)も対象となる.
+pub(crate) trait SignedParams {
+    /// パラメータの正規化表現を返す.
+    fn signed_params(&self) -> Vec<u8>;
+}
+macro_rules! impl_signed_params {
+    ($request:ty) => {
+        impl SignedParams for $request {
+            fn signed_params(&self) -> Vec<u8> {
+                let mut buf = Vec::new();
+                put_options(&mut buf, &self.options);
+                buf
+            }
+        }
+    };
+    ($request:ty, |$this:ident, $buf:ident| $body:expr) => {
+        impl SignedParams for $request {
+            fn signed_params(&self) -> Vec<u8> {
+                let $this = self;
+                let mut $buf = Vec::new();
+                put_options(&mut $buf, &$this.options);
+                $body;
+                $buf
+            }
+        }
+    };
+}
+impl_signed_params!(rpc::DeviceRequest);
+impl_signed_params!(rpc::LumpRequest, |this, buf| put_precondition(
+    &mut buf,
+    this.precondition.as_ref()
+));
+impl_signed_params!(rpc::PutLumpRequest, |this, buf| {
+    put_precondition(&mut buf, this.precondition.as_ref());
+    put_option_u64(&mut buf, this.checksum.map(u64::from));
+});
+impl_signed_params!(rpc::GetLumpRangeRequest, |this, buf| {
+    buf.extend_from_slice(&this.byte_range.start.to_be_bytes());
+    buf.extend_from_slice(&this.byte_range.end.to_be_bytes());
+});
+impl_signed_params!(rpc::RangeLumpRequest);
+impl_signed_params!(rpc::UsageRangeRequest);
+impl_signed_params!(rpc::CopyRangeRequest);
+impl_signed_params!(rpc::ExportLumpsRequest, |this, buf| buf
+    .extend_from_slice(&(this.max_lumps as u64).to_be_bytes()));
+impl_signed_params!(rpc::DeleteRangeBoundedRequest, |this, buf| buf
+    .extend_from_slice(&(this.max_lumps as u64).to_be_bytes()));
+impl_signed_params!(rpc::GetLumpsRequest, |this, buf| put_lump_ids(
+    &mut buf,
+    this.lump_ids.iter().cloned()
+));
+impl_signed_params!(rpc::PutLumpsRequest, |this, buf| put_lump_ids(
+    &mut buf,
+    this.lumps.iter().map(|(lump_id, _)| *lump_id)
+));
+impl_signed_params!(rpc::ImportLumpsRequest, |this, buf| {
+    buf.extend_from_slice(&this.session_id.to_be_bytes());
+    buf.extend_from_slice(&this.batch_seq.to_be_bytes());
+    put_lump_ids(&mut buf, this.lumps.iter().map(|(lump_id, _)| *lump_id));
+});
+impl_signed_params!(rpc::ImportSessionRequest, |this, buf| buf
+    .extend_from_slice(&this.session_id.to_be_bytes()));
+impl_signed_params!(rpc::ScriptRequest, |this, buf| {
+    buf.extend_from_slice(&(this.ops.len() as u64).to_be_bytes());
+    for op in &this.ops {
+        let (kind, lump_id) = match *op {
+            rpc::ScriptOp::Put(lump_id, _) => (0, lump_id),
+            rpc::ScriptOp::Head(lump_id) => (1, lump_id),
+            rpc::ScriptOp::Get(lump_id) => (2, lump_id),
+            rpc::ScriptOp::Delete(lump_id) => (3, lump_id),
+        };
+        buf.push(kind);
+        put_lump_id(&mut buf, lump_id);
+    }
+});
+impl_signed_params!(rpc::SetJournalSyncRequest, |this, buf| buf
+    .push(this.journal_sync as u8));
+impl_signed_params!(rpc::SetQueueLimitsRequest, |this, buf| {
+    put_option_u64(&mut buf, this.default_max_queue_len.map(|n| n as u64));
+    put_option_u64(&mut buf, this.max_queue_len_limit.map(|n| n as u64));
+});
+impl_signed_params!(rpc::SetWriteWatermarkRequest, |this, buf| put_option_u64(
+    &mut buf,
+    this.write_watermark.map(u64::from)
+));
+impl_signed_params!(rpc::ProvisionDeviceRequest, |this, buf| {
+    let spec = &this.spec;
+    match spec.nvm {
+        DeviceNvmSpec::Memory => buf.push(0),
+        DeviceNvmSpec::File(ref path) => {
+            buf.push(1);
+            put_bytes(&mut buf, path.to_string_lossy().as_bytes());
+        }
+    }
+    buf.extend_from_slice(&spec.capacity.to_be_bytes());
+    buf.extend_from_slice(&spec.block_size.as_u16().to_be_bytes());
+    buf.extend_from_slice(&(spec.labels.len() as u64).to_be_bytes());
+    for (key, value) in &spec.labels {
+        put_bytes(&mut buf, key.as_bytes());
+        put_bytes(&mut buf, value.as_bytes());
+    }
+});
+
+fn put_options(buf: &mut Vec<u8>, options: &rpc::RequestOptions) {
+    buf.push(options.prioritized as u8);
+    buf.push(options.journal_sync as u8);
+    put_option_u64(buf, options.max_queue_len.map(|n| n as u64));
+}
+
+fn put_precondition(buf: &mut Vec<u8>, precondition: Option<&rpc::Precondition>) {
+    if let Some(precondition) = precondition {
+        buf.push(1);
+        buf.push(precondition.if_exists as u8);
+        put_option_u64(buf, precondition.if_size_equals.map(u64::from));
+        buf.push(precondition.if_not_exists as u8);
+    } else {
+        buf.push(0);
+    }
+}
+
+fn put_option_u64(buf: &mut Vec<u8>, value: Option<u64>) {
+    if let Some(value) = value {
+        buf.push(1);
+        buf.extend_from_slice(&value.to_be_bytes());
+    } else {
+        buf.push(0);
+    }
+}
+
+fn put_lump_ids<I: ExactSizeIterator<Item = LumpId>>(buf: &mut Vec<u8>, lump_ids: I) {
+    buf.extend_from_slice(&(lump_ids.len() as u64).to_be_bytes());
+    for lump_id in lump_ids {
+        put_lump_id(buf, lump_id);
+    }
+}
+
+/// スクリプトのペイロード(`Put`操作のlumpデータ)を返す.
+pub(crate) fn script_payload(ops: &[rpc::ScriptOp]) -> Vec<&[u8]> {
+    ops.iter()
+        .filter_map(|op| match op {
+            rpc::ScriptOp::Put(_, data) => Some(data.as_bytes()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn envelope(
+    procedure: &str,
+    device_id: &DeviceId,
+    target: &RequestTarget,
+    params: &[u8],
+    payload: &[&[u8]],
+    signature: &RequestSignature,
+) -> Vec<u8> {
+    let mut digest = Sha256::new();
+    for data in payload {
+        digest.update(&(data.len() as u64).to_be_bytes());
+        digest.update(data);
+    }
+
+    let mut buf = Vec::new();
+    put_bytes(&mut buf, procedure.as_bytes());
+    put_bytes(&mut buf, device_id.as_str().as_bytes());
+    match *target {
+        RequestTarget::Device => buf.push(0),
+        RequestTarget::Lump(lump_id) => {
+            buf.push(1);
+            put_lump_id(&mut buf, lump_id);
+        }
+        RequestTarget::Range(ref range) => {
+            buf.push(2);
+            put_lump_id(&mut buf, range.start);
+            put_lump_id(&mut buf, range.end);
+        }
+    }
+    put_bytes(&mut buf, params);
+    buf.extend_from_slice(&digest.finish());
+    put_bytes(&mut buf, signature.key_id.as_bytes());
+    buf.extend_from_slice(&signature.timestamp.to_be_bytes());
+    buf.extend_from_slice(&signature.nonce.to_be_bytes());
+    buf
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn put_lump_id(buf: &mut Vec<u8>, lump_id: LumpId) {
+    buf.extend_from_slice(&lump_id.as_u128().to_be_bytes());
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(feature = "client")]
+fn random_u64() -> u64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    // `RandomState`はプロセス毎にランダムな鍵で初期化されるので、ノンスの初期値として利用できる
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(unix_millis());
+    hasher.finish()
+}
+
+#[cfg(feature = "server")]
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+
+    let mut block = [0; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&sha256(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(&block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let inner = inner.finish();
+
+    let mut outer = Sha256::new();
+    outer.update(&block.map(|b| b ^ 0x5c));
+    outer.update(&inner);
+    outer.finish()
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut digest = Sha256::new();
+    digest.update(data);
+    digest.finish()
+}
+
+// SHA-256(FIPS 180-4).
+struct Sha256 {
+    state: [u32; 8],
+    buf: Vec<u8>,
+    len: u64,
+}
+impl Sha256 {
+    const K: [u32; 64] = [
+        0x428a_2f98,
+        0x7137_4491,
+        0xb5c0_fbcf,
+        0xe9b5_dba5,
+        0x3956_c25b,
+        0x59f1_11f1,
+        0x923f_82a4,
+        0xab1c_5ed5,
+        0xd807_aa98,
+        0x1283_5b01,
+        0x2431_85be,
+        0x550c_7dc3,
+        0x72be_5d74,
+        0x80de_b1fe,
+        0x9bdc_06a7,
+        0xc19b_f174,
+        0xe49b_69c1,
+        0xefbe_4786,
+        0x0fc1_9dc6,
+        0x240c_a1cc,
+        0x2de9_2c6f,
+        0x4a74_84aa,
+        0x5cb0_a9dc,
+        0x76f9_88da,
+        0x983e_5152,
+        0xa831_c66d,
+        0xb003_27c8,
+        0xbf59_7fc7,
+        0xc6e0_0bf3,
+        0xd5a7_9147,
+        0x06ca_6351,
+        0x1429_2967,
+        0x27b7_0a85,
+        0x2e1b_2138,
+        0x4d2c_6dfc,
+        0x5338_0d13,
+        0x650a_7354,
+        0x766a_0abb,
+        0x81c2_c92e,
+        0x9272_2c85,
+        0xa2bf_e8a1,
+        0xa81a_664b,
+        0xc24b_8b70,
+        0xc76c_51a3,
+        0xd192_e819,
+        0xd699_0624,
+        0xf40e_3585,
+        0x106a_a070,
+        0x19a4_c116,
+        0x1e37_6c08,
+        0x2748_774c,
+        0x34b0_bcb5,
+        0x391c_0cb3,
+        0x4ed8_aa4a,
+        0x5b9c_ca4f,
+        0x682e_6ff3,
+        0x748f_82ee,
+        0x78a5_636f,
+        0x84c8_7814,
+        0x8cc7_0208,
+        0x90be_fffa,
+        0xa450_6ceb,
+        0xbef9_a3f7,
+        0xc671_78f2,
+    ];
+
+    fn new() -> Self {
+        Sha256 {
+            state: [
+                0x6a09_e667,
+                0xbb67_ae85,
+                0x3c6e_f372,
+                0xa54f_f53a,
+                0x510e_527f,
+                0x9b05_688c,
+                0x1f83_d9ab,
+                0x5be0_cd19,
+            ],
+            buf: Vec::with_capacity(64),
+            len: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+        if !self.buf.is_empty() {
+            let n = (64 - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.buf.len() < 64 {
+                return;
+            }
+            let block = std::mem::take(&mut self.buf);
+            self.compress(&block);
+        }
+        let mut chunks = data.chunks_exact(64);
+        for block in &mut chunks {
+            self.compress(block);
+        }
+        self.buf.extend_from_slice(chunks.remainder());
+    }
+
+    fn finish(mut self) -> [u8; 32] {
+        let bit_len = self.len.wrapping_mul(8);
+        let mut padding = vec![0x80];
+        let padded = (self.len + 1) % 64;
+        let zeros = if padded <= 56 {
+            56 - padded
+        } else {
+            120 - padded
+        };
+        padding.resize(1 + zeros as usize, 0);
+        padding.extend_from_slice(&bit_len.to_be_bytes());
+        self.update(&padding);
+        debug_assert!(self.buf.is_empty());
+
+        let mut digest = [0; 32];
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(&self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 64];
+        for (i, chunk) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for (k, w) in Self::K.iter().zip(&w) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(*k)
+                .wrapping_add(*w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (s, v) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(feature = "client", feature = "server"))]
+    fn options() -> rpc::RequestOptions {
+        rpc::RequestOptions {
+            deadline: cannyls::deadline::Deadline::Infinity,
+            max_queue_len: None,
+            prioritized: false,
+            journal_sync: false,
+            verbose_errors: false,
+            response_meta: false,
+            trace_id: None,
+            signature: None,
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn sha256_works() {
+        assert_eq!(
+            hex(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(&sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+
+        // 分割して入力した場合にも、同じ結果となる
+        let data = vec![0xab; 1000];
+        let mut digest = Sha256::new();
+        for chunk in data.chunks(7) {
+            digest.update(chunk);
+        }
+        assert_eq!(digest.finish(), sha256(&data));
+    }
+
+    #[test]
+    fn hmac_sha256_works() {
+        // RFC 4231のテストケース
+        assert_eq!(
+            hex(&hmac_sha256(&[0x0b; 20], b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex(&hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[cfg(all(feature = "client", feature = "server"))]
+    #[test]
+    fn signature_verifier_works() {
+        let key = SigningKey::new("foo", b"secret");
+        let mut verifier = SignatureVerifier::new(Duration::from_secs(60));
+        verifier.add_key(key.clone());
+
+        let device_id = DeviceId::new("dev");
+        let target = RequestTarget::Lump(LumpId::new(1));
+        let verify = |signature: &RequestSignature, payload: &[&[u8]]| {
+            verifier.verify("put", &device_id, &target, &[], payload, Some(signature))
+        };
+
+        let signature = key.sign("put", &device_id, &target, &[], &[b"data"]);
+        assert!(verify(&signature, &[b"data"]).is_ok());
+
+        // 再送
+        assert!(verify(&signature, &[b"data"]).is_err());
+
+        // 改竄
+        let signature = key.sign("put", &device_id, &target, &[], &[b"data"]);
+        assert!(verify(&signature, &[b"atad"]).is_err());
+        let mut forged = key.sign("put", &device_id, &target, &[], &[b"data"]);
+        forged.timestamp += 1;
+        assert!(verify(&forged, &[b"data"]).is_err());
+
+        // タイムスタンプが許容幅外
+        let mut stale = RequestSignature {
+            key_id: "foo".to_owned(),
+            timestamp: unix_millis() - 120_000,
+            nonce: 0,
+            mac: Vec::new(),
+        };
+        stale.mac = key.mac("put", &device_id, &target, &[], &[b"data"], &stale);
+        assert!(verify(&stale, &[b"data"]).is_err());
+
+        // 未知の鍵、ないし署名なし
+        let other = SigningKey::new("bar", b"secret");
+        let signature = other.sign("put", &device_id, &target, &[], &[b"data"]);
+        assert!(verify(&signature, &[b"data"]).is_err());
+        assert!(verifier
+            .verify("put", &device_id, &target, &[], &[], None)
+            .is_err());
+    }
+
+    #[cfg(all(feature = "client", feature = "server"))]
+    #[test]
+    fn signed_params_works() {
+        let key = SigningKey::new("foo", b"secret");
+        let mut verifier = SignatureVerifier::new(Duration::from_secs(60));
+        verifier.add_key(key.clone());
+
+        let device_id = DeviceId::new("dev");
+        let target = RequestTarget::Device;
+        let sign = |params: &[u8]| key.sign("get_lumps", &device_id, &target, params, &[]);
+        let verify = |params: &[u8], signature: &RequestSignature| {
+            verifier.verify(
+                "get_lumps",
+                &device_id,
+                &target,
+                params,
+                &[],
+                Some(signature),
+            )
+        };
+
+        // lump IDの改竄
+        let mut request = rpc::GetLumpsRequest {
+            device_id: device_id.clone(),
+            lump_ids: vec![LumpId::new(1), LumpId::new(2)],
+            options: options(),
+        };
+        let signature = sign(&request.signed_params());
+        request.lump_ids[1] = LumpId::new(3);
+        assert!(verify(&request.signed_params(), &signature).is_err());
+
+        // 事前条件の改竄
+        let mut request = rpc::LumpRequest {
+            device_id: device_id.clone(),
+            lump_id: LumpId::new(1),
+            options: options(),
+            precondition: Some(rpc::Precondition {
+                if_exists: false,
+                if_size_equals: Some(3),
+                if_not_exists: false,
+            }),
+        };
+        let signature = sign(&request.signed_params());
+        request.precondition = None;
+        assert!(verify(&request.signed_params(), &signature).is_err());
+
+        // スクリプトの操作種別の改竄
+        let mut request = rpc::ScriptRequest {
+            device_id: device_id.clone(),
+            ops: vec![rpc::ScriptOp::Get(LumpId::new(1))],
+            options: options(),
+        };
+        let signature = sign(&request.signed_params());
+        request.ops[0] = rpc::ScriptOp::Delete(LumpId::new(1));
+        assert!(verify(&request.signed_params(), &signature).is_err());
+
+        // 改竄されていなければ受理される
+        let signature = sign(&request.signed_params());
+        assert!(verify(&request.signed_params(), &signature).is_ok());
+    }
+}
@@ -0,0 +1,89 @@
+//! [`tracing`]クレート用のスパン(`tracing` featureが有効な場合のみ).
+//!
+//! サーバ側では、一つのRPC呼び出しに対して以下のスパンが作成される:
+//!
+//! - `cannyls_rpc.request`: RPCの受信から応答の生成までの全体
+//!   - `cannyls_rpc.dispatch`: 署名検証・アクセス制御・デバイスの検索等の前処理
+//!   - `cannyls_rpc.device`: デバイスへのコマンドの発行から完了まで(キューでの待機と実行の両方を含む)
+//!
+//! クライアント側では、一つの`Response`に対して`cannyls_rpc.client`スパンが作成される.
+//!
+//! なお、リクエストメッセージのデコードは`fibers_rpc`の内部で行われるため、スパンには含まれない.
+//!
+//! [`tracing`]: https://crates.io/crates/tracing
+use cannyls::Result;
+use std::time::Duration;
+use tracing::field;
+pub use tracing::Span;
+
+/// サーバ側のリクエスト全体を表すスパンを作成する.
+///
+/// `request_id`等の後から判明するフィールドは、`Span::record`で設定される.
+#[cfg(feature = "server")]
+pub fn server_span(procedure: &'static str, device_id: &str) -> Span {
+    tracing::info_span!(
+        "cannyls_rpc.request",
+        procedure,
+        device_id,
+        request_id = field::Empty,
+        trace_id = field::Empty,
+        elapsed_us = field::Empty,
+        error_kind = field::Empty,
+    )
+}
+
+/// 前処理を表すスパンを作成する.
+#[cfg(feature = "server")]
+pub fn dispatch_span(parent: &Span) -> Span {
+    tracing::debug_span!(parent: parent, "cannyls_rpc.dispatch")
+}
+
+/// デバイスでのコマンドの処理を表すスパンを作成する.
+///
+/// `queue_len`は、コマンドの発行時点でのデバイスのキューの長さ.
+#[cfg(feature = "server")]
+pub fn device_span(parent: &Span, queue_len: u64) -> Span {
+    tracing::debug_span!(parent: parent, "cannyls_rpc.device", queue_len)
+}
+
+/// クライアント側の呼び出しを表すスパンを作成する.
+#[cfg(feature = "client")]
+pub fn client_span(procedure: &'static str, server: std::net::SocketAddr) -> Span {
+    tracing::info_span!(
+        "cannyls_rpc.client",
+        procedure,
+        server = %server,
+        elapsed_us = field::Empty,
+        error_kind = field::Empty,
+    )
+}
+
+/// 呼び出しの結果をスパンに記録する.
+pub fn record_result<T>(span: &Span, result: &Result<T>, elapsed: Duration) {
+    span.record("elapsed_us", elapsed.as_micros() as u64);
+    if let Err(e) = result {
+        span.record("error_kind", field::debug(e.kind()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cannyls::ErrorKind;
+    use std::time::Duration;
+    use trackable::error::ErrorKindExt;
+
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn record_result_works() {
+        let span = server_span("PutLump", "dev0");
+        let e = ErrorKind::InvalidInput.error();
+        record_result(&span, &Err::<(), _>(e.into()), Duration::from_micros(10));
+        record_result(&span, &Ok(()), Duration::from_micros(20));
+
+        // サブスクライバが存在しない場合でも、子スパンの作成や記録で失敗しない
+        let _entered = dispatch_span(&span).entered();
+        let _ = device_span(&span, 3);
+    }
+}
@@ -0,0 +1,165 @@
+//! サーバ側で集計されるリクエストの統計情報.
+use cannyls::{ErrorKind, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use trackable::error::ErrorKindExt;
+
+use crate::device::DeviceId;
+use crate::info::RequestStats;
+
+/// デバイスおよびRPC単位の、リクエストの統計情報の集計器.
+///
+/// インスタンスをクローンした場合には、同じ集計結果が共有される.
+#[derive(Debug, Clone, Default)]
+pub struct RequestStatsCollector(Arc<Mutex<HashMap<(DeviceId, &'static str), Counter>>>);
+impl RequestStatsCollector {
+    /// 指定のデバイスおよびRPCに対するリクエストの結果を記録するためのレコーダを返す.
+    pub fn recorder(&self, procedure: &'static str, device_id: DeviceId) -> StatsRecorder {
+        StatsRecorder {
+            collector: self.clone(),
+            procedure,
+            device_id,
+        }
+    }
+
+    /// 統計情報を、デバイスIDおよびRPC名の昇順で返す.
+    ///
+    /// `device_ids`が空ではない場合には、それらのデバイスの統計情報のみが対象となる.
+    pub fn list(&self, device_ids: &[DeviceId]) -> Result<Vec<RequestStats>> {
+        let counters = track!(self
+            .0
+            .lock()
+            .map_err(|e| ErrorKind::Other.cause(e.to_string())))?;
+        let mut stats = counters
+            .iter()
+            .filter(|((id, _), _)| device_ids.is_empty() || device_ids.contains(id))
+            .map(|(&(ref id, procedure), c)| c.to_stats(id, procedure))
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| (&a.device_id, &a.procedure).cmp(&(&b.device_id, &b.procedure)));
+        Ok(stats)
+    }
+
+    /// 統計情報をリセットする.
+    ///
+    /// 結果としては、リセット直前の統計情報が`list`と同じ形式で返される.
+    pub fn reset(&self, device_ids: &[DeviceId]) -> Result<Vec<RequestStats>> {
+        let mut counters = track!(self
+            .0
+            .lock()
+            .map_err(|e| ErrorKind::Other.cause(e.to_string())))?;
+        let mut stats = Vec::new();
+        counters.retain(|&(ref id, procedure), c| {
+            if device_ids.is_empty() || device_ids.contains(id) {
+                stats.push(c.to_stats(id, procedure));
+                false
+            } else {
+                true
+            }
+        });
+        stats.sort_by(|a, b| (&a.device_id, &a.procedure).cmp(&(&b.device_id, &b.procedure)));
+        Ok(stats)
+    }
+
+    fn record<T>(&self, procedure: &'static str, device_id: &DeviceId, result: &Result<T>) {
+        if let Ok(mut counters) = self.0.lock() {
+            let counter = counters
+                .entry((device_id.clone(), procedure))
+                .or_insert_with(Counter::new);
+            match result {
+                Ok(_) => counter.succeeded += 1,
+                Err(e) => counter.increment_failed(*e.kind()),
+            }
+        }
+    }
+}
+
+/// 単一のリクエストの結果を記録するためのレコーダ.
+#[derive(Debug)]
+pub struct StatsRecorder {
+    collector: RequestStatsCollector,
+    procedure: &'static str,
+    device_id: DeviceId,
+}
+impl StatsRecorder {
+    /// リクエストの結果を記録する.
+    pub fn record<T>(self, result: &Result<T>) {
+        self.collector
+            .record(self.procedure, &self.device_id, result);
+    }
+}
+
+#[derive(Debug)]
+struct Counter {
+    succeeded: u64,
+    failed: Vec<(ErrorKind, u64)>,
+    start_time: Instant,
+}
+impl Counter {
+    fn new() -> Self {
+        Counter {
+            succeeded: 0,
+            failed: Vec::new(),
+            start_time: Instant::now(),
+        }
+    }
+
+    fn increment_failed(&mut self, kind: ErrorKind) {
+        if let Some(entry) = self.failed.iter_mut().find(|(k, _)| *k == kind) {
+            entry.1 += 1;
+        } else {
+            self.failed.push((kind, 1));
+        }
+    }
+
+    fn to_stats(&self, device_id: &DeviceId, procedure: &str) -> RequestStats {
+        RequestStats {
+            device_id: device_id.clone(),
+            procedure: procedure.to_owned(),
+            succeeded: self.succeeded,
+            failed: self.failed.clone(),
+            period: self.start_time.elapsed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_stats_collector_works() {
+        let collector = RequestStatsCollector::default();
+        let a = DeviceId::new("a");
+        let b = DeviceId::new("b");
+
+        collector.recorder("foo", a.clone()).record(&Ok(()));
+        collector.recorder("foo", a.clone()).record(&Ok(()));
+        collector
+            .recorder("bar", a.clone())
+            .record::<()>(&Err(ErrorKind::DeviceBusy.into()));
+        collector
+            .recorder("foo", b.clone())
+            .record::<()>(&Err(ErrorKind::InvalidInput.into()));
+        collector
+            .recorder("foo", b.clone())
+            .record::<()>(&Err(ErrorKind::InvalidInput.into()));
+
+        let stats = track_try_unwrap!(collector.list(&[]));
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats[0].procedure, "bar");
+        assert_eq!(stats[0].failed, vec![(ErrorKind::DeviceBusy, 1)]);
+        assert_eq!(stats[1].procedure, "foo");
+        assert_eq!(stats[1].succeeded, 2);
+        assert_eq!(stats[1].total(), 2);
+        assert_eq!(stats[2].device_id, b);
+        assert_eq!(stats[2].failed_total(), 2);
+
+        let stats = track_try_unwrap!(collector.reset(&[a]));
+        assert_eq!(stats.len(), 2);
+        let stats = track_try_unwrap!(collector.list(&[]));
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].device_id, b);
+        assert!(track_try_unwrap!(collector.list(&[DeviceId::new("a")])).is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+//! 結合テスト用のユーティリティ.
+//!
+//! メモリ上のデバイスを持つサーバを起動して、そのサーバに接続するクライアントを一度に構築できる.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate cannyls;
+//! # extern crate cannyls_rpc;
+//! use cannyls::lump::{LumpData, LumpId};
+//! use cannyls_rpc::testing::TestServer;
+//!
+//! let server = TestServer::start().unwrap();
+//! let request = server.client().request();
+//!
+//! let data = LumpData::new(b"foo".to_vec()).unwrap();
+//! let put = request.put_lump(server.device_id().clone(), LumpId::new(0), data);
+//! assert!(TestServer::wait(put).unwrap());
+//!
+//! let get = request.get_lump(server.device_id().clone(), LumpId::new(0));
+//! assert_eq!(TestServer::wait(get).unwrap(), Some(b"foo".to_vec()));
+//! ```
+use cannyls::device::DeviceBuilder;
+use cannyls::nvm::MemoryNvm;
+use cannyls::storage::StorageBuilder;
+use cannyls::{Error, ErrorKind, Result};
+use fibers::{Executor, InPlaceExecutor, Spawn};
+use fibers_rpc::client::ClientService;
+use fibers_rpc::server::ServerBuilder;
+use futures::{future, Async, Future};
+use slog::{Discard, Logger};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use trackable::error::ErrorKindExt;
+
+use crate::client::Client;
+use crate::device::DeviceId;
+use crate::registry::{DeviceRegistry, DeviceRegistryHandle};
+use crate::server::Server;
+
+/// `TestServerBuilder::device_id`のデフォルト値.
+pub const DEFAULT_DEVICE_ID: &str = "test";
+
+/// `TestServerBuilder::capacity`のデフォルト値.
+pub const DEFAULT_CAPACITY: usize = 32 * 1024 * 1024;
+
+/// デバイスの登録完了を待機する最大時間.
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Configure = Box<dyn FnOnce(&mut Server) + Send>;
+
+/// `TestServer`を構築するためのビルダ.
+pub struct TestServerBuilder {
+    logger: Logger,
+    device_id: DeviceId,
+    capacity: usize,
+    configure: Option<Configure>,
+}
+impl TestServerBuilder {
+    /// 新しい`TestServerBuilder`インスタンスを生成する.
+    pub fn new() -> Self {
+        TestServerBuilder {
+            logger: Logger::root(Discard, o!()),
+            device_id: DeviceId::new(DEFAULT_DEVICE_ID),
+            capacity: DEFAULT_CAPACITY,
+            configure: None,
+        }
+    }
+
+    /// デバイスレジストリおよびサーバが使用するロガーを設定する.
+    ///
+    /// デフォルトでは、ログは出力されない.
+    pub fn logger(&mut self, logger: Logger) -> &mut Self {
+        self.logger = logger;
+        self
+    }
+
+    /// 登録されるデバイスのIDを設定する.
+    ///
+    /// デフォルト値は`DEFAULT_DEVICE_ID`.
+    pub fn device_id(&mut self, device_id: DeviceId) -> &mut Self {
+        self.device_id = device_id;
+        self
+    }
+
+    /// 登録されるデバイスの容量(バイト単位)を設定する.
+    ///
+    /// デフォルト値は`DEFAULT_CAPACITY`.
+    pub fn capacity(&mut self, capacity: usize) -> &mut Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// RPCの登録前に、サーバの設定を変更するための関数を指定する.
+    ///
+    /// 管理用のRPCは、この関数の呼び出し前に有効化(`Server::enable_admin_rpc`)されている.
+    pub fn configure<F>(&mut self, f: F) -> &mut Self
+    where
+        F: FnOnce(&mut Server) + Send + 'static,
+    {
+        self.configure = Some(Box::new(f));
+        self
+    }
+
+    /// サーバを起動して、それに接続するクライアントを生成する.
+    ///
+    /// サーバはループバックアドレスの空きポートで待ち受け、それ用に生成されたスレッド上で動作し続ける.
+    /// この関数は、サーバが待ち受けを開始し、デバイスが登録されるまでブロックする.
+    pub fn start(&mut self) -> Result<TestServer> {
+        let executor = track!(InPlaceExecutor::new().map_err(Error::from))?;
+
+        // Device Registry
+        let registry = DeviceRegistry::new(self.logger.clone());
+        let registry_handle = registry.handle();
+        let nvm = MemoryNvm::new(vec![0; self.capacity]);
+        let storage = track!(StorageBuilder::new().create(nvm))?;
+        let storage_metrics = storage.metrics().clone();
+        let device = DeviceBuilder::new().spawn(|| Ok(storage));
+        track!(registry_handle.put_device_with_storage_metrics(
+            self.device_id.clone(),
+            device,
+            storage_metrics
+        ))?;
+        let logger = self.logger.clone();
+        executor.spawn(registry.map_err(move |e| {
+            error!(logger, "Device registry terminated abnormally: {}", e);
+        }));
+
+        // Server
+        let mut builder = ServerBuilder::new(([127, 0, 0, 1], 0).into());
+        builder.logger(self.logger.clone());
+        let mut server = Server::new(registry_handle.clone());
+        server.enable_admin_rpc();
+        if let Some(configure) = self.configure.take() {
+            configure(&mut server);
+        }
+        server.register(&mut builder);
+        let mut server = builder.finish(executor.handle());
+        let (addr_tx, addr_rx) = mpsc::channel();
+        let mut addr_tx = Some(addr_tx);
+        let logger = self.logger.clone();
+        let future = future::poll_fn(move || {
+            // 待ち受けを開始したら、そのアドレスを通知する
+            if let Some(tx) = addr_tx.take() {
+                if let Async::Ready(addr) = track!(server.poll_local_addr())? {
+                    let _ = tx.send(addr);
+                } else {
+                    addr_tx = Some(tx);
+                    return Ok(Async::NotReady);
+                }
+            }
+            track!(server.poll())
+        });
+        executor.spawn(future.map_err(move |e: fibers_rpc::Error| {
+            error!(logger, "RPC server terminated abnormally: {}", e);
+        }));
+
+        // RPC service
+        let service = ClientService::new(executor.handle());
+        let service_handle = service.handle();
+        let logger = self.logger.clone();
+        executor.spawn(service.map_err(move |e| {
+            error!(logger, "RPC client service terminated abnormally: {}", e);
+        }));
+
+        thread::spawn(move || {
+            let _ = executor.run();
+        });
+
+        let server_addr = track!(addr_rx.recv_timeout(REGISTRATION_TIMEOUT).map_err(|_| {
+            Error::from(ErrorKind::Other.cause("RPC server failed to start listening"))
+        }))?;
+        track!(wait_for_device(&registry_handle, &self.device_id))?;
+
+        Ok(TestServer {
+            client: Client::new(server_addr, service_handle),
+            registry: registry_handle,
+            device_id: self.device_id.clone(),
+        })
+    }
+}
+impl Default for TestServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl fmt::Debug for TestServerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "TestServerBuilder {{ device_id: {:?}, capacity: {} }}",
+            self.device_id, self.capacity
+        )
+    }
+}
+
+/// テスト用に起動されたサーバと、それに接続するクライアント.
+///
+/// サーバはテストプロセスの終了まで動作し続ける.
+#[derive(Debug, Clone)]
+pub struct TestServer {
+    client: Client,
+    registry: DeviceRegistryHandle,
+    device_id: DeviceId,
+}
+impl TestServer {
+    /// デフォルト設定でサーバを起動する.
+    ///
+    /// `TestServerBuilder::new().start()`と等価.
+    pub fn start() -> Result<Self> {
+        track!(TestServerBuilder::new().start())
+    }
+
+    /// サーバに接続するクライアントを返す.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// サーバのアドレスを返す.
+    pub fn server_addr(&self) -> SocketAddr {
+        self.client.server()
+    }
+
+    /// サーバが使用しているデバイスレジストリのハンドルを返す.
+    pub fn registry(&self) -> &DeviceRegistryHandle {
+        &self.registry
+    }
+
+    /// 登録されているデバイスのIDを返す.
+    pub fn device_id(&self) -> &DeviceId {
+        &self.device_id
+    }
+
+    /// `future`の完了を、現在のスレッドをブロックして待機する.
+    pub fn wait<F: Future>(mut future: F) -> std::result::Result<F::Item, F::Error> {
+        loop {
+            if let Async::Ready(item) = future.poll()? {
+                return Ok(item);
+            }
+            thread::yield_now();
+        }
+    }
+}
+
+fn wait_for_device(registry: &DeviceRegistryHandle, device_id: &DeviceId) -> Result<()> {
+    let interval = Duration::from_millis(10);
+    let mut elapsed = Duration::default();
+    while !registry.contains_device(device_id) {
+        track_assert!(
+            elapsed < REGISTRATION_TIMEOUT,
+            ErrorKind::Other,
+            "Device {:?} was not registered",
+            device_id
+        );
+        thread::sleep(interval);
+        elapsed += interval;
+    }
+    Ok(())
+}
@@ -1,25 +1,45 @@
+extern crate bytecodec;
 extern crate cannyls;
 extern crate cannyls_rpc;
 extern crate fibers;
 extern crate fibers_rpc;
 extern crate futures;
+extern crate prometrics;
 #[macro_use]
 extern crate slog;
 extern crate tempdir;
 #[macro_use]
 extern crate trackable;
 
-use cannyls::device::DeviceBuilder;
-use cannyls::lump::{LumpData, LumpId};
-use cannyls::nvm::MemoryNvm;
+use bytecodec::bytes::{BytesEncoder, RemainingBytesDecoder};
+use cannyls::device::{DeviceBuilder, DeviceStatus};
+use cannyls::nvm::{FileNvm, MemoryNvm};
 use cannyls::storage::StorageBuilder;
-use cannyls_rpc::{Client, DeviceId, DeviceRegistry, Server};
+use cannyls_rpc::rpc::{self, DeviceRequest};
+use cannyls_rpc::testing::{self, TestServer, TestServerBuilder};
+use cannyls_rpc::{
+    provision, AccessControl, BalancePolicy, CallContext, CallOptions, CircuitBreakerPolicy,
+    Client, ClientError, ClientInterceptor, ClientMetrics, Deadline, DeviceId, DeviceLabels,
+    DeviceRegistry, DeviceRegistryHandle, DeviceSpec, ErrorKind, ErrorVerbosity, LumpData, LumpId,
+    Mutation, MutationObserver, Permissions, ProcedureConfig, RecordedRequest, RegistryEvent,
+    ReplaySummary, Replayer, ReplicaSet, RequestContext, Router, ScriptOp, ScriptOpResult, Server,
+    ServerInterceptor, SignatureVerifier, SigningKey, TrafficRecorder,
+};
 use fibers::{Executor, InPlaceExecutor, Spawn};
 use fibers_rpc::client::ClientService;
-use fibers_rpc::server::ServerBuilder;
-use futures::{Async, Future};
-use slog::{Discard, Logger};
+use fibers_rpc::server::{HandleCall, Reply, ServerBuilder};
+use fibers_rpc::{Call, ProcedureId};
+use futures::{Async, AsyncSink, Future, Sink, Stream};
+use prometrics::metrics::MetricBuilder;
+use prometrics::Gatherer;
+use slog::{Discard, Drain, Level, Logger, Never, OwnedKVList, Record, KV};
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
+use tempdir::TempDir;
 
 macro_rules! wait {
     ($future:expr) => {{
@@ -32,7 +52,87 @@ macro_rules! wait {
     }};
 }
 
+macro_rules! wait_err {
+    ($future:expr) => {{
+        let mut f = $future;
+        loop {
+            match f.poll() {
+                Err(e) => break e,
+                Ok(Async::Ready(_)) => panic!("Unexpected success"),
+                Ok(Async::NotReady) => {}
+            }
+        }
+    }};
+}
+
+fn device_id() -> DeviceId {
+    DeviceId::new("foo")
+}
+
+fn lump_id(n: u128) -> LumpId {
+    LumpId::new(n)
+}
+
+fn start_server(port: u16) -> Client {
+    start_server_with(port, |server, builder| server.register(builder))
+}
+
+fn start_server_with<F>(port: u16, register: F) -> Client
+where
+    F: FnOnce(Server, &mut ServerBuilder),
+{
+    start_server_with_logger(port, Logger::root(Discard, o!()), register)
+}
+
+fn start_server_with_logger<F>(port: u16, logger: Logger, register: F) -> Client
+where
+    F: FnOnce(Server, &mut ServerBuilder),
+{
+    // Executor
+    let executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+
+    // Device Registry
+    let registry = DeviceRegistry::new(logger);
+    let registry_handle = registry.handle();
+
+    let nvm = MemoryNvm::new(vec![0; 16 * 1024 * 1024]);
+    let storage = track_try_unwrap!(StorageBuilder::new().create(nvm));
+    let storage_metrics = storage.metrics().clone();
+    let device = DeviceBuilder::new().spawn(|| Ok(storage));
+    track_try_unwrap!(registry_handle.put_device_with_storage_metrics(
+        device_id(),
+        device,
+        storage_metrics
+    ));
+
+    executor.spawn(registry.map_err(|e| panic!("{}", e)));
+
+    // Server
+    let server_addr = format!("127.0.0.1:{}", port).parse().unwrap();
+    let mut builder = ServerBuilder::new(server_addr);
+    let mut server = Server::new(registry_handle);
+    server.enable_admin_rpc();
+    register(server, &mut builder);
+    let server = builder.finish(executor.handle());
+    executor.spawn(server.map_err(|e| panic!("{}", e)));
+
+    // RPC service
+    let service = ClientService::new(executor.handle());
+    let service_handle = service.handle();
+    executor.spawn(service.map_err(|e| panic!("{}", e)));
+
+    thread::spawn(move || {
+        if let Err(e) = executor.run() {
+            panic!("{}", e);
+        }
+    });
+
+    // Client
+    Client::new(server_addr, service_handle)
+}
+
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn basic_rpc_works() {
     fn device_id() -> DeviceId {
         DeviceId::new("foo")
@@ -99,3 +199,3805 @@ fn basic_rpc_works() {
     assert_eq!(wait!(request.delete_lump(device_id(), lump_id(0))), true);
     assert_eq!(wait!(request.list_lumps(device_id())), vec![]);
 }
+
+#[test]
+fn exists_lump_works() {
+    let client = start_server(2010);
+    let request = client.request();
+    assert!(!wait!(request.exists_lump(device_id(), lump_id(0))));
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        LumpData::new("bar".into()).unwrap()
+    )));
+    assert!(wait!(request.exists_lump(device_id(), lump_id(0))));
+    assert!(wait!(request.delete_lump(device_id(), lump_id(0))));
+    assert!(!wait!(request.exists_lump(device_id(), lump_id(0))));
+}
+
+#[test]
+fn script_rpc_works() {
+    let client = start_server(1921);
+    let request = client.request();
+
+    let ops = vec![
+        ScriptOp::Put(lump_id(1), LumpData::new("foo".into()).unwrap()),
+        ScriptOp::Head(lump_id(1)),
+        ScriptOp::Get(lump_id(1)),
+        ScriptOp::Delete(lump_id(1)),
+        ScriptOp::Get(lump_id(1)),
+    ];
+    let results = wait!(request.execute_script(device_id(), ops));
+    assert_eq!(results.len(), 5);
+    assert!(matches!(results[0], Ok(ScriptOpResult::Put(true))));
+    assert!(matches!(
+        results[1],
+        Ok(ScriptOpResult::Head(Some(ref h))) if h.approximate_data_size == 3
+    ));
+    assert!(matches!(
+        results[2],
+        Ok(ScriptOpResult::Get(Some(ref d))) if d.as_bytes() == b"foo"
+    ));
+    assert!(matches!(results[3], Ok(ScriptOpResult::Delete(true))));
+    assert!(matches!(results[4], Ok(ScriptOpResult::Get(None))));
+}
+
+#[test]
+fn concurrent_script_works() {
+    let client = start_server(1962);
+    let request = client.request();
+    let data = |s: &str| LumpData::new(s.into()).unwrap();
+
+    let ops = vec![
+        ScriptOp::Put(lump_id(1), data("foo")),
+        ScriptOp::Delete(lump_id(2)),
+        ScriptOp::Put(lump_id(1), data("bar")),
+        ScriptOp::Get(lump_id(1)),
+        ScriptOp::Delete(lump_id(1)),
+        ScriptOp::Put(lump_id(3), data("baz")),
+    ];
+    let results = wait!(request.execute_concurrent_script(device_id(), ops));
+    assert_eq!(results.len(), 6);
+    assert!(matches!(results[0], Ok(ScriptOpResult::Put(true))));
+    assert!(matches!(results[1], Ok(ScriptOpResult::Delete(false))));
+    assert!(matches!(results[2], Ok(ScriptOpResult::Put(false))));
+    assert!(matches!(
+        results[3],
+        Ok(ScriptOpResult::Get(Some(ref d))) if d.as_bytes() == b"bar"
+    ));
+    assert!(matches!(results[4], Ok(ScriptOpResult::Delete(true))));
+    assert!(matches!(results[5], Ok(ScriptOpResult::Put(true))));
+    assert_eq!(wait!(request.list_lumps(device_id())), vec![lump_id(3)]);
+
+    // 書き込みが拒否される場合には、いずれの操作も実行されない
+    let large_data = LumpData::new(vec![0; 2 * 1024 * 1024]).unwrap();
+    assert!(wait!(request.put_lump(device_id(), lump_id(0), large_data)));
+    wait!(request.set_write_watermark(device_id(), Some(1)));
+    let ops = vec![
+        ScriptOp::Delete(lump_id(3)),
+        ScriptOp::Put(lump_id(4), data("qux")),
+    ];
+    let e = wait_err!(request.execute_concurrent_script(device_id(), ops));
+    assert_eq!(*e.kind(), ErrorKind::StorageFull);
+    assert_eq!(
+        wait!(request.list_lumps(device_id())),
+        vec![lump_id(0), lump_id(3)]
+    );
+}
+
+#[test]
+fn put_lumps_works() {
+    let client = start_server(1944);
+    let request = client.request();
+    let data = |s: &str| LumpData::new(s.into()).unwrap();
+
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        data("foo")
+    )));
+
+    let lumps = vec![
+        (lump_id(0), data("bar")),
+        (lump_id(1), data("baz")),
+        (lump_id(2), data("qux")),
+    ];
+    let results = wait!(request.put_lumps(device_id(), lumps));
+    assert_eq!(results.len(), 3);
+    assert!(matches!(results[0], Ok(false)));
+    assert!(matches!(results[1], Ok(true)));
+    assert!(matches!(results[2], Ok(true)));
+
+    assert_eq!(
+        wait!(request.list_lumps(device_id())),
+        vec![lump_id(0), lump_id(1), lump_id(2)]
+    );
+    assert_eq!(
+        wait!(request.get_lump(device_id(), lump_id(0))),
+        Some(b"bar".to_vec())
+    );
+    assert_eq!(
+        wait!(request.get_lump(device_id(), lump_id(2))),
+        Some(b"qux".to_vec())
+    );
+
+    // 存在しないデバイス
+    let e = wait_err!(request.put_lumps(DeviceId::new("bar"), vec![(lump_id(3), data("a"))]));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn get_lumps_works() {
+    let client = start_server(1945);
+    let request = client.request();
+    let data = |s: &str| LumpData::new(s.into()).unwrap();
+
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        data("foo")
+    )));
+    assert!(wait!(request.put_lump(device_id(), lump_id(2), data(""))));
+
+    let lump_ids = vec![lump_id(2), lump_id(1), lump_id(0), lump_id(0)];
+    let results = wait!(request.get_lumps(device_id(), lump_ids));
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0].as_ref().ok(), Some(&Some(Vec::new())));
+    assert_eq!(results[1].as_ref().ok(), Some(&None));
+    assert_eq!(results[2].as_ref().ok(), Some(&Some(b"foo".to_vec())));
+    assert_eq!(results[3].as_ref().ok(), Some(&Some(b"foo".to_vec())));
+
+    assert!(wait!(request.get_lumps(device_id(), Vec::new())).is_empty());
+
+    // 存在しないデバイス
+    let e = wait_err!(request.get_lumps(DeviceId::new("bar"), vec![lump_id(0)]));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn list_lumps_range_works() {
+    let client = start_server(1946);
+    let request = client.request();
+    for i in 0..10 {
+        let data = LumpData::new(vec![i as u8]).unwrap();
+        assert!(wait!(request.put_lump(device_id(), lump_id(i), data)));
+    }
+
+    assert_eq!(
+        wait!(request.list_lumps_range(device_id(), lump_id(3)..lump_id(6))),
+        vec![lump_id(3), lump_id(4), lump_id(5)]
+    );
+    assert_eq!(
+        wait!(request.list_lumps_range(device_id(), lump_id(8)..lump_id(100))),
+        vec![lump_id(8), lump_id(9)]
+    );
+    assert_eq!(
+        wait!(request.list_lumps_range(device_id(), lump_id(20)..lump_id(30))),
+        vec![]
+    );
+
+    // 存在しないデバイス
+    let e = wait_err!(request.list_lumps_range(DeviceId::new("bar"), lump_id(0)..lump_id(1)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn scrub_range_works() {
+    let client = start_server(1953);
+    let request = client.request();
+    for i in 0..5 {
+        // 埋め込みLumpとデータ領域に保存されるLumpの両方を対象にする
+        let data = LumpData::new(vec![i as u8; 1 + i as usize * 300]).unwrap();
+        assert!(wait!(request.put_lump(device_id(), lump_id(i), data)));
+    }
+
+    let failed = wait!(request.scrub_range(device_id(), lump_id(0)..lump_id(100)));
+    assert!(failed.is_empty());
+
+    let failed = wait!(request.scrub_range(device_id(), lump_id(20)..lump_id(30)));
+    assert!(failed.is_empty());
+
+    // 存在しないデバイス
+    let e = wait_err!(request.scrub_range(DeviceId::new("bar"), lump_id(0)..lump_id(1)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn precondition_works() {
+    let client = start_server(1922);
+    let data = || LumpData::new("bar".into()).unwrap();
+
+    let e = wait_err!(client
+        .request()
+        .if_exists()
+        .put_lump(device_id(), lump_id(0), data()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    assert!(wait!(client.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data()
+    )));
+    assert!(!wait!(client
+        .request()
+        .if_exists()
+        .if_size_equals(3)
+        .put_lump(device_id(), lump_id(0), data())));
+
+    let e = wait_err!(client
+        .request()
+        .if_size_equals(10)
+        .delete_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert!(wait!(client
+        .request()
+        .if_size_equals(3)
+        .delete_lump(device_id(), lump_id(0))));
+}
+
+#[test]
+fn get_lump_range_works() {
+    let client = start_server(1961);
+    let request = client.request();
+
+    let data = LumpData::new(b"0123456789".to_vec()).unwrap();
+    assert!(wait!(request.put_lump(device_id(), lump_id(0), data)));
+
+    let range = |r| wait!(request.get_lump_range(device_id(), lump_id(0), r));
+    assert_eq!(range(0..4), Some(b"0123".to_vec()));
+    assert_eq!(range(3..6), Some(b"345".to_vec()));
+    assert_eq!(range(8..100), Some(b"89".to_vec()));
+    assert_eq!(range(20..30), Some(Vec::new()));
+    assert_eq!(
+        wait!(request.get_lump_range(device_id(), lump_id(1), 0..4)),
+        None
+    );
+
+    let reversed = Range { start: 5, end: 1 };
+    let e = wait_err!(request.get_lump_range(device_id(), lump_id(0), reversed));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn put_lump_noack_works() {
+    let client = start_server(1963);
+    let request = client.request();
+
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    request
+        .put_lump_noack(device_id(), lump_id(0), data)
+        .unwrap();
+
+    // 保存の完了は通知されないので、反映されるまで待つ
+    let mut stored = None;
+    for _ in 0..100 {
+        stored = wait!(request.get_lump(device_id(), lump_id(0)));
+        if stored.is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(stored, Some(b"foo".to_vec()));
+}
+
+#[test]
+fn delete_lump_noack_works() {
+    let client = start_server(1964);
+    let request = client.request();
+
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(request.put_lump(device_id(), lump_id(0), data)));
+    request.delete_lump_noack(device_id(), lump_id(0)).unwrap();
+
+    // 削除の完了は通知されないので、反映されるまで待つ
+    let mut exists = true;
+    for _ in 0..100 {
+        exists = wait!(request.head_lump(device_id(), lump_id(0))).is_some();
+        if !exists {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(!exists);
+}
+
+#[test]
+fn circuit_breaker_works() {
+    let client = start_server(1965);
+
+    // サーバが起動していないアドレスを指すクライアント
+    let mut down = Client::new(
+        "127.0.0.1:1966".parse().unwrap(),
+        client.rpc_service().clone(),
+    );
+    down.enable_circuit_breaker(CircuitBreakerPolicy {
+        failure_threshold: 2,
+        cooldown: Duration::from_secs(60),
+    });
+    let rpc_options = fibers_rpc::client::Options {
+        timeout: Some(Duration::from_millis(200)),
+        ..Default::default()
+    };
+    for _ in 0..2 {
+        assert!(!down.is_circuit_open());
+        wait_err!(down
+            .request()
+            .rpc_options(rpc_options.clone())
+            .head_lump(device_id(), lump_id(0)));
+    }
+    assert!(down.is_circuit_open());
+
+    // 作動中は(クローンからのものも含めて)即座に失敗する
+    let mut future = down.clone().request().head_lump(device_id(), lump_id(0));
+    let e = future.poll().err().unwrap();
+    assert_eq!(*e.kind(), ErrorKind::Other);
+
+    // 他のサーバ向けのクライアントには影響しない
+    assert!(!client.is_circuit_open());
+    assert!(wait!(client.request().head_lump(device_id(), lump_id(0))).is_none());
+}
+
+#[test]
+fn client_error_works() {
+    let client = start_server(1985);
+
+    // サーバから返されたエラー
+    let e = ClientError::from(wait_err!(client
+        .request()
+        .head_lump(DeviceId::new("bar"), lump_id(0))));
+    assert!(matches!(e, ClientError::Remote(_)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    // サーバが起動していないアドレスへの通信の失敗
+    let mut down = Client::new(
+        "127.0.0.1:1986".parse().unwrap(),
+        client.rpc_service().clone(),
+    );
+    let rpc_options = fibers_rpc::client::Options {
+        timeout: Some(Duration::from_millis(200)),
+        ..Default::default()
+    };
+    let e = ClientError::from(wait_err!(down
+        .request()
+        .rpc_options(rpc_options)
+        .head_lump(device_id(), lump_id(0))));
+    assert!(e.is_transport());
+    assert_eq!(*e.kind(), ErrorKind::Other);
+
+    // サーキットブレーカによる拒否
+    down.enable_circuit_breaker(CircuitBreakerPolicy {
+        failure_threshold: 1,
+        cooldown: Duration::from_secs(60),
+    });
+    wait_err!(down.request().head_lump(device_id(), lump_id(0)));
+    let e = ClientError::from(wait_err!(down.request().head_lump(device_id(), lump_id(0))));
+    assert!(matches!(e, ClientError::Unreachable(_)));
+    assert_eq!(e.transport_kind(), Some(fibers_rpc::ErrorKind::Unavailable));
+}
+
+// 一定時間が経過してから、常に「存在しない」と応答するハンドラ.
+struct SlowHeadLumpHandler;
+impl HandleCall<rpc::HeadLumpRpc> for SlowHeadLumpHandler {
+    fn handle_call(&self, _: rpc::LumpRequest) -> Reply<rpc::HeadLumpRpc> {
+        let future = fibers::time::timer::timeout(Duration::from_secs(1))
+            .then(|_| Ok::<_, bytecodec::marker::Never>(Ok(None)));
+        Reply::future(future)
+    }
+}
+
+#[test]
+fn hedged_read_works() {
+    let slow = start_server_with(1967, |server, builder| {
+        builder.add_call_handler(SlowHeadLumpHandler);
+        server.register_except(builder, &[rpc::HeadLumpRpc::ID]);
+    });
+    let fast = start_server(1968);
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(fast.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data
+    )));
+
+    // 元のリクエストの応答が遅い場合には、代替サーバの応答が採用される
+    let header = wait!(slow
+        .request()
+        .hedge(&fast, Duration::from_millis(50))
+        .head_lump(device_id(), lump_id(0)));
+    assert!(header.is_some());
+    assert!(wait!(slow.request().head_lump(device_id(), lump_id(0))).is_none());
+
+    // 元のリクエストが失敗した場合には、即座に代替サーバにリクエストが発行される
+    let down = Client::new(
+        "127.0.0.1:1969".parse().unwrap(),
+        fast.rpc_service().clone(),
+    );
+    let data = wait!(down
+        .request()
+        .hedge(&fast, Duration::from_secs(60))
+        .get_lump(device_id(), lump_id(0)));
+    assert_eq!(data, Some(b"foo".to_vec()));
+}
+
+#[test]
+fn replica_set_works() {
+    let primary = start_server(1970);
+    let replica = start_server(1971);
+    let set = ReplicaSet::new(
+        primary.clone(),
+        vec![replica.clone()],
+        BalancePolicy::RoundRobin,
+    );
+
+    // 更新はプライマリにのみ発行される
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(set.put_lump(device_id(), lump_id(0), data)));
+    assert!(wait!(primary.request().head_lump(device_id(), lump_id(0))).is_some());
+    assert!(wait!(replica.request().head_lump(device_id(), lump_id(0))).is_none());
+
+    // 読み込みは各サーバに分散される
+    let found = (0..4)
+        .map(|_| wait!(set.get_lump(device_id(), lump_id(0))).is_some())
+        .collect::<Vec<_>>();
+    assert_eq!(found, vec![true, false, true, false]);
+    assert_eq!(wait!(set.list_lumps(device_id())), vec![lump_id(0)]);
+    assert!(wait!(set.head_lump(device_id(), lump_id(0))).is_none());
+
+    assert!(wait!(set.delete_lump(device_id(), lump_id(0))));
+    assert!(wait!(primary.request().head_lump(device_id(), lump_id(0))).is_none());
+}
+
+#[test]
+fn router_works() {
+    let a = start_server(1972);
+    let b = start_server(1973);
+    let router = Router::new(vec![(a.clone(), device_id()), (b.clone(), device_id())]);
+
+    for i in 0..10 {
+        let data = LumpData::new(vec![i as u8]).unwrap();
+        assert!(wait!(router.put_lump(lump_id(i), data)));
+    }
+
+    // 各lumpは、振り分け先のサーバにのみ保存される
+    let a_lumps = wait!(a.request().list_lumps(device_id()));
+    let b_lumps = wait!(b.request().list_lumps(device_id()));
+    assert_eq!(a_lumps.len() + b_lumps.len(), 10);
+    for id in a_lumps {
+        assert_eq!(router.route(id).unwrap().0.server(), a.server());
+    }
+
+    for i in 0..10 {
+        assert_eq!(wait!(router.get_lump(lump_id(i))), Some(vec![i as u8]));
+        assert!(wait!(router.head_lump(lump_id(i))).is_some());
+        assert!(wait!(router.delete_lump(lump_id(i))));
+    }
+    assert!(wait!(a.request().list_lumps(device_id())).is_empty());
+    assert!(wait!(b.request().list_lumps(device_id())).is_empty());
+}
+
+#[test]
+fn endpoint_works() {
+    let server = start_server(1974);
+    let mut client = track_try_unwrap!(Client::with_endpoint(
+        "127.0.0.1:1974",
+        server.rpc_service().clone()
+    ));
+    client.set_resolve_interval(Duration::from_millis(10));
+    assert_eq!(client.endpoint(), Some("127.0.0.1:1974"));
+    assert_eq!(client.server(), server.server());
+    assert_eq!(server.endpoint(), None);
+
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(client.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data
+    )));
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(client.server(), server.server());
+    assert_eq!(
+        wait!(client.request().get_lump(device_id(), lump_id(0))),
+        Some(b"foo".to_vec())
+    );
+}
+
+#[test]
+fn connect_works() {
+    let client = start_server(1975);
+    wait!(client.connect());
+
+    // サーバ側で`ServerInfoRpc`が無効な場合にも、疎通は確認できる
+    let disabled = start_server_with(1976, |mut server, builder| {
+        let mut config = ProcedureConfig::new();
+        config.disable::<rpc::ServerInfoRpc>();
+        server.configure_procedures(config);
+        server.register(builder)
+    });
+    wait!(disabled.connect());
+
+    // サーバが起動していない場合
+    let down = Client::new(
+        "127.0.0.1:1977".parse().unwrap(),
+        client.rpc_service().clone(),
+    );
+    let rpc_options = fibers_rpc::client::Options {
+        timeout: Some(Duration::from_millis(200)),
+        ..Default::default()
+    };
+    wait_err!(down.request().rpc_options(rpc_options).connect());
+}
+
+#[test]
+fn call_options_works() {
+    let client = start_server(1978);
+    let mut builder = client.request();
+    builder.deadline(Deadline::Infinity);
+
+    let opts = CallOptions {
+        deadline: Some(Deadline::Immediate),
+        max_queue_len: Some(100),
+        ..Default::default()
+    };
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(builder.put_lump_with_opts(
+        device_id(),
+        lump_id(0),
+        data,
+        &opts
+    )));
+    assert_eq!(
+        wait!(builder.get_lump_with_opts(device_id(), lump_id(0), &opts)),
+        Some(b"foo".to_vec())
+    );
+    assert!(wait!(builder.head_lump_with_opts(device_id(), lump_id(0), &opts)).is_some());
+    assert!(wait!(builder.delete_lump_with_opts(
+        device_id(),
+        lump_id(0),
+        &opts
+    )));
+
+    // RPCレベルのオプションも上書きされる
+    let down = Client::new(
+        "127.0.0.1:1979".parse().unwrap(),
+        client.rpc_service().clone(),
+    );
+    let opts = CallOptions {
+        rpc_options: Some(fibers_rpc::client::Options {
+            timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    wait_err!(down
+        .request()
+        .head_lump_with_opts(device_id(), lump_id(0), &opts));
+}
+
+#[test]
+fn default_options_works() {
+    let server = start_server(1980);
+    let mut down = Client::new(
+        "127.0.0.1:1981".parse().unwrap(),
+        server.rpc_service().clone(),
+    );
+    down.set_default_options(CallOptions {
+        max_queue_len: Some(10),
+        rpc_options: Some(fibers_rpc::client::Options {
+            timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    assert_eq!(down.default_options().max_queue_len, Some(10));
+
+    // 全てのビルダに初期値として適用される
+    wait_err!(down.request().head_lump(device_id(), lump_id(0)));
+    wait_err!(down.connect());
+}
+
+#[test]
+fn async_await_works() {
+    struct ThreadWaker(thread::Thread);
+    impl std::task::Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        let waker = std::task::Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                std::task::Poll::Ready(output) => return output,
+                std::task::Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    let mut client = start_server(1982);
+
+    // 未設定の場合には失敗する
+    let e = block_on(async { client.connect().await });
+    assert_eq!(*e.err().unwrap().kind(), ErrorKind::Other);
+
+    let executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+    client.set_spawner(executor.handle());
+    thread::spawn(move || {
+        if let Err(e) = executor.run() {
+            panic!("{}", e);
+        }
+    });
+    block_on(async {
+        client.connect().await.unwrap();
+
+        let data = LumpData::new(b"foo".to_vec()).unwrap();
+        let request = client.request();
+        assert!(request
+            .put_lump(device_id(), lump_id(0), data)
+            .await
+            .unwrap());
+        assert_eq!(
+            request.get_lump(device_id(), lump_id(0)).await.unwrap(),
+            Some(b"foo".to_vec())
+        );
+        let ids = request.list_lumps(device_id()).await.unwrap();
+        assert_eq!(ids, vec![lump_id(0)]);
+
+        let e = request.get_lump(DeviceId::new("bar"), lump_id(0)).await;
+        assert_eq!(*e.err().unwrap().kind(), ErrorKind::InvalidInput);
+
+        // ビルダを保持する`Future`
+        let results = request
+            .get_lumps_concurrent(device_id(), vec![lump_id(0), lump_id(1)], 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            results[&lump_id(0)].as_ref().ok(),
+            Some(&Some(b"foo".to_vec()))
+        );
+        assert_eq!(results[&lump_id(1)].as_ref().ok(), Some(&None));
+        let header = request
+            .retry(|request| request.head_lump(device_id(), lump_id(0)))
+            .await
+            .unwrap();
+        assert!(header.is_some());
+
+        // `Sink`と`Stream`
+        let (sink, results) = request.put_pipeline(4);
+        let mut sink = sink.compat();
+        for i in 1..4 {
+            let data = LumpData::new(b"bar".to_vec()).unwrap();
+            sink.send((device_id(), lump_id(i), data)).await.unwrap();
+        }
+        drop(sink);
+        let mut results = results.compat();
+        let mut count = 0;
+        while let Some((_, _, created)) = results.next().await.unwrap() {
+            assert!(created.unwrap());
+            count += 1;
+        }
+        assert_eq!(count, 3);
+
+        let range = lump_id(0)..lump_id(10);
+        let mut ids = request
+            .list_lumps_stream(device_id(), range.clone(), 2)
+            .compat();
+        let mut listed = Vec::new();
+        while let Some(id) = ids.next().await.unwrap() {
+            listed.push(id);
+        }
+        assert_eq!(listed, (0..4).map(lump_id).collect::<Vec<_>>());
+        assert!(ids.next().await.unwrap().is_none());
+
+        let mut chunks = request.export_lumps(device_id(), range, 3).compat();
+        let mut exported = 0;
+        while let Some(chunk) = chunks.next().await.unwrap() {
+            exported += chunk.len();
+        }
+        assert_eq!(exported, 4);
+
+        let mut failed = request
+            .list_lumps_stream(DeviceId::new("bar"), lump_id(0)..lump_id(10), 2)
+            .compat();
+        let e = failed.next().await;
+        assert_eq!(*e.err().unwrap().kind(), ErrorKind::InvalidInput);
+        assert!(failed.next().await.unwrap().is_none());
+    });
+}
+
+#[test]
+fn put_pipeline_works() {
+    let client = start_server(1983);
+    let (mut sink, mut results) = client.request().put_pipeline(4);
+    let item = |i: u128| {
+        (
+            device_id(),
+            lump_id(i),
+            LumpData::new(vec![i as u8]).unwrap(),
+        )
+    };
+
+    // 結果を取り出さない間は、上限を超えて送信できない
+    for i in 0..4 {
+        assert!(sink.start_send(item(i)).unwrap().is_ready());
+    }
+    assert!(sink.start_send(item(4)).unwrap().is_not_ready());
+
+    let mut pending = (4..20).map(item);
+    let mut next = pending.next();
+    let mut outputs = Vec::new();
+    loop {
+        while let Some(x) = next.take() {
+            match sink.start_send(x).unwrap() {
+                AsyncSink::Ready => next = pending.next(),
+                AsyncSink::NotReady(x) => {
+                    next = Some(x);
+                    break;
+                }
+            }
+        }
+        if next.is_none() {
+            sink.close().unwrap();
+        }
+        match results.poll().unwrap() {
+            Async::Ready(Some(output)) => outputs.push(output),
+            Async::Ready(None) => break,
+            Async::NotReady => {}
+        }
+    }
+    outputs.sort_by_key(|o| o.1);
+    assert_eq!(outputs.len(), 20);
+    for (i, (device, lump, result)) in outputs.into_iter().enumerate() {
+        assert_eq!(device, device_id());
+        assert_eq!(lump, lump_id(i as u128));
+        assert!(result.unwrap());
+    }
+    assert_eq!(wait!(client.request().list_lumps(device_id())).len(), 20);
+
+    // クローズ後の送信は失敗する
+    assert!(sink.start_send(item(0)).is_err());
+}
+
+#[test]
+fn put_lump_v2_works() {
+    let client = start_server(1959);
+    let request = client.request();
+
+    let header = wait!(request.put_lump_v2(
+        device_id(),
+        lump_id(0),
+        LumpData::new(b"foo".to_vec()).unwrap()
+    ));
+    assert!(header.is_none());
+
+    let header = wait!(request.put_lump_v2(
+        device_id(),
+        lump_id(0),
+        LumpData::new(b"barbaz".to_vec()).unwrap()
+    ));
+    assert_eq!(header.map(|h| h.approximate_data_size), Some(3));
+    assert_eq!(
+        wait!(request.get_lump(device_id(), lump_id(0))),
+        Some(b"barbaz".to_vec())
+    );
+
+    let e = wait_err!(client.request().if_not_exists().put_lump_v2(
+        device_id(),
+        lump_id(0),
+        LumpData::new(b"qux".to_vec()).unwrap()
+    ));
+    assert!(rpc::Precondition::is_failure(&e), "{}", e);
+}
+
+#[test]
+fn delete_lump_v2_works() {
+    let client = start_server(1960);
+    let request = client.request();
+
+    let data = LumpData::new(b"foobar".to_vec()).unwrap();
+    assert!(wait!(request.put_lump(device_id(), lump_id(0), data)));
+
+    let e = wait_err!(client
+        .request()
+        .if_size_equals(1)
+        .delete_lump_v2(device_id(), lump_id(0)));
+    assert!(rpc::Precondition::is_failure(&e), "{}", e);
+
+    let header = wait!(request.delete_lump_v2(device_id(), lump_id(0)));
+    assert_eq!(header.map(|h| h.approximate_data_size), Some(6));
+    assert!(wait!(request.delete_lump_v2(device_id(), lump_id(0))).is_none());
+}
+
+#[test]
+fn conditional_put_works() {
+    let client = start_server(1958);
+    let data = || LumpData::new("bar".into()).unwrap();
+
+    // overwrite-only
+    let e = wait_err!(client.request().if_exists().verbose_errors().put_lump(
+        device_id(),
+        lump_id(0),
+        data()
+    ));
+    assert!(rpc::Precondition::is_failure(&e), "{}", e);
+
+    // create-only
+    assert!(wait!(client.request().if_not_exists().put_lump(
+        device_id(),
+        lump_id(0),
+        data()
+    )));
+    let e = wait_err!(client
+        .request()
+        .if_not_exists()
+        .put_lump(device_id(), lump_id(0), data()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert!(rpc::Precondition::is_failure(&e), "{}", e);
+
+    assert!(!wait!(client.request().if_exists().put_lump(
+        device_id(),
+        lump_id(0),
+        data()
+    )));
+
+    // 事前条件以外のエラー
+    let e = wait_err!(client.request().if_not_exists().put_lump(
+        DeviceId::new("bar"),
+        lump_id(0),
+        data()
+    ));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert!(!rpc::Precondition::is_failure(&e), "{}", e);
+}
+
+#[test]
+fn concurrent_conditional_puts_works() {
+    let client = start_server(2003);
+
+    // 同時に発行された作成のみのPUTのうち、成功するのは一つだけ
+    let futures = (0..128)
+        .map(|i| {
+            let data = LumpData::new(vec![i; 8]).unwrap();
+            client
+                .request()
+                .if_not_exists()
+                .put_lump(device_id(), lump_id(1), data)
+                .then(Ok::<_, cannyls::Error>)
+        })
+        .collect::<Vec<_>>();
+    let results = wait!(futures::future::join_all(futures));
+    let mut succeeded = Vec::new();
+    for (i, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(created) => {
+                assert!(created);
+                succeeded.push(i as u8);
+            }
+            Err(e) => assert!(rpc::Precondition::is_failure(&e), "{}", e),
+        }
+    }
+    assert_eq!(succeeded.len(), 1);
+    assert_eq!(
+        wait!(client.request().get_lump(device_id(), lump_id(1))),
+        Some(vec![succeeded[0]; 8])
+    );
+}
+
+#[test]
+fn concurrent_conditional_deletes_works() {
+    let client = start_server(2004);
+    let data = LumpData::new(b"foobar".to_vec()).unwrap();
+    assert!(wait!(client.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data
+    )));
+
+    // 同時に発行された条件付きの削除のうち、削除前のヘッダを得られるのは一つだけ
+    let futures = (0..128)
+        .map(|_| {
+            client
+                .request()
+                .if_exists()
+                .if_size_equals(6)
+                .delete_lump_v2(device_id(), lump_id(0))
+                .then(Ok::<_, cannyls::Error>)
+        })
+        .collect::<Vec<_>>();
+    let results = wait!(futures::future::join_all(futures));
+    let mut deleted = 0;
+    for result in results {
+        match result {
+            Ok(header) => {
+                assert_eq!(header.map(|h| h.approximate_data_size), Some(6));
+                deleted += 1;
+            }
+            Err(e) => assert!(rpc::Precondition::is_failure(&e), "{}", e),
+        }
+    }
+    assert_eq!(deleted, 1);
+}
+
+#[test]
+fn concurrent_script_is_atomic() {
+    let client = start_server(2005);
+    let data = || LumpData::new(b"foo".to_vec()).unwrap();
+
+    // 操作列の実行中に、作成のみのPUTの事前条件の評価と保存が割り込まれることはない
+    // (i.e., 対象lumpを新規に作成したと報告されるのは、常にどちらか一方のみ)
+    let futures = (0..64)
+        .map(|i| {
+            let ops = vec![ScriptOp::Get(lump_id(i)), ScriptOp::Put(lump_id(i), data())];
+            let script = client
+                .request()
+                .execute_concurrent_script(device_id(), ops)
+                .then(Ok::<_, cannyls::Error>);
+            let put = client
+                .request()
+                .if_not_exists()
+                .put_lump(device_id(), lump_id(i), data())
+                .then(Ok::<_, cannyls::Error>);
+            put.join(script)
+        })
+        .collect::<Vec<_>>();
+    for (put, script) in wait!(futures::future::join_all(futures)) {
+        let put_created = match put {
+            Ok(created) => {
+                assert!(created);
+                true
+            }
+            Err(e) => {
+                assert!(rpc::Precondition::is_failure(&e), "{}", e);
+                false
+            }
+        };
+        let results = track_try_unwrap!(script);
+        let script_created = match (&results[0], &results[1]) {
+            (Ok(ScriptOpResult::Get(None)), Ok(ScriptOpResult::Put(true))) => true,
+            (Ok(ScriptOpResult::Get(Some(_))), Ok(ScriptOpResult::Put(false))) => false,
+            _ => panic!("{:?}", results),
+        };
+        assert_ne!(put_created, script_created);
+    }
+}
+
+#[test]
+fn put_lumps_is_serialized_with_conditional_puts() {
+    let client = start_server(2007);
+    let data = || LumpData::new(b"foo".to_vec()).unwrap();
+
+    // 一括保存の実行中に、作成のみのPUTの事前条件の評価と保存が割り込まれることはない
+    // (i.e., 対象lumpを新規に作成したと報告されるのは、常にどちらか一方のみ)
+    for round in 0..4 {
+        let lump_ids = (round * 64..(round + 1) * 64)
+            .map(lump_id)
+            .collect::<Vec<_>>();
+        let puts = lump_ids
+            .iter()
+            .map(|&lump_id| {
+                client
+                    .request()
+                    .if_not_exists()
+                    .put_lump(device_id(), lump_id, data())
+                    .then(Ok::<_, cannyls::Error>)
+            })
+            .collect::<Vec<_>>();
+        let batch = client.request().put_lumps(
+            device_id(),
+            lump_ids.iter().map(|&lump_id| (lump_id, data())).collect(),
+        );
+        let (puts, batch) = wait!(futures::future::join_all(puts).join(batch));
+        for (put, batch) in puts.into_iter().zip(batch) {
+            let put_created = match put {
+                Ok(created) => {
+                    assert!(created);
+                    true
+                }
+                Err(e) => {
+                    assert!(rpc::Precondition::is_failure(&e), "{}", e);
+                    false
+                }
+            };
+            assert_ne!(put_created, track_try_unwrap!(batch));
+        }
+    }
+}
+
+#[test]
+fn journal_usage_works() {
+    let client = start_server(1923);
+    let request = client.request();
+
+    let before = wait!(request.journal_usage(device_id()));
+    assert!(before.capacity_bytes > 0);
+
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        LumpData::new("bar".into()).unwrap()
+    )));
+    let after = wait!(request.journal_usage(device_id()));
+    assert_eq!(after.capacity_bytes, before.capacity_bytes);
+    assert!(after.usage_bytes > before.usage_bytes);
+    assert!(after.usage_ratio() > 0.0 && after.usage_ratio() < 1.0);
+    assert_eq!(
+        after.record_counts.total(),
+        before.record_counts.total() + 1
+    );
+    assert_eq!(after.record_counts.total(), after.records);
+}
+
+#[test]
+fn set_journal_sync_works() {
+    let client = start_server(1924);
+    let request = client.request();
+
+    let settings = wait!(request.set_journal_sync(device_id(), true));
+    assert!(settings.journal_sync);
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        LumpData::new("bar".into()).unwrap()
+    )));
+
+    let settings = wait!(request.set_journal_sync(device_id(), false));
+    assert!(!settings.journal_sync);
+}
+
+#[test]
+fn set_queue_limits_works() {
+    let client = start_server(1925);
+    let request = client.request();
+
+    let settings = wait!(request.set_queue_limits(device_id(), Some(100), Some(1000)));
+    assert_eq!(settings.default_max_queue_len, Some(100));
+    assert_eq!(settings.max_queue_len_limit, Some(1000));
+    assert_eq!(wait!(request.list_lumps(device_id())), vec![]);
+
+    let settings = wait!(request.set_queue_limits(device_id(), None, None));
+    assert_eq!(settings.default_max_queue_len, None);
+    assert_eq!(settings.max_queue_len_limit, None);
+}
+
+#[test]
+fn set_log_level_works() {
+    let client = start_server(1926);
+    let request = client.request();
+
+    assert_eq!(wait!(request.set_log_level(Level::Debug)), Level::Debug);
+    assert_eq!(wait!(request.list_lumps(device_id())), vec![]);
+    assert_eq!(wait!(request.set_log_level(Level::Warning)), Level::Warning);
+}
+
+#[test]
+fn metrics_snapshot_works() {
+    let client = start_server(1927);
+    let request = client.request();
+
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        LumpData::new("bar".into()).unwrap()
+    )));
+
+    let snapshots = wait!(request.metrics_snapshot(Vec::new()));
+    assert_eq!(snapshots.len(), 1);
+    assert_eq!(snapshots[0].device_id, device_id());
+    assert_eq!(snapshots[0].status, DeviceStatus::Running);
+    assert!(snapshots[0].dequeued_commands >= 1);
+    assert_eq!(snapshots[0].storage.as_ref().map(|s| s.lumps), Some(1));
+
+    let snapshots = wait!(request.metrics_snapshot(vec![DeviceId::new("unknown")]));
+    assert!(snapshots.is_empty());
+}
+
+#[test]
+fn list_in_flight_requests_works() {
+    let client = start_server(1928);
+    let request = client.request();
+
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        LumpData::new("bar".into()).unwrap()
+    )));
+    assert_eq!(wait!(request.list_in_flight_requests(Vec::new())), vec![]);
+    assert_eq!(
+        wait!(request.list_in_flight_requests(vec![device_id()])),
+        vec![]
+    );
+    assert!(!wait!(request.cancel_in_flight_request(0)));
+}
+
+#[test]
+fn request_stats_works() {
+    let client = start_server(1929);
+    let request = client.request();
+
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        LumpData::new("bar".into()).unwrap()
+    )));
+    let _ = wait_err!(client
+        .request()
+        .if_size_equals(10)
+        .delete_lump(device_id(), lump_id(0)));
+
+    let stats = wait!(request.request_stats(Vec::new()));
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[0].procedure, "cannyls.lump.delete");
+    assert_eq!(stats[0].failed, vec![(ErrorKind::InvalidInput, 1)]);
+    assert_eq!(stats[1].procedure, "cannyls.lump.put");
+    assert_eq!(stats[1].succeeded, 1);
+
+    let stats = wait!(request.reset_request_stats(vec![device_id()]));
+    assert_eq!(stats.len(), 2);
+    assert_eq!(wait!(request.request_stats(Vec::new())), vec![]);
+}
+
+#[test]
+fn set_write_watermark_works() {
+    let client = start_server(1930);
+    let request = client.request();
+
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        LumpData::new(vec![0; 2 * 1024 * 1024]).unwrap()
+    )));
+
+    let settings = wait!(request.set_write_watermark(device_id(), Some(1)));
+    assert_eq!(settings.write_watermark, Some(1));
+    let e = wait_err!(request.put_lump(
+        device_id(),
+        lump_id(1),
+        LumpData::new("bar".into()).unwrap()
+    ));
+    assert_eq!(*e.kind(), ErrorKind::StorageFull);
+    assert!(wait!(request.head_lump(device_id(), lump_id(0))).is_some());
+    assert!(wait!(request.delete_lump(device_id(), lump_id(0))));
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(1),
+        LumpData::new("bar".into()).unwrap()
+    )));
+
+    let e = wait_err!(request.set_write_watermark(device_id(), Some(101)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let settings = wait!(request.set_write_watermark(device_id(), None));
+    assert_eq!(settings.write_watermark, None);
+}
+
+#[test]
+fn request_template_works() {
+    let client = start_server(1931);
+    let template = client
+        .request()
+        .deadline(Deadline::Within(Duration::from_secs(10)))
+        .max_queue_len(100)
+        .to_template();
+
+    let handle = {
+        let template = template.clone();
+        thread::spawn(move || {
+            wait!(template.request().put_lump(
+                device_id(),
+                lump_id(0),
+                LumpData::new("bar".into()).unwrap()
+            ))
+        })
+    };
+    assert!(handle.join().unwrap());
+    assert_eq!(
+        wait!(template.request().get_lump(device_id(), lump_id(0))),
+        Some(Vec::from("bar"))
+    );
+}
+
+struct EchoRpc;
+impl Call for EchoRpc {
+    const ID: ProcedureId = ProcedureId(0x0100_0000);
+    const NAME: &'static str = "test.echo";
+
+    type Req = Vec<u8>;
+    type ReqEncoder = BytesEncoder<Vec<u8>>;
+    type ReqDecoder = RemainingBytesDecoder;
+
+    type Res = Vec<u8>;
+    type ResEncoder = BytesEncoder<Vec<u8>>;
+    type ResDecoder = RemainingBytesDecoder;
+}
+
+struct EchoHandler;
+impl HandleCall<EchoRpc> for EchoHandler {
+    fn handle_call(&self, request: Vec<u8>) -> Reply<EchoRpc> {
+        Reply::done(request)
+    }
+}
+
+#[test]
+fn custom_call_works() {
+    let client = start_server_with(1932, |server, builder| {
+        server.register(builder);
+        builder.add_call_handler(EchoHandler);
+    });
+    let request = client.request();
+
+    let response = wait!(track_any_err!(request.call::<EchoRpc>(b"hello".to_vec())));
+    assert_eq!(response, b"hello");
+    assert_eq!(wait!(request.list_lumps(device_id())), vec![]);
+}
+
+// デバイスIDの別名を解決するハンドラ.
+struct ListLumpHandler(Server);
+impl HandleCall<rpc::ListLumpRpc> for ListLumpHandler {
+    fn handle_call(&self, mut request: DeviceRequest) -> Reply<rpc::ListLumpRpc> {
+        if request.device_id.as_str() == "alias" {
+            request.device_id = device_id();
+        }
+        self.0.handle_call(request)
+    }
+}
+
+#[test]
+fn wrapped_handler_works() {
+    let client = start_server_with(1933, |server, builder| {
+        builder.add_call_handler(ListLumpHandler(server.clone()));
+        server.register_except(builder, &[rpc::ListLumpRpc::ID]);
+    });
+    let request = client.request();
+
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        LumpData::new("bar".into()).unwrap()
+    )));
+    assert_eq!(
+        wait!(request.list_lumps(DeviceId::new("alias"))),
+        vec![lump_id(0)]
+    );
+    assert_eq!(wait!(request.list_lumps(device_id())), vec![lump_id(0)]);
+}
+
+#[test]
+fn read_only_server_works() {
+    let client = start_server_with(1934, |mut server, builder| {
+        server.enable_admin_rpc();
+        server.register_read_only(builder)
+    });
+    let request = client
+        .request()
+        .rpc_options(fibers_rpc::client::Options {
+            timeout: Some(Duration::from_millis(500)),
+            ..Default::default()
+        })
+        .to_template();
+
+    assert_eq!(wait!(request.request().list_lumps(device_id())), vec![]);
+    assert_eq!(
+        wait!(request.request().get_lump(device_id(), lump_id(0))),
+        None
+    );
+    let _ = wait_err!(request.request().put_lump(
+        device_id(),
+        lump_id(0),
+        LumpData::new("bar".into()).unwrap()
+    ));
+    let _ = wait_err!(request.request().set_journal_sync(device_id(), true));
+
+    let info = wait!(client.server_info());
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert!(info.supports(rpc::GetLumpRpc::ID));
+    assert!(info.supports(rpc::ServerInfoRpc::ID));
+    assert!(!info.supports(rpc::PutLumpRpc::ID));
+    assert!(!info.supports(rpc::SetJournalSyncRpc::ID));
+}
+
+#[test]
+fn disabled_procedure_works() {
+    let client = start_server_with(1935, |mut server, builder| {
+        let mut config = ProcedureConfig::new();
+        config
+            .disable::<rpc::DeleteRangeRpc>()
+            .disable::<rpc::PutLumpRpc>();
+        server.configure_procedures(config);
+        server.register(builder)
+    });
+    let request = client.request();
+
+    let e = wait_err!(request.delete_range(device_id(), lump_id(0)..lump_id(10)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        LumpData::new("bar".into()).unwrap()
+    ));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert_eq!(wait!(request.list_lumps(device_id())), vec![]);
+
+    let info = wait!(client.server_info());
+    assert!(info.supports(rpc::ListLumpRpc::ID));
+    assert!(!info.supports(rpc::DeleteRangeRpc::ID));
+    assert!(!info.supports(rpc::PutLumpRpc::ID));
+}
+
+#[test]
+fn error_verbosity_works() {
+    let client = start_server_with(1936, |mut server, builder| {
+        server.error_verbosity(ErrorVerbosity::Summary);
+        server.register(builder)
+    });
+
+    // デバイスの取得に失敗したリクエスト
+    let e = wait_err!(client
+        .request()
+        .head_lump(DeviceId::new("unknown"), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert!(!has_server_history(&e), "{}", e);
+
+    // デバイスでの処理中に失敗したリクエスト
+    let e = wait_err!(client
+        .request()
+        .if_exists()
+        .delete_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert!(!has_server_history(&e), "{}", e);
+
+    // サーバで許可されていない場合には、完全なエラーを要求しても無視される
+    let e = wait_err!(client
+        .request()
+        .if_exists()
+        .verbose_errors()
+        .delete_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert!(!has_server_history(&e), "{}", e);
+
+    // サーバで許可されている場合には、完全なエラーが返される
+    let client = start_server_with(2012, |mut server, builder| {
+        server
+            .error_verbosity(ErrorVerbosity::Summary)
+            .allow_verbose_errors();
+        server.register(builder)
+    });
+    let e = wait_err!(client
+        .request()
+        .if_exists()
+        .delete_lump(device_id(), lump_id(0)));
+    assert!(!has_server_history(&e), "{}", e);
+    let e = wait_err!(client
+        .request()
+        .if_exists()
+        .verbose_errors()
+        .delete_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert!(has_server_history(&e), "{}", e);
+}
+
+fn has_server_history(e: &cannyls::Error) -> bool {
+    let e = e.to_string();
+    e.contains("src/server.rs") || e.contains("src/registry.rs")
+}
+
+#[test]
+fn mutation_observer_works() {
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<(DeviceId, Mutation)>>>);
+    impl MutationObserver for Recorder {
+        fn on_mutation(&self, device_id: &DeviceId, mutation: &Mutation) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((device_id.clone(), mutation.clone()));
+        }
+    }
+
+    let recorder = Recorder::default();
+    let observer = recorder.clone();
+    let client = start_server_with(1937, move |mut server, builder| {
+        server.add_mutation_observer(observer);
+        server.register(builder)
+    });
+    let request = client.request();
+
+    let data = LumpData::new("foo".into()).unwrap();
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        data.clone()
+    )));
+    assert!(!wait!(request.put_lump(device_id(), lump_id(0), data)));
+    assert!(wait!(request.delete_lump(device_id(), lump_id(0))));
+    let _ = wait_err!(client
+        .request()
+        .if_exists()
+        .delete_lump(device_id(), lump_id(0)));
+    let ops = vec![
+        ScriptOp::Put(lump_id(1), LumpData::new("bar".into()).unwrap()),
+        ScriptOp::Head(lump_id(1)),
+    ];
+    let _ = wait!(request.execute_script(device_id(), ops));
+    assert_eq!(
+        wait!(request.delete_range(device_id(), lump_id(0)..lump_id(10))),
+        vec![lump_id(1)]
+    );
+
+    let mutations = recorder.0.lock().unwrap().clone();
+    assert!(mutations.iter().all(|(id, _)| *id == device_id()));
+    let mutations = mutations.into_iter().map(|(_, m)| m).collect::<Vec<_>>();
+    assert_eq!(
+        mutations,
+        vec![
+            Mutation::Put {
+                lump_id: lump_id(0),
+                size: 3,
+                created: true
+            },
+            Mutation::Put {
+                lump_id: lump_id(0),
+                size: 3,
+                created: false
+            },
+            Mutation::Delete {
+                lump_id: lump_id(0),
+                deleted: true
+            },
+            Mutation::Put {
+                lump_id: lump_id(1),
+                size: 3,
+                created: true
+            },
+            Mutation::DeleteRange {
+                range: lump_id(0)..lump_id(10),
+                deleted: vec![lump_id(1)]
+            },
+        ]
+    );
+}
+
+#[test]
+fn devices_snapshot_works() {
+    let mut executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+    let registry = DeviceRegistry::new(Logger::root(Discard, o!()));
+    let handle = registry.handle();
+    executor.spawn(registry.map_err(|e| panic!("{}", e)));
+
+    let empty = handle.snapshot();
+    assert!(empty.is_empty());
+
+    let nvm = MemoryNvm::new(vec![0; 1024 * 1024]);
+    let storage = track_try_unwrap!(StorageBuilder::new().create(nvm));
+    let device = DeviceBuilder::new().spawn(|| Ok(storage));
+    track_try_unwrap!(handle.put_device(device_id(), device));
+    while !handle.contains_device(&device_id()) {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+
+    let snapshot = handle.snapshot();
+    assert_eq!(snapshot.len(), 1);
+    assert_eq!(
+        snapshot.device_ids().cloned().collect::<Vec<_>>(),
+        vec![device_id()]
+    );
+    assert!(snapshot.contains_device("foo"));
+    assert!(snapshot.get_device("foo").is_ok());
+    assert!(snapshot.get_device_settings("foo").is_ok());
+    assert_eq!(
+        *snapshot.get_storage_metrics("foo").err().unwrap().kind(),
+        ErrorKind::Other
+    );
+    assert_eq!(
+        *snapshot.get_device("bar").err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    // 取得後の変更は、既存のスナップショットには反映されない
+    assert!(empty.is_empty());
+    assert!(!empty.contains_device("foo"));
+}
+
+#[test]
+fn check_readiness_works() {
+    let client = start_server(1938);
+    let request = client.request();
+
+    let readiness = wait!(request.check_readiness(vec![]));
+    assert_eq!(readiness.len(), 1);
+    assert_eq!(readiness[0].device_id, device_id());
+    assert!(readiness[0].is_ready());
+
+    let readiness = wait!(request.check_readiness(vec![device_id(), DeviceId::new("bar")]));
+    assert_eq!(readiness.len(), 2);
+    assert_eq!(readiness[0].device_id, DeviceId::new("bar"));
+    assert_eq!(readiness[0].error, Some(ErrorKind::InvalidInput));
+    assert!(readiness[1].is_ready());
+}
+
+#[test]
+fn list_devices_works() {
+    let client = start_server(1947);
+    let request = client.request();
+
+    let devices = wait!(request.list_devices(false));
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].device_id, device_id());
+    assert_eq!(devices[0].status, None);
+
+    // デバイスの起動完了を待ってから、稼働状態付きで取得する
+    assert!(wait!(request.check_readiness(vec![]))[0].is_ready());
+    let devices = wait!(request.list_devices(true));
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].status, Some(DeviceStatus::Running));
+}
+
+#[test]
+fn provision_device_rejects_too_large_capacity() {
+    let client = start_server_with(2008, |mut server, builder| {
+        server.max_provision_capacity(1024 * 1024);
+        server.register(builder)
+    });
+    let request = client.request();
+
+    // 上限を超える容量の場合には、(メモリを確保せずに)拒否される
+    let spec = DeviceSpec::memory(DeviceId::new("too_large"), 1024 * 1024 + 1);
+    let e = wait_err!(request.provision_device(spec));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    let spec = DeviceSpec::memory(DeviceId::new("max"), 1024 * 1024);
+    assert!(wait!(request.provision_device(spec)));
+}
+
+#[test]
+fn provision_device_works() {
+    let client = start_server(1948);
+    let request = client.request();
+    let dir = track_try_unwrap!(track_any_err!(TempDir::new("cannyls_rpc_test")));
+
+    let mut spec = DeviceSpec::file(
+        DeviceId::new("provisioned"),
+        dir.path().join("provisioned.lusf"),
+        1024 * 1024,
+    );
+    spec.labels.insert("zone".to_owned(), "a".to_owned());
+    assert!(wait!(request.provision_device(spec)));
+    assert!(dir.path().join("provisioned.lusf").exists());
+
+    let spec = DeviceSpec::memory(DeviceId::new("mem"), 1024 * 1024);
+    assert!(wait!(request.provision_device(spec)));
+
+    // 容量が小さ過ぎる
+    let spec = DeviceSpec::memory(DeviceId::new("too_small"), 0);
+    let e = wait_err!(request.provision_device(spec));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    // レジストリへの登録は非同期に行われる
+    let mut devices = wait!(request.list_devices(false));
+    for _ in 0..100 {
+        if devices.len() == 3 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+        devices = wait!(request.list_devices(false));
+    }
+    let ids = devices
+        .into_iter()
+        .map(|d| d.device_id.into_string())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec!["foo", "mem", "provisioned"]);
+
+    let data = LumpData::new(b"bar".to_vec()).unwrap();
+    let provisioned = DeviceId::new("provisioned");
+    assert!(wait!(request.put_lump(
+        provisioned.clone(),
+        lump_id(0),
+        data
+    )));
+    assert_eq!(
+        wait!(request.get_lump(provisioned, lump_id(0))),
+        Some(b"bar".to_vec())
+    );
+}
+
+#[test]
+fn delete_device_works() {
+    let client = start_server(1949);
+    let request = client.request();
+    assert_eq!(wait!(request.list_devices(false)).len(), 1);
+
+    assert!(wait!(request.delete_device(device_id())));
+
+    // レジストリからの削除は非同期に行われる
+    let mut devices = wait!(request.list_devices(false));
+    for _ in 0..100 {
+        if devices.is_empty() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+        devices = wait!(request.list_devices(false));
+    }
+    assert!(devices.is_empty());
+
+    let e = wait_err!(request.head_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    // 存在しないデバイス
+    assert!(!wait!(request.delete_device(device_id())));
+}
+
+#[test]
+fn stop_device_works() {
+    let client = start_server(1950);
+    let request = client.request();
+    assert!(wait!(request.check_readiness(vec![]))[0].is_ready());
+
+    let mut request = client.request();
+    request.deadline(Deadline::Immediate);
+    assert!(wait!(request.stop_device(device_id())));
+    assert!(!wait!(request.stop_device(DeviceId::new("bar"))));
+
+    // 停止は非同期に行われる
+    let mut devices = wait!(request.list_devices(true));
+    for _ in 0..100 {
+        if devices[0].status == Some(DeviceStatus::Stopped) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+        devices = wait!(request.list_devices(true));
+    }
+    assert_eq!(devices[0].device_id, device_id());
+    assert_eq!(devices[0].status, Some(DeviceStatus::Stopped));
+
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    let e = wait_err!(request.put_lump(device_id(), lump_id(0), data));
+    assert_eq!(*e.kind(), ErrorKind::DeviceTerminated);
+}
+
+#[test]
+fn device_status_works() {
+    let client = start_server(1951);
+    let request = client.request();
+    assert!(wait!(request.check_readiness(vec![]))[0].is_ready());
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(request.put_lump(device_id(), lump_id(0), data)));
+
+    let report = wait!(request.device_status(device_id()));
+    assert_eq!(report.device_id, device_id());
+    assert!(report.is_registered());
+    assert!(!report.is_terminated());
+    assert_eq!(report.status, Some(DeviceStatus::Running));
+    assert_eq!(report.queue_len, 0);
+    assert_eq!(report.storage.map(|s| s.lumps), Some(1));
+
+    // 未登録のデバイス
+    let report = wait!(request.device_status(DeviceId::new("bar")));
+    assert!(!report.is_registered());
+    assert!(!report.is_terminated());
+    assert_eq!(report.storage, None);
+}
+
+#[test]
+fn device_labels_works() {
+    let server = track_try_unwrap!(TestServer::start());
+    let request = server.client().request();
+    let labels = track_try_unwrap!(TestServer::wait(
+        request.device_labels(server.device_id().clone())
+    ));
+    assert!(labels.is_empty());
+
+    let mut labels = DeviceLabels::new();
+    labels.insert("rack".to_owned(), "r1".to_owned());
+    labels.insert("media".to_owned(), "ssd".to_owned());
+    let nvm = MemoryNvm::new(vec![0; 1024 * 1024]);
+    let storage = track_try_unwrap!(StorageBuilder::new().create(nvm));
+    let storage_metrics = storage.metrics().clone();
+    let device = DeviceBuilder::new().spawn(|| Ok(storage));
+    track_try_unwrap!(server.registry().put_device_with_labels(
+        DeviceId::new("labeled"),
+        device,
+        Some(storage_metrics),
+        labels.clone()
+    ));
+    while !server.registry().contains_device(&DeviceId::new("labeled")) {
+        thread::sleep(Duration::from_millis(1));
+    }
+    let actual = track_try_unwrap!(TestServer::wait(
+        request.device_labels(DeviceId::new("labeled"))
+    ));
+    assert_eq!(actual, labels);
+
+    // 未登録のデバイス
+    let error = TestServer::wait(request.device_labels(DeviceId::new("unknown")))
+        .err()
+        .unwrap();
+    assert_eq!(*error.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn storage_header_works() {
+    let client = start_server(1952);
+    let request = client.request();
+
+    let header = wait!(request.storage_header(device_id()));
+    assert_eq!(header.block_size.as_u16(), 512);
+    assert!(header.journal_region_size > 0);
+    assert!(header.data_region_size > 0);
+    assert!(header.storage_size() <= 32 * 1024 * 1024);
+
+    let e = wait_err!(request.storage_header(DeviceId::new("bar")));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn export_lumps_works() {
+    let client = start_server(1954);
+    let request = client.request();
+
+    for i in 0..5 {
+        let data = LumpData::new(format!("data{}", i).into_bytes()).unwrap();
+        assert!(wait!(request.put_lump(device_id(), lump_id(i), data)));
+    }
+
+    let chunks = wait!(request
+        .export_lumps(device_id(), lump_id(1)..lump_id(100), 2)
+        .collect());
+    assert_eq!(chunks.iter().map(|c| c.len()).collect::<Vec<_>>(), [2, 2]);
+    let lumps = chunks.into_iter().flatten().collect::<Vec<_>>();
+    assert_eq!(
+        lumps,
+        (1..5)
+            .map(|i| (lump_id(i), format!("data{}", i).into_bytes()))
+            .collect::<Vec<_>>()
+    );
+
+    let chunks = wait!(request
+        .export_lumps(device_id(), lump_id(10)..lump_id(100), 2)
+        .collect());
+    assert!(chunks.is_empty());
+
+    let e = wait_err!(request
+        .export_lumps(DeviceId::new("bar"), lump_id(0)..lump_id(100), 2)
+        .collect());
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn list_lumps_stream_works() {
+    let client = start_server(1984);
+    let request = client.request();
+
+    for i in 0..7 {
+        let data = LumpData::new(b"foo".to_vec()).unwrap();
+        assert!(wait!(request.put_lump(device_id(), lump_id(i), data)));
+    }
+
+    let ids = wait!(request
+        .list_lumps_stream(device_id(), lump_id(1)..lump_id(100), 2)
+        .collect());
+    assert_eq!(ids, (1..7).map(lump_id).collect::<Vec<_>>());
+
+    // チャンクサイズに`0`を指定した場合には`1`として扱われる
+    let ids = wait!(request
+        .list_lumps_stream(device_id(), lump_id(0)..lump_id(3), 0)
+        .collect());
+    assert_eq!(ids, (0..3).map(lump_id).collect::<Vec<_>>());
+
+    let ids = wait!(request
+        .list_lumps_stream(device_id(), lump_id(10)..lump_id(100), 2)
+        .collect());
+    assert!(ids.is_empty());
+
+    let e = wait_err!(request
+        .list_lumps_stream(DeviceId::new("bar"), lump_id(0)..lump_id(100), 2)
+        .collect());
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn response_meta_works() {
+    let client = start_server(1987);
+    let request = client.request();
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+
+    let (created, meta) = wait!(request.put_lump_with_meta(device_id(), lump_id(0), data));
+    assert!(created);
+    let meta = meta.unwrap();
+    assert_eq!(meta.server_version, env!("CARGO_PKG_VERSION"));
+    assert!(meta.processing_time < Duration::from_secs(10));
+
+    let (data, meta) = wait!(request.get_lump_with_meta(device_id(), lump_id(0)));
+    assert_eq!(data.map(|d| d.into_bytes()), Some(b"foo".to_vec()));
+    assert!(meta.is_some());
+
+    let (header, meta) = wait!(request.head_lump_with_meta(device_id(), lump_id(1)));
+    assert!(header.is_none());
+    assert!(meta.is_some());
+
+    let (deleted, meta) = wait!(request.delete_lump_with_meta(device_id(), lump_id(0)));
+    assert!(deleted);
+    assert!(meta.is_some());
+
+    // 通常のメソッドも、引き続き利用可能
+    assert!(!wait!(request.delete_lump(device_id(), lump_id(0))));
+
+    // エラー時にはメタ情報は得られない
+    let e = wait_err!(request.get_lump_with_meta(DeviceId::new("bar"), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn trace_id_works() {
+    use trackable::Trackable;
+
+    let client = start_server(1988);
+    let has_trace_id = |e: &cannyls_rpc::Error, trace_id: &str| {
+        let expected = format!("trace_id={}", trace_id);
+        e.history()
+            .is_some_and(|h| h.events().iter().any(|l| l.message() == expected))
+    };
+
+    // 成功時の結果には影響しない
+    let mut request = client.request();
+    request.trace_id("foo");
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(request.put_lump(device_id(), lump_id(0), data)));
+
+    // デバイスの検索時のエラー
+    let e = wait_err!(request.get_lump(DeviceId::new("bar"), lump_id(0)));
+    assert!(has_trace_id(&e, "foo"), "{}", e);
+
+    // リクエストの処理中のエラー
+    let mut request = client.request();
+    request.trace_id("bar").if_exists();
+    let e = wait_err!(request.delete_lump(device_id(), lump_id(1)));
+    assert!(has_trace_id(&e, "bar"), "{}", e);
+
+    // 未指定の場合には記録されない
+    let e = wait_err!(client.request().get_lump(DeviceId::new("bar"), lump_id(0)));
+    assert!(!e.history().is_some_and(|h| h
+        .events()
+        .iter()
+        .any(|l| l.message().starts_with("trace_id="))));
+}
+
+#[test]
+fn client_metrics_works() {
+    let client = start_server(1989);
+    let server = client.server();
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(client.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data
+    )));
+    assert!(wait!(client.request().get_lump(device_id(), lump_id(0))).is_some());
+    let _ = wait_err!(client.request().get_lump(DeviceId::new("bar"), lump_id(0)));
+
+    let metrics = client.metrics().list();
+    assert_eq!(metrics.len(), 2);
+    assert_eq!(metrics[0].procedure, "cannyls.lump.get");
+    assert_eq!(metrics[0].server, server);
+    assert_eq!(metrics[0].succeeded, 1);
+    assert_eq!(metrics[0].failed, vec![(ErrorKind::InvalidInput, 1)]);
+    assert_eq!(metrics[0].transport_failed, 0);
+    assert_eq!(metrics[0].latency.count(), 2);
+    assert_eq!(metrics[1].procedure, "cannyls.lump.put");
+    assert_eq!(metrics[1].total(), 1);
+
+    // クローン間で共有される
+    let _ = wait!(client.clone().request().head_lump(device_id(), lump_id(0)));
+    assert!(client.metrics().get("cannyls.lump.head", server).is_some());
+
+    // 集計器を差し替えた場合
+    let mut other = client.clone();
+    other.set_metrics(ClientMetrics::new());
+    let _ = wait!(other.request().head_lump(device_id(), lump_id(0)));
+    assert_eq!(other.metrics().list().len(), 1);
+    assert_eq!(client.metrics().reset().len(), 3);
+    assert!(client.metrics().list().is_empty());
+}
+
+#[test]
+fn server_metrics_works() {
+    let mut gatherer = Gatherer::new();
+    let registry = gatherer.registry();
+    let client = start_server_with(1990, move |mut server, builder| {
+        server.metric_builder(MetricBuilder::with_registry(registry));
+        server.register(builder)
+    });
+    let data = LumpData::new(vec![0; 2000]).unwrap();
+    assert!(wait!(client.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data
+    )));
+    assert!(wait!(client.request().get_lump(device_id(), lump_id(0))).is_some());
+    let _ = wait_err!(client.request().get_lump(DeviceId::new("bar"), lump_id(0)));
+
+    let text = gatherer.gather().to_text();
+    let has = |line: &str| text.lines().any(|l| l == line);
+    assert!(
+        has(r#"cannyls_rpc_server_requests_total{procedure="cannyls.lump.put"} 1"#),
+        "{}",
+        text
+    );
+    assert!(has(
+        r#"cannyls_rpc_server_requests_total{procedure="cannyls.lump.get"} 2"#
+    ));
+    assert!(has(
+        r#"cannyls_rpc_server_errors_total{kind="InvalidInput",procedure="cannyls.lump.get"} 1"#
+    ));
+    assert!(has(
+        r#"cannyls_rpc_server_handler_duration_seconds_count{procedure="cannyls.lump.get"} 1"#
+    ));
+    assert!(has(
+        r#"cannyls_rpc_server_payload_bytes_sum{procedure="cannyls.lump.put"} 2000"#
+    ));
+    assert!(has(
+        r#"cannyls_rpc_server_payload_bytes_sum{procedure="cannyls.lump.get"} 2000"#
+    ));
+}
+
+#[test]
+fn access_log_sampling_works() {
+    let records = Records::default();
+    let logger = Logger::root(Capture(records.clone()), o!());
+    let client = start_server_with_logger(1992, logger, |mut server, builder| {
+        server.enable_access_log().access_log_sampling(1000);
+        server.register(builder)
+    });
+    for i in 0..10 {
+        let data = LumpData::new(b"foo".to_vec()).unwrap();
+        assert!(wait!(client.request().put_lump(
+            device_id(),
+            lump_id(i),
+            data
+        )));
+    }
+    let mut request = client.request();
+    request.if_exists();
+    let _ = wait_err!(request.delete_lump(device_id(), lump_id(100)));
+
+    // 成功したリクエストは間引かれるが、失敗したものは全て出力される
+    let records = records.lock().unwrap();
+    let count = |message: &str| {
+        records
+            .iter()
+            .filter(|(m, _)| m.starts_with(message))
+            .count()
+    };
+    assert!(count("RPC succeeded") <= 1);
+    assert_eq!(count("RPC failed"), 1);
+}
+
+#[test]
+fn slow_request_log_works() {
+    let records = Records::default();
+    let logger = Logger::root(Capture(records.clone()), o!());
+    let client = start_server_with_logger(1991, logger, |mut server, builder| {
+        server.slow_request_threshold(Duration::from_secs(0));
+        server.register(builder)
+    });
+    let mut request = client.request();
+    request.deadline(Deadline::Within(Duration::from_secs(10)));
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(request.put_lump(device_id(), lump_id(1), data)));
+
+    let records = records.lock().unwrap();
+    let (message, kvs) = records
+        .iter()
+        .find(|(m, _)| m.starts_with("Slow RPC"))
+        .cloned()
+        .expect("No slow request record");
+    assert!(message.contains("deadline=Within(10s)"), "{}", message);
+    let kvs = kvs.into_iter().collect::<std::collections::HashMap<_, _>>();
+    assert_eq!(kvs["procedure"], "cannyls.lump.put");
+    assert_eq!(kvs["device_id"], "foo");
+    assert_eq!(kvs["lump_id"], lump_id(1).to_string());
+    assert_eq!(kvs["succeeded"], "true");
+    assert!(kvs.contains_key("elapsed_us"));
+
+    // アクセスログは無効のまま
+    assert!(!records.iter().any(|(m, _)| m.starts_with("RPC succeeded")));
+}
+
+#[test]
+fn import_session_works() {
+    let client = start_server(1955);
+    let request = client.request();
+    let data = |s: &str| LumpData::new(s.into()).unwrap();
+
+    let session = wait!(request.open_import_session(device_id()));
+    let session_id = session.session_id;
+    assert_eq!(session.device_id, device_id());
+    assert_eq!(session.next_batch_seq, 0);
+
+    let batch0 = vec![(lump_id(0), data("foo")), (lump_id(1), data("bar"))];
+    let status = wait!(request.import_lumps(session_id, 0, batch0.clone()));
+    assert_eq!(status.next_batch_seq, 1);
+    assert_eq!(status.imported_lumps, 2);
+    assert_eq!(status.imported_bytes, 6);
+
+    // 処理済みのバッチの再送は無視される
+    let status = wait!(request.import_lumps(session_id, 0, batch0));
+    assert_eq!(status.next_batch_seq, 1);
+    assert_eq!(status.imported_lumps, 2);
+
+    // 番号を飛ばすことはできない
+    let e = wait_err!(request.import_lumps(session_id, 2, vec![(lump_id(9), data("x"))]));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    // 中断後の再開
+    let status = wait!(request.import_session_status(session_id));
+    assert_eq!(status.next_batch_seq, 1);
+    wait!(request.import_lumps(session_id, 1, vec![(lump_id(2), data("baz"))]));
+
+    let summary = wait!(request.commit_import_session(session_id));
+    assert_eq!(summary.next_batch_seq, 2);
+    assert_eq!(summary.imported_lumps, 3);
+    assert_eq!(summary.imported_bytes, 9);
+
+    assert_eq!(
+        wait!(request.list_lumps(device_id())),
+        [lump_id(0), lump_id(1), lump_id(2)]
+    );
+    assert!(wait!(request.get_lump(device_id(), lump_id(9))).is_none());
+
+    let e = wait_err!(request.import_session_status(session_id));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(request.open_import_session(DeviceId::new("bar")));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn delete_range_bounded_works() {
+    let client = start_server(1957);
+    let request = client.request();
+
+    for i in 0..5 {
+        let data = LumpData::new(b"foo".to_vec()).unwrap();
+        assert!(wait!(request.put_lump(device_id(), lump_id(i), data)));
+    }
+
+    let mut start = lump_id(1);
+    let mut deleted = Vec::new();
+    loop {
+        let chunk = wait!(request.delete_range_bounded(device_id(), start..lump_id(100), 2));
+        assert!(chunk.deleted.len() <= 2);
+        deleted.extend(chunk.deleted);
+        if let Some(next) = chunk.next {
+            start = next;
+        } else {
+            break;
+        }
+    }
+    assert_eq!(deleted, [lump_id(1), lump_id(2), lump_id(3), lump_id(4)]);
+    assert_eq!(wait!(request.list_lumps(device_id())), [lump_id(0)]);
+
+    let e = wait_err!(request.delete_range_bounded(device_id(), lump_id(0)..lump_id(100), 0));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn copy_range_works() {
+    let client = start_server(1956);
+    let request = client.request();
+    let data = |s: &str| LumpData::new(s.into()).unwrap();
+
+    let dst = DeviceId::new("dst");
+    assert!(wait!(
+        request.provision_device(DeviceSpec::memory(dst.clone(), 1024 * 1024))
+    ));
+    for _ in 0..100 {
+        if wait!(request.device_status(dst.clone())).is_registered() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    for i in 0..4 {
+        assert!(wait!(request.put_lump(
+            device_id(),
+            lump_id(i),
+            data(&format!("data{}", i))
+        )));
+    }
+    let copied = wait!(request.copy_range(device_id(), dst.clone(), lump_id(1)..lump_id(3)));
+    assert_eq!(copied, [lump_id(1), lump_id(2)]);
+    assert_eq!(
+        wait!(request.list_lumps(dst.clone())),
+        [lump_id(1), lump_id(2)]
+    );
+    assert_eq!(
+        wait!(request.get_lump(dst.clone(), lump_id(2))),
+        Some(b"data2".to_vec())
+    );
+
+    let e = wait_err!(request.copy_range(device_id(), device_id(), lump_id(0)..lump_id(3)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e =
+        wait_err!(request.copy_range(device_id(), DeviceId::new("bar"), lump_id(0)..lump_id(3)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn get_lumps_concurrent_works() {
+    let client = start_server(1939);
+    let request = client.request();
+    for i in 0..3 {
+        let data = LumpData::new(format!("data-{}", i).into_bytes()).unwrap();
+        assert!(wait!(request.put_lump(device_id(), lump_id(i), data)));
+    }
+
+    let ids = vec![lump_id(2), lump_id(0), lump_id(3), lump_id(1), lump_id(0)];
+    let results = wait!(request.get_lumps_concurrent(device_id(), ids, 2));
+    assert_eq!(
+        results.keys().cloned().collect::<Vec<_>>(),
+        vec![lump_id(0), lump_id(1), lump_id(2), lump_id(3)]
+    );
+    for i in 0..3 {
+        let data = track_try_unwrap!(results[&lump_id(i)].clone());
+        assert_eq!(data, Some(format!("data-{}", i).into_bytes()));
+    }
+    assert_eq!(track_try_unwrap!(results[&lump_id(3)].clone()), None);
+
+    // 存在しないデバイスの場合には、個々の結果がエラーとなる
+    let results = wait!(request.get_lumps_concurrent(DeviceId::new("bar"), vec![lump_id(0)], 0));
+    assert_eq!(
+        *results[&lump_id(0)].as_ref().err().unwrap().kind(),
+        ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn get_lump_to_writer_works() {
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct BrokenWriter;
+    impl Write for BrokenWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken"))
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let client = start_server(1940);
+    let request = client.request();
+    let bytes = (0..1024 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+    let data = LumpData::new(bytes.clone()).unwrap();
+    assert!(wait!(request.put_lump(device_id(), lump_id(0), data)));
+
+    let buf = SharedBuf::default();
+    let written = wait!(request.get_lump_to_writer(device_id(), lump_id(0), buf.clone()));
+    assert_eq!(written, Some(bytes.len() as u64));
+    assert_eq!(*buf.0.lock().unwrap(), bytes);
+
+    // 存在しないlump
+    let buf = SharedBuf::default();
+    let written = wait!(request.get_lump_to_writer(device_id(), lump_id(1), buf.clone()));
+    assert_eq!(written, None);
+    assert!(buf.0.lock().unwrap().is_empty());
+
+    // 書き込み先のエラー
+    let e = wait_err!(request.get_lump_to_writer(device_id(), lump_id(0), BrokenWriter));
+    assert_eq!(*e.kind(), ErrorKind::Other);
+
+    // 書き込み先のエラーは、後続のリクエストに影響しない
+    let written = wait!(request.get_lump_to_writer(device_id(), lump_id(0), io::sink()));
+    assert_eq!(written, Some(bytes.len() as u64));
+}
+
+#[test]
+fn verify_checksums_works() {
+    let client = start_server(1943);
+    let mut request = client.request();
+    request.verify_checksums(true);
+
+    let bytes = (0..1024 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+    let data = LumpData::new(bytes.clone()).unwrap();
+    assert!(wait!(request.put_lump(device_id(), lump_id(0), data)));
+    assert_eq!(
+        wait!(request.get_lump(device_id(), lump_id(0))),
+        Some(bytes.clone())
+    );
+    assert_eq!(wait!(request.get_lump(device_id(), lump_id(1))), None);
+
+    // チェックサムの不一致
+    let put = rpc::PutLumpRequest {
+        device_id: device_id(),
+        lump_id: lump_id(1),
+        lump_data: LumpData::new(bytes).unwrap(),
+        options: rpc::RequestOptions {
+            deadline: Deadline::Infinity,
+            max_queue_len: None,
+            prioritized: false,
+            journal_sync: false,
+            verbose_errors: false,
+            response_meta: false,
+            trace_id: None,
+            signature: None,
+        },
+        precondition: None,
+        checksum: Some(0),
+    };
+    let result = wait!(track_any_err!(request.call::<rpc::PutLumpRpc>(put)));
+    assert_eq!(*result.unwrap_err().kind(), ErrorKind::StorageCorrupted);
+    assert_eq!(wait!(request.get_lump(device_id(), lump_id(1))), None);
+}
+
+#[test]
+fn put_lump_from_reader_works() {
+    let client = start_server(1941);
+    let request = client.request();
+    let bytes = (0..1024 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+
+    let reader = io::Cursor::new(bytes.clone());
+    let len = bytes.len() as u64;
+    assert!(wait!(request.put_lump_from_reader(
+        device_id(),
+        lump_id(0),
+        reader,
+        len
+    )));
+    assert_eq!(
+        wait!(request.get_lump(device_id(), lump_id(0))),
+        Some(bytes.clone())
+    );
+
+    // 上書き(先頭の一部のみ)
+    let reader = io::Cursor::new(bytes.clone());
+    assert!(!wait!(request.put_lump_from_reader(
+        device_id(),
+        lump_id(0),
+        reader,
+        10
+    )));
+    assert_eq!(
+        wait!(request.get_lump(device_id(), lump_id(0))),
+        Some(bytes[..10].to_vec())
+    );
+
+    // 読み込み元のデータが足りない
+    let reader = io::Cursor::new(bytes.clone());
+    let len = bytes.len() as u64 + 1;
+    let e = wait_err!(request.put_lump_from_reader(device_id(), lump_id(1), reader, len));
+    assert_eq!(*e.kind(), ErrorKind::Other);
+}
+
+#[test]
+fn registry_metrics_works() {
+    let mut executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+    let mut gatherer = Gatherer::new();
+    let registry = DeviceRegistry::with_metric_builder(
+        Logger::root(Discard, o!()),
+        MetricBuilder::with_registry(gatherer.registry()),
+    );
+    let handle = registry.handle();
+    executor.spawn(registry.map_err(|e| panic!("{}", e)));
+    let metrics = handle.metrics();
+
+    let nvm = MemoryNvm::new(vec![0; 1024 * 1024]);
+    let storage = track_try_unwrap!(StorageBuilder::new().create(nvm));
+    let device = DeviceBuilder::new().spawn(|| Ok(storage));
+    track_try_unwrap!(handle.put_device(device_id(), device));
+
+    // ストレージの初期化に失敗するデバイス
+    let broken = DeviceBuilder::new()
+        .spawn::<_, MemoryNvm>(|| Err(cannyls::Error::from(ErrorKind::StorageCorrupted)));
+    track_try_unwrap!(handle.put_device(DeviceId::new("broken"), broken));
+    while metrics.abnormal_terminations() == 0 {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+    assert_eq!(metrics.devices(), 2);
+    assert_eq!(metrics.running_devices(), 1);
+    assert_eq!(metrics.commands(), 2);
+
+    track_try_unwrap!(handle.stop_device(device_id(), Deadline::Immediate));
+    track_try_unwrap!(handle.delete_device(DeviceId::new("broken")));
+    while metrics.normal_terminations() == 0 || metrics.devices() == 2 {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+    assert_eq!(metrics.devices(), 1);
+    assert_eq!(metrics.running_devices(), 0);
+    assert_eq!(metrics.abnormal_terminations(), 1);
+    assert_eq!(metrics.commands(), 4);
+
+    let text = gatherer.gather().to_text();
+    assert!(text
+        .lines()
+        .any(|l| l == r#"cannyls_rpc_registry_device_terminations_total{result="abnormal"} 1"#));
+}
+
+#[test]
+fn registry_events_works() {
+    let mut executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+    let registry = DeviceRegistry::new(Logger::root(Discard, o!()));
+    let handle = registry.handle();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    {
+        let events = Arc::clone(&events);
+        executor.spawn(
+            handle
+                .events()
+                .map_err(|e| panic!("{}", e))
+                .for_each(move |e| {
+                    let event = match e {
+                        RegistryEvent::DeviceAdded(id) => format!("added:{}", id.as_str()),
+                        RegistryEvent::DeviceRemoved(id) => format!("removed:{}", id.as_str()),
+                        RegistryEvent::DeviceTerminated { device_id, error } => {
+                            format!("terminated:{}:{}", device_id.as_str(), error.is_none())
+                        }
+                    };
+                    events.lock().unwrap().push(event);
+                    Ok(())
+                }),
+        );
+    }
+    executor.spawn(registry.map_err(|e| panic!("{}", e)));
+    let wait_events = |executor: &mut InPlaceExecutor, n: usize| {
+        while events.lock().unwrap().len() < n {
+            track_try_unwrap!(track_any_err!(executor.run_once()));
+        }
+    };
+
+    let new_device = || {
+        let nvm = MemoryNvm::new(vec![0; 1024 * 1024]);
+        let storage = track_try_unwrap!(StorageBuilder::new().create(nvm));
+        DeviceBuilder::new().spawn(|| Ok(storage))
+    };
+    track_try_unwrap!(handle.put_device(device_id(), new_device()));
+    track_try_unwrap!(handle.put_device(device_id(), new_device()));
+    wait_events(&mut executor, 3);
+
+    let broken = DeviceBuilder::new()
+        .spawn::<_, MemoryNvm>(|| Err(cannyls::Error::from(ErrorKind::StorageCorrupted)));
+    track_try_unwrap!(handle.put_device(DeviceId::new("broken"), broken));
+    wait_events(&mut executor, 5);
+
+    track_try_unwrap!(handle.stop_device(device_id(), Deadline::Immediate));
+    wait_events(&mut executor, 6);
+    track_try_unwrap!(handle.delete_device(DeviceId::new("broken")));
+    wait_events(&mut executor, 7);
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "added:foo",
+            "removed:foo",
+            "added:foo",
+            "added:broken",
+            "terminated:broken:false",
+            "terminated:foo:true",
+            "removed:broken",
+        ]
+    );
+}
+
+#[test]
+fn deregister_terminated_devices_works() {
+    let mut executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+    let mut registry = DeviceRegistry::new(Logger::root(Discard, o!()));
+    registry.deregister_terminated_devices(Duration::from_millis(100));
+    let handle = registry.handle();
+    executor.spawn(registry.map_err(|e| panic!("{}", e)));
+
+    let broken = DeviceBuilder::new()
+        .spawn::<_, MemoryNvm>(|| Err(cannyls::Error::from(ErrorKind::StorageCorrupted)));
+    track_try_unwrap!(handle.put_device(DeviceId::new("broken"), broken));
+    while handle.metrics().abnormal_terminations() == 0 {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+
+    // 猶予期間中は登録されたまま
+    assert!(handle.contains_device(&DeviceId::new("broken")));
+    while handle.contains_device(&DeviceId::new("broken")) {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        handle.get_device("broken").err().map(|e| *e.kind()),
+        Some(ErrorKind::InvalidInput)
+    );
+    assert_eq!(handle.metrics().devices(), 0);
+}
+
+#[test]
+fn many_devices_works() {
+    let mut executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+    let registry = DeviceRegistry::new(Logger::root(Discard, o!()));
+    let handle = registry.handle();
+    executor.spawn(registry.map_err(|e| panic!("{}", e)));
+
+    // 即座に停止するデバイス群(停止後も登録は維持される)
+    for i in 0..300 {
+        let device = DeviceBuilder::new()
+            .spawn::<_, MemoryNvm>(|| Err(cannyls::Error::from(ErrorKind::StorageCorrupted)));
+        track_try_unwrap!(handle.put_device(DeviceId::new(format!("dev{}", i)), device));
+    }
+    while handle.snapshot().len() < 300 {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+    let snapshot = handle.snapshot();
+    for i in (0..300).step_by(2) {
+        track_try_unwrap!(handle.delete_device(DeviceId::new(format!("dev{}", i))));
+    }
+    while handle.snapshot().len() > 150 {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+
+    // 取得済みのスナップショットは、その後の削除の影響を受けない
+    assert_eq!(snapshot.device_ids().count(), 300);
+    assert!(snapshot.contains_device("dev0"));
+
+    let snapshot = handle.snapshot();
+    let mut ids = snapshot
+        .device_ids()
+        .map(|id| id.as_str().to_owned())
+        .collect::<Vec<_>>();
+    ids.sort();
+    let mut expected = (1..300)
+        .step_by(2)
+        .map(|i| format!("dev{}", i))
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(ids, expected);
+    assert!(!snapshot.contains_device("dev0"));
+    assert!(handle.get_device("dev1").is_ok());
+    assert!(handle.get_device("dev2").is_err());
+    assert_eq!(track_try_unwrap!(handle.list_devices()).len(), 150);
+}
+
+#[test]
+fn provision_works() {
+    let mut executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+    let registry = DeviceRegistry::new(Logger::root(Discard, o!()));
+    let handle = registry.handle();
+    executor.spawn(registry.map_err(|e| panic!("{}", e)));
+
+    let dir = track_try_unwrap!(track_any_err!(TempDir::new("cannyls_rpc_test")));
+    let existing = dir.path().join("existing.lusf");
+    {
+        let nvm = track_try_unwrap!(FileNvm::create(&existing, 1024 * 1024));
+        let _ = track_try_unwrap!(StorageBuilder::new().create(nvm));
+    }
+
+    let mut labeled = DeviceSpec::memory(DeviceId::new("mem"), 1024 * 1024);
+    labeled.labels.insert("zone".to_owned(), "a".to_owned());
+    let specs = vec![
+        DeviceSpec::file(
+            DeviceId::new("new"),
+            dir.path().join("new.lusf"),
+            1024 * 1024,
+        ),
+        DeviceSpec::file(DeviceId::new("existing"), &existing, 1024 * 1024),
+        labeled,
+        DeviceSpec::memory(DeviceId::new("too_small"), 0),
+    ];
+    let results = provision(&handle, specs);
+    let results = results
+        .into_iter()
+        .map(|(id, r)| (id.into_string(), r.ok()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        results,
+        vec![
+            ("new".to_owned(), Some(true)),
+            ("existing".to_owned(), Some(false)),
+            ("mem".to_owned(), Some(true)),
+            ("too_small".to_owned(), None),
+        ]
+    );
+
+    while handle.snapshot().len() < 3 {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+    let snapshot = handle.snapshot();
+    assert!(!snapshot.contains_device("too_small"));
+    for id in &["new", "existing", "mem"] {
+        assert!(snapshot.get_storage_metrics(*id).is_ok());
+    }
+    assert!(track_try_unwrap!(snapshot.get_device_labels("new")).is_empty());
+    let labels = track_try_unwrap!(handle.get_device_labels("mem"));
+    assert_eq!(labels.get("zone").map(|v| v.as_str()), Some("a"));
+}
+
+#[test]
+fn load_config_works() {
+    let mut executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+    let registry = DeviceRegistry::new(Logger::root(Discard, o!()));
+    let handle = registry.handle();
+
+    let dir = track_try_unwrap!(track_any_err!(TempDir::new("cannyls_rpc_test")));
+    let config = dir.path().join("app.toml");
+    let lusf = dir.path().join("file.lusf");
+    let text = format!(
+        r#"
+workers = 4
+
+[[device]]
+id = "file"
+path = {:?}
+capacity = 1_048_576
+
+[[device]]
+id = "mem"
+memory = true
+capacity = 1_048_576
+labels = {{ zone = "a" }}
+
+[[device]]
+id = "too_small"
+memory = true
+capacity = 1
+"#,
+        lusf
+    );
+    std::fs::write(&config, &text).unwrap();
+
+    let results = track_try_unwrap!(registry.load_config(&config));
+    let results = results
+        .into_iter()
+        .map(|(id, r)| (id.into_string(), r.ok()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        results,
+        vec![
+            ("file".to_owned(), Some(true)),
+            ("mem".to_owned(), Some(true)),
+            ("too_small".to_owned(), None),
+        ]
+    );
+    executor.spawn(registry.map_err(|e| panic!("{}", e)));
+    while handle.snapshot().len() < 2 {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+    let labels = track_try_unwrap!(handle.get_device_labels("mem"));
+    assert_eq!(labels.get("zone").map(|v| v.as_str()), Some("a"));
+
+    // 検証に失敗した場合には、デバイスは一つも構築されない
+    let registry = DeviceRegistry::new(Logger::root(Discard, o!()));
+    let handle = registry.handle();
+    let text = format!(
+        "{}[[device]]\nid = \"mem\"\nmemory = true\ncapacity = 1\n",
+        text
+    );
+    std::fs::write(&config, text).unwrap();
+    assert!(registry.load_config(&config).is_err());
+    assert!(registry
+        .load_config(dir.path().join("missing.toml"))
+        .is_err());
+    executor.spawn(registry.map_err(|e| panic!("{}", e)));
+    track_try_unwrap!(track_any_err!(executor.run_once()));
+    assert!(handle.snapshot().is_empty());
+}
+
+#[test]
+fn reload_config_works() {
+    let mut executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+    let registry = DeviceRegistry::new(Logger::root(Discard, o!()));
+    let handle = registry.handle();
+    executor.spawn(registry.map_err(|e| panic!("{}", e)));
+
+    let dir = track_try_unwrap!(track_any_err!(TempDir::new("cannyls_rpc_test")));
+    let config = dir.path().join("devices.toml");
+    let write_config = |ids: &[&str]| {
+        let text = ids
+            .iter()
+            .map(|id| {
+                format!(
+                    "[[device]]\nid = {:?}\nmemory = true\ncapacity = 1_048_576\n",
+                    id
+                )
+            })
+            .collect::<String>();
+        std::fs::write(&config, text).unwrap();
+    };
+    let device_ids = |handle: &DeviceRegistryHandle| {
+        let mut ids = handle
+            .snapshot()
+            .device_ids()
+            .map(|id| id.as_str().to_owned())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    };
+    macro_rules! reload {
+        () => {
+            track_try_unwrap!(track_any_err!(
+                executor.run_future(handle.reload_config(&config))
+            ))
+        };
+    }
+
+    // 設定ファイル以外の経路で登録されたデバイスは、削除の対象とならない
+    // (登録の直後に再読み込みした場合でも、登録済みとして扱われる)
+    let spec = DeviceSpec::memory(DeviceId::new("runtime"), 1024 * 1024);
+    assert!(provision(&handle, vec![spec])[0].1.is_ok());
+
+    write_config(&["a", "b"]);
+    let reload = track_try_unwrap!(reload!());
+    assert_eq!(reload.added.len(), 2);
+    assert!(reload.removed.is_empty());
+    while device_ids(&handle) != ["a", "b", "runtime"] {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+
+    // 処理待ちのコマンドは、停止前に全て処理される
+    let a = track_try_unwrap!(handle.get_device("a"));
+    let put = a
+        .request()
+        .put(lump_id(0), track_try_unwrap!(LumpData::new(vec![1; 10])));
+
+    write_config(&["b", "c"]);
+    let reload = track_try_unwrap!(reload!());
+    let added = reload
+        .added
+        .iter()
+        .map(|(id, r)| (id.as_str(), r.is_ok()))
+        .collect::<Vec<_>>();
+    assert_eq!(added, [("c", true)]);
+    assert_eq!(reload.removed, [DeviceId::new("a")]);
+    while device_ids(&handle) != ["b", "c", "runtime"] {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+    assert!(track_try_unwrap!(track_any_err!(executor.run_future(put))).is_ok());
+
+    // 設定ファイルに追加されたデバイスが既に登録済みの場合には、そのまま維持される
+    write_config(&["b", "c", "runtime"]);
+    let reload = track_try_unwrap!(reload!());
+    assert!(reload.added.is_empty());
+    assert!(reload.removed.is_empty());
+
+    // 不正な設定ファイルの場合には、何も変更されない
+    std::fs::write(&config, "[[device]]\nid = \"d\"\n").unwrap();
+    assert!(reload!().is_err());
+    track_try_unwrap!(track_any_err!(executor.run_once()));
+    assert_eq!(device_ids(&handle), ["b", "c", "runtime"]);
+
+    // 停止済みのデバイスは即座に削除される
+    track_try_unwrap!(handle.stop_device(DeviceId::new("b"), Deadline::Immediate));
+    while handle.metrics().running_devices() != 2 {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+    track_try_unwrap!(handle.retire_device(DeviceId::new("b"), Deadline::Infinity));
+    track_try_unwrap!(track_any_err!(executor.run_once()));
+    assert_eq!(device_ids(&handle), ["c", "runtime"]);
+}
+
+#[test]
+fn reload_config_recreates_retiring_device() {
+    let mut executor = track_try_unwrap!(track_any_err!(InPlaceExecutor::new()));
+    let registry = DeviceRegistry::new(Logger::root(Discard, o!()));
+    let handle = registry.handle();
+    executor.spawn(registry.map_err(|e| panic!("{}", e)));
+
+    let dir = track_try_unwrap!(track_any_err!(TempDir::new("cannyls_rpc_test")));
+    let config = dir.path().join("devices.toml");
+    let write_config = |ids: &[&str]| {
+        let text = ids
+            .iter()
+            .map(|id| {
+                format!(
+                    "[[device]]\nid = {:?}\nmemory = true\ncapacity = 1_048_576\n",
+                    id
+                )
+            })
+            .collect::<String>();
+        std::fs::write(&config, text).unwrap();
+    };
+
+    write_config(&["a", "b"]);
+    let reload = handle.reload_config(&config);
+    let reload = track_try_unwrap!(track_try_unwrap!(track_any_err!(
+        executor.run_future(reload)
+    )));
+    assert_eq!(reload.added.len(), 2);
+    while handle.metrics().running_devices() != 2 {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+
+    // 一回目の再読み込みで"b"が停止処理中になり、二回目の再読み込みで再び追加される
+    // (二つのコマンドはレジストリ内で連続して処理されるので、二回目の時点では"b"は停止処理中)
+    write_config(&["a"]);
+    let first = handle.reload_config(&config);
+    write_config(&["a", "b"]);
+    let second = handle.reload_config(&config);
+
+    let first = track_try_unwrap!(track_try_unwrap!(
+        track_any_err!(executor.run_future(first))
+    ));
+    assert!(first.added.is_empty());
+    assert_eq!(first.removed, [DeviceId::new("b")]);
+
+    // 停止処理中のデバイスは未登録とみなされ、削除を待ってから構築し直される
+    let second = track_try_unwrap!(track_try_unwrap!(track_any_err!(
+        executor.run_future(second)
+    )));
+    let added = second
+        .added
+        .iter()
+        .map(|(id, r)| (id.as_str(), r.is_ok()))
+        .collect::<Vec<_>>();
+    assert_eq!(added, [("b", true)]);
+    assert!(second.removed.is_empty());
+
+    while handle.metrics().running_devices() != 2 {
+        track_try_unwrap!(track_any_err!(executor.run_once()));
+    }
+    assert!(handle.contains_device(&DeviceId::new("b")));
+    let b = track_try_unwrap!(handle.get_device("b"));
+    let put = b
+        .request()
+        .put(lump_id(0), track_try_unwrap!(LumpData::new(vec![1; 10])));
+    assert!(track_try_unwrap!(track_any_err!(executor.run_future(put))).is_ok());
+}
+
+type Records = Arc<Mutex<Vec<(String, Vec<(String, String)>)>>>;
+
+// ログレコードのメッセージとキー・値の組を記録するためのドレイン.
+struct Capture(Records);
+impl Drain for Capture {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<(), Never> {
+        let mut kvs = KeyValues(Vec::new());
+        record.kv().serialize(record, &mut kvs).unwrap();
+        values.serialize(record, &mut kvs).unwrap();
+        let message = record.msg().to_string();
+        self.0.lock().unwrap().push((message, kvs.0));
+        Ok(())
+    }
+}
+
+struct KeyValues(Vec<(String, String)>);
+impl slog::Serializer for KeyValues {
+    fn emit_arguments(&mut self, key: slog::Key, value: &fmt::Arguments) -> slog::Result {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+#[test]
+fn access_log_works() {
+    let records = Records::default();
+    let logger = Logger::root(Capture(records.clone()), o!());
+    let client = start_server_with_logger(1942, logger, |mut server, builder| {
+        server.enable_access_log();
+        server.register(builder)
+    });
+    let request = client.request();
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(request.put_lump(device_id(), lump_id(1), data)));
+    let _ = wait_err!(request
+        .clone()
+        .trace_id("trace-1")
+        .get_lump(DeviceId::new("bar"), lump_id(2)));
+    let _ = wait!(request.delete_range(device_id(), lump_id(0)..lump_id(10)));
+
+    let find = |message: &str, procedure: &str| {
+        let records = records.lock().unwrap();
+        let (_, kvs) = records
+            .iter()
+            .find(|(m, kvs)| {
+                m.starts_with(message)
+                    && kvs.contains(&("procedure".to_owned(), procedure.to_owned()))
+            })
+            .cloned()
+            .unwrap_or_else(|| panic!("No such record: {}, {}", message, procedure));
+        kvs.into_iter().collect::<std::collections::HashMap<_, _>>()
+    };
+
+    let kvs = find("RPC succeeded", "cannyls.lump.put");
+    assert_eq!(kvs["device_id"], "foo");
+    assert_eq!(kvs["lump_id"], lump_id(1).to_string());
+    assert!(kvs.contains_key("request_id"));
+    assert!(kvs.contains_key("elapsed_us"));
+    assert_eq!(kvs["deadline"], "Infinity");
+    assert!(!kvs.contains_key("trace_id"));
+
+    let kvs = find("RPC failed", "cannyls.lump.get");
+    assert_eq!(kvs["trace_id"], "trace-1");
+    assert!(kvs.contains_key("deadline"));
+    assert_eq!(kvs["device_id"], "bar");
+    assert_eq!(kvs["lump_id"], lump_id(2).to_string());
+    assert_eq!(kvs["error_kind"], "InvalidInput");
+    assert!(!kvs.contains_key("request_id"));
+
+    let kvs = find("RPC succeeded", "cannyls.lump.delete_range");
+    assert_eq!(kvs["range_start"], lump_id(0).to_string());
+    assert_eq!(kvs["range_end"], lump_id(10).to_string());
+}
+
+#[test]
+fn server_interceptor_works() {
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+    impl ServerInterceptor for Recorder {
+        fn before_dispatch(&self, context: &RequestContext) -> cannyls::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("before {}", context.procedure));
+            if context.procedure == "cannyls.lump.delete" {
+                track_panic!(ErrorKind::InvalidInput, "Deletion is not allowed");
+            }
+            Ok(())
+        }
+
+        fn after_reply(&self, context: &RequestContext, result: Result<(), &cannyls::Error>) {
+            let result = result.map_err(|e| format!("{:?}", e.kind()));
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("after {} {:?}", context.procedure, result));
+        }
+    }
+
+    let recorder = Recorder::default();
+    let calls = recorder.clone();
+    let client = start_server_with(1993, move |mut server, builder| {
+        server.add_interceptor(recorder);
+        server.register(builder)
+    });
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(client.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data
+    )));
+    let e = wait_err!(client.request().delete_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert!(wait!(client.request().head_lump(device_id(), lump_id(0))).is_some());
+
+    // 対象デバイスが存在しない場合には、呼び出されない
+    let _ = wait_err!(client.request().get_lump(DeviceId::new("bar"), lump_id(0)));
+
+    assert_eq!(
+        *calls.0.lock().unwrap(),
+        [
+            "before cannyls.lump.put",
+            r#"after cannyls.lump.put Ok(())"#,
+            "before cannyls.lump.delete",
+            r#"after cannyls.lump.delete Err("InvalidInput")"#,
+            "before cannyls.lump.head",
+            r#"after cannyls.lump.head Ok(())"#,
+        ]
+    );
+}
+
+#[test]
+fn client_interceptor_works() {
+    #[derive(Clone, Default)]
+    struct TraceIds(Arc<Mutex<Vec<Option<String>>>>);
+    impl ServerInterceptor for TraceIds {
+        fn before_dispatch(&self, context: &RequestContext) -> cannyls::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(context.options.trace_id.clone());
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Tracer(Arc<Mutex<Vec<String>>>);
+    impl ClientInterceptor for Tracer {
+        fn before_call(&self, context: &mut CallContext) -> cannyls::Result<()> {
+            if context.procedure == "cannyls.lump.delete" {
+                track_panic!(ErrorKind::InvalidInput, "Deletion is not allowed");
+            }
+            if let Some(ref mut options) = context.options {
+                options.trace_id = Some(format!("trace-{}", context.procedure));
+            }
+            context.rpc_options.timeout = Some(Duration::from_secs(5));
+            Ok(())
+        }
+
+        fn after_call(
+            &self,
+            context: &CallContext,
+            result: Result<(), &cannyls::Error>,
+            _elapsed: Duration,
+        ) {
+            if result.is_ok() {
+                assert_eq!(context.rpc_options.timeout, Some(Duration::from_secs(5)));
+            }
+            let result = result.map_err(|e| format!("{:?}", e.kind()));
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{} {:?}", context.procedure, result));
+        }
+    }
+
+    let trace_ids = TraceIds::default();
+    let server_trace_ids = trace_ids.clone();
+    let mut client = start_server_with(1994, move |mut server, builder| {
+        server.add_interceptor(server_trace_ids);
+        server.register(builder)
+    });
+    let tracer = Tracer::default();
+    client.add_interceptor(tracer.clone());
+
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(client.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data
+    )));
+    let e = wait_err!(client.request().delete_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let _ = wait!(client.server_info());
+
+    // 拒否されたリクエストは発行されない
+    assert_eq!(
+        *trace_ids.0.lock().unwrap(),
+        [Some("trace-cannyls.lump.put".to_owned())]
+    );
+    assert_eq!(
+        *tracer.0.lock().unwrap(),
+        [
+            "cannyls.lump.put Ok(())",
+            r#"cannyls.lump.delete Err("InvalidInput")"#,
+            "cannyls.server.info Ok(())",
+        ]
+    );
+}
+
+#[test]
+fn request_signing_works() {
+    let key = SigningKey::new("key1", b"secret");
+    let mut verifier = SignatureVerifier::new(Duration::from_secs(60));
+    verifier.add_key(key.clone());
+    let unsigned = start_server_with(1995, move |mut server, builder| {
+        server.verify_signatures(verifier);
+        server.register(builder)
+    });
+    let mut signed = unsigned.clone();
+    signed.set_signing_key(key);
+    let mut forged = unsigned.clone();
+    forged.set_signing_key(SigningKey::new("key1", b"guess"));
+    let data = |s: &str| LumpData::new(s.into()).unwrap();
+
+    let request = signed.request();
+    assert!(wait!(request.put_lump(
+        device_id(),
+        lump_id(0),
+        data("foo")
+    )));
+    assert_eq!(
+        wait!(request.get_lump(device_id(), lump_id(0))),
+        Some(b"foo".to_vec())
+    );
+    let (_, meta) = wait!(request.get_lump_with_meta(device_id(), lump_id(0)));
+    assert!(meta.is_some());
+    let results = wait!(request.put_lumps(device_id(), vec![(lump_id(1), data("bar"))]));
+    assert!(results[0].is_ok());
+    let ops = vec![
+        ScriptOp::Put(lump_id(2), data("baz")),
+        ScriptOp::Head(lump_id(0)),
+    ];
+    let results = wait!(request.execute_script(device_id(), ops));
+    assert!(results.iter().all(|r| r.is_ok()));
+    track_try_unwrap!(request.delete_lump_noack(device_id(), lump_id(2)));
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(
+        wait!(request.list_lumps(device_id())),
+        vec![lump_id(0), lump_id(1)]
+    );
+
+    // 署名がない、ないし不正なリクエストは拒否される
+    let e = wait_err!(unsigned.request().get_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(forged
+        .request()
+        .put_lump(device_id(), lump_id(0), data("bar")));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert_eq!(
+        wait!(request.get_lump(device_id(), lump_id(0))),
+        Some(b"foo".to_vec())
+    );
+
+    // 管理用のRPCは検証の対象外
+    let _ = wait!(unsigned.server_info());
+}
+
+#[test]
+fn access_control_works() {
+    let reader_key = SigningKey::new("reader", b"foo");
+    let writer_key = SigningKey::new("writer", b"bar");
+    let mut verifier = SignatureVerifier::new(Duration::from_secs(60));
+    verifier
+        .add_key(reader_key.clone())
+        .add_key(writer_key.clone());
+    let mut acl = AccessControl::new();
+    acl.grant("reader", Permissions::read_only())
+        .grant("writer", Permissions::all());
+    let anonymous = start_server_with(1996, move |mut server, builder| {
+        server.verify_signatures(verifier).access_control(acl);
+        server.register(builder)
+    });
+    let mut reader = anonymous.clone();
+    reader.set_signing_key(reader_key);
+    let mut writer = anonymous.clone();
+    writer.set_signing_key(writer_key);
+    let data = || LumpData::new(b"foo".to_vec()).unwrap();
+
+    assert!(wait!(writer.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data()
+    )));
+    assert!(wait!(reader.request().head_lump(device_id(), lump_id(0))).is_some());
+    assert_eq!(
+        wait!(reader.request().list_lumps(device_id())),
+        vec![lump_id(0)]
+    );
+
+    let e = wait_err!(reader.request().put_lump(device_id(), lump_id(1), data()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(reader.request().delete_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(reader
+        .request()
+        .delete_range(device_id(), lump_id(0)..lump_id(10)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    // 匿名のクライアントには、何も許可されていない
+    let e = wait_err!(anonymous.request().get_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    assert!(wait!(writer.request().delete_lump(device_id(), lump_id(0))));
+}
+
+#[test]
+fn device_scoped_access_control_works() {
+    let foo_key = SigningKey::new("tenant-foo", b"foo");
+    let bar_key = SigningKey::new("tenant-bar", b"bar");
+    let mut verifier = SignatureVerifier::new(Duration::from_secs(60));
+    verifier.add_key(foo_key.clone()).add_key(bar_key.clone());
+    let mut foo_permissions = Permissions::all();
+    foo_permissions.allow_device(device_id());
+    let mut bar_permissions = Permissions::all();
+    bar_permissions.allow_device_prefix("bar/");
+    let mut acl = AccessControl::new();
+    acl.grant("tenant-foo", foo_permissions)
+        .grant("tenant-bar", bar_permissions);
+    let client = start_server_with(1997, move |mut server, builder| {
+        server.verify_signatures(verifier).access_control(acl);
+        server.register(builder)
+    });
+    let mut foo = client.clone();
+    foo.set_signing_key(foo_key);
+    let mut bar = client.clone();
+    bar.set_signing_key(bar_key);
+    let data = || LumpData::new(b"foo".to_vec()).unwrap();
+
+    assert!(wait!(foo.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data()
+    )));
+    assert_eq!(
+        wait!(foo.request().list_lumps(device_id())),
+        vec![lump_id(0)]
+    );
+
+    // 他のテナントのデバイスにはアクセスできない
+    let e = wait_err!(bar.request().get_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(bar.request().put_lump(device_id(), lump_id(1), data()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert_eq!(
+        wait!(foo.request().list_lumps(device_id())),
+        vec![lump_id(0)]
+    );
+}
+
+#[test]
+fn access_control_for_device_info_works() {
+    let foo_key = SigningKey::new("tenant-foo", b"foo");
+    let bar_key = SigningKey::new("tenant-bar", b"bar");
+    let mut verifier = SignatureVerifier::new(Duration::from_secs(60));
+    verifier.add_key(foo_key.clone()).add_key(bar_key.clone());
+    let mut foo_permissions = Permissions::all();
+    foo_permissions.allow_device(device_id());
+    let mut bar_permissions = Permissions::all();
+    bar_permissions.allow_device_prefix("bar/");
+    let mut anonymous_permissions = Permissions::read_only();
+    anonymous_permissions.allow_device_prefix("bar/");
+    let mut acl = AccessControl::new();
+    acl.grant("tenant-foo", foo_permissions)
+        .grant("tenant-bar", bar_permissions)
+        .grant_anonymous(anonymous_permissions);
+    let anonymous = start_server_with(2002, move |mut server, builder| {
+        server.verify_signatures(verifier).access_control(acl);
+        server.register(builder)
+    });
+    let mut foo = anonymous.clone();
+    foo.set_signing_key(foo_key);
+    let mut bar = anonymous.clone();
+    bar.set_signing_key(bar_key);
+
+    let _ = wait!(foo.request().journal_usage(device_id()));
+    let _ = wait!(foo.request().storage_header(device_id()));
+    let _ = wait!(foo.request().device_status(device_id()));
+    let _ = wait!(foo.request().device_labels(device_id()));
+
+    // 他のテナントのデバイスの情報は参照できない
+    let e = wait_err!(bar.request().journal_usage(device_id()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(bar.request().storage_header(device_id()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(bar.request().device_status(device_id()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(bar.request().device_labels(device_id()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(bar.request().open_import_session(device_id()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    // オプションを伴わないRPCでは、匿名のクライアントが参照可能なデバイスのみが返される
+    assert!(wait!(foo.request().list_devices(false)).is_empty());
+    assert!(wait!(foo.request().check_readiness(vec![device_id()])).is_empty());
+    assert!(wait!(foo.request().metrics_snapshot(Vec::new())).is_empty());
+
+    // インポートセッションのコミットは、セッションを開始したクライアントのみが可能
+    let session = wait!(foo.request().open_import_session(device_id()));
+    let e = wait_err!(bar.request().commit_import_session(session.session_id));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(anonymous
+        .request()
+        .commit_import_session(session.session_id));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let summary = wait!(foo.request().commit_import_session(session.session_id));
+    assert_eq!(summary.imported_lumps, 0);
+}
+
+#[test]
+fn import_session_is_bound_to_signing_key() {
+    let alice_key = SigningKey::new("alice", b"foo");
+    let bob_key = SigningKey::new("bob", b"bar");
+    let mut verifier = SignatureVerifier::new(Duration::from_secs(60));
+    verifier.add_key(alice_key.clone()).add_key(bob_key.clone());
+    let mut acl = AccessControl::new();
+    acl.grant("alice", Permissions::all())
+        .grant("bob", Permissions::all())
+        .grant_anonymous(Permissions::all());
+    let anonymous = start_server_with(2009, move |mut server, builder| {
+        server.verify_signatures(verifier).access_control(acl);
+        server.register(builder)
+    });
+    let mut alice = anonymous.clone();
+    alice.set_signing_key(alice_key);
+    let mut bob = anonymous.clone();
+    bob.set_signing_key(bob_key);
+    let data = |s: &str| LumpData::new(s.into()).unwrap();
+
+    let session_id = wait!(alice.request().open_import_session(device_id())).session_id;
+
+    // 他のクライアントは、セッションの状態を参照したり、バッチを送信したりすることはできない
+    // (拒否されたバッチによって、セッションの状態が変化することもない)
+    for client in &[&bob, &anonymous] {
+        let request = client.request();
+        let e = wait_err!(request.import_lumps(session_id, 0, vec![(lump_id(0), data("x"))]));
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+        let e = wait_err!(request.import_session_status(session_id));
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+        let e = wait_err!(request.commit_import_session(session_id));
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    }
+    assert!(wait!(alice.request().list_lumps(device_id())).is_empty());
+
+    let request = alice.request();
+    let status = wait!(request.import_lumps(session_id, 0, vec![(lump_id(0), data("foo"))]));
+    assert_eq!(status.next_batch_seq, 1);
+    assert_eq!(
+        wait!(request.import_session_status(session_id)).next_batch_seq,
+        1
+    );
+    let summary = wait!(request.commit_import_session(session_id));
+    assert_eq!(summary.imported_lumps, 1);
+    assert_eq!(wait!(request.list_lumps(device_id())), [lump_id(0)]);
+}
+
+#[test]
+fn access_control_for_admin_rpc_works() {
+    let admin_key = SigningKey::new("admin", b"foo");
+    let writer_key = SigningKey::new("writer", b"bar");
+    let mut verifier = SignatureVerifier::new(Duration::from_secs(60));
+    verifier
+        .add_key(admin_key.clone())
+        .add_key(writer_key.clone());
+    let mut acl = AccessControl::new();
+    acl.grant("admin", Permissions::admin())
+        .grant("writer", Permissions::all())
+        .grant_anonymous(Permissions::read_only());
+    let anonymous = start_server_with(2006, move |mut server, builder| {
+        server
+            .verify_signatures(verifier)
+            .access_control(acl)
+            .enable_admin_rpc();
+        server.register(builder)
+    });
+    let mut admin = anonymous.clone();
+    admin.set_signing_key(admin_key);
+    let mut writer = anonymous.clone();
+    writer.set_signing_key(writer_key);
+
+    // 管理用のRPCは`Permissions::all`には含まれない
+    for client in &[&anonymous, &writer] {
+        let e = wait_err!(client
+            .request()
+            .set_queue_limits(device_id(), Some(10), None));
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+        let e = wait_err!(client.request().set_journal_sync(device_id(), true));
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+        let e = wait_err!(client
+            .request()
+            .provision_device(DeviceSpec::memory(DeviceId::new("new"), 1024 * 1024)));
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+        let e = wait_err!(client.request().delete_device(device_id()));
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    }
+
+    // 改竄された署名は拒否される
+    let mut forged = anonymous.clone();
+    forged.set_signing_key(SigningKey::new("admin", b"guess"));
+    let e = wait_err!(forged.request().delete_device(device_id()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    let settings = wait!(admin
+        .request()
+        .set_queue_limits(device_id(), Some(10), None));
+    assert_eq!(settings.default_max_queue_len, Some(10));
+    assert!(wait!(admin.request().provision_device(DeviceSpec::memory(
+        DeviceId::new("new"),
+        1024 * 1024
+    ))));
+
+    // 特定のデバイスを対象としない管理用のRPCは、匿名のクライアントとして検査される
+    let e = wait_err!(admin.request().set_log_level(Level::Debug));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(admin.request().reset_request_stats(Vec::new()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    assert!(wait!(admin.request().delete_device(device_id())));
+}
+
+#[test]
+fn loopback_works() {
+    #[derive(Clone, Default)]
+    struct Counter(Arc<Mutex<Vec<&'static str>>>);
+    impl ServerInterceptor for Counter {
+        fn before_dispatch(&self, context: &RequestContext) -> cannyls::Result<()> {
+            self.0.lock().unwrap().push(context.procedure);
+            Ok(())
+        }
+    }
+
+    let counter = Counter::default();
+    let dispatched = counter.clone();
+    let writer_key = SigningKey::new("writer", b"foo");
+    let mut verifier = SignatureVerifier::new(Duration::from_secs(60));
+    verifier.add_key(writer_key.clone());
+    let mut acl = AccessControl::new();
+    acl.grant("writer", Permissions::all());
+    let loopback = Arc::new(Mutex::new(None));
+    let loopback_handle = loopback.clone();
+    let remote = start_server_with(1998, move |mut server, builder| {
+        server
+            .add_interceptor(counter)
+            .verify_signatures(verifier)
+            .access_control(acl);
+        *loopback_handle.lock().unwrap() = Some(server.clone());
+        server.register(builder)
+    });
+    let loopback = loopback.lock().unwrap().take().unwrap();
+    for _ in 0..100 {
+        if loopback.registry().contains_device(&device_id()) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // 接続先には何も存在しないので、ループバックで処理されなかったRPCは失敗する
+    let unreachable = "127.0.0.1:1".parse().unwrap();
+    let mut client = Client::new(unreachable, remote.rpc_service().clone());
+    client.set_default_options(CallOptions {
+        rpc_options: Some(fibers_rpc::client::Options {
+            timeout: Some(Duration::from_millis(500)),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    let mut anonymous = client.clone();
+    anonymous.enable_loopback(loopback.clone());
+    client
+        .set_signing_key(writer_key)
+        .enable_loopback(loopback.clone());
+    let data = || LumpData::new(b"foo".to_vec()).unwrap();
+
+    // ループバックで処理されるRPC
+    assert!(wait!(client.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data()
+    )));
+    assert_eq!(
+        wait!(client.request().get_lump(device_id(), lump_id(0))),
+        Some(b"foo".to_vec())
+    );
+    assert!(wait!(client.request().head_lump(device_id(), lump_id(0))).is_some());
+    assert_eq!(
+        wait!(client.request().list_lumps(device_id())),
+        vec![lump_id(0)]
+    );
+    let e = wait_err!(client.request().get_lump(DeviceId::new("bar"), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert_eq!(
+        client
+            .metrics()
+            .get(rpc::PutLumpRpc::NAME, client.server())
+            .map(|m| m.succeeded),
+        Some(1)
+    );
+
+    // ループバックでも、事前条件やサーバ側のインターセプタ、署名の検証とアクセス制御が適用される
+    let e = wait_err!(client
+        .request()
+        .if_not_exists()
+        .put_lump(device_id(), lump_id(0), data()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    assert_eq!(
+        *dispatched.0.lock().unwrap(),
+        [
+            "cannyls.lump.put",
+            "cannyls.lump.get",
+            "cannyls.lump.head",
+            "cannyls.lump.list",
+            "cannyls.lump.put",
+        ]
+    );
+    let e = wait_err!(anonymous.request().list_lumps(device_id()));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+    let e = wait_err!(anonymous.request().delete_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+
+    // ループバックに対応していないリクエストは、(存在しない)サーバに発行される
+    let _ = wait_err!(client.request().exists_lump(device_id(), lump_id(0)));
+
+    // サーバに登録されていないRPCも同様
+    let mut unregistered = client.clone();
+    unregistered.enable_loopback(Server::new(loopback.registry().clone()));
+    let _ = wait_err!(unregistered.request().delete_lump(device_id(), lump_id(0)));
+
+    assert!(wait!(client.request().delete_lump(device_id(), lump_id(0))));
+    assert_eq!(wait!(client.request().list_lumps(device_id())), vec![]);
+}
+
+#[test]
+fn test_server_works() {
+    let server = track_try_unwrap!(TestServer::start());
+    let request = server.client().request();
+    let device_id = server.device_id().clone();
+    assert_eq!(device_id.as_str(), testing::DEFAULT_DEVICE_ID);
+    assert_ne!(server.server_addr().port(), 0);
+    assert!(server.registry().contains_device(&device_id));
+
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(track_try_unwrap!(TestServer::wait(request.put_lump(
+        device_id.clone(),
+        lump_id(0),
+        data
+    ))));
+    assert_eq!(
+        track_try_unwrap!(TestServer::wait(request.list_lumps(device_id))),
+        vec![lump_id(0)]
+    );
+
+    // 設定を変更した場合
+    let mut acl = AccessControl::new();
+    acl.grant_anonymous(Permissions::read_only());
+    let server = track_try_unwrap!(TestServerBuilder::new()
+        .device_id(DeviceId::new("bar"))
+        .capacity(1024 * 1024)
+        .configure(move |server| {
+            server.access_control(acl);
+        })
+        .start());
+    let request = server.client().request();
+    assert_eq!(
+        track_try_unwrap!(TestServer::wait(request.list_lumps(DeviceId::new("bar")))),
+        vec![]
+    );
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    let e = TestServer::wait(request.put_lump(DeviceId::new("bar"), lump_id(0), data))
+        .err()
+        .unwrap();
+    assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "fault_injection")]
+#[test]
+fn fault_injection_works() {
+    use cannyls_rpc::{Fault, FaultInjector};
+    use std::time::Instant;
+
+    let mut injector = FaultInjector::new(0);
+    injector
+        .inject::<rpc::GetLumpRpc>(Fault::Error(ErrorKind::DeviceBusy), 1.0)
+        .inject::<rpc::HeadLumpRpc>(Fault::Delay(Duration::from_millis(200)), 1.0)
+        .inject::<rpc::ExistsLumpRpc>(Fault::Drop, 1.0);
+    let injected = injector.clone();
+    let client = start_server_with(1999, move |mut server, builder| {
+        server.inject_faults(injector);
+        server.register(builder)
+    });
+    let request = client.request();
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(request.put_lump(device_id(), lump_id(0), data)));
+
+    let e = wait_err!(request.get_lump(device_id(), lump_id(0)));
+    assert_eq!(*e.kind(), ErrorKind::DeviceBusy);
+
+    let start = Instant::now();
+    assert!(wait!(request.head_lump(device_id(), lump_id(0))).is_some());
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    // 応答が返されないので、タイムアウトとなる
+    let rpc_options = fibers_rpc::client::Options {
+        timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    wait_err!(request
+        .clone()
+        .rpc_options(rpc_options)
+        .exists_lump(device_id(), lump_id(0)));
+    let in_flight = wait!(request.list_in_flight_requests(Vec::new()));
+    assert_eq!(in_flight.len(), 1);
+    assert_eq!(in_flight[0].procedure, "cannyls.lump.exists");
+    assert!(wait!(
+        request.cancel_in_flight_request(in_flight[0].request_id)
+    ));
+
+    assert_eq!(injected.injected(), 3);
+}
+
+#[cfg(feature = "fault_injection")]
+#[test]
+fn latency_injection_works() {
+    use cannyls_rpc::{LatencyDistribution, LatencyInjector, LatencyRule};
+    use std::time::Instant;
+
+    let mut injector = LatencyInjector::new(0);
+    injector.add_rule(
+        LatencyRule::new(LatencyDistribution::Constant(Duration::from_millis(300)))
+            .procedure::<rpc::GetLumpRpc>()
+            .device(device_id()),
+    );
+    let injected = injector.clone();
+    let mut client = start_server_with(2000, move |mut server, builder| {
+        server.inject_latency(injector);
+        server.register(builder)
+    });
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(client.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data
+    )));
+
+    let start = Instant::now();
+    assert!(wait!(client.request().get_lump(device_id(), lump_id(0))).is_some());
+    assert!(start.elapsed() >= Duration::from_millis(300));
+
+    // デフォルトではデッドラインに猶予(500ms)を加えた値がタイムアウトとなるので、
+    // 処理がデッドラインを過ぎても、猶予の範囲内であれば成功する
+    assert!(wait!(client
+        .request()
+        .deadline(Deadline::Within(Duration::from_millis(100)))
+        .get_lump(device_id(), lump_id(0)))
+    .is_some());
+
+    // デッドラインから導出されたタイムアウトにより失敗する
+    client.set_deadline_timeout_slack(Some(Duration::from_millis(0)));
+    wait_err!(client
+        .request()
+        .deadline(Deadline::Within(Duration::from_millis(100)))
+        .get_lump(device_id(), lump_id(0)));
+
+    assert!(wait!(client.request().head_lump(device_id(), lump_id(0))).is_some());
+    assert_eq!(injected.injected(), 3);
+}
+
+#[cfg(feature = "fault_injection")]
+#[test]
+fn deadline_timeout_does_not_cut_long_deadlines() {
+    use cannyls_rpc::{LatencyDistribution, LatencyInjector, LatencyRule};
+    use std::time::Instant;
+
+    let mut injector = LatencyInjector::new(0);
+    injector.add_rule(
+        LatencyRule::new(LatencyDistribution::Constant(Duration::from_millis(800)))
+            .procedure::<rpc::GetLumpRpc>()
+            .device(device_id()),
+    );
+    let client = start_server_with(2011, move |mut server, builder| {
+        server.inject_latency(injector);
+        server.register(builder)
+    });
+    let data = LumpData::new(b"foo".to_vec()).unwrap();
+    assert!(wait!(client.request().put_lump(
+        device_id(),
+        lump_id(0),
+        data
+    )));
+
+    // デフォルトの猶予のままでも、デッドライン内に完了するリクエストがタイムアウトで失敗することはない
+    let start = Instant::now();
+    assert!(wait!(client
+        .request()
+        .deadline(Deadline::Within(Duration::from_secs(2)))
+        .get_lump(device_id(), lump_id(0)))
+    .is_some());
+    assert!(start.elapsed() >= Duration::from_millis(800));
+
+    // デッドラインを過ぎても、猶予の範囲内であれば失敗しない
+    assert!(wait!(client
+        .request()
+        .deadline(Deadline::Within(Duration::from_millis(500)))
+        .get_lump(device_id(), lump_id(0)))
+    .is_some());
+}
+
+#[test]
+fn record_and_replay_works() {
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let buffer = SharedBuffer::default();
+    let recorder = TrafficRecorder::new(buffer.clone());
+    let source = {
+        let recorder = recorder.clone();
+        track_try_unwrap!(TestServerBuilder::new()
+            .configure(move |server| {
+                server.add_interceptor(recorder);
+            })
+            .start())
+    };
+    let device_id = source.device_id().clone();
+    let request = source.client().request();
+    for i in 0..2 {
+        let data = LumpData::new(b"foo".to_vec()).unwrap();
+        let put = request.put_lump(device_id.clone(), lump_id(i), data);
+        assert!(track_try_unwrap!(TestServer::wait(put)));
+    }
+    let get = request.get_lump(device_id.clone(), lump_id(0));
+    assert!(track_try_unwrap!(TestServer::wait(get)).is_some());
+    let get = request.get_lump_range(device_id.clone(), lump_id(0), 0..1);
+    assert!(track_try_unwrap!(TestServer::wait(get)).is_some());
+    let delete = request.delete_lump(device_id.clone(), lump_id(1));
+    assert!(track_try_unwrap!(TestServer::wait(delete)));
+    let list = request.list_lumps(DeviceId::new("unknown"));
+    assert!(TestServer::wait(list).is_err());
+    let list = request.list_lumps_range(device_id.clone(), lump_id(0)..lump_id(10));
+    assert_eq!(track_try_unwrap!(TestServer::wait(list)), vec![lump_id(0)]);
+
+    track_try_unwrap!(recorder.flush());
+    let records = track_try_unwrap!(RecordedRequest::read_all(&buffer.0.lock().unwrap()[..]));
+    // 対象デバイスの検索に失敗したリクエストは記録されない
+    let procedures = records
+        .iter()
+        .map(|r| r.procedure.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        procedures,
+        [
+            "cannyls.lump.put",
+            "cannyls.lump.put",
+            "cannyls.lump.get",
+            "cannyls.lump.get_range",
+            "cannyls.lump.delete",
+            "cannyls.lump.list_range"
+        ]
+    );
+    assert_eq!(records[0].payload_size, 3);
+    assert_eq!(records[0].error, None);
+    assert!(records.windows(2).all(|w| w[0].offset <= w[1].offset));
+
+    // 別のサーバに対して再生する
+    let target = track_try_unwrap!(TestServer::start());
+    let replayer = Replayer::new(target.client().clone(), records.clone());
+    let summary = track_try_unwrap!(TestServer::wait(replayer.replay()));
+    assert_eq!(
+        summary,
+        ReplaySummary {
+            issued: 5,
+            succeeded: 5,
+            failed: 0,
+            skipped: 1,
+            mismatched: 0,
+        }
+    );
+    let get = target
+        .client()
+        .request()
+        .get_lump(device_id.clone(), lump_id(0));
+    assert_eq!(track_try_unwrap!(TestServer::wait(get)), Some(vec![0; 3]));
+    let head = target
+        .client()
+        .request()
+        .head_lump(device_id.clone(), lump_id(1));
+    assert!(track_try_unwrap!(TestServer::wait(head)).is_none());
+
+    // 記録時の時間間隔を保って再生する
+    let mut replayer = Replayer::new(target.client().clone(), records);
+    replayer.speed(10.0);
+    let summary = track_try_unwrap!(TestServer::wait(replayer.replay()));
+    assert_eq!(summary.succeeded, 5);
+    // 二度目の削除は`false`を返すが、成功として扱われる
+    assert_eq!(summary.mismatched, 0);
+}
+
+#[test]
+fn cli_works() {
+    use std::process::{Command, Output};
+
+    let server = track_try_unwrap!(TestServer::start());
+    let addr = server.server_addr().to_string();
+    let dir = track_try_unwrap!(track_any_err!(TempDir::new("cannyls_rpc_test")));
+    let cli = |args: &[&str]| -> Output {
+        Command::new(env!("CARGO_BIN_EXE_cannyls-rpc"))
+            .arg("--server")
+            .arg(&addr)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let stdout = |output: &Output| String::from_utf8(output.stdout.clone()).unwrap();
+
+    let input = dir.path().join("input");
+    let output = dir.path().join("output");
+    std::fs::write(&input, b"foo").unwrap();
+    let input = input.to_str().unwrap();
+
+    let put = cli(&["put", "test", "ab12", "-i", input]);
+    assert!(put.status.success());
+    assert_eq!(stdout(&put), "created\n");
+    let put = cli(&["--prioritized", "put", "test", "ab12", "-i", input]);
+    assert_eq!(stdout(&put), "overwritten\n");
+
+    let get = cli(&["get", "test", "ab12", "-o", output.to_str().unwrap()]);
+    assert!(get.status.success());
+    assert_eq!(std::fs::read(&output).unwrap(), b"foo");
+    let get = cli(&["--deadline", "1000", "get", "test", "ab12"]);
+    assert_eq!(get.stdout, b"foo");
+
+    let head = cli(&["head", "test", "ab12"]);
+    assert_eq!(stdout(&head), "3\n");
+
+    let list = cli(&["list", "test"]);
+    assert_eq!(stdout(&list), "0000000000000000000000000000ab12\n");
+    let list = cli(&["list", "test", "0", "ab12"]);
+    assert_eq!(stdout(&list), "");
+
+    let usage = cli(&["usage_range", "test", "0", "ffff"]);
+    assert!(usage.status.success());
+
+    let delete = cli(&["delete", "test", "ab12"]);
+    assert_eq!(stdout(&delete), "deleted\n");
+    assert_eq!(cli(&["delete", "test", "ab12"]).status.code(), Some(3));
+    assert_eq!(cli(&["get", "test", "ab12"]).status.code(), Some(3));
+
+    let delete_range = cli(&["delete_range", "test", "0", "ffff"]);
+    assert_eq!(stdout(&delete_range), "");
+
+    // 不正なコマンドライン引数
+    assert_eq!(cli(&["get", "test"]).status.code(), Some(2));
+    assert_eq!(cli(&["get", "test", "xyz"]).status.code(), Some(2));
+    assert_eq!(
+        cli(&["head", "test", "0", "-o", "foo"]).status.code(),
+        Some(2)
+    );
+    assert_eq!(cli(&["unknown"]).status.code(), Some(2));
+    assert!(cli(&["--help"]).status.success());
+
+    // リクエストの失敗
+    assert_eq!(cli(&["get", "unknown", "0"]).status.code(), Some(1));
+}
+
+#[test]
+fn server_daemon_works() {
+    use std::process::{Command, Stdio};
+
+    let dir = track_try_unwrap!(track_any_err!(TempDir::new("cannyls_rpc_test")));
+    let config = dir.path().join("node.toml");
+    std::fs::write(
+        &config,
+        r#"
+listen = "127.0.0.1:2001"
+
+[[device]]
+id = "dev0"
+memory = true
+capacity = 10_485_760
+"#,
+    )
+    .unwrap();
+
+    let mut daemon = Command::new(env!("CARGO_BIN_EXE_cannyls-rpc-server"))
+        .arg(&config)
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let cli = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_cannyls-rpc"))
+            .args(["--server", "127.0.0.1:2001"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    // デバイスの構築や設定の再読み込みは非同期に行われるので、期待する終了コードになるまで繰り返す
+    let wait_for = |args: &[&str], code: i32| {
+        for _ in 0..100 {
+            if cli(args).status.code() == Some(code) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("{:?} did not exit with {}", args, code);
+    };
+
+    wait_for(&["put", "dev0", "1", "-i", config.to_str().unwrap()], 0);
+    let get = cli(&["get", "dev0", "1"]);
+    assert_eq!(get.stdout, std::fs::read(&config).unwrap());
+
+    // SIGHUPによる設定の再読み込み
+    std::fs::write(
+        &config,
+        "listen = \"127.0.0.1:2001\"\n[[device]]\nid = \"dev1\"\nmemory = true\ncapacity = 10_485_760\n",
+    )
+    .unwrap();
+    let hangup = Command::new("kill")
+        .args(["-HUP", &daemon.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(hangup.success());
+    wait_for(&["head", "dev1", "1"], 3);
+    wait_for(&["head", "dev0", "1"], 1);
+
+    daemon.kill().unwrap();
+    daemon.wait().unwrap();
+
+    // 不正な設定ファイル
+    std::fs::write(&config, "listen = 1").unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_cannyls-rpc-server"))
+        .arg(&config)
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(1));
+}